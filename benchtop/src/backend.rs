@@ -1,15 +1,98 @@
 use crate::{nomt::NomtDB, sov_db::SovDB, sp_trie::SpTrieDB, timer::Timer, workload::Workload};
+use std::sync::{Mutex, OnceLock};
 
-#[derive(Debug, Clone, clap::ValueEnum)]
-pub enum Backend {
-    SovDB,
-    Nomt,
-    SpTrie,
+/// The parameters backends are opened with. Backends ignore the parameters which don't apply to
+/// them.
+#[derive(Debug, Clone)]
+pub struct OpenParams {
+    /// Whether to erase any previous database and restart from an empty one. Otherwise, the
+    /// already present database is used.
+    pub reset: bool,
+    pub commit_concurrency: usize,
+    pub io_workers: usize,
+    pub hashtable_buckets: Option<u32>,
+}
+
+/// A database workloads can be executed against.
+///
+/// Implement this, along with [`Transaction`], and [`register`] the backend to benchmark a
+/// storage stack of your own.
+pub trait Database {
+    /// Run a step of the workload and commit it.
+    fn execute(&mut self, timer: Option<&mut Timer>, workload: &mut dyn Workload);
+
+    /// Run a step of each workload in parallel and commit them together.
+    ///
+    /// Not supported unless overridden.
+    fn parallel_execute(
+        &mut self,
+        _timer: Option<&mut Timer>,
+        _thread_pool: &rayon::ThreadPool,
+        _workloads: &mut [Box<dyn Workload>],
+    ) -> anyhow::Result<()> {
+        anyhow::bail!("parallel execution is not supported by this backend.")
+    }
+
+    /// Print metrics collected by the backend, if it supports metrics collection.
+    fn print_metrics(&self) {}
+}
+
+/// Opens the database of a backend.
+pub type OpenFn = fn(&OpenParams) -> Box<dyn Database>;
+
+/// A backend registered under a name. See [`register`].
+#[derive(Debug, Clone)]
+pub struct Backend {
+    name: &'static str,
+    open: OpenFn,
+}
+
+fn registry() -> &'static Mutex<Vec<Backend>> {
+    static REGISTRY: OnceLock<Mutex<Vec<Backend>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        Mutex::new(vec![
+            Backend {
+                name: "sov-db",
+                open: open_sov_db,
+            },
+            Backend {
+                name: "sp-trie",
+                open: open_sp_trie,
+            },
+            Backend {
+                name: "nomt",
+                open: open_nomt,
+            },
+        ])
+    })
+}
+
+/// Register a backend under the given name, making it selectable with `--backend`. Registering
+/// an existing name replaces the backend.
+///
+/// Call this before [`crate::run_cli`].
+pub fn register(name: &'static str, open: OpenFn) {
+    let mut registry = registry().lock().unwrap();
+    match registry.iter_mut().find(|backend| backend.name == name) {
+        Some(backend) => backend.open = open,
+        None => registry.push(Backend { name, open }),
+    }
 }
 
 impl Backend {
     pub fn all_backends() -> Vec<Self> {
-        vec![Backend::SovDB, Backend::SpTrie, Backend::Nomt]
+        registry().lock().unwrap().clone()
+    }
+
+    /// Look up a registered backend by name.
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::all_backends()
+            .into_iter()
+            .find(|backend| backend.name == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
     }
 
     // If reset is true, then erase any previous backend's database
@@ -22,19 +105,32 @@ impl Backend {
         io_workers: usize,
         hashtable_buckets: Option<u32>,
     ) -> DB {
-        match self {
-            Backend::SovDB => DB::Sov(SovDB::open(reset)),
-            Backend::Nomt => DB::Nomt(NomtDB::open(
-                reset,
-                commit_concurrency,
-                io_workers,
-                hashtable_buckets,
-            )),
-            Backend::SpTrie => DB::SpTrie(SpTrieDB::open(reset)),
-        }
+        DB((self.open)(&OpenParams {
+            reset,
+            commit_concurrency,
+            io_workers,
+            hashtable_buckets,
+        }))
     }
 }
 
+fn open_sov_db(params: &OpenParams) -> Box<dyn Database> {
+    Box::new(SovDB::open(params.reset))
+}
+
+fn open_sp_trie(params: &OpenParams) -> Box<dyn Database> {
+    Box::new(SpTrieDB::open(params.reset))
+}
+
+fn open_nomt(params: &OpenParams) -> Box<dyn Database> {
+    Box::new(NomtDB::open(
+        params.reset,
+        params.commit_concurrency,
+        params.io_workers,
+        params.hashtable_buckets,
+    ))
+}
+
 /// A transaction over the database which allows reading and writing.
 pub trait Transaction {
     /// Read a value from the database. If a value was previously written, return that.
@@ -47,12 +143,8 @@ pub trait Transaction {
     fn write(&mut self, key: &[u8], value: Option<&[u8]>);
 }
 
-/// A wrapper around all databases implemented in this tool.
-pub enum DB {
-    Sov(SovDB),
-    SpTrie(SpTrieDB),
-    Nomt(NomtDB),
-}
+/// The database of a registered backend.
+pub struct DB(Box<dyn Database>);
 
 impl DB {
     /// Execute a workload repeatedly until done or a time limit is reached.
@@ -70,17 +162,13 @@ impl DB {
                 break;
             }
             let timer = timer.as_deref_mut();
-            match self {
-                DB::Sov(db) => db.execute(timer, workload),
-                DB::SpTrie(db) => db.execute(timer, workload),
-                DB::Nomt(db) => db.execute(timer, workload),
-            }
+            self.0.execute(timer, workload);
         }
     }
 
     /// Execute several workloads in parallel, repeatedly, until all done or a time limit is reached.
     ///
-    /// Only works with the NOMT backend.
+    /// Only works with backends supporting parallel execution, such as NOMT.
     pub fn parallel_execute(
         &mut self,
         mut timer: Option<&mut Timer>,
@@ -96,15 +184,7 @@ impl DB {
                 break;
             }
             let timer = timer.as_deref_mut();
-            match self {
-                DB::Sov(_) => {
-                    anyhow::bail!("parallel execution is only supported with the NOMT backend.")
-                }
-                DB::SpTrie(_) => {
-                    anyhow::bail!("parallel execution is only supported with the NOMT backend.")
-                }
-                DB::Nomt(db) => db.parallel_execute(timer, thread_pool, workloads),
-            }
+            self.0.parallel_execute(timer, thread_pool, workloads)?;
         }
 
         Ok(())
@@ -112,9 +192,6 @@ impl DB {
 
     /// Print metrics collected by the Backend if it supports metrics collection
     pub fn print_metrics(&self) {
-        match self {
-            DB::Nomt(db) => db.print_metrics(),
-            _ => (),
-        }
+        self.0.print_metrics()
     }
 }
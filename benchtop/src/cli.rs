@@ -1,6 +1,6 @@
-use crate::backend::Backend;
+use crate::{backend::Backend, results::OutputFormat};
 use clap::{builder::PossibleValue, Args, Parser, Subcommand};
-use std::fmt::Display;
+use std::{fmt::Display, path::PathBuf};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -20,19 +20,29 @@ pub enum Commands {
     ///
     /// This will not reset the database unless `--reset` is provided.
     Run(RunParams),
+    /// Compare two results files written by `run --output`.
+    ///
+    /// Fails if any latency grew, or the throughput dropped, by more than the threshold.
+    Compare(CompareParams),
 }
 
 impl Display for Backend {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let name = match self {
-            Backend::SovDB => "sov-db",
-            Backend::Nomt => "nomt",
-            Backend::SpTrie => "sp-trie",
-        };
-        f.write_str(name)
+        f.write_str(self.name())
     }
 }
 
+/// Parse the name of a registered backend. See [`crate::backend::register`].
+fn parse_backend(name: &str) -> Result<Backend, String> {
+    Backend::by_name(name).ok_or_else(|| {
+        let names = Backend::all_backends()
+            .iter()
+            .map(|backend| backend.name())
+            .collect::<Vec<_>>();
+        format!("unknown backend, possible values: {}", names.join(", "))
+    })
+}
+
 /// Parameters to the init command.
 #[derive(Debug, Args)]
 pub struct InitParams {
@@ -40,7 +50,7 @@ pub struct InitParams {
     pub workload: WorkloadParams,
 
     /// The backend to run the workload against.
-    #[arg(required = true, long, short)]
+    #[arg(required = true, long, short, value_parser = parse_backend)]
     pub backend: Backend,
 }
 
@@ -54,7 +64,7 @@ pub struct RunParams {
     pub limits: RunLimits,
 
     /// The backend to run the workload against.
-    #[arg(required = true, long, short)]
+    #[arg(required = true, long, short, value_parser = parse_backend)]
     pub backend: Backend,
 
     /// How long to warm up for before collecting data.
@@ -68,18 +78,53 @@ pub struct RunParams {
     #[clap(default_value = "false")]
     #[arg(long, short)]
     pub reset: bool,
+
+    /// Write the results of the run, the latency percentiles of every span and the throughput,
+    /// to this file.
+    #[arg(long = "output", short)]
+    pub output: Option<PathBuf>,
+
+    /// The format of the results file.
+    #[arg(long = "output-format")]
+    #[clap(default_value = "json")]
+    pub output_format: OutputFormat,
+
+    /// Record the operations of the run to a trace file, which the `trace` workload can replay
+    /// against any backend.
+    ///
+    /// Every step of the workload becomes a block of the trace. With `--reset`, the
+    /// initialization is recorded as well, as block 0.
+    #[arg(long = "record-trace")]
+    pub record_trace: Option<PathBuf>,
+}
+
+/// Parameters to the compare command.
+#[derive(Debug, Args)]
+pub struct CompareParams {
+    /// The results file to compare against.
+    pub baseline: PathBuf,
+
+    /// The results file to compare.
+    pub current: PathBuf,
+
+    /// The change of a metric, in percent, beyond which it is flagged as a regression.
+    #[arg(long = "threshold")]
+    #[clap(default_value = "5.0")]
+    pub threshold: f64,
 }
 
 #[derive(Clone, Debug, Args)]
 pub struct WorkloadParams {
     /// Workload used by benchmarks.
     ///
-    /// Possible values are: transfer, randr, randw, randrw
+    /// Possible values are: transfer, randr, randw, randrw, trace
     ///
     /// `transfer` workload involves balancing transfer between two different accounts.
     ///
     /// `randr` and `randw` will perform randomly uniformly distributed reads and writes,
     /// respectively, over the key space.
+    ///
+    /// `trace` replays the operations of a trace file, see `--trace`.
     #[clap(default_value = "transfer")]
     #[arg(long = "workload-name", short = 'w')]
     pub name: String,
@@ -144,6 +189,14 @@ pub struct WorkloadParams {
     #[arg(long = "distribution")]
     #[clap(default_value = "uniform")]
     pub distribution: StateItemDistribution,
+
+    /// The trace file replayed by the `trace` workload.
+    ///
+    /// Every line holds an operation as `<block>,<op>,<key>,<value size>`, where op is one of
+    /// r, w or d and the key is hex-encoded. Block 0 is the initial state, written on init, and
+    /// every later block is committed on its own. Only used with the trace workload.
+    #[arg(long = "trace")]
+    pub trace: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Args)]
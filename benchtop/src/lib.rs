@@ -0,0 +1,129 @@
+//! A benchmarking harness for NOMT and other merkle storage backends.
+//!
+//! The benchtop binary runs the built-in backends. Custom backends implement
+//! [`backend::Database`] and [`backend::Transaction`] and are registered with
+//! [`backend::register`] by a binary of their own, which then calls [`run_cli`].
+
+pub mod backend;
+mod cli;
+mod custom_workload;
+mod nomt;
+mod results;
+mod sov_db;
+mod sp_trie;
+pub mod timer;
+mod trace_workload;
+mod transfer_workload;
+pub mod workload;
+
+use anyhow::Result;
+use clap::Parser;
+use cli::{Cli, Commands, InitParams, RunParams};
+use timer::Timer;
+
+/// Parse the command line and run the command against the registered backends.
+///
+/// Crates embedding benchtop register their own backends with [`backend::register`] before
+/// calling this from their `main` function.
+pub fn run_cli() -> Result<()> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Commands::Init(params) => init(params),
+        Commands::Run(params) => run(params),
+        Commands::Compare(params) => results::compare(params),
+    }
+}
+
+fn init(params: InitParams) -> Result<()> {
+    let workload_params = params.workload;
+    let (mut init, _) = workload::parse(&workload_params, u64::max_value())?;
+
+    let mut db = params.backend.instantiate(
+        true,
+        workload_params.commit_concurrency,
+        workload_params.io_workers,
+        workload_params.hashtable_buckets,
+    );
+    db.execute(None, &mut *init, None);
+
+    Ok(())
+}
+
+fn run(params: RunParams) -> Result<()> {
+    let workload_params = params.workload;
+    let (mut init, mut workloads) = workload::parse(
+        &workload_params,
+        params.limits.ops.unwrap_or(u64::max_value()),
+    )?;
+    if let Some(ref path) = params.record_trace {
+        let recorder = trace_workload::TraceRecorder::create(path)?;
+        init = Box::new(trace_workload::RecordingWorkload::new(
+            init,
+            recorder.clone(),
+            true,
+        ));
+        workloads = workloads
+            .into_iter()
+            .map(|workload| {
+                Box::new(trace_workload::RecordingWorkload::new(
+                    workload,
+                    recorder.clone(),
+                    false,
+                )) as Box<dyn workload::Workload>
+            })
+            .collect();
+    }
+
+    let mut db = params.backend.instantiate(
+        params.reset,
+        workload_params.commit_concurrency,
+        workload_params.io_workers,
+        workload_params.hashtable_buckets,
+    );
+
+    if params.reset {
+        db.execute(None, &mut *init, None);
+    }
+
+    let mut timer = Timer::new(format!("{}", params.backend));
+    let warmup_timeout = params
+        .warm_up
+        .map(|time_limit| std::time::Instant::now() + time_limit.into());
+
+    let thread_pool = rayon::ThreadPoolBuilder::new()
+        .thread_name(|_| "benchtop-workload".into())
+        .num_threads(workload_params.workload_concurrency as usize)
+        .build()?;
+
+    if let Some(t) = warmup_timeout {
+        if workload_params.workload_concurrency == 1 {
+            db.execute(Some(&mut timer), &mut *workloads[0], Some(t));
+        } else {
+            db.parallel_execute(Some(&mut timer), &thread_pool, &mut workloads, Some(t))?;
+        };
+
+        timer = Timer::new(format!("{}", params.backend));
+    }
+
+    let timeout = params
+        .limits
+        .time
+        .map(|time_limit| std::time::Instant::now() + time_limit.into());
+
+    if workload_params.workload_concurrency == 1 {
+        db.execute(Some(&mut timer), &mut *workloads[0], timeout);
+    } else {
+        db.parallel_execute(Some(&mut timer), &thread_pool, &mut workloads, timeout)?;
+    };
+
+    db.print_metrics();
+    timer.print(workload_params.size);
+    if let Some(ref path) = params.output {
+        timer
+            .results(workload_params.size)
+            .write(path, params.output_format)?;
+    }
+
+    Ok(())
+}
@@ -1,4 +1,8 @@
-use crate::{backend::Transaction, timer::Timer, workload::Workload};
+use crate::{
+    backend::{Database, Transaction},
+    timer::Timer,
+    workload::Workload,
+};
 use fxhash::FxHashMap;
 use nomt::{Blake3Hasher, KeyPath, KeyReadWrite, Nomt, Options, Session};
 use sha2::Digest;
@@ -37,8 +41,10 @@ impl NomtDB {
         let nomt = Nomt::open(opts).unwrap();
         Self { nomt }
     }
+}
 
-    pub fn execute(&self, mut timer: Option<&mut Timer>, workload: &mut dyn Workload) {
+impl Database for NomtDB {
+    fn execute(&mut self, mut timer: Option<&mut Timer>, workload: &mut dyn Workload) {
         let _timer_guard_total = timer.as_mut().map(|t| t.record_span("workload"));
 
         let session = self.nomt.begin_session();
@@ -63,12 +69,12 @@ impl NomtDB {
     // note: this is only intended to be used with workloads which are disjoint, i.e. no workload
     // writes a key which another workload reads. re-implementing BlockSTM or other OCC methods are
     // beyond the scope of benchtop.
-    pub fn parallel_execute(
-        &self,
+    fn parallel_execute(
+        &mut self,
         mut timer: Option<&mut Timer>,
         thread_pool: &rayon::ThreadPool,
         workloads: &mut [Box<dyn Workload>],
-    ) {
+    ) -> anyhow::Result<()> {
         let _timer_guard_total = timer.as_mut().map(|t| t.record_span("workload"));
 
         let session = self.nomt.begin_session();
@@ -111,9 +117,10 @@ impl NomtDB {
             .collect();
         actual_access.sort_by_key(|(k, _)| *k);
         self.nomt.commit_and_prove(session, actual_access).unwrap();
+        Ok(())
     }
 
-    pub fn print_metrics(&self) {
+    fn print_metrics(&self) {
         self.nomt.metrics().print()
     }
 }
@@ -0,0 +1,193 @@
+//! Machine-readable results of a run and the comparison of two of them.
+//!
+//! Results are written as JSON or CSV. Both flatten to the same set of named metrics:
+//! `throughput_ops_per_s` and, for every span, `<span>.count`, `<span>.mean_ns`, `<span>.p50_ns`,
+//! `<span>.p95_ns`, `<span>.p99_ns`, `<span>.p999_ns` and `<span>.max_ns`. The CSV format lists
+//! these as `metric,value` rows.
+
+use crate::{cli::CompareParams, timer::SpanStats};
+use anyhow::{bail, Context as _, Result};
+use clap::builder::PossibleValue;
+use std::{collections::BTreeMap, path::Path};
+
+/// The format of a results file.
+#[derive(Debug, Clone, Copy)]
+pub enum OutputFormat {
+    Json,
+    Csv,
+}
+
+impl clap::ValueEnum for OutputFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[OutputFormat::Json, OutputFormat::Csv]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self {
+            OutputFormat::Json => PossibleValue::new("json").help("a JSON object"),
+            OutputFormat::Csv => PossibleValue::new("csv").help("`metric,value` rows"),
+        })
+    }
+}
+
+/// The results of a run.
+pub struct Results {
+    /// The name of the backend the run was against.
+    pub backend: String,
+    /// The mean throughput in operations per second, if measured.
+    pub throughput: Option<f64>,
+    /// The statistics of every measured span.
+    pub spans: Vec<(String, SpanStats)>,
+}
+
+impl Results {
+    /// Write the results to a file in the given format.
+    pub fn write(&self, path: &Path, format: OutputFormat) -> Result<()> {
+        let contents = match format {
+            OutputFormat::Json => self.to_json(),
+            OutputFormat::Csv => self.to_csv(),
+        };
+        std::fs::write(path, contents)
+            .with_context(|| format!("writing results to {}", path.display()))
+    }
+
+    fn to_json(&self) -> String {
+        let spans = self
+            .spans
+            .iter()
+            .map(|(name, stats)| {
+                let stats = span_metrics(stats)
+                    .into_iter()
+                    .map(|(metric, value)| (metric.to_string(), serde_json::Value::from(value)))
+                    .collect::<serde_json::Map<_, _>>();
+                (name.clone(), serde_json::Value::Object(stats))
+            })
+            .collect::<serde_json::Map<_, _>>();
+        let results = serde_json::json!({
+            "backend": self.backend,
+            "throughput_ops_per_s": self.throughput,
+            "spans": spans,
+        });
+        // UNWRAP: serializing a JSON value cannot fail.
+        serde_json::to_string_pretty(&results).unwrap()
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = String::from("metric,value\n");
+        if let Some(throughput) = self.throughput {
+            csv.push_str(&format!("throughput_ops_per_s,{throughput}\n"));
+        }
+        for (name, stats) in &self.spans {
+            for (metric, value) in span_metrics(stats) {
+                csv.push_str(&format!("{name}.{metric},{value}\n"));
+            }
+        }
+        csv
+    }
+}
+
+fn span_metrics(stats: &SpanStats) -> [(&'static str, u64); 7] {
+    [
+        ("count", stats.count),
+        ("mean_ns", stats.mean),
+        ("p50_ns", stats.p50),
+        ("p95_ns", stats.p95),
+        ("p99_ns", stats.p99),
+        ("p999_ns", stats.p999),
+        ("max_ns", stats.max),
+    ]
+}
+
+/// Load the metrics of a results file in either format.
+fn load_metrics(path: &Path) -> Result<BTreeMap<String, f64>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("reading results from {}", path.display()))?;
+    if contents.trim_start().starts_with('{') {
+        parse_json(&contents)
+    } else {
+        parse_csv(&contents)
+    }
+    .with_context(|| format!("parsing results from {}", path.display()))
+}
+
+fn parse_json(contents: &str) -> Result<BTreeMap<String, f64>> {
+    let results: serde_json::Value = serde_json::from_str(contents)?;
+    let mut metrics = BTreeMap::new();
+    if let Some(throughput) = results["throughput_ops_per_s"].as_f64() {
+        metrics.insert("throughput_ops_per_s".to_string(), throughput);
+    }
+    let Some(spans) = results["spans"].as_object() else {
+        bail!("missing spans");
+    };
+    for (name, stats) in spans {
+        let Some(stats) = stats.as_object() else {
+            bail!("invalid statistics of span {name}");
+        };
+        for (metric, value) in stats {
+            let Some(value) = value.as_f64() else {
+                bail!("invalid value of {name}.{metric}");
+            };
+            metrics.insert(format!("{name}.{metric}"), value);
+        }
+    }
+    Ok(metrics)
+}
+
+fn parse_csv(contents: &str) -> Result<BTreeMap<String, f64>> {
+    let mut metrics = BTreeMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line == "metric,value" {
+            continue;
+        }
+        let Some((metric, value)) = line.split_once(',') else {
+            bail!("line {}: expected `metric,value`", i + 1);
+        };
+        let value = value
+            .trim()
+            .parse()
+            .with_context(|| format!("line {}: invalid value", i + 1))?;
+        metrics.insert(metric.trim().to_string(), value);
+    }
+    Ok(metrics)
+}
+
+/// Compare two results files, printing the change of every metric present in both, and fail if
+/// any latency grew or the throughput dropped by more than the threshold.
+pub fn compare(params: CompareParams) -> Result<()> {
+    let baseline = load_metrics(&params.baseline)?;
+    let current = load_metrics(&params.current)?;
+    let threshold = params.threshold / 100.0;
+
+    let mut regressions = 0;
+    for (metric, &old) in &baseline {
+        // Counts depend on how long the run was, not on how fast.
+        if metric.ends_with(".count") {
+            continue;
+        }
+        let Some(&new) = current.get(metric) else {
+            println!("{metric}: missing from {}", params.current.display());
+            continue;
+        };
+        let change = if old == 0.0 { 0.0 } else { (new - old) / old };
+        let regressed = if metric.ends_with("_ns") {
+            change > threshold
+        } else {
+            change < -threshold
+        };
+        println!(
+            "{metric}: {old} -> {new} ({:+.1}%){}",
+            change * 100.0,
+            if regressed { "  REGRESSION" } else { "" }
+        );
+        regressions += regressed as usize;
+    }
+
+    if regressions > 0 {
+        bail!(
+            "{regressions} metrics regressed by more than {}%",
+            params.threshold
+        );
+    }
+    Ok(())
+}
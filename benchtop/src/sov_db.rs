@@ -1,4 +1,4 @@
-use crate::backend::Transaction;
+use crate::backend::{Database, Transaction};
 use crate::timer::Timer;
 use crate::workload::Workload;
 use fxhash::{FxHashMap, FxHashSet};
@@ -74,8 +74,10 @@ impl SovDB {
             trie_qm: Arc::new(RwLock::new(trie_qm)),
         }
     }
+}
 
-    pub fn execute(&mut self, mut timer: Option<&mut Timer>, workload: &mut dyn Workload) {
+impl Database for SovDB {
+    fn execute(&mut self, mut timer: Option<&mut Timer>, workload: &mut dyn Workload) {
         // sov-db's API initializes the StateDB struct afresh for each "block" - it is not meant
         // to be a long-term handle. We do the same here with the following steps.
         // Reads through go through these stages:
@@ -149,6 +151,7 @@ impl SovDB {
 
         // 4. up to now, nothing has been committed to disk. do that by freezing and committing
         //    to underlying DB handle.
+        let _timer_guard_sync = timer.as_mut().map(|t| t.record_span("sync"));
         let trie_qm = self.trie_qm.read().unwrap();
 
         trie_qm
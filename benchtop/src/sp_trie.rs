@@ -1,4 +1,8 @@
-use crate::{backend::Transaction, timer::Timer, workload::Workload};
+use crate::{
+    backend::{self, Transaction},
+    timer::Timer,
+    workload::Workload,
+};
 use hash_db::{AsHashDB, HashDB, Prefix};
 use kvdb::KeyValueDB;
 use kvdb_rocksdb::{Database, DatabaseConfig};
@@ -47,8 +51,10 @@ impl SpTrieDB {
 
         Self { kvdb, root }
     }
+}
 
-    pub fn execute(&mut self, mut timer: Option<&mut Timer>, workload: &mut dyn Workload) {
+impl backend::Database for SpTrieDB {
+    fn execute(&mut self, mut timer: Option<&mut Timer>, workload: &mut dyn Workload) {
         let _timer_guard_total = timer.as_mut().map(|t| t.record_span("workload"));
 
         let mut new_root = self.root;
@@ -60,7 +66,7 @@ impl SpTrieDB {
         };
 
         let recorder: sp_trie::recorder::Recorder<Hasher> = Default::default();
-        let _timer_guard_commit = {
+        let (mut timer, _timer_guard_commit) = {
             let mut trie_recorder = recorder.as_trie_recorder(new_root);
 
             let trie_db_mut = if self.root == Hash::default() {
@@ -86,11 +92,12 @@ impl SpTrieDB {
             let timer_guard_commit = timer.as_mut().map(|t| t.record_span("commit_and_prove"));
 
             trie_db_mut.commit();
-            timer_guard_commit
+            (timer, timer_guard_commit)
         };
 
         let _proof = recorder.drain_storage_proof().is_empty();
 
+        let _timer_guard_sync = timer.as_mut().map(|t| t.record_span("sync"));
         let mut transaction = self.kvdb.transaction();
         for (key, (value, ref_count)) in overlay.drain() {
             if ref_count > 0 {
@@ -4,10 +4,17 @@ use std::{
     rc::Rc,
 };
 
+use crate::results::Results;
+
 // At least three spans are expected to be measured
 // + `workload`
 // + `read`
 // + `commit_and_prove`
+//
+// `sync`, the time spent writing a commit to disk, is measured by backends which can tell it
+// apart from `commit_and_prove`.
+const EXPECTED_SPANS: [&str; 4] = ["workload", "read", "commit_and_prove", "sync"];
+
 pub struct Timer {
     name: String,
     spans: HashMap<&'static str, Rc<RefCell<hdrhistogram::Histogram<u64>>>>,
@@ -88,40 +95,90 @@ impl Timer {
             .mean() as u64)
     }
 
+    /// The statistics of every measured span, the expected spans first and the others sorted by
+    /// name.
+    pub fn span_stats(&self) -> Vec<(&'static str, SpanStats)> {
+        let mut spans = self
+            .spans
+            .iter()
+            .map(|(name, h)| (*name, SpanStats::of(&h.borrow())))
+            .collect::<Vec<_>>();
+        spans.sort_by_key(|(name, _)| {
+            let expected = EXPECTED_SPANS.iter().position(|expected| expected == name);
+            (expected.unwrap_or(EXPECTED_SPANS.len()), *name)
+        });
+        spans
+    }
+
+    /// The mean throughput of the workload in operations per second, if measured.
+    pub fn throughput(&self, workload_size: u64) -> Option<f64> {
+        let workload_mean_ns = self.get_mean_workload_duration().ok()?;
+        Some(workload_size as f64 / (workload_mean_ns as f64 / 1_000_000_000.0))
+    }
+
     pub fn print(&mut self, workload_size: u64) {
         println!("{}", self.name);
 
-        let expected_spans = ["workload", "read", "commit_and_prove"];
-
-        // print expectd spans in order
-        for span_name in expected_spans {
-            let h = self.spans.get(span_name);
-            match h {
-                Some(h) => println!(
-                    "  mean {}: {}",
-                    span_name,
-                    pretty_display_ns(h.borrow().mean() as u64)
-                ),
-                None => println!("{} not measured", span_name),
-            };
+        let spans = self.span_stats();
+        for expected in EXPECTED_SPANS {
+            if !spans.iter().any(|(name, _)| *name == expected) {
+                println!("{} not measured", expected);
+            }
+        }
+
+        for (span_name, stats) in spans {
+            println!(
+                "  {}: mean {}, p50 {}, p95 {}, p99 {}, p999 {}",
+                span_name,
+                pretty_display_ns(stats.mean),
+                pretty_display_ns(stats.p50),
+                pretty_display_ns(stats.p95),
+                pretty_display_ns(stats.p99),
+                pretty_display_ns(stats.p999),
+            );
         }
 
-        if let Ok(workload_mean_ns) = self.get_mean_workload_duration() {
-            let ops_per_second = workload_size as f64 / (workload_mean_ns as f64 / 1_000_000_000.0);
+        if let Some(ops_per_second) = self.throughput(workload_size) {
             println!("  mean throughput: {ops_per_second:.1} ops/s");
         }
+    }
 
-        // print all other measured spans
-        for (span_name, h) in &self.spans {
-            if expected_spans.contains(span_name) {
-                continue;
-            }
+    /// The results of the run, for writing to a results file.
+    pub fn results(&self, workload_size: u64) -> Results {
+        Results {
+            backend: self.name.clone(),
+            throughput: self.throughput(workload_size),
+            spans: self
+                .span_stats()
+                .into_iter()
+                .map(|(name, stats)| (name.to_string(), stats))
+                .collect(),
+        }
+    }
+}
 
-            println!(
-                "  mean {}: {}",
-                span_name,
-                pretty_display_ns(h.borrow().mean() as u64)
-            )
+/// Latency statistics of a span, in nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpanStats {
+    pub count: u64,
+    pub mean: u64,
+    pub p50: u64,
+    pub p95: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+impl SpanStats {
+    fn of(h: &hdrhistogram::Histogram<u64>) -> Self {
+        SpanStats {
+            count: h.len(),
+            mean: h.mean() as u64,
+            p50: h.value_at_quantile(0.5),
+            p95: h.value_at_quantile(0.95),
+            p99: h.value_at_quantile(0.99),
+            p999: h.value_at_quantile(0.999),
+            max: h.max(),
         }
     }
 }
@@ -0,0 +1,308 @@
+//! Recording and replaying traces of real read and write operations.
+//!
+//! A trace is a text file with one operation per line, in the order the operations were made:
+//!
+//! ```text
+//! <block>,<op>,<key>,<value size>
+//! ```
+//!
+//! where `block` is the number of the block the operation belongs to, `op` is `r` for a read, `w`
+//! for a write or `d` for a delete, `key` is the hex-encoded key and `value size` is the number of
+//! bytes written, which is ignored for reads and deletes. Empty lines and lines starting with `#`
+//! are skipped. Blocks must be non-decreasing.
+//!
+//! Block 0 holds the state the trace starts from: it is written by the `init` command and must
+//! only contain writes. Every later block is replayed as one step of the workload, i.e. one
+//! commit, so that the access patterns of a real chain are reproduced for every backend.
+
+use crate::{backend::Transaction, workload::Workload};
+use anyhow::{bail, Context as _, Result};
+use std::{
+    fs::File,
+    io::{BufRead as _, BufReader, BufWriter, Write as _},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// The number of writes of the initial state made per commit.
+const MAX_INIT_PER_ITERATION: usize = 2 * 1024 * 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Op {
+    Read(Vec<u8>),
+    Write(Vec<u8>, usize),
+    Delete(Vec<u8>),
+}
+
+/// A trace loaded into memory. See the [module docs](self).
+pub struct Trace {
+    /// The operations of block 0.
+    initial: Vec<Op>,
+    /// The operations of every later block, in order.
+    blocks: Vec<Vec<Op>>,
+}
+
+impl Trace {
+    /// Load a trace from a file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("opening trace {}", path.display()))?;
+        let mut trace = Trace {
+            initial: Vec::new(),
+            blocks: Vec::new(),
+        };
+        let mut last_block = 0;
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (block, op) = parse_line(line).with_context(|| format!("trace line {}", i + 1))?;
+            if block < last_block {
+                bail!(
+                    "trace line {}: block {block} after block {last_block}",
+                    i + 1
+                );
+            }
+            if block == 0 {
+                if !matches!(op, Op::Write(..)) {
+                    bail!("trace line {}: block 0 may only contain writes", i + 1);
+                }
+                trace.initial.push(op);
+            } else {
+                if block != last_block {
+                    trace.blocks.push(Vec::new());
+                }
+                // UNWRAP: a block was pushed above for the first operation of every block.
+                trace.blocks.last_mut().unwrap().push(op);
+            }
+            last_block = block;
+        }
+        Ok(trace)
+    }
+}
+
+fn parse_line(line: &str) -> Result<(u64, Op)> {
+    let mut fields = line.split(',').map(str::trim);
+    let (Some(block), Some(op), Some(key), value_size, None) = (
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+    ) else {
+        bail!("expected `<block>,<op>,<key>,<value size>`");
+    };
+    let block = block.parse().context("invalid block number")?;
+    let key = decode_hex(key)?;
+    let op = match op {
+        "r" => Op::Read(key),
+        "d" => Op::Delete(key),
+        "w" => {
+            let Some(value_size) = value_size else {
+                bail!("missing value size of write");
+            };
+            Op::Write(key, value_size.parse().context("invalid value size")?)
+        }
+        op => bail!("unknown operation {op:?}"),
+    };
+    Ok((block, op))
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if hex.len() % 2 != 0 {
+        bail!("odd length of hex-encoded key");
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).context("invalid hex-encoded key"))
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The value written by a replayed write. Only the size of values is traced, so the contents are
+/// derived from the key and the block, making every write change the value.
+fn value(key: &[u8], block: usize, size: usize) -> Vec<u8> {
+    let seed = key.iter().fold(block as u8, |acc, b| acc.wrapping_add(*b));
+    (0..size).map(|i| seed.wrapping_add(i as u8)).collect()
+}
+
+/// Writes the initial state of a trace, block 0.
+pub struct TraceInit {
+    trace: Arc<Trace>,
+    next_op: usize,
+}
+
+impl Workload for TraceInit {
+    fn run_step(&mut self, transaction: &mut dyn Transaction) {
+        let ops = &self.trace.initial[self.next_op..];
+        for op in ops.iter().take(MAX_INIT_PER_ITERATION) {
+            if let Op::Write(key, size) = op {
+                transaction.write(key, Some(&value(key, 0, *size)));
+            }
+        }
+        self.next_op += ops.len().min(MAX_INIT_PER_ITERATION);
+        println!(
+            "populating {:.1}%",
+            100.0 * self.next_op as f64 / self.trace.initial.len().max(1) as f64
+        );
+    }
+
+    fn is_done(&self) -> bool {
+        self.next_op == self.trace.initial.len()
+    }
+}
+
+/// Create a workload writing the initial state of the trace.
+pub fn init(trace: Arc<Trace>) -> TraceInit {
+    TraceInit { trace, next_op: 0 }
+}
+
+/// Replays the blocks of a trace after the initial state, one per step.
+pub struct TraceWorkload {
+    trace: Arc<Trace>,
+    next_block: usize,
+    ops_remaining: u64,
+}
+
+impl Workload for TraceWorkload {
+    fn run_step(&mut self, transaction: &mut dyn Transaction) {
+        let Some(ops) = self.trace.blocks.get(self.next_block) else {
+            return;
+        };
+        // Blocks are numbered from 1, after the initial state.
+        let block = self.next_block + 1;
+        for op in ops {
+            match op {
+                Op::Read(key) => {
+                    let _ = transaction.read(key);
+                }
+                Op::Write(key, size) => transaction.write(key, Some(&value(key, block, *size))),
+                Op::Delete(key) => transaction.write(key, None),
+            }
+        }
+        self.next_block += 1;
+        self.ops_remaining = self.ops_remaining.saturating_sub(ops.len() as u64);
+    }
+
+    fn is_done(&self) -> bool {
+        self.ops_remaining == 0 || self.next_block == self.trace.blocks.len()
+    }
+}
+
+/// Create a workload replaying the trace until its end or until `op_limit` operations are done.
+pub fn build(trace: Arc<Trace>, op_limit: u64) -> TraceWorkload {
+    TraceWorkload {
+        trace,
+        next_block: 0,
+        ops_remaining: op_limit,
+    }
+}
+
+/// Records the operations of workloads to a trace file. See [`RecordingWorkload`].
+pub struct TraceRecorder {
+    out: Mutex<BufWriter<File>>,
+    next_block: AtomicU64,
+}
+
+impl TraceRecorder {
+    /// Create the trace file, replacing any existing one.
+    pub fn create(path: &Path) -> Result<Arc<Self>> {
+        let file =
+            File::create(path).with_context(|| format!("creating trace {}", path.display()))?;
+        Ok(Arc::new(TraceRecorder {
+            out: Mutex::new(BufWriter::new(file)),
+            next_block: AtomicU64::new(1),
+        }))
+    }
+
+    fn append(&self, lines: &str) {
+        let mut out = self.out.lock().unwrap();
+        out.write_all(lines.as_bytes())
+            .and_then(|()| out.flush())
+            .expect("failed to write trace");
+    }
+}
+
+/// A workload whose operations are recorded to a trace, each step as a block of its own.
+///
+/// The steps of a workload writing the initial state all go to block 0.
+pub struct RecordingWorkload {
+    inner: Box<dyn Workload>,
+    recorder: Arc<TraceRecorder>,
+    initial: bool,
+}
+
+impl RecordingWorkload {
+    pub fn new(inner: Box<dyn Workload>, recorder: Arc<TraceRecorder>, initial: bool) -> Self {
+        RecordingWorkload {
+            inner,
+            recorder,
+            initial,
+        }
+    }
+}
+
+impl Workload for RecordingWorkload {
+    fn run_step(&mut self, transaction: &mut dyn Transaction) {
+        let block = if self.initial {
+            0
+        } else {
+            self.recorder.next_block.fetch_add(1, Ordering::Relaxed)
+        };
+        let mut tx = RecordingTransaction {
+            inner: transaction,
+            block,
+            lines: String::new(),
+        };
+        self.inner.run_step(&mut tx);
+        self.recorder.append(&tx.lines);
+    }
+
+    fn is_done(&self) -> bool {
+        self.inner.is_done()
+    }
+}
+
+struct RecordingTransaction<'a> {
+    inner: &'a mut dyn Transaction,
+    block: u64,
+    lines: String,
+}
+
+impl RecordingTransaction<'_> {
+    fn record(&mut self, op: char, key: &[u8], value_size: usize) {
+        self.lines.push_str(&format!(
+            "{},{op},{},{value_size}\n",
+            self.block,
+            encode_hex(key)
+        ));
+    }
+}
+
+impl Transaction for RecordingTransaction<'_> {
+    fn read(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        self.record('r', key, 0);
+        self.inner.read(key)
+    }
+
+    fn note_read(&mut self, key: &[u8], value: Option<Vec<u8>>) {
+        self.record('r', key, 0);
+        self.inner.note_read(key, value);
+    }
+
+    fn write(&mut self, key: &[u8], value: Option<&[u8]>) {
+        match value {
+            Some(value) => self.record('w', key, value.len()),
+            None => self.record('d', key, 0),
+        }
+        self.inner.write(key, value);
+    }
+}
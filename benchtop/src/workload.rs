@@ -11,7 +11,9 @@
 use crate::{
     backend::Transaction,
     cli::{StateItemDistribution, WorkloadParams},
-    custom_workload, transfer_workload,
+    custom_workload,
+    trace_workload::{self, Trace},
+    transfer_workload,
 };
 use anyhow::Result;
 use lru::LruCache;
@@ -40,6 +42,7 @@ pub fn parse(
         fresh,
         cache_size,
         distribution,
+        trace,
         ..
     } = workload_params.clone();
 
@@ -126,6 +129,23 @@ pub fn parse(
                 ),
             ),
         ),
+        "trace" => {
+            let Some(trace) = trace else {
+                anyhow::bail!("the trace workload requires a trace file, see --trace");
+            };
+            if threads != 1 {
+                anyhow::bail!("the trace workload is replayed on a single thread");
+            }
+            let trace = std::sync::Arc::new(Trace::load(&trace)?);
+            (
+                Box::new(trace_workload::init(trace.clone())),
+                dyn_vec(
+                    cache_size,
+                    threads,
+                    vec![trace_workload::build(trace, op_limit)],
+                ),
+            )
+        }
         name => anyhow::bail!("invalid workload name: {}", name),
     })
 }
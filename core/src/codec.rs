@@ -0,0 +1,354 @@
+//! Canonical encodings of proofs, for exchanging them between provers and verifiers.
+//!
+//! With the `scale` feature, the proof types implement SCALE `Encode` and `Decode`. Their encoding
+//! is canonical: every value has exactly one encoding and decoding rejects any other byte string,
+//! such as a trie position with bits set past its depth or a path with more than 256 siblings.
+//! [`encode_versioned`] prefixes the encoding with [`FORMAT_VERSION`] and [`decode_versioned`]
+//! checks the version, enforces [`DecodeLimits`] and rejects trailing bytes.
+//!
+//! With the `serde` feature, the same types implement `Serialize` and `Deserialize`, for use with
+//! self-describing formats. Those encodings are only as canonical as the format used.
+
+#[cfg(feature = "scale")]
+use crate::{
+    multi_proof::MultiPathProof,
+    proof::{PathProof, PathProofTerminal},
+    trie::Node,
+};
+use crate::{
+    page_id::{ChildPageIndex, PageId, ROOT_PAGE_ID},
+    trie_pos::TriePosition,
+};
+
+use bitvec::prelude::*;
+
+#[cfg(all(any(feature = "scale", feature = "serde"), not(feature = "std")))]
+use alloc::vec::Vec;
+
+/// The version of the encoding produced by [`encode_versioned`].
+///
+/// This is bumped on any change to the encoding of any type.
+pub const FORMAT_VERSION: u8 = 1;
+
+/// The largest encoding [`DecodeLimits::default`] allows: 64 MiB.
+pub const DEFAULT_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+/// Limits enforced by [`decode_versioned`] before decoding anything.
+///
+/// Decoding never allocates more than a small multiple of the size of the input, so bounding the
+/// size of the input bounds the memory an untrusted encoding can make the decoder allocate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// The maximum size of an encoding in bytes, including the version byte.
+    pub max_bytes: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+}
+
+/// Errors in decoding a versioned encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input exceeds [`DecodeLimits::max_bytes`].
+    TooLarge,
+    /// The input is empty.
+    MissingVersion,
+    /// The input was encoded in a version other than [`FORMAT_VERSION`].
+    UnsupportedVersion(u8),
+    /// The input is not a canonical encoding, or has trailing bytes.
+    Malformed,
+}
+
+/// Encode a value, prefixed with [`FORMAT_VERSION`].
+#[cfg(feature = "scale")]
+pub fn encode_versioned<T: parity_scale_codec::Encode>(value: &T) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(1 + value.size_hint());
+    bytes.push(FORMAT_VERSION);
+    value.encode_to(&mut bytes);
+    bytes
+}
+
+/// Decode a value encoded with [`encode_versioned`].
+#[cfg(feature = "scale")]
+pub fn decode_versioned<T: parity_scale_codec::Decode>(
+    bytes: &[u8],
+    limits: &DecodeLimits,
+) -> Result<T, DecodeError> {
+    if bytes.len() > limits.max_bytes {
+        return Err(DecodeError::TooLarge);
+    }
+    match bytes.split_first() {
+        None => Err(DecodeError::MissingVersion),
+        Some((&FORMAT_VERSION, mut payload)) => {
+            <T as parity_scale_codec::DecodeAll>::decode_all(&mut payload)
+                .map_err(|_| DecodeError::Malformed)
+        }
+        Some((&version, _)) => Err(DecodeError::UnsupportedVersion(version)),
+    }
+}
+
+/// The depth of a trie position and its path, with the bits past the depth unset.
+fn canonical_position(position: &TriePosition) -> (u16, [u8; 32]) {
+    let mut path = [0u8; 32];
+    let bits = position.path();
+    path.view_bits_mut::<Msb0>()[..bits.len()].copy_from_bitslice(bits);
+    (position.depth(), path)
+}
+
+/// The trie position of the given depth and path, if no bits are set past the depth.
+fn position_from_canonical(depth: u16, path: [u8; 32]) -> Option<TriePosition> {
+    if depth > 256 || path.view_bits::<Msb0>()[depth as usize..].any() {
+        return None;
+    }
+    Some(if depth == 0 {
+        TriePosition::new()
+    } else {
+        TriePosition::from_path_and_depth(path, depth)
+    })
+}
+
+/// The page ID reached from the root by the given child indices, if they are valid.
+///
+/// Page IDs are encoded as these indices rather than with [`PageId::encode`], whose fixed-width
+/// representation [`PageId::decode`] doesn't invert.
+fn page_id_from_path(path: &[u8]) -> Option<PageId> {
+    path.iter().try_fold(ROOT_PAGE_ID, |page_id, &index| {
+        page_id.child_page_id(ChildPageIndex::new(index)?).ok()
+    })
+}
+
+#[cfg(feature = "scale")]
+mod scale {
+    use super::*;
+    use parity_scale_codec::{Decode, Encode, Error, Input, Output};
+
+    impl Encode for TriePosition {
+        fn size_hint(&self) -> usize {
+            2 + 32
+        }
+
+        fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+            let (depth, path) = canonical_position(self);
+            depth.encode_to(dest);
+            path.encode_to(dest);
+        }
+    }
+
+    impl Decode for TriePosition {
+        fn decode<I: Input>(input: &mut I) -> Result<Self, Error> {
+            let depth = u16::decode(input)?;
+            let path = <[u8; 32]>::decode(input)?;
+            position_from_canonical(depth, path).ok_or_else(|| "non-canonical trie position".into())
+        }
+    }
+
+    impl Encode for PageId {
+        fn size_hint(&self) -> usize {
+            self.length_dependent_encoding().size_hint()
+        }
+
+        fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+            self.length_dependent_encoding().encode_to(dest);
+        }
+    }
+
+    impl Decode for PageId {
+        fn decode<I: Input>(input: &mut I) -> Result<Self, Error> {
+            let path = Vec::<u8>::decode(input)?;
+            page_id_from_path(&path).ok_or_else(|| "invalid page ID".into())
+        }
+    }
+
+    impl Encode for PathProof {
+        fn size_hint(&self) -> usize {
+            self.terminal.size_hint() + self.siblings.size_hint()
+        }
+
+        fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+            self.terminal.encode_to(dest);
+            self.siblings.encode_to(dest);
+        }
+    }
+
+    impl Decode for PathProof {
+        fn decode<I: Input>(input: &mut I) -> Result<Self, Error> {
+            let terminal = PathProofTerminal::decode(input)?;
+            let siblings = Vec::<Node>::decode(input)?;
+            if siblings.len() > 256 {
+                return Err("path proof with more than 256 siblings".into());
+            }
+            Ok(PathProof { terminal, siblings })
+        }
+    }
+
+    impl Encode for MultiPathProof {
+        fn size_hint(&self) -> usize {
+            self.terminal.size_hint() + 2
+        }
+
+        fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+            self.terminal.encode_to(dest);
+            (self.depth as u16).encode_to(dest);
+        }
+    }
+
+    impl Decode for MultiPathProof {
+        fn decode<I: Input>(input: &mut I) -> Result<Self, Error> {
+            let terminal = PathProofTerminal::decode(input)?;
+            let depth = u16::decode(input)?;
+            if depth > 256 {
+                return Err("multi path proof deeper than 256".into());
+            }
+            Ok(MultiPathProof {
+                terminal,
+                depth: depth as usize,
+            })
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use super::*;
+    use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+    impl Serialize for TriePosition {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            canonical_position(self).serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for TriePosition {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let (depth, path) = <(u16, [u8; 32])>::deserialize(deserializer)?;
+            position_from_canonical(depth, path)
+                .ok_or_else(|| D::Error::custom("non-canonical trie position"))
+        }
+    }
+
+    impl Serialize for PageId {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.length_dependent_encoding().serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for PageId {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let path = Vec::<u8>::deserialize(deserializer)?;
+            page_id_from_path(&path).ok_or_else(|| D::Error::custom("invalid page ID"))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "scale"))]
+mod tests {
+    use super::*;
+    use crate::{
+        multi_proof::MultiProof,
+        proof::{PathProof, PathProofTerminal},
+        trie::LeafData,
+    };
+
+    fn path_proof() -> PathProof {
+        PathProof {
+            terminal: PathProofTerminal::Leaf(LeafData {
+                key_path: [0b1010_0000; 32],
+                value_hash: [7; 32],
+            }),
+            siblings: vec![[1; 32], [2; 32], [3; 32]],
+        }
+    }
+
+    #[test]
+    fn versioned_round_trip() {
+        let proof = path_proof();
+        let bytes = encode_versioned(&proof);
+        assert_eq!(bytes[0], FORMAT_VERSION);
+        let decoded: PathProof = decode_versioned(&bytes, &DecodeLimits::default()).unwrap();
+        assert_eq!(decoded.terminal, proof.terminal);
+        assert_eq!(decoded.siblings, proof.siblings);
+        assert_eq!(encode_versioned(&decoded), bytes);
+
+        let multi_proof = MultiProof::from_path_proofs(vec![proof]);
+        let bytes = encode_versioned(&multi_proof);
+        let decoded: MultiProof = decode_versioned(&bytes, &DecodeLimits::default()).unwrap();
+        assert_eq!(encode_versioned(&decoded), bytes);
+    }
+
+    #[test]
+    fn version_and_limits_are_enforced() {
+        let mut bytes = encode_versioned(&path_proof());
+        let limits = DecodeLimits {
+            max_bytes: bytes.len() - 1,
+        };
+        assert_eq!(
+            decode_versioned::<PathProof>(&bytes, &limits).err(),
+            Some(DecodeError::TooLarge)
+        );
+        let limits = DecodeLimits::default();
+        assert_eq!(
+            decode_versioned::<PathProof>(&[], &limits).err(),
+            Some(DecodeError::MissingVersion)
+        );
+
+        bytes.push(0);
+        assert_eq!(
+            decode_versioned::<PathProof>(&bytes, &limits).err(),
+            Some(DecodeError::Malformed)
+        );
+        bytes.pop();
+        bytes[0] = FORMAT_VERSION + 1;
+        assert_eq!(
+            decode_versioned::<PathProof>(&bytes, &limits).err(),
+            Some(DecodeError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn non_canonical_positions_are_rejected() {
+        let position = TriePosition::from_path_and_depth([0xff; 32], 3);
+        let bytes = encode_versioned(&position);
+        // Only the first three bits of the path are kept.
+        assert_eq!(&bytes[3..5], &[0b1110_0000, 0]);
+        let decoded: TriePosition = decode_versioned(&bytes, &DecodeLimits::default()).unwrap();
+        assert_eq!(decoded, position);
+
+        let mut bytes = bytes;
+        bytes[3] |= 1;
+        assert_eq!(
+            decode_versioned::<TriePosition>(&bytes, &DecodeLimits::default()).err(),
+            Some(DecodeError::Malformed)
+        );
+
+        let root = encode_versioned(&TriePosition::new());
+        let decoded: TriePosition = decode_versioned(&root, &DecodeLimits::default()).unwrap();
+        assert_eq!(decoded.depth(), 0);
+    }
+
+    #[test]
+    fn page_ids_round_trip() {
+        let limits = DecodeLimits::default();
+        let mut page_id = ROOT_PAGE_ID;
+        for index in [0, 19, 63] {
+            page_id = page_id
+                .child_page_id(ChildPageIndex::new(index).unwrap())
+                .unwrap();
+            let bytes = encode_versioned(&page_id);
+            let decoded: PageId = decode_versioned(&bytes, &limits).unwrap();
+            assert_eq!(decoded, page_id);
+        }
+
+        // Child indices past the last child are rejected.
+        let mut bytes = encode_versioned(&page_id);
+        *bytes.last_mut().unwrap() = 64;
+        assert_eq!(
+            decode_versioned::<PageId>(&bytes, &limits).err(),
+            Some(DecodeError::Malformed)
+        );
+    }
+}
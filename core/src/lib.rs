@@ -10,11 +10,15 @@
 
 extern crate alloc;
 
+#[cfg(any(feature = "scale", feature = "serde"))]
+pub mod codec;
 pub mod multi_proof;
 pub mod multi_proof_verification;
 pub mod page;
 pub mod page_id;
 pub mod proof;
+pub mod smt_proof;
+pub mod subtree_proof;
 pub mod trie;
 pub mod trie_pos;
 pub mod update;
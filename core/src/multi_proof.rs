@@ -11,7 +11,10 @@ use crate::{
 use alloc::{vec, vec::Vec};
 
 /// This struct includes the terminal node and its depth
+///
+/// The SCALE encoding of this type is implemented in [`crate::codec`].
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MultiPathProof {
     /// Terminal node
     pub terminal: PathProofTerminal,
@@ -21,6 +24,11 @@ pub struct MultiPathProof {
 
 /// A proof of multiple paths through the trie.
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "scale",
+    derive(parity_scale_codec::Encode, parity_scale_codec::Decode)
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MultiProof {
     /// List of all provable paths. These are sorted in ascending order by bit-path
     pub paths: Vec<MultiPathProof>,
@@ -1,20 +1,29 @@
 //! Proving and verifying inclusion, non-inclusion, and updates to the trie.
 
 use crate::trie::{
-    self, InternalData, KeyPath, LeafData, Node, NodeHasher, NodeHasherExt, NodeKind, TERMINATOR,
+    self, InternalData, KeyPath, LeafData, Node, NodeHasher, NodeHasherExt, NodeKind, ValueHash,
+    TERMINATOR,
 };
 use crate::trie_pos::TriePosition;
 
 use bitvec::prelude::*;
 
+use alloc::collections::BTreeMap;
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
 
 /// Wrapper for a terminal node, it will store the LeafData if it is a leaf node,
 /// and just the KeyPath to that terminal if it is a terminator node
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "scale",
+    derive(parity_scale_codec::Encode, parity_scale_codec::Decode)
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PathProofTerminal {
+    #[cfg_attr(feature = "scale", codec(index = 0))]
     Leaf(LeafData),
+    #[cfg_attr(feature = "scale", codec(index = 1))]
     Terminator(TriePosition),
 }
 
@@ -36,7 +45,10 @@ impl PathProofTerminal {
 }
 
 /// A proof of some particular path through the trie.
+///
+/// The SCALE encoding of this type is implemented in [`crate::codec`].
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PathProof {
     /// The terminal node encountered when looking up a key. This is always either a terminator or
     /// leaf.
@@ -110,7 +122,7 @@ pub fn hash_path<H: NodeHasher>(
 pub struct KeyOutOfScope;
 
 /// Errors in path proof verification.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PathProofVerificationError {
     /// Amount of provided siblings is impossible for the expected trie depth.
     TooManySiblings,
@@ -129,7 +141,7 @@ pub enum PathProofVerificationError {
 ///
 /// Statement (2) is true for any key which begins with the proven path, where the terminal node is
 /// either not a leaf or contains a value for a different key.
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct VerifiedPathProof {
     key_path: BitVec<u8, Msb0>,
     terminal: Option<LeafData>,
@@ -178,6 +190,26 @@ impl VerifiedPathProof {
         })
     }
 
+    /// Check whether this path resolves to the given leaf, taking an absent key to hold the
+    /// value with the given hash. This suits keys with a protocol-defined default value, which
+    /// are not stored while they hold it.
+    ///
+    /// A return value of `Ok(true)` confirms that the key has this value, whether stored in the
+    /// trie or by default. `Ok(false)` confirms that it has a different one.
+    ///
+    /// Fails if the key is out of the scope of this path.
+    pub fn confirm_value_or_default(
+        &self,
+        expected_leaf: &LeafData,
+        default_value_hash: ValueHash,
+    ) -> Result<bool, KeyOutOfScope> {
+        if self.confirm_value(expected_leaf)? {
+            return Ok(true);
+        }
+        Ok(expected_leaf.value_hash == default_value_hash
+            && self.confirm_nonexistence(&expected_leaf.key_path)?)
+    }
+
     fn in_scope(&self, key_path: &KeyPath) -> Result<(), KeyOutOfScope> {
         let this_path = self.path();
         let other_path = &key_path.view_bits::<Msb0>()[..self.key_path.len()];
@@ -189,6 +221,67 @@ impl VerifiedPathProof {
     }
 }
 
+/// Errors in [`verify_prior_values`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriorValuesError {
+    /// A path proof does not verify against the root.
+    InvalidPath {
+        /// The index of the path proof.
+        path_index: usize,
+        /// The reason the path proof is invalid.
+        error: PathProofVerificationError,
+    },
+    /// A key refers to a path proof which doesn't exist.
+    MissingPath {
+        /// The key.
+        key: KeyPath,
+    },
+    /// A key is out of the scope of the path proof it refers to.
+    KeyOutOfScope {
+        /// The key.
+        key: KeyPath,
+    },
+}
+
+/// Verify path proofs against the root and return the values of the given keys as of the root,
+/// e.g. to re-execute the operations covered by a witness without access to the state.
+///
+/// The path proofs are given along with their query paths. Every key is given along with the
+/// index of the path proof covering it. Path proofs are verified once, and only if a key refers
+/// to them. The values are returned as value hashes, `None` meaning that the key has no value.
+pub fn verify_prior_values<'a, H: NodeHasher>(
+    root: Node,
+    paths: impl IntoIterator<Item = (&'a BitSlice<u8, Msb0>, &'a PathProof)>,
+    keys: impl IntoIterator<Item = (KeyPath, usize)>,
+) -> Result<BTreeMap<KeyPath, Option<ValueHash>>, PriorValuesError> {
+    let mut paths = paths
+        .into_iter()
+        .map(|path| (path, None))
+        .collect::<Vec<_>>();
+    let mut values = BTreeMap::new();
+    for (key, path_index) in keys {
+        let Some(((query_path, proof), verified)) = paths.get_mut(path_index) else {
+            return Err(PriorValuesError::MissingPath { key });
+        };
+        let verified: &VerifiedPathProof = match verified {
+            Some(verified) => verified,
+            None => verified.insert(
+                proof
+                    .verify::<H>(query_path, root)
+                    .map_err(|error| PriorValuesError::InvalidPath { path_index, error })?,
+            ),
+        };
+        let value = match verified.confirm_nonexistence(&key) {
+            Err(KeyOutOfScope) => return Err(PriorValuesError::KeyOutOfScope { key }),
+            Ok(true) => None,
+            // UNWRAP: the key exists, so the path ends at its leaf.
+            Ok(false) => Some(verified.terminal().unwrap().value_hash),
+        };
+        values.insert(key, value);
+    }
+    Ok(values)
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum VerifyUpdateError {
     PathsOutOfOrder,
@@ -218,12 +311,134 @@ pub fn verify_update<H: NodeHasher>(
     prev_root: Node,
     paths: &[PathUpdate],
 ) -> Result<Node, VerifyUpdateError> {
+    check_update(prev_root, paths)?;
+    let items = paths
+        .iter()
+        .map(|path| FoldItem {
+            path: path.inner.path(),
+            siblings: &path.inner.siblings,
+            sub_root: updated_sub_root::<H>(path),
+        })
+        .collect::<Vec<_>>();
+    Ok(fold_items::<H>(&items, 0))
+}
+
+/// The depth at which [`verify_update_parallel`] splits the trie into subtrees verified
+/// independently of each other.
+#[cfg(feature = "parallel")]
+const PARALLEL_SPLIT_DEPTH: usize = 8;
+
+/// Verify path proofs against the root on the rayon thread pool, returning the verified paths in
+/// the order of the given ones. See [`PathProof::verify`].
+///
+/// The path proofs are given along with their query paths. Run this within
+/// `rayon::ThreadPool::install` to use a pool other than the global one.
+#[cfg(feature = "parallel")]
+pub fn verify_parallel<H: NodeHasher>(
+    root: Node,
+    paths: &[(&BitSlice<u8, Msb0>, &PathProof)],
+) -> Result<Vec<VerifiedPathProof>, InvalidPathProof> {
+    use rayon::prelude::*;
+
+    paths
+        .par_iter()
+        .enumerate()
+        .map(|(path_index, (query_path, proof))| {
+            proof
+                .verify::<H>(query_path, root)
+                .map_err(|error| InvalidPathProof { path_index, error })
+        })
+        .collect()
+}
+
+/// Verify an update operation against the root node like [`verify_update`], on the rayon thread
+/// pool.
+///
+/// The paths are grouped by the subtree they fall into at a fixed depth. The subtrees are updated
+/// independently of each other, and their new roots are then hashed up to the new root. Paths
+/// ending above that depth are handled along with the subtree roots. The result is the same as
+/// that of [`verify_update`]. Run this within `rayon::ThreadPool::install` to use a pool other
+/// than the global one.
+#[cfg(feature = "parallel")]
+pub fn verify_update_parallel<H: NodeHasher>(
+    prev_root: Node,
+    paths: &[PathUpdate],
+) -> Result<Node, VerifyUpdateError> {
+    use rayon::prelude::*;
+
+    check_update(prev_root, paths)?;
+
+    // Runs of consecutive paths below the split depth which share the subtree there. Paths ending
+    // at or above the split depth form runs of their own.
+    let mut runs = Vec::new();
+    let mut start = 0;
+    while start < paths.len() {
+        let path = paths[start].inner.path();
+        let mut end = start + 1;
+        if path.len() > PARALLEL_SPLIT_DEPTH {
+            let prefix = &path[..PARALLEL_SPLIT_DEPTH];
+            while paths.get(end).is_some_and(|next| {
+                let next = next.inner.path();
+                next.len() > PARALLEL_SPLIT_DEPTH && next[..PARALLEL_SPLIT_DEPTH] == *prefix
+            }) {
+                end += 1;
+            }
+        }
+        runs.push(start..end);
+        start = end;
+    }
+
+    let sub_roots = runs
+        .par_iter()
+        .map(|run| {
+            let run = &paths[run.clone()];
+            if run[0].inner.path().len() <= PARALLEL_SPLIT_DEPTH {
+                return updated_sub_root::<H>(&run[0]);
+            }
+            let items = run
+                .par_iter()
+                .map(|path| FoldItem {
+                    path: path.inner.path(),
+                    siblings: &path.inner.siblings,
+                    sub_root: updated_sub_root::<H>(path),
+                })
+                .collect::<Vec<_>>();
+            fold_items::<H>(&items, PARALLEL_SPLIT_DEPTH)
+        })
+        .collect::<Vec<_>>();
+
+    let items = runs
+        .iter()
+        .zip(sub_roots)
+        .map(|(run, sub_root)| {
+            let first = &paths[run.start].inner;
+            let depth = core::cmp::min(first.path().len(), PARALLEL_SPLIT_DEPTH);
+            FoldItem {
+                path: &first.path()[..depth],
+                siblings: &first.siblings[..depth],
+                sub_root,
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(fold_items::<H>(&items, 0))
+}
+
+/// A path proof which failed verification in [`verify_parallel`].
+#[cfg(feature = "parallel")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidPathProof {
+    /// The index of the path proof.
+    pub path_index: usize,
+    /// The reason the path proof is invalid.
+    pub error: PathProofVerificationError,
+}
+
+/// Check the roots, the order and the scope of the paths and their ops. See [`verify_update`].
+fn check_update(prev_root: Node, paths: &[PathUpdate]) -> Result<(), VerifyUpdateError> {
     if paths.iter().any(|p| p.inner.root() != prev_root) {
         return Err(VerifyUpdateError::RootMismatch);
     }
 
-    // left frontier
-    let mut pending_siblings: Vec<(Node, usize)> = Vec::new();
     for (i, path) in paths.iter().enumerate() {
         if i != 0 && paths[i - 1].inner.path() >= path.inner.path() {
             return Err(VerifyUpdateError::PathsOutOfOrder);
@@ -242,38 +457,55 @@ pub fn verify_update<H: NodeHasher>(
         if path.ops.is_empty() {
             return Err(VerifyUpdateError::PathWithoutOps);
         }
+    }
+    Ok(())
+}
 
-        let leaf = path.inner.terminal().map(|x| x.clone());
-        let ops = &path.ops;
-        let skip = path.inner.path().len();
+/// The root of the sub-trie at the end of the path after applying its ops.
+fn updated_sub_root<H: NodeHasher>(path: &PathUpdate) -> Node {
+    let leaf = path.inner.terminal().cloned();
+    let ops = crate::update::leaf_ops_spliced(leaf, &path.ops);
+    crate::update::build_trie::<H>(path.inner.path().len(), ops, |_| {})
+}
+
+/// A path along with its siblings and the new node at its end.
+struct FoldItem<'a> {
+    path: &'a BitSlice<u8, Msb0>,
+    siblings: &'a [Node],
+    sub_root: Node,
+}
 
-        let up_layers = match paths.get(i + 1) {
-            None => skip, // go to root
-            Some(p) => {
-                let n = shared_bits(p.inner.path(), path.inner.path());
+/// Hash the new nodes at the ends of ascending paths up to the given layer, which all the paths
+/// must be below, returning the new node there.
+fn fold_items<H: NodeHasher>(items: &[FoldItem], end_layer: usize) -> Node {
+    // left frontier
+    let mut pending_siblings: Vec<(Node, usize)> = Vec::new();
+    for (i, item) in items.iter().enumerate() {
+        let skip = item.path.len();
+
+        let up_layers = match items.get(i + 1) {
+            None => skip - end_layer, // go to the end layer
+            Some(next) => {
+                let n = shared_bits(next.path, item.path);
                 // n always < skip
                 // we want to end at layer n + 1
                 skip - (n + 1)
             }
         };
 
-        let ops = crate::update::leaf_ops_spliced(leaf, ops);
-        let sub_root = crate::update::build_trie::<H>(skip, ops, |_| {});
-
-        let mut cur_node = sub_root;
+        let mut cur_node = item.sub_root;
         let mut cur_layer = skip;
         let end_layer = skip - up_layers;
         // iterate siblings up to the point of collision with next path, replacing with pending
         // siblings, and compacting where possible.
         // push (node, end_layer) to pending siblings when done.
-        for (bit, sibling) in path
-            .inner
-            .path()
+        for (bit, sibling) in item
+            .path
             .iter()
             .by_vals()
             .rev()
             .take(up_layers)
-            .zip(path.inner.siblings.iter().rev())
+            .zip(item.siblings.iter().rev())
         {
             let sibling = if pending_siblings.last().map_or(false, |p| p.1 == cur_layer) {
                 // unwrap: checked above
@@ -310,8 +542,9 @@ pub fn verify_update<H: NodeHasher>(
         pending_siblings.push((cur_node, end_layer));
     }
 
-    // The last `path` iterates up to layer 0, so this always stores the root if paths nonempty.
-    Ok(pending_siblings.pop().map(|n| n.0).unwrap_or(TERMINATOR))
+    // The last item iterates up to the end layer, so this always stores the node there if items
+    // are nonempty.
+    pending_siblings.pop().map(|n| n.0).unwrap_or(TERMINATOR)
 }
 
 // TODO: dedup, this appears in `update` as well.
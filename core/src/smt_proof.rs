@@ -0,0 +1,260 @@
+//! Path proofs in the bitmap-and-siblings encoding of sparse merkle trees.
+//!
+//! Sparse merkle tree libraries commonly encode a proof as a bitmap with one bit per level of the
+//! path, set when the sibling at that level is not the empty sub-trie, followed by only the
+//! non-empty siblings. [`SmtProof`] converts a [`PathProof`] to and from this encoding, so that
+//! verifiers which already parse such proofs can consume NOMT proofs. The nodes are hashed as in
+//! NOMT: empty sub-tries are [`TERMINATOR`]s and a path ends at the first leaf or terminator,
+//! possibly above the full depth of 256.
+
+use crate::{
+    proof::{PathProof, PathProofTerminal, PathProofVerificationError, VerifiedPathProof},
+    trie::{is_terminator, KeyPath, LeafData, Node, NodeHasher, TERMINATOR},
+    trie_pos::TriePosition,
+};
+
+use bitvec::prelude::*;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+/// A path proof encoded as a bitmap of non-empty siblings followed by those siblings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmtProof {
+    /// The depth of the terminal node, i.e. the number of levels covered by the bitmap.
+    pub depth: u16,
+    /// Bit `i`, most significant bit of the first byte first, is set if the sibling at depth
+    /// `i + 1` is not a terminator. Bits at and beyond `depth` are unset.
+    pub bitmap: [u8; 32],
+    /// The non-terminator siblings, from the root downwards.
+    pub siblings: Vec<Node>,
+    /// The leaf at the end of the path. `None` means the path ends at a terminator.
+    pub leaf: Option<LeafData>,
+}
+
+/// Errors in decoding or verifying an [`SmtProof`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtProofError {
+    /// The encoding is malformed, or the bitmap doesn't match the depth or the siblings.
+    Malformed,
+    /// The proof does not verify against the root.
+    Verification(PathProofVerificationError),
+}
+
+impl SmtProof {
+    /// Encode a path proof.
+    pub fn from_path_proof(proof: &PathProof) -> Self {
+        let mut bitmap = [0; 32];
+        let mut siblings = Vec::new();
+        for (i, sibling) in proof.siblings.iter().enumerate() {
+            if !is_terminator(sibling) {
+                bitmap.view_bits_mut::<Msb0>().set(i, true);
+                siblings.push(*sibling);
+            }
+        }
+        SmtProof {
+            depth: proof.siblings.len() as u16,
+            bitmap,
+            siblings,
+            leaf: match proof.terminal {
+                PathProofTerminal::Leaf(ref leaf) => Some(leaf.clone()),
+                PathProofTerminal::Terminator(_) => None,
+            },
+        }
+    }
+
+    /// Restore the path proof of the given key.
+    ///
+    /// Fails if the bitmap doesn't match the depth or the siblings.
+    pub fn to_path_proof(&self, key_path: &KeyPath) -> Result<PathProof, SmtProofError> {
+        self.check_bitmap()?;
+        let mut non_empty = self.siblings.iter();
+        let siblings = self.bitmap.view_bits::<Msb0>()[..self.depth as usize]
+            .iter()
+            .by_vals()
+            // UNWRAP: the number of set bits matches the number of siblings.
+            .map(|set| {
+                if set {
+                    *non_empty.next().unwrap()
+                } else {
+                    TERMINATOR
+                }
+            })
+            .collect();
+        let terminal = match self.leaf {
+            Some(ref leaf) => PathProofTerminal::Leaf(leaf.clone()),
+            None => PathProofTerminal::Terminator(TriePosition::from_path_and_depth(
+                *key_path, self.depth,
+            )),
+        };
+        Ok(PathProof { terminal, siblings })
+    }
+
+    /// Verify the proof of the given key against the root.
+    pub fn verify<H: NodeHasher>(
+        &self,
+        key_path: &KeyPath,
+        root: Node,
+    ) -> Result<VerifiedPathProof, SmtProofError> {
+        self.to_path_proof(key_path)?
+            .verify::<H>(key_path.view_bits(), root)
+            .map_err(SmtProofError::Verification)
+    }
+
+    /// Encode the proof as the depth (2 bytes, little-endian), the bitmap (32 bytes), a flag byte
+    /// followed by the key path and the value hash if the path ends at a leaf, and finally the
+    /// siblings.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(99 + self.siblings.len() * 32);
+        buf.extend_from_slice(&self.depth.to_le_bytes());
+        buf.extend_from_slice(&self.bitmap);
+        match self.leaf {
+            None => buf.push(0),
+            Some(ref leaf) => {
+                buf.push(1);
+                buf.extend_from_slice(&leaf.key_path);
+                buf.extend_from_slice(&leaf.value_hash);
+            }
+        }
+        for sibling in &self.siblings {
+            buf.extend_from_slice(sibling);
+        }
+        buf
+    }
+
+    /// Decode a proof encoded with [`SmtProof::encode`].
+    pub fn decode(buf: &[u8]) -> Result<Self, SmtProofError> {
+        let (depth, buf) = buf
+            .split_first_chunk::<2>()
+            .ok_or(SmtProofError::Malformed)?;
+        let (bitmap, buf) = buf
+            .split_first_chunk::<32>()
+            .ok_or(SmtProofError::Malformed)?;
+        let (flag, mut buf) = buf.split_first().ok_or(SmtProofError::Malformed)?;
+        let leaf = match flag {
+            0 => None,
+            1 => {
+                let (key_path, rest) = buf
+                    .split_first_chunk::<32>()
+                    .ok_or(SmtProofError::Malformed)?;
+                let (value_hash, rest) = rest
+                    .split_first_chunk::<32>()
+                    .ok_or(SmtProofError::Malformed)?;
+                buf = rest;
+                Some(LeafData {
+                    key_path: *key_path,
+                    value_hash: *value_hash,
+                })
+            }
+            _ => return Err(SmtProofError::Malformed),
+        };
+        if buf.len() % 32 != 0 {
+            return Err(SmtProofError::Malformed);
+        }
+        let proof = SmtProof {
+            depth: u16::from_le_bytes(*depth),
+            bitmap: *bitmap,
+            // UNWRAP: the chunks are 32 bytes long.
+            siblings: buf
+                .chunks_exact(32)
+                .map(|s| s.try_into().unwrap())
+                .collect(),
+            leaf,
+        };
+        proof.check_bitmap()?;
+        Ok(proof)
+    }
+
+    fn check_bitmap(&self) -> Result<(), SmtProofError> {
+        let bits = self.bitmap.view_bits::<Msb0>();
+        if self.depth > 256
+            || bits[self.depth as usize..].any()
+            || bits.count_ones() != self.siblings.len()
+        {
+            return Err(SmtProofError::Malformed);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SmtProof, SmtProofError};
+    use crate::{
+        proof::{hash_path, PathProof, PathProofTerminal},
+        trie::{self, LeafData, NodeHasher, NodeHasherExt, TERMINATOR},
+        trie_pos::TriePosition,
+    };
+    use bitvec::prelude::*;
+
+    struct DummyNodeHasher;
+
+    impl NodeHasher for DummyNodeHasher {
+        fn hash_node(data: &trie::NodePreimage) -> [u8; 32] {
+            blake3::hash(data).into()
+        }
+    }
+
+    #[test]
+    fn roundtrip_and_verify() {
+        let leaf = LeafData {
+            key_path: [0b0100_0000; 32],
+            value_hash: [1; 32],
+        };
+        let sibling = DummyNodeHasher::hash_leaf(&LeafData {
+            key_path: [0; 32],
+            value_hash: [2; 32],
+        });
+        // The leaf sits at depth 3, with an empty sub-trie at depth 1.
+        let proof = PathProof {
+            terminal: PathProofTerminal::Leaf(leaf.clone()),
+            siblings: vec![TERMINATOR, [3; 32], sibling],
+        };
+        let root = hash_path::<DummyNodeHasher>(
+            DummyNodeHasher::hash_leaf(&leaf),
+            &leaf.key_path.view_bits::<Msb0>()[..3],
+            proof.siblings.iter().rev().cloned(),
+        );
+
+        let smt = SmtProof::from_path_proof(&proof);
+        assert_eq!(smt.depth, 3);
+        assert_eq!(smt.bitmap[0], 0b0110_0000);
+        assert_eq!(smt.siblings, vec![[3; 32], sibling]);
+
+        let decoded = SmtProof::decode(&smt.encode()).unwrap();
+        assert_eq!(decoded, smt);
+        let verified = decoded
+            .verify::<DummyNodeHasher>(&leaf.key_path, root)
+            .unwrap();
+        assert!(verified.confirm_value(&leaf).unwrap());
+
+        let restored = decoded.to_path_proof(&leaf.key_path).unwrap();
+        assert_eq!(restored.siblings, proof.siblings);
+    }
+
+    #[test]
+    fn terminator_and_malformed() {
+        let key_path = [0xff; 32];
+        let proof = PathProof {
+            terminal: PathProofTerminal::Terminator(TriePosition::from_path_and_depth(key_path, 1)),
+            siblings: vec![[5; 32]],
+        };
+        let smt = SmtProof::from_path_proof(&proof);
+        let mut encoded = smt.encode();
+        assert_eq!(SmtProof::decode(&encoded).unwrap(), smt);
+        assert_eq!(
+            smt.to_path_proof(&key_path).unwrap().terminal,
+            proof.terminal
+        );
+
+        // A bit beyond the depth.
+        encoded[2] |= 0b0100_0000;
+        assert_eq!(SmtProof::decode(&encoded), Err(SmtProofError::Malformed));
+        // A truncated sibling.
+        let encoded = smt.encode();
+        assert_eq!(
+            SmtProof::decode(&encoded[..encoded.len() - 1]),
+            Err(SmtProofError::Malformed)
+        );
+    }
+}
@@ -0,0 +1,251 @@
+//! Proofs of the root of the subtree holding all keys under a prefix.
+//!
+//! The root of the subtree under a prefix is the node at the position of the prefix, as long as
+//! the subtree holds two keys or more. As the trie is compacted, a subtree holding a single key
+//! is the leaf of that key, which may sit above the position of the prefix, and an empty subtree
+//! is a [`TERMINATOR`], which may sit above it as well. A [`SubtreeProof`] carries the siblings
+//! along the prefix down to where its path ends, and what is found there.
+
+use crate::{
+    proof::hash_path,
+    trie::{LeafData, Node, NodeHasher, NodeHasherExt, TERMINATOR},
+};
+
+use bitvec::prelude::*;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+/// The node at the end of the path of a [`SubtreeProof`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "scale",
+    derive(parity_scale_codec::Encode, parity_scale_codec::Decode)
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SubtreeProofTerminal {
+    /// The node at the position of the prefix, which is the root of the subtree.
+    #[cfg_attr(feature = "scale", codec(index = 0))]
+    Node(Node),
+    /// A leaf at or above the position of the prefix. The subtree holds the key of the leaf if it
+    /// starts with the prefix, and is empty otherwise.
+    #[cfg_attr(feature = "scale", codec(index = 1))]
+    Leaf(LeafData),
+    /// A terminator at or above the position of the prefix: the subtree is empty.
+    #[cfg_attr(feature = "scale", codec(index = 2))]
+    Terminator,
+}
+
+/// A proof of the root of the subtree under a prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "scale",
+    derive(parity_scale_codec::Encode, parity_scale_codec::Decode)
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SubtreeProof {
+    /// The node found at the end of the path.
+    pub terminal: SubtreeProofTerminal,
+    /// Sibling nodes along the prefix, from the root downwards. Their number is the depth of the
+    /// terminal.
+    pub siblings: Vec<Node>,
+}
+
+/// Errors in verifying a [`SubtreeProof`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtreeProofError {
+    /// The prefix is longer than a key path.
+    PrefixTooLong,
+    /// There are more siblings than bits in the prefix, or a node other than a leaf or terminator
+    /// is given above the position of the prefix.
+    WrongDepth,
+    /// The leaf is not on the path of the prefix.
+    LeafOffPath,
+    /// Root hash mismatched at the end of the verification.
+    RootMismatch,
+}
+
+/// The root of a subtree, as proven by a [`SubtreeProof`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedSubtree {
+    /// The root of the subtree. This is [`TERMINATOR`] if the subtree is empty.
+    pub root: Node,
+    /// The only leaf of the subtree, if it holds exactly one key which sits above the position of
+    /// the prefix or at it.
+    pub leaf: Option<LeafData>,
+}
+
+impl SubtreeProof {
+    /// Verify this proof of the root of the subtree under the given prefix against the root of
+    /// the trie.
+    pub fn verify<H: NodeHasher>(
+        &self,
+        prefix: &BitSlice<u8, Msb0>,
+        root: Node,
+    ) -> Result<VerifiedSubtree, SubtreeProofError> {
+        if prefix.len() > 256 {
+            return Err(SubtreeProofError::PrefixTooLong);
+        }
+        let depth = self.siblings.len();
+        if depth > prefix.len() {
+            return Err(SubtreeProofError::WrongDepth);
+        }
+
+        let (node, subtree) = match self.terminal {
+            SubtreeProofTerminal::Node(node) => {
+                if depth != prefix.len() {
+                    return Err(SubtreeProofError::WrongDepth);
+                }
+                let subtree = VerifiedSubtree {
+                    root: node,
+                    leaf: None,
+                };
+                (node, subtree)
+            }
+            SubtreeProofTerminal::Leaf(ref leaf) => {
+                let key_path = leaf.key_path.view_bits::<Msb0>();
+                if key_path[..depth] != prefix[..depth] {
+                    return Err(SubtreeProofError::LeafOffPath);
+                }
+                let node = H::hash_leaf(leaf);
+                let subtree = if key_path[..prefix.len()] == *prefix {
+                    VerifiedSubtree {
+                        root: node,
+                        leaf: Some(leaf.clone()),
+                    }
+                } else {
+                    VerifiedSubtree {
+                        root: TERMINATOR,
+                        leaf: None,
+                    }
+                };
+                (node, subtree)
+            }
+            SubtreeProofTerminal::Terminator => {
+                let subtree = VerifiedSubtree {
+                    root: TERMINATOR,
+                    leaf: None,
+                };
+                (TERMINATOR, subtree)
+            }
+        };
+
+        let new_root = hash_path::<H>(node, &prefix[..depth], self.siblings.iter().rev().cloned());
+        if new_root == root {
+            Ok(subtree)
+        } else {
+            Err(SubtreeProofError::RootMismatch)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SubtreeProof, SubtreeProofError, SubtreeProofTerminal};
+    use crate::{
+        proof::hash_path,
+        trie::{self, LeafData, NodeHasher, NodeHasherExt, TERMINATOR},
+    };
+    use bitvec::prelude::*;
+
+    struct DummyNodeHasher;
+
+    impl NodeHasher for DummyNodeHasher {
+        fn hash_node(data: &trie::NodePreimage) -> [u8; 32] {
+            blake3::hash(data).into()
+        }
+    }
+
+    fn leaf(first_byte: u8) -> LeafData {
+        let mut key_path = [0; 32];
+        key_path[0] = first_byte;
+        LeafData {
+            key_path,
+            value_hash: [first_byte; 32],
+        }
+    }
+
+    #[test]
+    fn internal_subtree_root() {
+        // The subtree under `01` is an internal node at depth 2.
+        let subtree_root = [7; 32];
+        let siblings = vec![[1; 32], [2; 32]];
+        let prefix = bits![u8, Msb0; 0, 1];
+        let root =
+            hash_path::<DummyNodeHasher>(subtree_root, prefix, siblings.iter().rev().cloned());
+        let proof = SubtreeProof {
+            terminal: SubtreeProofTerminal::Node(subtree_root),
+            siblings,
+        };
+
+        let verified = proof.verify::<DummyNodeHasher>(prefix, root).unwrap();
+        assert_eq!(verified.root, subtree_root);
+        assert_eq!(verified.leaf, None);
+
+        assert_eq!(
+            proof.verify::<DummyNodeHasher>(bits![u8, Msb0; 0, 0], root),
+            Err(SubtreeProofError::RootMismatch)
+        );
+        assert_eq!(
+            proof.verify::<DummyNodeHasher>(bits![u8, Msb0; 0, 1, 1], root),
+            Err(SubtreeProofError::WrongDepth)
+        );
+    }
+
+    #[test]
+    fn single_leaf_above_the_prefix() {
+        // A leaf at depth 1 under `1`: the subtree under `101` holds its key, the one under `11`
+        // is empty.
+        let leaf = leaf(0b1010_0000);
+        let siblings = vec![[1; 32]];
+        let root = hash_path::<DummyNodeHasher>(
+            DummyNodeHasher::hash_leaf(&leaf),
+            bits![u8, Msb0; 1],
+            siblings.iter().rev().cloned(),
+        );
+        let proof = SubtreeProof {
+            terminal: SubtreeProofTerminal::Leaf(leaf.clone()),
+            siblings,
+        };
+
+        let verified = proof
+            .verify::<DummyNodeHasher>(bits![u8, Msb0; 1, 0, 1], root)
+            .unwrap();
+        assert_eq!(verified.root, DummyNodeHasher::hash_leaf(&leaf));
+        assert_eq!(verified.leaf, Some(leaf));
+
+        let verified = proof
+            .verify::<DummyNodeHasher>(bits![u8, Msb0; 1, 1], root)
+            .unwrap();
+        assert_eq!(verified.root, TERMINATOR);
+        assert_eq!(verified.leaf, None);
+
+        assert_eq!(
+            proof.verify::<DummyNodeHasher>(bits![u8, Msb0; 0, 1], root),
+            Err(SubtreeProofError::LeafOffPath)
+        );
+    }
+
+    #[test]
+    fn empty_subtree() {
+        let siblings = vec![[1; 32], [2; 32]];
+        let root = hash_path::<DummyNodeHasher>(
+            TERMINATOR,
+            bits![u8, Msb0; 1, 1],
+            siblings.iter().rev().cloned(),
+        );
+        let proof = SubtreeProof {
+            terminal: SubtreeProofTerminal::Terminator,
+            siblings,
+        };
+
+        let verified = proof
+            .verify::<DummyNodeHasher>(bits![u8, Msb0; 1, 1, 0, 0], root)
+            .unwrap();
+        assert_eq!(verified.root, TERMINATOR);
+        assert_eq!(
+            proof.verify::<DummyNodeHasher>(bits![u8, Msb0; 1], root),
+            Err(SubtreeProofError::WrongDepth)
+        );
+    }
+}
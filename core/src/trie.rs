@@ -98,6 +98,11 @@ impl InternalData {
 
 /// The data of a leaf node.
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "scale",
+    derive(parity_scale_codec::Encode, parity_scale_codec::Decode)
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LeafData {
     /// The total path to this value within the trie.
     ///
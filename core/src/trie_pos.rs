@@ -241,7 +241,7 @@ impl TriePosition {
 
     /// Fast path for checking whether this is in the first layer in the page.
     pub fn is_first_layer_in_page(&self) -> bool {
-        self.node_index & 1 == 0
+        self.node_index & !1 == 0
     }
 
     /// Get the number of shared bits between this position and `other`.
@@ -360,4 +360,20 @@ mod tests {
         assert_eq!(p.depth as usize, 255);
         p.down(false);
     }
+
+    #[test]
+    fn first_layer_in_page() {
+        assert!(TriePosition::from_str("0").is_first_layer_in_page());
+        assert!(TriePosition::from_str("1").is_first_layer_in_page());
+        assert!(!TriePosition::from_str("10").is_first_layer_in_page());
+        assert!(!TriePosition::from_str("101010").is_first_layer_in_page());
+
+        let mut p = TriePosition::from_str("101010");
+        p.down(false);
+        assert!(p.is_first_layer_in_page());
+        p.sibling();
+        assert!(p.is_first_layer_in_page());
+        p.down(true);
+        assert!(!p.is_first_layer_in_page());
+    }
 }
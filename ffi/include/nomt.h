@@ -0,0 +1,137 @@
+/*
+ * C bindings for NOMT, the Nearly-Optimal Merkle Trie Database.
+ *
+ * Link against the `nomt_ffi` library built from the `nomt-ffi` crate, either the shared
+ * (`libnomt_ffi.so`) or the static (`libnomt_ffi.a`) one. The trie is hashed with BLAKE3. Keys
+ * are 32-byte key paths, which should be uniformly distributed, e.g. hashes of the actual keys.
+ *
+ * Errors
+ * ------
+ * Every fallible function returns a `nomt_status`. On `NOMT_ERROR` and `NOMT_PANIC`, a message
+ * describing the failure can be obtained with `nomt_last_error` on the same thread. Outputs are
+ * only written on `NOMT_OK`, except where noted otherwise.
+ *
+ * Memory ownership
+ * ----------------
+ * Memory is freed by the side which allocated it:
+ *  - Buffers passed to NOMT, i.e. paths, keys and values, are copied. They remain owned by the
+ *    caller and may be released as soon as the call returns.
+ *  - Buffers returned by NOMT, i.e. values and witnesses, are owned by the caller until released
+ *    with `nomt_buffer_free`, exactly once. They must not be released with `free`.
+ *  - Handles are owned by the caller until released: databases with `nomt_close`, sessions with
+ *    either `nomt_session_commit` or `nomt_session_free`. All sessions of a database must be
+ *    released before closing it.
+ *  - Error messages are owned by NOMT and valid until the next call on the same thread.
+ *
+ * Threads
+ * -------
+ * A database handle may be used from multiple threads concurrently. A session handle must not be
+ * used from multiple threads concurrently, and only one session may be active per database.
+ */
+
+#ifndef NOMT_H
+#define NOMT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define NOMT_KEY_LEN 32
+#define NOMT_ROOT_LEN 32
+
+typedef enum nomt_status {
+    /* The call succeeded. */
+    NOMT_OK = 0,
+    /* The operation failed, see `nomt_last_error`. */
+    NOMT_ERROR = 1,
+    /* A required pointer was null or an argument was malformed. Nothing was done. */
+    NOMT_INVALID_ARGUMENT = 2,
+    /* NOMT panicked. The handles involved must not be used anymore, except for releasing them. */
+    NOMT_PANIC = 3,
+} nomt_status;
+
+/* An open database. */
+typedef struct nomt_db nomt_db;
+
+/* A session of a database, along with the reads and writes made through it. */
+typedef struct nomt_session nomt_session;
+
+/* A buffer allocated by NOMT. Owned by the caller until released with `nomt_buffer_free`. */
+typedef struct nomt_buffer {
+    /* The start of the buffer. Null for an empty buffer. */
+    uint8_t *ptr;
+    /* The length of the buffer in bytes. */
+    size_t len;
+} nomt_buffer;
+
+/* Returns the message of the last error on the calling thread, or null if there was none. */
+const char *nomt_last_error(void);
+
+/*
+ * Open the database at the nul-terminated `path`, creating it if it doesn't exist.
+ * `commit_concurrency` is the number of threads updating the trie and must be greater than zero.
+ */
+nomt_status nomt_open(const char *path, uint32_t commit_concurrency, nomt_db **out);
+
+/* Close the database. Null is ignored. */
+void nomt_close(nomt_db *db);
+
+/* Write the root of the trie to `out_root`, which must hold `NOMT_ROOT_LEN` bytes. */
+nomt_status nomt_root(const nomt_db *db, uint8_t *out_root);
+
+/* Begin a session, recording a witness if `record_witness` is non-zero. */
+nomt_status nomt_session_begin(const nomt_db *db, int record_witness, nomt_session **out);
+
+/* Release a session without committing it. Null is ignored. */
+void nomt_session_free(nomt_session *session);
+
+/*
+ * Read the value of the `NOMT_KEY_LEN`-byte key, reflecting the writes made earlier in the
+ * session. `out_found` is set to 1 if the key has a value, which is then stored in `out_value`,
+ * and to 0 otherwise, in which case `out_value` is set to an empty buffer.
+ */
+nomt_status nomt_session_read(
+    nomt_session *session,
+    const uint8_t *key,
+    nomt_buffer *out_value,
+    int *out_found
+);
+
+/*
+ * Write `len` bytes of `value` to the `NOMT_KEY_LEN`-byte key as part of the commit of the
+ * session, or delete the key if `value` is null, in which case `len` must be 0. Empty values are
+ * written, not deleted.
+ */
+nomt_status nomt_session_write(
+    nomt_session *session,
+    const uint8_t *key,
+    const uint8_t *value,
+    size_t len
+);
+
+/*
+ * Commit the session and write the new root to `out_root`, which must hold `NOMT_ROOT_LEN` bytes.
+ * The session is released in any case, even if the call fails.
+ *
+ * If `out_witness` is not null, the session must record a witness, which is then stored in
+ * `out_witness`: the number of path proofs as 8 bytes little-endian, followed by the encoded
+ * path proofs. See `Witness::encode` of the Rust crate.
+ */
+nomt_status nomt_session_commit(
+    const nomt_db *db,
+    nomt_session *session,
+    uint8_t *out_root,
+    nomt_buffer *out_witness
+);
+
+/* Release a buffer returned by NOMT. Empty buffers are ignored. */
+void nomt_buffer_free(nomt_buffer buffer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* NOMT_H */
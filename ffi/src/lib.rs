@@ -0,0 +1,367 @@
+//! C bindings for NOMT, declared in `include/nomt.h`.
+//!
+//! The database and its sessions are passed across the boundary as opaque handles. Every fallible
+//! function returns a [`nomt_status`] and reports its outputs through pointers, and the message of
+//! the last error on the calling thread is available through [`nomt_last_error`]. Panics are
+//! caught at the boundary and reported as [`nomt_status::NOMT_PANIC`].
+//!
+//! Memory ownership follows a single rule: memory is freed by the side which allocated it.
+//! Buffers passed into NOMT are copied and remain owned by the caller. Buffers returned by NOMT,
+//! i.e. values and witnesses, are owned by the caller until released with [`nomt_buffer_free`],
+//! and handles are owned by the caller until released with [`nomt_close`], [`nomt_session_free`]
+//! or [`nomt_session_commit`].
+//!
+//! The trie is hashed with BLAKE3, see [`nomt::Blake3Hasher`].
+
+#![allow(non_camel_case_types)]
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use nomt::{Blake3Hasher, KeyPath, KeyReadWrite, Nomt, Options, Session, SessionParams, Value};
+
+/// The outcome of a call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum nomt_status {
+    /// The call succeeded.
+    NOMT_OK = 0,
+    /// The operation failed, see [`nomt_last_error`].
+    NOMT_ERROR = 1,
+    /// A required pointer was null or an argument was malformed. Nothing was done.
+    NOMT_INVALID_ARGUMENT = 2,
+    /// NOMT panicked. The handles involved must not be used anymore, except for releasing them.
+    NOMT_PANIC = 3,
+}
+
+/// An open database.
+pub struct nomt_db {
+    nomt: Nomt<Blake3Hasher>,
+}
+
+/// A session of a database, along with the reads and writes made through it.
+pub struct nomt_session {
+    session: Session,
+    actuals: BTreeMap<KeyPath, KeyReadWrite>,
+}
+
+/// A buffer allocated by NOMT. Owned by the caller until released with [`nomt_buffer_free`].
+#[repr(C)]
+pub struct nomt_buffer {
+    /// The start of the buffer. Null for an empty buffer.
+    pub ptr: *mut u8,
+    /// The length of the buffer in bytes.
+    pub len: usize,
+}
+
+impl nomt_buffer {
+    fn empty() -> Self {
+        nomt_buffer {
+            ptr: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    fn from_vec(buf: Vec<u8>) -> Self {
+        if buf.is_empty() {
+            return Self::empty();
+        }
+        let buf = Box::into_raw(buf.into_boxed_slice());
+        nomt_buffer {
+            ptr: buf as *mut u8,
+            len: buf.len(),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // Messages with interior nul bytes are cut off at the first one.
+    let message = CString::new(message).unwrap_or_else(|e| {
+        let nul = e.nul_position();
+        // UNWRAP: the bytes before the first nul byte contain none.
+        CString::new(&e.into_vec()[..nul]).unwrap()
+    });
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Run the body, turning errors and panics into statuses.
+fn guard(body: impl FnOnce() -> anyhow::Result<nomt_status>) -> nomt_status {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(status)) => status,
+        Ok(Err(e)) => {
+            set_last_error(format!("{e:#}"));
+            nomt_status::NOMT_ERROR
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            set_last_error(format!("panic: {message}"));
+            nomt_status::NOMT_PANIC
+        }
+    }
+}
+
+/// Returns the message of the last error on the calling thread, or null if there was none.
+///
+/// The message is owned by NOMT and valid until the next call on the same thread.
+#[no_mangle]
+pub extern "C" fn nomt_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Open the database at the given path, creating it if it doesn't exist.
+///
+/// `commit_concurrency` is the number of threads updating the trie, see
+/// [`Options::commit_concurrency`]. On success, the handle is stored in `out` and must be released
+/// with [`nomt_close`].
+///
+/// # Safety
+///
+/// `path` must be a nul-terminated string and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn nomt_open(
+    path: *const c_char,
+    commit_concurrency: u32,
+    out: *mut *mut nomt_db,
+) -> nomt_status {
+    if path.is_null() || out.is_null() {
+        return nomt_status::NOMT_INVALID_ARGUMENT;
+    }
+    let Ok(path) = CStr::from_ptr(path).to_str() else {
+        return nomt_status::NOMT_INVALID_ARGUMENT;
+    };
+    guard(|| {
+        let mut o = Options::new();
+        o.path(path);
+        o.commit_concurrency(commit_concurrency as usize);
+        let nomt = Nomt::open(o)?;
+        *out = Box::into_raw(Box::new(nomt_db { nomt }));
+        Ok(nomt_status::NOMT_OK)
+    })
+}
+
+/// Close the database. Null is ignored.
+///
+/// All sessions of the database must have been released before.
+///
+/// # Safety
+///
+/// `db` must be null or a handle returned by [`nomt_open`] which was not closed before.
+#[no_mangle]
+pub unsafe extern "C" fn nomt_close(db: *mut nomt_db) {
+    if !db.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(db))));
+    }
+}
+
+/// Write the root of the trie to `out_root`.
+///
+/// # Safety
+///
+/// `db` must be an open database and `out_root` must be valid for writes of 32 bytes.
+#[no_mangle]
+pub unsafe extern "C" fn nomt_root(db: *const nomt_db, out_root: *mut u8) -> nomt_status {
+    if db.is_null() || out_root.is_null() {
+        return nomt_status::NOMT_INVALID_ARGUMENT;
+    }
+    guard(|| {
+        let root = (*db).nomt.root();
+        ptr::copy_nonoverlapping(root.as_ptr(), out_root, root.len());
+        Ok(nomt_status::NOMT_OK)
+    })
+}
+
+/// Begin a session, recording a witness if `record_witness` is non-zero.
+///
+/// Only one session may be active at a time, see [`Nomt::begin_session`]. On success, the handle
+/// is stored in `out` and must be released with either [`nomt_session_commit`] or
+/// [`nomt_session_free`].
+///
+/// # Safety
+///
+/// `db` must be an open database and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn nomt_session_begin(
+    db: *const nomt_db,
+    record_witness: c_int,
+    out: *mut *mut nomt_session,
+) -> nomt_status {
+    if db.is_null() || out.is_null() {
+        return nomt_status::NOMT_INVALID_ARGUMENT;
+    }
+    guard(|| {
+        let mut params = SessionParams::default();
+        params.record_witness(record_witness != 0);
+        let session = (*db).nomt.begin_session_with_params(params);
+        *out = Box::into_raw(Box::new(nomt_session {
+            session,
+            actuals: BTreeMap::new(),
+        }));
+        Ok(nomt_status::NOMT_OK)
+    })
+}
+
+/// Release a session without committing it. Null is ignored.
+///
+/// # Safety
+///
+/// `session` must be null or a handle returned by [`nomt_session_begin`] which was not released
+/// before.
+#[no_mangle]
+pub unsafe extern "C" fn nomt_session_free(session: *mut nomt_session) {
+    if !session.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(session))));
+    }
+}
+
+/// Read the value of a key, reflecting the writes made earlier in the session.
+///
+/// `out_found` is set to 1 if the key has a value and to 0 otherwise. If it has, the value is
+/// stored in `out_value`, which must then be released with [`nomt_buffer_free`]; otherwise
+/// `out_value` is set to an empty buffer. The read is part of the witness of the session.
+///
+/// # Safety
+///
+/// `session` must be an active session, `key` must be valid for reads of 32 bytes, and
+/// `out_value` and `out_found` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn nomt_session_read(
+    session: *mut nomt_session,
+    key: *const u8,
+    out_value: *mut nomt_buffer,
+    out_found: *mut c_int,
+) -> nomt_status {
+    if session.is_null() || key.is_null() || out_value.is_null() || out_found.is_null() {
+        return nomt_status::NOMT_INVALID_ARGUMENT;
+    }
+    let key = read_key(key);
+    let session = &mut *session;
+    guard(|| {
+        let value = match session.actuals.get(&key) {
+            Some(KeyReadWrite::Read(value))
+            | Some(KeyReadWrite::Write(value))
+            | Some(KeyReadWrite::ReadThenWrite(_, value)) => value.clone(),
+            None => {
+                session.session.warm_up(key);
+                let value = session.session.read(key)?;
+                session
+                    .actuals
+                    .insert(key, KeyReadWrite::Read(value.clone()));
+                value
+            }
+        };
+        *out_found = value.is_some() as c_int;
+        *out_value = value.map_or_else(nomt_buffer::empty, nomt_buffer::from_vec);
+        Ok(nomt_status::NOMT_OK)
+    })
+}
+
+/// Write a value to a key as part of the commit of the session, or delete the key if `value` is
+/// null. The value is copied. Empty values are written, not deleted, so pass a null `value` with
+/// a `len` of 0 to delete.
+///
+/// # Safety
+///
+/// `session` must be an active session, `key` must be valid for reads of 32 bytes, and `value`
+/// must be null or valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn nomt_session_write(
+    session: *mut nomt_session,
+    key: *const u8,
+    value: *const u8,
+    len: usize,
+) -> nomt_status {
+    if session.is_null() || key.is_null() || (value.is_null() && len != 0) {
+        return nomt_status::NOMT_INVALID_ARGUMENT;
+    }
+    let key = read_key(key);
+    let value: Option<Value> = match value.is_null() {
+        true => None,
+        false => Some(std::slice::from_raw_parts(value, len).to_vec()),
+    };
+    let session = &mut *session;
+    guard(|| {
+        let read_write = match session.actuals.remove(&key) {
+            Some(KeyReadWrite::Read(prior)) | Some(KeyReadWrite::ReadThenWrite(prior, _)) => {
+                KeyReadWrite::ReadThenWrite(prior, value)
+            }
+            Some(KeyReadWrite::Write(_)) | None => {
+                session.session.warm_up(key);
+                KeyReadWrite::Write(value)
+            }
+        };
+        session.actuals.insert(key, read_write);
+        Ok(nomt_status::NOMT_OK)
+    })
+}
+
+/// Commit the session and write the new root to `out_root`. The session is released in any case.
+///
+/// If `out_witness` is not null, the session must record a witness, which is stored in
+/// `out_witness` in the format of [`nomt::Witness::encode`] and must be released with
+/// [`nomt_buffer_free`].
+///
+/// # Safety
+///
+/// `db` must be the database of the session, `session` must be an active session, `out_root`
+/// must be valid for writes of 32 bytes and `out_witness` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn nomt_session_commit(
+    db: *const nomt_db,
+    session: *mut nomt_session,
+    out_root: *mut u8,
+    out_witness: *mut nomt_buffer,
+) -> nomt_status {
+    if session.is_null() {
+        return nomt_status::NOMT_INVALID_ARGUMENT;
+    }
+    let session = Box::from_raw(session);
+    if db.is_null() || out_root.is_null() {
+        return nomt_status::NOMT_INVALID_ARGUMENT;
+    }
+    guard(|| {
+        let nomt_session { session, actuals } = *session;
+        let actuals = actuals.into_iter().collect();
+        let root = if out_witness.is_null() {
+            (*db).nomt.commit(session, actuals)?
+        } else {
+            let (root, witness, _) = (*db).nomt.commit_and_prove(session, actuals)?;
+            *out_witness = nomt_buffer::from_vec(witness.encode());
+            root
+        };
+        ptr::copy_nonoverlapping(root.as_ptr(), out_root, root.len());
+        Ok(nomt_status::NOMT_OK)
+    })
+}
+
+/// Release a buffer returned by NOMT. Empty buffers are ignored.
+///
+/// # Safety
+///
+/// `buffer` must have been returned by NOMT and not been released before.
+#[no_mangle]
+pub unsafe extern "C" fn nomt_buffer_free(buffer: nomt_buffer) {
+    if !buffer.ptr.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            buffer.ptr, buffer.len,
+        )));
+    }
+}
+
+unsafe fn read_key(key: *const u8) -> KeyPath {
+    let mut path = KeyPath::default();
+    ptr::copy_nonoverlapping(key, path.as_mut_ptr(), path.len());
+    path
+}
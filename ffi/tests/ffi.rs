@@ -0,0 +1,169 @@
+use std::{
+    ffi::{CStr, CString},
+    ptr,
+};
+
+use nomt::{Blake3Hasher, WitnessView};
+use nomt_ffi::*;
+
+fn open(name: &str) -> *mut nomt_db {
+    let path = format!("test/{name}");
+    let _ = std::fs::remove_dir_all(&path);
+    let path = CString::new(path).unwrap();
+    let mut db = ptr::null_mut();
+    assert_eq!(
+        unsafe { nomt_open(path.as_ptr(), 1, &mut db) },
+        nomt_status::NOMT_OK
+    );
+    db
+}
+
+fn root(db: *const nomt_db) -> [u8; 32] {
+    let mut root = [0; 32];
+    assert_eq!(
+        unsafe { nomt_root(db, root.as_mut_ptr()) },
+        nomt_status::NOMT_OK
+    );
+    root
+}
+
+fn read(session: *mut nomt_session, key: &[u8; 32]) -> Option<Vec<u8>> {
+    let mut value = nomt_buffer {
+        ptr: ptr::null_mut(),
+        len: 0,
+    };
+    let mut found = 0;
+    assert_eq!(
+        unsafe { nomt_session_read(session, key.as_ptr(), &mut value, &mut found) },
+        nomt_status::NOMT_OK
+    );
+    if found == 0 {
+        assert!(value.ptr.is_null());
+        return None;
+    }
+    let copy = match value.ptr.is_null() {
+        true => Vec::new(),
+        false => unsafe { std::slice::from_raw_parts(value.ptr, value.len) }.to_vec(),
+    };
+    unsafe { nomt_buffer_free(value) };
+    Some(copy)
+}
+
+fn write(session: *mut nomt_session, key: &[u8; 32], value: Option<&[u8]>) {
+    let (ptr, len) = value.map_or((ptr::null(), 0), |v| (v.as_ptr(), v.len()));
+    assert_eq!(
+        unsafe { nomt_session_write(session, key.as_ptr(), ptr, len) },
+        nomt_status::NOMT_OK
+    );
+}
+
+fn begin(db: *const nomt_db, record_witness: bool) -> *mut nomt_session {
+    let mut session = ptr::null_mut();
+    assert_eq!(
+        unsafe { nomt_session_begin(db, record_witness as _, &mut session) },
+        nomt_status::NOMT_OK
+    );
+    session
+}
+
+#[test]
+fn write_commit_and_read_back() {
+    let db = open("ffi_roundtrip");
+    let session = begin(db, false);
+    // The value buffer is copied and may be dropped right away.
+    write(session, &[1; 32], Some(&[7; 100]));
+    write(session, &[2; 32], Some(&[]));
+    assert_eq!(read(session, &[1; 32]), Some(vec![7; 100]));
+    let mut new_root = [0; 32];
+    assert_eq!(
+        unsafe { nomt_session_commit(db, session, new_root.as_mut_ptr(), ptr::null_mut()) },
+        nomt_status::NOMT_OK
+    );
+    assert_eq!(new_root, root(db));
+
+    let session = begin(db, false);
+    assert_eq!(read(session, &[1; 32]), Some(vec![7; 100]));
+    assert_eq!(read(session, &[2; 32]), Some(Vec::new()));
+    assert_eq!(read(session, &[3; 32]), None);
+    write(session, &[1; 32], None);
+    assert_eq!(read(session, &[1; 32]), None);
+    unsafe { nomt_session_free(session) };
+
+    // The uncommitted deletion was discarded.
+    let session = begin(db, false);
+    assert_eq!(read(session, &[1; 32]), Some(vec![7; 100]));
+    unsafe {
+        nomt_session_free(session);
+        nomt_close(db);
+    }
+}
+
+#[test]
+fn witness_is_returned_encoded() {
+    let db = open("ffi_witness");
+    let session = begin(db, false);
+    write(session, &[1; 32], Some(&[1]));
+    let mut prev_root = [0; 32];
+    assert_eq!(
+        unsafe { nomt_session_commit(db, session, prev_root.as_mut_ptr(), ptr::null_mut()) },
+        nomt_status::NOMT_OK
+    );
+
+    let session = begin(db, true);
+    assert_eq!(read(session, &[1; 32]), Some(vec![1]));
+    write(session, &[2; 32], Some(&[2]));
+    let mut new_root = [0; 32];
+    let mut witness = nomt_buffer {
+        ptr: ptr::null_mut(),
+        len: 0,
+    };
+    assert_eq!(
+        unsafe { nomt_session_commit(db, session, new_root.as_mut_ptr(), &mut witness) },
+        nomt_status::NOMT_OK
+    );
+    let encoded = unsafe { std::slice::from_raw_parts(witness.ptr, witness.len) };
+    let view = WitnessView::new(encoded).unwrap();
+    assert!(!view.is_empty());
+    view.verify::<Blake3Hasher>(prev_root).unwrap();
+    unsafe {
+        nomt_buffer_free(witness);
+        nomt_close(db);
+    }
+}
+
+#[test]
+fn errors_are_reported() {
+    let db = open("ffi_errors");
+    let mut out = ptr::null_mut();
+    assert_eq!(
+        unsafe { nomt_open(ptr::null(), 1, &mut out) },
+        nomt_status::NOMT_INVALID_ARGUMENT
+    );
+    assert_eq!(
+        unsafe { nomt_session_write(ptr::null_mut(), [0; 32].as_ptr(), ptr::null(), 0) },
+        nomt_status::NOMT_INVALID_ARGUMENT
+    );
+
+    // A witness can only be extracted from sessions recording one. The session is released
+    // regardless.
+    let session = begin(db, false);
+    let mut root = [0; 32];
+    let mut witness = nomt_buffer {
+        ptr: ptr::null_mut(),
+        len: 0,
+    };
+    assert_eq!(
+        unsafe { nomt_session_commit(db, session, root.as_mut_ptr(), &mut witness) },
+        nomt_status::NOMT_ERROR
+    );
+    let message = unsafe { CStr::from_ptr(nomt_last_error()) };
+    assert!(message.to_str().unwrap().contains("witness"));
+    assert!(witness.ptr.is_null());
+
+    // A new session can begin since the failed commit released the previous one.
+    let session = begin(db, false);
+    unsafe {
+        nomt_session_free(session);
+        nomt_close(db);
+    }
+}
@@ -0,0 +1,64 @@
+#[cfg(feature = "benchmarks")]
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+#[cfg(feature = "benchmarks")]
+use nomt::PagePool;
+#[cfg(feature = "benchmarks")]
+use std::{
+    sync::Barrier,
+    time::{Duration, Instant},
+};
+
+// The number of pages every thread allocates before freeing them again. This is well beyond the
+// capacity of the thread-local freelists, so that every round exchanges pages with the shared ones,
+// like the worker threads of a large commit do.
+#[cfg(feature = "benchmarks")]
+const PAGES_PER_ROUND: usize = 8192;
+
+// Time `iters` rounds of allocating and freeing pages on each of `threads` threads at once.
+#[cfg(feature = "benchmarks")]
+fn alloc_dealloc(pool: &PagePool, threads: usize, iters: u64) -> Duration {
+    let barrier = Barrier::new(threads + 1);
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                let mut pages = Vec::with_capacity(PAGES_PER_ROUND);
+                barrier.wait();
+                for _ in 0..iters {
+                    pages.extend((0..PAGES_PER_ROUND).map(|_| pool.alloc()));
+                    for page in pages.drain(..) {
+                        pool.dealloc(page);
+                    }
+                }
+                barrier.wait();
+            });
+        }
+        barrier.wait();
+        let start = Instant::now();
+        barrier.wait();
+        start.elapsed()
+    })
+}
+
+#[cfg(feature = "benchmarks")]
+fn page_pool_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("page_pool_alloc_dealloc");
+    for threads in [1, 4, 16, 32, 64] {
+        // A pool per thread count, warmed up by the first iterations, so that reserving memory
+        // from the OS is not measured.
+        let pool = PagePool::new();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(threads),
+            &threads,
+            |b, &threads| b.iter_custom(|iters| alloc_dealloc(&pool, threads, iters)),
+        );
+    }
+    group.finish();
+}
+
+#[cfg(feature = "benchmarks")]
+criterion_group!(benches, page_pool_benchmark);
+#[cfg(feature = "benchmarks")]
+criterion_main!(benches);
+
+#[cfg(not(feature = "benchmarks"))]
+fn main() {}
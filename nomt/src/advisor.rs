@@ -0,0 +1,231 @@
+//! Tuning recommendations derived from the runtime behavior of a database.
+//!
+//! The advisor compares two observations of the metrics and the hash-table statistics and applies
+//! a small set of rules of thumb to the interval between them. The rules are deliberately
+//! conservative: each fires only once the interval holds enough activity to judge, and suggests a
+//! single step, such as doubling a setting, rather than a final value.
+
+use std::fmt;
+
+use crate::{bitbox::HashTableStats, metrics::Metric, metrics::Metrics, Options};
+
+/// The fill level of the hash-table beyond which growing it is recommended.
+const MAX_OCCUPANCY: f64 = 0.8;
+
+/// The mean number of buckets probed per page load beyond which probing is considered slow.
+const MAX_PROBE_LENGTH: f64 = 2.0;
+
+/// The share of page requests missing the page cache beyond which warm-up is recommended.
+const MAX_PAGE_CACHE_MISS_RATE: f64 = 0.25;
+
+/// The minimum number of page loads or page requests in an interval to judge rates by.
+const MIN_SAMPLES: u64 = 1000;
+
+/// The minimum number of commits in an interval to judge commit phase times by.
+const MIN_COMMITS: u64 = 10;
+
+/// A change of the configuration of a database which is expected to improve its performance. See
+/// [`crate::Nomt::tuning_advice`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Recommendation {
+    /// Grow the hash-table storing the pages of the merkle trie to the given number of buckets,
+    /// as it is filling up or page loads probe too many buckets. Can be applied live, see
+    /// [`crate::Nomt::resize_hash_table`].
+    GrowHashTable {
+        /// The suggested number of buckets.
+        buckets: u32,
+        /// The share of buckets holding a page.
+        occupancy: f64,
+    },
+    /// Raise [`Options::hashtable_compaction_budget`], as tombstones left behind by deleted pages
+    /// lengthen the probes of page loads.
+    HashTableCompactionBudget {
+        /// The suggested budget.
+        budget: usize,
+        /// The mean number of buckets probed per page load in the interval.
+        probe_length: f64,
+    },
+    /// Enable [`Options::warm_up`], as many page requests of commits miss the page cache.
+    WarmUp {
+        /// The share of page requests which missed the page cache in the interval.
+        miss_rate: f64,
+    },
+    /// Raise [`Options::commit_concurrency`], as updating the merkle trie dominates commits.
+    CommitConcurrency {
+        /// The suggested number of commit workers.
+        concurrency: usize,
+    },
+    /// Enable [`Options::commit_coalescing`], as syncing to disk dominates commits.
+    CommitCoalescing {
+        /// The mean time of a sync in the interval, in nanoseconds.
+        sync_mean_ns: u64,
+        /// The mean time of a merkle update in the interval, in nanoseconds.
+        merkle_update_mean_ns: u64,
+    },
+}
+
+impl Recommendation {
+    /// Whether the recommendation can be applied to an open database with
+    /// [`crate::Nomt::apply_tuning`]. Others require reopening the database with adjusted
+    /// options.
+    pub fn is_live(&self) -> bool {
+        matches!(self, Recommendation::GrowHashTable { .. })
+    }
+}
+
+impl fmt::Display for Recommendation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Recommendation::GrowHashTable { buckets, occupancy } => write!(
+                f,
+                "grow the hash-table to {buckets} buckets, it is {:.0}% full",
+                occupancy * 100.0
+            ),
+            Recommendation::HashTableCompactionBudget {
+                budget,
+                probe_length,
+            } => write!(
+                f,
+                "raise the hash-table compaction budget to {budget}, page loads probe \
+                 {probe_length:.1} buckets on average"
+            ),
+            Recommendation::WarmUp { miss_rate } => write!(
+                f,
+                "enable warm-up, {:.0}% of page requests miss the page cache",
+                miss_rate * 100.0
+            ),
+            Recommendation::CommitConcurrency { concurrency } => write!(
+                f,
+                "raise the commit concurrency to {concurrency}, merkle updates dominate commits"
+            ),
+            Recommendation::CommitCoalescing {
+                sync_mean_ns,
+                merkle_update_mean_ns,
+            } => write!(
+                f,
+                "enable commit coalescing, syncs take {} us against {} us for merkle updates",
+                sync_mean_ns / 1000,
+                merkle_update_mean_ns / 1000
+            ),
+        }
+    }
+}
+
+/// The options the rules take into account.
+pub(crate) struct Config {
+    warm_up: bool,
+    commit_concurrency: usize,
+    commit_coalescing: bool,
+    hashtable_compaction_budget: usize,
+}
+
+impl Config {
+    pub(crate) fn new(o: &Options) -> Self {
+        Config {
+            warm_up: o.warm_up,
+            commit_concurrency: o.commit_concurrency,
+            commit_coalescing: o.commit_coalescing.is_some(),
+            hashtable_compaction_budget: o.hashtable_compaction_budget,
+        }
+    }
+
+    /// Take a commit concurrency changed with [`crate::Nomt::reconfigure`] into account.
+    pub(crate) fn set_commit_concurrency(&mut self, commit_concurrency: usize) {
+        self.commit_concurrency = commit_concurrency;
+    }
+}
+
+/// The totals the rules are applied to the differences of.
+#[derive(Clone)]
+pub(crate) struct Observation {
+    page_requests: u64,
+    page_cache_misses: u64,
+    /// The number of records and the sum of the recorded times of the timers.
+    merkle_update_time: (u64, u64),
+    sync_time: (u64, u64),
+    page_loads: u64,
+    probed_buckets: u64,
+}
+
+impl Observation {
+    pub(crate) fn new(metrics: &Metrics, hash_table: &HashTableStats) -> Self {
+        let snapshot = metrics.snapshot();
+        Observation {
+            page_requests: snapshot.as_ref().map_or(0, |s| s.page_requests),
+            page_cache_misses: snapshot.as_ref().map_or(0, |s| s.page_cache_misses),
+            merkle_update_time: metrics.timer_totals(Metric::MerkleUpdateTime),
+            sync_time: metrics.timer_totals(Metric::SyncTime),
+            page_loads: hash_table.page_loads,
+            probed_buckets: hash_table.probed_buckets,
+        }
+    }
+}
+
+/// Apply the rules to the interval between two observations, given the current hash-table
+/// statistics.
+pub(crate) fn advise(
+    config: &Config,
+    base: &Observation,
+    now: &Observation,
+    hash_table: &HashTableStats,
+) -> Vec<Recommendation> {
+    let mut advice = Vec::new();
+
+    let page_loads = now.page_loads.saturating_sub(base.page_loads);
+    let probe_length = (page_loads >= MIN_SAMPLES)
+        .then(|| now.probed_buckets.saturating_sub(base.probed_buckets) as f64 / page_loads as f64);
+    if hash_table.resizing_to.is_none() && hash_table.buckets > 0 {
+        let occupancy = hash_table.occupied_buckets as f64 / hash_table.buckets as f64;
+        let probing_slow = probe_length.is_some_and(|len| len > MAX_PROBE_LENGTH);
+        let tombstones_dominate = hash_table.tombstones > hash_table.occupied_buckets / 2;
+        if occupancy > MAX_OCCUPANCY || (probing_slow && !tombstones_dominate) {
+            advice.push(Recommendation::GrowHashTable {
+                buckets: (hash_table.buckets as u32).saturating_mul(2),
+                occupancy,
+            });
+        } else if probing_slow && tombstones_dominate {
+            advice.push(Recommendation::HashTableCompactionBudget {
+                budget: (config.hashtable_compaction_budget * 2).max(1024),
+                // UNWRAP: probing is only slow if the probe length is known.
+                probe_length: probe_length.unwrap(),
+            });
+        }
+    }
+
+    let page_requests = now.page_requests.saturating_sub(base.page_requests);
+    if !config.warm_up && page_requests >= MIN_SAMPLES {
+        let miss_rate = now.page_cache_misses.saturating_sub(base.page_cache_misses) as f64
+            / page_requests as f64;
+        if miss_rate > MAX_PAGE_CACHE_MISS_RATE {
+            advice.push(Recommendation::WarmUp { miss_rate });
+        }
+    }
+
+    let (commits, merkle_update_sum) = timer_delta(base.merkle_update_time, now.merkle_update_time);
+    let (syncs, sync_sum) = timer_delta(base.sync_time, now.sync_time);
+    if commits >= MIN_COMMITS && syncs > 0 {
+        let merkle_update_mean_ns = merkle_update_sum / commits;
+        let sync_mean_ns = sync_sum / syncs;
+        let max_concurrency = std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(crate::MAX_COMMIT_CONCURRENCY);
+        if merkle_update_mean_ns > sync_mean_ns && config.commit_concurrency < max_concurrency {
+            advice.push(Recommendation::CommitConcurrency {
+                concurrency: (config.commit_concurrency * 2).min(max_concurrency),
+            });
+        } else if sync_mean_ns > 2 * merkle_update_mean_ns && !config.commit_coalescing {
+            advice.push(Recommendation::CommitCoalescing {
+                sync_mean_ns,
+                merkle_update_mean_ns,
+            });
+        }
+    }
+
+    advice
+}
+
+/// The number of records and the sum of the recorded times of a timer between two observations.
+fn timer_delta(base: (u64, u64), now: (u64, u64)) -> (u64, u64) {
+    (now.0.saturating_sub(base.0), now.1.saturating_sub(base.1))
+}
@@ -0,0 +1,237 @@
+//! An Ed25519-signed, append-only log of committed roots.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read as _, Write as _},
+    path::Path,
+};
+
+use ed25519_dalek::{Signature, Signer as _, SigningKey, VerifyingKey, SIGNATURE_LENGTH};
+use parking_lot::Mutex;
+
+use super::RootAnchor;
+use crate::Node;
+
+const RECORD_SIZE: usize = 4 + 32 + SIGNATURE_LENGTH;
+
+/// Prefixed to every signed message, so that signatures of anchors can't be mistaken for
+/// signatures of anything else made with the same key.
+const SIGNATURE_CONTEXT: &[u8] = b"nomt-root-anchor-v1";
+
+/// A [`RootAnchor`] appending roots to an append-only, signed log file.
+///
+/// Every record consists of the sync sequence number, the root and an Ed25519 signature over the
+/// record and the signature of the previous record. Anyone holding the verifying key can check the
+/// log with [`RootAnchorLog::verify`], while only the holder of the signing key can append to it.
+/// Altering, removing or reordering records breaks the chain.
+///
+/// Dropping records from the end of the log leaves a valid chain, so it can only be detected
+/// against a sequence number or signature anchored elsewhere, e.g. [`crate::Nomt::sync_seqn`] of
+/// the database or the last signature of the log as published by its writer, see
+/// [`RootAnchorLog::last_signature`].
+pub struct RootAnchorLog {
+    signing_key: SigningKey,
+    state: Mutex<LogState>,
+}
+
+struct LogState {
+    file: File,
+    last_signature: [u8; SIGNATURE_LENGTH],
+    error: Option<std::io::Error>,
+}
+
+impl RootAnchorLog {
+    /// Open the log at the given path, creating it if it does not exist, to append records signed
+    /// with the given Ed25519 secret key.
+    ///
+    /// An existing log is verified with the verifying key of the signing key. This fails if the log
+    /// ends with a partially written record, as left by a crash while appending. Such a record can
+    /// be removed with [`RootAnchorLog::discard_partial_record`].
+    pub fn open(path: impl AsRef<Path>, signing_key: [u8; 32]) -> anyhow::Result<Self> {
+        let signing_key = SigningKey::from_bytes(&signing_key);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        check_complete(&data)?;
+        let last_signature = verify_records(&signing_key.verifying_key(), &data)?.1;
+
+        Ok(Self {
+            signing_key,
+            state: Mutex::new(LogState {
+                file,
+                last_signature,
+                error: None,
+            }),
+        })
+    }
+
+    /// Truncate a partially written trailing record from the log at the given path, returning the
+    /// number of bytes removed.
+    pub fn discard_partial_record(path: impl AsRef<Path>) -> std::io::Result<u64> {
+        let file = OpenOptions::new().write(true).open(path)?;
+        let len = file.metadata()?.len();
+        let partial = len % RECORD_SIZE as u64;
+        if partial != 0 {
+            file.set_len(len - partial)?;
+            file.sync_all()?;
+        }
+        Ok(partial)
+    }
+
+    /// Read the log at the given path and verify it with the given Ed25519 verifying key,
+    /// returning all anchored sync sequence numbers and roots.
+    pub fn verify(path: impl AsRef<Path>, verifying_key: [u8; 32]) -> anyhow::Result<Anchors> {
+        let verifying_key = VerifyingKey::from_bytes(&verifying_key)?;
+        let data = std::fs::read(path)?;
+        check_complete(&data)?;
+        Ok(verify_records(&verifying_key, &data)?.0)
+    }
+
+    /// The verifying key of the signing key of the log.
+    pub fn verifying_key(&self) -> [u8; 32] {
+        self.signing_key.verifying_key().to_bytes()
+    }
+
+    /// The signature of the last record of the log, or all zeros if it is empty.
+    pub fn last_signature(&self) -> [u8; SIGNATURE_LENGTH] {
+        self.state.lock().last_signature
+    }
+
+    /// Take the first error encountered when appending to the log, if any. No further records are
+    /// appended after an error.
+    pub fn take_error(&self) -> Option<std::io::Error> {
+        self.state.lock().error.take()
+    }
+}
+
+impl RootAnchor for RootAnchorLog {
+    fn anchor(&self, sync_seqn: u32, root: Node) {
+        let mut state = self.state.lock();
+        if state.error.is_some() {
+            return;
+        }
+
+        let mut record = [0; RECORD_SIZE];
+        record[0..4].copy_from_slice(&sync_seqn.to_le_bytes());
+        record[4..36].copy_from_slice(&root);
+        let signature = self
+            .signing_key
+            .sign(&signed_message(&state.last_signature, &record[..36]))
+            .to_bytes();
+        record[36..].copy_from_slice(&signature);
+
+        let res = state
+            .file
+            .write_all(&record)
+            .and_then(|()| state.file.sync_data());
+        match res {
+            Ok(()) => state.last_signature = signature,
+            Err(e) => state.error = Some(e),
+        }
+    }
+}
+
+fn signed_message(prev_signature: &[u8; SIGNATURE_LENGTH], payload: &[u8]) -> Vec<u8> {
+    [SIGNATURE_CONTEXT, prev_signature, payload].concat()
+}
+
+fn check_complete(data: &[u8]) -> anyhow::Result<()> {
+    let partial = data.len() % RECORD_SIZE;
+    if partial != 0 {
+        anyhow::bail!(
+            "root anchor log ends with a partial record of {partial} bytes after {} records",
+            data.len() / RECORD_SIZE,
+        );
+    }
+    Ok(())
+}
+
+/// Anchored sync sequence numbers and roots, in log order.
+type Anchors = Vec<(u32, Node)>;
+
+fn verify_records(
+    verifying_key: &VerifyingKey,
+    data: &[u8],
+) -> anyhow::Result<(Anchors, [u8; SIGNATURE_LENGTH])> {
+    let mut last_signature = [0; SIGNATURE_LENGTH];
+    let mut anchors = Vec::with_capacity(data.len() / RECORD_SIZE);
+    for (i, record) in data.chunks_exact(RECORD_SIZE).enumerate() {
+        // UNWRAP: slices have the exact lengths.
+        let signature = Signature::from_bytes(record[36..].try_into().unwrap());
+        let message = signed_message(&last_signature, &record[..36]);
+        if verifying_key.verify_strict(&message, &signature).is_err() {
+            anyhow::bail!("root anchor log record {i} failed verification");
+        }
+        let sync_seqn = u32::from_le_bytes(record[0..4].try_into().unwrap());
+        let root = record[4..36].try_into().unwrap();
+        anchors.push((sync_seqn, root));
+        last_signature = signature.to_bytes();
+    }
+    Ok((anchors, last_signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RootAnchor, RootAnchorLog, RECORD_SIZE};
+
+    #[test]
+    fn log_roundtrip_and_tamper_detection() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("anchors");
+        let signing_key = [7; 32];
+
+        let log = RootAnchorLog::open(&path, signing_key).unwrap();
+        let verifying_key = log.verifying_key();
+        log.anchor(1, [1; 32]);
+        log.anchor(2, [2; 32]);
+        let last_signature = log.last_signature();
+        drop(log);
+
+        // Reopening continues the chain.
+        let log = RootAnchorLog::open(&path, signing_key).unwrap();
+        assert_eq!(log.last_signature(), last_signature);
+        log.anchor(3, [3; 32]);
+        assert!(log.take_error().is_none());
+        drop(log);
+
+        assert_eq!(
+            RootAnchorLog::verify(&path, verifying_key).unwrap(),
+            vec![(1, [1; 32]), (2, [2; 32]), (3, [3; 32])],
+        );
+        let other_key = RootAnchorLog::open(dir.path().join("other"), [8; 32])
+            .unwrap()
+            .verifying_key();
+        assert!(RootAnchorLog::verify(&path, other_key).is_err());
+        // Records signed with another key are rejected on open.
+        assert!(RootAnchorLog::open(&path, [8; 32]).is_err());
+
+        // Dropping a record from the middle breaks the chain.
+        let data = std::fs::read(&path).unwrap();
+        let mut tampered = data[..RECORD_SIZE].to_vec();
+        tampered.extend_from_slice(&data[2 * RECORD_SIZE..]);
+        std::fs::write(&path, &tampered).unwrap();
+        assert!(RootAnchorLog::verify(&path, verifying_key).is_err());
+
+        // Altering a root breaks the signature.
+        let mut tampered = data.clone();
+        tampered[RECORD_SIZE + 4] ^= 1;
+        std::fs::write(&path, &tampered).unwrap();
+        assert!(RootAnchorLog::verify(&path, verifying_key).is_err());
+
+        // A torn trailing record is reported rather than silently discarded.
+        std::fs::write(&path, &data[..2 * RECORD_SIZE + 5]).unwrap();
+        assert!(RootAnchorLog::open(&path, signing_key).is_err());
+        assert!(RootAnchorLog::verify(&path, verifying_key).is_err());
+        assert_eq!(RootAnchorLog::discard_partial_record(&path).unwrap(), 5);
+        drop(RootAnchorLog::open(&path, signing_key).unwrap());
+        assert_eq!(
+            RootAnchorLog::verify(&path, verifying_key).unwrap().len(),
+            2
+        );
+    }
+}
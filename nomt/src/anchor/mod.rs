@@ -0,0 +1,58 @@
+//! Anchoring of committed roots to an external log.
+
+use std::sync::Arc;
+
+use threadpool::ThreadPool;
+
+use crate::Node;
+
+#[cfg(feature = "root-anchor-log")]
+pub use log::RootAnchorLog;
+
+#[cfg(feature = "root-anchor-log")]
+mod log;
+
+/// A hook which is informed of every root persisted by a sync.
+///
+/// This can be used to maintain an independent, tamper-evident trail of state roots. With the
+/// `root-anchor-log` feature, `RootAnchorLog` is a built-in implementation.
+pub trait RootAnchor: Send + Sync {
+    /// Record that the sync with the given sequence number persisted the given root.
+    ///
+    /// This is invoked on a background thread, in sync order, after the sync. Of the commits
+    /// coalesced into a single sync, see [`crate::Options::commit_coalescing`], only the root of
+    /// the last one is anchored, as the others are never persisted. Implementations are
+    /// responsible for handling their own failures.
+    fn anchor(&self, sync_seqn: u32, root: Node);
+}
+
+/// Dispatches roots to a [`RootAnchor`] on a dedicated background thread.
+pub(crate) struct AnchorWorker {
+    anchor: Arc<dyn RootAnchor>,
+    // A single thread ensures anchors are invoked in commit order.
+    tp: ThreadPool,
+}
+
+impl AnchorWorker {
+    pub fn new(anchor: Arc<dyn RootAnchor>) -> Self {
+        Self {
+            anchor,
+            tp: threadpool::Builder::new()
+                .num_threads(1)
+                .thread_name("nomt-root-anchor".to_string())
+                .build(),
+        }
+    }
+
+    pub fn dispatch(&self, sync_seqn: u32, root: Node) {
+        let anchor = self.anchor.clone();
+        self.tp.execute(move || anchor.anchor(sync_seqn, root));
+    }
+}
+
+impl Drop for AnchorWorker {
+    fn drop(&mut self) {
+        // Don't lose roots which are still pending.
+        self.tp.join();
+    }
+}
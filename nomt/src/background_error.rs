@@ -0,0 +1,96 @@
+//! Reporting of errors which occur on internal threads, outside of any call made by the embedder.
+
+use std::{fmt, sync::Arc};
+
+/// The part of the database a [`BackgroundError`] occurred in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BackgroundErrorSource {
+    /// An I/O worker panicked and stopped serving requests. I/O submitted afterwards may never
+    /// complete, so the database should be reopened.
+    IoWorker,
+    /// A [`crate::WalSink`] failed to store the WAL blob of the given sync. The sync itself only
+    /// fails if the quorum of sinks is not reached.
+    WalSink {
+        /// The index of the sink, in the order the sinks were added to the options.
+        index: usize,
+        /// The sequence number of the sync the blob was produced by.
+        sync_seqn: u32,
+    },
+    /// The compaction of the hash-table could not read a page. The sweep starts over with the
+    /// next sync.
+    HashTableCompaction,
+    /// A page could not be moved while the hash-table is being resized. It is retried with the
+    /// next sync.
+    HashTableResize,
+    /// A file of the b-tree could not be truncated after pages were freed at its end. This only
+    /// leaves free space at its end.
+    FileTruncation,
+    /// A hash-table file was left sparse despite preallocation, e.g. by a file system which
+    /// doesn't store zeros written to it, and may fragment. See [`crate::Options::preallocate_ht`].
+    SparseHashTable,
+    /// The commits held back by [`crate::Options::commit_coalescing`] could not be synced when
+    /// the database was dropped, so they are lost. Call [`crate::Nomt::flush`] before dropping
+    /// the database to handle this error instead.
+    FlushOnDrop,
+}
+
+/// An error which occurred on an internal thread of the database.
+///
+/// These errors don't fail any call of the embedder, either because the work is best-effort and
+/// is retried later, or because nothing is waiting for it. They are delivered to the callback set
+/// with [`crate::Options::on_background_error`], which may decide to halt, degrade or alert.
+#[derive(Debug)]
+pub struct BackgroundError {
+    /// Where the error occurred.
+    pub source: BackgroundErrorSource,
+    /// The error.
+    pub error: anyhow::Error,
+}
+
+impl fmt::Display for BackgroundError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "background error in {:?}: {:#}", self.source, self.error)
+    }
+}
+
+/// A callback receiving the errors of internal threads.
+///
+/// It is invoked on the thread the error occurred on and must not block for long, nor call into
+/// the database.
+pub type BackgroundErrorCallback = Arc<dyn Fn(BackgroundError) + Send + Sync>;
+
+/// Delivers background errors to the configured callback. They are discarded if there is none.
+#[derive(Clone, Default)]
+pub struct BackgroundErrors {
+    callback: Option<BackgroundErrorCallback>,
+}
+
+impl BackgroundErrors {
+    pub fn new(callback: Option<BackgroundErrorCallback>) -> Self {
+        BackgroundErrors { callback }
+    }
+
+    /// Report an error which occurred in the given part of the database.
+    pub fn report(&self, source: BackgroundErrorSource, error: anyhow::Error) {
+        if let Some(ref callback) = self.callback {
+            callback(BackgroundError { source, error });
+        }
+    }
+
+    /// Report a panic caught on an internal thread.
+    pub fn report_panic(
+        &self,
+        source: BackgroundErrorSource,
+        payload: Box<dyn std::any::Any + Send>,
+    ) {
+        let message = if let Some(s) = payload.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "unknown panic payload".to_string()
+        };
+        self.report(source, anyhow::anyhow!("panicked: {message}"));
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
     beatree::FREELIST_EMPTY,
-    io::{self, page_pool::FatPage, PagePool, PAGE_SIZE},
+    io::{self, page_pool::FatPage, PageCipher, PagePool, PAGE_SIZE},
 };
 use std::{collections::BTreeSet, fs::File};
 
@@ -29,6 +29,7 @@ pub struct FreeList {
 impl FreeList {
     pub fn read(
         page_pool: &PagePool,
+        cipher: Option<&PageCipher>,
         store_file: &File,
         free_list_head: Option<PageNumber>,
     ) -> anyhow::Result<FreeList> {
@@ -49,7 +50,7 @@ impl FreeList {
                 break;
             }
 
-            let page = io::read_page(page_pool, store_file, free_list_pn.0 as u64)?;
+            let page = io::read_page(page_pool, cipher, store_file, free_list_pn.0 as u64)?;
 
             let (prev, free_list) = decode_free_list_page(page);
             free_list_portions.push((free_list_pn, free_list));
@@ -204,6 +205,107 @@ impl FreeList {
         pages
     }
 
+    /// Drop the free pages at the end of the store by lowering `bump` to the first of them, and
+    /// rewrite the free-list to hold the remaining free pages. This returns a vector of pages to
+    /// write, each tagged with the page number it should be written at.
+    ///
+    /// The pages currently storing the free-list are left untouched, as the manifest refers to
+    /// them until the sync is complete. Past that, they are free and listed as such. The new
+    /// free-list is stored in other free pages, ordered such that the lowest page numbers are
+    /// popped first, keeping the end of the store free.
+    ///
+    /// Must only be called on a clean free-list, with no pages freed in the same sync. Leaves the
+    /// free-list as is if nothing can be dropped.
+    pub fn trim(
+        &mut self,
+        page_pool: &PagePool,
+        bump: &mut PageNumber,
+    ) -> Vec<(PageNumber, FatPage)> {
+        self.rewrite_ordered(page_pool, bump, false)
+    }
+
+    /// Like [`Self::trim`], but rewrite the free-list such that the lowest page numbers are popped
+    /// first even if nothing can be dropped.
+    pub fn trim_and_order(
+        &mut self,
+        page_pool: &PagePool,
+        bump: &mut PageNumber,
+    ) -> Vec<(PageNumber, FatPage)> {
+        self.rewrite_ordered(page_pool, bump, true)
+    }
+
+    fn rewrite_ordered(
+        &mut self,
+        page_pool: &PagePool,
+        bump: &mut PageNumber,
+        always: bool,
+    ) -> Vec<(PageNumber, FatPage)> {
+        assert!(!self.pop && self.released_portions.is_empty());
+
+        // Page 0 is the nil page.
+        let tracked = self.all_tracked_pages();
+        let mut new_bump = *bump;
+        while new_bump.0 > 1 && tracked.contains(&PageNumber(new_bump.0 - 1)) {
+            new_bump.0 -= 1;
+        }
+        let free = tracked.range(..new_bump).copied().collect::<Vec<_>>();
+        if (new_bump == *bump && !always) || free.len() <= 1 {
+            // A single free page can't store a free-list listing itself.
+            return vec![];
+        }
+
+        let old_portions = self
+            .portions
+            .iter()
+            .map(|(pn, _)| *pn)
+            .collect::<BTreeSet<_>>();
+        let n_portions = free.len().div_ceil(MAX_PNS_PER_PAGE + 1);
+        let portion_pns = free
+            .iter()
+            .filter(|pn| !old_portions.contains(pn))
+            .take(n_portions)
+            .copied()
+            .collect::<BTreeSet<_>>();
+        if portion_pns.len() < n_portions {
+            return vec![];
+        }
+
+        // Pops take the last item of the head, which is the last portion.
+        let items = free
+            .into_iter()
+            .rev()
+            .filter(|pn| !portion_pns.contains(pn))
+            .collect::<Vec<_>>();
+        let mut chunks = items
+            .chunks(MAX_PNS_PER_PAGE)
+            .map(|chunk| chunk.to_vec())
+            .collect::<Vec<_>>();
+        if chunks.len() < n_portions {
+            // One portion too many was set aside. Leave the free-list fragmented, as described in
+            // the comment on `fn commit`, rather than listing no pages in the head.
+            // UNWRAP: `n_portions` is at least 1 more than the number of chunks, which are full.
+            let last = chunks.last_mut().unwrap().pop().unwrap();
+            chunks.push(vec![last]);
+        }
+
+        self.portions = portion_pns.into_iter().zip(chunks).collect();
+        let (len, fragmented) = len_and_fragmented(&self.portions);
+        self.len = len;
+        self.fragmented = fragmented;
+        *bump = new_bump;
+
+        self.portions
+            .iter()
+            .enumerate()
+            .map(|(i, (pn, pns))| {
+                let prev_pn = i
+                    .checked_sub(1)
+                    .map_or(FREELIST_EMPTY, |i| self.portions[i].0);
+                (*pn, encode_free_list_page(page_pool, prev_pn, pns))
+            })
+            .collect()
+    }
+
     // determines the exact number of pops and bumps which are needed in order to fulfill the
     // request. also schedules pushing of all touched pages' previous page numbers.
     fn preallocate(
@@ -822,4 +924,82 @@ mod tests {
 
         assert_eq!(predicted_pops, actual_pops);
     }
+
+    #[test]
+    fn trim_drops_free_pages_at_the_end() {
+        let mut free_list = FreeList {
+            portions: vec![(
+                PageNumber(20),
+                [5, 6, 7]
+                    .into_iter()
+                    .chain(10..20)
+                    .map(PageNumber)
+                    .collect(),
+            )],
+            pop: false,
+            released_portions: Vec::new(),
+            len: 13,
+            fragmented: false,
+        };
+
+        // Pages 10 to 20, including the one storing the free-list, are dropped. The free-list
+        // is rewritten to a page which was not storing it.
+        let page_pool = PagePool::new();
+        let mut bump = PageNumber(21);
+        let result = free_list.trim(&page_pool, &mut bump);
+        assert_eq!(bump, PageNumber(10));
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, PageNumber(5));
+        assert_eq!(
+            free_list.portions,
+            vec![(PageNumber(5), vec![PageNumber(7), PageNumber(6)])]
+        );
+        assert_eq!(free_list.len, 2);
+        assert_eq!(free_list.pop(), Some(PageNumber(6)));
+    }
+
+    #[test]
+    fn trim_without_free_pages_at_the_end() {
+        let portions = vec![(PageNumber(20), vec![PageNumber(5), PageNumber(6)])];
+        let mut free_list = FreeList {
+            portions: portions.clone(),
+            pop: false,
+            released_portions: Vec::new(),
+            len: 2,
+            fragmented: false,
+        };
+
+        let page_pool = PagePool::new();
+        let mut bump = PageNumber(30);
+        assert!(free_list.trim(&page_pool, &mut bump).is_empty());
+        assert_eq!(bump, PageNumber(30));
+        assert_eq!(free_list.portions, portions);
+    }
+
+    #[test]
+    fn trim_and_order_without_free_pages_at_the_end() {
+        let mut free_list = FreeList {
+            portions: vec![(
+                PageNumber(20),
+                vec![PageNumber(5), PageNumber(9), PageNumber(6)],
+            )],
+            pop: false,
+            released_portions: Vec::new(),
+            len: 3,
+            fragmented: false,
+        };
+
+        // Nothing is dropped, but the free-list is rewritten to page 5 and lists the page which
+        // stored it before, such that the lowest page numbers are popped first.
+        let page_pool = PagePool::new();
+        let mut bump = PageNumber(30);
+        let result = free_list.trim_and_order(&page_pool, &mut bump);
+        assert_eq!(bump, PageNumber(30));
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, PageNumber(5));
+        assert_eq!(free_list.len, 3);
+        assert_eq!(free_list.pop(), Some(PageNumber(6)));
+        assert_eq!(free_list.pop(), Some(PageNumber(9)));
+        assert_eq!(free_list.pop(), Some(PageNumber(20)));
+    }
 }
@@ -1,4 +1,4 @@
-use crate::io::{self, page_pool::FatPage, IoCommand, IoKind, PagePool, PAGE_SIZE};
+use crate::io::{self, page_pool::FatPage, IoCommand, IoKind, PageCipher, PagePool, PAGE_SIZE};
 
 use crossbeam_channel::{Receiver, Sender};
 use parking_lot::{ArcMutexGuard, Mutex};
@@ -10,7 +10,7 @@ use std::{
         unix::fs::MetadataExt,
     },
     sync::{
-        atomic::{AtomicU32, AtomicUsize, Ordering},
+        atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
 };
@@ -44,13 +44,19 @@ pub const FREELIST_EMPTY: PageNumber = PageNumber(0);
 #[derive(Clone)]
 pub struct Store {
     file: Arc<File>,
+    /// The cipher pages are encrypted with, if any.
+    cipher: Option<Arc<PageCipher>>,
     sync: Arc<Mutex<StoreSync>>,
+    /// The number of pages read from the file.
+    page_reads: Arc<AtomicU64>,
 }
 
 impl Store {
-    /// Create a new `Store` over an existing file.
+    /// Create a new `Store` over an existing file, whose pages are encrypted with the given
+    /// cipher, if any.
     pub fn open(
         page_pool: &PagePool,
+        cipher: Option<Arc<PageCipher>>,
         file: Arc<File>,
         bump: PageNumber,
         free_list_head: Option<PageNumber>,
@@ -58,24 +64,36 @@ impl Store {
         let file_size = file.metadata()?.size() as usize;
 
         let sync = StoreSync {
-            free_list: FreeList::read(page_pool, &file, free_list_head)?,
+            free_list: FreeList::read(page_pool, cipher.as_deref(), &file, free_list_head)?,
             bump,
             max_bump: PageNumber((file_size / PAGE_SIZE) as u32),
+            trim: false,
+            order_free_list: false,
+            truncate_to: None,
         };
 
         Ok(Store {
             file,
+            cipher,
             sync: Arc::new(Mutex::new(sync)),
+            page_reads: Arc::new(AtomicU64::new(0)),
         })
     }
 
     /// Reads the page with the specified page number. Blocks the current thread.
     pub fn query(&self, page_pool: &PagePool, pn: PageNumber) -> FatPage {
-        io::read_page(page_pool, &self.file, pn.0 as u64).unwrap()
+        self.page_reads.fetch_add(1, Ordering::Relaxed);
+        io::read_page(page_pool, self.cipher.as_deref(), &self.file, pn.0 as u64).unwrap()
+    }
+
+    /// The number of pages read through [`Self::query`] or [`Self::io_command`] so far.
+    pub fn page_reads(&self) -> u64 {
+        self.page_reads.load(Ordering::Relaxed)
     }
 
     /// Create an I/O command for querying a page by number.
     pub fn io_command(&self, page_pool: &PagePool, pn: PageNumber, user_data: u64) -> IoCommand {
+        self.page_reads.fetch_add(1, Ordering::Relaxed);
         let page = page_pool.alloc_fat_page();
         IoCommand {
             kind: IoKind::Read(self.file.as_raw_fd(), pn.0 as u64, page),
@@ -124,6 +142,62 @@ impl Store {
     pub fn store_fd(&self) -> RawFd {
         self.file.as_raw_fd()
     }
+
+    /// Request the next sync to drop the free pages at the end of the store, see
+    /// [`FreeList::trim`]. This only happens if the sync neither allocates nor frees pages.
+    ///
+    /// Blocks if a sync is ongoing.
+    pub fn request_trim(&self) {
+        self.sync.lock().trim = true;
+    }
+
+    /// Like [`Self::request_trim`], but also have the free-list rewritten such that the lowest page
+    /// numbers are allocated first, even if there are no free pages at the end of the store. See
+    /// [`FreeList::trim_and_order`].
+    ///
+    /// Blocks if a sync is ongoing.
+    pub fn request_ordered_trim(&self) {
+        let mut sync = self.sync.lock();
+        sync.trim = true;
+        sync.order_free_list = true;
+    }
+
+    /// The number of pages of the store, excluding the nil page, and the number of those which are
+    /// free or store the free-list.
+    ///
+    /// Blocks if a sync is ongoing.
+    pub fn page_counts(&self) -> (u64, u64) {
+        let sync = self.sync.lock();
+        let free = sync.free_list.all_tracked_pages().len() as u64;
+        (sync.bump.0.saturating_sub(1) as u64, free)
+    }
+
+    /// The pages the free-list hands out to the next allocations, in order.
+    ///
+    /// Blocks if a sync is ongoing.
+    pub fn next_allocations(&self) -> Vec<PageNumber> {
+        let sync = self.sync.lock();
+        let free_list = sync.free_list.as_clean();
+        (0..free_list.len())
+            .map(|n| free_list.get_nth_pop(n))
+            .collect()
+    }
+
+    /// Truncate the file after the free pages at its end have been dropped by a sync, along with
+    /// the space it was grown by in advance.
+    ///
+    /// Must be called after the manifest reflecting the sync has been written, as the free-list
+    /// it replaced may be stored in the truncated pages. Returns the number of bytes given back.
+    pub fn truncate(&self) -> std::io::Result<u64> {
+        let mut sync = self.sync.lock();
+        let Some(len) = sync.truncate_to.take() else {
+            return Ok(0);
+        };
+        let old_len = self.file.metadata()?.size();
+        self.file.set_len(len.0 as u64 * PAGE_SIZE as u64)?;
+        sync.max_bump = len;
+        Ok(old_len.saturating_sub(len.0 as u64 * PAGE_SIZE as u64))
+    }
 }
 
 /// A convenience wrapper around a [`Store`]. This wraps the page pool, along with
@@ -164,6 +238,12 @@ struct StoreSync {
     max_bump: PageNumber,
     /// the free-list of pages.
     free_list: FreeList,
+    /// whether the next sync should drop the free pages at the end of the store.
+    trim: bool,
+    /// whether a trim should rewrite the free-list in order even if nothing can be dropped.
+    order_free_list: bool,
+    /// the length in pages to truncate the file to once the sync which trimmed it is complete.
+    truncate_to: Option<PageNumber>,
 }
 
 type StoreSyncGuard = ArcMutexGuard<parking_lot::RawMutex, StoreSync>;
@@ -304,7 +384,19 @@ impl SyncFinisher {
 
         // remaining allocations all logically incremented bump.
         let mut next_bump = PageNumber(sync.bump.0 + bumps as u32);
-        let freelist_pages = sync.free_list.commit(page_pool, freed, &mut next_bump);
+        let trim = std::mem::take(&mut sync.trim) && allocations == 0 && freed.is_empty();
+        let order = std::mem::take(&mut sync.order_free_list);
+        let freelist_pages = if trim {
+            let pages = if order {
+                sync.free_list.trim_and_order(page_pool, &mut next_bump)
+            } else {
+                sync.free_list.trim(page_pool, &mut next_bump)
+            };
+            sync.truncate_to = Some(next_bump);
+            pages
+        } else {
+            sync.free_list.commit(page_pool, freed, &mut next_bump)
+        };
 
         // writing the free-list pages might require more bumps, which may require growing the file
         // further.
@@ -0,0 +1,309 @@
+//! Statistics on the space used by the b-tree, and compaction of the leaf store.
+//!
+//! Deleted entries stay in the leaves until the sync following their commit, and the pages freed
+//! by syncs go to the free-lists of the stores, to be reused by later syncs. The files only shrink
+//! once the pages at their end are free, which a store with long-lived leaves at its end may never
+//! see. Compaction rewrites the leaves and large values stored at the end of the leaf store, so
+//! that they move into free pages before them and the end of the file can be given back.
+
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
+
+use super::{
+    allocator::{PageNumber, StoreReader},
+    index::Index,
+    leaf::{
+        self,
+        node::{LeafNode, MAX_LEAF_VALUE_SIZE},
+        overflow,
+    },
+    leaf_cache::LeafCache,
+    LeafInfo, Shared, Tree, ValueChange,
+};
+use crate::{io::PAGE_SIZE, ValueHandle};
+
+/// Statistics on the space used by the value store. See [`crate::Nomt::compaction_stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionStats {
+    /// The number of entries stored in the leaves.
+    pub live_entries: u64,
+    /// The number of deletions committed but not written to the leaves yet. The deleted entries
+    /// keep taking space in the leaves until the next sync.
+    pub dead_entries: u64,
+    /// The number of leaves.
+    pub leaf_pages: u64,
+    /// The number of bytes used by entries, out of [`LeafInfo::CAPACITY`] per leaf.
+    pub leaf_used_bytes: u64,
+    /// The number of overflow pages, storing values too large for a leaf.
+    pub overflow_pages: u64,
+    /// The number of bytes of the overflow pages which don't store value bytes, including the
+    /// page headers and the page numbers stored in the pages.
+    pub overflow_wasted_bytes: u64,
+    /// The number of pages of the leaf store file.
+    pub ln_pages: u64,
+    /// The number of pages of the leaf store file which are free or store its free-list.
+    pub ln_free_pages: u64,
+    /// The number of pages of the branch node file.
+    pub bbn_pages: u64,
+    /// The number of pages of the branch node file which are free or store its free-list.
+    pub bbn_free_pages: u64,
+}
+
+impl CompactionStats {
+    /// The fraction of the pages of the leaf store file which are free, between 0 and 1.
+    pub fn ln_fragmentation(&self) -> f64 {
+        ratio(self.ln_free_pages, self.ln_pages)
+    }
+
+    /// The fraction of the pages of the branch node file which are free, between 0 and 1.
+    pub fn bbn_fragmentation(&self) -> f64 {
+        ratio(self.bbn_free_pages, self.bbn_pages)
+    }
+
+    /// The fraction of the capacity of the leaves in use, between 0 and 1.
+    pub fn leaf_fill_level(&self) -> f64 {
+        ratio(
+            self.leaf_used_bytes,
+            self.leaf_pages * LeafInfo::CAPACITY as u64,
+        )
+    }
+
+    /// The fraction of the overflow pages which doesn't store value bytes, between 0 and 1.
+    pub fn overflow_waste(&self) -> f64 {
+        ratio(
+            self.overflow_wasted_bytes,
+            self.overflow_pages * PAGE_SIZE as u64,
+        )
+    }
+}
+
+fn ratio(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+/// How to have a leaf rewritten: by inserting anew the value of one of its cells.
+#[derive(Clone, Copy)]
+enum Touch {
+    /// The value of the cell is stored in the leaf.
+    Inline(usize),
+    /// The value of the cell is stored in the given number of overflow pages.
+    Overflow(usize, usize),
+}
+
+/// What is stored in a page of the leaf store, by the index of the leaf in key order.
+enum Owner {
+    Leaf(usize),
+    /// One of the pages of the large value of the given cell.
+    Overflow(usize, usize, usize),
+}
+
+impl Tree {
+    /// Gather [`CompactionStats`].
+    ///
+    /// Every leaf not cached is read from disk, blocking the current thread. Syncs cannot start
+    /// meanwhile.
+    pub fn compaction_stats(&self) -> CompactionStats {
+        // Holding the sync lock waits for an ongoing sync and keeps the leaves in place.
+        let _sync = self.sync.lock();
+        let shared = self.shared.read();
+        let (ln_pages, ln_free_pages) = shared.leaf_store.page_counts();
+        let (bbn_pages, bbn_free_pages) = shared.bbn_store.page_counts();
+        let mut stats = CompactionStats {
+            dead_entries: dead_entries(&shared),
+            ln_pages,
+            ln_free_pages,
+            bbn_pages,
+            bbn_free_pages,
+            ..CompactionStats::default()
+        };
+        let leaves = leaf_page_numbers(&shared.bbn_index);
+        let leaf_cache = shared.leaf_cache.clone();
+        let leaf_store_rd = shared.leaf_store_rd.clone();
+        // Don't hold back commits while reading the leaves.
+        drop(shared);
+
+        for pn in leaves {
+            let leaf = fetch_leaf(&leaf_cache, &leaf_store_rd, pn);
+            let n = leaf.n();
+            stats.leaf_pages += 1;
+            stats.live_entries += n as u64;
+            if n > 0 {
+                stats.leaf_used_bytes += leaf::node::body_size(n, leaf.values_size(0, n)) as u64;
+            }
+            for i in 0..n {
+                let (cell, flags) = leaf.value(i);
+                if !flags.overflow {
+                    continue;
+                }
+                let (value_size, _, _) = overflow::decode_cell(cell);
+                let pages = overflow::total_needed_pages(value_size) as u64;
+                stats.overflow_pages += pages;
+                stats.overflow_wasted_bytes += pages * PAGE_SIZE as u64 - value_size as u64;
+            }
+        }
+        stats
+    }
+
+    /// Stage changes moving the leaves and large values stored at the end of the leaf store into
+    /// the pages the next sync allocates first, as long as those come before them. The changes
+    /// insert the values already stored, leaving the contents of the tree as they are.
+    ///
+    /// Returns the highest page to be moved, or `None` if none can be. Meant to be called with
+    /// nothing staged, after a sync which has ordered the free-list, see
+    /// [`Tree::request_ordered_trim`].
+    pub fn stage_relocation(&self) -> Option<PageNumber> {
+        let _sync = self.sync.lock();
+        let shared = self.shared.read();
+        let next_allocations = shared.leaf_store.next_allocations();
+
+        let leaves = leaf_page_numbers(&shared.bbn_index);
+        let mut touches = Vec::with_capacity(leaves.len());
+        let mut owners = Vec::new();
+        let mut overflow_pages = Vec::new();
+        for (leaf_index, &pn) in leaves.iter().enumerate() {
+            let leaf = fetch_leaf(&shared.leaf_cache, &shared.leaf_store_rd, pn);
+            owners.push((pn, Owner::Leaf(leaf_index)));
+            let mut touch = None;
+            for i in 0..leaf.n() {
+                let (cell, flags) = leaf.value(i);
+                if flags.overflow {
+                    overflow_pages.clear();
+                    overflow::delete(cell, &shared.leaf_store_rd, &mut overflow_pages);
+                    let n_pages = overflow_pages.len();
+                    owners.extend(
+                        overflow_pages
+                            .iter()
+                            .map(|&pn| (pn, Owner::Overflow(leaf_index, i, n_pages))),
+                    );
+                    touch = touch.or(Some(Touch::Overflow(i, n_pages)));
+                } else if !flags.value_log
+                    && !matches!(touch, Some(Touch::Inline(_)))
+                    && leaf::compression::decode_inline(cell, flags).len() <= MAX_LEAF_VALUE_SIZE
+                {
+                    // Rewriting a small value is cheaper than rewriting a large one.
+                    touch = Some(Touch::Inline(i));
+                }
+            }
+            touches.push(touch);
+        }
+
+        // Walk the used pages from the end of the file, as long as the pages the rewrites will be
+        // allocated all come before the pages moved.
+        owners.sort_unstable_by_key(|owner| std::cmp::Reverse(owner.0));
+        let mut allocations = 0;
+        let mut highest_allocation = PageNumber(0);
+        let mut touched = HashSet::new();
+        let mut rewrites = BTreeSet::new();
+        let mut highest_moved = None;
+        for (pn, owner) in owners {
+            let (leaf_index, rewrite) = match owner {
+                Owner::Leaf(leaf_index) if touched.contains(&leaf_index) => continue,
+                Owner::Leaf(leaf_index) => match touches[leaf_index] {
+                    None => break,
+                    Some(Touch::Inline(i)) => (leaf_index, (i, 0)),
+                    Some(Touch::Overflow(i, n_pages)) => (leaf_index, (i, n_pages)),
+                },
+                Owner::Overflow(leaf_index, i, _) if rewrites.contains(&(leaf_index, i)) => {
+                    continue
+                }
+                Owner::Overflow(leaf_index, i, n_pages) => (leaf_index, (i, n_pages)),
+            };
+            let (cell, n_pages) = rewrite;
+            let needed = n_pages + usize::from(!touched.contains(&leaf_index));
+            let Some(planned) = next_allocations.get(allocations..allocations + needed) else {
+                break;
+            };
+            let highest = planned
+                .iter()
+                .copied()
+                .fold(highest_allocation, PageNumber::max);
+            if highest >= pn {
+                break;
+            }
+
+            allocations += needed;
+            highest_allocation = highest;
+            highest_moved = highest_moved.or(Some(pn));
+            touched.insert(leaf_index);
+            rewrites.insert((leaf_index, cell));
+        }
+
+        let changes = rewrites
+            .into_iter()
+            .map(|(leaf_index, i)| {
+                let leaf = fetch_leaf(
+                    &shared.leaf_cache,
+                    &shared.leaf_store_rd,
+                    leaves[leaf_index],
+                );
+                let (cell, flags) = leaf.value(i);
+                let value =
+                    leaf::compression::read(cell, flags, &shared.leaf_store_rd, &shared.value_log);
+                let change = if flags.overflow {
+                    let (_, value_hash, _) = overflow::decode_cell(cell);
+                    ValueChange::InsertOverflow(ValueHandle::new(value), value_hash)
+                } else {
+                    ValueChange::Insert(ValueHandle::new(value))
+                };
+                (leaf.key(i), change)
+            })
+            .collect::<Vec<_>>();
+        drop(shared);
+
+        self.stage(changes);
+        highest_moved
+    }
+
+    /// Like [`Tree::request_trim`], but also have the free-lists rewritten such that the lowest
+    /// pages are allocated first, see [`super::allocator::Store::request_ordered_trim`].
+    pub fn request_ordered_trim(&self) {
+        let shared = self.shared.read();
+        shared.leaf_store.request_ordered_trim();
+        shared.bbn_store.request_ordered_trim();
+    }
+}
+
+/// The number of keys whose most recent staged change is a deletion.
+fn dead_entries(shared: &Shared) -> u64 {
+    let primary = shared
+        .primary_staging
+        .iter()
+        .filter(|(_, change)| matches!(change, ValueChange::Delete))
+        .count();
+    let secondary = shared
+        .secondary_staging
+        .iter()
+        .flat_map(|staging| staging.iter())
+        .filter(|(key, change)| {
+            matches!(change, ValueChange::Delete) && !shared.primary_staging.contains_key(*key)
+        })
+        .count();
+    (primary + secondary) as u64
+}
+
+/// The page numbers of all leaves, in key order.
+fn leaf_page_numbers(bbn_index: &Index) -> Vec<PageNumber> {
+    bbn_index
+        .iter()
+        .flat_map(|(_, branch)| {
+            (0..branch.n() as usize).map(|i| PageNumber(branch.node_pointer(i)))
+        })
+        .collect()
+}
+
+/// Get a leaf from the cache, or read it from disk without adding it to the cache.
+fn fetch_leaf(
+    leaf_cache: &LeafCache,
+    leaf_store_rd: &StoreReader,
+    pn: PageNumber,
+) -> Arc<LeafNode> {
+    leaf_cache.get(pn).unwrap_or_else(|| {
+        Arc::new(LeafNode {
+            inner: leaf_store_rd.query(pn),
+        })
+    })
+}
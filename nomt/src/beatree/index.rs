@@ -43,6 +43,11 @@ impl Index {
         self.first_key_map.insert(separator, branch)
     }
 
+    /// Iterate over all branches in order of their separator keys.
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &Arc<BranchNode>)> {
+        self.first_key_map.iter()
+    }
+
     #[cfg(test)]
     pub fn into_iter(self) -> impl Iterator<Item = (Key, Arc<BranchNode>)> {
         self.first_key_map.into_iter()
@@ -0,0 +1,70 @@
+//! Compression of the values stored in leaves and overflow pages.
+//!
+//! Whether a value is compressed is recorded in its cell pointer, see [`super::node::CellFlags`].
+//! Value hashes are always computed over the uncompressed value.
+
+use crate::{
+    beatree::{allocator::StoreReader, leaf::node::CellFlags, value_log::ValueLog},
+    ValueCompression,
+};
+
+use super::overflow;
+use std::io::Read;
+
+/// Compress a value, returning `None` if compression is disabled or does not make it smaller.
+pub fn compress(value: &[u8], compression: ValueCompression) -> Option<Vec<u8>> {
+    let ValueCompression::Zstd { level } = compression else {
+        return None;
+    };
+    match zstd::bulk::compress(value, level) {
+        Ok(compressed) if compressed.len() < value.len() => Some(compressed),
+        _ => None,
+    }
+}
+
+/// Decode a value stored in a leaf which is neither an overflow cell nor refers to the value log.
+pub fn decode_inline(value: &[u8], flags: CellFlags) -> Vec<u8> {
+    assert!(!flags.overflow && !flags.value_log);
+    if flags.compressed {
+        decompress(value)
+    } else {
+        value.to_vec()
+    }
+}
+
+/// Decode the value of any cell, reading the overflow pages or the value log if necessary.
+pub fn read(
+    cell: &[u8],
+    flags: CellFlags,
+    leaf_reader: &StoreReader,
+    value_log: &ValueLog,
+) -> Vec<u8> {
+    let value = if flags.value_log {
+        value_log.read(cell)
+    } else if flags.overflow {
+        overflow::read(cell, leaf_reader)
+    } else {
+        return decode_inline(cell, flags);
+    };
+    decode(value, flags)
+}
+
+/// Decode a value read from overflow pages or the value log.
+pub fn decode(value: Vec<u8>, flags: CellFlags) -> Vec<u8> {
+    if flags.compressed {
+        decompress(&value)
+    } else {
+        value
+    }
+}
+
+/// Decompress a value as it is read from the given reader.
+pub fn decoder(reader: Box<dyn Read + Send>) -> Box<dyn Read + Send> {
+    // UNWRAP: creating a decoder only fails on allocation failure.
+    Box::new(zstd::stream::read::Decoder::new(reader).expect("failed to create zstd decoder"))
+}
+
+fn decompress(value: &[u8]) -> Vec<u8> {
+    // UNWRAP: compressed values are only written by `compress`. Failure means corruption.
+    zstd::stream::decode_all(value).expect("corrupted compressed value")
+}
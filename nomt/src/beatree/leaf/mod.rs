@@ -9,5 +9,6 @@
 // As soon as the handle is dropped, the data becomes inaccessible and another disk roundtrip would
 // be required to access the data again.
 
+pub mod compression;
 pub mod node;
 pub mod overflow;
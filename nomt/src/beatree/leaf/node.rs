@@ -18,8 +18,10 @@
 /// The length of the value is determined by the difference between the start offsets
 /// of this value and the next.
 ///
-/// When a cell is an overflow cell, the high bit in the offset is set to `1`. Only the low
-/// 15 bits should count when considering the offset.
+/// When a cell is an overflow cell, the high bit in the offset is set to `1`. When the value of
+/// a cell is compressed, the second-highest bit is set to `1`. For overflow cells this refers to
+/// the value stored in the overflow pages. Only the low 14 bits should count when considering
+/// the offset.
 ///
 /// Cells are left-aligned and thus the last value is always attached to the end.
 ///
@@ -46,6 +48,23 @@ pub const MAX_OVERFLOW_CELL_NODE_POINTERS: usize = 15;
 /// We use the high bit to encode whether a cell is an overflow cell.
 const OVERFLOW_BIT: u16 = 1 << 15;
 
+/// We use the second-highest bit to encode whether the value of a cell is compressed.
+const COMPRESSED_BIT: u16 = 1 << 14;
+
+/// We use the third-highest bit to encode whether a cell refers to a value in the value log.
+const VALUE_LOG_BIT: u16 = 1 << 13;
+
+/// How the value of a cell is stored.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CellFlags {
+    /// The cell is an overflow cell.
+    pub overflow: bool,
+    /// The value is compressed with zstd.
+    pub compressed: bool,
+    /// The cell refers to a value in the value log, see [`crate::beatree::value_log`].
+    pub value_log: bool,
+}
+
 pub struct LeafNode {
     pub inner: FatPage,
 }
@@ -63,18 +82,18 @@ impl LeafNode {
         extract_key(&self.cell_pointers()[i])
     }
 
-    pub fn value(&self, i: usize) -> (&[u8], bool) {
-        let (range, overflow) = self.value_range(self.cell_pointers(), i);
-        (&self.inner[range], overflow)
+    pub fn value(&self, i: usize) -> (&[u8], CellFlags) {
+        let (range, flags) = self.value_range(self.cell_pointers(), i);
+        (&self.inner[range], flags)
     }
 
-    pub fn get(&self, key: &Key) -> Option<(&[u8], bool)> {
+    pub fn get(&self, key: &Key) -> Option<(&[u8], CellFlags)> {
         let cell_pointers = self.cell_pointers();
 
         search(cell_pointers, key)
             .ok()
             .map(|index| self.value_range(cell_pointers, index))
-            .map(|(range, overflow)| (&self.inner[range], overflow))
+            .map(|(range, flags)| (&self.inner[range], flags))
     }
 
     pub fn values_size(&self, from: usize, to: usize) -> usize {
@@ -85,15 +104,15 @@ impl LeafNode {
     }
 
     // returns the range at which the value of a cell is stored
-    fn value_range(&self, cell_pointers: &[[u8; 34]], index: usize) -> (Range<usize>, bool) {
-        let (start, overflow) = cell_offset(cell_pointers, index);
+    fn value_range(&self, cell_pointers: &[[u8; 34]], index: usize) -> (Range<usize>, CellFlags) {
+        let (start, flags) = cell_offset(cell_pointers, index);
         let end = if index == cell_pointers.len() - 1 {
             PAGE_SIZE
         } else {
             cell_offset(cell_pointers, index + 1).0
         };
 
-        (start..end, overflow)
+        (start..end, flags)
     }
 
     pub fn cell_pointers(&self) -> &[[u8; 34]] {
@@ -131,13 +150,13 @@ impl LeafBuilder {
         }
     }
 
-    pub fn push_cell(&mut self, key: Key, value: &[u8], overflow: bool) {
+    pub fn push_cell(&mut self, key: Key, value: &[u8], flags: CellFlags) {
         assert!(self.index < self.leaf.n());
 
         let offset = PAGE_SIZE - self.remaining_value_size;
         let cell_pointer = &mut self.leaf.cell_pointers_mut()[self.index];
 
-        encode_cell_pointer(&mut cell_pointer[..], key, offset, overflow);
+        encode_cell_pointer(&mut cell_pointer[..], key, offset, flags);
         self.leaf.inner[offset..][..value.len()].copy_from_slice(value);
 
         self.index += 1;
@@ -205,25 +224,35 @@ pub fn extract_key(cell_pointer: &[u8; 34]) -> Key {
     buf
 }
 
-// get the cell offset and whether the cell is an overflow cell.
-fn cell_offset(cell_pointers: &[[u8; 34]], index: usize) -> (usize, bool) {
+// get the cell offset and how the value of the cell is stored.
+fn cell_offset(cell_pointers: &[[u8; 34]], index: usize) -> (usize, CellFlags) {
     let mut buf = [0; 2];
     buf.copy_from_slice(&cell_pointers[index][32..34]);
     let val = u16::from_le_bytes(buf);
     (
-        (val & !OVERFLOW_BIT) as usize,
-        val & OVERFLOW_BIT == OVERFLOW_BIT,
+        (val & !(OVERFLOW_BIT | COMPRESSED_BIT | VALUE_LOG_BIT)) as usize,
+        CellFlags {
+            overflow: val & OVERFLOW_BIT == OVERFLOW_BIT,
+            compressed: val & COMPRESSED_BIT == COMPRESSED_BIT,
+            value_log: val & VALUE_LOG_BIT == VALUE_LOG_BIT,
+        },
     )
 }
 
-// panics if offset is bigger than 2^15 - 1.
-fn encode_cell_pointer(cell: &mut [u8], key: [u8; 32], offset: usize, overflow: bool) {
+// panics if offset is bigger than 2^13 - 1.
+fn encode_cell_pointer(cell: &mut [u8], key: [u8; 32], offset: usize, flags: CellFlags) {
     let mut val = u16::try_from(offset).unwrap();
-    assert!(val < OVERFLOW_BIT);
+    assert!(val < VALUE_LOG_BIT);
 
-    if overflow {
+    if flags.overflow {
         val |= OVERFLOW_BIT;
     }
+    if flags.compressed {
+        val |= COMPRESSED_BIT;
+    }
+    if flags.value_log {
+        val |= VALUE_LOG_BIT;
+    }
 
     cell[0..32].copy_from_slice(&key);
     cell[32..34].copy_from_slice(&val.to_le_bytes());
@@ -240,7 +269,7 @@ pub mod benches {
     use crate::{
         beatree::{
             benches::get_keys,
-            leaf::node::{LeafBuilder, LEAF_NODE_BODY_SIZE},
+            leaf::node::{CellFlags, LeafBuilder, LEAF_NODE_BODY_SIZE},
         },
         io::PagePool,
     };
@@ -264,7 +293,7 @@ pub mod benches {
         let mut keys = get_keys(0, n);
         keys.sort();
         for (index, k) in keys.iter().enumerate() {
-            leaf_builder.push_cell(k.clone(), &(index as u32).to_le_bytes()[..], false);
+            leaf_builder.push_cell(*k, &(index as u32).to_le_bytes()[..], CellFlags::default());
         }
         let leaf = leaf_builder.finish();
 
@@ -310,7 +339,7 @@ pub mod benches {
                     |(keys, value)| {
                         let mut leaf_builder = LeafBuilder::new(&page_pool, n, n * value_size);
                         for k in keys.into_iter() {
-                            leaf_builder.push_cell(k, &value[..], false);
+                            leaf_builder.push_cell(k, &value[..], CellFlags::default());
                         }
                         leaf_builder.finish();
                     },
@@ -23,17 +23,18 @@ const BODY_SIZE: usize = PAGE_SIZE - 4;
 const MAX_PNS: usize = BODY_SIZE / 4;
 const HEADER_SIZE: usize = 4;
 
-/// Encode a large value into freshly allocated overflow pages. Returns a vector of page pointers
-/// and the total number of page writes submitted.
+/// Encode a large value, given as consecutive parts, into freshly allocated overflow pages.
+/// Returns a vector of page pointers and the total number of page writes submitted.
 pub fn chunk(
-    value: &[u8],
+    parts: &[&[u8]],
     leaf_writer: &SyncAllocator,
     page_pool: &PagePool,
     io_handle: &IoHandle,
 ) -> anyhow::Result<(Vec<PageNumber>, usize)> {
-    assert!(!value.is_empty());
+    let value_len = parts.iter().map(|part| part.len()).sum::<usize>();
+    assert!(value_len > 0);
 
-    let total_pages = total_needed_pages(value.len());
+    let total_pages = total_needed_pages(value_len);
     let cell_pages = std::cmp::min(total_pages, MAX_OVERFLOW_CELL_NODE_POINTERS);
     let cell = (0..cell_pages)
         .map(|_| leaf_writer.allocate())
@@ -46,10 +47,12 @@ pub fn chunk(
     let all_pages = cell.iter().cloned().chain(other_pages.iter().cloned());
     let mut to_write = other_pages.iter().cloned();
 
-    let mut value = value;
+    let mut parts = parts.iter().copied().filter(|part| !part.is_empty());
+    let mut value = parts.next().unwrap_or_default();
+    let mut remaining = value_len;
     // loop over all page numbers.
     for pn in all_pages {
-        assert!(!value.is_empty());
+        assert!(remaining > 0);
 
         // allocate a page.
         let mut page = page_pool.alloc_fat_page();
@@ -65,16 +68,26 @@ pub fn chunk(
         }
 
         // then write as many value bytes as possible.
-        let bytes = std::cmp::min(BODY_SIZE - pns_written * 4, value.len());
+        let bytes = std::cmp::min(BODY_SIZE - pns_written * 4, remaining);
 
         // write the header.
         page[0..2].copy_from_slice(&(pns_written as u16).to_le_bytes());
         page[2..4].copy_from_slice(&(bytes as u16).to_le_bytes());
 
-        let start = HEADER_SIZE + pns_written * 4;
+        // the bytes of a page may span several parts.
+        let mut start = HEADER_SIZE + pns_written * 4;
         let end = start + bytes;
-        page[start..end].copy_from_slice(&value[..bytes]);
-        value = &value[bytes..];
+        while start < end {
+            if value.is_empty() {
+                // UNWRAP: the parts add up to `remaining` bytes.
+                value = parts.next().unwrap();
+            }
+            let n = std::cmp::min(end - start, value.len());
+            page[start..start + n].copy_from_slice(&value[..n]);
+            value = &value[n..];
+            start += n;
+        }
+        remaining -= bytes;
 
         // write the page.
         let command = IoCommand {
@@ -83,7 +96,7 @@ pub fn chunk(
         };
         io_handle.send(command).expect("I/O Pool Down");
     }
-    assert!(value.is_empty());
+    assert_eq!(remaining, 0);
 
     Ok((cell, total_pages))
 }
@@ -116,7 +129,7 @@ pub fn encode_cell(value_size: usize, value_hash: [u8; 32], pages: &[PageNumber]
     v
 }
 
-fn total_needed_pages(value_size: usize) -> usize {
+pub fn total_needed_pages(value_size: usize) -> usize {
     // the encoded size is equal to the size of the value plus the number of node pointers that
     // will appear in pages.
     let needed_pages_raw_value = needed_pages(value_size);
@@ -194,6 +207,123 @@ pub fn read(cell: &[u8], leaf_reader: &StoreReader) -> Vec<u8> {
     value
 }
 
+/// Reads the value of an overflow cell from its pages, one page at a time as it is consumed.
+///
+/// Unlike [`read`], the value is never held in memory in its entirety.
+pub struct Reader {
+    leaf_reader: StoreReader,
+    total_pages: usize,
+    page_numbers: Vec<PageNumber>,
+    next_page: usize,
+    /// The page being read along with the range of its value bytes not read yet.
+    page: Option<(FatPage, usize, usize)>,
+}
+
+impl Reader {
+    pub fn new(cell: &[u8], leaf_reader: StoreReader) -> Self {
+        let (value_size, _, cell_pages) = decode_cell(cell);
+        let total_pages = total_needed_pages(value_size);
+        let mut page_numbers = Vec::with_capacity(total_pages);
+        page_numbers.extend(cell_pages);
+        Reader {
+            leaf_reader,
+            total_pages,
+            page_numbers,
+            next_page: 0,
+            page: None,
+        }
+    }
+}
+
+impl std::io::Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if let Some((ref page, ref mut start, end)) = self.page {
+                if *start < end {
+                    let n = std::cmp::min(buf.len(), end - *start);
+                    buf[..n].copy_from_slice(&page[*start..*start + n]);
+                    *start += n;
+                    return Ok(n);
+                }
+            }
+            if self.next_page == self.total_pages {
+                return Ok(0);
+            }
+
+            let page = self.leaf_reader.query(self.page_numbers[self.next_page]);
+            self.next_page += 1;
+            let known_pages = self.page_numbers.len();
+            let (page_pns, bytes) = read_page(&page);
+            let n_bytes = bytes.len();
+            self.page_numbers.extend(page_pns);
+            // the value bytes follow the page numbers.
+            let start = HEADER_SIZE + (self.page_numbers.len() - known_pages) * 4;
+            self.page = Some((page, start, start + n_bytes));
+        }
+    }
+}
+
+/// Reassembles the value of an overflow cell from its pages, which may be read in any order.
+///
+/// This is the non-blocking counterpart of [`read`]: the caller reads the pages returned by
+/// [`Self::take_requests`] and passes them to [`Self::complete`]. Pages become known as the pages
+/// pointing to them are completed.
+pub struct Assembler {
+    value_size: usize,
+    total_pages: usize,
+    page_numbers: Vec<PageNumber>,
+    pages: Vec<Option<FatPage>>,
+    requested: usize,
+    parsed: usize,
+    value: Vec<u8>,
+}
+
+impl Assembler {
+    pub fn new(cell: &[u8]) -> Self {
+        let (value_size, _, cell_pages) = decode_cell(cell);
+        let total_pages = total_needed_pages(value_size);
+        let mut page_numbers = Vec::with_capacity(total_pages);
+        page_numbers.extend(cell_pages);
+        Assembler {
+            value_size,
+            total_pages,
+            page_numbers,
+            pages: (0..total_pages).map(|_| None).collect(),
+            requested: 0,
+            parsed: 0,
+            value: Vec::with_capacity(value_size),
+        }
+    }
+
+    /// The index and number of the pages which are known but have not been requested yet.
+    pub fn take_requests(&mut self) -> Vec<(usize, PageNumber)> {
+        let requests = (self.requested..self.page_numbers.len())
+            .map(|i| (i, self.page_numbers[i]))
+            .collect();
+        self.requested = self.page_numbers.len();
+        requests
+    }
+
+    /// Record a page that has been read. Returns the value once all pages have been completed.
+    pub fn complete(&mut self, index: usize, page: FatPage) -> Option<Vec<u8>> {
+        self.pages[index] = Some(page);
+        // Pages must be parsed in order, as the pointers of one page extend the list of pages.
+        while let Some(page) = self.pages.get_mut(self.parsed).and_then(Option::take) {
+            let (page_pns, bytes) = read_page(&page);
+            self.page_numbers.extend(page_pns);
+            self.value.extend(bytes);
+            self.parsed += 1;
+        }
+        if self.parsed < self.total_pages {
+            return None;
+        }
+
+        assert_eq!(self.page_numbers.len(), self.total_pages);
+        assert_eq!(self.value.len(), self.value_size);
+        Some(std::mem::take(&mut self.value))
+    }
+}
+
 /// Iterate all pages related to an overflow cell and push onto a free-list.
 pub fn delete(cell: &[u8], leaf_reader: &StoreReader, freed: &mut Vec<PageNumber>) {
     let (value_size, _, cell_pages) = decode_cell(cell);
@@ -2,8 +2,28 @@
 
 use crate::beatree::{allocator::PageNumber, leaf::node::LeafNode};
 use lru::LruCache;
-use parking_lot::{Mutex, MutexGuard};
-use std::{collections::hash_map::RandomState, hash::BuildHasher, sync::Arc};
+use parking_lot::{Condvar, Mutex, MutexGuard};
+use std::{
+    collections::{
+        hash_map::{Entry, RandomState},
+        HashMap,
+    },
+    hash::BuildHasher,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// How a leaf is placed in the LRU order when it is accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    /// The leaf becomes the most recently used one.
+    Hot,
+    /// The leaf keeps its position, or becomes the least recently used one if it wasn't cached.
+    /// This is used for scans, which should not displace the working set.
+    Cold,
+}
 
 /// A cache for leaf nodes.
 ///
@@ -23,11 +43,13 @@ impl LeafCache {
                 shards: (0..shards)
                     .map(|_| Shard {
                         cache: LruCache::unbounded(),
+                        in_flight: HashMap::new(),
                         max_items: items_per_shard,
                     })
                     .map(Mutex::new)
                     .collect::<Vec<_>>(),
                 shard_assigner: RandomState::new(),
+                deduplicated_fetches: AtomicU64::new(0),
             }),
         }
     }
@@ -39,6 +61,65 @@ impl LeafCache {
         shard.cache.get(&page_number).map(|x| x.clone())
     }
 
+    /// Get a cache entry, or fetch it with the given function and insert it.
+    ///
+    /// Concurrent fetches of the same page are deduplicated: only the first caller fetches the
+    /// page, while others block until it is available. If the fetch panics, the waiting callers
+    /// retry, one of them fetching the page in turn.
+    pub fn get_or_fetch(
+        &self,
+        page_number: PageNumber,
+        admission: Admission,
+        fetch: impl FnOnce() -> LeafNode,
+    ) -> Arc<LeafNode> {
+        let in_flight = loop {
+            let in_flight = {
+                let mut shard = self.inner.shard_for(page_number);
+                let cached = match admission {
+                    Admission::Hot => shard.cache.get(&page_number),
+                    Admission::Cold => shard.cache.peek(&page_number),
+                };
+                if let Some(leaf) = cached {
+                    return leaf.clone();
+                }
+                match shard.in_flight.entry(page_number) {
+                    Entry::Occupied(e) => e.get().clone(),
+                    Entry::Vacant(e) => break e.insert(Arc::new(InFlight::default())).clone(),
+                }
+            };
+
+            self.inner
+                .deduplicated_fetches
+                .fetch_add(1, Ordering::Relaxed);
+            if let Some(leaf) = in_flight.wait() {
+                return leaf;
+            }
+        };
+
+        let guard = FetchGuard {
+            shared: &self.inner,
+            page_number,
+            in_flight,
+            completed: false,
+        };
+        let leaf = Arc::new(fetch());
+        let mut shard = self.inner.shard_for(page_number);
+        shard.cache.put(page_number, leaf.clone());
+        if admission == Admission::Cold {
+            shard.cache.demote(&page_number);
+        }
+        shard.in_flight.remove(&page_number);
+        drop(shard);
+        guard.complete(leaf.clone());
+        leaf
+    }
+
+    /// The number of fetches which were served by attaching to a fetch of the same page already in
+    /// progress.
+    pub fn deduplicated_fetches(&self) -> u64 {
+        self.inner.deduplicated_fetches.load(Ordering::Relaxed)
+    }
+
     /// Insert a cache entry. This does not evict anything.
     pub fn insert(&self, page_number: PageNumber, node: Arc<LeafNode>) {
         let mut shard = self.inner.shard_for(page_number);
@@ -46,6 +127,26 @@ impl LeafCache {
         shard.cache.put(page_number, node);
     }
 
+    /// Insert a leaf fetched by the caller according to the given admission policy, unless it is
+    /// cached already. This does not evict anything.
+    pub fn admit(&self, page_number: PageNumber, node: Arc<LeafNode>, admission: Admission) {
+        let mut shard = self.inner.shard_for(page_number);
+        if shard.cache.contains(&page_number) {
+            return;
+        }
+        shard.cache.put(page_number, node);
+        if admission == Admission::Cold {
+            shard.cache.demote(&page_number);
+        }
+    }
+
+    /// Remove all items from the cache. Fetches in progress are not affected.
+    pub fn clear(&self) {
+        for shard in &self.inner.shards {
+            shard.lock().cache.clear();
+        }
+    }
+
     /// Evict all excess items from the cache.
     pub fn evict(&self) {
         for shard in &self.inner.shards {
@@ -60,6 +161,7 @@ impl LeafCache {
 struct Shared {
     shards: Vec<Mutex<Shard>>,
     shard_assigner: RandomState,
+    deduplicated_fetches: AtomicU64,
 }
 
 impl Shared {
@@ -74,5 +176,161 @@ impl Shared {
 
 struct Shard {
     cache: LruCache<PageNumber, Arc<LeafNode>>,
+    /// Fetches in progress, which concurrent callers of `get_or_fetch` wait on.
+    in_flight: HashMap<PageNumber, Arc<InFlight>>,
     max_items: usize,
 }
+
+#[derive(Default)]
+struct InFlight {
+    state: Mutex<FetchState>,
+    cvar: Condvar,
+}
+
+#[derive(Default)]
+enum FetchState {
+    #[default]
+    Pending,
+    Done(Arc<LeafNode>),
+    /// The fetching caller panicked.
+    Abandoned,
+}
+
+impl InFlight {
+    /// Wait for the fetch to finish. Returns `None` if it was abandoned.
+    fn wait(&self) -> Option<Arc<LeafNode>> {
+        let mut state = self.state.lock();
+        self.cvar
+            .wait_while(&mut state, |state| matches!(state, FetchState::Pending));
+        match *state {
+            FetchState::Done(ref leaf) => Some(leaf.clone()),
+            FetchState::Pending | FetchState::Abandoned => None,
+        }
+    }
+
+    fn finish(&self, state: FetchState) {
+        *self.state.lock() = state;
+        self.cvar.notify_all();
+    }
+}
+
+/// Abandons a fetch registered in the in-flight map when dropped before completing it, e.g.
+/// because the fetch panicked. This removes the registration and wakes the waiting callers.
+struct FetchGuard<'a> {
+    shared: &'a Shared,
+    page_number: PageNumber,
+    in_flight: Arc<InFlight>,
+    completed: bool,
+}
+
+impl FetchGuard<'_> {
+    /// Hand the fetched leaf to the waiting callers. The registration must be removed already.
+    fn complete(mut self, leaf: Arc<LeafNode>) {
+        self.in_flight.finish(FetchState::Done(leaf));
+        self.completed = true;
+    }
+}
+
+impl Drop for FetchGuard<'_> {
+    fn drop(&mut self) {
+        if self.completed {
+            return;
+        }
+        self.shared
+            .shard_for(self.page_number)
+            .in_flight
+            .remove(&self.page_number);
+        self.in_flight.finish(FetchState::Abandoned);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Admission, LeafCache};
+    use crate::{
+        beatree::{allocator::PageNumber, leaf::node::LeafNode},
+        io::PagePool,
+    };
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc, Arc,
+        },
+        thread,
+    };
+
+    #[test]
+    fn concurrent_fetches_are_deduplicated() {
+        let cache = LeafCache::new(1, 16);
+        let page_pool = PagePool::new();
+        let pn = PageNumber(1);
+        let fetches = AtomicUsize::new(0);
+        let (started_tx, started_rx) = mpsc::channel();
+        let fetch = || {
+            fetches.fetch_add(1, Ordering::Relaxed);
+            LeafNode {
+                inner: page_pool.alloc_fat_page(),
+            }
+        };
+
+        thread::scope(|s| {
+            // The first fetch only completes once both other callers have attached to it.
+            let fetcher = s.spawn(|| {
+                cache.get_or_fetch(pn, Admission::Hot, || {
+                    started_tx.send(()).unwrap();
+                    while cache.deduplicated_fetches() < 2 {
+                        thread::yield_now();
+                    }
+                    fetch()
+                })
+            });
+            started_rx.recv().unwrap();
+            let waiters = [
+                s.spawn(|| cache.get_or_fetch(pn, Admission::Hot, fetch)),
+                s.spawn(|| cache.get_or_fetch(pn, Admission::Cold, fetch)),
+            ];
+
+            let leaf = fetcher.join().unwrap();
+            for waiter in waiters {
+                assert!(Arc::ptr_eq(&waiter.join().unwrap(), &leaf));
+            }
+        });
+        assert_eq!(fetches.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.deduplicated_fetches(), 2);
+
+        // Cached leaves are not fetched, nor counted as deduplicated.
+        cache.get_or_fetch(pn, Admission::Hot, fetch);
+        assert_eq!(fetches.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.deduplicated_fetches(), 2);
+    }
+
+    #[test]
+    fn panicking_fetch_wakes_waiters() {
+        let cache = LeafCache::new(1, 16);
+        let page_pool = PagePool::new();
+        let pn = PageNumber(1);
+        let (started_tx, started_rx) = mpsc::channel();
+
+        thread::scope(|s| {
+            let fetcher = s.spawn(|| {
+                cache.get_or_fetch(pn, Admission::Hot, || {
+                    started_tx.send(()).unwrap();
+                    while cache.deduplicated_fetches() == 0 {
+                        thread::yield_now();
+                    }
+                    panic!("fetch failed");
+                })
+            });
+            started_rx.recv().unwrap();
+            let waiter = s.spawn(|| {
+                cache.get_or_fetch(pn, Admission::Hot, || LeafNode {
+                    inner: page_pool.alloc_fat_page(),
+                })
+            });
+
+            assert!(fetcher.join().is_err());
+            waiter.join().unwrap();
+        });
+        assert!(cache.get(pn).is_some());
+    }
+}
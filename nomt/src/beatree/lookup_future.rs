@@ -0,0 +1,218 @@
+//! Lookups which wait for I/O without blocking the thread. See [`super::Tree::lookup_async`].
+//!
+//! Leaves and overflow pages are read through the I/O pool, whose workers wake the task once a
+//! read completes. A page read this way is only used if no sync has finished since the page number
+//! was taken from the index: pages freed by a sync are not overwritten before the next sync, which
+//! starts after it has finished. Otherwise, the lookup starts over.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+
+use parking_lot::{Mutex, RwLock};
+
+use super::{
+    allocator::PageNumber,
+    leaf::{
+        self,
+        node::{CellFlags, LeafNode},
+        overflow,
+    },
+    leaf_cache::Admission,
+    ops, Key, Shared,
+};
+use crate::{io::CompleteIo, ReadConsistency};
+
+/// A lookup of a key in the btree, resolving to its value. Values stored in the value log are read
+/// blocking, once the leaf referring to them has been read.
+pub struct LookupFuture {
+    shared: Arc<RwLock<Shared>>,
+    key: Key,
+    admission: Admission,
+    consistency: ReadConsistency,
+    state: State,
+    /// Distinguishes the reads of the current attempt from those of earlier ones.
+    attempt: u32,
+    reads: Arc<Mutex<Reads>>,
+}
+
+enum State {
+    Start,
+    /// Waiting for the leaf which may hold the key, taken from the index as of the given sync.
+    Leaf(PageNumber, u64),
+    /// Waiting for the overflow pages of the value, taken from the index as of the given sync.
+    Overflow(overflow::Assembler, CellFlags, u64),
+}
+
+/// The reads completed since the last poll, along with the waker of the task awaiting them.
+#[derive(Default)]
+struct Reads {
+    completed: Vec<CompleteIo>,
+    waker: Option<Waker>,
+}
+
+type Lookup = Poll<anyhow::Result<Option<Vec<u8>>>>;
+
+impl LookupFuture {
+    pub(super) fn new(
+        shared: Arc<RwLock<Shared>>,
+        key: Key,
+        admission: Admission,
+        consistency: ReadConsistency,
+    ) -> Self {
+        LookupFuture {
+            shared,
+            key,
+            admission,
+            consistency,
+            state: State::Start,
+            attempt: 0,
+            reads: Arc::default(),
+        }
+    }
+
+    fn advance(&mut self, completed: Vec<CompleteIo>) -> Lookup {
+        let shared = self.shared.clone();
+        let shared = shared.read();
+        match std::mem::replace(&mut self.state, State::Start) {
+            State::Start => self.start(&shared),
+            State::Leaf(pn, sync_epoch) => {
+                // UNWRAP: a single read is issued for the leaf.
+                let complete = completed.into_iter().next().unwrap();
+                if let Err(e) = complete.result {
+                    return Poll::Ready(Err(e.into()));
+                }
+                if shared.sync_epoch != sync_epoch {
+                    return self.restart(&shared);
+                }
+                let leaf = Arc::new(LeafNode {
+                    inner: complete.command.kind.unwrap_buf(),
+                });
+                shared.leaf_cache.admit(pn, leaf.clone(), self.admission);
+                self.read_cell(&shared, &leaf, sync_epoch)
+            }
+            State::Overflow(mut assembler, flags, sync_epoch) => {
+                for complete in completed {
+                    if let Err(e) = complete.result {
+                        return Poll::Ready(Err(e.into()));
+                    }
+                    if shared.sync_epoch != sync_epoch {
+                        return self.restart(&shared);
+                    }
+                    let index = complete.command.user_data as u32 as usize;
+                    let page = complete.command.kind.unwrap_buf();
+                    if let Some(value) = assembler.complete(index, page) {
+                        return Poll::Ready(Ok(Some(leaf::compression::decode(value, flags))));
+                    }
+                }
+                if let Err(e) = self.submit(&shared, assembler.take_requests()) {
+                    return Poll::Ready(Err(e));
+                }
+                self.state = State::Overflow(assembler, flags, sync_epoch);
+                Poll::Pending
+            }
+        }
+    }
+
+    fn start(&mut self, shared: &Shared) -> Lookup {
+        if let Some(change) = super::staged_change(shared, self.key, self.consistency) {
+            return Poll::Ready(Ok(change.as_option().map(|v| v.to_vec())));
+        }
+
+        let Some(pn) = ops::find_leaf(self.key, &shared.bbn_index) else {
+            return Poll::Ready(Ok(None));
+        };
+        if let Some(leaf) = shared.leaf_cache.get(pn) {
+            return self.read_cell(shared, &leaf, shared.sync_epoch);
+        }
+        if let Err(e) = self.submit(shared, [(0, pn)]) {
+            return Poll::Ready(Err(e));
+        }
+        self.state = State::Leaf(pn, shared.sync_epoch);
+        Poll::Pending
+    }
+
+    fn restart(&mut self, shared: &Shared) -> Lookup {
+        self.attempt += 1;
+        self.start(shared)
+    }
+
+    fn read_cell(&mut self, shared: &Shared, leaf: &LeafNode, sync_epoch: u64) -> Lookup {
+        let Some((cell, flags)) = leaf.get(&self.key) else {
+            return Poll::Ready(Ok(None));
+        };
+        if !flags.overflow {
+            let value =
+                leaf::compression::read(cell, flags, &shared.leaf_store_rd, &shared.value_log);
+            return Poll::Ready(Ok(Some(value)));
+        }
+
+        let mut assembler = overflow::Assembler::new(cell);
+        if let Err(e) = self.submit(shared, assembler.take_requests()) {
+            return Poll::Ready(Err(e));
+        }
+        self.state = State::Overflow(assembler, flags, sync_epoch);
+        Poll::Pending
+    }
+
+    /// Read the given pages, tagging each with its index.
+    fn submit(
+        &self,
+        shared: &Shared,
+        pages: impl IntoIterator<Item = (usize, PageNumber)>,
+    ) -> anyhow::Result<()> {
+        for (index, pn) in pages {
+            let user_data = (self.attempt as u64) << 32 | index as u64;
+            let command = shared.leaf_store_rd.io_command(pn, user_data);
+            let reads = self.reads.clone();
+            shared
+                .io_handle
+                .io_pool()
+                .send_with_callback(command, move |complete| {
+                    let mut reads = reads.lock();
+                    reads.completed.push(complete);
+                    let waker = reads.waker.take();
+                    drop(reads);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                })
+                .map_err(|_| anyhow::anyhow!("I/O pool hung up"))?;
+        }
+        Ok(())
+    }
+}
+
+impl Future for LookupFuture {
+    type Output = anyhow::Result<Option<Vec<u8>>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Lookup {
+        let this = &mut *self;
+        if let State::Start = this.state {
+            if let Poll::Ready(res) = this.start(&this.shared.clone().read()) {
+                return Poll::Ready(res);
+            }
+        }
+        loop {
+            let completed = {
+                let mut reads = this.reads.lock();
+                let attempt = this.attempt;
+                // Reads of earlier attempts are stale.
+                reads
+                    .completed
+                    .retain(|complete| (complete.command.user_data >> 32) as u32 == attempt);
+                if reads.completed.is_empty() {
+                    reads.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                std::mem::take(&mut reads.completed)
+            };
+            if let Poll::Ready(res) = this.advance(completed) {
+                return Poll::Ready(res);
+            }
+        }
+    }
+}
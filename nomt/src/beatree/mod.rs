@@ -5,22 +5,34 @@ use crossbeam_channel::Receiver;
 use imbl::OrdMap;
 
 use leaf::node::MAX_LEAF_VALUE_SIZE;
+pub use leaf_cache::Admission;
 use nomt_core::trie::ValueHash;
 use parking_lot::{ArcMutexGuard, Condvar, Mutex, RwLock};
-use std::{fs::File, mem, path::Path, sync::Arc};
+use std::{collections::BTreeMap, fs::File, mem, path::Path, sync::Arc};
 use threadpool::ThreadPool;
 
-use crate::io::{fsyncer::Fsyncer, IoHandle, IoPool, PagePool};
+use crate::{
+    background_error::BackgroundErrorSource,
+    explain::{ReadStepKind, StepRecorder},
+    io::{fsyncer::Fsyncer, IoHandle, IoPool, PagePool},
+    threads, ReadConsistency, SyncedFile, ThreadConfig, ValueCompression, ValueHandle, ValueReader,
+};
 
 mod allocator;
 mod branch;
+mod compaction;
 mod index;
 mod leaf;
 mod leaf_cache;
+mod lookup_future;
 mod ops;
+mod value_log;
 
 mod writeout;
+pub use compaction::CompactionStats;
 use index::Index;
+pub use lookup_future::LookupFuture;
+pub use value_log::ValueLog;
 
 #[cfg(feature = "benchmarks")]
 pub mod benches;
@@ -32,6 +44,7 @@ pub struct Tree {
     read_transaction_counter: ReadTransactionCounter,
     shared: Arc<RwLock<Shared>>,
     sync: Arc<Mutex<Sync>>,
+    prefetch_tp: ThreadPool,
 }
 
 struct Shared {
@@ -48,11 +61,15 @@ struct Shared {
     /// if there is no sync in progress.
     secondary_staging: Option<OrdMap<Key, ValueChange>>,
     leaf_cache: leaf_cache::LeafCache,
+    value_log: ValueLog,
+    /// The number of syncs finished since the tree was opened.
+    sync_epoch: u64,
 }
 
 struct Sync {
     tp: ThreadPool,
     commit_concurrency: usize,
+    value_compression: ValueCompression,
     bbn_fsync: Arc<Fsyncer>,
     ln_fsync: Arc<Fsyncer>,
 }
@@ -77,6 +94,9 @@ impl Tree {
         bbn_file: Arc<File>,
         ln_file: Arc<File>,
         commit_concurrency: usize,
+        value_compression: ValueCompression,
+        value_log: ValueLog,
+        threads: &ThreadConfig,
     ) -> Result<Tree> {
         let ln_freelist_pn = Some(ln_freelist_pn)
             .map(PageNumber)
@@ -88,14 +108,28 @@ impl Tree {
         let ln_bump = PageNumber(ln_bump);
         let bbn_bump = PageNumber(bbn_bump);
 
-        let leaf_store = Store::open(&page_pool, ln_file.clone(), ln_bump, ln_freelist_pn)?;
+        let cipher = io_pool.cipher().cloned();
+        let leaf_store = Store::open(
+            &page_pool,
+            cipher.clone(),
+            ln_file.clone(),
+            ln_bump,
+            ln_freelist_pn,
+        )?;
 
-        let bbn_store = Store::open(&page_pool, bbn_file.clone(), bbn_bump, bbn_freelist_pn)?;
+        let bbn_store = Store::open(
+            &page_pool,
+            cipher.clone(),
+            bbn_file.clone(),
+            bbn_bump,
+            bbn_freelist_pn,
+        )?;
 
         let bbn_freelist_tracked = bbn_store.all_tracked_freelist_pages();
         let index = ops::reconstruct(
             bbn_file.clone(),
             &page_pool,
+            cipher.as_deref(),
             &bbn_freelist_tracked,
             bbn_bump,
         )
@@ -110,34 +144,55 @@ impl Tree {
             primary_staging: OrdMap::new(),
             secondary_staging: None,
             leaf_cache: leaf_cache::LeafCache::new(32, 1 << 16),
+            value_log,
+            sync_epoch: 0,
         };
 
         let sync = Sync {
             // +1 for the begin_sync task.
-            tp: ThreadPool::with_name("beatree-sync".into(), commit_concurrency + 1),
+            tp: threads::pool(
+                threads::thread_name(threads, "beatree-sync"),
+                commit_concurrency + 1,
+                &threads.commit,
+            )?,
             commit_concurrency,
-            bbn_fsync: Arc::new(Fsyncer::new("bbn", bbn_file)),
-            ln_fsync: Arc::new(Fsyncer::new("ln", ln_file)),
+            value_compression,
+            bbn_fsync: Arc::new(Fsyncer::new(
+                "bbn",
+                bbn_file,
+                SyncedFile::Branches,
+                io_pool.clone(),
+            )),
+            ln_fsync: Arc::new(Fsyncer::new(
+                "ln",
+                ln_file,
+                SyncedFile::Leaves,
+                io_pool.clone(),
+            )),
         };
 
         Ok(Tree {
             shared: Arc::new(RwLock::new(shared)),
             sync: Arc::new(Mutex::new(sync)),
             read_transaction_counter: ReadTransactionCounter::new(),
+            prefetch_tp: ThreadPool::with_name("beatree-prefetch".into(), 4),
         })
     }
 
-    /// Lookup a key in the btree.
-    pub fn lookup(&self, key: Key) -> Option<Vec<u8>> {
+    /// Lookup a key in the btree, placing the leaf holding it in the leaf cache according to the
+    /// given admission policy.
+    ///
+    /// With [`ReadConsistency::LastSynced`], the staged changes are skipped, so that only the btree
+    /// as of the last completed sync is consulted.
+    pub fn lookup(
+        &self,
+        key: Key,
+        admission: Admission,
+        consistency: ReadConsistency,
+    ) -> Option<Vec<u8>> {
         let shared = self.shared.read();
 
-        // First look up in the primary staging which contains the most recent changes.
-        if let Some(val) = shared.primary_staging.get(&key) {
-            return val.as_option().map(|v| v.to_vec());
-        }
-
-        // Then check the secondary staging which is a bit older, but fresher still than the btree.
-        if let Some(val) = shared.secondary_staging.as_ref().and_then(|x| x.get(&key)) {
+        if let Some(val) = staged_change(&shared, key, consistency) {
             return val.as_option().map(|v| v.to_vec());
         }
 
@@ -147,10 +202,300 @@ impl Tree {
             &shared.bbn_index,
             &shared.leaf_cache,
             &shared.leaf_store_rd,
+            &shared.value_log,
+            admission,
         )
         .unwrap()
     }
 
+    /// Lookup a key like [`Self::lookup`], recording the steps taken. The leaf is admitted to the
+    /// leaf cache as cold.
+    pub fn explain_lookup(
+        &self,
+        key: Key,
+        consistency: ReadConsistency,
+        steps: &mut StepRecorder,
+    ) -> Option<Vec<u8>> {
+        let shared = self.shared.read();
+
+        let staged = staged_change(&shared, key, consistency);
+        steps.record(
+            ReadStepKind::Staging {
+                hit: staged.is_some(),
+            },
+            0,
+        );
+        if let Some(val) = staged {
+            return val.as_option().map(|v| v.to_vec());
+        }
+
+        ops::explain_lookup(
+            key,
+            &shared.bbn_index,
+            &shared.leaf_cache,
+            &shared.leaf_store_rd,
+            &shared.value_log,
+            steps,
+        )
+    }
+
+    /// Lookup a key in the btree like [`Self::lookup`], returning a reader of the value which reads
+    /// overflow pages and the value log as it is consumed. The leaf is admitted to the leaf cache
+    /// as cold.
+    ///
+    /// A reader of a value stored on disk holds a read transaction, so that its pages are not
+    /// reused while it is alive.
+    pub fn lookup_streaming(&self, key: Key, consistency: ReadConsistency) -> Option<ValueReader> {
+        // The read transaction is taken first, so that no sync can start between reading the
+        // cell and the reader taking over.
+        let read_tx = self.read_transaction();
+        let shared = self.shared.read();
+
+        if let Some(val) = staged_change(&shared, key, consistency) {
+            return match val {
+                ValueChange::Delete => None,
+                ValueChange::Insert(v) | ValueChange::InsertOverflow(v, _) => {
+                    Some(ValueReader::from_handle(v.clone()))
+                }
+            };
+        }
+
+        let (cell, flags) = ops::lookup_cell(
+            key,
+            &shared.bbn_index,
+            &shared.leaf_cache,
+            &shared.leaf_store_rd,
+        )?;
+        let raw: Box<dyn std::io::Read + Send> = if flags.value_log {
+            Box::new(shared.value_log.reader(&cell))
+        } else if flags.overflow {
+            Box::new(leaf::overflow::Reader::new(
+                &cell,
+                shared.leaf_store_rd.clone(),
+            ))
+        } else {
+            return Some(ValueReader::from_vec(leaf::compression::decode_inline(
+                &cell, flags,
+            )));
+        };
+        let reader = match flags.compressed {
+            true => leaf::compression::decoder(raw),
+            false => raw,
+        };
+        Some(ValueReader::new(reader, Some(read_tx)))
+    }
+
+    /// Lookup a key in the btree like [`Self::lookup`], but without blocking the thread on reading
+    /// the leaf holding it or its overflow pages. See [`LookupFuture`].
+    pub fn lookup_async(
+        &self,
+        key: Key,
+        admission: Admission,
+        consistency: ReadConsistency,
+    ) -> LookupFuture {
+        LookupFuture::new(self.shared.clone(), key, admission, consistency)
+    }
+
+    /// Bring the leaf which may contain the key into the leaf cache in the background, unless
+    /// the key has been changed since the last sync.
+    ///
+    /// A [`Tree::lookup`] of the same leaf issued while the fetch is in progress waits for it
+    /// instead of fetching the leaf again.
+    pub fn prefetch(&self, key: Key) {
+        let shared = self.shared.clone();
+        self.prefetch_tp.execute(move || {
+            let shared = shared.read();
+            if shared.primary_staging.contains_key(&key)
+                || shared
+                    .secondary_staging
+                    .as_ref()
+                    .is_some_and(|x| x.contains_key(&key))
+            {
+                return;
+            }
+            ops::prefetch(
+                key,
+                &shared.bbn_index,
+                &shared.leaf_cache,
+                &shared.leaf_store_rd,
+            );
+        });
+    }
+
+    /// Bring the leaves which may contain the given keys into the leaf cache in the background,
+    /// skipping keys which have been changed since the last sync.
+    ///
+    /// Unlike calling [`Tree::prefetch`] for each key, the reads of all leaves are in flight at
+    /// once and each leaf is read only once.
+    pub fn prefetch_batch(&self, keys: Vec<Key>) {
+        let shared = self.shared.clone();
+        self.prefetch_tp.execute(move || {
+            let shared = shared.read();
+            let keys = keys
+                .into_iter()
+                .filter(|key| {
+                    !shared.primary_staging.contains_key(key)
+                        && !shared
+                            .secondary_staging
+                            .as_ref()
+                            .is_some_and(|x| x.contains_key(key))
+                })
+                .collect::<Vec<_>>();
+            let io_handle = shared.io_handle.io_pool().make_handle();
+            let _ = ops::prefetch_batch(
+                &keys,
+                &shared.bbn_index,
+                &shared.leaf_cache,
+                &shared.leaf_store_rd,
+                &io_handle,
+            );
+        });
+    }
+
+    /// Remove all leaves from the leaf cache.
+    pub fn drop_caches(&self) {
+        self.shared.read().leaf_cache.clear();
+    }
+
+    /// Collect the value log files in which at most `max_live_ratio` of the bytes belong to values
+    /// still referenced by the btree. Their live values are staged to be rewritten by the next
+    /// sync, and the files are deleted once it has finished. Returns the number of bytes
+    /// reclaimed.
+    ///
+    /// Must not be called while a sync is in progress.
+    pub fn collect_value_log_garbage(&self, max_live_ratio: f64) -> Result<u64> {
+        let value_log = self.shared.read().value_log.clone();
+        let mut reclaimed = 0;
+        for file_id in value_log.seal() {
+            let (entries, file_len) = value_log.entries(file_id)?;
+            let shared = self.shared.read();
+            let mut live = Vec::new();
+            let mut live_bytes = 0;
+            for (key, offset) in entries {
+                // A staged change replaces the cell anyway.
+                if shared.primary_staging.contains_key(&key) {
+                    continue;
+                }
+                let Some((cell, flags)) = ops::lookup_cell(
+                    key,
+                    &shared.bbn_index,
+                    &shared.leaf_cache,
+                    &shared.leaf_store_rd,
+                ) else {
+                    continue;
+                };
+                if !flags.value_log {
+                    continue;
+                }
+                let (cell_file_id, cell_offset, len, _) = value_log::decode_cell(&cell);
+                if (cell_file_id, cell_offset) == (file_id, offset) {
+                    live_bytes += (value_log::ENTRY_HEADER_SIZE + len) as u64;
+                    live.push((key, cell, flags));
+                }
+            }
+            if live_bytes as f64 > max_live_ratio * file_len as f64 {
+                continue;
+            }
+
+            let changeset = live
+                .into_iter()
+                .map(|(key, cell, flags)| {
+                    let (_, _, _, value_hash) = value_log::decode_cell(&cell);
+                    let value =
+                        leaf::compression::read(&cell, flags, &shared.leaf_store_rd, &value_log);
+                    let change = ValueChange::InsertOverflow(ValueHandle::new(value), value_hash);
+                    (key, change)
+                })
+                .collect();
+            drop(shared);
+            // The values must be staged before the file is retired, so that the sync deleting the
+            // file rewrites them.
+            Tree::commit(&self.shared, changeset);
+            value_log.retire(file_id);
+            reclaimed += file_len - live_bytes;
+        }
+        Ok(reclaimed)
+    }
+
+    /// Link the files of the value log into the given directory. See [`ValueLog::link_to`].
+    ///
+    /// Must not be called while a sync is in progress.
+    pub fn link_value_log(&self, dir: &Path) -> Result<()> {
+        self.shared.read().value_log.link_to(dir)
+    }
+
+    /// Bring up to `max_leaves` leaves which may contain keys in the inclusive range `start..=end`
+    /// into the leaf cache in the background, in key order. Leaves are admitted cold, so they are
+    /// the first to be evicted unless accessed in the meantime.
+    pub fn prefetch_range(&self, start: Key, end: Key, max_leaves: usize) {
+        let shared = self.shared.clone();
+        self.prefetch_tp.execute(move || {
+            let shared = shared.read();
+            for leaf_pn in ops::find_leaves_in_range(start, end, max_leaves, &shared.bbn_index) {
+                let _ = shared
+                    .leaf_cache
+                    .get_or_fetch(leaf_pn, Admission::Cold, || leaf::node::LeafNode {
+                        inner: shared.leaf_store_rd.query(leaf_pn),
+                    });
+            }
+        });
+    }
+
+    /// Read the first `limit` values stored under keys in the inclusive range `start..=end`, in
+    /// key order.
+    ///
+    /// Leaves which are not cached are read from disk, blocking the current thread, and admitted
+    /// cold.
+    pub fn range(&self, start: Key, end: Key, limit: usize) -> Vec<(Key, Vec<u8>)> {
+        let shared = self.shared.read();
+
+        let mut range = Vec::new();
+        let mut start = start;
+        while range.len() < limit {
+            let covered = range_batch(&shared, start, end, limit - range.len(), &mut range);
+            match next_key(covered) {
+                Some(next) if covered < end => start = next,
+                _ => break,
+            }
+        }
+        range
+    }
+
+    /// The number of leaf fetches which attached to a fetch of the same leaf already in progress,
+    /// e.g. a lookup of a key which was being prefetched.
+    pub fn deduplicated_leaf_fetches(&self) -> u64 {
+        self.shared.read().leaf_cache.deduplicated_fetches()
+    }
+
+    /// The number of b-tree pages read from disk since the tree was opened.
+    pub fn page_reads(&self) -> u64 {
+        let shared = self.shared.read();
+        shared.leaf_store.page_reads() + shared.bbn_store.page_reads()
+    }
+
+    /// Lookup a key in the btree without performing any I/O.
+    ///
+    /// Returns `None` if the answer is not available without I/O, e.g. the relevant leaf is not
+    /// cached or the value is stored in overflow pages.
+    pub fn lookup_cached(&self, key: Key, consistency: ReadConsistency) -> Option<Option<Vec<u8>>> {
+        let shared = self.shared.read();
+
+        if let Some(val) = staged_change(&shared, key, consistency) {
+            return Some(val.as_option().map(|v| v.to_vec()));
+        }
+
+        ops::lookup_cached(key, &shared.bbn_index, &shared.leaf_cache)
+    }
+
+    /// Request the next sync to give back the free pages at the end of the leaf and branch node
+    /// files, see [`Store::request_trim`]. The files are truncated by
+    /// [`SyncController::post_meta`].
+    pub fn request_trim(&self) {
+        let shared = self.shared.read();
+        shared.leaf_store.request_trim();
+        shared.bbn_store.request_trim();
+    }
+
     /// Returns a controller for the sync process. This is blocked by other `sync`s running as well
     /// as the existence of any read transactions.
     pub fn sync(&self) -> SyncController {
@@ -172,7 +517,6 @@ impl Tree {
 
     /// Initiate a new read transaction, as-of the current state of the last commit.
     /// This blocks new sync operations from starting until it is dropped.
-    #[allow(unused)]
     pub fn read_transaction(&self) -> ReadTransaction {
         // Increment the count. This will block any sync from starting between now and the point
         // where the read transaction is dropped.
@@ -184,6 +528,7 @@ impl Tree {
             secondary_staging: shared.secondary_staging.clone(),
             leaf_store: shared.leaf_store.clone(),
             leaf_cache: shared.leaf_cache.clone(),
+            page_pool: shared.page_pool.clone(),
             read_counter: self.read_transaction_counter.clone(),
         }
     }
@@ -205,6 +550,12 @@ impl Tree {
         }
     }
 
+    /// Stage the changeset without syncing it. The changes are visible to lookups right away and
+    /// are written out by the next sync.
+    pub fn stage(&self, changeset: Vec<(Key, ValueChange)>) {
+        Tree::commit(&self.shared, changeset);
+    }
+
     /// Dump all changes performed by commits to the underlying storage medium.
     /// The returned received indicates that all eviction has completed and it is safe to swap the
     /// index. This receiver must be blocked on before finishing sync.
@@ -215,7 +566,7 @@ impl Tree {
         sync: &Sync,
         shared: &Arc<RwLock<Shared>>,
         read_transaction_counter: &ReadTransactionCounter,
-    ) -> (SyncData, Index, Receiver<()>) {
+    ) -> anyhow::Result<(SyncData, Index, Receiver<()>)> {
         // Take the shared lock. Briefly.
         let staged_changeset;
         let bbn_index;
@@ -224,6 +575,7 @@ impl Tree {
         let leaf_store;
         let bbn_store;
         let io_handle;
+        let value_log;
         {
             // Wait for all outstanding read transactions to conclude. This ensures they won't
             // be invalidated by any destructive changes.
@@ -262,12 +614,14 @@ impl Tree {
 
             let mut shared = shared.write();
             staged_changeset = shared.take_staged_changeset();
+            shared.value_log.prepare_sync();
             bbn_index = shared.bbn_index.clone();
             page_pool = shared.page_pool.clone();
             leaf_cache = shared.leaf_cache.clone();
             leaf_store = shared.leaf_store.clone();
             bbn_store = shared.bbn_store.clone();
             io_handle = shared.io_handle.clone();
+            value_log = shared.value_log.clone();
         }
 
         {
@@ -298,8 +652,9 @@ impl Tree {
                 io_handle,
                 sync.tp.clone(),
                 sync.commit_concurrency,
+                sync.value_compression,
+                value_log,
             )
-            .unwrap()
         }
     }
 
@@ -308,6 +663,8 @@ impl Tree {
         let mut shared = shared.write();
         shared.secondary_staging = None;
         shared.bbn_index = bbn_index;
+        shared.value_log.finish_sync();
+        shared.sync_epoch += 1;
     }
 }
 
@@ -316,16 +673,17 @@ impl Tree {
 pub enum ValueChange {
     /// The key-value pair is deleted.
     Delete,
-    /// A new value small enough to fit in a leaf is inserted.
-    Insert(Vec<u8>),
+    /// A new value small enough to fit in a leaf is inserted. The value may be empty, which is
+    /// distinct from [`ValueChange::Delete`].
+    Insert(ValueHandle),
     /// A new value which requires an overflow page is inserted.
-    InsertOverflow(Vec<u8>, ValueHash),
+    InsertOverflow(ValueHandle, ValueHash),
 }
 
 impl ValueChange {
     /// Create a [`ValueChange`] from an option, determining whether to use the normal or overflow
     /// variant based on size.
-    pub fn from_option<T: crate::ValueHasher>(maybe_value: Option<Vec<u8>>) -> Self {
+    pub fn from_option<T: crate::ValueHasher>(maybe_value: Option<ValueHandle>) -> Self {
         match maybe_value {
             None => ValueChange::Delete,
             Some(v) => Self::insert::<T>(v),
@@ -333,9 +691,9 @@ impl ValueChange {
     }
 
     /// Create an insertion, determining whether to use the normal or overflow variant based on size.
-    pub fn insert<T: crate::ValueHasher>(v: Vec<u8>) -> Self {
+    pub fn insert<T: crate::ValueHasher>(v: ValueHandle) -> Self {
         if v.len() > MAX_LEAF_VALUE_SIZE {
-            let value_hash = T::hash_value(&v);
+            let value_hash = v.hash::<T>();
             ValueChange::InsertOverflow(v, value_hash)
         } else {
             ValueChange::Insert(v)
@@ -357,6 +715,10 @@ pub struct SyncData {
     pub ln_bump: u32,
     pub bbn_freelist_pn: u32,
     pub bbn_bump: u32,
+    /// The number of nodes split off, leaves and branches alike.
+    pub splits: usize,
+    /// The number of nodes merged into their right sibling, leaves and branches alike.
+    pub merges: usize,
 }
 
 /// Creates the required files for the beatree.
@@ -403,7 +765,8 @@ struct SharedSyncController {
     sync: ArcMutexGuard<parking_lot::RawMutex, Sync>,
     shared: Arc<RwLock<Shared>>,
     read_transaction_counter: ReadTransactionCounter,
-    sync_data: Mutex<Option<SyncData>>,
+    /// The outcome of writing the tree, which fails e.g. if a page can't be written.
+    sync_data: Mutex<Option<anyhow::Result<SyncData>>>,
     bbn_index: Mutex<Option<Index>>,
     pre_swap_rx: Mutex<Option<Receiver<()>>>,
 }
@@ -418,21 +781,24 @@ impl SyncController {
         let inner = self.inner.clone();
         self.inner.sync.tp.execute(move || {
             Tree::commit(&inner.shared, changeset);
-            let (out_meta, out_bbn_index, out_pre_swap_rx) =
-                Tree::prepare_sync(&inner.sync, &inner.shared, &inner.read_transaction_counter);
+            let out_meta =
+                Tree::prepare_sync(&inner.sync, &inner.shared, &inner.read_transaction_counter)
+                    .map(|(out_meta, out_bbn_index, out_pre_swap_rx)| {
+                        let mut bbn_index = inner.bbn_index.lock();
+                        *bbn_index = Some(out_bbn_index);
+                        drop(bbn_index);
+
+                        let mut pre_swap_rx = inner.pre_swap_rx.lock();
+                        *pre_swap_rx = Some(out_pre_swap_rx);
+                        drop(pre_swap_rx);
+
+                        out_meta
+                    });
 
             let mut sync_data = inner.sync_data.lock();
             *sync_data = Some(out_meta);
             drop(sync_data);
 
-            let mut bbn_index = inner.bbn_index.lock();
-            *bbn_index = Some(out_bbn_index);
-            drop(bbn_index);
-
-            let mut pre_swap_rx = inner.pre_swap_rx.lock();
-            *pre_swap_rx = Some(out_pre_swap_rx);
-            drop(pre_swap_rx);
-
             inner.sync.bbn_fsync.fsync();
             inner.sync.ln_fsync.fsync();
         });
@@ -443,11 +809,14 @@ impl SyncController {
     ///
     /// This must be called after [`Self::begin_sync`].
     pub fn wait_pre_meta(&mut self) -> anyhow::Result<SyncData> {
-        self.inner.sync.bbn_fsync.wait()?;
-        self.inner.sync.ln_fsync.wait()?;
+        // Both fsyncs are waited for, so that neither is still pending on an error.
+        let bbn_result = self.inner.sync.bbn_fsync.wait();
+        let ln_result = self.inner.sync.ln_fsync.wait();
 
         // UNWRAP: fsync of bbn and ln above ensures that sync_data is Some.
-        let sync_data = self.inner.sync_data.lock().take().unwrap();
+        let sync_data = self.inner.sync_data.lock().take().unwrap()?;
+        bbn_result?;
+        ln_result?;
         Ok(sync_data)
     }
 
@@ -464,6 +833,17 @@ impl SyncController {
 
         let bbn_index = self.inner.bbn_index.lock().take().unwrap();
         Tree::finish_sync(&self.inner.shared, bbn_index);
+
+        // Failing to truncate the files only leaves free space at their end.
+        let shared = self.inner.shared.read();
+        for (name, store) in [("ln", &shared.leaf_store), ("bbn", &shared.bbn_store)] {
+            if let Err(e) = store.truncate() {
+                shared.io_handle.io_pool().background_errors().report(
+                    BackgroundErrorSource::FileTruncation,
+                    anyhow::Error::from(e).context(format!("failed to truncate the {name} file")),
+                );
+            }
+        }
     }
 }
 
@@ -485,9 +865,185 @@ pub struct ReadTransaction {
     secondary_staging: Option<OrdMap<Key, ValueChange>>,
     leaf_store: Store,
     leaf_cache: leaf_cache::LeafCache,
+    page_pool: PagePool,
     read_counter: ReadTransactionCounter,
 }
 
+impl ReadTransaction {
+    /// Iterate over all leaf nodes in key order, reporting their physical placement and occupancy.
+    ///
+    /// Leaves which are not cached are read from disk, blocking the current thread, and are not
+    /// added to the cache.
+    pub fn leaves(self) -> LeafIter {
+        let leaves = self
+            .bbn_index
+            .iter()
+            .flat_map(|(_, branch)| {
+                (0..branch.n() as usize)
+                    .map(|i| (ops::get_key(branch, i), PageNumber(branch.node_pointer(i))))
+            })
+            .collect::<Vec<_>>();
+        LeafIter {
+            leaves: leaves.into_iter(),
+            read_tx: self,
+        }
+    }
+}
+
+/// The change to the key staged since the last completed sync, if any and if the given consistency
+/// takes staged changes into account.
+fn staged_change(shared: &Shared, key: Key, consistency: ReadConsistency) -> Option<&ValueChange> {
+    if consistency == ReadConsistency::LastSynced {
+        return None;
+    }
+
+    // The primary staging contains the most recent changes. The secondary staging is a bit older,
+    // but fresher still than the btree.
+    shared.primary_staging.get(&key).or_else(|| {
+        let staging = shared.secondary_staging.as_ref()?;
+        staging.get(&key)
+    })
+}
+
+/// Append up to `limit` values stored under keys in `start..=end` to `out`, in key order.
+///
+/// Reads at most `limit` leaves, or two if `limit` is smaller, and returns the key up to which the
+/// range has been covered. Values under keys beyond it may have been skipped.
+fn range_batch(
+    shared: &Shared,
+    start: Key,
+    end: Key,
+    limit: usize,
+    out: &mut Vec<(Key, Vec<u8>)>,
+) -> Key {
+    // Reading two leaves at least ensures progress: all keys of the second one are past `start`.
+    let max_leaves = limit.max(2);
+    let leaves = ops::find_leaves_in_range(start, end, max_leaves, &shared.bbn_index);
+    let mut entries = BTreeMap::new();
+    let mut last_key = start;
+    for leaf_pn in &leaves {
+        let leaf = shared
+            .leaf_cache
+            .get_or_fetch(*leaf_pn, Admission::Cold, || leaf::node::LeafNode {
+                inner: shared.leaf_store_rd.query(*leaf_pn),
+            });
+        if leaf.n() > 0 {
+            last_key = last_key.max(leaf.key(leaf.n() - 1));
+        }
+        for i in 0..leaf.n() {
+            let key = leaf.key(i);
+            if key < start || key > end {
+                continue;
+            }
+            let (value, flags) = leaf.value(i);
+            let value =
+                leaf::compression::read(value, flags, &shared.leaf_store_rd, &shared.value_log);
+            entries.insert(key, Some(value));
+        }
+    }
+
+    // If the leaves were capped, the range is only covered up to the last key of the last leaf.
+    let covered = if leaves.len() < max_leaves {
+        end
+    } else {
+        last_key.min(end)
+    };
+
+    // The stagings are fresher than the btree, and the primary staging is the freshest.
+    for staging in shared
+        .secondary_staging
+        .iter()
+        .chain(std::iter::once(&shared.primary_staging))
+    {
+        for (key, change) in staging.range(start..=covered) {
+            entries.insert(*key, change.as_option().map(|v| v.to_vec()));
+        }
+    }
+
+    out.extend(
+        entries
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value?)))
+            .take(limit),
+    );
+    covered
+}
+
+/// The key following the given one, if any.
+pub(crate) fn next_key(mut key: Key) -> Option<Key> {
+    for byte in key.iter_mut().rev() {
+        if *byte == u8::MAX {
+            *byte = 0;
+        } else {
+            *byte += 1;
+            return Some(key);
+        }
+    }
+    None
+}
+
+/// The physical placement and occupancy of a leaf node of the value store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeafInfo {
+    /// The separator of the leaf: all keys stored in the leaf are greater than or equal to it.
+    pub separator: Key,
+    /// The page number of the leaf within the leaf store file.
+    pub page_number: u32,
+    /// The number of entries stored in the leaf.
+    pub entries: usize,
+    /// The number of bytes used by entries, out of [`LeafInfo::CAPACITY`].
+    pub used_bytes: usize,
+}
+
+impl LeafInfo {
+    /// The number of bytes available for entries in a leaf.
+    pub const CAPACITY: usize = leaf::node::LEAF_NODE_BODY_SIZE;
+
+    /// The fraction of the leaf's capacity which is in use, between 0 and 1.
+    pub fn fill_level(&self) -> f64 {
+        self.used_bytes as f64 / Self::CAPACITY as f64
+    }
+}
+
+/// An iterator over the leaves of the value store, in key order. See [`LeafInfo`].
+///
+/// The iterator works on a snapshot of the store as of its creation. Syncs cannot start while the
+/// iterator is alive, so it should not be held across commits.
+pub struct LeafIter {
+    leaves: std::vec::IntoIter<(Key, PageNumber)>,
+    read_tx: ReadTransaction,
+}
+
+impl Iterator for LeafIter {
+    type Item = LeafInfo;
+
+    fn next(&mut self) -> Option<LeafInfo> {
+        let (separator, pn) = self.leaves.next()?;
+        let leaf = match self.read_tx.leaf_cache.get(pn) {
+            Some(leaf) => leaf,
+            None => Arc::new(leaf::node::LeafNode {
+                inner: self.read_tx.leaf_store.query(&self.read_tx.page_pool, pn),
+            }),
+        };
+        let entries = leaf.n();
+        let used_bytes = if entries == 0 {
+            0
+        } else {
+            leaf::node::body_size(entries, leaf.values_size(0, entries))
+        };
+        Some(LeafInfo {
+            separator,
+            page_number: pn.0,
+            entries,
+            used_bytes,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.leaves.size_hint()
+    }
+}
+
 impl Drop for ReadTransaction {
     fn drop(&mut self) {
         self.read_counter.release_one()
@@ -514,7 +1070,9 @@ impl ReadTransactionCounter {
     fn release_one(&self) {
         // UNWRAP: this is only called when a read transaction is dropped, which always pairs with
         // the `add_one` call when the read transaction was created.
-        self.inner.read_transactions.lock().checked_sub(1).unwrap();
+        let mut read_transactions = self.inner.read_transactions.lock();
+        *read_transactions = read_transactions.checked_sub(1).unwrap();
+        drop(read_transactions);
         self.inner.cvar.notify_one();
     }
 
@@ -3,14 +3,20 @@
 use anyhow::Result;
 use bitvec::prelude::*;
 
-use std::{cmp::Ordering, sync::Arc};
+use std::cmp::Ordering;
+
+use crate::{
+    explain::{ReadStepKind, StepRecorder},
+    io::{IoHandle, PAGE_SIZE},
+};
 
 use super::{
     allocator::{PageNumber, StoreReader},
     branch::BranchNode,
     index::Index,
     leaf::{self, node::LeafNode},
-    leaf_cache::LeafCache,
+    leaf_cache::{Admission, LeafCache},
+    value_log::ValueLog,
     Key,
 };
 
@@ -27,37 +33,206 @@ pub fn lookup(
     bbn_index: &Index,
     leaf_cache: &LeafCache,
     leaf_store: &StoreReader,
+    value_log: &ValueLog,
+    admission: Admission,
 ) -> Result<Option<Vec<u8>>> {
-    let branch = match bbn_index.lookup(key) {
+    let leaf_pn = match find_leaf(key, bbn_index) {
         None => return Ok(None),
-        Some((_, branch)) => branch,
+        Some(leaf_pn) => leaf_pn,
     };
 
-    let leaf_pn = match search_branch(&branch, key.clone()) {
-        None => return Ok(None),
-        Some((_, leaf_pn)) => leaf_pn,
+    let leaf = leaf_cache.get_or_fetch(leaf_pn, admission, || LeafNode {
+        inner: leaf_store.query(leaf_pn),
+    });
+
+    let maybe_value = leaf
+        .get(&key)
+        .map(|(v, flags)| leaf::compression::read(v, flags, leaf_store, value_log));
+
+    Ok(maybe_value)
+}
+
+/// Lookup a key in the btree like [`lookup`], recording the steps taken.
+pub fn explain_lookup(
+    key: Key,
+    bbn_index: &Index,
+    leaf_cache: &LeafCache,
+    leaf_store: &StoreReader,
+    value_log: &ValueLog,
+    steps: &mut StepRecorder,
+) -> Option<Vec<u8>> {
+    let leaf_pn = find_leaf(key, bbn_index);
+    steps.record(
+        ReadStepKind::BranchIndex {
+            leaf: leaf_pn.map(|pn| pn.0),
+        },
+        0,
+    );
+    let leaf_pn = leaf_pn?;
+
+    let cached = leaf_cache.get(leaf_pn).is_some();
+    let leaf = leaf_cache.get_or_fetch(leaf_pn, Admission::Cold, || LeafNode {
+        inner: leaf_store.query(leaf_pn),
+    });
+    steps.record(
+        ReadStepKind::Leaf {
+            page_number: leaf_pn.0,
+            cached,
+        },
+        if cached { 0 } else { PAGE_SIZE as u64 },
+    );
+
+    let (cell, flags) = leaf.get(&key)?;
+    let value = if flags.value_log {
+        let value = value_log.read(cell);
+        steps.record(ReadStepKind::ValueLog, value.len() as u64);
+        value
+    } else if flags.overflow {
+        let (value_size, _, _) = leaf::overflow::decode_cell(cell);
+        let pages = leaf::overflow::total_needed_pages(value_size);
+        let value = leaf::overflow::read(cell, leaf_store);
+        steps.record(ReadStepKind::Overflow { pages }, (pages * PAGE_SIZE) as u64);
+        value
+    } else if flags.compressed {
+        cell.to_vec()
+    } else {
+        return Some(cell.to_vec());
     };
+    if !flags.compressed {
+        return Some(value);
+    }
+    let compressed_len = value.len();
+    let value = leaf::compression::decode(value, flags);
+    steps.record(ReadStepKind::Decompress { compressed_len }, 0);
+    Some(value)
+}
 
-    let leaf = match leaf_cache.get(leaf_pn) {
-        Some(leaf) => leaf,
-        None => {
-            let leaf = Arc::new(LeafNode {
-                inner: leaf_store.query(leaf_pn),
-            });
-            leaf_cache.insert(leaf_pn, leaf.clone());
-            leaf
-        }
+/// Lookup a key in the btree without performing any I/O.
+///
+/// Returns `None` if answering requires a leaf which is not cached, an overflow value or a value
+/// in the value log.
+pub fn lookup_cached(
+    key: Key,
+    bbn_index: &Index,
+    leaf_cache: &LeafCache,
+) -> Option<Option<Vec<u8>>> {
+    let leaf_pn = match find_leaf(key, bbn_index) {
+        None => return Some(None),
+        Some(leaf_pn) => leaf_pn,
     };
 
-    let maybe_value = leaf.get(&key).map(|(v, is_overflow)| {
-        if is_overflow {
-            leaf::overflow::read(v, leaf_store)
-        } else {
-            v.to_vec()
-        }
+    let leaf = leaf_cache.get(leaf_pn)?;
+    match leaf.get(&key) {
+        None => Some(None),
+        Some((_, flags)) if flags.overflow || flags.value_log => None,
+        Some((v, flags)) => Some(Some(leaf::compression::decode_inline(v, flags))),
+    }
+}
+
+/// Look up the raw cell stored under a key in the btree, without decoding the value.
+pub fn lookup_cell(
+    key: Key,
+    bbn_index: &Index,
+    leaf_cache: &LeafCache,
+    leaf_store: &StoreReader,
+) -> Option<(Vec<u8>, leaf::node::CellFlags)> {
+    let leaf_pn = find_leaf(key, bbn_index)?;
+    let leaf = leaf_cache.get_or_fetch(leaf_pn, Admission::Cold, || LeafNode {
+        inner: leaf_store.query(leaf_pn),
     });
+    leaf.get(&key).map(|(cell, flags)| (cell.to_vec(), flags))
+}
 
-    Ok(maybe_value)
+/// Bring the leaf which may contain the key into the leaf cache.
+pub fn prefetch(key: Key, bbn_index: &Index, leaf_cache: &LeafCache, leaf_store: &StoreReader) {
+    if let Some(leaf_pn) = find_leaf(key, bbn_index) {
+        let _ = leaf_cache.get_or_fetch(leaf_pn, Admission::Hot, || LeafNode {
+            inner: leaf_store.query(leaf_pn),
+        });
+    }
+}
+
+/// Bring the leaves which may contain the given keys into the leaf cache. The reads of all leaves
+/// not cached yet are submitted to the I/O pool at once, then awaited.
+pub fn prefetch_batch(
+    keys: &[Key],
+    bbn_index: &Index,
+    leaf_cache: &LeafCache,
+    leaf_store: &StoreReader,
+    io_handle: &IoHandle,
+) -> Result<()> {
+    let mut leaves = keys
+        .iter()
+        .filter_map(|key| find_leaf(*key, bbn_index))
+        .filter(|pn| leaf_cache.get(*pn).is_none())
+        .collect::<Vec<_>>();
+    leaves.sort_unstable_by_key(|pn| pn.0);
+    leaves.dedup();
+
+    for (index, pn) in leaves.iter().enumerate() {
+        io_handle
+            .send(leaf_store.io_command(*pn, index as u64))
+            .map_err(|_| anyhow::anyhow!("I/O pool hung up"))?;
+    }
+    for _ in 0..leaves.len() {
+        let completion = io_handle
+            .recv()
+            .map_err(|_| anyhow::anyhow!("I/O pool hung up"))?;
+        completion.result?;
+        let pn = leaves[completion.command.user_data as usize];
+        let node = LeafNode {
+            inner: completion.command.kind.unwrap_buf(),
+        };
+        leaf_cache.admit(pn, std::sync::Arc::new(node), Admission::Hot);
+    }
+    Ok(())
+}
+
+/// Find the page numbers of the leaves which may contain keys in the inclusive range
+/// `start..=end`, in key order, up to a maximum number of leaves. Like [`find_leaf`], this never
+/// performs I/O.
+pub fn find_leaves_in_range(
+    start: Key,
+    end: Key,
+    max_leaves: usize,
+    bbn_index: &Index,
+) -> Vec<PageNumber> {
+    let mut leaves = Vec::new();
+    let mut next_branch = bbn_index
+        .lookup(start)
+        .map(|(separator, _)| separator)
+        .or_else(|| bbn_index.next_key(start));
+    while let Some(separator) = next_branch {
+        if separator > end {
+            break;
+        }
+        // UNWRAP: the separator was just taken from the index.
+        let (_, branch) = bbn_index.lookup(separator).unwrap();
+        let n = branch.n() as usize;
+        for i in 0..n {
+            if get_key(&branch, i) > end || leaves.len() == max_leaves {
+                return leaves;
+            }
+            // The leaf ends where the next one begins. Skip it if that's not past the start.
+            if i + 1 < n && get_key(&branch, i + 1) <= start {
+                continue;
+            }
+            leaves.push(PageNumber(branch.node_pointer(i)));
+        }
+        next_branch = bbn_index.next_key(separator);
+    }
+    leaves
+}
+
+/// Find the page number of the leaf which may contain the key. Branch nodes are always in memory,
+/// so this never performs I/O.
+pub fn find_leaf(key: Key, bbn_index: &Index) -> Option<PageNumber> {
+    let branch = match bbn_index.lookup(key) {
+        None => return None,
+        Some((_, branch)) => branch,
+    };
+
+    search_branch(&branch, key).map(|(_, leaf_pn)| leaf_pn)
 }
 
 /// Binary search a branch node for the child node containing the key. This returns the last child
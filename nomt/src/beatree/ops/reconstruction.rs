@@ -16,20 +16,30 @@ use crate::beatree::{
     branch::{BranchNode, BranchNodeView, BRANCH_NODE_SIZE},
     index::Index,
 };
-use crate::io::PagePool;
+use crate::io::{PageCipher, PagePool};
 
 /// Reconstruct the upper branch nodes of the btree from the bottom branch nodes and the leaf nodes.
 /// This places all branches into the BNP and returns an index into all BBNs.
 pub fn reconstruct(
     bn_fd: Arc<File>,
     page_pool: &PagePool,
+    cipher: Option<&PageCipher>,
     bbn_freelist_tracked: &BTreeSet<PageNumber>,
     bump: PageNumber,
 ) -> Result<Index> {
     let mut index = Index::default();
 
     let mut chunker = SeqFileReader::new(bn_fd, bump.0)?;
+    let mut decrypted = vec![0; BRANCH_NODE_SIZE];
     while let Some((pn, node)) = chunker.next()? {
+        let node = match cipher {
+            Some(cipher) => {
+                decrypted.copy_from_slice(node);
+                cipher.decrypt(pn as u64, &mut decrypted);
+                &decrypted[..]
+            }
+            None => node,
+        };
         let view = BranchNodeView::from_slice(node);
 
         if view.n() == 0 && node == [0; BRANCH_NODE_SIZE] {
@@ -34,6 +34,10 @@ pub struct BranchStageOutput {
     pub submitted_io: usize,
     /// Data which should be dropped after all submitted I/Os have concluded.
     pub post_io_drop: PostIoDrop,
+    /// The number of branches split off.
+    pub splits: usize,
+    /// The number of branches merged into their right sibling.
+    pub merges: usize,
 }
 
 /// Change the btree's branch nodes in the specified way.
@@ -101,6 +105,8 @@ fn apply_bbn_changes(
     output: &mut BranchStageOutput,
     mut worker_output: BranchWorkerOutput,
 ) {
+    output.splits += worker_output.splits;
+    output.merges += worker_output.merges;
     for (key, changed_branch) in worker_output.branches_tracker.inner {
         match changed_branch.inserted {
             Some((node, _pn)) => {
@@ -271,6 +277,8 @@ fn reset_branch_base_fresh(
 
 struct BranchWorkerOutput {
     branches_tracker: BranchesTracker,
+    splits: usize,
+    merges: usize,
 }
 
 fn run_worker(
@@ -396,6 +404,8 @@ fn run_worker(
 
     BranchWorkerOutput {
         branches_tracker: new_branch_state.branches_tracker,
+        splits: branch_updater.splits,
+        merges: branch_updater.merges,
     }
 }
 
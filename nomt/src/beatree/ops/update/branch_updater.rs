@@ -118,6 +118,10 @@ pub struct BranchUpdater {
     gauge: BranchGauge,
     page_pool: PagePool,
     bulk_split: Option<BranchBulkSplitter>,
+    /// The number of branches split off so far.
+    pub splits: usize,
+    /// The number of branches merged into their right sibling so far.
+    pub merges: usize,
 }
 
 impl BranchUpdater {
@@ -129,6 +133,8 @@ impl BranchUpdater {
             gauge: BranchGauge::new(),
             page_pool,
             bulk_split: None,
+            splits: 0,
+            merges: 0,
         }
     }
 
@@ -391,6 +397,7 @@ impl BranchUpdater {
             return 0;
         };
 
+        self.splits += splitter.items.len();
         let mut start = 0;
         for (item_count, gauge) in splitter.items {
             let branch_ops = &self.ops[start..][..item_count];
@@ -406,6 +413,7 @@ impl BranchUpdater {
     }
 
     fn split(&mut self, new_branches: &mut impl HandleNewBranch) -> DigestResult {
+        self.splits += 1;
         let midpoint = self.gauge.body_size() / 2;
         let mut split_point = 0;
 
@@ -655,6 +663,7 @@ impl BranchUpdater {
     }
 
     fn prepare_merge_ops(&mut self, split_point: usize) {
+        self.merges += 1;
         self.ops.drain(..split_point);
 
         let Some(ref base) = self.base else { return };
@@ -7,7 +7,11 @@ use threadpool::ThreadPool;
 use crate::beatree::{
     allocator::{PageNumber, StoreReader, SyncAllocator},
     index::Index,
-    leaf::{node::LeafNode, overflow},
+    leaf::{
+        compression,
+        node::{CellFlags, LeafNode, MAX_LEAF_VALUE_SIZE},
+        overflow,
+    },
     leaf_cache::LeafCache,
     ops::{
         search_branch,
@@ -20,13 +24,19 @@ use crate::beatree::{
             leaf_updater::{BaseLeaf, DigestResult as LeafDigestResult, LeafUpdater},
         },
     },
+    value_log::{self, ValueLog},
     Key, ValueChange,
 };
 use crate::io::{IoCommand, IoHandle, IoKind, IoPool};
+use crate::ValueCompression;
 
 /// Tracker of all changes that happen to leaves during an update
 pub type LeavesTracker = super::NodesTracker<LeafNode>;
 
+/// The changes to apply to the leaves, ordered by key: the cell stored for a key along with its
+/// flags, or `None` to delete the key.
+type Changeset = [(Key, Option<(Vec<u8>, CellFlags)>)];
+
 fn indexed_leaf(bbn_index: &Index, key: Key) -> Option<(Key, Option<Key>, PageNumber)> {
     let Some((_, branch)) = bbn_index.lookup(key) else {
         return None;
@@ -75,6 +85,10 @@ pub struct LeafStageOutput {
     pub submitted_io: usize,
     /// Work which should be done after I/O but before sync has finished.
     pub post_io_work: PostIoWork,
+    /// The number of leaves split off.
+    pub splits: usize,
+    /// The number of leaves merged into their right sibling.
+    pub merges: usize,
 }
 
 /// Change the btree's leaves in the specified way
@@ -87,6 +101,8 @@ pub fn run(
     changeset: OrdMap<Key, ValueChange>,
     thread_pool: ThreadPool,
     num_workers: usize,
+    value_compression: ValueCompression,
+    value_log: &ValueLog,
 ) -> anyhow::Result<LeafStageOutput> {
     if changeset.is_empty() {
         return Ok(LeafStageOutput::default());
@@ -98,14 +114,59 @@ pub fn run(
     let changeset = changeset
         .iter()
         .map(|(k, v)| match v {
-            ValueChange::Insert(v) => Ok((*k, Some((v.clone(), false)))),
+            ValueChange::Insert(v) => match compression::compress(v, value_compression) {
+                Some(compressed) => {
+                    let flags = CellFlags {
+                        compressed: true,
+                        ..CellFlags::default()
+                    };
+                    Ok((*k, Some((compressed, flags))))
+                }
+                None => Ok((*k, Some((v.to_vec(), CellFlags::default())))),
+            },
             ValueChange::InsertOverflow(large_value, value_hash) => {
+                // streamed values are not compressed, which would require them to be contiguous.
+                let compressed = large_value
+                    .as_contiguous()
+                    .and_then(|v| compression::compress(v, value_compression));
+                let flags = CellFlags {
+                    overflow: true,
+                    compressed: compressed.is_some(),
+                    value_log: false,
+                };
+                if let Some(compressed) = compressed.as_ref() {
+                    if compressed.len() <= MAX_LEAF_VALUE_SIZE {
+                        // compression made the value small enough to be stored in the leaf.
+                        let flags = CellFlags {
+                            overflow: false,
+                            ..flags
+                        };
+                        return Ok((*k, Some((compressed.clone(), flags))));
+                    }
+                }
+                let stored = match compressed {
+                    Some(ref compressed) => vec![&compressed[..]],
+                    None => large_value.parts(),
+                };
+                let stored_len = stored.iter().map(|part| part.len()).sum::<usize>();
+
+                if value_log.accepts(large_value.len()) {
+                    let flags = CellFlags {
+                        overflow: false,
+                        value_log: true,
+                        ..flags
+                    };
+                    let (file_id, offset) = value_log.append_parts(*k, &stored)?;
+                    let cell = value_log::encode_cell(file_id, offset, stored_len, *value_hash);
+                    return Ok((*k, Some((cell, flags))));
+                }
+
                 let (pages, num_writes) =
-                    overflow::chunk(&large_value, &leaf_writer, &page_pool, &io_handle)?;
+                    overflow::chunk(&stored, &leaf_writer, &page_pool, &io_handle)?;
                 overflow_io += num_writes;
 
-                let cell = overflow::encode_cell(large_value.len(), value_hash.clone(), &pages);
-                Ok((*k, Some((cell, true))))
+                let cell = overflow::encode_cell(stored_len, *value_hash, &pages);
+                Ok((*k, Some((cell, flags))))
             }
             ValueChange::Delete => Ok((*k, None)),
         })
@@ -183,6 +244,8 @@ fn apply_worker_changes(
     output: &mut LeafStageOutput,
     mut worker_output: LeafWorkerOutput,
 ) {
+    output.splits += worker_output.splits;
+    output.merges += worker_output.merges;
     for deleted_overflow_cell in worker_output.overflow_deleted.drain(..) {
         overflow::delete(&deleted_overflow_cell, leaf_reader, &mut output.freed_pages);
     }
@@ -210,7 +273,7 @@ fn apply_worker_changes(
 
 fn prepare_workers(
     bbn_index: &Index,
-    changeset: &[(Key, Option<(Vec<u8>, bool)>)],
+    changeset: &Changeset,
     worker_count: usize,
 ) -> Vec<WorkerParams<LeafNode>> {
     let mut remaining_workers = worker_count;
@@ -413,6 +476,8 @@ fn reset_leaf_base_fresh(
 struct LeafWorkerOutput {
     leaves_tracker: LeavesTracker,
     overflow_deleted: Vec<Vec<u8>>,
+    splits: usize,
+    merges: usize,
 }
 
 fn run_worker(
@@ -422,7 +487,7 @@ fn run_worker(
     leaf_writer: SyncAllocator,
     io_handle: IoHandle,
     prepared_leaves: &mut PreparedLeafIter,
-    changeset: &[(Key, Option<(Vec<u8>, bool)>)],
+    changeset: &Changeset,
     mut worker_params: WorkerParams<LeafNode>,
 ) -> LeafWorkerOutput {
     let mut leaf_updater = LeafUpdater::new(leaf_reader.page_pool().clone(), None, None);
@@ -489,13 +554,13 @@ fn run_worker(
             );
         }
 
-        let (value_change, overflow) = match op {
-            None => (None, false),
-            Some((v, overflow)) => (Some(v.clone()), *overflow),
+        let (value_change, flags) = match op {
+            None => (None, CellFlags::default()),
+            Some((v, flags)) => (Some(v.clone()), *flags),
         };
 
         let delete_overflow = |overflow_cell: &[u8]| overflow_deleted.push(overflow_cell.to_vec());
-        leaf_updater.ingest(*key, value_change, overflow, delete_overflow);
+        leaf_updater.ingest(*key, value_change, flags, delete_overflow);
     }
 
     while let LeafDigestResult::NeedsMerge(cutoff) = leaf_updater.digest(&mut new_leaf_state) {
@@ -559,6 +624,8 @@ fn run_worker(
     LeafWorkerOutput {
         leaves_tracker: new_leaf_state.leaves_tracker,
         overflow_deleted,
+        splits: leaf_updater.splits,
+        merges: leaf_updater.merges,
     }
 }
 
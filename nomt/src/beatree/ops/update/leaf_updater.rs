@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use crate::beatree::{
-    leaf::node::{self as leaf_node, LeafBuilder, LeafNode, LEAF_NODE_BODY_SIZE},
+    leaf::node::{self as leaf_node, CellFlags, LeafBuilder, LeafNode, LEAF_NODE_BODY_SIZE},
     ops::bit_ops::separate,
     Key,
 };
@@ -63,20 +63,20 @@ impl BaseLeaf {
         self.node.key(i)
     }
 
-    fn key_cell(&self, i: usize) -> (Key, &[u8], bool) {
-        let (value, overflow) = self.node.value(i);
-        (self.node.key(i), value, overflow)
+    fn key_cell(&self, i: usize) -> (Key, &[u8], CellFlags) {
+        let (value, flags) = self.node.value(i);
+        (self.node.key(i), value, flags)
     }
 
-    fn cell(&self, i: usize) -> (&[u8], bool) {
+    fn cell(&self, i: usize) -> (&[u8], CellFlags) {
         self.node.value(i)
     }
 }
 
 #[derive(Debug, PartialEq)]
 enum LeafOp {
-    // Key, Value, Overflow
-    Insert(Key, Vec<u8>, bool),
+    // Key, Value, Cell flags
+    Insert(Key, Vec<u8>, CellFlags),
     // From, To, Values size
     KeepChunk(usize, usize, usize),
 }
@@ -107,6 +107,10 @@ pub struct LeafUpdater {
     gauge: LeafGauge,
     bulk_split: Option<LeafBulkSplitter>,
     page_pool: PagePool,
+    /// The number of leaves split off so far.
+    pub splits: usize,
+    /// The number of leaves merged into their right sibling so far.
+    pub merges: usize,
 }
 
 impl LeafUpdater {
@@ -119,6 +123,8 @@ impl LeafUpdater {
             gauge: LeafGauge::default(),
             bulk_split: None,
             page_pool,
+            splits: 0,
+            merges: 0,
         }
     }
 
@@ -141,13 +147,13 @@ impl LeafUpdater {
         &mut self,
         key: Key,
         value_change: Option<Vec<u8>>,
-        overflow: bool,
+        flags: CellFlags,
         with_deleted_overflow: impl FnMut(&[u8]),
     ) {
         self.keep_up_to(Some(&key), with_deleted_overflow);
 
         if let Some(value) = value_change {
-            self.ops.push(LeafOp::Insert(key, value, overflow));
+            self.ops.push(LeafOp::Insert(key, value, flags));
             self.bulk_split_step(self.ops.len() - 1);
         }
     }
@@ -166,9 +172,21 @@ impl LeafUpdater {
 
         if self.gauge.body_size() == 0 {
             self.ops.clear();
-            self.separator_override = None;
 
-            DigestResult::Finished
+            match self.cutoff {
+                // the first leaf always has a separator of all 0, so the next leaf takes it over.
+                // otherwise keys below the separator of the next leaf would have no leaf to go to.
+                // a bulk split has already built the first leaf, in which case there's nothing to
+                // take over.
+                Some(cutoff) if last_ops_start == 0 && self.separator() == [0u8; 32] => {
+                    self.separator_override = Some([0u8; 32]);
+                    DigestResult::NeedsMerge(cutoff)
+                }
+                _ => {
+                    self.separator_override = None;
+                    DigestResult::Finished
+                }
+            }
         } else if self.gauge.body_size() > LEAF_NODE_BODY_SIZE {
             assert_eq!(
                 last_ops_start, 0,
@@ -230,8 +248,8 @@ impl LeafUpdater {
         self.ops.push(LeafOp::KeepChunk(from, to, values_size));
 
         if found {
-            let (val, overflow) = base.cell(to);
-            if overflow {
+            let (val, flags) = base.cell(to);
+            if flags.overflow {
                 with_deleted_overflow(val);
             }
         }
@@ -331,6 +349,7 @@ impl LeafUpdater {
             return 0;
         };
 
+        self.splits += splitter.items.len();
         let mut start = 0;
         for item_count in splitter.items {
             let leaf_ops = &self.ops[start..][..item_count];
@@ -371,6 +390,7 @@ impl LeafUpdater {
     }
 
     fn split(&mut self, new_leaves: &mut impl HandleNewLeaf) -> DigestResult {
+        self.splits += 1;
         let midpoint = self.gauge.body_size() / 2;
         let mut split_point = 0;
 
@@ -467,6 +487,7 @@ impl LeafUpdater {
     }
 
     fn prepare_merge_ops(&mut self, split_point: usize) {
+        self.merges += 1;
         self.ops.drain(..split_point);
 
         let Some(ref base) = self.base else { return };
@@ -628,8 +649,8 @@ impl LeafGauge {
 #[cfg(test)]
 mod tests {
     use super::{
-        separate, BaseLeaf, DigestResult, HandleNewLeaf, Key, LeafBuilder, LeafNode, LeafOp,
-        LeafUpdater, PagePool,
+        separate, BaseLeaf, CellFlags, DigestResult, HandleNewLeaf, Key, LeafBuilder, LeafNode,
+        LeafOp, LeafUpdater, PagePool,
     };
     use std::{collections::HashMap, sync::Arc};
 
@@ -658,7 +679,11 @@ mod tests {
 
         let mut builder = LeafBuilder::new(&PAGE_POOL, n, total_value_size);
         for (k, v, overflow) in vs {
-            builder.push_cell(k, &v, overflow);
+            let flags = CellFlags {
+                overflow,
+                ..CellFlags::default()
+            };
+            builder.push_cell(k, &v, flags);
         }
 
         Arc::new(builder.finish())
@@ -724,7 +749,7 @@ mod tests {
         );
         let mut new_leaves = TestHandleNewLeaf::default();
 
-        updater.ingest(key(2), Some(vec![2u8; 1000]), false, |_| {});
+        updater.ingest(key(2), Some(vec![2u8; 1000]), CellFlags::default(), |_| {});
         let DigestResult::Finished = updater.digest(&mut new_leaves) else {
             panic!()
         };
@@ -757,7 +782,7 @@ mod tests {
         );
         let mut new_leaves = TestHandleNewLeaf::default();
 
-        updater.ingest(key(4), Some(vec![1u8; 900]), false, |_| {});
+        updater.ingest(key(4), Some(vec![1u8; 900]), CellFlags::default(), |_| {});
         let DigestResult::Finished = updater.digest(&mut new_leaves) else {
             panic!()
         };
@@ -791,7 +816,7 @@ mod tests {
         );
         let mut new_leaves = TestHandleNewLeaf::default();
 
-        updater.ingest(key(4), Some(vec![1u8; 1200]), false, |_| {});
+        updater.ingest(key(4), Some(vec![1u8; 1200]), CellFlags::default(), |_| {});
         let DigestResult::Finished = updater.digest(&mut new_leaves) else {
             panic!()
         };
@@ -830,7 +855,7 @@ mod tests {
         );
         let mut new_leaves = TestHandleNewLeaf::default();
 
-        updater.ingest(key(2), None, false, |_| {});
+        updater.ingest(key(2), None, CellFlags::default(), |_| {});
         let DigestResult::Finished = updater.digest(&mut new_leaves) else {
             panic!()
         };
@@ -867,7 +892,7 @@ mod tests {
         );
         let mut new_leaves = TestHandleNewLeaf::default();
 
-        updater.ingest(key(2), None, false, |_| {});
+        updater.ingest(key(2), None, CellFlags::default(), |_| {});
         let DigestResult::NeedsMerge(merge_key) = updater.digest(&mut new_leaves) else {
             panic!()
         };
@@ -897,6 +922,92 @@ mod tests {
         assert_eq!(new_leaf.get(&key(5)).unwrap().0, &[1u8; 1100]);
     }
 
+    #[test]
+    fn delete_first_leaf_keeps_zero_separator() {
+        let leaf = make_leaf(vec![
+            (key(1), vec![1u8; 1200], false),
+            (key(2), vec![1u8; 1200], false),
+        ]);
+
+        let leaf2 = make_leaf(vec![
+            (key(4), vec![1u8; 1200], false),
+            (key(5), vec![1u8; 1200], false),
+        ]);
+
+        let mut updater = LeafUpdater::new(
+            PAGE_POOL.clone(),
+            Some(BaseLeaf {
+                node: leaf,
+                low: 0,
+                separator: key(0),
+            }),
+            Some(key(4)),
+        );
+        let mut new_leaves = TestHandleNewLeaf::default();
+
+        updater.ingest(key(1), None, CellFlags::default(), |_| {});
+        updater.ingest(key(2), None, CellFlags::default(), |_| {});
+        let DigestResult::NeedsMerge(merge_key) = updater.digest(&mut new_leaves) else {
+            panic!()
+        };
+        assert_eq!(merge_key, key(4));
+
+        updater.reset_base(
+            Some(BaseLeaf {
+                node: leaf2,
+                low: 0,
+                separator: key(4),
+            }),
+            None,
+        );
+
+        let DigestResult::Finished = updater.digest(&mut new_leaves) else {
+            panic!()
+        };
+        assert!(!new_leaves.inner.contains_key(&key(4)));
+        let new_leaf = &new_leaves.inner.get(&key(0)).unwrap().0;
+        assert_eq!(new_leaf.n(), 2);
+        assert_eq!(new_leaf.get(&key(4)).unwrap().0, &[1u8; 1200]);
+        assert_eq!(new_leaf.get(&key(5)).unwrap().0, &[1u8; 1200]);
+    }
+
+    #[test]
+    fn bulk_split_first_leaf() {
+        let leaf = make_leaf(vec![(key(1), vec![1u8; 1200], false)]);
+
+        let mut updater = LeafUpdater::new(
+            PAGE_POOL.clone(),
+            Some(BaseLeaf {
+                node: leaf,
+                low: 0,
+                separator: key(0),
+            }),
+            Some(key(0x80)),
+        );
+        let mut new_leaves = TestHandleNewLeaf::default();
+
+        // the inserted values fill exactly three leaves, leaving nothing for a leaf after them.
+        updater.ingest(key(1), None, CellFlags::default(), |_| {});
+        for i in 2..11 {
+            updater.ingest(key(i), Some(vec![i; 1200]), CellFlags::default(), |_| {});
+        }
+        let DigestResult::Finished = updater.digest(&mut new_leaves) else {
+            panic!()
+        };
+        assert_eq!(updater.separator_override, None);
+
+        assert_eq!(new_leaves.inner.len(), 3);
+        let first_leaf = &new_leaves.inner.get(&key(0)).unwrap().0;
+        assert_eq!(first_leaf.n(), 3);
+        assert_eq!(first_leaf.get(&key(2)).unwrap().0, &[2u8; 1200]);
+        let n = new_leaves
+            .inner
+            .values()
+            .map(|(leaf, _)| leaf.n())
+            .sum::<usize>();
+        assert_eq!(n, 9);
+    }
+
     #[test]
     fn delete_calls_with_deleted_overflow() {
         let leaf = make_leaf(vec![
@@ -917,7 +1028,7 @@ mod tests {
         let mut new_leaves = TestHandleNewLeaf::default();
 
         let mut called = false;
-        updater.ingest(key(2), None, false, |_| called = true);
+        updater.ingest(key(2), None, CellFlags::default(), |_| called = true);
         assert!(called);
         let DigestResult::Finished = updater.digest(&mut new_leaves) else {
             panic!()
@@ -942,8 +1053,8 @@ mod tests {
         );
         let mut new_leaves = TestHandleNewLeaf::default();
 
-        updater.ingest(key(1), None, false, |_| {});
-        updater.ingest(key(2), None, false, |_| {});
+        updater.ingest(key(1), None, CellFlags::default(), |_| {});
+        updater.ingest(key(2), None, CellFlags::default(), |_| {});
         let DigestResult::Finished = updater.digest(&mut new_leaves) else {
             panic!()
         };
@@ -969,7 +1080,7 @@ mod tests {
         );
         let mut new_leaves = TestHandleNewLeaf::default();
 
-        updater.ingest(key(1), None, false, |_| {});
+        updater.ingest(key(1), None, CellFlags::default(), |_| {});
         let DigestResult::Finished = updater.digest(&mut new_leaves) else {
             panic!()
         };
@@ -999,7 +1110,7 @@ mod tests {
         );
         let mut new_leaves = TestHandleNewLeaf::default();
 
-        updater.ingest(key(4), Some(vec![1; 300]), false, |_| {});
+        updater.ingest(key(4), Some(vec![1; 300]), CellFlags::default(), |_| {});
         let DigestResult::NeedsMerge(merge_key) = updater.digest(&mut new_leaves) else {
             panic!()
         };
@@ -1015,8 +1126,8 @@ mod tests {
         assert_eq!(
             updater.ops,
             vec![
-                LeafOp::Insert(key(3), vec![1u8; 300], false),
-                LeafOp::Insert(key(4), vec![1u8; 300], false),
+                LeafOp::Insert(key(3), vec![1u8; 300], CellFlags::default()),
+                LeafOp::Insert(key(4), vec![1u8; 300], CellFlags::default()),
             ]
         );
     }
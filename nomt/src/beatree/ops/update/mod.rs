@@ -12,9 +12,11 @@ use crate::beatree::{
     leaf::node::LEAF_NODE_BODY_SIZE,
     leaf_cache::LeafCache,
     ops::get_key,
+    value_log::ValueLog,
     Key, SyncData, ValueChange,
 };
 use crate::io::{IoHandle, PagePool};
+use crate::ValueCompression;
 
 mod branch_stage;
 mod branch_updater;
@@ -53,6 +55,8 @@ pub fn update(
     io_handle: IoHandle,
     thread_pool: ThreadPool,
     workers: usize,
+    value_compression: ValueCompression,
+    value_log: ValueLog,
 ) -> Result<(SyncData, Index, Receiver<()>)> {
     let leaf_reader = StoreReader::new(leaf_store.clone(), page_pool.clone());
     let (leaf_writer, leaf_finisher) = leaf_store.start_sync();
@@ -67,8 +71,13 @@ pub fn update(
         changeset,
         thread_pool.clone(),
         workers,
+        value_compression,
+        &value_log,
     )?;
 
+    // Values appended to the log must be durable before the leaves referring to them.
+    value_log.sync()?;
+
     let branch_stage_outputs = branch_stage::run(
         &mut bbn_index,
         bbn_writer,
@@ -92,9 +101,16 @@ pub fn update(
     crate::beatree::writeout::submit_freelist_write(&io_handle, &leaf_store, ln_freelist_pages)?;
     crate::beatree::writeout::submit_freelist_write(&io_handle, &bbn_store, bbn_freelist_pages)?;
 
+    // All completions are received before failing on any of them, so that none is left over for
+    // the next sync.
+    let mut write_result = Ok(());
     for _ in 0..total_io {
-        io_handle.recv()?;
+        let completion = io_handle.recv()?;
+        if write_result.is_ok() {
+            write_result = completion.result;
+        }
     }
+    write_result?;
 
     let (tx, rx) = crossbeam_channel::bounded(1);
     thread_pool.execute(move || {
@@ -110,6 +126,8 @@ pub fn update(
             ln_bump: ln_meta.bump,
             bbn_freelist_pn: bbn_meta.freelist_pn,
             bbn_bump: bbn_meta.bump,
+            splits: leaf_stage_outputs.splits + branch_stage_outputs.splits,
+            merges: leaf_stage_outputs.merges + branch_stage_outputs.merges,
         },
         bbn_index,
         rx,
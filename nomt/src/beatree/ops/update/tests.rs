@@ -15,9 +15,10 @@ use crate::{
                 leaf_stage::LeafStageOutput, LEAF_MERGE_THRESHOLD,
             },
         },
-        Index, ValueChange,
+        Index, ValueChange, ValueLog,
     },
     io::{start_test_io_pool, IoPool, PagePool},
+    ValueCompression,
 };
 use lazy_static::lazy_static;
 use quickcheck::{Arbitrary, Gen, QuickCheck, TestResult};
@@ -25,6 +26,7 @@ use rand::{Rng, SeedableRng};
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::fs::File;
+use std::path::PathBuf;
 use std::sync::Arc;
 use threadpool::ThreadPool;
 
@@ -88,6 +90,7 @@ impl TreeData {
     fn leaf_store(&self) -> Store {
         Store::open(
             &PAGE_POOL,
+            None,
             self.ln_fd.clone(),
             PageNumber(self.ln_bump),
             Some(PageNumber(self.ln_freelist_pn)),
@@ -121,15 +124,15 @@ fn init_beatree() -> TreeData {
         .map(|key| (key, vec![170u8; rng.gen_range(500..MAX_LEAF_VALUE_SIZE)]))
         .collect();
 
-    let leaf_store = Store::open(&PAGE_POOL, ln_fd.clone(), PageNumber(1), None).unwrap();
+    let leaf_store = Store::open(&PAGE_POOL, None, ln_fd.clone(), PageNumber(1), None).unwrap();
 
-    let bbn_store = Store::open(&PAGE_POOL, bbn_fd.clone(), PageNumber(1), None).unwrap();
+    let bbn_store = Store::open(&PAGE_POOL, None, bbn_fd.clone(), PageNumber(1), None).unwrap();
 
     let (sync_data, bbn_index, _) = super::update(
         initial_items
             .clone()
             .into_iter()
-            .map(|(k, v)| (k, ValueChange::Insert(v)))
+            .map(|(k, v)| (k, ValueChange::Insert(v.into())))
             .collect(),
         Index::default(),
         LeafCache::new(1, 1024),
@@ -139,6 +142,8 @@ fn init_beatree() -> TreeData {
         IO_POOL.make_handle(),
         THREAD_POOL.clone(),
         1,
+        ValueCompression::None,
+        ValueLog::open(PathBuf::new(), None).unwrap(),
     )
     .unwrap();
 
@@ -212,6 +217,8 @@ fn exec_leaf_stage(
         changeset.into_iter().collect(),
         THREAD_POOL.clone(),
         commit_concurrency,
+        ValueCompression::None,
+        &ValueLog::open(PathBuf::new(), None).unwrap(),
     )
     .unwrap();
 
@@ -322,7 +329,7 @@ fn leaf_stage_inner(insertions: BTreeMap<Key, u16>, deletions: Vec<u16>) -> Test
         .into_iter()
         // rescale raw_size to be between 0 and MAX_LEAF_VALUE_SIZE
         .map(|(k, raw_size)| (k, rescale(raw_size, 1, MAX_LEAF_VALUE_SIZE)))
-        .map(|(k, size)| (k.inner, ValueChange::Insert(vec![170; size])))
+        .map(|(k, size)| (k.inner, ValueChange::Insert(vec![170; size].into())))
         .collect();
 
     let mut changeset: BTreeMap<[u8; 32], ValueChange> = insertions.clone();
@@ -472,6 +479,7 @@ fn branch_stage_inner(insertions: BTreeMap<Key, u32>, deletions: Vec<u16>) -> Te
 
     let bbn_store = Store::open(
         &PAGE_POOL,
+        None,
         bbn_fd.clone(),
         PageNumber(SEPARATORS.len() as u32),
         None,
@@ -0,0 +1,377 @@
+//! The value log stores very large values outside of the btree, in append-only files.
+//!
+//! When enabled with [`crate::Options::value_log_threshold`], values of at least the threshold
+//! size which don't fit in a leaf are appended to a log file instead of being chunked into
+//! overflow pages, and the leaf cell only refers to them, see [`encode_cell`]. Updating such a
+//! value then rewrites a small cell instead of a chain of overflow pages.
+//!
+//! The logs are size-tiered: each tier of value sizes is appended to its own files, so that a file
+//! holds values of similar size. A file is never appended to again once the database is reopened
+//! or garbage collection has run, so a crash can only leave a torn entry at the end of a file.
+//!
+//! Overwritten and deleted values are not tracked. Garbage collection scans the sealed files for
+//! the entries still referenced by the btree and stages those to be rewritten by the next sync,
+//! see [`super::Tree::collect_value_log_garbage`]. The file is deleted once that sync has
+//! finished and no read transaction may refer to it anymore, i.e. when the sync after it starts.
+//!
+//! Each entry is laid out as: key (32 bytes) | value length (u32 LE) | value.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{File, OpenOptions},
+    os::unix::fs::FileExt as _,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use nomt_core::trie::ValueHash;
+use parking_lot::Mutex;
+
+use super::Key;
+
+/// The number of size tiers. Each tier covers values up to 4 times as large as the previous one,
+/// starting at the threshold. The last tier has no upper bound.
+const TIERS: usize = 4;
+
+/// Files are sealed once appending an entry would make them larger than this.
+const MAX_FILE_SIZE: u64 = 1 << 30;
+
+/// The size of the header preceding each value in a log file.
+pub const ENTRY_HEADER_SIZE: usize = 36;
+
+/// The size of a cell referring to a value in the log.
+pub const CELL_SIZE: usize = 48;
+
+/// The value log of a database. Cheap to clone.
+#[derive(Clone)]
+pub struct ValueLog {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    dir: PathBuf,
+    threshold: Option<usize>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    files: BTreeMap<u32, Arc<File>>,
+    /// The file each tier is appended to along with its length.
+    active: [Option<(u32, u64)>; TIERS],
+    next_id: u32,
+    /// Files appended to since the last call to `sync`.
+    unsynced: BTreeSet<u32>,
+    /// Whether a file was created since the last call to `sync`.
+    created: bool,
+    /// Files whose live values have been staged to be rewritten by the next sync.
+    retired: Vec<u32>,
+    /// Files whose live values are rewritten by the ongoing sync.
+    retiring: Vec<u32>,
+    /// Files no longer referenced by the btree, but possibly by a read transaction.
+    obsolete: Vec<u32>,
+}
+
+impl ValueLog {
+    /// Open the value log in the given directory, which is created once a value is appended.
+    ///
+    /// Values are only appended if a threshold is given, but values already in the log are always
+    /// readable.
+    pub fn open(dir: PathBuf, threshold: Option<usize>) -> anyhow::Result<Self> {
+        let mut state = State::default();
+        if dir.exists() {
+            for entry in std::fs::read_dir(&dir)? {
+                let name = entry?.file_name();
+                let Some(id) = name
+                    .to_str()
+                    .and_then(|name| name.strip_suffix(".log"))
+                    .and_then(|id| id.parse::<u32>().ok())
+                else {
+                    continue;
+                };
+                let file = File::open(file_path(&dir, id))?;
+                state.files.insert(id, Arc::new(file));
+                state.next_id = state.next_id.max(id + 1);
+            }
+        }
+        Ok(ValueLog {
+            inner: Arc::new(Inner {
+                dir,
+                threshold,
+                state: Mutex::new(state),
+            }),
+        })
+    }
+
+    /// Whether a value of the given size which doesn't fit in a leaf should be stored in the log.
+    pub fn accepts(&self, len: usize) -> bool {
+        self.inner
+            .threshold
+            .is_some_and(|threshold| len >= threshold)
+    }
+
+    /// Append a value, given as consecutive parts, to the log of its size tier, returning the file
+    /// and offset of the entry.
+    ///
+    /// The value is durable only after the next call to [`Self::sync`].
+    pub fn append_parts(&self, key: Key, parts: &[&[u8]]) -> anyhow::Result<(u32, u64)> {
+        let value_len = parts.iter().map(|part| part.len()).sum::<usize>();
+        let tier = self.tier(value_len);
+        let entry_len = (ENTRY_HEADER_SIZE + value_len) as u64;
+
+        let (file_id, offset, file) = {
+            let mut state = self.inner.state.lock();
+            let (file_id, offset) = match state.active[tier] {
+                Some((file_id, len)) if len == 0 || len + entry_len <= MAX_FILE_SIZE => {
+                    (file_id, len)
+                }
+                _ => (state.create_file(&self.inner.dir)?, 0),
+            };
+            state.active[tier] = Some((file_id, offset + entry_len));
+            state.unsynced.insert(file_id);
+            (file_id, offset, state.files[&file_id].clone())
+        };
+
+        let mut header = [0; ENTRY_HEADER_SIZE];
+        header[..32].copy_from_slice(&key);
+        header[32..].copy_from_slice(&u32::try_from(value_len)?.to_le_bytes());
+        file.write_all_at(&header, offset)?;
+        let mut part_offset = offset + ENTRY_HEADER_SIZE as u64;
+        for part in parts {
+            file.write_all_at(part, part_offset)?;
+            part_offset += part.len() as u64;
+        }
+        Ok((file_id, offset))
+    }
+
+    /// Make all appended values durable.
+    pub fn sync(&self) -> anyhow::Result<()> {
+        let (unsynced, created) = {
+            let mut state = self.inner.state.lock();
+            let unsynced = std::mem::take(&mut state.unsynced)
+                .into_iter()
+                .map(|file_id| state.files[&file_id].clone())
+                .collect::<Vec<_>>();
+            (unsynced, std::mem::take(&mut state.created))
+        };
+        for file in unsynced {
+            file.sync_data()?;
+        }
+        if created {
+            File::open(&self.inner.dir)?.sync_all()?;
+            if let Some(parent) = self.inner.dir.parent() {
+                File::open(parent)?.sync_all()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the value referred to by a cell.
+    ///
+    /// Panics if the value cannot be read.
+    pub fn read(&self, cell: &[u8]) -> Vec<u8> {
+        let (file_id, offset, len, _) = decode_cell(cell);
+        let file = self.inner.state.lock().files.get(&file_id).cloned();
+        let file = file.expect("value log file missing");
+
+        let mut header = [0; ENTRY_HEADER_SIZE];
+        file.read_exact_at(&mut header, offset)
+            .expect("value log read failed");
+        assert_eq!(read_len(&header), len, "corrupted value log entry");
+
+        let mut value = vec![0; len];
+        file.read_exact_at(&mut value, offset + ENTRY_HEADER_SIZE as u64)
+            .expect("value log read failed");
+        value
+    }
+
+    /// A reader of the value referred to by a cell, reading the log file as it is consumed.
+    ///
+    /// Panics if the value cannot be read.
+    pub fn reader(&self, cell: &[u8]) -> EntryReader {
+        let (file_id, offset, len, _) = decode_cell(cell);
+        let file = self.inner.state.lock().files.get(&file_id).cloned();
+        let file = file.expect("value log file missing");
+
+        let mut header = [0; ENTRY_HEADER_SIZE];
+        file.read_exact_at(&mut header, offset)
+            .expect("value log read failed");
+        assert_eq!(read_len(&header), len, "corrupted value log entry");
+
+        EntryReader {
+            file,
+            offset: offset + ENTRY_HEADER_SIZE as u64,
+            remaining: len,
+        }
+    }
+
+    /// Stop appending to the current files and return all files which are not retired yet.
+    pub fn seal(&self) -> Vec<u32> {
+        let mut state = self.inner.state.lock();
+        state.active = Default::default();
+        let state = &*state;
+        state
+            .files
+            .keys()
+            .copied()
+            .filter(|file_id| {
+                ![&state.retired, &state.retiring, &state.obsolete]
+                    .iter()
+                    .any(|retired| retired.contains(file_id))
+            })
+            .collect()
+    }
+
+    /// Read the headers of all entries in a file, returning the key and offset of each along with
+    /// the size of the file. A torn entry at the end of the file is ignored.
+    pub fn entries(&self, file_id: u32) -> anyhow::Result<(Vec<(Key, u64)>, u64)> {
+        let file = self.inner.state.lock().files[&file_id].clone();
+        let file_len = file.metadata()?.len();
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        let mut header = [0; ENTRY_HEADER_SIZE];
+        while offset + ENTRY_HEADER_SIZE as u64 <= file_len {
+            file.read_exact_at(&mut header, offset)?;
+            let entry_len = (ENTRY_HEADER_SIZE + read_len(&header)) as u64;
+            if offset + entry_len > file_len {
+                break;
+            }
+            // UNWRAP: the header is 36 bytes long.
+            entries.push((header[..32].try_into().unwrap(), offset));
+            offset += entry_len;
+        }
+        Ok((entries, file_len))
+    }
+
+    /// Mark a sealed file for deletion once the live values staged for rewriting are synced.
+    pub fn retire(&self, file_id: u32) {
+        self.inner.state.lock().retired.push(file_id);
+    }
+
+    /// Called when a sync starts, after all read transactions have concluded. Deletes the files
+    /// which became obsolete with the previous sync.
+    pub fn prepare_sync(&self) {
+        let mut state = self.inner.state.lock();
+        for file_id in std::mem::take(&mut state.obsolete) {
+            state.files.remove(&file_id);
+            // A file which fails to be deleted only holds dead entries, and is collected again by
+            // the next garbage collection.
+            let _ = std::fs::remove_file(file_path(&self.inner.dir, file_id));
+        }
+        state.retiring = std::mem::take(&mut state.retired);
+    }
+
+    /// Called when a sync has finished.
+    pub fn finish_sync(&self) {
+        let mut state = self.inner.state.lock();
+        let retiring = std::mem::take(&mut state.retiring);
+        state.obsolete.extend(retiring);
+    }
+
+    /// Link all files of the log into a new directory, copying them if hard links are not
+    /// supported.
+    ///
+    /// Sharing the files is safe as they are only ever appended to, and a database never appends
+    /// to files it didn't create since it was opened.
+    pub fn link_to(&self, dir: &Path) -> anyhow::Result<()> {
+        let file_ids = self
+            .inner
+            .state
+            .lock()
+            .files
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        if file_ids.is_empty() {
+            return Ok(());
+        }
+        std::fs::create_dir(dir)?;
+        for file_id in file_ids {
+            let (src, dst) = (file_path(&self.inner.dir, file_id), file_path(dir, file_id));
+            if std::fs::hard_link(&src, &dst).is_err() {
+                std::fs::copy(&src, &dst)?;
+                File::open(&dst)?.sync_all()?;
+            }
+        }
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+
+    fn tier(&self, len: usize) -> usize {
+        let mut bound = self.inner.threshold.unwrap_or(0).saturating_mul(4);
+        let mut tier = 0;
+        while tier < TIERS - 1 && len >= bound {
+            tier += 1;
+            bound = bound.saturating_mul(4);
+        }
+        tier
+    }
+}
+
+impl State {
+    fn create_file(&mut self, dir: &Path) -> anyhow::Result<u32> {
+        std::fs::create_dir_all(dir)?;
+        let file_id = self.next_id;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(file_path(dir, file_id))?;
+        self.files.insert(file_id, Arc::new(file));
+        self.next_id += 1;
+        self.created = true;
+        Ok(file_id)
+    }
+}
+
+/// Reads a value from a log file. See [`ValueLog::reader`].
+pub struct EntryReader {
+    file: Arc<File>,
+    offset: u64,
+    remaining: usize,
+}
+
+impl std::io::Read for EntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(self.remaining);
+        if n == 0 {
+            return Ok(0);
+        }
+        self.file.read_exact_at(&mut buf[..n], self.offset)?;
+        self.offset += n as u64;
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+/// Encode a cell referring to a value in the log.
+pub fn encode_cell(file_id: u32, offset: u64, len: usize, value_hash: ValueHash) -> Vec<u8> {
+    let mut cell = Vec::with_capacity(CELL_SIZE);
+    cell.extend_from_slice(&file_id.to_le_bytes());
+    cell.extend_from_slice(&offset.to_le_bytes());
+    // UNWRAP: values are at most `u32::MAX` bytes long.
+    cell.extend_from_slice(&u32::try_from(len).unwrap().to_le_bytes());
+    cell.extend_from_slice(&value_hash);
+    cell
+}
+
+/// Decode a cell referring to a value in the log into the file, offset, value size and value
+/// hash.
+pub fn decode_cell(cell: &[u8]) -> (u32, u64, usize, ValueHash) {
+    assert_eq!(cell.len(), CELL_SIZE);
+    // UNWRAP: the cell has the right size.
+    let file_id = u32::from_le_bytes(cell[0..4].try_into().unwrap());
+    let offset = u64::from_le_bytes(cell[4..12].try_into().unwrap());
+    let len = u32::from_le_bytes(cell[12..16].try_into().unwrap()) as usize;
+    let value_hash = cell[16..48].try_into().unwrap();
+    (file_id, offset, len, value_hash)
+}
+
+fn read_len(header: &[u8; ENTRY_HEADER_SIZE]) -> usize {
+    // UNWRAP: the length is the last 4 bytes of the header.
+    u32::from_le_bytes(header[32..].try_into().unwrap()) as usize
+}
+
+fn file_path(dir: &Path, file_id: u32) -> PathBuf {
+    dir.join(format!("{file_id:08}.log"))
+}
@@ -0,0 +1,22 @@
+//! Check the integrity of a database. See `nomt::integrity`.
+//!
+//! Usage: `nomt-fsck <path>`. Prints the report and exits with status 1 if any issues were found.
+
+use std::process::ExitCode;
+
+use nomt::{integrity, Blake3Hasher};
+
+fn main() -> anyhow::Result<ExitCode> {
+    let mut args = std::env::args().skip(1);
+    let (Some(path), None) = (args.next(), args.next()) else {
+        anyhow::bail!("usage: nomt-fsck <path>");
+    };
+
+    let report = integrity::check::<Blake3Hasher>(&path)?;
+    print!("{report}");
+    Ok(if report.is_ok() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
@@ -0,0 +1,267 @@
+//! Incremental compaction of tombstoned buckets.
+//!
+//! Deleting a page leaves a tombstone in its bucket, which every probe sequence passing through
+//! the bucket has to skip. Tombstones are reused by newly allocated pages, but are otherwise never
+//! removed, so probe sequences only ever grow longer.
+//!
+//! Compaction sweeps over the whole hash-table, examining a bounded number of pages on every sync.
+//! A page is moved to the first tombstone on its probe sequence, if there is one. Otherwise, all
+//! tombstones its probe sequence passes through are marked as needed. Once the sweep is complete,
+//! every tombstone which existed throughout the sweep and was not needed by any page is reclaimed,
+//! i.e. the bucket is made empty again.
+//!
+//! This is sound because pages allocated during the sweep take the first free bucket on their probe
+//! sequence, so their probe sequences don't pass through any tombstone existing at that time.
+
+use std::collections::HashMap;
+
+use bitvec::prelude::*;
+use nomt_core::page_id::PageId;
+
+use super::{hash_raw_page_id, meta_map::MetaMap, ProbeSequence, Shared, Table, MAX_PROBES};
+use crate::{
+    background_error::BackgroundErrorSource,
+    io::{self, FatPage, PAGE_SIZE},
+};
+
+/// A change to the hash-table decided by compaction. The meta-map has already been updated.
+pub enum CompactionOp {
+    /// Move a page to another bucket.
+    Relocate {
+        page_id: PageId,
+        page: FatPage,
+        hash: u64,
+        from: u64,
+        to: u64,
+    },
+    /// Turn a tombstone into an empty bucket.
+    Reclaim { bucket: u64 },
+}
+
+/// The change made to the meta-map by a [`CompactionOp`].
+///
+/// Page loads must not see the change before the pages are written to the hash-table file: until
+/// then, a relocated page is only found in its old bucket, and a reclaimed tombstone may still be
+/// on the way to it. So the change is reverted once the meta pages to write out have been taken
+/// from the meta-map, and applied again after the write.
+#[derive(Clone, Copy)]
+pub enum MetaChange {
+    Relocate { hash: u64, from: u64, to: u64 },
+    Reclaim { bucket: u64 },
+}
+
+impl MetaChange {
+    pub fn apply(&self, meta_map: &mut MetaMap) {
+        match *self {
+            MetaChange::Relocate { hash, from, to } => {
+                meta_map.set_full(to as usize, hash);
+                meta_map.set_tombstone(from as usize);
+            }
+            MetaChange::Reclaim { bucket } => meta_map.set_empty(bucket as usize),
+        }
+    }
+
+    pub fn revert(&self, meta_map: &mut MetaMap) {
+        match *self {
+            MetaChange::Relocate { hash, from, to } => {
+                meta_map.set_full(from as usize, hash);
+                meta_map.set_tombstone(to as usize);
+            }
+            MetaChange::Reclaim { bucket } => meta_map.set_tombstone(bucket as usize),
+        }
+    }
+}
+
+pub struct Compaction {
+    /// The maximum number of pages examined per sync.
+    budget: usize,
+    sweep: Option<Sweep>,
+}
+
+struct Sweep {
+    /// The next bucket to examine.
+    cursor: u64,
+    /// Tombstones which existed when the sweep started and have not been filled since.
+    stale: BitVec,
+    /// Tombstones on the probe sequence of some page examined during the sweep.
+    needed: BitVec,
+}
+
+impl Compaction {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            sweep: None,
+        }
+    }
+
+    /// Abandon the current sweep, if any, e.g. because the hash-table was replaced.
+    pub fn reset(&mut self) {
+        self.sweep = None;
+    }
+
+    /// Note that a page has been written to the given bucket.
+    pub fn note_filled(&mut self, bucket: u64) {
+        if let Some(ref mut sweep) = self.sweep {
+            sweep.stale.set(bucket as usize, false);
+        }
+    }
+
+    /// Advance the sweep over the given table, returning the changes made to its meta-map.
+    ///
+    /// `written` maps the buckets written in the current sync to the hashes of their pages. Those
+    /// pages are not relocated, as their contents on disk are out of date. Relocated pages are
+    /// added.
+    pub fn step(
+        &mut self,
+        shared: &Shared,
+        table: &mut Table,
+        written: &mut HashMap<u64, u64>,
+    ) -> Vec<CompactionOp> {
+        let sweep = self
+            .sweep
+            .get_or_insert_with(|| Sweep::start(&table.meta_map));
+        let mut ops = Vec::new();
+
+        let mut examined = 0;
+        while examined < self.budget && sweep.cursor < table.meta_map.len() as u64 {
+            let bucket = sweep.cursor;
+            sweep.cursor += 1;
+            if table.meta_map.hint_empty(bucket as usize)
+                || table.meta_map.hint_tombstone(bucket as usize)
+            {
+                continue;
+            }
+
+            examined += 1;
+            match sweep.examine(shared, table, written, bucket) {
+                Ok(Some(op)) => ops.push(op),
+                Ok(None) => {}
+                Err(e) => {
+                    // Compaction is best-effort. Start over with the next sync, without reclaiming
+                    // anything based on an incomplete sweep.
+                    shared
+                        .io_pool
+                        .background_errors()
+                        .report(BackgroundErrorSource::HashTableCompaction, e);
+                    self.sweep = None;
+                    return ops;
+                }
+            }
+        }
+
+        if sweep.cursor == table.meta_map.len() as u64 {
+            for bucket in sweep.stale.iter_ones() {
+                if !sweep.needed[bucket] && table.meta_map.hint_tombstone(bucket) {
+                    table.meta_map.set_empty(bucket);
+                    ops.push(CompactionOp::Reclaim {
+                        bucket: bucket as u64,
+                    });
+                }
+            }
+            self.sweep = None;
+        }
+
+        ops
+    }
+}
+
+impl Sweep {
+    fn start(meta_map: &MetaMap) -> Self {
+        let mut stale = bitvec![0; meta_map.len()];
+        for bucket in 0..meta_map.len() {
+            if meta_map.hint_tombstone(bucket) {
+                stale.set(bucket, true);
+            }
+        }
+        Sweep {
+            cursor: 0,
+            stale,
+            needed: bitvec![0; meta_map.len()],
+        }
+    }
+
+    fn examine(
+        &mut self,
+        shared: &Shared,
+        table: &mut Table,
+        written: &mut HashMap<u64, u64>,
+        bucket: u64,
+    ) -> anyhow::Result<Option<CompactionOp>> {
+        let meta_map = &mut table.meta_map;
+        let (hash, page) = match written.get(&bucket) {
+            Some(&hash) => (hash, None),
+            None => {
+                let pn = table.offsets.data_page_index(bucket);
+                let page = io::read_page(&shared.page_pool, shared.cipher(), &table.fd, pn)?;
+                // UNWRAP: slice is exactly 32 bytes.
+                let raw_page_id: [u8; 32] = page[PAGE_SIZE - 32..].try_into().unwrap();
+                let hash = hash_raw_page_id(raw_page_id, &shared.seed);
+                if meta_map.hint_not_match(bucket as usize, hash) {
+                    anyhow::bail!("bucket {bucket} holds an unexpected page");
+                }
+                let Some(page_id) = decode_stored_page_id(raw_page_id) else {
+                    anyhow::bail!("bucket {bucket} holds an invalid page ID");
+                };
+                (hash, Some((page_id, page)))
+            }
+        };
+
+        let mut probe_seq = ProbeSequence::from_hash(hash, meta_map);
+        let mut tombstones = Vec::new();
+        for _ in 0..MAX_PROBES {
+            let probed = probe_seq.next_bucket(meta_map);
+            if probed == bucket {
+                return Ok(match (tombstones.first(), page) {
+                    (Some(&to), Some((page_id, page))) => {
+                        meta_map.set_full(to as usize, hash);
+                        meta_map.set_tombstone(bucket as usize);
+                        self.stale.set(to as usize, false);
+                        written.insert(to, hash);
+                        Some(CompactionOp::Relocate {
+                            page_id,
+                            page,
+                            hash,
+                            from: bucket,
+                            to,
+                        })
+                    }
+                    _ => {
+                        for tombstone in tombstones {
+                            self.needed.set(tombstone as usize, true);
+                        }
+                        None
+                    }
+                });
+            }
+
+            if meta_map.hint_empty(probed as usize) {
+                anyhow::bail!("page in bucket {bucket} is unreachable");
+            }
+            if meta_map.hint_tombstone(probed as usize) {
+                tombstones.push(probed);
+            }
+        }
+
+        anyhow::bail!("probe sequence of bucket {bucket} is too long")
+    }
+}
+
+/// Recover a page ID from its encoding stored in a page, as produced by [`PageId::encode`].
+///
+/// `PageId::encode` shifts the encoding by one extra sextet compared to what `PageId::decode`
+/// expects, so undo that first. The result is checked to round-trip.
+pub(super) fn decode_stored_page_id(raw: [u8; 32]) -> Option<PageId> {
+    let mut shifted = [0u8; 32];
+    for i in 0..32 {
+        shifted[i] = raw[i] >> 6;
+        if i > 0 {
+            shifted[i] |= raw[i - 1] << 2;
+        }
+    }
+
+    [shifted, raw]
+        .into_iter()
+        .filter_map(|bytes| PageId::decode(bytes).ok())
+        .find(|page_id| page_id.encode() == raw)
+}
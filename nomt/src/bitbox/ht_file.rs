@@ -1,11 +1,15 @@
 /// The HT file.
 ///
 /// The file that stores the hash-table buckets and the meta map.
-use super::meta_map::MetaMap;
-use crate::io::{self, PagePool, PAGE_SIZE};
+use super::{meta_map::MetaMap, HashTableCreationCallback, HashTableCreationProgress};
+use crate::{
+    background_error::{BackgroundErrorSource, BackgroundErrors},
+    io::{self, PageCipher, PagePool, PAGE_SIZE},
+};
 use std::{
     fs::{File, OpenOptions},
-    path::PathBuf,
+    os::unix::fs::{FileExt as _, MetadataExt as _},
+    path::{Path, PathBuf},
 };
 
 /// The offsets of the HT file.
@@ -28,11 +32,11 @@ impl HTOffsets {
     }
 }
 
-fn expected_file_len(num_pages: u32) -> u64 {
+pub(super) fn expected_file_len(num_pages: u32) -> u64 {
     (num_meta_byte_pages(num_pages) + num_pages) as u64 * PAGE_SIZE as u64
 }
 
-fn num_meta_byte_pages(num_pages: u32) -> u32 {
+pub(super) fn num_meta_byte_pages(num_pages: u32) -> u32 {
     (num_pages + 4095) / PAGE_SIZE as u32
 }
 
@@ -40,6 +44,7 @@ fn num_meta_byte_pages(num_pages: u32) -> u32 {
 pub fn open(
     num_pages: u32,
     page_pool: &PagePool,
+    cipher: Option<&PageCipher>,
     ht_fd: &File,
 ) -> anyhow::Result<(HTOffsets, MetaMap)> {
     if ht_fd.metadata()?.len() != expected_file_len(num_pages) {
@@ -49,7 +54,7 @@ pub fn open(
     let num_meta_byte_pages = num_meta_byte_pages(num_pages);
     let mut meta_bytes = Vec::with_capacity(num_meta_byte_pages as usize * PAGE_SIZE);
     for pn in 0..num_meta_byte_pages {
-        let extra_meta_page = io::read_page(page_pool, ht_fd, pn as u64)?;
+        let extra_meta_page = io::read_page(page_pool, cipher, ht_fd, pn as u64)?;
         meta_bytes.extend_from_slice(&*extra_meta_page);
     }
 
@@ -60,87 +65,182 @@ pub fn open(
     ))
 }
 
+/// The number of bytes allocated at once when preallocating the HT file, between which the
+/// progress is reported.
+const PREALLOCATION_CHUNK: u64 = 1 << 30;
+
 /// Creates the store file. Fails if store file already exists.
 ///
-/// Lays out the meta page. If `preallocate` is true, preallocates the blocks for the file.
-pub fn create(path: PathBuf, num_pages: u32, preallocate: bool) -> std::io::Result<()> {
-    let start = std::time::Instant::now();
-    let ht_path = path.join("ht");
-    let ht_file = OpenOptions::new().write(true).create(true).open(ht_path)?;
+/// Lays out the meta page. If `preallocate` is true, preallocates the blocks for the file,
+/// reporting the progress to the given callback, if any.
+pub fn create(
+    path: PathBuf,
+    num_pages: u32,
+    preallocate: bool,
+    progress: Option<&HashTableCreationCallback>,
+    background_errors: &BackgroundErrors,
+) -> std::io::Result<()> {
+    create_table(
+        &path.join("ht"),
+        num_pages,
+        preallocate,
+        progress,
+        background_errors,
+    )?;
+
+    let wal_path = path.join("wal");
+    let wal_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(wal_path)?;
+    wal_file.sync_all()?;
+    drop(wal_file);
+    Ok(())
+}
+
+/// Creates a hash-table file with the given number of buckets, all of them empty, and syncs it.
+///
+/// Returns the number of pages of the file.
+pub(super) fn create_table(
+    path: &Path,
+    num_pages: u32,
+    preallocate: bool,
+    progress: Option<&HashTableCreationCallback>,
+    background_errors: &BackgroundErrors,
+) -> std::io::Result<u32> {
+    let ht_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
 
     // number of pages + pages required for meta bits.
     let page_count = num_pages + num_meta_byte_pages(num_pages);
-    let len = page_count as usize * PAGE_SIZE;
-
-    resize_and_prealloc(&ht_file, len as u64, preallocate)?;
+    let len = page_count as u64 * PAGE_SIZE as u64;
+
+    let report = |allocated: u64| {
+        if let Some(progress) = progress {
+            progress(HashTableCreationProgress {
+                allocated,
+                total: len,
+            });
+        }
+    };
+    report(0);
+    resize_and_prealloc(&ht_file, len, preallocate, report, background_errors)?;
 
     ht_file.sync_all()?;
-    drop(ht_file);
-
-    let wal_path = path.join("wal");
-    let wal_file = OpenOptions::new().write(true).create(true).open(wal_path)?;
-    wal_file.sync_all()?;
-    drop(wal_file);
+    Ok(page_count)
+}
 
-    println!(
-        "Created file with {} total pages in {}ms",
-        page_count,
-        start.elapsed().as_millis()
-    );
-    Ok(())
+/// How the blocks of the HT file are allocated.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Preallocation {
+    /// `fallocate` with `FALLOC_FL_ZERO_RANGE`.
+    ZeroRange,
+    /// Plain `fallocate`, for file systems not supporting `FALLOC_FL_ZERO_RANGE`.
+    Allocate,
+    /// Writing zeros, for file systems not supporting `fallocate` at all.
+    Write,
 }
 
 /// Sets the file size and attempts to preallocate the file if `preallocate` is true.
 ///
 /// Returns an error if setting the file size fails. File preallocation is done on a best-effort basis
-/// and may silently fall back to regular allocation.
+/// and may silently fall back to regular allocation. The file is allocated in chunks of
+/// [`PREALLOCATION_CHUNK`] bytes, after each of which the number of bytes allocated so far is
+/// reported.
+///
+/// Without preallocation, the file is sparse: blocks are only allocated once the buckets in them
+/// are first written, which happens in hash order, so the file tends to end up fragmented. The
+/// same holds on tmpfs, which is memory-backed anyway, and on file systems which don't store
+/// zeros written to a file, e.g. those compressing data. In that case a
+/// [`BackgroundErrorSource::SparseHashTable`] is reported.
 ///
 /// After this call, if successful, the file size is set to `len` bytes.
-fn resize_and_prealloc(ht_file: &File, len: u64, preallocate: bool) -> std::io::Result<()> {
+fn resize_and_prealloc(
+    ht_file: &File,
+    len: u64,
+    preallocate: bool,
+    report: impl Fn(u64),
+    background_errors: &BackgroundErrors,
+) -> std::io::Result<()> {
     if !preallocate {
         // If not preallocating, just set the file size and return.
         ht_file.set_len(len)?;
+        report(len);
         return Ok(());
     }
 
-    cfg_if::cfg_if! {
-        if #[cfg(target_os = "linux")] {
-            // To preallocate on Linux systems, try using fallocate with ZERO_RANGE first as it's more
-            // efficient. fallocate sets the file size as well, so ftruncate (aka file.set_len()) is
-            // not needed.
-            if crate::sys::linux::tmpfs_check(ht_file) {
-                // Skip preallocation for tmpfs. It doesn't support fallocate and it's
-                // memory-backed anyway. ftruncate and bail.
-                ht_file.set_len(len)?;
-                return Ok(());
-            }
-            if let Err(_) = crate::sys::linux::falloc_zero_file(ht_file, len) {
-                // If fallocate fails, fall back to zeroing the file with write.
-                resize_and_zero_file(ht_file, len)?;
+    #[cfg(target_os = "linux")]
+    if crate::sys::linux::tmpfs_check(ht_file) {
+        // Skip preallocation for tmpfs. It doesn't support fallocate and it's memory-backed
+        // anyway. ftruncate and bail.
+        ht_file.set_len(len)?;
+        report(len);
+        return Ok(());
+    }
+
+    // To preallocate on Linux systems, try using fallocate with ZERO_RANGE first as it's more
+    // efficient. fallocate sets the file size as well, so ftruncate (aka file.set_len()) is not
+    // needed.
+    #[cfg(target_os = "linux")]
+    let mut preallocation = Preallocation::ZeroRange;
+    #[cfg(not(target_os = "linux"))]
+    let mut preallocation = Preallocation::Write;
+
+    let mut offset = 0;
+    while offset < len {
+        let chunk = (len - offset).min(PREALLOCATION_CHUNK);
+        // Fall back to the next method whenever one fails. Chunks are allocated in order, so the
+        // file size only grows.
+        loop {
+            let result = match preallocation {
+                #[cfg(target_os = "linux")]
+                Preallocation::ZeroRange => {
+                    crate::sys::linux::falloc_zero_range(ht_file, offset, chunk)
+                }
+                #[cfg(target_os = "linux")]
+                Preallocation::Allocate => crate::sys::linux::falloc_range(ht_file, offset, chunk),
+                #[cfg(not(target_os = "linux"))]
+                Preallocation::ZeroRange | Preallocation::Allocate => unreachable!(),
+                Preallocation::Write => {
+                    zero_range(ht_file, offset, chunk)?;
+                    break;
+                }
+            };
+            match result {
+                Ok(()) => break,
+                Err(_) if preallocation == Preallocation::ZeroRange => {
+                    preallocation = Preallocation::Allocate
+                }
+                Err(_) => preallocation = Preallocation::Write,
             }
-        } else {
-            resize_and_zero_file(ht_file, len)?;
         }
+        offset += chunk;
+        report(offset);
     }
 
+    let allocated = ht_file.metadata()?.blocks() * 512;
+    if allocated < len {
+        background_errors.report(
+            BackgroundErrorSource::SparseHashTable,
+            anyhow::anyhow!("only {allocated} of {len} bytes are allocated despite preallocation"),
+        );
+    }
     Ok(())
 }
 
 // Fallback method for allocating extents for the file: just incrementally write zeroes to the file.
-fn resize_and_zero_file(mut file: &File, len: u64) -> std::io::Result<()> {
-    use std::io::Write;
-
-    // Set the file size first.
-    file.set_len(len)?;
-
-    // Zero the file.
-    let len = len as usize;
-    let buf = [0u8; PAGE_SIZE * 4];
-    let mut remaining = len;
-    while remaining > 0 {
-        let len = std::cmp::min(remaining, buf.len());
-        file.write_all(&buf[..len])?;
-        remaining -= len;
+fn zero_range(file: &File, offset: u64, len: u64) -> std::io::Result<()> {
+    // Large writes let the file system allocate large extents.
+    let buf = vec![0u8; PAGE_SIZE * 256];
+    let mut written = 0;
+    while written < len {
+        let n = std::cmp::min(len - written, buf.len() as u64);
+        file.write_all_at(&buf[..n as usize], offset + written)?;
+        written += n;
     }
     Ok(())
 }
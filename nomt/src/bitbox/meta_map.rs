@@ -11,23 +11,33 @@ fn full_entry(hash: u64) -> u8 {
 pub struct MetaMap {
     buckets: usize,
     bitvec: Vec<u8>,
+    full: usize,
+    tombstones: usize,
 }
 
 impl MetaMap {
     // Create a new meta-map from an existing vector.
     pub fn from_bytes(meta_bytes: Vec<u8>, buckets: usize) -> Self {
         assert_eq!(meta_bytes.len() % 4096, 0);
+        let full = meta_bytes
+            .iter()
+            .filter(|&&byte| byte & FULL_MASK != 0)
+            .count();
+        let tombstones = meta_bytes.iter().filter(|&&byte| byte == TOMBSTONE).count();
         MetaMap {
             buckets,
             bitvec: meta_bytes,
+            full,
+            tombstones,
         }
     }
 
     pub fn full_count(&self) -> usize {
-        self.bitvec
-            .iter()
-            .filter(|&&byte| byte & FULL_MASK != 0)
-            .count()
+        self.full
+    }
+
+    pub fn tombstone_count(&self) -> usize {
+        self.tombstones
     }
 
     pub fn len(&self) -> usize {
@@ -35,11 +45,31 @@ impl MetaMap {
     }
 
     pub fn set_full(&mut self, bucket: usize, hash: u64) {
-        self.bitvec[bucket] = full_entry(hash);
+        self.set(bucket, full_entry(hash));
     }
 
     pub fn set_tombstone(&mut self, bucket: usize) {
-        self.bitvec[bucket] = TOMBSTONE;
+        self.set(bucket, TOMBSTONE);
+    }
+
+    pub fn set_empty(&mut self, bucket: usize) {
+        self.set(bucket, EMPTY);
+    }
+
+    fn set(&mut self, bucket: usize, byte: u8) {
+        let prev = std::mem::replace(&mut self.bitvec[bucket], byte);
+        if prev & FULL_MASK != 0 {
+            self.full -= 1;
+        }
+        if byte & FULL_MASK != 0 {
+            self.full += 1;
+        }
+        if prev == TOMBSTONE {
+            self.tombstones -= 1;
+        }
+        if byte == TOMBSTONE {
+            self.tombstones += 1;
+        }
     }
 
     // true means definitely empty.
@@ -6,32 +6,63 @@ use parking_lot::{ArcRwLockReadGuard, Mutex, RwLock};
 use std::{
     collections::{HashMap, HashSet},
     fs::File,
+    ops::ControlFlow,
     os::{fd::AsRawFd, unix::fs::FileExt},
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
 };
 use threadpool::ThreadPool;
 
 use crate::{
-    io::{self, page_pool::FatPage, IoCommand, IoHandle, IoKind, PagePool, PAGE_SIZE},
+    io::{
+        self, page_pool::FatPage, IoCommand, IoHandle, IoKind, IoPool, PageCipher, PagePool,
+        PAGE_SIZE,
+    },
     merkle,
     page_cache::PageCache,
     page_diff::PageDiff,
     store::MerkleTransaction,
+    threads, SyncedFile, ThreadConfig,
 };
 
-use self::{ht_file::HTOffsets, meta_map::MetaMap};
+use self::{
+    compact::{Compaction, CompactionOp, MetaChange},
+    ht_file::HTOffsets,
+    meta_map::MetaMap,
+    resize::{Migration, Resize},
+};
 
 pub use self::ht_file::create;
-pub use wal::WalBlobBuilder;
+pub use wal::{WalBlobBuilder, WalSink, WalSinks};
 
+mod compact;
 mod ht_file;
 mod meta_map;
+pub(crate) mod reseed;
+pub(crate) mod resize;
 mod wal;
 pub(crate) mod writeout;
 
+/// The maximum number of buckets probed before giving up.
+const MAX_PROBES: usize = 10000;
+
+/// An occupied bucket of the hash-table, as read by [`DB::check_buckets`].
+#[derive(Debug, Clone)]
+pub struct CheckedBucket {
+    /// The index of the bucket.
+    pub bucket: u64,
+    /// The encoded ID of the page stored in the bucket.
+    pub page_id: [u8; 32],
+    /// Whether the hash bits kept for the bucket in the meta-map match the page ID.
+    pub meta_matches: bool,
+    /// Whether the bucket lies on the probe sequence of the page ID before any empty bucket, i.e.
+    /// whether loading the page finds it.
+    pub reachable: bool,
+}
+
 /// The index of a bucket within the map.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BucketIndex(u64);
@@ -43,54 +74,346 @@ pub struct DB {
 
 pub struct Shared {
     page_pool: PagePool,
-    store: HTOffsets,
+    /// The directory holding the HT file.
+    dir: PathBuf,
     seed: [u8; 16],
-    meta_map: Arc<RwLock<MetaMap>>,
+    tables: Arc<RwLock<Tables>>,
     wal_blob_builder: Arc<Mutex<WalBlobBuilder>>,
     occupied_buckets: AtomicUsize,
     wal_fd: File,
-    ht_fd: File,
+    /// A copy of the WAL file, kept on a different device.
+    wal_mirror_fd: Option<File>,
+    wal_sinks: Option<WalSinks>,
+    /// Syncs the WAL and consults the fault injector before writing it.
+    io_pool: IoPool,
     sync_tp: ThreadPool,
+    /// `None` if compaction is disabled.
+    compaction: Option<Mutex<Compaction>>,
+    /// The maximum number of pages moved to the new table on each sync while resizing.
+    resize_budget: usize,
+    page_loads: AtomicU64,
+    probed_buckets: AtomicU64,
+    relocated_pages: AtomicU64,
+    reclaimed_tombstones: AtomicU64,
+    migrated_pages: AtomicU64,
+}
+
+/// A hash-table file along with its meta-map.
+struct Table {
+    meta_map: MetaMap,
+    offsets: HTOffsets,
+    fd: Arc<File>,
+}
+
+/// The hash-tables holding the pages: the main one and, while it is being grown, the one it is
+/// grown into. See [`resize`].
+///
+/// Bucket indices number the buckets of the main table starting from `base`, followed by those of
+/// the table being grown into. When a resize completes, `base` moves past the buckets of the old
+/// table, so that the indices of the buckets of the new table stay the same.
+struct Tables {
+    main: Table,
+    resize: Option<Resize>,
+    base: u64,
+}
+
+impl Tables {
+    /// Returns whether the bucket belongs to the table being grown into, along with its index
+    /// within its table.
+    fn locate(&self, bucket: u64) -> (bool, u64) {
+        let bucket = bucket - self.base;
+        let main_len = self.main.meta_map.len() as u64;
+        if bucket < main_len {
+            (false, bucket)
+        } else {
+            (true, bucket - main_len)
+        }
+    }
+
+    /// Returns the bucket index of the given bucket of the main table or the table being grown
+    /// into.
+    fn bucket_index(&self, resized: bool, bucket: u64) -> BucketIndex {
+        if resized {
+            BucketIndex(self.base + self.main.meta_map.len() as u64 + bucket)
+        } else {
+            BucketIndex(self.base + bucket)
+        }
+    }
+
+    /// Returns the bucket as recorded in the WAL, which numbers the buckets from the start of the
+    /// main table.
+    fn wal_bucket(&self, bucket: u64) -> u64 {
+        bucket - self.base
+    }
+
+    fn table(&self, resized: bool) -> &Table {
+        match self.resize {
+            Some(ref resize) if resized => &resize.table,
+            _ => &self.main,
+        }
+    }
+
+    fn table_mut(&mut self, resized: bool) -> &mut Table {
+        match self.resize {
+            Some(ref mut resize) if resized => &mut resize.table,
+            _ => &mut self.main,
+        }
+    }
+}
+
+impl Shared {
+    /// The cipher the pages of the hash-table and the WAL are encrypted with, if any.
+    fn cipher(&self) -> Option<&PageCipher> {
+        self.io_pool.cipher().map(Arc::as_ref)
+    }
+}
+
+/// Statistics about the hash-table storing the pages of the merkle trie.
+///
+/// The counters are cumulative since the database was opened. Compare snapshots taken at
+/// different times to observe trends, such as the average probe length growing as pages are
+/// deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashTableStats {
+    /// The total number of buckets.
+    pub buckets: usize,
+    /// The number of buckets holding a page.
+    pub occupied_buckets: usize,
+    /// The number of buckets left behind by deleted pages.
+    pub tombstones: usize,
+    /// The number of buckets the hash-table is being resized to, if it is being resized. See
+    /// [`crate::Nomt::resize_hash_table`] and [`crate::Nomt::shrink`].
+    pub resizing_to: Option<usize>,
+    /// The number of pages yet to be moved to the resized hash-table, if it is being resized.
+    pub pages_to_migrate: usize,
+    /// The number of page loads.
+    pub page_loads: u64,
+    /// The number of buckets probed by page loads.
+    pub probed_buckets: u64,
+    /// The number of pages moved to an earlier bucket of their probe sequence by compaction.
+    pub relocated_pages: u64,
+    /// The number of tombstones turned back into empty buckets by compaction.
+    pub reclaimed_tombstones: u64,
+    /// The number of pages moved to a resized hash-table.
+    pub migrated_pages: u64,
+}
+
+impl HashTableStats {
+    /// The average number of buckets probed per page load, or `None` if no pages were loaded.
+    pub fn average_probe_length(&self) -> Option<f64> {
+        if self.page_loads == 0 {
+            None
+        } else {
+            Some(self.probed_buckets as f64 / self.page_loads as f64)
+        }
+    }
+}
+
+/// The progress of creating the hash-table file of a new database. See
+/// [`crate::Options::hash_table_creation_progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashTableCreationProgress {
+    /// The number of bytes of the file allocated so far.
+    pub allocated: u64,
+    /// The length of the file in bytes.
+    pub total: u64,
+}
+
+/// A callback informed of the progress of creating the hash-table file.
+pub type HashTableCreationCallback = Arc<dyn Fn(HashTableCreationProgress) + Send + Sync>;
+
+/// The progress of replaying the WAL while opening the database. See
+/// [`crate::Options::wal_replay_progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalReplayProgress {
+    /// The number of WAL records replayed so far, including those replayed by earlier, cancelled
+    /// attempts.
+    pub replayed: u64,
+    /// The total number of WAL records.
+    pub total: u64,
+}
+
+/// The error returned by [`crate::Nomt::open`] if the WAL replay was cancelled. See
+/// [`crate::Options::wal_replay_progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalReplayCancelled;
+
+impl std::fmt::Display for WalReplayCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WAL replay cancelled")
+    }
+}
+
+impl std::error::Error for WalReplayCancelled {}
+
+/// A callback informed of the progress of the WAL replay, which may cancel it.
+pub type WalReplayCallback = Arc<dyn Fn(WalReplayProgress) -> ControlFlow<()> + Send + Sync>;
+
+/// The number of WAL records replayed between calls to the [`WalReplayCallback`].
+const WAL_REPLAY_REPORT_INTERVAL: u64 = 1024;
+
+/// How to replay the WAL when opening the database.
+pub struct WalReplay {
+    /// The sequence number of the last sync according to the manifest. The WAL of any other sync
+    /// belongs to one which never committed and is discarded.
+    pub sync_seqn: u32,
+    /// Whether the WAL starts with the sequence number of the sync which produced it. WALs left
+    /// by databases whose manifest predates the header have none and are always replayed.
+    pub wal_header: bool,
+    /// The file recording how far a cancelled replay got, so that the next one can resume.
+    pub checkpoint_path: PathBuf,
+    /// Informed of the progress and able to cancel the replay.
+    pub progress: Option<WalReplayCallback>,
 }
 
 impl DB {
     /// Opens an existing bitbox database.
+    #[allow(clippy::too_many_arguments)]
+    ///
+    /// `resize` is the number of buckets and the file of the hash-table being grown into, if the
+    /// manifest records a resize in progress.
     pub fn open(
         num_pages: u32,
         seed: [u8; 16],
         page_pool: PagePool,
+        dir: PathBuf,
         ht_fd: File,
+        resize: Option<(u32, File)>,
         wal_fd: File,
+        wal_mirror_fd: Option<File>,
+        wal_sinks: Option<WalSinks>,
+        io_pool: IoPool,
+        compaction_budget: usize,
+        resize_budget: usize,
+        threads: &ThreadConfig,
+        replay: &WalReplay,
     ) -> anyhow::Result<Self> {
-        let (store, mut meta_map) = match ht_file::open(num_pages, &page_pool, &ht_fd) {
+        let cipher = io_pool.cipher().map(Arc::as_ref);
+        let (offsets, meta_map) = match ht_file::open(num_pages, &page_pool, cipher, &ht_fd) {
             Ok(x) => x,
             Err(e) => {
                 anyhow::bail!("encountered error in opening store: {e:?}");
             }
         };
+        let resize = match resize {
+            Some((num_pages, fd)) => match resize::open(&page_pool, cipher, num_pages, fd) {
+                Ok(table) => Some(Resize::new(table)),
+                Err(e) => {
+                    anyhow::bail!("encountered error in opening resized store: {e:?}");
+                }
+            },
+            None => None,
+        };
+        let mut tables = Tables {
+            main: Table {
+                meta_map,
+                offsets,
+                fd: Arc::new(ht_fd),
+            },
+            resize,
+            base: 0,
+        };
 
-        if wal_fd.metadata()?.len() > 0 {
-            recover(&ht_fd, &wal_fd, &page_pool, &store, &mut meta_map, seed)?;
+        let wal_fds = std::iter::once(&wal_fd)
+            .chain(wal_mirror_fd.as_ref())
+            .collect::<Vec<_>>();
+        let mut wal_len = 0;
+        for wal_fd in &wal_fds {
+            wal_len = wal_len.max(wal_fd.metadata()?.len());
+        }
+        if wal_len > 0 {
+            recover(&mut tables, &wal_fds, &page_pool, cipher, seed, replay)?;
         }
 
-        let occupied_buckets = meta_map.full_count();
+        let occupied_buckets = tables.main.meta_map.full_count()
+            + tables
+                .resize
+                .as_ref()
+                .map_or(0, |resize| resize.table.meta_map.full_count());
 
         let wal_blob_builder = WalBlobBuilder::new()?;
         Ok(Self {
             shared: Arc::new(Shared {
                 page_pool,
-                store,
+                dir,
                 seed,
-                meta_map: Arc::new(RwLock::new(meta_map)),
+                tables: Arc::new(RwLock::new(tables)),
                 wal_blob_builder: Arc::new(Mutex::new(wal_blob_builder)),
                 occupied_buckets: AtomicUsize::new(occupied_buckets),
                 wal_fd,
-                ht_fd,
-                sync_tp: ThreadPool::with_name("bitbox-sync".into(), 2),
+                wal_mirror_fd,
+                wal_sinks,
+                io_pool,
+                sync_tp: threads::pool(
+                    threads::thread_name(threads, "bitbox-sync"),
+                    2,
+                    &threads.commit,
+                )?,
+                compaction: (compaction_budget > 0)
+                    .then(|| Mutex::new(Compaction::new(compaction_budget))),
+                resize_budget,
+                page_loads: AtomicU64::new(0),
+                probed_buckets: AtomicU64::new(0),
+                relocated_pages: AtomicU64::new(0),
+                reclaimed_tombstones: AtomicU64::new(0),
+                migrated_pages: AtomicU64::new(0),
             }),
         })
     }
 
+    /// Write the WAL blob to the WAL file and its mirror, if any.
+    fn write_local_wal(&self, sync_seqn: u32, wal_blob: &[u8]) -> anyhow::Result<()> {
+        let io_pool = &self.shared.io_pool;
+        if let Some(fault_injector) = io_pool.fault_injector() {
+            fault_injector.wal_write(sync_seqn, wal_blob)?;
+        }
+        std::thread::scope(|scope| {
+            let mirror_write = self.shared.wal_mirror_fd.as_ref().map(|wal_mirror_fd| {
+                scope.spawn(|| {
+                    let file = SyncedFile::WalMirror;
+                    writeout::write_wal(wal_mirror_fd, wal_blob, file, io_pool)
+                })
+            });
+            let file = SyncedFile::Wal;
+            let wal_result = writeout::write_wal(&self.shared.wal_fd, wal_blob, file, io_pool);
+            match mirror_write {
+                // UNWRAP: the writeout doesn't panic.
+                Some(mirror_write) => wal_result.and(mirror_write.join().unwrap()),
+                None => wal_result,
+            }
+        })
+    }
+
+    /// Redistribute the pages according to a new seed. See [`reseed`].
+    ///
+    /// This must be done right after opening the database, while it is not shared. The reseed is
+    /// completed by persisting the new seed in the manifest and then calling [`reseed::finish`].
+    pub fn reseed(self, dir: &Path, seed: [u8; 16]) -> anyhow::Result<Self> {
+        if self.shared.tables.read().resize.is_some() {
+            anyhow::bail!("the hash-table can't be reseeded while it is being resized");
+        }
+        reseed::prepare(&self.shared, dir, seed)?;
+        let Ok(mut shared) = Arc::try_unwrap(self.shared) else {
+            anyhow::bail!("hash-table reseeded while in use");
+        };
+
+        let mut tables = shared.tables.write();
+        reseed::apply(dir, &shared.page_pool, &tables.main.fd)?;
+        let num_pages = tables.main.meta_map.len() as u32;
+        let (_, meta_map) = ht_file::open(
+            num_pages,
+            &shared.page_pool,
+            shared.cipher(),
+            &tables.main.fd,
+        )?;
+        tables.main.meta_map = meta_map;
+        drop(tables);
+        shared.seed = seed;
+        Ok(Self {
+            shared: Arc::new(shared),
+        })
+    }
+
     /// Return a bucket allocator, used to determine the buckets which any newly inserted pages
     /// will clear.
     pub fn bucket_allocator(&self) -> BucketAllocator {
@@ -104,21 +427,169 @@ impl DB {
         SyncController::new(self.clone())
     }
 
+    /// Returns statistics about the hash-table.
+    pub fn stats(&self) -> HashTableStats {
+        let tables = self.shared.tables.read();
+        let resize = tables.resize.as_ref().map(|resize| &resize.table.meta_map);
+        HashTableStats {
+            buckets: tables.main.meta_map.len(),
+            occupied_buckets: self.shared.occupied_buckets.load(Ordering::Relaxed),
+            tombstones: tables.main.meta_map.tombstone_count()
+                + resize.map_or(0, |meta_map| meta_map.tombstone_count()),
+            resizing_to: resize.map(|meta_map| meta_map.len()),
+            pages_to_migrate: resize.map_or(0, |_| tables.main.meta_map.full_count()),
+            page_loads: self.shared.page_loads.load(Ordering::Relaxed),
+            probed_buckets: self.shared.probed_buckets.load(Ordering::Relaxed),
+            relocated_pages: self.shared.relocated_pages.load(Ordering::Relaxed),
+            reclaimed_tombstones: self.shared.reclaimed_tombstones.load(Ordering::Relaxed),
+            migrated_pages: self.shared.migrated_pages.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Read every occupied bucket and check it against the meta-map, for
+    /// [`crate::integrity::check`].
+    ///
+    /// This reads the whole hash-table and must not run concurrently with a sync.
+    pub fn check_buckets(&self) -> anyhow::Result<Vec<CheckedBucket>> {
+        let tables = self.shared.tables.read();
+        let main_len = tables.main.meta_map.len() as u64;
+        let mut checked = Vec::new();
+        let resize = tables
+            .resize
+            .as_ref()
+            .map(|resize| (&resize.table, main_len));
+        for (table, offset) in std::iter::once((&tables.main, 0)).chain(resize) {
+            let meta_map = &table.meta_map;
+            for bucket in 0..meta_map.len() {
+                if meta_map.hint_empty(bucket) || meta_map.hint_tombstone(bucket) {
+                    continue;
+                }
+
+                let pn = table.offsets.data_page_index(bucket as u64);
+                let page =
+                    io::read_page(&self.shared.page_pool, self.shared.cipher(), &table.fd, pn)?;
+                // UNWRAP: the slice is 32 bytes long.
+                let page_id: [u8; 32] = page[PAGE_SIZE - 32..].try_into().unwrap();
+                let hash = hash_raw_page_id(page_id, &self.shared.seed);
+
+                // A load gives up at the first empty bucket of the probe sequence.
+                let mut probe_seq = ProbeSequence::from_hash(hash, meta_map);
+                let mut reachable = false;
+                for _ in 0..meta_map.len().min(MAX_PROBES) {
+                    let probed = probe_seq.next_bucket(meta_map);
+                    if probed == bucket as u64 {
+                        reachable = true;
+                        break;
+                    }
+                    if meta_map.hint_empty(probed as usize) {
+                        break;
+                    }
+                }
+
+                checked.push(CheckedBucket {
+                    bucket: tables.base + offset + bucket as u64,
+                    page_id,
+                    meta_matches: !meta_map.hint_not_match(bucket, hash),
+                    reachable,
+                });
+            }
+        }
+        Ok(checked)
+    }
+
+    /// Start resizing the hash-table to the given number of buckets. See [`resize`].
+    ///
+    /// The new hash-table may also be smaller, as long as it has room for all pages.
+    ///
+    /// Must not run concurrently with a sync.
+    pub fn begin_resize(&self, num_pages: u32) -> anyhow::Result<()> {
+        {
+            let tables = self.shared.tables.read();
+            if tables.resize.is_some() {
+                anyhow::bail!("the hash-table is already being resized");
+            }
+            if num_pages as usize == tables.main.meta_map.len() {
+                anyhow::bail!("the hash-table has {num_pages} buckets already");
+            }
+            if num_pages as usize <= tables.main.meta_map.full_count() {
+                anyhow::bail!(
+                    "{num_pages} buckets can't hold the {} pages of the hash-table",
+                    tables.main.meta_map.full_count()
+                );
+            }
+        }
+
+        // Create the file without blocking page loads.
+        let table = resize::create(
+            &self.shared.dir,
+            &self.shared.page_pool,
+            self.shared.cipher(),
+            num_pages,
+            self.shared.io_pool.background_errors(),
+        )?;
+        self.shared.tables.write().resize = Some(Resize::new(table));
+        Ok(())
+    }
+
+    /// Returns the raw descriptor of the HT file.
+    pub fn ht_raw_fd(&self) -> std::os::fd::RawFd {
+        self.shared.tables.read().main.fd.as_raw_fd()
+    }
+
+    /// Returns the number of buckets of the hash-table and of the one it is being grown into, if
+    /// any.
+    pub fn num_pages(&self) -> (u32, Option<u32>) {
+        let tables = self.shared.tables.read();
+        (
+            tables.main.meta_map.len() as u32,
+            tables
+                .resize
+                .as_ref()
+                .map(|resize| resize.table.meta_map.len() as u32),
+        )
+    }
+
+    /// The number of buckets probed by page loads, each of which is a page read from disk.
+    pub fn probed_buckets(&self) -> u64 {
+        self.shared.probed_buckets.load(Ordering::Relaxed)
+    }
+
     fn prepare_sync(
         &self,
+        sync_seqn: u32,
         page_pool: &PagePool,
+        page_cache: &PageCache,
         changes: Vec<(PageId, BucketIndex, Option<(FatPage, PageDiff)>)>,
         wal_blob_builder: &mut WalBlobBuilder,
-    ) -> Vec<(u64, FatPage)> {
-        wal_blob_builder.reset();
-
-        let mut meta_map = self.shared.meta_map.write();
+    ) -> HtWrites {
+        wal_blob_builder.reset(sync_seqn);
+
+        let mut tables = self.shared.tables.write();
+        let tables = &mut *tables;
+        let mut compaction = self.shared.compaction.as_ref().map(|c| c.lock());
+        // The buckets of the main table written in this sync, along with the hashes of their
+        // pages.
+        let mut written = HashMap::new();
+
+        let complete_resize = resize::complete(tables);
+        if complete_resize {
+            if let Some(ref mut compaction) = compaction {
+                compaction.reset();
+            }
+        }
 
+        // The meta pages changed, along with whether they belong to the table being grown into.
         let mut changed_meta_pages = HashSet::new();
+        // The changes made to the meta-map of the main table by compaction.
+        let mut compacted = Vec::new();
         let mut ht_pages = Vec::new();
 
         let mut occupied_buckets_delta = 0isize;
         for (page_id, BucketIndex(bucket), page_info) in changes {
+            let wal_bucket = tables.wal_bucket(bucket);
+            let (resized, bucket) = tables.locate(bucket);
+            let table = tables.table_mut(resized);
+            let meta_map = &mut table.meta_map;
             // let's extract its bucket
             match page_info {
                 Some((mut page, page_diff)) => {
@@ -130,40 +601,115 @@ impl DB {
                     if meta_map_changed {
                         occupied_buckets_delta += 1;
                         meta_map.set_full(bucket as usize, hash);
-                        changed_meta_pages.insert(meta_map.page_index(bucket as usize));
+                        changed_meta_pages.insert((resized, meta_map.page_index(bucket as usize)));
+                    }
+                    match compaction {
+                        Some(ref mut compaction) if !resized => {
+                            compaction.note_filled(bucket);
+                            written.insert(bucket, hash);
+                        }
+                        _ => {}
                     }
 
                     wal_blob_builder.write_update(
                         page_id.encode(),
                         &page_diff,
                         page_diff.pack_changed_nodes(&page),
-                        bucket,
+                        wal_bucket,
                     );
 
-                    let pn = self.shared.store.data_page_index(bucket);
-                    ht_pages.push((pn, page));
+                    let pn = table.offsets.data_page_index(bucket);
+                    ht_pages.push((resized, pn, page));
                 }
                 None => {
                     occupied_buckets_delta -= 1;
                     meta_map.set_tombstone(bucket as usize);
-                    changed_meta_pages.insert(meta_map.page_index(bucket as usize));
-                    wal_blob_builder.write_clear(bucket);
+                    changed_meta_pages.insert((resized, meta_map.page_index(bucket as usize)));
+                    wal_blob_builder.write_clear(wal_bucket);
                 }
             };
         }
 
-        for changed_meta_page in changed_meta_pages {
+        let Tables { main, resize, base } = tables;
+        if let Some(resize) = resize {
+            let main_len = main.meta_map.len() as u64;
+            for migration in resize.step(&self.shared, main, self.shared.resize_budget) {
+                let Migration {
+                    page_id,
+                    page,
+                    from,
+                    to,
+                } = migration;
+                wal_blob_builder.write_update(
+                    page_id.encode(),
+                    &PageDiff::full(),
+                    PageDiff::full().pack_changed_nodes(&page),
+                    main_len + to,
+                );
+                wal_blob_builder.write_clear(from);
+                changed_meta_pages.insert((false, main.meta_map.page_index(from as usize)));
+                changed_meta_pages.insert((true, resize.table.meta_map.page_index(to as usize)));
+
+                page_cache.relocate(page_id, BucketIndex(*base + main_len + to));
+                let pn = resize.table.offsets.data_page_index(to);
+                ht_pages.push((true, pn, page));
+                self.shared.migrated_pages.fetch_add(1, Ordering::Relaxed);
+            }
+        } else if let Some(ref mut compaction) = compaction {
+            for op in compaction.step(&self.shared, main, &mut written) {
+                match op {
+                    CompactionOp::Relocate {
+                        page_id,
+                        page,
+                        hash,
+                        from,
+                        to,
+                    } => {
+                        compacted.push(MetaChange::Relocate { hash, from, to });
+                        wal_blob_builder.write_update(
+                            page_id.encode(),
+                            &PageDiff::full(),
+                            PageDiff::full().pack_changed_nodes(&page),
+                            to,
+                        );
+                        wal_blob_builder.write_clear(from);
+                        changed_meta_pages.insert((false, main.meta_map.page_index(from as usize)));
+                        changed_meta_pages.insert((false, main.meta_map.page_index(to as usize)));
+
+                        page_cache.relocate(page_id, BucketIndex(*base + to));
+                        let pn = main.offsets.data_page_index(to);
+                        ht_pages.push((false, pn, page));
+                        self.shared.relocated_pages.fetch_add(1, Ordering::Relaxed);
+                    }
+                    CompactionOp::Reclaim { bucket } => {
+                        compacted.push(MetaChange::Reclaim { bucket });
+                        wal_blob_builder.write_reclaim(bucket);
+                        changed_meta_pages
+                            .insert((false, main.meta_map.page_index(bucket as usize)));
+                        self.shared
+                            .reclaimed_tombstones
+                            .fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+
+        for (resized, changed_meta_page) in changed_meta_pages {
+            let table = tables.table(resized);
             let mut buf = page_pool.alloc_fat_page();
-            buf[..].copy_from_slice(meta_map.page_slice(changed_meta_page));
-            let pn = self.shared.store.meta_bytes_index(changed_meta_page as u64);
-            ht_pages.push((pn, buf));
+            buf[..].copy_from_slice(table.meta_map.page_slice(changed_meta_page));
+            let pn = table.offsets.meta_bytes_index(changed_meta_page as u64);
+            ht_pages.push((resized, pn, buf));
+        }
+        for change in compacted.iter().rev() {
+            change.revert(&mut tables.main.meta_map);
         }
 
         if cfg!(debug_assertions) {
             // Make sure that there are no duplicate pages.
             let orig_len = ht_pages.len();
-            ht_pages.sort_unstable_by_key(|(pn, _)| *pn);
-            ht_pages.dedup_by_key(|(pn, _)| *pn);
+            ht_pages.sort_unstable_by_key(|(resized, pn, _)| (*resized, *pn));
+            ht_pages.dedup_by_key(|(resized, pn, _)| (*resized, *pn));
             assert_eq!(orig_len, ht_pages.len());
         }
 
@@ -179,18 +725,58 @@ impl DB {
 
         wal_blob_builder.finalize();
 
-        ht_pages
+        let (resized_pages, main_pages) = ht_pages
+            .into_iter()
+            .partition::<Vec<_>, _>(|(resized, _, _)| *resized);
+        let strip = |pages: Vec<(bool, u64, FatPage)>| {
+            pages
+                .into_iter()
+                .map(|(_, pn, page)| (pn, page))
+                .collect::<Vec<_>>()
+        };
+        let mut files = vec![(tables.main.fd.clone(), strip(main_pages))];
+        if let Some(ref resize) = tables.resize {
+            files.push((resize.table.fd.clone(), strip(resized_pages)));
+        }
+        HtWrites {
+            files,
+            num_pages: (
+                tables.main.meta_map.len() as u32,
+                tables
+                    .resize
+                    .as_ref()
+                    .map(|resize| resize.table.meta_map.len() as u32),
+            ),
+            complete_resize,
+            compacted,
+        }
     }
 }
 
+/// A hash-table file along with the pages to write to it and their page numbers.
+type FileWrites = (Arc<File>, Vec<(u64, FatPage)>);
+
+/// The pages to write out to the hash-table files once the manifest is updated.
+struct HtWrites {
+    /// The pages along with their page numbers, per file.
+    files: Vec<FileWrites>,
+    /// The number of buckets of the hash-table and of the one it is being grown into, if any, as
+    /// of the sync.
+    num_pages: (u32, Option<u32>),
+    /// Whether the sync completes a resize, making the file grown into the HT file.
+    complete_resize: bool,
+    /// The changes made by compaction, to apply to the meta-map once the pages are written.
+    compacted: Vec<MetaChange>,
+}
+
 pub struct SyncController {
     db: DB,
     /// The channel to send the result of the WAL writeout. Option is to allow `take`.
     wal_result_tx: Option<Sender<anyhow::Result<()>>>,
     /// The channel to receive the result of the WAL writeout.
     wal_result_rx: Receiver<anyhow::Result<()>>,
-    /// The pages along with their page numbers to write out to the HT file.
-    ht_to_write: Arc<Mutex<Option<Vec<(u64, FatPage)>>>>,
+    /// The pages to write out to the HT files.
+    ht_to_write: Arc<Mutex<Option<HtWrites>>>,
 }
 
 impl SyncController {
@@ -209,6 +795,7 @@ impl SyncController {
     /// Non-blocking.
     pub fn begin_sync(
         &mut self,
+        sync_seqn: u32,
         page_cache: PageCache,
         mut merkle_tx: MerkleTransaction,
         page_diffs: merkle::PageDiffs,
@@ -223,32 +810,47 @@ impl SyncController {
             page_cache.prepare_transaction(page_diffs.into_iter(), &mut merkle_tx);
 
             let mut wal_blob_builder = wal_blob_builder.lock();
-            let ht_pages =
-                bitbox.prepare_sync(&page_pool, merkle_tx.new_pages, &mut *wal_blob_builder);
+            let ht_writes = bitbox.prepare_sync(
+                sync_seqn,
+                &page_pool,
+                &page_cache,
+                merkle_tx.new_pages,
+                &mut wal_blob_builder,
+            );
             drop(wal_blob_builder);
 
-            Self::spawn_wal_writeout(wal_result_tx, bitbox);
+            // Stash the HT pages before the WAL writeout may complete and unblock `post_meta`.
+            *ht_to_write.lock() = Some(ht_writes);
 
-            let mut ht_to_write = ht_to_write.lock();
-            *ht_to_write = Some(ht_pages);
+            Self::spawn_wal_writeout(wal_result_tx, bitbox, sync_seqn);
 
             // evict outside of the critical path.
             page_cache.evict();
         });
     }
 
-    fn spawn_wal_writeout(wal_result_tx: Sender<anyhow::Result<()>>, bitbox: DB) {
+    fn spawn_wal_writeout(wal_result_tx: Sender<anyhow::Result<()>>, bitbox: DB, sync_seqn: u32) {
         let bitbox = bitbox.clone();
         let tp = bitbox.shared.sync_tp.clone();
         tp.execute(move || {
             let wal_blob_builder = bitbox.shared.wal_blob_builder.lock();
             let wal_slice = wal_blob_builder.as_slice();
-            let wal_result = writeout::write_wal(&bitbox.shared.wal_fd, wal_slice);
+            let sinks_write = bitbox
+                .shared
+                .wal_sinks
+                .as_ref()
+                .map(|sinks| sinks.dispatch(sync_seqn, wal_slice));
+            let mut wal_result = bitbox.write_local_wal(sync_seqn, wal_slice);
+            drop(wal_blob_builder);
+            if let Some(sinks_write) = sinks_write {
+                wal_result = wal_result.and_then(|()| sinks_write.wait());
+            }
             let _ = wal_result_tx.send(wal_result);
         });
     }
 
-    /// Wait for the pre-meta WAL file to be written out.
+    /// Wait for the pre-meta WAL file and its mirror, if any, to be written out, and acknowledged by a quorum of WAL
+    /// sinks, if any.
     ///
     /// Must be invoked by the sync thread. Blocking.
     pub fn wait_pre_meta(&self) -> anyhow::Result<()> {
@@ -258,57 +860,134 @@ impl SyncController {
         }
     }
 
-    /// Write out the HT pages and truncate the WAL file.
+    /// Returns the number of buckets of the hash-table and of the one it is being grown into, if
+    /// any, to record in the manifest of the sync.
+    ///
+    /// Has to be called after [`Self::wait_pre_meta`].
+    pub fn num_pages(&self) -> (u32, Option<u32>) {
+        // UNWRAP: the HT pages are stashed before the WAL writeout begins.
+        self.ht_to_write.lock().as_ref().unwrap().num_pages
+    }
+
+    /// Write out the HT pages and truncate the WAL file and its mirror, if any.
+    ///
+    /// If `verify` is true, the HT pages are read back after being written and the WAL file is
+    /// only truncated if they match what was written. Otherwise, an error is returned and the WAL
+    /// is left in place to be replayed on the next open.
     ///
     /// Has to be called after the manifest is updated. Must be invoked by the sync
     /// thread. Blocking.
-    pub fn post_meta(&self, io_handle: IoHandle) -> anyhow::Result<()> {
-        let ht_pages = self.ht_to_write.lock().take().unwrap();
-        writeout::write_ht(io_handle, &self.db.shared.ht_fd, ht_pages)?;
+    pub fn post_meta(&self, io_handle: IoHandle, verify: bool) -> anyhow::Result<()> {
+        let ht_writes = self.ht_to_write.lock().take().unwrap();
+        for (ht_fd, ht_pages) in ht_writes.files {
+            writeout::write_ht(io_handle.clone(), &ht_fd, ht_pages, verify)?;
+        }
+        if !ht_writes.compacted.is_empty() {
+            let mut tables = self.db.shared.tables.write();
+            for change in ht_writes.compacted {
+                change.apply(&mut tables.main.meta_map);
+            }
+        }
+        if ht_writes.complete_resize {
+            resize::finish(&self.db.shared.dir)?;
+        }
         writeout::truncate_wal(&self.db.shared.wal_fd)?;
+        if let Some(ref wal_mirror_fd) = self.db.shared.wal_mirror_fd {
+            writeout::truncate_wal(wal_mirror_fd)?;
+        }
         Ok(())
     }
 }
 
 /// Perform recovery by applying the WAL to the HT file.
+/// Replay the WAL onto the hash-table file.
+///
+/// `wal_fds` are the WAL file and its mirror, if any. The copy with the longest valid prefix is
+/// replayed, so that a copy which was corrupted or only partially written doesn't get in the way.
+///
+/// Replay may be cancelled through the progress callback. In that case, everything replayed so far
+/// is made durable and a checkpoint is written, from which the next replay of the same WAL resumes.
 fn recover(
-    ht_fd: &File,
-    mut wal_fd: &File,
+    tables: &mut Tables,
+    wal_fds: &[&File],
     page_pool: &PagePool,
-    ht_offsets: &HTOffsets,
-    meta_map: &mut MetaMap,
+    cipher: Option<&PageCipher>,
     seed: [u8; 16],
+    replay: &WalReplay,
 ) -> anyhow::Result<()> {
-    use crate::bitbox::wal::WalBlobReader;
-    use std::io::{Seek, SeekFrom};
-
-    wal_fd.seek(SeekFrom::Start(0))?;
-
-    // The indicies of pages (in the metabits page space) that were changed and require updates.
-    // Note those are not ht page numbers yet and still require additional conversion.
+    // The indicies of pages (in the metabits page space) that were changed and require updates,
+    // along with whether they belong to the table being grown into. Note those are not ht page
+    // numbers yet and still require additional conversion.
     let mut changed_meta_page_ixs = HashSet::new();
-    let mut wal_reader = WalBlobReader::new(page_pool, wal_fd)?;
+    let mut wal_reader = load_wal(page_pool, cipher, wal_fds, replay)?;
+    if wal_reader
+        .sync_seqn()
+        .is_some_and(|sync_seqn| sync_seqn != replay.sync_seqn)
+    {
+        // The crash happened before the manifest of the sync was written. The HT file hasn't been
+        // touched by it yet.
+        return finish_recovery(wal_fds, replay);
+    }
 
-    while let Some(entry) = wal_reader.read_entry()? {
+    // Count the records. Parsing is cheap compared to applying them.
+    let mut total = 0;
+    while wal_reader.read_entry()?.is_some() {
+        total += 1;
+    }
+
+    // The checkpoint consists of the hash of the WAL it belongs to, followed by the offset of the
+    // next record and the number of records replayed. Checkpoints of other WALs are ignored.
+    let wal_hash = blake3::hash(wal_reader.contents());
+    let (offset, mut replayed) = match std::fs::read(&replay.checkpoint_path) {
+        Ok(checkpoint) if checkpoint.len() == 48 && checkpoint[..32] == wal_hash.as_bytes()[..] => {
+            (
+                u64::from_le_bytes(checkpoint[32..40].try_into().unwrap()) as usize,
+                u64::from_le_bytes(checkpoint[40..48].try_into().unwrap()),
+            )
+        }
+        _ => (wal_reader.start(), 0),
+    };
+    wal_reader.set_offset(offset);
+
+    let report = |replayed: u64| match replay.progress {
+        Some(ref progress) => progress(WalReplayProgress { replayed, total }),
+        None => ControlFlow::Continue(()),
+    };
+    let mut cancelled = report(replayed).is_break();
+
+    while !cancelled {
+        let Some(entry) = wal_reader.read_entry()? else {
+            break;
+        };
+        // Right after opening, buckets are numbered as in the WAL.
+        let (resized, bucket) = tables.locate(entry.bucket());
+        let table = tables.table_mut(resized);
+        let meta_map = &mut table.meta_map;
         match entry {
-            wal::WalEntry::Clear { bucket } => {
+            wal::WalEntry::Clear { .. } => {
                 meta_map.set_tombstone(bucket as usize);
 
                 // Note that the meta page requires update.
-                changed_meta_page_ixs.insert(meta_map.page_index(bucket as usize));
+                changed_meta_page_ixs.insert((resized, meta_map.page_index(bucket as usize)));
+            }
+            wal::WalEntry::Reclaim { .. } => {
+                meta_map.set_empty(bucket as usize);
+
+                // Note that the meta page requires update.
+                changed_meta_page_ixs.insert((resized, meta_map.page_index(bucket as usize)));
             }
             wal::WalEntry::Update {
                 page_id,
                 page_diff,
                 changed_nodes,
-                bucket,
+                ..
             } => {
                 let hash = hash_raw_page_id(page_id, &seed);
                 let meta_map_changed = meta_map.hint_not_match(bucket as usize, hash);
                 if meta_map_changed {
                     meta_map.set_full(bucket as usize, hash);
                     // Note that the meta page requires update.
-                    changed_meta_page_ixs.insert(meta_map.page_index(bucket as usize));
+                    changed_meta_page_ixs.insert((resized, meta_map.page_index(bucket as usize)));
                 }
 
                 // Apply the diff to the page in the ht file.
@@ -318,9 +997,9 @@ fn recover(
                 // - for each index of a bit in a diff that equals to 1, copy the changed node into
                 //   the page.
                 // - store the changed page.
-                let pn = ht_offsets.data_page_index(bucket);
+                let pn = table.offsets.data_page_index(bucket);
 
-                let mut page = io::read_page(page_pool, ht_fd, pn)?;
+                let mut page = io::read_page(page_pool, cipher, &table.fd, pn)?;
                 if page_diff.count() != changed_nodes.len() {
                     anyhow::bail!(
                         "mismatched number of changed nodes: {} != {}",
@@ -329,40 +1008,99 @@ fn recover(
                     );
                 }
                 page_diff.unpack_changed_nodes(&changed_nodes, &mut page);
+                // The bucket may previously have held a different page.
+                page[PAGE_SIZE - 32..].copy_from_slice(&page_id);
 
-                ht_fd.write_all_at(&page, pn * PAGE_SIZE as u64)?;
+                io::write_page(page_pool, cipher, &table.fd, pn, &page)?;
             }
         }
+
+        replayed += 1;
+        if replayed % WAL_REPLAY_REPORT_INTERVAL == 0 || replayed == total {
+            cancelled = report(replayed).is_break();
+        }
     }
 
     // Now that we have applied all the updates, we know precisely which meta pages have been
     // updated.
     //
     // We now write those pages out to the HT file.
-    for changed_meta_page_ix in changed_meta_page_ixs {
-        unsafe {
-            let page = page_pool.alloc();
-            // SAFETY: page is a fresh allocation from page pool and it's not aliased.
-            let page_data = page.as_mut_slice();
-            page_data[..].copy_from_slice(meta_map.page_slice(changed_meta_page_ix));
-
-            let pn = ht_offsets.meta_bytes_index(changed_meta_page_ix as u64);
-            ht_fd.write_all_at(page_data, pn * PAGE_SIZE as u64)?;
+    for (resized, changed_meta_page_ix) in changed_meta_page_ixs {
+        let table = tables.table(resized);
+        let page = table.meta_map.page_slice(changed_meta_page_ix);
+        let pn = table.offsets.meta_bytes_index(changed_meta_page_ix as u64);
+        io::write_page(page_pool, cipher, &table.fd, pn, page)?;
+    }
 
-            page_pool.dealloc(page);
+    if cancelled {
+        tables.main.fd.sync_all()?;
+        if let Some(ref resize) = tables.resize {
+            resize.table.fd.sync_all()?;
         }
+        let mut checkpoint = wal_hash.as_bytes().to_vec();
+        checkpoint.extend_from_slice(&(wal_reader.offset() as u64).to_le_bytes());
+        checkpoint.extend_from_slice(&replayed.to_le_bytes());
+        let checkpoint_fd = File::create(&replay.checkpoint_path)?;
+        checkpoint_fd.write_all_at(&checkpoint, 0)?;
+        checkpoint_fd.sync_all()?;
+        return Err(WalReplayCancelled.into());
     }
 
-    // Finally, we collapse the WAL file.
-    wal_fd.set_len(0)?;
+    finish_recovery(wal_fds, replay)
+}
+
+/// Collapse the WAL file and its mirror, and remove the replay checkpoint.
+fn finish_recovery(wal_fds: &[&File], replay: &WalReplay) -> anyhow::Result<()> {
+    for wal_fd in wal_fds {
+        wal_fd.set_len(0)?;
+    }
+    match std::fs::remove_file(&replay.checkpoint_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
 
     Ok(())
 }
 
+/// Load the copy of the WAL with the longest valid prefix, preferring copies produced by the last
+/// sync of the manifest. Ties go to the earlier copy.
+///
+/// Copies which can't be loaded at all are skipped, unless none can be loaded.
+fn load_wal(
+    page_pool: &PagePool,
+    cipher: Option<&PageCipher>,
+    wal_fds: &[&File],
+    replay: &WalReplay,
+) -> anyhow::Result<wal::WalBlobReader> {
+    let mut best: Option<((bool, usize), wal::WalBlobReader)> = None;
+    let mut first_err = None;
+    for wal_fd in wal_fds {
+        match wal::WalBlobReader::new(page_pool, cipher, wal_fd, replay.wal_header) {
+            Ok(mut reader) => {
+                let current = reader
+                    .sync_seqn()
+                    .is_none_or(|sync_seqn| sync_seqn == replay.sync_seqn);
+                let rank = (current, reader.valid_prefix_len());
+                if best.as_ref().is_none_or(|(best_rank, _)| rank > *best_rank) {
+                    best = Some((rank, reader));
+                }
+            }
+            Err(e) => {
+                first_err.get_or_insert(e);
+            }
+        }
+    }
+    match (best, first_err) {
+        (Some((_, reader)), _) => Ok(reader),
+        (None, Some(e)) => Err(e),
+        (None, None) => anyhow::bail!("no WAL to recover from"),
+    }
+}
+
 /// A utility for loading pages from bitbox.
 pub struct PageLoader {
     shared: Arc<Shared>,
-    meta_map: ArcRwLockReadGuard<parking_lot::RawRwLock, MetaMap>,
+    tables: ArcRwLockReadGuard<parking_lot::RawRwLock, Tables>,
     io_handle: IoHandle,
 }
 
@@ -371,15 +1109,21 @@ impl PageLoader {
     pub fn new(db: &DB, io_handle: IoHandle) -> Self {
         PageLoader {
             shared: db.shared.clone(),
-            meta_map: RwLock::read_arc(&db.shared.meta_map),
+            tables: RwLock::read_arc(&db.shared.tables),
             io_handle,
         }
     }
 
     /// Create a new page load.
     pub fn start_load(&self, page_id: PageId) -> PageLoad {
+        self.shared.page_loads.fetch_add(1, Ordering::Relaxed);
+        // While resizing, probe the table being grown into first, as it receives all new pages.
+        let resized = self.tables.resize.is_some();
+        let meta_map = &self.tables.table(resized).meta_map;
         PageLoad {
-            probe_sequence: ProbeSequence::new(&page_id, &self.meta_map, &self.shared.seed),
+            probe_sequence: ProbeSequence::new(&page_id, meta_map, &self.shared.seed),
+            resized,
+            bucket_index: BucketIndex(0),
             page_id,
             state: PageLoadState::Pending,
         }
@@ -393,19 +1137,40 @@ impl PageLoader {
     /// This returns `Ok(true)` if the page request has been submitted and a completion will be
     /// coming. `Ok(false)` means that the page is guaranteed to be fresh.
     pub fn advance(&self, load: &mut PageLoad, user_data: u64) -> anyhow::Result<bool> {
+        let mut probed = 0;
         let bucket = loop {
-            match load.probe_sequence.next(&self.meta_map) {
+            let meta_map = &self.tables.table(load.resized).meta_map;
+            let probes_before = load.probe_sequence.step;
+            let result = load.probe_sequence.next(meta_map);
+            probed += load.probe_sequence.step - probes_before;
+            match result {
                 ProbeResult::Tombstone(_) => continue,
-                ProbeResult::Empty(_) => return Ok(false),
-                ProbeResult::PossibleHit(bucket) => break BucketIndex(bucket),
+                ProbeResult::Empty(_) if load.resized => {
+                    // Not in the table being grown into. Go on with the old table.
+                    load.resized = false;
+                    load.probe_sequence = ProbeSequence::from_hash(
+                        load.probe_sequence.hash,
+                        &self.tables.main.meta_map,
+                    );
+                }
+                ProbeResult::Empty(_) => break None,
+                ProbeResult::PossibleHit(bucket) => break Some(bucket),
             }
         };
+        self.shared
+            .probed_buckets
+            .fetch_add(probed, Ordering::Relaxed);
+        let Some(bucket) = bucket else {
+            return Ok(false);
+        };
 
-        let data_page_index = self.shared.store.data_page_index(bucket.0);
+        let table = self.tables.table(load.resized);
+        let data_page_index = table.offsets.data_page_index(bucket);
+        load.bucket_index = self.tables.bucket_index(load.resized, bucket);
 
         let page = self.io_handle.page_pool().alloc_fat_page();
         let command = IoCommand {
-            kind: IoKind::Read(self.shared.ht_fd.as_raw_fd(), data_page_index, page),
+            kind: IoKind::Read(table.fd.as_raw_fd(), data_page_index, page),
             user_data,
         };
 
@@ -479,7 +1244,7 @@ impl PageLoadCompletion {
     pub fn apply_to(self, load: &mut PageLoad) -> Option<(FatPage, BucketIndex)> {
         assert!(load.needs_completion());
         if self.page[PAGE_SIZE - 32..] == load.page_id.encode() {
-            Some((self.page, BucketIndex(load.probe_sequence.bucket())))
+            Some((self.page, load.bucket_index))
         } else {
             load.state = PageLoadState::Pending;
             None
@@ -490,6 +1255,10 @@ impl PageLoadCompletion {
 pub struct PageLoad {
     page_id: PageId,
     probe_sequence: ProbeSequence,
+    /// Whether the probe sequence is that of the table being grown into.
+    resized: bool,
+    /// The bucket index of the bucket read by the last submitted request.
+    bucket_index: BucketIndex,
     state: PageLoadState,
 }
 
@@ -521,17 +1290,22 @@ impl BucketAllocator {
     ///
     /// `allocate` and `free` must be called in the same order that items are passed to `commit`,
     /// or pages may silently disappear later.
+    ///
+    /// While the hash-table is being resized, pages are allocated in the table being grown into.
     pub fn allocate(&mut self, page_id: PageId) -> BucketIndex {
-        let meta_map = self.shared.meta_map.read();
-        let mut probe_seq = ProbeSequence::new(&page_id, &meta_map, &self.shared.seed);
+        let tables = self.shared.tables.read();
+        let resized = tables.resize.is_some();
+        let meta_map = &tables.table(resized).meta_map;
+        let mut probe_seq = ProbeSequence::new(&page_id, meta_map, &self.shared.seed);
 
         let mut i = 0;
         loop {
             i += 1;
-            assert!(i < 10000, "hash-table full");
-            match probe_seq.next(&meta_map) {
+            assert!(i < MAX_PROBES, "hash-table full");
+            match probe_seq.next(meta_map) {
                 ProbeResult::PossibleHit(_) => continue,
                 ProbeResult::Tombstone(bucket) | ProbeResult::Empty(bucket) => {
+                    let BucketIndex(bucket) = tables.bucket_index(resized, bucket);
                     // unless some other page has taken the bucket, fill it.
                     if self.changed_buckets.get(&bucket).map_or(true, |full| !full) {
                         self.changed_buckets.insert(bucket, true);
@@ -542,6 +1316,13 @@ impl BucketAllocator {
         }
     }
 
+    /// Whether the bucket belongs to a hash-table being resized away from. Pages written to such
+    /// a bucket are moved to the table being grown into instead.
+    pub fn is_retiring(&self, bucket_index: BucketIndex) -> bool {
+        let tables = self.shared.tables.read();
+        tables.resize.is_some() && !tables.locate(bucket_index.0).0
+    }
+
     /// Free a bucket which is known to be occupied by the given page ID.
     pub fn free(&mut self, bucket_index: BucketIndex) {
         self.changed_buckets.insert(bucket_index.0, false);
@@ -576,7 +1357,10 @@ enum ProbeResult {
 
 impl ProbeSequence {
     fn new(page_id: &PageId, meta_map: &MetaMap, seed: &[u8; 16]) -> Self {
-        let hash = hash_page_id(page_id, seed);
+        Self::from_hash(hash_page_id(page_id, seed), meta_map)
+    }
+
+    fn from_hash(hash: u64, meta_map: &MetaMap) -> Self {
         Self {
             hash,
             bucket: hash % meta_map.len() as u64,
@@ -584,13 +1368,19 @@ impl ProbeSequence {
         }
     }
 
+    // advance to the next bucket in the sequence, regardless of its contents
+    fn next_bucket(&mut self, meta_map: &MetaMap) -> u64 {
+        // Triangular probing
+        self.bucket += self.step;
+        self.step += 1;
+        self.bucket %= meta_map.len() as u64;
+        self.bucket
+    }
+
     // probe until there is a possible hit or an empty bucket is found
     fn next(&mut self, meta_map: &MetaMap) -> ProbeResult {
         loop {
-            // Triangular probing
-            self.bucket += self.step;
-            self.step += 1;
-            self.bucket %= meta_map.len() as u64;
+            self.next_bucket(meta_map);
 
             if meta_map.hint_empty(self.bucket as usize) {
                 return ProbeResult::Empty(self.bucket);
@@ -607,8 +1397,4 @@ impl ProbeSequence {
             return ProbeResult::PossibleHit(self.bucket);
         }
     }
-
-    fn bucket(&self) -> u64 {
-        self.bucket
-    }
 }
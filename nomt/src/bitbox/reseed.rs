@@ -0,0 +1,143 @@
+//! Redistribution of the hash-table pages according to a new seed.
+//!
+//! The pages are first laid out according to the new seed in a separate file next to the HT file.
+//! Once that file is complete, a marker holding the new seed is written. From then on, the reseed
+//! is carried to completion, across crashes if need be: the file is copied over the HT file, the
+//! manifest is updated with the new seed and finally the file and the marker are removed.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Read as _, Write as _},
+    os::unix::fs::FileExt as _,
+    path::Path,
+};
+
+use super::{
+    hash_raw_page_id, ht_file, meta_map::MetaMap, ProbeResult, ProbeSequence, Shared, MAX_PROBES,
+};
+use crate::io::{self, PagePool, PAGE_SIZE};
+
+/// The file holding the hash-table laid out according to the new seed.
+const RESEED_FILE: &str = "ht.reseed";
+/// The file holding the new seed. Its presence means that [`RESEED_FILE`] is complete.
+const MARKER_FILE: &str = "ht.reseed.seed";
+
+/// Lay out the pages of the hash-table according to the given seed in a file next to the HT file.
+pub(super) fn prepare(shared: &Shared, dir: &Path, seed: [u8; 16]) -> anyhow::Result<()> {
+    let tables = shared.tables.read();
+    let table = &tables.main;
+    let meta_map = &table.meta_map;
+    let num_pages = meta_map.len() as u32;
+    let num_meta_byte_pages = ht_file::num_meta_byte_pages(num_pages) as usize;
+    let mut new_meta_map =
+        MetaMap::from_bytes(vec![0; num_meta_byte_pages * PAGE_SIZE], num_pages as usize);
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dir.join(RESEED_FILE))?;
+    file.set_len(ht_file::expected_file_len(num_pages))?;
+
+    for bucket in 0..meta_map.len() {
+        if meta_map.hint_empty(bucket) || meta_map.hint_tombstone(bucket) {
+            continue;
+        }
+
+        let pn = table.offsets.data_page_index(bucket as u64);
+        let page = io::read_page(&shared.page_pool, shared.cipher(), &table.fd, pn)?;
+        // UNWRAP: the slice is 32 bytes long.
+        let hash = hash_raw_page_id(page[PAGE_SIZE - 32..].try_into().unwrap(), &seed);
+
+        // There are no tombstones in the new hash-table, so the page goes into the first empty
+        // bucket of its probe sequence.
+        let mut probe_seq = ProbeSequence::from_hash(hash, &new_meta_map);
+        let mut i = 0;
+        let new_bucket = loop {
+            i += 1;
+            assert!(i < MAX_PROBES, "hash-table full");
+            if let ProbeResult::Empty(new_bucket) = probe_seq.next(&new_meta_map) {
+                break new_bucket;
+            }
+        };
+        new_meta_map.set_full(new_bucket as usize, hash);
+
+        let new_pn = table.offsets.data_page_index(new_bucket);
+        io::write_page(&shared.page_pool, shared.cipher(), &file, new_pn, &page)?;
+    }
+
+    for page_index in 0..num_meta_byte_pages {
+        let page = new_meta_map.page_slice(page_index);
+        let pn = table.offsets.meta_bytes_index(page_index as u64);
+        io::write_page(&shared.page_pool, shared.cipher(), &file, pn, page)?;
+    }
+    file.sync_all()?;
+
+    let mut marker = File::create(dir.join(MARKER_FILE))?;
+    marker.write_all(&seed)?;
+    marker.sync_all()?;
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Whether a reseed is in progress or has left leftovers, without dealing with them.
+pub fn in_progress(dir: &Path) -> bool {
+    dir.join(MARKER_FILE).exists()
+}
+
+/// Returns the seed of a reseed which has been prepared but not finished, if any.
+///
+/// Discards the leftovers of a reseed which was interrupted before it had been prepared.
+pub fn pending(dir: &Path) -> anyhow::Result<Option<[u8; 16]>> {
+    let mut seed = Vec::new();
+    match File::open(dir.join(MARKER_FILE)) {
+        Ok(mut marker) => {
+            marker.read_to_end(&mut seed)?;
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    match seed.try_into() {
+        Ok(seed) => Ok(Some(seed)),
+        Err(_) => {
+            // The marker is missing or was not completely written.
+            finish(dir)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Copy the prepared hash-table over the HT file.
+///
+/// This may be repeated any number of times until the reseed is finished. Pages are copied as they
+/// are stored, so encrypted pages are copied without being decrypted.
+pub fn apply(dir: &Path, page_pool: &PagePool, ht_fd: &File) -> anyhow::Result<()> {
+    let file = File::open(dir.join(RESEED_FILE))?;
+    let num_pages = file.metadata()?.len() / PAGE_SIZE as u64;
+    if ht_fd.metadata()?.len() != num_pages * PAGE_SIZE as u64 {
+        anyhow::bail!("Store corrupted; reseeded hash-table has unexpected length");
+    }
+    for pn in 0..num_pages {
+        let page = io::read_page(page_pool, None, &file, pn)?;
+        ht_fd.write_all_at(&page, pn * PAGE_SIZE as u64)?;
+    }
+    ht_fd.sync_all()?;
+    Ok(())
+}
+
+/// Remove the files of a reseed once the new seed has been persisted in the manifest.
+pub fn finish(dir: &Path) -> anyhow::Result<()> {
+    remove_if_exists(&dir.join(MARKER_FILE))?;
+    remove_if_exists(&dir.join(RESEED_FILE))?;
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,225 @@
+//! Online growth of the hash-table.
+//!
+//! The bucket of a page depends on the number of buckets, so growing the hash-table means moving
+//! its pages. Instead of laying out a new hash-table in one go while the database is unavailable,
+//! the pages are moved incrementally into a larger hash-table kept in a file next to the HT file:
+//!
+//! - New pages are allocated in the new table, and pages written by a commit are moved to it along
+//!   with the write.
+//! - On every sync, a bounded number of the remaining pages of the old table are moved as well.
+//! - Page loads probe the new table and then the old one. Every page is in exactly one of them.
+//!
+//! Once the old table holds no pages anymore, the next sync makes the new table the main one. Its
+//! manifest records the new number of buckets and no resize in progress, after which the file of
+//! the new table is renamed over the HT file.
+//!
+//! The same applies to shrinking the hash-table, as long as the smaller one has room for all pages.
+//!
+//! Bucket indices keep referring to the same bucket throughout, see [`super::Tables`], so that the
+//! buckets known to the page cache remain valid. In the WAL, buckets of the new table follow those
+//! of the old table.
+
+use std::{
+    fs::{File, OpenOptions},
+    path::Path,
+    sync::Arc,
+};
+
+use nomt_core::page_id::PageId;
+
+use super::{
+    compact::decode_stored_page_id, hash_raw_page_id, ht_file, ProbeResult, ProbeSequence, Shared,
+    Table, Tables, MAX_PROBES,
+};
+use crate::{
+    background_error::{BackgroundErrorSource, BackgroundErrors},
+    io::{self, FatPage, PageCipher, PagePool, PAGE_SIZE},
+};
+
+/// The file holding the hash-table being grown into.
+pub const RESIZE_FILE: &str = "ht.resize";
+
+/// A resize in progress.
+pub(super) struct Resize {
+    /// The hash-table being grown into.
+    pub(super) table: Table,
+    /// The next bucket of the old table to move the page of.
+    cursor: u64,
+}
+
+/// A page moved from the old table to the new one. The meta-maps have already been updated.
+pub(super) struct Migration {
+    pub(super) page_id: PageId,
+    pub(super) page: FatPage,
+    /// The bucket of the old table the page was moved from.
+    pub(super) from: u64,
+    /// The bucket of the new table the page was moved to.
+    pub(super) to: u64,
+}
+
+impl Resize {
+    pub(super) fn new(table: Table) -> Self {
+        Resize { table, cursor: 0 }
+    }
+
+    /// Move up to `budget` pages from the old table to the new one.
+    ///
+    /// The pages written in the current sync have been moved already, so the remaining pages of
+    /// the old table are up to date on disk. Moving pages is best-effort: if a page can't be read,
+    /// the step ends early, the error is reported as a background error and the page is retried
+    /// with the next sync.
+    pub(super) fn step(
+        &mut self,
+        shared: &Shared,
+        old: &mut Table,
+        budget: usize,
+    ) -> Vec<Migration> {
+        let mut migrations = Vec::new();
+        while migrations.len() < budget && old.meta_map.full_count() > 0 {
+            if self.cursor == old.meta_map.len() as u64 {
+                self.cursor = 0;
+            }
+            let bucket = self.cursor;
+            if old.meta_map.hint_empty(bucket as usize)
+                || old.meta_map.hint_tombstone(bucket as usize)
+            {
+                self.cursor += 1;
+                continue;
+            }
+
+            match self.migrate(shared, old, bucket) {
+                Ok(migration) => migrations.push(migration),
+                Err(e) => {
+                    shared
+                        .io_pool
+                        .background_errors()
+                        .report(BackgroundErrorSource::HashTableResize, e);
+                    break;
+                }
+            }
+            self.cursor += 1;
+        }
+        migrations
+    }
+
+    fn migrate(
+        &mut self,
+        shared: &Shared,
+        old: &mut Table,
+        from: u64,
+    ) -> anyhow::Result<Migration> {
+        let pn = old.offsets.data_page_index(from);
+        let page = io::read_page(&shared.page_pool, shared.cipher(), &old.fd, pn)?;
+        // UNWRAP: slice is exactly 32 bytes.
+        let raw_page_id: [u8; 32] = page[PAGE_SIZE - 32..].try_into().unwrap();
+        let hash = hash_raw_page_id(raw_page_id, &shared.seed);
+        if old.meta_map.hint_not_match(from as usize, hash) {
+            anyhow::bail!("bucket {from} holds an unexpected page");
+        }
+        let Some(page_id) = decode_stored_page_id(raw_page_id) else {
+            anyhow::bail!("bucket {from} holds an invalid page ID");
+        };
+
+        let meta_map = &mut self.table.meta_map;
+        let mut probe_seq = ProbeSequence::from_hash(hash, meta_map);
+        let mut i = 0;
+        let to = loop {
+            i += 1;
+            if i >= MAX_PROBES {
+                anyhow::bail!("hash-table full");
+            }
+            match probe_seq.next(meta_map) {
+                ProbeResult::PossibleHit(_) => continue,
+                ProbeResult::Tombstone(bucket) | ProbeResult::Empty(bucket) => break bucket,
+            }
+        };
+        meta_map.set_full(to as usize, hash);
+        old.meta_map.set_tombstone(from as usize);
+
+        Ok(Migration {
+            page_id,
+            page,
+            from,
+            to,
+        })
+    }
+}
+
+/// Create the file of a hash-table with the given number of buckets to grow into.
+pub(super) fn create(
+    dir: &Path,
+    page_pool: &PagePool,
+    cipher: Option<&PageCipher>,
+    num_pages: u32,
+    background_errors: &BackgroundErrors,
+) -> anyhow::Result<Table> {
+    let path = dir.join(RESIZE_FILE);
+    remove_if_exists(&path)?;
+    ht_file::create_table(&path, num_pages, true, None, background_errors)?;
+    File::open(dir)?.sync_all()?;
+    let fd = OpenOptions::new().read(true).write(true).open(&path)?;
+    open(page_pool, cipher, num_pages, fd)
+}
+
+/// Open the file of the hash-table being grown into.
+pub(super) fn open(
+    page_pool: &PagePool,
+    cipher: Option<&PageCipher>,
+    num_pages: u32,
+    fd: File,
+) -> anyhow::Result<Table> {
+    let (offsets, meta_map) = ht_file::open(num_pages, page_pool, cipher, &fd)?;
+    Ok(Table {
+        meta_map,
+        offsets,
+        fd: Arc::new(fd),
+    })
+}
+
+/// Make the new table the main one if all pages have been moved out of the old table.
+///
+/// Returns whether it did.
+pub(super) fn complete(tables: &mut Tables) -> bool {
+    if tables.resize.is_none() || tables.main.meta_map.full_count() > 0 {
+        return false;
+    }
+    // UNWRAP: checked above.
+    let resize = tables.resize.take().unwrap();
+    tables.base += tables.main.meta_map.len() as u64;
+    tables.main = resize.table;
+    true
+}
+
+/// Rename the file of the new table over the HT file, once the manifest records the completed
+/// resize.
+pub(super) fn finish(dir: &Path) -> anyhow::Result<()> {
+    std::fs::rename(dir.join(RESIZE_FILE), dir.join("ht"))?;
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Deal with the file of a resize which the manifest doesn't record as in progress, if any.
+///
+/// Either the resize was completed by the last sync and the file was not renamed over the HT file
+/// yet, or it was started after the last sync and is abandoned. The HT file tells them apart, as
+/// it has the number of buckets recorded in the manifest only in the latter case.
+pub fn settle(dir: &Path, num_pages: u32) -> anyhow::Result<()> {
+    let path = dir.join(RESIZE_FILE);
+    if !path.exists() {
+        return Ok(());
+    }
+    if std::fs::metadata(dir.join("ht"))?.len() == ht_file::expected_file_len(num_pages) {
+        std::fs::remove_file(&path)?;
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    } else {
+        finish(dir)
+    }
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -1,11 +1,18 @@
 const WAL_ENTRY_TAG_END: u8 = 0;
 const WAL_ENTRY_TAG_CLEAR: u8 = 1;
 const WAL_ENTRY_TAG_UPDATE: u8 = 2;
+const WAL_ENTRY_TAG_RECLAIM: u8 = 3;
+
+/// The size of the header preceding the entries, which holds the sequence number of the sync the
+/// WAL was produced by. Introduced in version 7 of the manifest.
+pub const WAL_HEADER_SIZE: usize = 4;
 
 pub use read::{WalBlobReader, WalEntry};
+pub use sink::{WalSink, WalSinks};
 pub use write::WalBlobBuilder;
 
 mod read;
+mod sink;
 mod write;
 
 #[cfg(test)]
@@ -1,8 +1,11 @@
 //! The read-path for the WAL.
 
-use super::{WAL_ENTRY_TAG_CLEAR, WAL_ENTRY_TAG_END, WAL_ENTRY_TAG_UPDATE};
+use super::{
+    WAL_ENTRY_TAG_CLEAR, WAL_ENTRY_TAG_END, WAL_ENTRY_TAG_RECLAIM, WAL_ENTRY_TAG_UPDATE,
+    WAL_HEADER_SIZE,
+};
 use crate::{
-    io::{self, PagePool, PAGE_SIZE},
+    io::{self, PageCipher, PagePool, PAGE_SIZE},
     page_diff::PageDiff,
 };
 use anyhow::bail;
@@ -26,10 +29,27 @@ pub enum WalEntry {
         /// The bucket index which is being cleared.
         bucket: u64,
     },
+    Reclaim {
+        /// The index of the tombstoned bucket which is being made empty again.
+        bucket: u64,
+    },
+}
+
+impl WalEntry {
+    /// The bucket index the entry applies to.
+    pub fn bucket(&self) -> u64 {
+        match *self {
+            WalEntry::Update { bucket, .. }
+            | WalEntry::Clear { bucket }
+            | WalEntry::Reclaim { bucket } => bucket,
+        }
+    }
 }
 
 pub struct WalBlobReader {
     wal: Vec<u8>,
+    /// The size of the header preceding the entries, 0 for a WAL without one.
+    header_size: usize,
     offset: usize,
 }
 
@@ -37,8 +57,14 @@ impl WalBlobReader {
     /// Creates a new WAL blob reader.
     ///
     /// The `wal_fd` is expected to be positioned at the start of the WAL file. The file must be
-    /// a multiple of the page size.
-    pub fn new(page_pool: &PagePool, mut wal_fd: &File) -> anyhow::Result<Self> {
+    /// a multiple of the page size. `header` tells whether the WAL starts with the sequence number
+    /// of its sync, see [`crate::bitbox::WalReplay::wal_header`].
+    pub fn new(
+        page_pool: &PagePool,
+        cipher: Option<&PageCipher>,
+        mut wal_fd: &File,
+        header: bool,
+    ) -> anyhow::Result<Self> {
         let stat = wal_fd.metadata()?;
         let file_size = stat.len() as usize;
         if file_size % PAGE_SIZE != 0 {
@@ -52,7 +78,7 @@ impl WalBlobReader {
         let mut wal = Vec::with_capacity(file_size);
         let mut pn = 0;
         loop {
-            let page = match io::read_page(page_pool, wal_fd, pn) {
+            let page = match io::read_page(page_pool, cipher, wal_fd, pn) {
                 Ok(page) => page,
                 Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
                 Err(e) => return Err(e.into()),
@@ -60,8 +86,60 @@ impl WalBlobReader {
             pn += 1;
             wal.extend_from_slice(&*page);
         }
+        let header_size = if header { WAL_HEADER_SIZE } else { 0 };
+        if wal.len() <= header_size {
+            anyhow::bail!("WAL file is empty");
+        }
+
+        Ok(Self {
+            wal,
+            header_size,
+            offset: header_size,
+        })
+    }
+
+    /// The sequence number of the sync which produced the WAL, `None` if the WAL has no header.
+    pub fn sync_seqn(&self) -> Option<u32> {
+        let header = self.wal[..self.header_size].try_into().ok()?;
+        Some(u32::from_le_bytes(header))
+    }
 
-        Ok(Self { wal, offset: 0 })
+    /// The offset of the first entry within the WAL file.
+    pub fn start(&self) -> usize {
+        self.header_size
+    }
+
+    /// The contents of the WAL file.
+    pub fn contents(&self) -> &[u8] {
+        &self.wal
+    }
+
+    /// The offset of the next entry within the WAL file.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Continue reading at the given offset, which must be the start of an entry.
+    pub fn set_offset(&mut self, offset: usize) {
+        self.offset = offset;
+    }
+
+    /// The length of the longest prefix of the WAL file consisting of well-formed entries,
+    /// including the end marker, if reached.
+    ///
+    /// Leaves the reader at the first entry.
+    pub fn valid_prefix_len(&mut self) -> usize {
+        self.offset = self.header_size;
+        let valid_len = loop {
+            let start = self.offset;
+            match self.read_entry() {
+                Ok(Some(_)) => continue,
+                Ok(None) => break self.offset,
+                Err(_) => break start,
+            }
+        };
+        self.offset = self.header_size;
+        valid_len
     }
 
     /// Reads the next entry from the WAL file.
@@ -75,6 +153,10 @@ impl WalBlobReader {
                 let bucket = self.read_u64()?;
                 Ok(Some(WalEntry::Clear { bucket }))
             }
+            WAL_ENTRY_TAG_RECLAIM => {
+                let bucket = self.read_u64()?;
+                Ok(Some(WalEntry::Reclaim { bucket }))
+            }
             WAL_ENTRY_TAG_UPDATE => {
                 let page_id: [u8; 32] = self.read_buf()?;
                 let page_diff: [u8; 16] = self.read_buf()?;
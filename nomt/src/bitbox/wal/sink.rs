@@ -0,0 +1,118 @@
+//! Streaming of WAL blobs to external sinks.
+
+use crossbeam_channel::Receiver;
+use std::sync::Arc;
+use threadpool::ThreadPool;
+
+use crate::background_error::{BackgroundErrorSource, BackgroundErrors};
+
+/// A destination to which the WAL is streamed on every commit, in addition to the local WAL file.
+///
+/// The WAL only covers the hash-table pages of the merkle trie. Values are written to the b-tree
+/// files directly and never reach a sink, so a sink is not a replica of the database state and
+/// gives no remote durability: the database can't be recovered from what the sinks store.
+pub trait WalSink: Send + Sync {
+    /// Durably store the WAL blob produced by the sync with the given sequence number.
+    ///
+    /// Returning `Ok` acknowledges that the blob is durable. This is called from a background
+    /// thread and may block.
+    fn write(&self, sync_seqn: u32, wal_blob: &[u8]) -> anyhow::Result<()>;
+}
+
+/// A set of [`WalSink`]s along with the number of acknowledgements a WAL writeout requires.
+pub struct WalSinks {
+    sinks: Vec<Arc<dyn WalSink>>,
+    quorum: usize,
+    tp: ThreadPool,
+    background_errors: BackgroundErrors,
+}
+
+impl WalSinks {
+    /// Create a new set of sinks. Returns `None` if there are no sinks.
+    ///
+    /// Every failed write is reported as a background error, whether or not the quorum is reached.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the quorum exceeds the number of sinks.
+    pub fn new(
+        sinks: Vec<Arc<dyn WalSink>>,
+        quorum: usize,
+        background_errors: BackgroundErrors,
+    ) -> Option<Self> {
+        assert!(quorum <= sinks.len());
+        if sinks.is_empty() {
+            return None;
+        }
+        let tp = threadpool::Builder::new()
+            .num_threads(sinks.len())
+            .thread_name("nomt-wal-sink".to_string())
+            .build();
+        Some(Self {
+            sinks,
+            quorum,
+            tp,
+            background_errors,
+        })
+    }
+
+    /// Start writing the WAL blob to all sinks. Non-blocking.
+    pub fn dispatch(&self, sync_seqn: u32, wal_blob: &[u8]) -> PendingWrite {
+        let wal_blob: Arc<[u8]> = wal_blob.into();
+        let (tx, rx) = crossbeam_channel::bounded(self.sinks.len());
+        for (index, sink) in self.sinks.iter().enumerate() {
+            let sink = sink.clone();
+            let wal_blob = wal_blob.clone();
+            let tx = tx.clone();
+            let background_errors = self.background_errors.clone();
+            self.tp.execute(move || {
+                let result = sink.write(sync_seqn, &wal_blob);
+                if let Err(ref e) = result {
+                    background_errors.report(
+                        BackgroundErrorSource::WalSink { index, sync_seqn },
+                        anyhow::anyhow!("{e:#}"),
+                    );
+                }
+                let _ = tx.send(result);
+            });
+        }
+        PendingWrite {
+            rx,
+            sinks: self.sinks.len(),
+            quorum: self.quorum,
+        }
+    }
+}
+
+/// A WAL writeout to a set of sinks which is in progress.
+pub struct PendingWrite {
+    rx: Receiver<anyhow::Result<()>>,
+    sinks: usize,
+    quorum: usize,
+}
+
+impl PendingWrite {
+    /// Block until a quorum of sinks acknowledged the write, or until a quorum became impossible.
+    ///
+    /// Sinks which have not responded by then keep writing in the background.
+    pub fn wait(self) -> anyhow::Result<()> {
+        let mut acks = 0;
+        let mut failures = 0;
+        while acks < self.quorum {
+            // UNWRAP: every sink sends exactly one result, and we stop before receiving them all.
+            match self.rx.recv().unwrap() {
+                Ok(()) => acks += 1,
+                Err(e) => {
+                    failures += 1;
+                    if self.sinks - failures < self.quorum {
+                        return Err(e.context(format!(
+                            "WAL sink quorum not reached: {} of {} sinks failed, {} required",
+                            failures, self.sinks, self.quorum
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
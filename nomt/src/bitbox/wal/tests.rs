@@ -1,6 +1,6 @@
-use super::{WalBlobBuilder, WalBlobReader, WalEntry};
-use crate::{io::page_pool::PagePool, page_diff::PageDiff};
-use std::{fs::OpenOptions, io::Write as _};
+use super::{WalBlobBuilder, WalBlobReader, WalEntry, WalSink, WalSinks, WAL_HEADER_SIZE};
+use crate::{background_error::BackgroundErrors, io::page_pool::PagePool, page_diff::PageDiff};
+use std::{fs::OpenOptions, io::Write as _, sync::Arc};
 
 #[test]
 fn test_write_read() {
@@ -14,6 +14,7 @@ fn test_write_read() {
     };
 
     let mut builder = WalBlobBuilder::new().unwrap();
+    builder.reset(7);
     builder.write_clear(0);
     builder.write_update(
         [0; 32],
@@ -46,12 +47,14 @@ fn test_write_read() {
         (0..126).map(|x| [x; 32]),
         2,
     );
+    builder.write_reclaim(3);
     builder.finalize();
     wal_fd.write_all(builder.as_slice()).unwrap();
     wal_fd.sync_data().unwrap();
 
     let page_pool = PagePool::new();
-    let mut reader = WalBlobReader::new(&page_pool, &wal_fd).unwrap();
+    let mut reader = WalBlobReader::new(&page_pool, None, &wal_fd, true).unwrap();
+    assert_eq!(reader.sync_seqn(), Some(7));
     assert_eq!(
         reader.read_entry().unwrap(),
         Some(WalEntry::Clear { bucket: 0 })
@@ -97,5 +100,88 @@ fn test_write_read() {
             bucket: 2,
         })
     );
+    assert_eq!(
+        reader.read_entry().unwrap(),
+        Some(WalEntry::Reclaim { bucket: 3 })
+    );
     assert_eq!(reader.read_entry().unwrap(), None);
 }
+
+#[test]
+fn test_read_headerless() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let mut wal_fd = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(tempdir.path().join("wal"))
+        .unwrap();
+
+    // WALs written before the header was introduced start with the first entry.
+    let mut builder = WalBlobBuilder::new().unwrap();
+    builder.reset(7);
+    builder.write_clear(5);
+    builder.write_reclaim(6);
+    builder.finalize();
+    let blob = builder.as_slice();
+    wal_fd.write_all(&blob[WAL_HEADER_SIZE..]).unwrap();
+    wal_fd.write_all(&[0; WAL_HEADER_SIZE]).unwrap();
+    wal_fd.sync_data().unwrap();
+
+    let page_pool = PagePool::new();
+    let mut reader = WalBlobReader::new(&page_pool, None, &wal_fd, false).unwrap();
+    assert_eq!(reader.sync_seqn(), None);
+    assert_eq!(
+        reader.read_entry().unwrap(),
+        Some(WalEntry::Clear { bucket: 5 })
+    );
+    assert_eq!(
+        reader.read_entry().unwrap(),
+        Some(WalEntry::Reclaim { bucket: 6 })
+    );
+    assert_eq!(reader.read_entry().unwrap(), None);
+}
+
+struct TestSink {
+    fail: bool,
+}
+
+impl WalSink for TestSink {
+    fn write(&self, sync_seqn: u32, wal_blob: &[u8]) -> anyhow::Result<()> {
+        assert_eq!(sync_seqn, 7);
+        assert_eq!(wal_blob, &[1, 2, 3]);
+        if self.fail {
+            anyhow::bail!("sink failure");
+        }
+        Ok(())
+    }
+}
+
+fn sinks(outcomes: &[bool], quorum: usize) -> WalSinks {
+    let sinks = outcomes
+        .iter()
+        .map(|ok| Arc::new(TestSink { fail: !ok }) as Arc<dyn WalSink>)
+        .collect();
+    WalSinks::new(sinks, quorum, BackgroundErrors::default()).unwrap()
+}
+
+#[test]
+fn wal_sinks_quorum() {
+    assert!(sinks(&[true, true, true], 3)
+        .dispatch(7, &[1, 2, 3])
+        .wait()
+        .is_ok());
+    assert!(sinks(&[true, false, true], 2)
+        .dispatch(7, &[1, 2, 3])
+        .wait()
+        .is_ok());
+    assert!(sinks(&[false, false, true], 2)
+        .dispatch(7, &[1, 2, 3])
+        .wait()
+        .is_err());
+    assert!(sinks(&[false, false], 0)
+        .dispatch(7, &[1, 2, 3])
+        .wait()
+        .is_ok());
+    assert!(WalSinks::new(Vec::new(), 0, BackgroundErrors::default()).is_none());
+}
@@ -1,6 +1,9 @@
 //! The write-path for the WAL.
 
-use super::{WAL_ENTRY_TAG_CLEAR, WAL_ENTRY_TAG_END, WAL_ENTRY_TAG_UPDATE};
+use super::{
+    WAL_ENTRY_TAG_CLEAR, WAL_ENTRY_TAG_END, WAL_ENTRY_TAG_RECLAIM, WAL_ENTRY_TAG_UPDATE,
+    WAL_HEADER_SIZE,
+};
 use crate::{io::PAGE_SIZE, page_diff::PageDiff};
 
 const MAX_SIZE: usize = 1 << 37; // 128 GiB
@@ -94,6 +97,13 @@ impl WalBlobBuilder {
         }
     }
 
+    pub fn write_reclaim(&mut self, bucket_index: u64) {
+        unsafe {
+            self.write_byte(WAL_ENTRY_TAG_RECLAIM);
+            self.write(&bucket_index.to_le_bytes());
+        }
+    }
+
     pub fn write_update(
         &mut self,
         page_id: [u8; 32],
@@ -172,9 +182,15 @@ impl WalBlobBuilder {
         Ok(())
     }
 
-    /// Resets the builder preparing it for a new batch of writes.
-    pub fn reset(&mut self) {
+    /// Resets the builder preparing it for the batch of writes of the sync with the given sequence
+    /// number.
+    pub fn reset(&mut self, sync_seqn: u32) {
         self.cur = 0;
+        let header: [u8; WAL_HEADER_SIZE] = sync_seqn.to_le_bytes();
+        unsafe {
+            // SAFETY: This slice trivially does not overlap with the mmap.
+            self.write(&header);
+        }
     }
 
     /// Finalizes the builder.
@@ -2,7 +2,8 @@
 
 // The logic for writeout is split into three parts:
 // - first we write out the wal blob to the WAL file and wait for the MANIFEST to be synced.
-// - then we write out the metabits and bucket pages to the HT file.
+// - then we write out the metabits and bucket pages to the HT file, optionally reading them back
+//   to verify them.
 // - finally, we truncate the WAL file.
 
 use std::{
@@ -11,13 +12,29 @@ use std::{
     os::fd::AsRawFd as _,
 };
 
-use crate::io::{FatPage, IoCommand, IoHandle, IoKind};
+use crate::{
+    io::{self, FatPage, IoCommand, IoHandle, IoKind, IoPool, PAGE_SIZE},
+    SyncedFile,
+};
 
-pub(super) fn write_wal(mut wal_fd: &File, wal_blob: &[u8]) -> anyhow::Result<()> {
+/// Write the WAL blob to the WAL file, page by page if the pages are encrypted.
+pub(super) fn write_wal(
+    mut wal_fd: &File,
+    wal_blob: &[u8],
+    synced_file: SyncedFile,
+    io_pool: &IoPool,
+) -> anyhow::Result<()> {
     wal_fd.set_len(0)?;
     wal_fd.seek(SeekFrom::Start(0))?;
-    wal_fd.write_all(wal_blob)?;
-    wal_fd.sync_all()?;
+    match io_pool.cipher() {
+        Some(cipher) => {
+            for (pn, page) in wal_blob.chunks(PAGE_SIZE).enumerate() {
+                io::write_page(io_pool.page_pool(), Some(cipher), wal_fd, pn as u64, page)?;
+            }
+        }
+        None => wal_fd.write_all(wal_blob)?,
+    }
+    io_pool.sync_file(synced_file, wal_fd)?;
     Ok(())
 }
 
@@ -31,26 +48,87 @@ pub(super) fn write_ht(
     io_handle: IoHandle,
     ht_fd: &File,
     mut ht: Vec<(u64, FatPage)>,
+    verify: bool,
 ) -> anyhow::Result<()> {
     let mut sent = 0;
+    let mut checksums = Vec::new();
 
     ht.sort_unstable_by_key(|item| item.0);
     for (pn, page) in ht {
-        io_handle
-            .send(IoCommand {
-                kind: IoKind::Write(ht_fd.as_raw_fd(), pn, page),
-                user_data: 0,
-            })
-            .unwrap();
+        if verify {
+            checksums.push((pn, blake3::hash(&page)));
+        }
+        let command = IoCommand {
+            kind: IoKind::Write(ht_fd.as_raw_fd(), pn, page),
+            user_data: 0,
+        };
+        if io_handle.send(command).is_err() {
+            anyhow::bail!("I/O pool hangup");
+        }
         sent += 1;
     }
 
+    // All completions are received before failing on any of them, so that none is left over.
+    let mut write_result = Ok(());
     while sent > 0 {
-        io_handle.recv().unwrap();
+        let Ok(completion) = io_handle.recv() else {
+            anyhow::bail!("I/O pool hangup");
+        };
+        if write_result.is_ok() {
+            write_result = completion.result;
+        }
         sent -= 1;
     }
+    write_result?;
+
+    io_handle
+        .io_pool()
+        .sync_file(SyncedFile::HashTable, ht_fd)?;
+
+    if verify {
+        verify_ht(&io_handle, ht_fd, checksums)?;
+    }
 
-    ht_fd.sync_all()?;
+    Ok(())
+}
 
+/// Read back the written pages and compare them against the checksums taken before writing.
+fn verify_ht(
+    io_handle: &IoHandle,
+    ht_fd: &File,
+    checksums: Vec<(u64, blake3::Hash)>,
+) -> anyhow::Result<()> {
+    for (i, (pn, _)) in checksums.iter().enumerate() {
+        let page = io_handle.page_pool().alloc_fat_page();
+        let command = IoCommand {
+            kind: IoKind::Read(ht_fd.as_raw_fd(), *pn, page),
+            user_data: i as u64,
+        };
+        if io_handle.send(command).is_err() {
+            anyhow::bail!("I/O pool hangup");
+        }
+    }
+
+    let mut mismatched = Vec::new();
+    for _ in 0..checksums.len() {
+        let Ok(completion) = io_handle.recv() else {
+            anyhow::bail!("I/O pool hangup");
+        };
+        completion.result?;
+        let (IoKind::Read(_, _, page), Some(&(pn, checksum))) = (
+            completion.command.kind,
+            checksums.get(completion.command.user_data as usize),
+        ) else {
+            anyhow::bail!("unexpected I/O completion while verifying HT pages");
+        };
+        if blake3::hash(&page) != checksum {
+            mismatched.push(pn);
+        }
+    }
+
+    if !mismatched.is_empty() {
+        mismatched.sort_unstable();
+        anyhow::bail!("HT pages {mismatched:?} did not read back as written");
+    }
     Ok(())
 }
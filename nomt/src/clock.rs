@@ -0,0 +1,93 @@
+//! The source of time of time-based behavior. See [`crate::Options::clock`].
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// A source of time.
+///
+/// All time-based behavior of the database consults the clock it was opened with: the delay of
+/// commit coalescing and the intervals reported by [`crate::Nomt::read_amplification`]. The
+/// expiry of values is not, see [`crate::SessionParams::time`]. Measurements of how long operations
+/// take, such as the metrics, always use the system clock.
+pub trait Clock: Send + Sync {
+    /// The time elapsed since an arbitrary point fixed for the lifetime of the clock. Never
+    /// decreases.
+    fn monotonic(&self) -> Duration;
+
+    /// The wall-clock time in milliseconds since the unix epoch.
+    fn unix_millis(&self) -> u64;
+}
+
+/// The clock of the system. This is the default.
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    /// Create a clock whose monotonic time starts now.
+    pub fn new() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn monotonic(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn unix_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64)
+    }
+}
+
+/// A clock which only moves when told to, for tests and simulations.
+///
+/// Monotonic and wall-clock time advance together with [`ManualClock::advance`]. Share the clock
+/// with the database through an `Arc` to advance it from outside.
+pub struct ManualClock {
+    /// The monotonic time in nanoseconds.
+    monotonic: AtomicU64,
+    unix_millis: AtomicU64,
+}
+
+impl ManualClock {
+    /// Create a clock at the given wall-clock time, in milliseconds since the unix epoch, and
+    /// monotonic time zero.
+    pub fn new(unix_millis: u64) -> Self {
+        ManualClock {
+            monotonic: AtomicU64::new(0),
+            unix_millis: AtomicU64::new(unix_millis),
+        }
+    }
+
+    /// Move the clock forward by the given duration. The wall-clock time moves in whole
+    /// milliseconds, truncating the duration.
+    pub fn advance(&self, by: Duration) {
+        self.monotonic
+            .fetch_add(by.as_nanos() as u64, Ordering::Relaxed);
+        self.unix_millis
+            .fetch_add(by.as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn monotonic(&self) -> Duration {
+        Duration::from_nanos(self.monotonic.load(Ordering::Relaxed))
+    }
+
+    fn unix_millis(&self) -> u64 {
+        self.unix_millis.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,191 @@
+//! Golden test vectors guarding the commitment scheme.
+//!
+//! Every vector is a fixed sequence of commits along with the roots and witnesses they are expected
+//! to produce, using [`Blake3Hasher`]. The expected values are committed here and must never change:
+//! a change means that the same data is committed to differently, which breaks compatibility with
+//! existing state roots and proofs. Downstream users can run [`verify_vectors`] after upgrading to
+//! check that this is not the case.
+
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{Blake3Hasher, KeyPath, KeyReadWrite, Nomt, Options, Value};
+
+/// A golden vector.
+struct Vector {
+    name: &'static str,
+    /// Generates the actuals of the given commit. Reads must be consistent with prior commits.
+    actuals: fn(usize) -> Vec<(KeyPath, KeyReadWrite)>,
+    /// The expected root and witness commitment after each commit, hex-encoded. The witness
+    /// commitment is that of [`crate::Witness::into_chunks`] with a single chunk.
+    expected: &'static [(&'static str, &'static str)],
+}
+
+const VECTORS: &[Vector] = &[
+    Vector {
+        name: "single_key",
+        actuals: |_| vec![(key(0), KeyReadWrite::Write(Some(value(0, 16))))],
+        expected: &[(
+            "9e3c513d150789dc544fbe1c8923966f1aacb7a7bcd4636a6999ca457f6fdddd",
+            "7b9e67d2592d4403f0bb40539cdc3263b6646e8c9393b7b27d200e82b5a3b454",
+        )],
+    },
+    Vector {
+        name: "dense",
+        actuals: |commit| match commit {
+            0 => (0..100)
+                .map(|i| (key(i), KeyReadWrite::Write(Some(value(i, 32)))))
+                .collect(),
+            _ => {
+                let mut actuals = Vec::new();
+                for i in 0..10 {
+                    actuals.push((key(i), KeyReadWrite::Read(Some(value(i, 32)))));
+                }
+                for i in 10..20 {
+                    actuals.push((
+                        key(i),
+                        KeyReadWrite::ReadThenWrite(Some(value(i, 32)), Some(value(i + 1000, 8))),
+                    ));
+                }
+                for i in 20..30 {
+                    actuals.push((key(i), KeyReadWrite::Write(None)));
+                }
+                for i in 100..110 {
+                    actuals.push((key(i), KeyReadWrite::Write(Some(value(i, 64)))));
+                }
+                actuals.push((key(1000), KeyReadWrite::Read(None)));
+                actuals
+            }
+        },
+        expected: &[
+            (
+                "136ee40206ddda0cd7f867f9ffcccae5369a6cb3967be756027d1a74105963a6",
+                "7b9e67d2592d4403f0bb40539cdc3263b6646e8c9393b7b27d200e82b5a3b454",
+            ),
+            (
+                "1905871862f63e738ee97d6e9c0c665d90527c3cb832d4367f31adac2ba15262",
+                "df48cb9a8b449a5e562049c8ec714e2ccefbb8000d3fac7c024c9bdaa62c77f3",
+            ),
+        ],
+    },
+    Vector {
+        name: "shared_prefixes",
+        actuals: |commit| {
+            // Keys which differ only in their last bits, producing leaves far below the root page.
+            (0..8u8)
+                .map(|i| {
+                    let mut key = key(0);
+                    key[31] = i;
+                    let value = if commit == 0 || i % 2 == 0 {
+                        Some(value(i as u64, 4096))
+                    } else {
+                        None
+                    };
+                    (key, KeyReadWrite::Write(value))
+                })
+                .collect()
+        },
+        expected: &[
+            (
+                "67e4e396c9e217a4cb7bc65c95f3ed53d677a9d0f3fa55521dd0f8771c39189c",
+                "7b9e67d2592d4403f0bb40539cdc3263b6646e8c9393b7b27d200e82b5a3b454",
+            ),
+            (
+                "4c3e0e2b7d75b724d65c4abf77d3c8948b36dc9000fe7419710186099cb8c3fe",
+                "541bca9c5dbd3970b96b76414e483b33e17e676f5888fb29a828e7b317e52cd2",
+            ),
+        ],
+    },
+];
+
+/// Run all golden vectors against fresh databases in the temporary directory and check that they
+/// produce the expected roots and witnesses.
+///
+/// Fails, naming the offending vectors, if any root or witness differs.
+pub fn verify_vectors() -> anyhow::Result<()> {
+    let mismatches = run_vectors()?
+        .into_iter()
+        .zip(VECTORS)
+        .filter(|(actual, vector)| actual.as_slice() != expected(vector))
+        .map(|(_, vector)| vector.name)
+        .collect::<Vec<_>>();
+    if !mismatches.is_empty() {
+        anyhow::bail!("golden vectors mismatched: {}", mismatches.join(", "));
+    }
+    Ok(())
+}
+
+fn expected(vector: &Vector) -> Vec<(String, String)> {
+    vector
+        .expected
+        .iter()
+        .map(|(root, witness)| (root.to_string(), witness.to_string()))
+        .collect()
+}
+
+/// Run all vectors, returning the hex-encoded roots and witness commitments they produce.
+fn run_vectors() -> anyhow::Result<Vec<Vec<(String, String)>>> {
+    static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+    let mut results = Vec::new();
+    for vector in VECTORS {
+        let dir = std::env::temp_dir().join(format!(
+            "nomt-compat-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = run_vector(vector, dir.clone());
+        let _ = std::fs::remove_dir_all(&dir);
+        results.push(result?);
+    }
+    Ok(results)
+}
+
+fn run_vector(vector: &Vector, dir: PathBuf) -> anyhow::Result<Vec<(String, String)>> {
+    let mut o = Options::new();
+    o.path(dir);
+    o.bitbox_seed([0; 16]);
+    let nomt = Nomt::<Blake3Hasher>::open(o)?;
+
+    let mut results = Vec::new();
+    for commit in 0..vector.expected.len() {
+        let mut actuals = (vector.actuals)(commit);
+        actuals.sort_by_key(|(key, _)| *key);
+        let prev_root = nomt.root();
+        let session = nomt.begin_session();
+        let (root, witness, _) = nomt.commit_and_prove(session, actuals)?;
+        let chunks = witness
+            .into_chunks(prev_root, usize::MAX)
+            .map_err(|e| anyhow::anyhow!("failed to chunk witness: {e:?}"))?;
+        results.push((hex(&root), hex(&chunks[0].commitment)));
+    }
+    Ok(results)
+}
+
+fn key(i: u64) -> KeyPath {
+    *blake3::hash(&i.to_le_bytes()).as_bytes()
+}
+
+fn value(i: u64, len: usize) -> Value {
+    let mut value = Vec::with_capacity(len);
+    let mut xof = blake3::Hasher::new()
+        .update(&i.to_le_bytes())
+        .finalize_xof();
+    value.resize(len, 0);
+    xof.fill(&mut value);
+    value
+}
+
+fn hex(bytes: &[u8; 32]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn golden_vectors() {
+        super::verify_vectors().unwrap();
+    }
+}
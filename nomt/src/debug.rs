@@ -0,0 +1,181 @@
+//! Debugging aids for inspecting the merkle trie.
+//!
+//! These read the trie node by node and are only meant for small subtrees, e.g. when tracking down
+//! unexpected root mismatches on test fixtures.
+
+use std::{collections::HashMap, fmt::Write as _};
+
+use bitvec::prelude::*;
+use nomt_core::{
+    page::DEPTH,
+    page_id::{PageId, ROOT_PAGE_ID},
+    trie::{self, KeyPath, Node},
+    trie_pos::TriePosition,
+};
+
+use crate::{io::FatPage, HashAlgorithm, Nomt};
+
+/// The output format of [`dump_subtree`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// An indented tree, one node per line.
+    Text,
+    /// A graph in the graphviz DOT language.
+    Dot,
+}
+
+/// Render the subtree of the committed trie rooted at `prefix`, down to `depth` levels below it.
+///
+/// Every node is shown with its path, its kind and a truncated hash. Leaves additionally show a
+/// truncated key and the size of their value. Nodes below `depth` are elided.
+pub fn dump_subtree<T: HashAlgorithm>(
+    nomt: &Nomt<T>,
+    prefix: &BitSlice<u8, Msb0>,
+    depth: u16,
+    format: DumpFormat,
+) -> anyhow::Result<String> {
+    if prefix.len() > 256 {
+        anyhow::bail!("prefix longer than 256 bits");
+    }
+    let mut dumper = Dumper {
+        nomt,
+        pages: HashMap::new(),
+        format,
+        max_depth: (prefix.len() + depth as usize).min(256),
+        out: String::new(),
+    };
+
+    let position = if prefix.is_empty() {
+        TriePosition::new()
+    } else {
+        TriePosition::from_bitslice(prefix)
+    };
+    if format == DumpFormat::Dot {
+        dumper
+            .out
+            .push_str("digraph subtree {\n  node [shape=box, fontname=monospace];\n");
+    }
+    dumper.dump(position, prefix.len())?;
+    if format == DumpFormat::Dot {
+        dumper.out.push_str("}\n");
+    }
+    Ok(dumper.out)
+}
+
+struct Dumper<'a, T: HashAlgorithm> {
+    nomt: &'a Nomt<T>,
+    pages: HashMap<PageId, Option<FatPage>>,
+    format: DumpFormat,
+    max_depth: usize,
+    out: String,
+}
+
+impl<T: HashAlgorithm> Dumper<'_, T> {
+    fn dump(&mut self, position: TriePosition, prefix_len: usize) -> anyhow::Result<()> {
+        let node = self.node(&position)?;
+        let path = bits_string(position.path());
+        let label = if trie::is_terminator(&node) {
+            "terminator".to_string()
+        } else if trie::is_leaf(&node) {
+            let key_path = self.leaf_key_path(&position)?;
+            let value_size = self.nomt.store.load_value(key_path)?.map(|v| v.len());
+            let value = match value_size {
+                Some(size) => format!("{size} bytes"),
+                None => "missing".to_string(),
+            };
+            format!(
+                "leaf {} key={} value={}",
+                short_hex(&node),
+                short_hex(&key_path),
+                value
+            )
+        } else {
+            format!("internal {}", short_hex(&node))
+        };
+
+        let elided = trie::is_internal(&node) && position.depth() as usize >= self.max_depth;
+        match self.format {
+            DumpFormat::Text => {
+                let indent = "  ".repeat(position.depth() as usize - prefix_len);
+                let _ = writeln!(self.out, "{indent}[{path}] {label}");
+                if elided {
+                    let _ = writeln!(self.out, "{indent}  ...");
+                }
+            }
+            DumpFormat::Dot => {
+                let _ = writeln!(self.out, "  n{path} [label=\"[{path}] {label}\"];");
+                if elided {
+                    let _ = writeln!(self.out, "  n{path}_elided [label=\"...\", shape=plain];");
+                    let _ = writeln!(self.out, "  n{path} -> n{path}_elided;");
+                }
+            }
+        }
+
+        if !trie::is_internal(&node) || elided {
+            return Ok(());
+        }
+        for bit in [false, true] {
+            let mut child = position.clone();
+            child.down(bit);
+            if self.format == DumpFormat::Dot {
+                let child_path = bits_string(child.path());
+                let _ = writeln!(
+                    self.out,
+                    "  n{path} -> n{child_path} [label=\"{}\"];",
+                    bit as u8
+                );
+            }
+            self.dump(child, prefix_len)?;
+        }
+        Ok(())
+    }
+
+    fn node(&mut self, position: &TriePosition) -> anyhow::Result<Node> {
+        match position.page_id() {
+            None => Ok(self.nomt.root()),
+            Some(page_id) => Ok(self.slot(page_id, position.node_index())?),
+        }
+    }
+
+    // The leaf data of a leaf node is stored in its two child slots.
+    fn leaf_key_path(&mut self, position: &TriePosition) -> anyhow::Result<KeyPath> {
+        let (page_id, index) = match position.page_id() {
+            None => (ROOT_PAGE_ID, 0),
+            Some(page_id) if position.depth_in_page() == DEPTH => {
+                let child_page_id = page_id
+                    .child_page_id(position.child_page_index())
+                    .map_err(|_| anyhow::anyhow!("leaf at maximum depth"))?;
+                (child_page_id, 0)
+            }
+            Some(page_id) => (page_id, position.child_node_indices().left()),
+        };
+        self.slot(page_id, index)
+    }
+
+    fn slot(&mut self, page_id: PageId, index: usize) -> anyhow::Result<[u8; 32]> {
+        let page = match self.pages.get(&page_id) {
+            Some(page) => page,
+            None => {
+                let page = self.nomt.store.load_page(page_id.clone())?;
+                self.pages
+                    .entry(page_id)
+                    .or_insert(page.map(|(page, _)| page))
+            }
+        };
+        let mut slot = [0; 32];
+        if let Some(page) = page {
+            slot.copy_from_slice(&page[index * 32..][..32]);
+        }
+        Ok(slot)
+    }
+}
+
+fn bits_string(bits: &BitSlice<u8, Msb0>) -> String {
+    bits.iter()
+        .map(|bit| if *bit { '1' } else { '0' })
+        .collect()
+}
+
+fn short_hex(bytes: &[u8; 32]) -> String {
+    bytes[..4].iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,78 @@
+//! Values read for absent keys under configured prefixes. See [`crate::Options::default_value`].
+
+use bitvec::prelude::*;
+
+use crate::{KeyPath, KeyReadWrite, Value, ValueHandle};
+
+/// The default values of absent keys, by key-path prefix.
+#[derive(Clone, Default)]
+pub struct DefaultValues {
+    /// Sorted by descending prefix length, so that the first match is the longest one.
+    prefixes: Vec<(BitVec<u8, Msb0>, Value)>,
+}
+
+impl DefaultValues {
+    /// Set the default value of keys starting with the prefix, replacing the one of the same
+    /// prefix.
+    pub fn insert(&mut self, prefix: &BitSlice<u8, Msb0>, value: Value) {
+        self.prefixes.retain(|(p, _)| p[..] != *prefix);
+        self.prefixes.push((prefix.to_bitvec(), value));
+        self.prefixes
+            .sort_by_key(|(p, _)| std::cmp::Reverse(p.len()));
+    }
+
+    /// The default value of the key, taken from the longest prefix it starts with.
+    pub fn get(&self, key_path: &KeyPath) -> Option<&Value> {
+        let bits = key_path.view_bits::<Msb0>();
+        self.prefixes
+            .iter()
+            .find(|(prefix, _)| bits.starts_with(prefix))
+            .map(|(_, value)| value)
+    }
+
+    /// Replace a read absence with the default value of the key.
+    pub fn apply(&self, key_path: &KeyPath, value: Option<Value>) -> Option<Value> {
+        value.or_else(|| self.get(key_path).cloned())
+    }
+
+    /// Turn the reads and writes of default values into reads and writes of absences, so that
+    /// keys holding their default value are never stored.
+    pub fn normalize(
+        &self,
+        actuals: &mut [(KeyPath, KeyReadWrite)],
+        bulk_writes: &mut [(KeyPath, Option<ValueHandle>)],
+    ) {
+        if self.prefixes.is_empty() {
+            return;
+        }
+        for (path, read_write) in actuals {
+            let Some(default) = self.get(path) else {
+                continue;
+            };
+            let clear = |value: &mut Option<Value>| {
+                if value.as_ref() == Some(default) {
+                    *value = None;
+                }
+            };
+            match read_write {
+                KeyReadWrite::Read(value) | KeyReadWrite::Write(value) => clear(value),
+                KeyReadWrite::ReadThenWrite(prior, value) => {
+                    clear(prior);
+                    clear(value);
+                }
+            }
+        }
+        for (path, value) in bulk_writes {
+            let is_default = match (self.get(path), &*value) {
+                // Comparing the lengths first spares gathering streamed values.
+                (Some(default), Some(value)) => {
+                    default.len() == value.len() && default[..] == value[..]
+                }
+                _ => false,
+            };
+            if is_default {
+                *value = None;
+            }
+        }
+    }
+}
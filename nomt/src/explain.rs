@@ -0,0 +1,175 @@
+//! Tracing the steps taken by a single lookup. See [`crate::Session::explain_read`].
+
+use std::{fmt, time::Duration};
+
+use crate::Value;
+
+/// The steps taken to read a single key, along with the value read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadExplanation {
+    /// The value read, as returned by [`crate::Session::read`].
+    pub value: Option<Value>,
+    /// The steps taken, in order.
+    pub steps: Vec<ReadStep>,
+}
+
+impl ReadExplanation {
+    /// The time taken by all steps.
+    pub fn elapsed(&self) -> Duration {
+        self.steps.iter().map(|step| step.elapsed).sum()
+    }
+
+    /// The number of bytes read from disk by all steps.
+    pub fn bytes_read(&self) -> u64 {
+        self.steps.iter().map(|step| step.bytes_read).sum()
+    }
+}
+
+impl fmt::Display for ReadExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(
+                f,
+                "{:>10?} {:>8} B  {}",
+                step.elapsed, step.bytes_read, step.kind
+            )?;
+        }
+        let value = match self.value {
+            Some(ref value) => format!("{} bytes", value.len()),
+            None => "absent".to_string(),
+        };
+        write!(
+            f,
+            "{:>10?} {:>8} B  total, value {value}",
+            self.elapsed(),
+            self.bytes_read()
+        )
+    }
+}
+
+/// A single step of a lookup. See [`ReadExplanation`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadStep {
+    /// What was done.
+    pub kind: ReadStepKind,
+    /// The number of bytes read from disk.
+    pub bytes_read: u64,
+    /// The time taken.
+    pub elapsed: Duration,
+}
+
+/// What was done in a [`ReadStep`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReadStepKind {
+    /// The expiry of the key was checked, see [`crate::Session::write_with_ttl`]. Expired keys are
+    /// read as absent without further steps.
+    Expiry {
+        /// Whether the value of the key has expired.
+        expired: bool,
+    },
+    /// The changes of commits not yet written to the b-tree were searched.
+    Staging {
+        /// Whether the key was changed by one of them, which ends the lookup.
+        hit: bool,
+    },
+    /// The in-memory index of the b-tree branch nodes was searched for the leaf which may hold
+    /// the key.
+    BranchIndex {
+        /// The page number of the leaf, or `None` if the b-tree is empty.
+        leaf: Option<u32>,
+    },
+    /// The b-tree leaf was read, from the leaf cache or from disk.
+    Leaf {
+        /// The page number of the leaf.
+        page_number: u32,
+        /// Whether the leaf was in the leaf cache.
+        cached: bool,
+    },
+    /// The value was read from overflow pages, as it is too large to be stored in the leaf.
+    Overflow {
+        /// The number of overflow pages read.
+        pages: usize,
+    },
+    /// The value was read from the value log, see [`crate::Options::value_log_threshold`].
+    ValueLog,
+    /// The value was decompressed, see [`crate::Options::value_compression`].
+    Decompress {
+        /// The size of the compressed value.
+        compressed_len: usize,
+    },
+    /// A page of the merkle path of the key was read, from the page cache or from the
+    /// hash-table. These pages are not needed to read the value, but to update or prove the key
+    /// when committing the session.
+    TriePage {
+        /// The depth of the page, where the root page has depth 0.
+        depth: usize,
+        /// Whether the page was in the page cache.
+        cached: bool,
+        /// The number of hash-table buckets probed to find the page. Pages found in the cache
+        /// probe none.
+        probed_buckets: u64,
+    },
+}
+
+impl fmt::Display for ReadStepKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cache = |cached: bool| if cached { "cache hit" } else { "cache miss" };
+        match self {
+            ReadStepKind::Expiry { expired } => write!(f, "expiry, expired: {expired}"),
+            ReadStepKind::Staging { hit } => write!(f, "unsynced changes, hit: {hit}"),
+            ReadStepKind::BranchIndex { leaf: Some(leaf) } => {
+                write!(f, "branch index, leaf {leaf}")
+            }
+            ReadStepKind::BranchIndex { leaf: None } => write!(f, "branch index, empty b-tree"),
+            ReadStepKind::Leaf {
+                page_number,
+                cached,
+            } => write!(f, "leaf {page_number}, {}", cache(*cached)),
+            ReadStepKind::Overflow { pages } => write!(f, "overflow, {pages} pages"),
+            ReadStepKind::ValueLog => write!(f, "value log"),
+            ReadStepKind::Decompress { compressed_len } => {
+                write!(f, "decompress, {compressed_len} bytes")
+            }
+            ReadStepKind::TriePage {
+                depth,
+                cached,
+                probed_buckets,
+            } => write!(
+                f,
+                "trie page at depth {depth}, {}, {probed_buckets} buckets probed",
+                cache(*cached)
+            ),
+        }
+    }
+}
+
+/// Records steps, timing each from the end of the previous one.
+pub struct StepRecorder {
+    steps: Vec<ReadStep>,
+    last: std::time::Instant,
+}
+
+impl StepRecorder {
+    pub fn new() -> Self {
+        StepRecorder {
+            steps: Vec::new(),
+            last: std::time::Instant::now(),
+        }
+    }
+
+    /// Record a step which ends now.
+    pub fn record(&mut self, kind: ReadStepKind, bytes_read: u64) {
+        let now = std::time::Instant::now();
+        self.steps.push(ReadStep {
+            kind,
+            bytes_read,
+            elapsed: now - self.last,
+        });
+        self.last = now;
+    }
+
+    pub fn finish(self) -> Vec<ReadStep> {
+        self.steps
+    }
+}
@@ -0,0 +1,58 @@
+//! Hooks for injecting faults into the I/O of a database.
+
+/// A file of the database which is synced to disk on every commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncedFile {
+    /// The manifest, which is written and synced at once.
+    Meta,
+    /// The hash-table holding the pages of the merkle trie.
+    HashTable,
+    /// The WAL of the hash-table.
+    Wal,
+    /// The mirror of the WAL. See [`crate::Options::wal_mirror`].
+    WalMirror,
+    /// The leaf nodes of the b-tree.
+    Leaves,
+    /// The branch nodes of the b-tree.
+    Branches,
+}
+
+/// A page read or write submitted to the I/O workers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageIo {
+    /// A read of the page with the given number.
+    Read(u64),
+    /// A write of the page with the given number.
+    Write(u64),
+}
+
+/// A hook which is consulted before the database performs I/O and may fail it instead.
+///
+/// This is meant for downstream chaos testing: wrap an implementation around a schedule of faults
+/// and check how the application copes with the resulting errors. A failed operation is not
+/// performed and reports the returned error to the part of the database that issued it. Note that
+/// many I/O errors during a commit are currently fatal to it, and that once a sync has failed,
+/// every commit and read fails until the database is reopened. All methods default to letting
+/// the operation proceed.
+///
+/// The hooks are invoked on internal threads and must not block for long.
+pub trait FaultInjector: Send + Sync {
+    /// Called before a page is read or written by the I/O workers.
+    fn page_io(&self, io: PageIo) -> std::io::Result<()> {
+        let _ = io;
+        Ok(())
+    }
+
+    /// Called before the WAL blob produced by the sync with the given sequence number is written
+    /// to the local WAL file and its mirror, if any. [`crate::WalSink`]s are not affected.
+    fn wal_write(&self, sync_seqn: u32, wal_blob: &[u8]) -> std::io::Result<()> {
+        let _ = (sync_seqn, wal_blob);
+        Ok(())
+    }
+
+    /// Called before the given file is synced to disk.
+    fn fsync(&self, file: SyncedFile) -> std::io::Result<()> {
+        let _ = file;
+        Ok(())
+    }
+}
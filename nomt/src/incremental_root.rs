@@ -0,0 +1,249 @@
+//! Computing the root of a set of updates incrementally, as sorted batches of them arrive. See
+//! [`crate::Nomt::incremental_root`].
+//!
+//! The key space is split by key prefix into `2^seal_bits` subtrees. Since updates arrive in key
+//! order, a subtree can no longer change once an update to a key of a later subtree arrives: it is
+//! sealed and its new root computed right away, from the updates to its keys and the committed
+//! trie. The root of the whole trie follows from the roots of the subtrees once all are sealed.
+//!
+//! Only the nodes of the committed trie on the paths to updated keys are read, much like a commit
+//! does, but nothing is written: the updates still need to be committed for the database to reach
+//! the root.
+
+use bitvec::prelude::*;
+use nomt_core::{
+    trie::{self, InternalData, KeyPath, LeafData, Node, NodeHasherExt, NodeKind, ValueHash},
+    trie_pos::TriePosition,
+    update::build_trie,
+};
+
+use crate::{
+    state_sync::{chunk_prefix, NodeReader},
+    HashAlgorithm, Nomt, Value,
+};
+
+/// The maximum number of bits of the prefixes of sealed subtrees.
+pub const MAX_SEAL_BITS: u32 = 16;
+
+/// A subtree which no later update can change, along with its new root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SealedSubtree {
+    /// The prefix shared by all keys of the subtree, in bits.
+    pub prefix: BitVec<u8, Msb0>,
+    /// The root of the subtree after the updates, as defined by
+    /// [`crate::SubtreeArchive::root`].
+    pub root: Node,
+}
+
+/// Computes the root resulting from updates fed in key order. See [`crate::Nomt::incremental_root`].
+pub struct IncrementalRoot<'a, T: HashAlgorithm> {
+    nomt: &'a Nomt<T>,
+    reader: NodeReader<'a, T>,
+    /// The root of the trie the updates apply to.
+    base_root: Node,
+    seal_bits: u32,
+    /// The index of the first subtree not sealed yet.
+    next_subtree: u64,
+    /// The updates to the keys of the first subtree not sealed yet.
+    pending: Vec<(KeyPath, Option<ValueHash>)>,
+    last_key: Option<KeyPath>,
+    /// The roots of the sealed subtrees, in order.
+    roots: Vec<Node>,
+}
+
+impl<'a, T: HashAlgorithm> IncrementalRoot<'a, T> {
+    pub(crate) fn new(nomt: &'a Nomt<T>, seal_bits: u32) -> Self {
+        IncrementalRoot {
+            nomt,
+            reader: NodeReader::new(nomt),
+            base_root: nomt.root(),
+            seal_bits,
+            next_subtree: 0,
+            pending: Vec::new(),
+            last_key: None,
+            roots: Vec::new(),
+        }
+    }
+
+    /// Feed the next batch of updates, `None` deleting a key, and return the subtrees sealed by
+    /// it.
+    ///
+    /// Keys must be strictly ascending, within the batch and across batches. Every subtree before
+    /// the one of the last key of the batch is sealed.
+    pub fn feed(
+        &mut self,
+        batch: &[(KeyPath, Option<Value>)],
+    ) -> anyhow::Result<Vec<SealedSubtree>> {
+        self.check_base()?;
+        let mut sealed = Vec::new();
+        for (key, value) in batch {
+            if self.last_key.is_some_and(|last| *key <= last) {
+                anyhow::bail!("incremental root: keys not strictly ascending");
+            }
+            self.last_key = Some(*key);
+
+            let subtree = key.view_bits::<Msb0>()[..self.seal_bits as usize]
+                .iter()
+                .fold(0u64, |index, bit| index << 1 | *bit as u64);
+            while self.next_subtree < subtree {
+                sealed.push(self.seal()?);
+            }
+            self.pending
+                .push((*key, value.as_deref().map(T::hash_value)));
+        }
+        Ok(sealed)
+    }
+
+    /// Seal the remaining subtrees and return them along with the root of the trie after all
+    /// updates fed.
+    pub fn finish(mut self) -> anyhow::Result<(Node, Vec<SealedSubtree>)> {
+        self.check_base()?;
+        let mut sealed = Vec::new();
+        while self.next_subtree < 1 << self.seal_bits {
+            sealed.push(self.seal()?);
+        }
+
+        let mut nodes = self.roots;
+        while nodes.len() > 1 {
+            nodes = nodes
+                .chunks(2)
+                .map(|pair| compact::<T>(pair[0], pair[1]))
+                .collect();
+        }
+        Ok((nodes[0], sealed))
+    }
+
+    fn check_base(&self) -> anyhow::Result<()> {
+        if self.nomt.root() != self.base_root {
+            anyhow::bail!("incremental root: the database was committed to while feeding updates");
+        }
+        Ok(())
+    }
+
+    fn seal(&mut self) -> anyhow::Result<SealedSubtree> {
+        let prefix = chunk_prefix(self.seal_bits, self.next_subtree);
+        let (position, committed) = self.committed_subtree(&prefix)?;
+        let pending = std::mem::take(&mut self.pending);
+        let root = self.update(position, committed, &pending)?;
+        self.roots.push(root);
+        self.next_subtree += 1;
+        Ok(SealedSubtree { prefix, root })
+    }
+
+    /// Returns the position of the prefix along with the committed node there.
+    ///
+    /// If the trie ends above the prefix in a leaf of a key under the prefix, that leaf is the only
+    /// node of the subtree.
+    fn committed_subtree(
+        &mut self,
+        prefix: &BitSlice<u8, Msb0>,
+    ) -> anyhow::Result<(TriePosition, Committed)> {
+        let prefix_position = if prefix.is_empty() {
+            TriePosition::new()
+        } else {
+            TriePosition::from_bitslice(prefix)
+        };
+        let mut node = self.base_root;
+        let mut position = TriePosition::new();
+        for bit in prefix.iter().by_vals() {
+            match NodeKind::of(&node) {
+                NodeKind::Terminator => return Ok((prefix_position, Committed::Terminator)),
+                NodeKind::Leaf => {
+                    let leaf = self.reader.leaf_data(&position)?;
+                    let committed = if leaf.key_path.view_bits::<Msb0>().starts_with(prefix) {
+                        Committed::Leaf(node, leaf)
+                    } else {
+                        Committed::Terminator
+                    };
+                    return Ok((prefix_position, committed));
+                }
+                NodeKind::Internal => {
+                    position.down(bit);
+                    node = self.reader.node(&position)?;
+                }
+            }
+        }
+        let committed = self.classify(&position, node)?;
+        Ok((prefix_position, committed))
+    }
+
+    fn classify(&mut self, position: &TriePosition, node: Node) -> anyhow::Result<Committed> {
+        Ok(match NodeKind::of(&node) {
+            NodeKind::Terminator => Committed::Terminator,
+            NodeKind::Leaf => Committed::Leaf(node, self.reader.leaf_data(position)?),
+            NodeKind::Internal => Committed::Internal(node),
+        })
+    }
+
+    /// Returns the node at the position after applying the updates, which are sorted and lie
+    /// under the position, to the committed subtree there.
+    fn update(
+        &mut self,
+        position: TriePosition,
+        committed: Committed,
+        updates: &[(KeyPath, Option<ValueHash>)],
+    ) -> anyhow::Result<Node> {
+        let depth = position.depth() as usize;
+        match committed {
+            _ if updates.is_empty() => Ok(committed.node()),
+            Committed::Terminator => Ok(build_trie::<T>(
+                depth,
+                updates
+                    .iter()
+                    .filter_map(|(key, value)| Some((*key, (*value)?))),
+                |_| {},
+            )),
+            Committed::Leaf(_, leaf) => {
+                let mut leaves = updates
+                    .iter()
+                    .filter_map(|(key, value)| Some((*key, (*value)?)))
+                    .collect::<Vec<_>>();
+                if !updates.iter().any(|(key, _)| *key == leaf.key_path) {
+                    let index = leaves.partition_point(|(key, _)| *key < leaf.key_path);
+                    leaves.insert(index, (leaf.key_path, leaf.value_hash));
+                }
+                Ok(build_trie::<T>(depth, leaves, |_| {}))
+            }
+            Committed::Internal(_) => {
+                // Updates are sorted, so those going left come first.
+                let split = updates.partition_point(|(key, _)| !key.view_bits::<Msb0>()[depth]);
+                let mut children = [trie::TERMINATOR; 2];
+                for (bit, updates) in [(false, &updates[..split]), (true, &updates[split..])] {
+                    let mut child = position.clone();
+                    child.down(bit);
+                    let node = self.reader.node(&child)?;
+                    let committed = self.classify(&child, node)?;
+                    children[bit as usize] = self.update(child, committed, updates)?;
+                }
+                Ok(compact::<T>(children[0], children[1]))
+            }
+        }
+    }
+}
+
+/// A node of the committed trie.
+enum Committed {
+    Terminator,
+    Leaf(Node, LeafData),
+    Internal(Node),
+}
+
+impl Committed {
+    fn node(&self) -> Node {
+        match self {
+            Committed::Terminator => trie::TERMINATOR,
+            Committed::Leaf(node, _) | Committed::Internal(node) => *node,
+        }
+    }
+}
+
+/// The parent of two sibling nodes, moving a leaf up in place of its parent if its sibling is a
+/// terminator.
+fn compact<T: HashAlgorithm>(left: Node, right: Node) -> Node {
+    match (NodeKind::of(&left), NodeKind::of(&right)) {
+        (NodeKind::Terminator, NodeKind::Terminator) => trie::TERMINATOR,
+        (NodeKind::Leaf, NodeKind::Terminator) => left,
+        (NodeKind::Terminator, NodeKind::Leaf) => right,
+        _ => T::hash_internal(&InternalData { left, right }),
+    }
+}
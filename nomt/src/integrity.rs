@@ -0,0 +1,503 @@
+//! Offline integrity checking of a database, e.g. after a suspected disk corruption.
+//!
+//! [`check`] opens a database read-only and cross-checks everything it stores against the root:
+//!
+//! - the committed trie is walked from the root, recomputing the hash of every internal node from
+//!   its children and of every leaf from its key and value hash,
+//! - every leaf is matched against the value stored under its key in the b-tree, and every value
+//!   in the b-tree against a leaf,
+//! - every occupied bucket of the hash-table is read and checked against the meta-map, and must be
+//!   found by probing for the page it holds,
+//! - pages in the hash-table which are not reachable from the root are reported as orphaned.
+//!
+//! All problems found are collected in a [`Report`] rather than failing the check, so that the
+//! extent of the damage can be judged. The check reads the whole database and takes time
+//! proportional to its size.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::Path,
+};
+
+use bitvec::prelude::*;
+use nomt_core::{
+    page::DEPTH,
+    page_id::{PageId, ROOT_PAGE_ID},
+    trie::{self, InternalData, KeyPath, LeafData, Node, NodeHasherExt, ValueHash},
+    trie_pos::TriePosition,
+};
+
+use crate::{beatree::next_key, io::FatPage, HashAlgorithm, Nomt, Options};
+
+/// The number of values loaded from the b-tree at once.
+const BATCH_SIZE: usize = 1024;
+
+/// A problem found by [`check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// The hash of an internal node differs from the one recomputed from its children.
+    InternalHashMismatch {
+        /// The position of the node.
+        position: BitVec<u8, Msb0>,
+        /// The hash stored for the node.
+        stored: Node,
+        /// The hash of its children.
+        computed: Node,
+    },
+    /// The hash of a leaf node differs from the one recomputed from its leaf data.
+    LeafHashMismatch {
+        /// The position of the node.
+        position: BitVec<u8, Msb0>,
+        /// The key path of the leaf data.
+        key_path: KeyPath,
+    },
+    /// The key path of a leaf does not start with the position of the leaf.
+    MisplacedLeaf {
+        /// The position of the node.
+        position: BitVec<u8, Msb0>,
+        /// The key path of the leaf data.
+        key_path: KeyPath,
+    },
+    /// A node below the maximum depth of the trie.
+    TooDeep {
+        /// The position of the node.
+        position: BitVec<u8, Msb0>,
+    },
+    /// A page holding nodes reachable from the root is missing from the hash-table.
+    MissingPage {
+        /// The encoded page ID.
+        page_id: [u8; 32],
+    },
+    /// A leaf has no value stored under its key in the b-tree.
+    MissingValue {
+        /// The key of the leaf.
+        key_path: KeyPath,
+    },
+    /// The value stored under the key of a leaf does not match the value hash of the leaf.
+    ValueHashMismatch {
+        /// The key of the leaf.
+        key_path: KeyPath,
+    },
+    /// A value stored in the b-tree has no leaf in the trie.
+    OrphanedValue {
+        /// The key of the value.
+        key_path: KeyPath,
+    },
+    /// The meta-map entry of an occupied bucket does not match the page stored in it.
+    MetaMapMismatch {
+        /// The index of the bucket.
+        bucket: u64,
+        /// The encoded ID of the page in the bucket.
+        page_id: [u8; 32],
+    },
+    /// A page can't be found by probing for it, because an empty bucket precedes its bucket.
+    UnreachableBucket {
+        /// The index of the bucket.
+        bucket: u64,
+        /// The encoded ID of the page in the bucket.
+        page_id: [u8; 32],
+    },
+    /// A bucket holds bytes which are not a valid page ID.
+    InvalidPageId {
+        /// The index of the bucket.
+        bucket: u64,
+    },
+    /// A page is stored in more than one bucket.
+    DuplicatePage {
+        /// The index of the bucket holding the second copy.
+        bucket: u64,
+        /// The encoded page ID.
+        page_id: [u8; 32],
+    },
+    /// A page stored in the hash-table is not reachable from the root.
+    OrphanedPage {
+        /// The index of the bucket.
+        bucket: u64,
+        /// The encoded page ID.
+        page_id: [u8; 32],
+    },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::InternalHashMismatch {
+                position,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "internal node [{}]: stored hash {} but children hash to {}",
+                bits(position),
+                hex(stored),
+                hex(computed)
+            ),
+            Issue::LeafHashMismatch { position, key_path } => write!(
+                f,
+                "leaf [{}] of key {}: hash does not match leaf data",
+                bits(position),
+                hex(key_path)
+            ),
+            Issue::MisplacedLeaf { position, key_path } => write!(
+                f,
+                "leaf [{}]: key {} does not lie under its position",
+                bits(position),
+                hex(key_path)
+            ),
+            Issue::TooDeep { position } => {
+                write!(f, "node [{}] below maximum depth", bits(position))
+            }
+            Issue::MissingPage { page_id } => write!(f, "page {} missing", hex(page_id)),
+            Issue::MissingValue { key_path } => {
+                write!(f, "leaf of key {} has no value", hex(key_path))
+            }
+            Issue::ValueHashMismatch { key_path } => write!(
+                f,
+                "value of key {} does not match the hash of its leaf",
+                hex(key_path)
+            ),
+            Issue::OrphanedValue { key_path } => {
+                write!(f, "value of key {} has no leaf", hex(key_path))
+            }
+            Issue::MetaMapMismatch { bucket, page_id } => write!(
+                f,
+                "bucket {bucket}: meta-map does not match page {}",
+                hex(page_id)
+            ),
+            Issue::UnreachableBucket { bucket, page_id } => write!(
+                f,
+                "bucket {bucket}: page {} not reachable by probing",
+                hex(page_id)
+            ),
+            Issue::InvalidPageId { bucket } => write!(f, "bucket {bucket}: invalid page ID"),
+            Issue::DuplicatePage { bucket, page_id } => write!(
+                f,
+                "bucket {bucket}: page {} stored more than once",
+                hex(page_id)
+            ),
+            Issue::OrphanedPage { bucket, page_id } => write!(
+                f,
+                "bucket {bucket}: page {} not reachable from the root",
+                hex(page_id)
+            ),
+        }
+    }
+}
+
+/// The outcome of [`check`].
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// The root the database was checked against.
+    pub root: Node,
+    /// The number of internal nodes checked.
+    pub internal_nodes: u64,
+    /// The number of leaves checked.
+    pub leaves: u64,
+    /// The number of trie pages read.
+    pub pages: u64,
+    /// The number of values stored in the b-tree.
+    pub values: u64,
+    /// The number of occupied buckets of the hash-table.
+    pub buckets: u64,
+    /// The problems found, in the order they were found.
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    /// Whether no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "root: {}", hex(&self.root))?;
+        writeln!(
+            f,
+            "checked {} internal nodes, {} leaves, {} pages, {} values, {} buckets",
+            self.internal_nodes, self.leaves, self.pages, self.values, self.buckets
+        )?;
+        for issue in &self.issues {
+            writeln!(f, "{issue}")?;
+        }
+        if self.is_ok() {
+            writeln!(f, "no issues found")
+        } else {
+            writeln!(f, "{} issues found", self.issues.len())
+        }
+    }
+}
+
+/// Check the integrity of the database at the given path. See the [module docs](self).
+///
+/// The database is opened read-only. Fails only if the database can't be opened or read, and
+/// reports corruption through the returned [`Report`].
+pub fn check<T: HashAlgorithm>(path: impl AsRef<Path>) -> anyhow::Result<Report> {
+    let mut o = Options::new();
+    o.path(path.as_ref());
+    o.read_only(true);
+    let nomt = Nomt::<T>::open(o)?;
+    check_nomt(&nomt)
+}
+
+/// Check the integrity of an open database, like [`check`].
+///
+/// Commits held back by [`Options::commit_coalescing`] are flushed first. Must not run
+/// concurrently with commits.
+pub fn check_nomt<T: HashAlgorithm>(nomt: &Nomt<T>) -> anyhow::Result<Report> {
+    nomt.flush()?;
+    let mut walker = Walker {
+        nomt,
+        pages: HashMap::new(),
+        visited: HashSet::new(),
+        leaves: Vec::new(),
+        report: Report {
+            root: nomt.root(),
+            internal_nodes: 0,
+            leaves: 0,
+            pages: 0,
+            values: 0,
+            buckets: 0,
+            issues: Vec::new(),
+        },
+    };
+    walker.walk(TriePosition::new(), nomt.root())?;
+
+    let Walker {
+        visited,
+        mut leaves,
+        mut report,
+        ..
+    } = walker;
+    check_values::<T>(nomt, &mut leaves, &mut report);
+    check_buckets(nomt, &visited, &mut report)?;
+    Ok(report)
+}
+
+/// Walks the trie depth-first, keeping only the pages on the current path loaded.
+struct Walker<'a, T: HashAlgorithm> {
+    nomt: &'a Nomt<T>,
+    pages: HashMap<PageId, Option<FatPage>>,
+    /// The encoded IDs of the pages read.
+    visited: HashSet<[u8; 32]>,
+    /// The key and value hash of every leaf.
+    leaves: Vec<(KeyPath, ValueHash)>,
+    report: Report,
+}
+
+impl<T: HashAlgorithm> Walker<'_, T> {
+    fn walk(&mut self, position: TriePosition, node: Node) -> anyhow::Result<()> {
+        if trie::is_terminator(&node) {
+            return Ok(());
+        }
+
+        // The children of a node at the bottom of a page, and the leaf data of a leaf there, are
+        // stored in the child page.
+        let child_page = if (position.depth() as usize).is_multiple_of(DEPTH) {
+            let page_id = match position.page_id() {
+                None => ROOT_PAGE_ID,
+                Some(page_id) => match page_id.child_page_id(position.child_page_index()) {
+                    Ok(child_page_id) => child_page_id,
+                    Err(_) => {
+                        self.report.issues.push(Issue::TooDeep {
+                            position: position.path().to_bitvec(),
+                        });
+                        return Ok(());
+                    }
+                },
+            };
+            if !self.load(&page_id)? {
+                self.report.issues.push(Issue::MissingPage {
+                    page_id: page_id.encode(),
+                });
+                self.pages.remove(&page_id);
+                return Ok(());
+            }
+            Some(page_id)
+        } else {
+            None
+        };
+
+        if trie::is_leaf(&node) {
+            self.check_leaf(&position, node);
+        } else if position.depth() as usize >= 256 {
+            self.report.issues.push(Issue::TooDeep {
+                position: position.path().to_bitvec(),
+            });
+        } else {
+            self.report.internal_nodes += 1;
+            let mut children = [position.clone(), position.clone()];
+            children[0].down(false);
+            children[1].down(true);
+            let left = self.node(&children[0]);
+            let right = self.node(&children[1]);
+            let computed = T::hash_internal(&InternalData { left, right });
+            if computed != node {
+                self.report.issues.push(Issue::InternalHashMismatch {
+                    position: position.path().to_bitvec(),
+                    stored: node,
+                    computed,
+                });
+            }
+            let [left_position, right_position] = children;
+            self.walk(left_position, left)?;
+            self.walk(right_position, right)?;
+        }
+
+        if let Some(page_id) = child_page {
+            self.pages.remove(&page_id);
+        }
+        Ok(())
+    }
+
+    fn check_leaf(&mut self, position: &TriePosition, node: Node) {
+        self.report.leaves += 1;
+        let leaf = self.leaf_data(position);
+        let path = position.path();
+        if T::hash_leaf(&leaf) != node {
+            self.report.issues.push(Issue::LeafHashMismatch {
+                position: path.to_bitvec(),
+                key_path: leaf.key_path,
+            });
+        }
+        if !leaf.key_path.view_bits::<Msb0>().starts_with(path) {
+            self.report.issues.push(Issue::MisplacedLeaf {
+                position: path.to_bitvec(),
+                key_path: leaf.key_path,
+            });
+        }
+        self.leaves.push((leaf.key_path, leaf.value_hash));
+    }
+
+    /// Load the page unless it is loaded already. Returns whether it exists.
+    fn load(&mut self, page_id: &PageId) -> anyhow::Result<bool> {
+        if let Some(page) = self.pages.get(page_id) {
+            return Ok(page.is_some());
+        }
+        let page = self.nomt.store.load_page(page_id.clone())?;
+        if page.is_some() {
+            self.report.pages += 1;
+            self.visited.insert(page_id.encode());
+        }
+        let exists = page.is_some();
+        self.pages
+            .insert(page_id.clone(), page.map(|(page, _)| page));
+        Ok(exists)
+    }
+
+    // The pages on the path to the position are loaded by `walk`.
+    fn node(&self, position: &TriePosition) -> Node {
+        match position.page_id() {
+            None => self.nomt.root(),
+            Some(page_id) => self.slot(&page_id, position.node_index()),
+        }
+    }
+
+    // The leaf data of a leaf node is stored in its two child slots.
+    fn leaf_data(&self, position: &TriePosition) -> LeafData {
+        let (page_id, index) = match position.page_id() {
+            None => (ROOT_PAGE_ID, 0),
+            // UNWRAP: `walk` checked that the child page exists.
+            Some(page_id) if position.depth_in_page() == DEPTH => (
+                page_id.child_page_id(position.child_page_index()).unwrap(),
+                0,
+            ),
+            Some(page_id) => (page_id, position.child_node_indices().left()),
+        };
+        LeafData {
+            key_path: self.slot(&page_id, index),
+            value_hash: self.slot(&page_id, index + 1),
+        }
+    }
+
+    fn slot(&self, page_id: &PageId, index: usize) -> [u8; 32] {
+        let mut slot = [0; 32];
+        if let Some(Some(page)) = self.pages.get(page_id) {
+            slot.copy_from_slice(&page[index * 32..][..32]);
+        }
+        slot
+    }
+}
+
+/// Match the leaves against the values of the b-tree, both in key order.
+fn check_values<T: HashAlgorithm>(
+    nomt: &Nomt<T>,
+    leaves: &mut Vec<(KeyPath, ValueHash)>,
+    report: &mut Report,
+) {
+    // Misplaced leaves may break the order of the walk.
+    leaves.sort_unstable_by_key(|(key_path, _)| *key_path);
+    let mut leaves = leaves.drain(..).peekable();
+    let mut next_start = Some([0; 32]);
+    while let Some(start) = next_start {
+        let batch = nomt.store.load_value_range(start, [0xff; 32], BATCH_SIZE);
+        next_start = match batch.last() {
+            Some((last, _)) if batch.len() == BATCH_SIZE => next_key(*last),
+            _ => None,
+        };
+        for (key_path, value) in batch {
+            report.values += 1;
+            while let Some((missing, _)) = leaves.next_if(|(leaf_key, _)| *leaf_key < key_path) {
+                report
+                    .issues
+                    .push(Issue::MissingValue { key_path: missing });
+            }
+            match leaves.next_if(|(leaf_key, _)| *leaf_key == key_path) {
+                Some((_, value_hash)) if T::hash_value(&value) != value_hash => {
+                    report.issues.push(Issue::ValueHashMismatch { key_path })
+                }
+                Some(_) => {}
+                None => report.issues.push(Issue::OrphanedValue { key_path }),
+            }
+        }
+    }
+    for (key_path, _) in leaves {
+        report.issues.push(Issue::MissingValue { key_path });
+    }
+}
+
+/// Check the buckets of the hash-table, and that they hold exactly the pages reachable from the
+/// root.
+fn check_buckets<T: HashAlgorithm>(
+    nomt: &Nomt<T>,
+    visited: &HashSet<[u8; 32]>,
+    report: &mut Report,
+) -> anyhow::Result<()> {
+    let mut seen = HashSet::new();
+    for checked in nomt.store.check_buckets()? {
+        report.buckets += 1;
+        let (bucket, page_id) = (checked.bucket, checked.page_id);
+        if PageId::decode(page_id).is_err() {
+            report.issues.push(Issue::InvalidPageId { bucket });
+            continue;
+        }
+        if !checked.meta_matches {
+            report
+                .issues
+                .push(Issue::MetaMapMismatch { bucket, page_id });
+        }
+        if !checked.reachable {
+            report
+                .issues
+                .push(Issue::UnreachableBucket { bucket, page_id });
+        }
+        if !seen.insert(page_id) {
+            report.issues.push(Issue::DuplicatePage { bucket, page_id });
+        } else if !visited.contains(&page_id) {
+            report.issues.push(Issue::OrphanedPage { bucket, page_id });
+        }
+    }
+    Ok(())
+}
+
+fn bits(bits: &BitSlice<u8, Msb0>) -> String {
+    bits.iter()
+        .map(|bit| if *bit { '1' } else { '0' })
+        .collect()
+}
+
+fn hex(bytes: &[u8; 32]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,40 @@
+//! Encryption of pages at rest. See [`crate::Options::encryption_key`].
+//!
+//! Pages are encrypted with AES-256 in XTS mode, using the page number within its file as the
+//! tweak. XTS is length-preserving, so encrypted pages have the same size and layout on disk as
+//! plain ones. It gives confidentiality only: pages are not authenticated, and rewriting a page with
+//! the same contents at the same position produces the same ciphertext.
+//!
+//! All-zero pages are left as they are, both ways. Files are extended with zeros, e.g. the
+//! hash-table with empty buckets, so that unwritten pages read back as zeros like without
+//! encryption.
+
+#[cfg(feature = "encryption")]
+pub use xts::PageCipher;
+
+#[cfg(feature = "encryption")]
+mod xts;
+
+/// Stands in for the cipher without the `encryption` feature. It has no values, so pages are
+/// never encrypted.
+#[cfg(not(feature = "encryption"))]
+pub enum PageCipher {}
+
+#[cfg(not(feature = "encryption"))]
+impl PageCipher {
+    pub fn encrypt(&self, _pn: u64, _page: &mut [u8]) {
+        match *self {}
+    }
+
+    pub fn decrypt(&self, _pn: u64, _page: &mut [u8]) {
+        match *self {}
+    }
+}
+
+impl PageCipher {
+    /// A value which identifies the key without revealing it, recorded in the manifest to detect
+    /// opening the database with the wrong key.
+    pub fn key_check(key: &[u8; 32]) -> [u8; 32] {
+        blake3::derive_key("nomt page encryption 2024 key check", key)
+    }
+}
@@ -0,0 +1,127 @@
+//! Copy-on-write preservation of files written through the I/O pool.
+//!
+//! A file registered here is copied to a destination chunk by chunk while writes to it continue.
+//! Before a write through the I/O pool lands on a chunk which hasn't been copied yet, the chunk is
+//! copied, so that the destination ends up holding the file as it was at registration.
+
+use super::{IoKind, PagePool, PooledBuf, PAGE_SIZE};
+use parking_lot::{Mutex, RwLock};
+use std::{
+    fs::File,
+    os::fd::RawFd,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// The number of pages copied at once.
+const CHUNK_PAGES: u64 = 256;
+
+/// The files currently being copied. Shared by all handles of an I/O pool.
+#[derive(Default)]
+pub struct CowFiles {
+    files: RwLock<Vec<Arc<CowFile>>>,
+}
+
+impl CowFiles {
+    /// Start copying the file written through the I/O pool via `fd` to `dst`. `src` must be a
+    /// separate descriptor of the same file, used for reading. Chunks are copied through buffers
+    /// allocated from the given pool.
+    ///
+    /// The caller must ensure that no writes to the file are in flight.
+    pub fn register(
+        &self,
+        fd: RawFd,
+        src: File,
+        dst: File,
+        page_pool: PagePool,
+    ) -> std::io::Result<Arc<CowFile>> {
+        let len = src.metadata()?.len();
+        dst.set_len(len)?;
+        let chunks = len.div_ceil(CHUNK_PAGES * PAGE_SIZE as u64);
+        let file = Arc::new(CowFile {
+            fd,
+            src,
+            dst,
+            len,
+            page_pool,
+            copied: Mutex::new(vec![false; chunks as usize]),
+            failed: AtomicBool::new(false),
+        });
+        self.files.write().push(file.clone());
+        Ok(file)
+    }
+
+    /// Stop preserving the pages of the given file.
+    pub fn unregister(&self, file: &Arc<CowFile>) {
+        self.files.write().retain(|f| !Arc::ptr_eq(f, file));
+    }
+
+    /// Whether no file is being copied.
+    pub fn is_empty(&self) -> bool {
+        self.files.read().is_empty()
+    }
+
+    /// Copy the chunk about to be overwritten by the given I/O, if any.
+    pub fn before_io(&self, kind: &IoKind) {
+        let (fd, pn) = match *kind {
+            IoKind::Write(fd, pn, _) | IoKind::WriteRaw(fd, pn, _) => (fd, pn),
+            IoKind::Read(..) => return,
+        };
+        let files = self.files.read();
+        for file in files.iter().filter(|file| file.fd == fd) {
+            if file.copy_chunk(pn / CHUNK_PAGES).is_err() {
+                // Reported by `CowFile::copy_all`. Failing the write would corrupt the database.
+                file.failed.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// A file being copied.
+pub struct CowFile {
+    fd: RawFd,
+    src: File,
+    dst: File,
+    /// The length of the file at registration, in bytes.
+    len: u64,
+    page_pool: PagePool,
+    copied: Mutex<Vec<bool>>,
+    failed: AtomicBool,
+}
+
+impl CowFile {
+    /// Copy all chunks not yet copied and sync the destination.
+    pub fn copy_all(&self) -> std::io::Result<()> {
+        let chunks = self.copied.lock().len() as u64;
+        for chunk in 0..chunks {
+            self.copy_chunk(chunk)?;
+        }
+        if self.failed.load(Ordering::Relaxed) {
+            return Err(std::io::Error::other(
+                "failed to preserve overwritten pages",
+            ));
+        }
+        self.dst.sync_all()
+    }
+
+    fn copy_chunk(&self, chunk: u64) -> std::io::Result<()> {
+        let mut copied = self.copied.lock();
+        // Pages past the length at registration were not part of the file.
+        if copied.get(chunk as usize) != Some(&false) {
+            return Ok(());
+        }
+        let start = chunk * CHUNK_PAGES * PAGE_SIZE as u64;
+        let end = self.len.min(start + CHUNK_PAGES * PAGE_SIZE as u64);
+        let buf = PooledBuf::read_at(
+            self.page_pool.clone(),
+            &self.src,
+            start,
+            (end - start) as usize,
+        )?;
+        buf.write_at(&self.dst, start)?;
+        copied[chunk as usize] = true;
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+//! Simulated power failures for testing crash consistency.
+//!
+//! The simulator keeps the content every page written through the I/O pool had when its file was
+//! last synced. At the configured sync point it fails the sync, and every later sync and WAL write,
+//! so that the commit in progress never completes. Once the database has been dropped,
+//! [`CrashSimulator::power_loss`] puts the files into a state a power failure at that point could
+//! have left them in: every page written since the last sync of its file is kept, dropped or torn,
+//! independently of the others, which also covers writes reaching the disk out of order. Pages
+//! written after the crash are always dropped. The WAL and the manifest are not written through the
+//! I/O pool and are left as written.
+
+use super::{IoKind, PAGE_SIZE};
+use crate::{FaultInjector, SyncedFile};
+use parking_lot::Mutex;
+use rand::{rngs::StdRng, Rng as _, SeedableRng as _};
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    os::{fd::RawFd, unix::fs::FileExt as _},
+    path::Path,
+    sync::Arc,
+};
+
+/// The size of the unit a torn page write is split into.
+const SECTOR_SIZE: usize = 512;
+
+/// The sync at which a [`CrashSimulator`] crashes: the `nth` sync of `file`, counting from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrashPoint {
+    /// The file whose sync fails.
+    pub file: SyncedFile,
+    /// The number of the sync of `file` which fails, counting from 1.
+    pub nth: usize,
+}
+
+/// What [`CrashSimulator::power_loss`] did with the pages written since the last sync of their
+/// files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PowerLoss {
+    /// Pages left as written.
+    pub persisted: usize,
+    /// Pages restored to their content at the last sync.
+    pub dropped: usize,
+    /// Pages of which only some leading sectors were written.
+    pub torn: usize,
+}
+
+/// Simulates a power failure at a sync point. See [`crate::Options::crash_simulator`].
+///
+/// The choice of the pages which are kept, dropped or torn is deterministic for a given seed and
+/// set of unsynced pages. Syncs of different files may run concurrently, so the set of unsynced
+/// pages at the crash point may vary between runs.
+pub struct CrashSimulator {
+    crash_point: CrashPoint,
+    seed: u64,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    crashed: bool,
+    /// The number of syncs so far, by file.
+    syncs: Vec<(SyncedFile, usize)>,
+    /// The files written through the I/O pool, opened separately for preserving their pages.
+    files: Vec<RegisteredFile>,
+    /// The pages written since the last sync of their file, by file index and page number.
+    unsynced: BTreeMap<(usize, u64), UnsyncedPage>,
+}
+
+struct RegisteredFile {
+    synced_file: SyncedFile,
+    fd: RawFd,
+    file: File,
+}
+
+struct UnsyncedPage {
+    /// The content at the last sync.
+    synced: Vec<u8>,
+    /// The content at the crash, if the page was written again after it.
+    at_crash: Option<Vec<u8>>,
+}
+
+impl CrashSimulator {
+    /// Create a simulator crashing at the given sync point.
+    pub fn new(crash_point: CrashPoint, seed: u64) -> Arc<Self> {
+        Arc::new(CrashSimulator {
+            crash_point,
+            seed,
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// Whether the crash point has been reached.
+    pub fn has_crashed(&self) -> bool {
+        self.state.lock().crashed
+    }
+
+    /// Leave the files in a state a power failure at the crash point could have left them in.
+    ///
+    /// Call this after the crash, once the database has been dropped. Does nothing if the crash
+    /// point has not been reached.
+    pub fn power_loss(&self) -> std::io::Result<PowerLoss> {
+        let mut state = self.state.lock();
+        let mut outcome = PowerLoss::default();
+        if !state.crashed {
+            return Ok(outcome);
+        }
+
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let unsynced = std::mem::take(&mut state.unsynced);
+        for ((file_index, pn), page) in unsynced {
+            let file = &state.files[file_index].file;
+            let offset = pn * PAGE_SIZE as u64;
+            let written = match page.at_crash {
+                Some(at_crash) => at_crash,
+                None => read_page(file, pn)?,
+            };
+            let persisted_sectors = match rng.gen_range(0..3) {
+                0 => {
+                    outcome.persisted += 1;
+                    PAGE_SIZE / SECTOR_SIZE
+                }
+                1 => {
+                    outcome.dropped += 1;
+                    0
+                }
+                _ => {
+                    outcome.torn += 1;
+                    rng.gen_range(1..PAGE_SIZE / SECTOR_SIZE)
+                }
+            };
+            let mut content = page.synced;
+            let split = persisted_sectors * SECTOR_SIZE;
+            content[..split].copy_from_slice(&written[..split]);
+            file.write_all_at(&content, offset)?;
+        }
+        for registered in &state.files {
+            registered.file.sync_all()?;
+        }
+        Ok(outcome)
+    }
+
+    /// Open the file written through the I/O pool via `fd` separately, for preserving its pages.
+    pub(crate) fn register(
+        &self,
+        synced_file: SyncedFile,
+        fd: RawFd,
+        path: &Path,
+    ) -> std::io::Result<()> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        self.state.lock().files.push(RegisteredFile {
+            synced_file,
+            fd,
+            file,
+        });
+        Ok(())
+    }
+
+    /// Preserve the page about to be overwritten by the given I/O, if it wasn't yet since the
+    /// last sync of its file.
+    pub(crate) fn before_io(&self, kind: &IoKind) {
+        let (fd, pn) = match *kind {
+            IoKind::Write(fd, pn, _) | IoKind::WriteRaw(fd, pn, _) => (fd, pn),
+            IoKind::Read(..) => return,
+        };
+        let mut state = self.state.lock();
+        let Some(file_index) = state.files.iter().position(|file| file.fd == fd) else {
+            return;
+        };
+        let crashed = state.crashed;
+        let key = (file_index, pn);
+        let preserved = match state.unsynced.get(&key) {
+            Some(page) => !crashed || page.at_crash.is_some(),
+            None => false,
+        };
+        if preserved {
+            return;
+        }
+
+        // Failing to read the page would make the simulation unsound, so give up loudly.
+        let content = read_page(&state.files[file_index].file, pn)
+            .expect("failed to preserve a page for crash simulation");
+        match state.unsynced.get_mut(&key) {
+            Some(page) => page.at_crash = Some(content),
+            None => {
+                // A page first written after the crash never reaches the disk.
+                let at_crash = crashed.then(|| content.clone());
+                let page = UnsyncedPage {
+                    synced: content,
+                    at_crash,
+                };
+                state.unsynced.insert(key, page);
+            }
+        }
+    }
+}
+
+impl FaultInjector for CrashSimulator {
+    fn wal_write(&self, _sync_seqn: u32, _wal_blob: &[u8]) -> std::io::Result<()> {
+        if self.state.lock().crashed {
+            return Err(crashed());
+        }
+        Ok(())
+    }
+
+    fn fsync(&self, file: SyncedFile) -> std::io::Result<()> {
+        let mut state = self.state.lock();
+        if state.crashed {
+            return Err(crashed());
+        }
+
+        let index = match state.syncs.iter().position(|(f, _)| *f == file) {
+            Some(index) => index,
+            None => {
+                state.syncs.push((file, 0));
+                state.syncs.len() - 1
+            }
+        };
+        state.syncs[index].1 += 1;
+        if file == self.crash_point.file && state.syncs[index].1 == self.crash_point.nth {
+            state.crashed = true;
+            return Err(crashed());
+        }
+
+        // The pages of the file are on disk once the sync completes.
+        if let Some(file_index) = state.files.iter().position(|f| f.synced_file == file) {
+            state.unsynced.retain(|(index, _), _| *index != file_index);
+        }
+        Ok(())
+    }
+}
+
+fn crashed() -> std::io::Error {
+    std::io::Error::other("simulated power failure")
+}
+
+/// Read a page through a buffered descriptor. Pages past the end of the file read as zeros.
+fn read_page(file: &File, pn: u64) -> std::io::Result<Vec<u8>> {
+    let mut content = vec![0; PAGE_SIZE];
+    let offset = pn * PAGE_SIZE as u64;
+    let mut read = 0;
+    while read < PAGE_SIZE {
+        match file.read_at(&mut content[read..], offset + read as u64)? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(content)
+}
@@ -1,3 +1,5 @@
+use super::IoPool;
+use crate::SyncedFile;
 use parking_lot::{Condvar, Mutex};
 use std::{fs::File, sync::Arc};
 
@@ -37,7 +39,14 @@ pub struct Fsyncer {
 
 impl Fsyncer {
     /// Creates a new fsyncer with the given file descriptor and identifier.
-    pub fn new(name: &'static str, fd: Arc<File>) -> Self {
+    ///
+    /// The file is synced through the I/O pool, see [`IoPool::sync_file`].
+    pub fn new(
+        name: &'static str,
+        fd: Arc<File>,
+        synced_file: SyncedFile,
+        io_pool: IoPool,
+    ) -> Self {
         let name = format!("nomt-fsyncer-{}", name);
         let shared = Arc::new(Shared {
             cv: Condvar::new(),
@@ -48,7 +57,7 @@ impl Fsyncer {
             .spawn({
                 let shared = shared.clone();
                 move || {
-                    worker(fd, shared);
+                    worker(fd, synced_file, io_pool, shared);
                 }
             })
             .expect("failed to spawn fsyncer thread");
@@ -90,7 +99,7 @@ impl Drop for Fsyncer {
     }
 }
 
-fn worker(fd: Arc<File>, shared: Arc<Shared>) {
+fn worker(fd: Arc<File>, synced_file: SyncedFile, io_pool: IoPool, shared: Arc<Shared>) {
     let bomb = Bomb;
     'outer: loop {
         let mut s_guard = shared.s.lock();
@@ -103,7 +112,7 @@ fn worker(fd: Arc<File>, shared: Arc<Shared>) {
         assert!(matches!(&*s_guard, State::Started | State::Done(_)));
         drop(s_guard);
 
-        let sync_result = fd.sync_all();
+        let sync_result = io_pool.sync_file(synced_file, &fd);
 
         let mut s_guard = shared.s.lock();
         if matches!(&*s_guard, State::HandleDead) {
@@ -1,48 +1,90 @@
-use super::{CompleteIo, IoCommand, IoKind, IoKindResult, IoPacket, PAGE_SIZE};
-use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use super::{
+    CompleteIo, CompletionSender, Encryption, FatPage, IoCommand, IoKind, IoKindResult, IoPacket,
+    IoQueues, PAGE_SIZE,
+};
+use crate::{background_error::BackgroundErrors, threads, IoUringMode, ThreadConfig};
+use crossbeam_channel::TryRecvError;
 use io_uring::{cqueue, opcode, squeue, types, IoUring};
 use slab::Slab;
-use std::collections::VecDeque;
-
-const RING_CAPACITY: u32 = 128;
-
-// max number of inflight requests is bounded by the slab.
-const MAX_IN_FLIGHT: usize = RING_CAPACITY as usize;
+use std::{
+    collections::VecDeque,
+    os::fd::{AsRawFd as _, RawFd},
+};
 
 struct PendingIo {
     command: IoCommand,
-    completion_sender: Sender<CompleteIo>,
+    completion_sender: CompletionSender,
+    /// The encrypted copy of the page written, kept alive until the write completes.
+    ciphertext: Option<FatPage>,
 }
 
-pub fn start_io_worker(io_workers: usize, iopoll: bool) -> Sender<IoPacket> {
-    // main bound is from the pending slab.
-    let (command_tx, command_rx) = crossbeam_channel::unbounded();
-
-    start_workers(command_rx, io_workers, iopoll);
-
-    command_tx
-}
-
-fn start_workers(command_rx: Receiver<IoPacket>, io_workers: usize, iopoll: bool) {
+pub fn start_io_workers(
+    queues: IoQueues,
+    io_workers: usize,
+    mode: IoUringMode,
+    threads: &ThreadConfig,
+    encryption: Option<Encryption>,
+    background_errors: &BackgroundErrors,
+) -> anyhow::Result<()> {
+    // The rings are built upfront so that all of them can share the SQPOLL thread of the first.
+    // They are sized for the limit set at opening, which later changes can't exceed.
+    let max_in_flight = queues.tuning.limits().max_in_flight;
+    let mut first_ring_fd = None;
     for i in 0..io_workers {
-        let command_rx = command_rx.clone();
-        let _ = std::thread::Builder::new()
-            .name(format!("io_worker-{i}"))
-            .spawn(move || run_worker(command_rx, iopoll))
-            .unwrap();
+        let ring = build_ring(mode, first_ring_fd, max_in_flight);
+        first_ring_fd.get_or_insert(ring.as_raw_fd());
+
+        let queues = queues.clone();
+        let encryption = encryption.clone();
+        let background_errors = background_errors.clone();
+        threads::spawn(
+            threads::thread_name(threads, &format!("io_worker-{i}")),
+            &threads.io,
+            move || {
+                super::run_reporting_panics(&background_errors, || {
+                    run_worker(queues, ring, max_in_flight, encryption)
+                })
+            },
+        )?;
     }
+    Ok(())
 }
 
-fn run_worker(command_rx: Receiver<IoPacket>, iopoll: bool) {
-    let mut pending: Slab<PendingIo> = Slab::with_capacity(MAX_IN_FLIGHT);
-
+fn build_ring(mode: IoUringMode, attach_to: Option<RawFd>, max_in_flight: usize) -> IoUring {
     let mut ring_builder = IoUring::<squeue::Entry, cqueue::Entry>::builder();
-    if iopoll {
-        ring_builder.setup_iopoll();
+    match mode {
+        IoUringMode::Interrupt => {}
+        IoUringMode::IoPoll => {
+            ring_builder.setup_iopoll();
+        }
+        IoUringMode::Cooperative => {
+            ring_builder.setup_iopoll().setup_coop_taskrun();
+        }
+        IoUringMode::SqPoll { idle_ms, cpu } => {
+            ring_builder.setup_iopoll().setup_sqpoll(idle_ms);
+            if let Some(cpu) = cpu {
+                ring_builder.setup_sqpoll_cpu(cpu);
+            }
+            if let Some(fd) = attach_to {
+                ring_builder.setup_attach_wq(fd);
+            }
+        }
     }
-    let mut ring = ring_builder
-        .build(RING_CAPACITY)
-        .expect("Error building io_uring");
+    // The submission queue must hold all in-flight requests. The kernel rounds the size up to a
+    // power of two anyway.
+    ring_builder
+        .build(max_in_flight.next_power_of_two() as u32)
+        .expect("Error building io_uring")
+}
+
+fn run_worker(
+    mut queues: IoQueues,
+    mut ring: IoUring,
+    ring_in_flight: usize,
+    encryption: Option<Encryption>,
+) {
+    // max number of inflight requests is bounded by the slab.
+    let mut pending: Slab<PendingIo> = Slab::with_capacity(ring_in_flight);
 
     let (submitter, mut submit_queue, mut complete_queue) = ring.split();
     let mut retries = VecDeque::<IoPacket>::new();
@@ -56,8 +98,9 @@ fn run_worker(command_rx: Receiver<IoPacket>, iopoll: bool) {
                     continue;
                 }
                 let PendingIo {
-                    command,
+                    mut command,
                     completion_sender,
+                    ciphertext: _,
                 } = pending.remove(completion_event.user_data() as usize);
 
                 // io_uring never uses errno to pass back error information.
@@ -70,6 +113,7 @@ fn run_worker(command_rx: Receiver<IoPacket>, iopoll: bool) {
                 let result = match command.kind.get_result(syscall_result as isize) {
                     IoKindResult::Ok => Ok(()),
                     IoKindResult::Err => Err(std::io::Error::from_raw_os_error(io_uring_res.abs())),
+                    // A retry encrypts the page again.
                     IoKindResult::Retry => {
                         retries.push_back(IoPacket {
                             command,
@@ -79,59 +123,80 @@ fn run_worker(command_rx: Receiver<IoPacket>, iopoll: bool) {
                     }
                 };
 
+                if let (Ok(()), Some(encryption)) = (&result, &encryption) {
+                    encryption.decrypt(&mut command.kind);
+                }
                 let complete = CompleteIo { command, result };
-                let _ = completion_sender.send(complete);
+                completion_sender.send(complete);
             }
         }
 
         // 2. accept new I/O requests when slab has space & submission queue is not full.
-        let mut to_submit = false;
+        // The limits may have changed since the last round.
+        let limits = queues.tuning.limits();
+        let max_in_flight = limits.max_in_flight.min(ring_in_flight);
+        let mut to_submit = 0;
 
         submit_queue.sync();
-        while pending.len() < MAX_IN_FLIGHT && !submit_queue.is_full() {
+        while pending.len() < max_in_flight && !submit_queue.is_full() {
             let next_io = if !retries.is_empty() {
                 // re-apply partially failed reads and writes
                 // unwrap: known not empty
                 retries.pop_front().unwrap()
             } else if pending.is_empty() {
                 // block on new I/O if nothing in-flight.
-                match command_rx.recv() {
+                match queues.recv() {
                     Ok(command) => command,
                     Err(_) => break, // disconnected
                 }
             } else {
-                match command_rx.try_recv() {
+                match queues.try_recv() {
                     Ok(command) => command,
                     Err(TryRecvError::Empty) => break,
                     Err(TryRecvError::Disconnected) => break, // TODO: wait on pending I/O?
                 }
             };
 
-            to_submit = true;
+            let ciphertext = encryption
+                .as_ref()
+                .and_then(|encryption| encryption.encrypt(&next_io.command.kind));
             let pending_index = pending.insert(PendingIo {
                 command: next_io.command,
                 completion_sender: next_io.completion_sender,
+                ciphertext,
             });
 
-            let entry = submission_entry(&mut pending.get_mut(pending_index).unwrap().command)
+            let pending_io = pending.get_mut(pending_index).unwrap();
+            let entry = submission_entry(&mut pending_io.command, pending_io.ciphertext.as_ref())
                 .user_data(pending_index as u64);
 
             // unwrap: known not full
             unsafe { submit_queue.push(&entry).unwrap() };
+
+            // hand full batches to the kernel right away, so it can start on them while the
+            // rest are prepared.
+            to_submit += 1;
+            if to_submit == limits.submit_batch {
+                submit_queue.sync();
+                submitter.submit().unwrap();
+                to_submit = 0;
+            }
         }
 
-        // 3. submit all together.
-        if to_submit {
+        // 3. submit the remainder all together.
+        if to_submit > 0 {
             submit_queue.sync();
         }
 
-        let wait = if pending.len() == MAX_IN_FLIGHT { 1 } else { 0 };
+        let wait = if pending.len() >= max_in_flight { 1 } else { 0 };
 
         submitter.submit_and_wait(wait).unwrap();
     }
 }
 
-fn submission_entry(command: &mut IoCommand) -> squeue::Entry {
+/// The submission of the command, writing the given encrypted copy of the page instead of the page
+/// itself, if any.
+fn submission_entry(command: &mut IoCommand, ciphertext: Option<&FatPage>) -> squeue::Entry {
     match command.kind {
         IoKind::Read(fd, page_index, ref mut page) => {
             opcode::Read::new(types::Fd(fd), page.as_mut_ptr(), PAGE_SIZE as u32)
@@ -139,12 +204,14 @@ fn submission_entry(command: &mut IoCommand) -> squeue::Entry {
                 .build()
         }
         IoKind::Write(fd, page_index, ref page) => {
-            opcode::Write::new(types::Fd(fd), page.as_ptr(), PAGE_SIZE as u32)
+            let buf = ciphertext.map_or(page.as_ptr(), |c| c.as_ptr());
+            opcode::Write::new(types::Fd(fd), buf, PAGE_SIZE as u32)
                 .offset(page_index * PAGE_SIZE as u64)
                 .build()
         }
         IoKind::WriteRaw(fd, page_index, ref page) => {
-            opcode::Write::new(types::Fd(fd), page.as_ptr(), PAGE_SIZE as u32)
+            let buf = ciphertext.map_or(page.as_ptr(), |c| c.as_ptr());
+            opcode::Write::new(types::Fd(fd), buf, PAGE_SIZE as u32)
                 .offset(page_index * PAGE_SIZE as u64)
                 .build()
         }
@@ -1,24 +1,37 @@
 #[cfg(not(target_family = "unix"))]
 std::compile_error!("NOMT only supports Unix-based OSs");
 
-use crossbeam_channel::{Receiver, RecvError, SendError, Sender, TryRecvError};
+use crate::background_error::{BackgroundErrorSource, BackgroundErrors};
+use crate::{Durability, FaultInjector, IoBackend, IoUringMode, PageIo, SyncedFile, ThreadConfig};
+use crossbeam_channel::{Receiver, RecvError, Select, SendError, Sender, TryRecvError};
 use page_pool::Page;
-use std::{fmt, fs::File, os::fd::RawFd};
+use std::{
+    fmt,
+    fs::File,
+    os::fd::RawFd,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 #[cfg(target_os = "linux")]
-#[path = "linux.rs"]
-mod platform;
-
-#[cfg(not(target_os = "linux"))]
-#[path = "unix.rs"]
-mod platform;
+mod linux;
+mod posix;
 
+pub mod cipher;
+pub mod cow;
+#[cfg(feature = "crash-simulation")]
+pub mod crash;
 pub mod fsyncer;
 pub mod page_pool;
+pub mod pooled_buf;
 
 pub const PAGE_SIZE: usize = 4096;
 
+pub use cipher::PageCipher;
 pub use page_pool::{FatPage, PagePool};
+pub use pooled_buf::PooledBuf;
 
 pub enum IoKind {
     Read(RawFd, u64, FatPage),
@@ -77,6 +90,39 @@ impl IoKind {
 
 unsafe impl Send for IoKind {}
 
+/// Encrypts the pages written and decrypts the pages read by the workers of an I/O pool.
+#[derive(Clone)]
+struct Encryption {
+    cipher: Arc<PageCipher>,
+    page_pool: PagePool,
+}
+
+impl Encryption {
+    /// The encrypted copy of the page written by the command, if it is a write. Pages of writes
+    /// may be read concurrently, so they can't be encrypted in place.
+    fn encrypt(&self, kind: &IoKind) -> Option<FatPage> {
+        let (pn, page) = match *kind {
+            IoKind::Read(..) => return None,
+            IoKind::Write(_, pn, ref page) => (pn, &page[..]),
+            // SAFETY: the page of a raw write is kept alive until its completion, and only read.
+            IoKind::WriteRaw(_, pn, ref page) => (pn, unsafe {
+                std::slice::from_raw_parts(page.as_ptr(), PAGE_SIZE)
+            }),
+        };
+        let mut ciphertext = self.page_pool.alloc_fat_page();
+        ciphertext.copy_from_slice(page);
+        self.cipher.encrypt(pn, &mut ciphertext);
+        Some(ciphertext)
+    }
+
+    /// Decrypt the page read by the command, once it has completed.
+    fn decrypt(&self, kind: &mut IoKind) {
+        if let IoKind::Read(_, pn, ref mut page) = *kind {
+            self.cipher.decrypt(pn, page);
+        }
+    }
+}
+
 pub struct IoCommand {
     pub kind: IoKind,
     // note: this isn't passed to io_uring, it's higher-level userdata.
@@ -90,20 +136,297 @@ pub struct CompleteIo {
 
 struct IoPacket {
     command: IoCommand,
-    completion_sender: Sender<CompleteIo>,
+    completion_sender: CompletionSender,
+}
+
+/// Where the completion of an I/O command is delivered.
+enum CompletionSender {
+    /// The completion channel of an [`IoHandle`].
+    Channel(Sender<CompleteIo>),
+    /// A callback invoked on the I/O worker thread. See [`IoPool::send_with_callback`].
+    Callback(Box<dyn FnOnce(CompleteIo) + Send>),
 }
 
-/// Create an I/O worker managing an io_uring and sending responses back via channels to a number
+impl CompletionSender {
+    fn send(self, complete: CompleteIo) {
+        match self {
+            // The handle may have been dropped, in which case nobody waits for the completion.
+            CompletionSender::Channel(sender) => {
+                let _ = sender.send(complete);
+            }
+            CompletionSender::Callback(callback) => callback(complete),
+        }
+    }
+}
+
+/// Bounds on the I/O requests handled by a single io_uring instance.
+#[derive(Clone, Copy)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub struct IoLimits {
+    /// The number of requests pushed to the submission queue before submitting them.
+    pub submit_batch: usize,
+    /// The maximum number of requests in flight.
+    pub max_in_flight: usize,
+}
+
+/// The settings of an I/O pool which may be changed while it runs. Workers pick up changes the
+/// next time they take requests.
+pub struct IoTuning {
+    submit_batch: AtomicUsize,
+    max_in_flight: AtomicUsize,
+    power_loss_protected: AtomicBool,
+    prioritize_reads: AtomicBool,
+}
+
+impl IoTuning {
+    pub fn new(limits: IoLimits, durability: Durability, prioritize_reads: bool) -> Self {
+        IoTuning {
+            submit_batch: AtomicUsize::new(limits.submit_batch),
+            max_in_flight: AtomicUsize::new(limits.max_in_flight),
+            power_loss_protected: AtomicBool::new(durability == Durability::PowerLossProtected),
+            prioritize_reads: AtomicBool::new(prioritize_reads),
+        }
+    }
+
+    pub fn limits(&self) -> IoLimits {
+        IoLimits {
+            submit_batch: self.submit_batch.load(Ordering::Relaxed),
+            max_in_flight: self.max_in_flight.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Change the limits. The maximum number of requests in flight can't exceed the one the
+    /// io_uring instances were created with.
+    pub fn set_limits(&self, limits: IoLimits) {
+        self.submit_batch
+            .store(limits.submit_batch, Ordering::Relaxed);
+        self.max_in_flight
+            .store(limits.max_in_flight, Ordering::Relaxed);
+    }
+
+    pub fn durability(&self) -> Durability {
+        if self.power_loss_protected.load(Ordering::Relaxed) {
+            Durability::PowerLossProtected
+        } else {
+            Durability::Fsync
+        }
+    }
+
+    pub fn set_durability(&self, durability: Durability) {
+        self.power_loss_protected.store(
+            durability == Durability::PowerLossProtected,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Whether reads are taken before writes. See [`IoQueues`].
+    pub fn prioritize_reads(&self) -> bool {
+        self.prioritize_reads.load(Ordering::Relaxed)
+    }
+
+    pub fn set_prioritize_reads(&self, prioritize_reads: bool) {
+        self.prioritize_reads
+            .store(prioritize_reads, Ordering::Relaxed);
+    }
+}
+
+/// The queues of requests the workers of an I/O pool take from: one for reads and one for writes.
+///
+/// Reads are mostly made on behalf of sessions waiting for them, while writes are mostly the
+/// writeback of a sync in the background. With [`IoTuning::prioritize_reads`], queued reads are
+/// always taken first, so a sync writing many pages does not delay the reads of sessions.
+/// Otherwise, both queues are taken from in turns.
+struct IoQueues {
+    reads: Receiver<IoPacket>,
+    writes: Receiver<IoPacket>,
+    tuning: Arc<IoTuning>,
+    /// Whether writes are taken first next time, when reads are not prioritized.
+    writes_first: bool,
+}
+
+impl Clone for IoQueues {
+    fn clone(&self) -> Self {
+        IoQueues {
+            reads: self.reads.clone(),
+            writes: self.writes.clone(),
+            tuning: self.tuning.clone(),
+            writes_first: false,
+        }
+    }
+}
+
+impl IoQueues {
+    /// Take the next request, if any is queued.
+    fn try_recv(&mut self) -> Result<IoPacket, TryRecvError> {
+        let writes_first = !self.tuning.prioritize_reads() && self.writes_first;
+        self.writes_first = !self.writes_first;
+        let (first, second) = if writes_first {
+            (&self.writes, &self.reads)
+        } else {
+            (&self.reads, &self.writes)
+        };
+        match first.try_recv() {
+            Ok(packet) => Ok(packet),
+            Err(TryRecvError::Empty) => second.try_recv().map_err(|_| TryRecvError::Empty),
+            Err(TryRecvError::Disconnected) => second.try_recv(),
+        }
+    }
+
+    /// Block until a request is queued and take it. This fails once the pool has been dropped
+    /// and both queues are empty.
+    fn recv(&mut self) -> Result<IoPacket, RecvError> {
+        loop {
+            match self.try_recv() {
+                Ok(packet) => return Ok(packet),
+                Err(TryRecvError::Disconnected) => return Err(RecvError),
+                Err(TryRecvError::Empty) => {}
+            }
+            // Wait for either queue, then take from them in order of priority.
+            let mut select = Select::new();
+            select.recv(&self.reads);
+            select.recv(&self.writes);
+            select.ready();
+        }
+    }
+}
+
+/// The settings an I/O pool shares with all of its handles.
+pub struct IoPoolConfig {
+    /// The settings which may be changed while the pool runs.
+    pub tuning: IoTuning,
+    /// The cipher of pages at rest, if the database is encrypted.
+    pub cipher: Option<Arc<PageCipher>>,
+    /// The hook which may fail I/O submitted through the pool.
+    pub fault_injector: Option<Arc<dyn FaultInjector>>,
+    /// Where errors of the I/O workers are reported.
+    pub background_errors: BackgroundErrors,
+}
+
+/// Create the I/O workers of the given backend, sending responses back via channels to a number
 /// of handles.
-pub fn start_io_pool(io_workers: usize, iopoll: bool, page_pool: PagePool) -> IoPool {
-    let sender = platform::start_io_worker(io_workers, iopoll);
-    IoPool { sender, page_pool }
+pub fn start_io_pool(
+    io_workers: usize,
+    backend: IoBackend,
+    mode: IoUringMode,
+    threads: &ThreadConfig,
+    page_pool: PagePool,
+    config: IoPoolConfig,
+) -> anyhow::Result<IoPool> {
+    let IoPoolConfig {
+        tuning,
+        cipher,
+        fault_injector,
+        background_errors,
+    } = config;
+    let tuning = Arc::new(tuning);
+    let (reads, writes, queues) = io_queues(tuning.clone());
+    let encryption = cipher.clone().map(|cipher| Encryption {
+        cipher,
+        page_pool: page_pool.clone(),
+    });
+    start_io_workers(
+        io_workers,
+        backend,
+        mode,
+        threads,
+        queues,
+        encryption,
+        &background_errors,
+    )?;
+    Ok(IoPool {
+        reads,
+        writes,
+        page_pool,
+        cipher,
+        cow_files: Arc::default(),
+        fault_injector,
+        tuning,
+        background_errors,
+        #[cfg(feature = "crash-simulation")]
+        crash_simulator: None,
+    })
+}
+
+/// Create the queues of an I/O pool, returning the senders of reads and writes and the receiving
+/// end.
+fn io_queues(tuning: Arc<IoTuning>) -> (Sender<IoPacket>, Sender<IoPacket>, IoQueues) {
+    // main bound is from the pending slab of each worker.
+    let (reads_tx, reads) = crossbeam_channel::unbounded();
+    let (writes_tx, writes) = crossbeam_channel::unbounded();
+    let queues = IoQueues {
+        reads,
+        writes,
+        tuning,
+        writes_first: false,
+    };
+    (reads_tx, writes_tx, queues)
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+fn start_io_workers(
+    io_workers: usize,
+    backend: IoBackend,
+    mode: IoUringMode,
+    threads: &ThreadConfig,
+    queues: IoQueues,
+    encryption: Option<Encryption>,
+    background_errors: &BackgroundErrors,
+) -> anyhow::Result<()> {
+    match backend {
+        #[cfg(target_os = "linux")]
+        IoBackend::IoUring => linux::start_io_workers(
+            queues,
+            io_workers,
+            mode,
+            threads,
+            encryption,
+            background_errors,
+        ),
+        #[cfg(not(target_os = "linux"))]
+        IoBackend::IoUring => anyhow::bail!("the io_uring backend is only available on Linux"),
+        IoBackend::Posix => {
+            posix::start_io_workers(queues, io_workers, threads, encryption, background_errors)
+        }
+    }
+}
+
+/// Run the loop of an I/O worker, reporting it as a background error if it panics.
+fn run_reporting_panics(background_errors: &BackgroundErrors, worker: impl FnOnce()) {
+    if let Err(payload) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(worker)) {
+        background_errors.report_panic(BackgroundErrorSource::IoWorker, payload);
+    }
 }
 
 #[cfg(test)]
 pub fn start_test_io_pool(io_workers: usize, page_pool: PagePool) -> IoPool {
-    let sender = platform::start_io_worker(io_workers, false);
-    IoPool { sender, page_pool }
+    let limits = IoLimits {
+        submit_batch: 128,
+        max_in_flight: 128,
+    };
+    let tuning = Arc::new(IoTuning::new(limits, Durability::Fsync, false));
+    let (reads, writes, queues) = io_queues(tuning.clone());
+    start_io_workers(
+        io_workers,
+        IoBackend::default(),
+        IoUringMode::Interrupt,
+        &ThreadConfig::default(),
+        queues,
+        None,
+        &BackgroundErrors::default(),
+    )
+    .unwrap();
+    IoPool {
+        reads,
+        writes,
+        page_pool,
+        cipher: None,
+        cow_files: Arc::default(),
+        fault_injector: None,
+        tuning,
+        background_errors: BackgroundErrors::default(),
+        #[cfg(feature = "crash-simulation")]
+        crash_simulator: None,
+    }
 }
 
 /// A manager for the broader I/O pool. This can be used to create new I/O handles.
@@ -111,8 +434,16 @@ pub fn start_test_io_pool(io_workers: usize, page_pool: PagePool) -> IoPool {
 /// Dropping this does not close any outstanding I/O handles or shut down I/O workers.
 #[derive(Clone)]
 pub struct IoPool {
-    sender: Sender<IoPacket>,
+    reads: Sender<IoPacket>,
+    writes: Sender<IoPacket>,
     page_pool: PagePool,
+    cipher: Option<Arc<PageCipher>>,
+    cow_files: Arc<cow::CowFiles>,
+    fault_injector: Option<Arc<dyn FaultInjector>>,
+    tuning: Arc<IoTuning>,
+    background_errors: BackgroundErrors,
+    #[cfg(feature = "crash-simulation")]
+    crash_simulator: Option<Arc<crash::CrashSimulator>>,
 }
 
 impl IoPool {
@@ -129,6 +460,108 @@ impl IoPool {
     pub fn page_pool(&self) -> &PagePool {
         &self.page_pool
     }
+
+    /// The cipher pages are encrypted with at rest, if any. Pages read and written through the
+    /// pool are decrypted and encrypted by it.
+    pub fn cipher(&self) -> Option<&Arc<PageCipher>> {
+        self.cipher.as_ref()
+    }
+
+    /// Send an I/O command, invoking `on_complete` with its completion instead of delivering it to
+    /// a handle. This fails if the channel has hung up, but does not block the thread.
+    ///
+    /// The callback runs on an I/O worker thread, so it must be quick, e.g. waking a task. If the
+    /// fault injector fails the command, the callback runs right away on the calling thread.
+    pub fn send_with_callback(
+        &self,
+        command: IoCommand,
+        on_complete: impl FnOnce(CompleteIo) + Send + 'static,
+    ) -> Result<(), SendError<IoCommand>> {
+        if let Some(error) = self.injected_fault(&command.kind) {
+            on_complete(CompleteIo {
+                command,
+                result: Err(error),
+            });
+            return Ok(());
+        }
+        self.before_io(&command.kind);
+        self.enqueue(IoPacket {
+            command,
+            completion_sender: CompletionSender::Callback(Box::new(on_complete)),
+        })
+    }
+
+    /// Push the request to the queue of its kind. See [`IoQueues`].
+    fn enqueue(&self, packet: IoPacket) -> Result<(), SendError<IoCommand>> {
+        let queue = match packet.command.kind {
+            IoKind::Read(..) => &self.reads,
+            IoKind::Write(..) | IoKind::WriteRaw(..) => &self.writes,
+        };
+        queue
+            .send(packet)
+            .map_err(|SendError(packet)| SendError(packet.command))
+    }
+
+    /// The settings of the pool which may be changed while it runs.
+    pub fn tuning(&self) -> &IoTuning {
+        &self.tuning
+    }
+
+    /// The files whose pages are preserved before being overwritten by I/O sent through this pool.
+    pub fn cow_files(&self) -> &cow::CowFiles {
+        &self.cow_files
+    }
+
+    /// Preserve the pages overwritten through this pool with the given simulator.
+    #[cfg(feature = "crash-simulation")]
+    pub fn set_crash_simulator(&mut self, crash_simulator: Arc<crash::CrashSimulator>) {
+        self.crash_simulator = Some(crash_simulator);
+    }
+
+    /// Preserve the pages about to be overwritten by the given I/O.
+    fn before_io(&self, kind: &IoKind) {
+        self.cow_files.before_io(kind);
+        #[cfg(feature = "crash-simulation")]
+        if let Some(ref crash_simulator) = self.crash_simulator {
+            crash_simulator.before_io(kind);
+        }
+    }
+
+    /// The hook consulted before I/O, if any.
+    pub fn fault_injector(&self) -> Option<&Arc<dyn FaultInjector>> {
+        self.fault_injector.as_ref()
+    }
+
+    /// Where errors of internal threads are reported.
+    pub fn background_errors(&self) -> &BackgroundErrors {
+        &self.background_errors
+    }
+
+    /// Make the writes to the given file durable, unless the fault injector fails the sync.
+    ///
+    /// This issues an `fsync`, or only writes the file back with [`Durability::PowerLossProtected`].
+    /// The manifest is never synced through here and is always synced with `fsync`.
+    pub fn sync_file(&self, synced_file: SyncedFile, file: &File) -> std::io::Result<()> {
+        if let Some(ref fault_injector) = self.fault_injector {
+            fault_injector.fsync(synced_file)?;
+        }
+        match self.tuning.durability() {
+            Durability::Fsync => file.sync_all(),
+            #[cfg(target_os = "linux")]
+            Durability::PowerLossProtected => crate::sys::linux::write_back(file),
+            #[cfg(not(target_os = "linux"))]
+            Durability::PowerLossProtected => file.sync_data(),
+        }
+    }
+
+    /// The error the fault injector fails the I/O command with, if any.
+    fn injected_fault(&self, kind: &IoKind) -> Option<std::io::Error> {
+        let page_io = match *kind {
+            IoKind::Read(_, pn, _) => PageIo::Read(pn),
+            IoKind::Write(_, pn, _) | IoKind::WriteRaw(_, pn, _) => PageIo::Write(pn),
+        };
+        self.fault_injector.as_ref()?.page_io(page_io).err()
+    }
 }
 
 /// A handle for submitting I/O commands and receiving their completions.
@@ -149,14 +582,22 @@ pub struct IoHandle {
 
 impl IoHandle {
     /// Send an I/O command. This fails if the channel has hung up, but does not block the thread.
+    ///
+    /// If the command overwrites a page of a file being copied, the page is copied first. If the
+    /// fault injector fails the command, it completes right away with the injected error.
     pub fn send(&self, command: IoCommand) -> Result<(), SendError<IoCommand>> {
-        self.io_pool
-            .sender
-            .send(IoPacket {
+        if let Some(error) = self.io_pool.injected_fault(&command.kind) {
+            let _ = self.completion_sender.send(CompleteIo {
                 command,
-                completion_sender: self.completion_sender.clone(),
-            })
-            .map_err(|SendError(packet)| SendError(packet.command))
+                result: Err(error),
+            });
+            return Ok(());
+        }
+        self.io_pool.before_io(&command.kind);
+        self.io_pool.enqueue(IoPacket {
+            command,
+            completion_sender: CompletionSender::Channel(self.completion_sender.clone()),
+        })
     }
 
     /// Block the current thread on receiving an I/O completion.
@@ -184,10 +625,38 @@ impl IoHandle {
     }
 }
 
-/// Read a page from the file at the given page number.
-pub fn read_page(page_pool: &PagePool, fd: &File, pn: u64) -> std::io::Result<FatPage> {
+/// Read a page from the file at the given page number, decrypting it with the given cipher, if
+/// any.
+pub fn read_page(
+    page_pool: &PagePool,
+    cipher: Option<&PageCipher>,
+    fd: &File,
+    pn: u64,
+) -> std::io::Result<FatPage> {
     use std::os::unix::fs::FileExt as _;
     let mut page = page_pool.alloc_fat_page();
     fd.read_exact_at(&mut page[..], pn * PAGE_SIZE as u64)?;
+    if let Some(cipher) = cipher {
+        cipher.decrypt(pn, &mut page);
+    }
     Ok(page)
 }
+
+/// Write a page to the file at the given page number, encrypting it with the given cipher, if
+/// any.
+pub fn write_page(
+    page_pool: &PagePool,
+    cipher: Option<&PageCipher>,
+    fd: &File,
+    pn: u64,
+    page: &[u8],
+) -> std::io::Result<()> {
+    use std::os::unix::fs::FileExt as _;
+    // Direct I/O requires an aligned buffer, which the given page need not be.
+    let mut buf = page_pool.alloc_fat_page();
+    buf.copy_from_slice(page);
+    if let Some(cipher) = cipher {
+        cipher.encrypt(pn, &mut buf);
+    }
+    fd.write_all_at(&buf, pn * PAGE_SIZE as u64)
+}
@@ -1,10 +1,12 @@
 use super::PAGE_SIZE;
-use parking_lot::{RwLock, RwLockWriteGuard};
+use crate::HugePages;
+use crossbeam::utils::CachePadded;
+use parking_lot::Mutex;
 use std::{
     cell::RefCell,
     ops::{Deref, DerefMut},
     sync::{
-        atomic::{AtomicPtr, AtomicU32, Ordering},
+        atomic::{AtomicPtr, AtomicU32, AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
 };
@@ -22,9 +24,19 @@ const REGION_COUNT: usize = 4096;
 
 const TLS_FREELIST_CAPACITY: usize = 1024;
 
+/// The generation of the next pool. Every pool gets a distinct one, which its pages are stamped
+/// with in debug builds and with the `hardened-page-pool` feature.
+#[cfg(any(debug_assertions, feature = "hardened-page-pool"))]
+static NEXT_GENERATION: AtomicU32 = AtomicU32::new(1);
+
 /// A page reference to the pool.
 #[derive(Clone)]
-pub struct Page(*mut u8);
+pub struct Page {
+    ptr: *mut u8,
+    /// The generation of the pool the page was allocated from.
+    #[cfg(any(debug_assertions, feature = "hardened-page-pool"))]
+    generation: u32,
+}
 
 unsafe impl Send for Page {}
 unsafe impl Sync for Page {}
@@ -32,12 +44,12 @@ unsafe impl Sync for Page {}
 impl Page {
     /// Returns a pointer to the page.
     pub fn as_ptr(&self) -> *const u8 {
-        self.0
+        self.ptr
     }
 
     /// Returns a mutable pointer to the page.
     pub fn as_mut_ptr(&self) -> *mut u8 {
-        self.0
+        self.ptr
     }
 
     /// This is a convenience function that uses [`std::slice::from_raw_parts_mut`] to create a
@@ -48,9 +60,11 @@ impl Page {
     /// The caller is responsible for making sure:
     ///
     /// 1. that the page is not freed,
-    /// 2. that the [`PagePool`] is the same that was used to allocate the page.
+    /// 2. that the [`PagePool`] is the same that was used to allocate the page. Passing the page to
+    ///    another pool panics in debug builds and with the `hardened-page-pool` feature.
     /// 3. that the [`PagePool`] is not dropped while the slice is used.
     /// 4. that there is only a single mutable slice into the page at any given time.
+    #[allow(clippy::mut_from_ref)]
     pub unsafe fn as_mut_slice(&self) -> &mut [u8] {
         std::slice::from_raw_parts_mut(self.as_mut_ptr(), PAGE_SIZE)
     }
@@ -69,11 +83,13 @@ pub struct FatPage {
 impl FatPage {
     /// See [`Page::as_ptr`].
     pub fn as_ptr(&self) -> *const u8 {
+        self.page_pool.check_generation(&self.page);
         self.page.as_ptr()
     }
 
     /// See [`Page::as_mut_ptr`].
     pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.page_pool.check_generation(&self.page);
         self.page.as_mut_ptr()
     }
 
@@ -90,12 +106,14 @@ impl Deref for FatPage {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
+        self.page_pool.check_generation(&self.page);
         unsafe { self.page.as_mut_slice() }
     }
 }
 
 impl DerefMut for FatPage {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        self.page_pool.check_generation(&self.page);
         unsafe { self.page.as_mut_slice() }
     }
 }
@@ -108,7 +126,17 @@ impl Drop for FatPage {
 
 /// [`PagePool`] is an efficient allocator for pages used in IO operations.
 ///
-/// It allows for efficient allocation and deallocation of pages.
+/// It allows for efficient allocation and deallocation of pages. Memory is reserved from the OS in
+/// regions of 256 MiB which are only returned once the pool is dropped.
+///
+/// Every thread keeps a small freelist of its own and exchanges pages in batches with one of
+/// several shared freelists, its home shard. Threads are spread evenly over the shards, so that
+/// alloc and dealloc rarely contend even with many threads. A thread whose home shard runs dry
+/// steals pages from the other shards before new memory is reserved.
+///
+/// The pool is cheap to clone. Several databases may share one pool by passing it to
+/// [`crate::Options::page_pool`], so that they reuse each other's free pages rather than each
+/// reserving its own regions.
 #[derive(Clone)]
 pub struct PagePool {
     inner: Arc<Inner>,
@@ -122,23 +150,78 @@ struct Inner {
     // Moreover, the pointer stored in `regions[i]` where `i < n_regions` is immutable once set.
     regions: [AtomicPtr<u8>; REGION_COUNT],
     n_regions: AtomicU32,
-    freelist: RwLock<Vec<Page>>,
-    // The local freelist for the current thread used to avoid contention on the global freelist.
-    tls_freelist: ThreadLocal<RefCell<Vec<Page>>>,
+    // The maximum number of regions which may be allocated, at most [`REGION_COUNT`].
+    max_regions: u32,
+    // The shared freelists. Each thread refills its local freelist from its home shard, or steals
+    // from the others if that is empty.
+    shards: Box<[CachePadded<Mutex<Vec<Page>>>]>,
+    // The shard the next thread to use the pool is homed at, modulo the number of shards.
+    next_shard: AtomicUsize,
+    // Serializes the reservation of regions, so that threads finding all shards empty at once
+    // don't reserve a region each.
+    grow_lock: Mutex<()>,
+    // The local freelist for the current thread used to avoid contention on the shared ones.
+    tls_freelist: ThreadLocal<LocalFreelist>,
+    // The counters of every thread-local freelist, which can't be iterated over from other
+    // threads.
+    local_stats: Mutex<Vec<Arc<LocalStats>>>,
+    // How regions are backed.
+    huge_pages: HugePages,
+    numa_node: Option<u32>,
+    // Distinct for every pool. Stamped on the pages allocated from it.
+    #[cfg(any(debug_assertions, feature = "hardened-page-pool"))]
+    generation: u32,
 }
 
 impl PagePool {
     /// Creates a new empty page pool.
     pub fn new() -> Self {
+        Self::with_regions(None, HugePages::Off, None)
+    }
+
+    /// Creates a new empty page pool which reserves at most `max_bytes` bytes from the OS, rounded
+    /// up to a whole number of regions.
+    ///
+    /// Allocating a page while all reserved pages are in use panics once the limit is reached.
+    pub fn with_limit(max_bytes: usize) -> Self {
+        Self::with_regions(Some(max_bytes), HugePages::Off, None)
+    }
+
+    /// Creates a new empty page pool, optionally limited to `max_bytes` as in
+    /// [`Self::with_limit`], whose regions are backed by huge pages and bound to a NUMA node as
+    /// given.
+    ///
+    /// Both are best-effort: a region is backed by regular pages if no huge pages are available,
+    /// and allocated according to the default policy of the process if it can't be bound to the
+    /// node.
+    pub fn with_regions(
+        max_bytes: Option<usize>,
+        huge_pages: HugePages,
+        numa_node: Option<u32>,
+    ) -> Self {
+        let max_regions = match max_bytes {
+            Some(max_bytes) => max_bytes.div_ceil(REGION_BYTE_SIZE).clamp(1, REGION_COUNT),
+            None => REGION_COUNT,
+        };
         let regions = std::array::from_fn(|_| AtomicPtr::new(std::ptr::null_mut()));
-        // The capacity is chosen to be large enough to fit 4 times as much as 50k pages.
-        let freelist = RwLock::new(Vec::with_capacity(200000));
+        let n_shards = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let shards = (0..n_shards)
+            .map(|_| CachePadded::new(Mutex::new(Vec::new())))
+            .collect();
         Self {
             inner: Arc::new(Inner {
                 regions,
                 n_regions: AtomicU32::new(0),
-                freelist,
+                max_regions: max_regions as u32,
+                shards,
+                next_shard: AtomicUsize::new(0),
+                grow_lock: Mutex::new(()),
                 tls_freelist: ThreadLocal::new(),
+                local_stats: Mutex::new(Vec::new()),
+                huge_pages,
+                numa_node,
+                #[cfg(any(debug_assertions, feature = "hardened-page-pool"))]
+                generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
             }),
         }
     }
@@ -157,64 +240,186 @@ impl PagePool {
     /// The contents of the page are undefined.
     pub fn alloc(&self) -> Page {
         // fast path: try to serve request from the thread-local freelist.
-        let mut tls_freelist = self.tls_freelist();
+        let local = self.tls_freelist();
+        let mut tls_freelist = local.pages.borrow_mut();
+        local.stats.count_alloc();
         if let Some(page) = tls_freelist.pop() {
+            local.stats.set_free_pages(tls_freelist.len());
             return page;
         }
 
-        // if none is available, try to replenish the thread-local freelist from the global one.
-        let mut freelist = self.inner.freelist.write();
+        // if none is available, try to replenish the thread-local freelist from the shards.
+        let page = loop {
+            if self.steal(local.shard, &mut tls_freelist) {
+                break tls_freelist.pop().unwrap();
+            }
 
-        if freelist.len() < TLS_FREELIST_CAPACITY {
-            // ensure that the global freelist has enough pages to refill the thread-local one.
-            self.grow(&mut freelist);
-            assert!(freelist.len() >= TLS_FREELIST_CAPACITY);
-        }
+            // all shards are empty. Reserve a new region, unless another thread has done so while
+            // we were waiting for the lock.
+            let _grow_guard = self.inner.grow_lock.lock();
+            if self.steal(local.shard, &mut tls_freelist) {
+                break tls_freelist.pop().unwrap();
+            }
+            self.grow(local.shard);
+        };
+        local.stats.set_free_pages(tls_freelist.len());
+        page
+    }
 
-        // transfer at most TLS_FREELIST_CAPACITY pages from the global freelist to the
-        // thread-local freelist.
-        tls_freelist.extend(freelist.drain(..TLS_FREELIST_CAPACITY));
-        tls_freelist.pop().unwrap()
+    /// Transfer at most [`TLS_FREELIST_CAPACITY`] pages to the thread-local freelist from the
+    /// first non-empty shard, starting with the home shard. Returns `false` if all shards are
+    /// empty.
+    fn steal(&self, home: usize, tls_freelist: &mut Vec<Page>) -> bool {
+        let shards = &self.inner.shards;
+        for i in 0..shards.len() {
+            let mut shard = shards[(home + i) % shards.len()].lock();
+            if shard.is_empty() {
+                continue;
+            }
+            let start = shard.len().saturating_sub(TLS_FREELIST_CAPACITY);
+            tls_freelist.extend(shard.drain(start..));
+            return true;
+        }
+        false
     }
 
     /// Deallocates a [`Page`].
+    ///
+    /// The page must have been allocated from this pool. This is checked in debug builds and with
+    /// the `hardened-page-pool` feature.
     pub fn dealloc(&self, page: Page) {
+        self.check_generation(&page);
+
         // fast path: try to place page in thread-local freelist.
-        let mut tls_freelist = self.tls_freelist();
+        let local = self.tls_freelist();
+        let mut tls_freelist = local.pages.borrow_mut();
         tls_freelist.push(page);
 
-        if tls_freelist.len() < TLS_FREELIST_CAPACITY * 2 {
-            return;
+        if tls_freelist.len() >= TLS_FREELIST_CAPACITY * 2 {
+            // slow path: drain TLS free-list to the home shard.
+            let mut shard = self.inner.shards[local.shard].lock();
+            shard.extend(tls_freelist.drain(TLS_FREELIST_CAPACITY..));
         }
+        local.stats.set_free_pages(tls_freelist.len());
+    }
 
-        // slow path: drain TLS free-list to global free-list.
-        let mut freelist = self.inner.freelist.write();
-        freelist.extend(tls_freelist.drain(TLS_FREELIST_CAPACITY..));
+    /// Return the memory of the free pages to the OS. The pages remain in the pool and are
+    /// backed by memory again once used.
+    ///
+    /// Only the pages in the shared freelists and in the freelist of the current thread are
+    /// released. Regions backed by [`HugePages::Explicit`] only release whole huge pages.
+    pub fn release_free_pages(&self) {
+        let shards = self
+            .inner
+            .shards
+            .iter()
+            .map(|shard| shard.lock())
+            .collect::<Vec<_>>();
+        let tls_freelist = self.tls_freelist().pages.borrow();
+        let mut ptrs = shards
+            .iter()
+            .flat_map(|shard| shard.iter())
+            .chain(tls_freelist.iter())
+            .map(|page| page.as_mut_ptr())
+            .collect::<Vec<_>>();
+        ptrs.sort_unstable();
+
+        // release contiguous runs of pages with one call each.
+        let mut i = 0;
+        while i < ptrs.len() {
+            let start = ptrs[i];
+            let mut len = PAGE_SIZE;
+            i += 1;
+            while i < ptrs.len() && ptrs[i] == start.wrapping_add(len) {
+                len += PAGE_SIZE;
+                i += 1;
+            }
+            unsafe {
+                // SAFETY: the run consists of free pages within regions allocated by this pool.
+                // Their contents are undefined anyway, so it is fine for them to be zeroed.
+                libc::madvise(start as *mut libc::c_void, len, libc::MADV_DONTNEED);
+            }
+        }
     }
 
-    fn tls_freelist<'a>(&'a self) -> std::cell::RefMut<'a, Vec<Page>> {
+    /// Panic if the page was not allocated from this pool.
+    #[cfg(any(debug_assertions, feature = "hardened-page-pool"))]
+    #[inline]
+    fn check_generation(&self, page: &Page) {
+        assert!(
+            page.generation == self.inner.generation,
+            "page of pool generation {} used with pool generation {}",
+            page.generation,
+            self.inner.generation,
+        );
+    }
+
+    #[cfg(not(any(debug_assertions, feature = "hardened-page-pool")))]
+    #[inline]
+    fn check_generation(&self, _page: &Page) {}
+
+    fn tls_freelist(&self) -> &LocalFreelist {
+        self.inner.tls_freelist.get_or(|| {
+            let next_shard = self.inner.next_shard.fetch_add(1, Ordering::Relaxed);
+            let stats = Arc::new(LocalStats::default());
+            self.inner.local_stats.lock().push(stats.clone());
+            LocalFreelist {
+                pages: RefCell::new(Vec::with_capacity(TLS_FREELIST_CAPACITY * 2)),
+                shard: next_shard % self.inner.shards.len(),
+                stats,
+            }
+        })
+    }
+
+    /// The number of free pages, both in the shared freelists and in those of the threads.
+    ///
+    /// The thread-local freelists are counted as of their last change, so the result is exact
+    /// only while no other thread is using the pool.
+    pub fn free_pages(&self) -> usize {
+        let shared = self
+            .inner
+            .shards
+            .iter()
+            .map(|shard| shard.lock().len())
+            .sum::<usize>();
+        let local = self
+            .inner
+            .local_stats
+            .lock()
+            .iter()
+            .map(|stats| stats.free_pages.load(Ordering::Relaxed))
+            .sum::<usize>();
+        shared + local
+    }
+
+    /// The number of pages allocated from the pool so far, by all threads.
+    pub fn allocations(&self) -> u64 {
         self.inner
-            .tls_freelist
-            .get_or(|| RefCell::new(Vec::with_capacity(TLS_FREELIST_CAPACITY)))
-            .borrow_mut()
+            .local_stats
+            .lock()
+            .iter()
+            .map(|stats| stats.allocations.load(Ordering::Relaxed))
+            .sum()
     }
 
+    /// The number of bytes reserved from the OS so far.
+    pub fn reserved_bytes(&self) -> usize {
+        self.inner.n_regions.load(Ordering::Acquire) as usize * REGION_BYTE_SIZE
+    }
+
+    /// Reserve a new region and place its pages in the given shard. Must be called with the grow
+    /// lock held.
     #[cold]
-    fn grow(&self, freelist_guard: &mut RwLockWriteGuard<Vec<Page>>) {
-        // First step is to allocate a new region.
-        let region_ptr = unsafe {
-            libc::mmap(
-                std::ptr::null_mut(),
-                REGION_BYTE_SIZE,
-                libc::PROT_READ | libc::PROT_WRITE,
-                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
-                /* fd */ -1,
-                /* offset */ 0,
-            )
-        };
-        if region_ptr == libc::MAP_FAILED {
-            panic!("Failed to allocate memory");
+    fn grow(&self, shard: usize) {
+        if self.inner.n_regions.load(Ordering::Relaxed) >= self.inner.max_regions {
+            panic!(
+                "Page pool limit of {} bytes exhausted",
+                self.inner.max_regions as usize * REGION_BYTE_SIZE
+            );
         }
+
+        // First step is to allocate a new region.
+        let region_ptr = self.map_region();
         assert!(!region_ptr.is_null());
 
         // Next, we need to store the region pointer in the regions array.
@@ -224,17 +429,99 @@ impl PagePool {
         // yet. Likewise, drop cannot happen during this operation. We still do it in this order
         // to just err on the safe side and avoid any potential issues.
         //
-        // Also, note the ordering is not really important here since we own the lock.
+        // Also, note the ordering is not really important here since we own the grow lock.
         let region_ix = self.inner.n_regions.load(Ordering::Relaxed);
         self.inner.regions[region_ix as usize].store(region_ptr as *mut u8, Ordering::Relaxed);
         self.inner.n_regions.fetch_add(1, Ordering::Release);
 
-        // Finally, we need to populate the freelist with the pages in the new region.
+        // Finally, we need to populate the shard with the pages in the new region.
+        let mut shard = self.inner.shards[shard].lock();
+        shard.reserve(SLOTS_PER_REGION);
         for slot in 0..SLOTS_PER_REGION {
             let page_ptr = unsafe { region_ptr.add(slot * PAGE_SIZE) } as *mut u8;
-            freelist_guard.push(Page(page_ptr));
+            shard.push(Page {
+                ptr: page_ptr,
+                #[cfg(any(debug_assertions, feature = "hardened-page-pool"))]
+                generation: self.inner.generation,
+            });
         }
     }
+
+    /// Reserve a region from the OS, backed and bound as configured.
+    fn map_region(&self) -> *mut u8 {
+        #[cfg(target_os = "linux")]
+        use crate::sys::linux as platform;
+        #[cfg(target_os = "macos")]
+        use crate::sys::macos as platform;
+
+        let huge_region = match self.inner.huge_pages {
+            HugePages::Explicit => platform::map_huge_pages(REGION_BYTE_SIZE).ok(),
+            HugePages::Off | HugePages::Transparent => None,
+        };
+        let region_ptr = match huge_region {
+            Some(region_ptr) => region_ptr,
+            None => {
+                let region_ptr = unsafe {
+                    libc::mmap(
+                        std::ptr::null_mut(),
+                        REGION_BYTE_SIZE,
+                        libc::PROT_READ | libc::PROT_WRITE,
+                        libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                        /* fd */ -1,
+                        /* offset */ 0,
+                    )
+                };
+                if region_ptr == libc::MAP_FAILED {
+                    panic!("Failed to allocate memory");
+                }
+                let region_ptr = region_ptr as *mut u8;
+                if self.inner.huge_pages != HugePages::Off {
+                    // Fall back to transparent huge pages. Failing that, regular pages will do.
+                    let _ = platform::advise_huge_pages(region_ptr, REGION_BYTE_SIZE);
+                }
+                region_ptr
+            }
+        };
+
+        if let Some(node) = self.inner.numa_node {
+            // The region hasn't been touched yet, so all of its pages are allocated on the node.
+            let _ = platform::bind_to_numa_node(region_ptr, REGION_BYTE_SIZE, node);
+        }
+        region_ptr
+    }
+}
+
+/// The freelist of a thread, along with the shard it exchanges pages with.
+struct LocalFreelist {
+    pages: RefCell<Vec<Page>>,
+    shard: usize,
+    stats: Arc<LocalStats>,
+}
+
+/// The counters of a thread-local freelist. They are only written by the thread owning the
+/// freelist, so plain loads and stores suffice and the hot path stays free of atomic
+/// read-modify-writes.
+#[derive(Default)]
+struct LocalStats {
+    allocations: AtomicU64,
+    free_pages: AtomicUsize,
+}
+
+impl LocalStats {
+    fn count_alloc(&self) {
+        let allocations = self.allocations.load(Ordering::Relaxed);
+        self.allocations.store(allocations + 1, Ordering::Relaxed);
+    }
+
+    fn set_free_pages(&self, free_pages: usize) {
+        self.free_pages.store(free_pages, Ordering::Relaxed);
+    }
+}
+
+impl Default for PagePool {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Drop for Inner {
@@ -253,3 +540,53 @@ impl Drop for Inner {
 
 unsafe impl Send for PagePool {}
 unsafe impl Sync for PagePool {}
+
+#[cfg(test)]
+mod tests {
+    use super::{PagePool, REGION_BYTE_SIZE, SLOTS_PER_REGION, TLS_FREELIST_CAPACITY};
+
+    #[test]
+    fn pages_are_stolen_from_other_shards() {
+        // A single region, so that allocations beyond the pages freed by the first thread panic.
+        let pool = PagePool::with_limit(REGION_BYTE_SIZE);
+        let pages = (0..SLOTS_PER_REGION)
+            .map(|_| pool.alloc())
+            .collect::<Vec<_>>();
+        for page in pages {
+            pool.dealloc(page);
+        }
+
+        // The other thread may be homed at another shard, but must find the freed pages anyway.
+        let n = SLOTS_PER_REGION - 2 * TLS_FREELIST_CAPACITY;
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let pages = (0..n).map(|_| pool.alloc()).collect::<Vec<_>>();
+                for page in pages {
+                    pool.dealloc(page);
+                }
+            });
+        });
+        assert_eq!(pool.reserved_bytes(), REGION_BYTE_SIZE);
+    }
+
+    #[test]
+    fn stats_include_thread_local_freelists() {
+        let pool = PagePool::new();
+        let page = pool.alloc();
+        std::thread::scope(|scope| {
+            scope.spawn(|| pool.dealloc(pool.alloc()));
+        });
+        assert_eq!(pool.allocations(), 2);
+        assert_eq!(pool.free_pages(), SLOTS_PER_REGION - 1);
+
+        pool.dealloc(page);
+        assert_eq!(pool.free_pages(), SLOTS_PER_REGION);
+    }
+
+    #[test]
+    #[should_panic(expected = "used with pool generation")]
+    fn dealloc_into_other_pool_panics() {
+        let page = PagePool::new().alloc();
+        PagePool::new().dealloc(page);
+    }
+}
@@ -0,0 +1,186 @@
+//! A growable byte buffer backed by pages of a [`PagePool`].
+//!
+//! Every page of the buffer is aligned to [`PAGE_SIZE`], so the buffer can be read from and
+//! written to files opened with `O_DIRECT` page by page, unlike a `Vec<u8>`, whose alignment is
+//! arbitrary.
+
+use super::{FatPage, PagePool, PAGE_SIZE};
+use std::{fs::File, os::unix::fs::FileExt as _};
+
+/// A growable byte buffer backed by pool pages. See the [module docs](self).
+///
+/// The bytes of the last page past the length of the buffer are always zero, so that writing
+/// whole pages pads the buffer with zeros.
+pub struct PooledBuf {
+    page_pool: PagePool,
+    pages: Vec<FatPage>,
+    len: usize,
+}
+
+impl PooledBuf {
+    /// Create an empty buffer allocating its pages from the given pool.
+    pub fn new(page_pool: PagePool) -> Self {
+        PooledBuf {
+            page_pool,
+            pages: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Create a buffer of `len` zero bytes.
+    pub fn zeroed(page_pool: PagePool, len: usize) -> Self {
+        let mut buf = Self::new(page_pool);
+        buf.resize(len);
+        buf
+    }
+
+    /// Read `len` bytes of the file, starting at `offset`, into a new buffer. The file is read
+    /// page by page.
+    ///
+    /// For files opened with `O_DIRECT`, `offset` must be a multiple of [`PAGE_SIZE`] and so must
+    /// `len`, unless the read ends at the end of the file.
+    pub fn read_at(
+        page_pool: PagePool,
+        file: &File,
+        offset: u64,
+        len: usize,
+    ) -> std::io::Result<Self> {
+        let mut buf = Self::zeroed(page_pool, len);
+        for (i, page) in buf.pages.iter_mut().enumerate() {
+            let n = (len - i * PAGE_SIZE).min(PAGE_SIZE);
+            file.read_exact_at(&mut page[..n], offset + (i * PAGE_SIZE) as u64)?;
+        }
+        Ok(buf)
+    }
+
+    /// Append the given bytes, allocating pages as needed.
+    pub fn extend_from_slice(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let offset = self.len % PAGE_SIZE;
+            if offset == 0 {
+                self.push_page();
+            }
+            let n = bytes.len().min(PAGE_SIZE - offset);
+            // UNWRAP: a page was pushed above unless the last one has room.
+            let page = self.pages.last_mut().unwrap();
+            page[offset..offset + n].copy_from_slice(&bytes[..n]);
+            self.len += n;
+            bytes = &bytes[n..];
+        }
+    }
+
+    /// Shorten the buffer or extend it with zeros to `len` bytes. Pages no longer needed are
+    /// returned to the pool.
+    pub fn resize(&mut self, len: usize) {
+        if len < self.len {
+            self.pages.truncate(len.div_ceil(PAGE_SIZE));
+            let offset = len % PAGE_SIZE;
+            if offset != 0 {
+                // UNWRAP: the buffer is not empty, since `offset` is not zero.
+                self.pages.last_mut().unwrap()[offset..].fill(0);
+            }
+        } else {
+            while self.pages.len() * PAGE_SIZE < len {
+                self.push_page();
+            }
+        }
+        self.len = len;
+    }
+
+    /// The number of bytes in the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The pages of the buffer, each [`PAGE_SIZE`] bytes long. The last one is padded with zeros.
+    pub fn pages(&self) -> impl Iterator<Item = &[u8]> {
+        self.pages.iter().map(|page| &page[..])
+    }
+
+    /// The page of the buffer at the given index, [`PAGE_SIZE`] bytes long.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn page(&self, index: usize) -> &[u8] {
+        &self.pages[index]
+    }
+
+    /// Write the buffer to the file at `offset`, page by page.
+    ///
+    /// For files opened with `O_DIRECT`, `offset` must be a multiple of [`PAGE_SIZE`] and so must
+    /// the length of the buffer, see [`PooledBuf::resize`].
+    pub fn write_at(&self, file: &File, offset: u64) -> std::io::Result<()> {
+        for (i, page) in self.pages.iter().enumerate() {
+            let n = (self.len - i * PAGE_SIZE).min(PAGE_SIZE);
+            file.write_all_at(&page[..n], offset + (i * PAGE_SIZE) as u64)?;
+        }
+        Ok(())
+    }
+
+    /// Copy the contents of the buffer to a `Vec`.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut vec = Vec::with_capacity(self.len);
+        for (i, page) in self.pages.iter().enumerate() {
+            let n = (self.len - i * PAGE_SIZE).min(PAGE_SIZE);
+            vec.extend_from_slice(&page[..n]);
+        }
+        vec
+    }
+
+    fn push_page(&mut self) {
+        let mut page = self.page_pool.alloc_fat_page();
+        page.fill(0);
+        self.pages.push(page);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(buf: &PooledBuf) -> Vec<u8> {
+        let file = tempfile::tempfile().unwrap();
+        buf.write_at(&file, 0).unwrap();
+        let mut contents = vec![0; buf.len];
+        file.read_exact_at(&mut contents, 0).unwrap();
+        contents
+    }
+
+    #[test]
+    fn extend_and_resize() {
+        let mut buf = PooledBuf::new(PagePool::new());
+        let bytes = (0..PAGE_SIZE + 100).map(|i| i as u8).collect::<Vec<_>>();
+        buf.extend_from_slice(&bytes[..10]);
+        buf.extend_from_slice(&bytes[10..]);
+        assert_eq!(buf.len, PAGE_SIZE + 100);
+        assert_eq!(contents(&buf), bytes);
+        assert_eq!(buf.pages.len(), 2);
+        for page in &buf.pages {
+            assert_eq!(page.as_ptr() as usize % PAGE_SIZE, 0);
+        }
+
+        // Shrinking zeroes the tail, so that growing again yields zeros.
+        buf.resize(PAGE_SIZE + 50);
+        buf.resize(2 * PAGE_SIZE);
+        let vec = contents(&buf);
+        assert_eq!(&vec[..PAGE_SIZE + 50], &bytes[..PAGE_SIZE + 50]);
+        assert!(vec[PAGE_SIZE + 50..].iter().all(|b| *b == 0));
+
+        buf.resize(10);
+        assert_eq!(buf.pages.len(), 1);
+        buf.resize(0);
+        assert_eq!(buf.pages.len(), 0);
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let file = tempfile::tempfile().unwrap();
+        let page_pool = PagePool::new();
+        let mut buf = PooledBuf::new(page_pool.clone());
+        buf.extend_from_slice(&[7; 3 * PAGE_SIZE / 2]);
+        buf.write_at(&file, PAGE_SIZE as u64).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 5 * PAGE_SIZE as u64 / 2);
+
+        let read = PooledBuf::read_at(page_pool, &file, PAGE_SIZE as u64, buf.len).unwrap();
+        assert_eq!(contents(&read), contents(&buf));
+    }
+}
@@ -0,0 +1,88 @@
+//! A portable I/O backend: a pool of threads issuing blocking `pread`/`pwrite` calls.
+
+use super::{CompleteIo, Encryption, IoCommand, IoKind, IoKindResult, IoQueues, PAGE_SIZE};
+use crate::{background_error::BackgroundErrors, threads, ThreadConfig};
+
+pub fn start_io_workers(
+    queues: IoQueues,
+    io_workers: usize,
+    threads: &ThreadConfig,
+    encryption: Option<Encryption>,
+    background_errors: &BackgroundErrors,
+) -> anyhow::Result<()> {
+    for _ in 0..io_workers {
+        spawn_worker_thread(
+            queues.clone(),
+            threads,
+            encryption.clone(),
+            background_errors.clone(),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn spawn_worker_thread(
+    mut queues: IoQueues,
+    threads: &ThreadConfig,
+    encryption: Option<Encryption>,
+    background_errors: BackgroundErrors,
+) -> anyhow::Result<()> {
+    let work = move || loop {
+        let Ok(packet) = queues.recv() else {
+            break;
+        };
+        let complete = execute(packet.command, encryption.as_ref());
+        packet.completion_sender.send(complete);
+    };
+
+    threads::spawn(
+        threads::thread_name(threads, "nomt-io-worker"),
+        &threads.io,
+        move || super::run_reporting_panics(&background_errors, work),
+    )
+}
+
+fn execute(mut command: IoCommand, encryption: Option<&Encryption>) -> CompleteIo {
+    let ciphertext = encryption.and_then(|encryption| encryption.encrypt(&command.kind));
+    let result = loop {
+        let res = match command.kind {
+            IoKind::Read(fd, page_index, ref mut page) => unsafe {
+                libc::pread(
+                    fd,
+                    page.as_mut_ptr() as *mut libc::c_void,
+                    PAGE_SIZE as libc::size_t,
+                    (page_index * PAGE_SIZE as u64) as libc::off_t,
+                )
+            },
+            IoKind::Write(fd, page_index, ref page) => unsafe {
+                libc::pwrite(
+                    fd,
+                    ciphertext.as_ref().map_or(page.as_ptr(), |c| c.as_ptr())
+                        as *const libc::c_void,
+                    PAGE_SIZE as libc::size_t,
+                    (page_index * PAGE_SIZE as u64) as libc::off_t,
+                )
+            },
+            IoKind::WriteRaw(fd, page_index, ref mut page) => unsafe {
+                libc::pwrite(
+                    fd,
+                    ciphertext.as_ref().map_or(page.as_ptr(), |c| c.as_ptr())
+                        as *const libc::c_void,
+                    PAGE_SIZE as libc::size_t,
+                    (page_index * PAGE_SIZE as u64) as libc::off_t,
+                )
+            },
+        };
+        match command.kind.get_result(res) {
+            IoKindResult::Ok => break Ok(()),
+            IoKindResult::Err => break Err(std::io::Error::last_os_error()),
+            IoKindResult::Retry => (),
+        }
+    };
+    if let (Ok(()), Some(encryption)) = (&result, encryption) {
+        encryption.decrypt(&mut command.kind);
+    }
+
+    CompleteIo { command, result }
+}
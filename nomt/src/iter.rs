@@ -0,0 +1,107 @@
+//! Iteration over all keys and values in trie order.
+//!
+//! Trie order is the order in which the leaves of the trie appear from left to right, i.e. key
+//! paths ordered bit by bit, most significant bit of the first byte first. This coincides with the
+//! lexicographic order of the key paths as byte arrays. The order is part of the API and will not
+//! change across versions, so it may be relied upon for canonical encodings, e.g. snapshot formats
+//! which are hashed.
+
+use std::iter::Peekable;
+
+use crate::{beatree::next_key, store::Store, KeyPath, Value};
+
+/// The number of values loaded at once.
+const BATCH_SIZE: usize = 1024;
+
+/// An iterator over keys and their values in trie order. See [`crate::Nomt::iter`] and
+/// [`crate::Session::iter_range`].
+///
+/// Every key is visited exactly once and keys are strictly ascending, in the order of
+/// `KeyPath`'s `Ord` implementation. Values which have expired by the time of the session, see
+/// [`crate::Session::write_with_ttl`], are skipped.
+///
+/// Values are loaded in batches, each reflecting the last commit as of loading it. Commits made
+/// while iterating may thus be partially visible; finish iterating before committing for a
+/// consistent view.
+pub struct KeyValueIter {
+    store: Store,
+    /// The last key of the range, inclusive.
+    end: KeyPath,
+    /// The key to continue loading from. `None` once all values are loaded.
+    next_start: Option<KeyPath>,
+    batch: Peekable<std::vec::IntoIter<(KeyPath, Value)>>,
+    /// Uncommitted writes taking precedence over the stored values, sorted by key. `None` means
+    /// the key is deleted.
+    overlay: Peekable<std::vec::IntoIter<(KeyPath, Option<Value>)>>,
+    /// The time by which values expire, if any. See [`crate::SessionParams::time`].
+    time: Option<u64>,
+}
+
+impl KeyValueIter {
+    /// Create an iterator over the inclusive range `start..=end`. The overlay must be sorted by
+    /// key, hold every key at most once and lie within the range. Values which have expired by
+    /// `time` are skipped.
+    pub(crate) fn new(
+        store: Store,
+        start: KeyPath,
+        end: KeyPath,
+        overlay: Vec<(KeyPath, Option<Value>)>,
+        time: Option<u64>,
+    ) -> Self {
+        KeyValueIter {
+            store,
+            end,
+            next_start: (start <= end).then_some(start),
+            batch: Vec::new().into_iter().peekable(),
+            overlay: overlay.into_iter().peekable(),
+            time,
+        }
+    }
+
+    /// Returns the key of the next stored value, loading the next batch if needed.
+    fn peek_stored(&mut self) -> Option<KeyPath> {
+        // A batch may be empty once expired values are removed, but more may follow.
+        while self.batch.peek().is_none() {
+            let start = self.next_start?;
+            let mut batch = self.store.load_value_range(start, self.end, BATCH_SIZE);
+            self.next_start = match batch.last() {
+                Some((last, _)) if batch.len() == BATCH_SIZE && *last < self.end => next_key(*last),
+                _ => None,
+            };
+            if let Some(now) = self.time {
+                self.store.retain_unexpired(&mut batch, now);
+            }
+            self.batch = batch.into_iter().peekable();
+        }
+        self.batch.peek().map(|(key, _)| *key)
+    }
+}
+
+impl Iterator for KeyValueIter {
+    type Item = (KeyPath, Value);
+
+    fn next(&mut self) -> Option<(KeyPath, Value)> {
+        loop {
+            let stored = self.peek_stored();
+            let overlaid = self.overlay.peek().map(|(key, _)| *key);
+            let take_stored = match (stored, overlaid) {
+                (None, None) => return None,
+                (Some(stored), Some(overlaid)) => stored < overlaid,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+            };
+            if take_stored {
+                return self.batch.next();
+            }
+            if stored == overlaid {
+                // The stored value is shadowed by the overlay.
+                self.batch.next();
+            }
+            // UNWRAP: peeked above.
+            let (key, value) = self.overlay.next().unwrap();
+            if let Some(value) = value {
+                return Some((key, value));
+            }
+        }
+    }
+}
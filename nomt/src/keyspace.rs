@@ -0,0 +1,197 @@
+//! Named keyspaces sharing a single database.
+//!
+//! A keyspace is a subtree of the trie: it holds the keys whose first byte equals its id. Since
+//! all keyspaces live in the same trie, they share the hash-table, the b-tree and the WAL, and a
+//! single commit updates any number of them atomically, with a single sync. The root of a
+//! keyspace is the node at the position of its prefix, i.e. the root of its subtree as defined by
+//! [`crate::SubtreeArchive::root`], so it changes only when keys of the keyspace change.
+//!
+//! Keys are given to a keyspace as regular key paths, whose first byte is replaced with the id of
+//! the keyspace. Key paths of the same keyspace must therefore differ in their remaining 31 bytes,
+//! which holds for key paths derived by hashing.
+//!
+//! Ids are assigned to names in order of first use, starting with 0, and recorded in the auxiliary
+//! column under [`AUX_PREFIX`] with the next commit. Keys written directly, without going through a
+//! keyspace, belong to the keyspace whose id equals their first byte.
+
+use std::collections::HashMap;
+
+use bitvec::prelude::*;
+use nomt_core::{
+    trie::{self, KeyPath, Node, TERMINATOR},
+    trie_pos::TriePosition,
+};
+
+use crate::{
+    state_sync::NodeReader, subtree::prefix_range, HashAlgorithm, KeyValueIter, Nomt, Session,
+    Value, ValueHandle,
+};
+
+/// The prefix of the auxiliary records mapping the names of keyspaces to their ids.
+pub(crate) const AUX_PREFIX: &[u8] = b"\0nomt:keyspace:";
+
+/// The maximum number of keyspaces, one per value of the first byte of a key path.
+pub const MAX_KEYSPACES: usize = 256;
+
+/// A named part of the key space of a database. See [`crate::Nomt::keyspace`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyspaceHandle {
+    id: u8,
+}
+
+impl KeyspaceHandle {
+    /// The id of the keyspace, which is the first byte of all of its key paths.
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// The prefix shared by all keys of the keyspace, in bits.
+    pub fn prefix(&self) -> BitVec<u8, Msb0> {
+        BitVec::from_element(self.id)
+    }
+
+    /// Returns the key path under which the given key path is stored in the trie, i.e. the key
+    /// path with its first byte replaced by the id of the keyspace.
+    ///
+    /// Use this to build the actuals of a commit and to look up keys of the keyspace in witnesses.
+    pub fn key_path(&self, mut path: KeyPath) -> KeyPath {
+        path[0] = self.id;
+        path
+    }
+
+    /// Read and write the keyspace within the given session.
+    ///
+    /// Views of several keyspaces may be used one after another within the same session, whose
+    /// commit then updates all of them atomically.
+    pub fn session<'a>(&self, session: &'a mut Session) -> KeyspaceSession<'a> {
+        KeyspaceSession {
+            keyspace: *self,
+            session,
+        }
+    }
+}
+
+/// A view of a [`Session`] restricted to a single keyspace. See [`KeyspaceHandle::session`].
+pub struct KeyspaceSession<'a> {
+    keyspace: KeyspaceHandle,
+    session: &'a mut Session,
+}
+
+impl KeyspaceSession<'_> {
+    /// Synchronously read the value stored under the given key of the keyspace. See
+    /// [`Session::read`].
+    pub fn read(&self, path: KeyPath) -> anyhow::Result<Option<Value>> {
+        self.session.read(self.keyspace.key_path(path))
+    }
+
+    /// Signal that the given key of the keyspace is going to be accessed. See
+    /// [`Session::warm_up`].
+    pub fn warm_up(&self, path: KeyPath) {
+        self.session.warm_up(self.keyspace.key_path(path))
+    }
+
+    /// Write the given values to keys of the keyspace, or delete them if `None`. See
+    /// [`Session::write_all`].
+    pub fn write_all(&mut self, writes: impl IntoIterator<Item = (KeyPath, Option<ValueHandle>)>) {
+        let keyspace = self.keyspace;
+        self.session.write_all(
+            writes
+                .into_iter()
+                .map(|(path, value)| (keyspace.key_path(path), value)),
+        )
+    }
+
+    /// Iterate over the keys of the keyspace and their values, as stored in the trie. See
+    /// [`Session::iter_range`].
+    pub fn iter(&self) -> KeyValueIter {
+        self.session.iter_prefix(&self.keyspace.prefix())
+    }
+}
+
+/// The ids assigned to the names of keyspaces.
+#[derive(Default)]
+pub(crate) struct Registry {
+    ids: HashMap<String, u8>,
+    /// Assignments not yet recorded in the auxiliary column, in order of assignment.
+    unrecorded: Vec<(String, u8)>,
+}
+
+impl Registry {
+    /// Load the assignments recorded in the auxiliary column.
+    pub(crate) fn load(records: Vec<(Vec<u8>, Vec<u8>)>) -> anyhow::Result<Self> {
+        let mut registry = Registry::default();
+        for (aux_key, value) in records {
+            let (Ok(name), Ok([id])) = (
+                String::from_utf8(aux_key[AUX_PREFIX.len()..].to_vec()),
+                <[u8; 1]>::try_from(&value[..]),
+            ) else {
+                anyhow::bail!("malformed keyspace record in the auxiliary column");
+            };
+            registry.ids.insert(name, id);
+        }
+        Ok(registry)
+    }
+
+    /// Returns the keyspace with the given name, assigning it the next free id if it has none.
+    pub(crate) fn get_or_assign(
+        &mut self,
+        name: &str,
+        assign: bool,
+    ) -> anyhow::Result<KeyspaceHandle> {
+        if let Some(&id) = self.ids.get(name) {
+            return Ok(KeyspaceHandle { id });
+        }
+        if !assign {
+            anyhow::bail!("unknown keyspace {name:?}");
+        }
+        if self.ids.len() == MAX_KEYSPACES {
+            anyhow::bail!("no more than {MAX_KEYSPACES} keyspaces are supported");
+        }
+        let id = self.ids.len() as u8;
+        self.ids.insert(name.to_string(), id);
+        self.unrecorded.push((name.to_string(), id));
+        Ok(KeyspaceHandle { id })
+    }
+
+    /// The auxiliary records of the assignments not yet recorded.
+    pub(crate) fn unrecorded(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.unrecorded
+            .iter()
+            .map(|(name, id)| ([AUX_PREFIX, name.as_bytes()].concat(), vec![*id]))
+            .collect()
+    }
+
+    /// Mark the first `n` unrecorded assignments as recorded.
+    pub(crate) fn mark_recorded(&mut self, n: usize) {
+        self.unrecorded.drain(..n);
+    }
+}
+
+/// Returns the root of the subtree of the synced trie under the given prefix.
+///
+/// If the trie ends above the prefix in a leaf of a key under the prefix, that leaf is the only
+/// node of the subtree and thus its root.
+pub(crate) fn subtree_root<T: HashAlgorithm>(
+    nomt: &Nomt<T>,
+    prefix: &BitSlice<u8, Msb0>,
+) -> anyhow::Result<Node> {
+    let mut reader = NodeReader::new(nomt);
+    let mut node = nomt.root();
+    let mut position = TriePosition::new();
+    for bit in prefix.iter().by_vals() {
+        if !trie::is_internal(&node) {
+            break;
+        }
+        position.down(bit);
+        node = reader.node(&position)?;
+    }
+
+    if (position.depth() as usize) < prefix.len() && trie::is_leaf(&node) {
+        let (start, end) = prefix_range(prefix);
+        let key_path = reader.leaf_data(&position)?.key_path;
+        if key_path < start || key_path > end {
+            return Ok(TERMINATOR);
+        }
+    }
+    Ok(node)
+}
@@ -0,0 +1,248 @@
+//! Storing values under variable-length keys.
+//!
+//! The trie only supports fixed-size [`KeyPath`]s. Variable-length keys are mapped onto key paths
+//! in two levels: every key is hashed to a key path, and the value stored under that key path is a
+//! bucket holding the full keys along with their values. Keys whose hashes collide share a bucket,
+//! so collisions never cause one key to overwrite another.
+//!
+//! Since the full key is part of the bucket, a proof of the bucket's key path proves the value
+//! of the full key: the verifier decodes the proven value with [`LargeKeyBucket::decode`] and looks
+//! up the key in it.
+
+use std::collections::BTreeMap;
+
+use crate::{KeyPath, KeyReadWrite, Session, Value};
+
+/// Maps variable-length keys of up to a maximum length onto key paths.
+#[derive(Clone, Copy)]
+pub struct LargeKeys {
+    max_key_len: usize,
+    key_path: fn(&[u8]) -> KeyPath,
+}
+
+impl LargeKeys {
+    /// Create a mapping for keys of up to `max_key_len` bytes, which are hashed to key paths with
+    /// BLAKE3 in key derivation mode.
+    pub fn new(max_key_len: usize) -> Self {
+        Self::with_key_path(max_key_len, |key| {
+            blake3::Hasher::new_derive_key("nomt large key")
+                .update(key)
+                .finalize()
+                .into()
+        })
+    }
+
+    /// Create a mapping for keys of up to `max_key_len` bytes, using a custom function to map keys
+    /// onto key paths.
+    ///
+    /// The function must be deterministic and its outputs should be uniformly distributed. It need
+    /// not be collision-resistant: colliding keys only make their bucket larger.
+    pub fn with_key_path(max_key_len: usize, key_path: fn(&[u8]) -> KeyPath) -> Self {
+        LargeKeys {
+            max_key_len,
+            key_path,
+        }
+    }
+
+    /// Returns the key path of the bucket holding the given key.
+    ///
+    /// Fails if the key is longer than the maximum key length.
+    pub fn key_path(&self, key: &[u8]) -> anyhow::Result<KeyPath> {
+        if key.len() > self.max_key_len {
+            anyhow::bail!(
+                "key of {} bytes exceeds the maximum of {} bytes",
+                key.len(),
+                self.max_key_len
+            );
+        }
+        Ok((self.key_path)(key))
+    }
+
+    /// Synchronously read the value stored under the given key. See [`Session::read`].
+    pub fn read(&self, session: &Session, key: &[u8]) -> anyhow::Result<Option<Value>> {
+        let bucket = match session.read(self.key_path(key)?)? {
+            Some(value) => LargeKeyBucket::decode(&value)?,
+            None => return Ok(None),
+        };
+        Ok(bucket.get(key).map(|value| value.to_vec()))
+    }
+
+    /// Create an empty batch of writes.
+    pub fn batch(&self) -> LargeKeyBatch {
+        LargeKeyBatch {
+            keys: *self,
+            writes: BTreeMap::new(),
+        }
+    }
+}
+
+/// A batch of writes to variable-length keys, which is turned into the actuals of a commit.
+pub struct LargeKeyBatch {
+    keys: LargeKeys,
+    writes: BTreeMap<KeyPath, BTreeMap<Vec<u8>, Option<Value>>>,
+}
+
+impl LargeKeyBatch {
+    /// Write a value to the given key, or delete it if `value` is `None`. Later writes to the same
+    /// key replace earlier ones.
+    ///
+    /// Fails if the key is longer than the maximum key length.
+    pub fn write(&mut self, key: &[u8], value: Option<Value>) -> anyhow::Result<()> {
+        let key_path = self.keys.key_path(key)?;
+        self.writes
+            .entry(key_path)
+            .or_default()
+            .insert(key.to_vec(), value);
+        Ok(())
+    }
+
+    /// Read the buckets affected by the batch from the session and apply the writes to them.
+    ///
+    /// Returns the actuals to pass to [`crate::Nomt::commit`], sorted by key path. Every affected
+    /// bucket is read and, if its contents change, written. Buckets which become empty are deleted.
+    pub fn into_actuals(self, session: &Session) -> anyhow::Result<Vec<(KeyPath, KeyReadWrite)>> {
+        for key_path in self.writes.keys() {
+            session.warm_up(*key_path);
+        }
+
+        let mut actuals = Vec::with_capacity(self.writes.len());
+        for (key_path, writes) in self.writes {
+            let prior = session.read(key_path)?;
+            let mut bucket = match prior {
+                Some(ref value) => LargeKeyBucket::decode(value)?,
+                None => LargeKeyBucket::default(),
+            };
+            let before = bucket.clone();
+            for (key, value) in writes {
+                match value {
+                    Some(value) => bucket.insert(key, value),
+                    None => bucket.remove(&key),
+                }
+            }
+
+            let access = if bucket == before {
+                KeyReadWrite::Read(prior)
+            } else if bucket.is_empty() {
+                KeyReadWrite::ReadThenWrite(prior, None)
+            } else {
+                KeyReadWrite::ReadThenWrite(prior, Some(bucket.encode()))
+            };
+            actuals.push((key_path, access));
+        }
+        Ok(actuals)
+    }
+}
+
+/// The set of keys and values stored under a single key path. See the [module docs][self].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LargeKeyBucket {
+    entries: BTreeMap<Vec<u8>, Value>,
+}
+
+impl LargeKeyBucket {
+    /// Decode a bucket from the value stored under its key path.
+    ///
+    /// The encoding is the number of entries, followed by every entry as its key and value, each
+    /// prefixed by its length. Entries are sorted by key. All integers are little-endian `u32`s.
+    pub fn decode(mut buf: &[u8]) -> anyhow::Result<Self> {
+        let count = read_u32(&mut buf)?;
+        let mut entries = BTreeMap::new();
+        for _ in 0..count {
+            let key = read_bytes(&mut buf)?;
+            let value = read_bytes(&mut buf)?;
+            if entries
+                .last_key_value()
+                .is_some_and(|(last, _)| *last >= key)
+            {
+                anyhow::bail!("malformed bucket: entries not sorted");
+            }
+            entries.insert(key, value);
+        }
+        if !buf.is_empty() {
+            anyhow::bail!("malformed bucket: trailing bytes");
+        }
+        Ok(LargeKeyBucket { entries })
+    }
+
+    /// Encode the bucket. See [`LargeKeyBucket::decode`].
+    pub fn encode(&self) -> Value {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for (key, value) in &self.entries {
+            buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            buf.extend_from_slice(key);
+            buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            buf.extend_from_slice(value);
+        }
+        buf
+    }
+
+    /// Returns the value stored under the given key, if any.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.entries.get(key).map(|value| &value[..])
+    }
+
+    /// Returns an iterator over all keys and values in the bucket, sorted by key.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (&key[..], &value[..]))
+    }
+
+    /// Returns the number of keys in the bucket.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the bucket holds no keys.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn insert(&mut self, key: Vec<u8>, value: Value) {
+        self.entries.insert(key, value);
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.entries.remove(key);
+    }
+}
+
+fn read_u32(buf: &mut &[u8]) -> anyhow::Result<u32> {
+    let Some((bytes, rest)) = buf.split_first_chunk::<4>() else {
+        anyhow::bail!("malformed bucket: unexpected end");
+    };
+    *buf = rest;
+    Ok(u32::from_le_bytes(*bytes))
+}
+
+fn read_bytes(buf: &mut &[u8]) -> anyhow::Result<Vec<u8>> {
+    let len = read_u32(buf)? as usize;
+    if buf.len() < len {
+        anyhow::bail!("malformed bucket: unexpected end");
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LargeKeyBucket;
+
+    #[test]
+    fn bucket_roundtrip() {
+        let mut bucket = LargeKeyBucket::default();
+        bucket.insert(b"b".to_vec(), vec![2; 100]);
+        bucket.insert(vec![7; 1000], vec![]);
+        bucket.insert(b"a".to_vec(), vec![1]);
+
+        let encoded = bucket.encode();
+        assert_eq!(LargeKeyBucket::decode(&encoded).unwrap(), bucket);
+        assert!(LargeKeyBucket::decode(&encoded[..encoded.len() - 1]).is_err());
+
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(LargeKeyBucket::decode(&trailing).is_err());
+    }
+}
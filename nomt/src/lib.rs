@@ -1,74 +1,390 @@
 #![warn(missing_docs)]
 
 //! A Nearly-Optimal Merkle Trie Database.
+//!
+//! The storage engine is gated behind the `storage` feature, which is enabled by default. Building
+//! with `default-features = false` only exposes the types needed for keys, proofs and witnesses,
+//! without pulling in the I/O machinery and its dependencies.
 
+#[cfg(feature = "storage")]
 use bitvec::prelude::*;
-use io::PagePool;
-use metrics::{Metric, Metrics};
+#[cfg(feature = "storage")]
+use metrics::Metric;
+use std::mem;
+#[cfg(feature = "storage")]
 use std::{
-    mem,
+    collections::{BTreeMap, HashMap},
     sync::{atomic::AtomicUsize, Arc},
+    time::{Duration, Instant},
 };
 
+#[cfg(feature = "storage")]
 use merkle::{UpdatePool, Updater};
+#[cfg(feature = "storage")]
 use nomt_core::{
-    page_id::ROOT_PAGE_ID,
-    proof::PathProof,
-    trie::{InternalData, NodeHasher, NodeHasherExt, ValueHash, TERMINATOR},
-    trie_pos::TriePosition,
+    page_id::{PageId, ROOT_PAGE_ID},
+    trie::{InternalData, NodeHasherExt, TERMINATOR},
 };
+use nomt_core::{proof::PathProof, trie::ValueHash, trie_pos::TriePosition};
+#[cfg(feature = "storage")]
 use page_cache::PageCache;
+#[cfg(feature = "storage")]
 use parking_lot::Mutex;
+#[cfg(feature = "storage")]
 use store::Store;
 
 // CARGO HACK: silence lint; this is used in integration tests
 
-pub use nomt_core::proof;
-pub use nomt_core::trie::{KeyPath, LeafData, Node, NodePreimage};
-pub use options::Options;
+#[cfg(feature = "storage")]
+pub use advisor::Recommendation;
+#[cfg(feature = "storage")]
+pub use anchor::RootAnchor;
+#[cfg(feature = "root-anchor-log")]
+pub use anchor::RootAnchorLog;
+#[cfg(feature = "storage")]
+pub use background_error::{BackgroundError, BackgroundErrorSource};
+#[cfg(feature = "storage")]
+pub use beatree::{CompactionStats, LeafInfo, LeafIter};
+#[cfg(feature = "storage")]
+pub use bitbox::{
+    HashTableCreationProgress, HashTableStats, WalReplayCancelled, WalReplayProgress, WalSink,
+};
+#[cfg(feature = "storage")]
+pub use clock::{Clock, ManualClock, SystemClock};
+#[cfg(feature = "storage")]
+pub use explain::{ReadExplanation, ReadStep, ReadStepKind};
+#[cfg(feature = "storage")]
+pub use fault::{FaultInjector, PageIo, SyncedFile};
+#[cfg(feature = "storage")]
+pub use incremental_root::{IncrementalRoot, SealedSubtree, MAX_SEAL_BITS};
+#[cfg(feature = "crash-simulation")]
+pub use io::crash::{CrashPoint, CrashSimulator, PowerLoss};
+#[cfg(feature = "storage")]
+pub use io::PagePool;
+#[cfg(feature = "storage")]
+pub use iter::KeyValueIter;
+#[cfg(feature = "storage")]
+pub use keyspace::{KeyspaceHandle, KeyspaceSession, MAX_KEYSPACES};
+#[cfg(feature = "storage")]
+pub use large_keys::{LargeKeyBatch, LargeKeyBucket, LargeKeys};
+#[cfg(feature = "storage")]
+pub use metrics::{encode_prometheus, registered_metrics, Metrics, MetricsSnapshot};
+#[cfg(any(feature = "scale", feature = "serde"))]
+pub use nomt_core::codec;
+pub use nomt_core::trie::{KeyPath, LeafData, Node, NodeHasher, NodePreimage};
+pub use nomt_core::{multi_proof, multi_proof_verification, proof, smt_proof, subtree_proof};
+#[cfg(feature = "storage")]
+pub use options::{
+    Durability, HugePages, IoBackend, IoUringMode, Options, PageCacheEviction, ReadConsistency,
+    RuntimeConfig, SessionParams, ThreadConfig, ThreadPriority, ThreadSettings, ValueCompression,
+    WitnessMode,
+};
+#[cfg(feature = "storage")]
+pub use page_cache::PageCacheStats;
+#[cfg(feature = "storage")]
+pub use sharded::{ShardedNomt, ShardedSession};
+pub use state_delta::{StateDeltaError, StateDeltaProof};
+#[cfg(feature = "storage")]
+pub use state_sync::{ChunkImporter, StateChunk};
+#[cfg(feature = "storage")]
+pub use streaming::ValueReader;
+#[cfg(feature = "storage")]
+pub use subtree::SubtreeArchive;
+#[cfg(feature = "storage")]
+pub use wal_archive::WalSegment;
+pub use witness_chunks::{WitnessChunk, WitnessChunkError};
+pub use witness_stats::WitnessStats;
+#[cfg(feature = "storage")]
+pub use witness_view::MappedWitness;
+pub use witness_view::{PathView, PathViews, WitnessView};
 
 // beatree module needs to be exposed to be benchmarked
 #[cfg(feature = "benchmarks")]
 #[allow(missing_docs)]
 pub mod beatree;
-#[cfg(not(feature = "benchmarks"))]
+#[cfg(all(feature = "storage", not(feature = "benchmarks")))]
 mod beatree;
 
+#[cfg(feature = "storage")]
+mod advisor;
+#[cfg(feature = "storage")]
+mod anchor;
+#[cfg(feature = "storage")]
+mod background_error;
+#[cfg(feature = "storage")]
 mod bitbox;
+#[cfg(feature = "storage")]
+mod clock;
+#[cfg(feature = "storage")]
+pub mod compat;
+#[cfg(feature = "storage")]
+pub mod debug;
+#[cfg(feature = "storage")]
+mod default_values;
+#[cfg(feature = "storage")]
+mod explain;
+#[cfg(feature = "storage")]
+mod fault;
+#[cfg(feature = "storage")]
+mod incremental_root;
+#[cfg(feature = "storage")]
+pub mod integrity;
+#[cfg(feature = "storage")]
+mod iter;
+#[cfg(feature = "storage")]
+mod keyspace;
+#[cfg(feature = "storage")]
+mod large_keys;
+#[cfg(feature = "storage")]
 mod merkle;
+#[cfg(feature = "storage")]
 mod metrics;
+#[cfg(feature = "storage")]
 mod options;
+#[cfg(feature = "storage")]
 mod page_cache;
+#[cfg(feature = "storage")]
 mod page_diff;
+#[cfg(feature = "storage")]
 mod page_region;
+#[cfg(feature = "storage")]
 mod rollback;
+#[cfg(feature = "storage")]
 mod rw_pass_cell;
+#[cfg(feature = "storage")]
 mod seglog;
+#[cfg(feature = "storage")]
+mod sharded;
+mod state_delta;
+#[cfg(feature = "storage")]
+mod state_sync;
+#[cfg(feature = "storage")]
 mod store;
+#[cfg(feature = "storage")]
+mod streaming;
+#[cfg(feature = "storage")]
+mod subtree;
+#[cfg(feature = "storage")]
 mod sys;
+#[cfg(feature = "storage")]
+mod threads;
 
+#[cfg(feature = "storage")]
 mod io;
 
+#[cfg(feature = "storage")]
+mod wal_archive;
+mod witness_chunks;
+#[cfg(feature = "scale")]
+mod witness_codec;
+mod witness_stats;
+mod witness_view;
+
+#[cfg(feature = "storage")]
 const MAX_COMMIT_CONCURRENCY: usize = 64;
 
 /// A full value stored within the trie.
+///
+/// Values may be empty. An empty value is present like any other: it is stored, read back as
+/// `Some(vec![])`, proven by the hash of the empty byte string and restored by rollback. Only
+/// `None`, wherever a value is optional, means that no value is stored under the key.
 pub type Value = Vec<u8>;
 
+/// A shared, immutable handle to a value buffer. See [`Session::write_all`].
+///
+/// Cloning the handle doesn't copy the buffer. Any type which can be viewed as a byte slice can
+/// back the handle, e.g. a `Vec<u8>`, an `Arc<[u8]>` or a reference-counted buffer from a
+/// networking or memory-mapping library.
+///
+/// Handles created by [`Session::write_streaming`] are not backed by a contiguous buffer. Viewing
+/// one as a byte slice gathers the value into a contiguous buffer, once.
+#[cfg(feature = "storage")]
+#[derive(Clone)]
+pub struct ValueHandle(ValueBuf);
+
+#[cfg(feature = "storage")]
+#[derive(Clone)]
+enum ValueBuf {
+    Contiguous(Arc<dyn AsRef<[u8]> + Send + Sync>),
+    Streamed(Arc<streaming::StreamedValue>),
+}
+
+#[cfg(feature = "storage")]
+impl ValueHandle {
+    /// Create a handle backed by the given buffer.
+    pub fn new(buf: impl AsRef<[u8]> + Send + Sync + 'static) -> Self {
+        ValueHandle(ValueBuf::Contiguous(Arc::new(buf)))
+    }
+
+    /// The size of the value in bytes.
+    pub fn len(&self) -> usize {
+        match self.0 {
+            ValueBuf::Contiguous(ref buf) => (**buf).as_ref().len(),
+            ValueBuf::Streamed(ref value) => value.len(),
+        }
+    }
+
+    /// Whether the value is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn streamed(value: streaming::StreamedValue) -> Self {
+        ValueHandle(ValueBuf::Streamed(Arc::new(value)))
+    }
+
+    /// The value as a byte slice, unless gathering it into a contiguous buffer would be needed.
+    pub(crate) fn as_contiguous(&self) -> Option<&[u8]> {
+        match self.0 {
+            ValueBuf::Contiguous(ref buf) => Some((**buf).as_ref()),
+            ValueBuf::Streamed(_) => None,
+        }
+    }
+
+    /// The consecutive parts making up the value.
+    pub(crate) fn parts(&self) -> Vec<&[u8]> {
+        match self.0 {
+            ValueBuf::Contiguous(ref buf) => vec![(**buf).as_ref()],
+            ValueBuf::Streamed(ref value) => value.chunks().collect(),
+        }
+    }
+
+    /// The hash of the value, computed without gathering it into a contiguous buffer.
+    pub(crate) fn hash<T: ValueHasher>(&self) -> ValueHash {
+        match self.0 {
+            ValueBuf::Contiguous(ref buf) => T::hash_value((**buf).as_ref()),
+            ValueBuf::Streamed(ref value) => T::hash_value_chunks(value.chunks()),
+        }
+    }
+
+    /// Copy the bytes starting at `pos` into `out`, returning the number of bytes copied.
+    pub(crate) fn read_at(&self, pos: usize, out: &mut [u8]) -> usize {
+        match self.0 {
+            ValueBuf::Contiguous(ref buf) => {
+                let value = (**buf).as_ref();
+                let rest = value.get(pos..).unwrap_or_default();
+                let n = out.len().min(rest.len());
+                out[..n].copy_from_slice(&rest[..n]);
+                n
+            }
+            ValueBuf::Streamed(ref value) => value.read_at(pos, out),
+        }
+    }
+}
+
+#[cfg(feature = "storage")]
+impl std::ops::Deref for ValueHandle {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self.0 {
+            ValueBuf::Contiguous(ref buf) => (**buf).as_ref(),
+            ValueBuf::Streamed(ref value) => value.contiguous(),
+        }
+    }
+}
+
+#[cfg(feature = "storage")]
+impl From<Value> for ValueHandle {
+    fn from(value: Value) -> Self {
+        ValueHandle::new(value)
+    }
+}
+
+#[cfg(feature = "storage")]
+impl std::fmt::Debug for ValueHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ValueHandle").field(&&self[..]).finish()
+    }
+}
+
+/// The error returned by commits writing a value larger than [`Options::max_value_size`].
+#[cfg(feature = "storage")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueTooLarge {
+    /// The maximum size of a value in bytes.
+    pub max: usize,
+    /// The size of the value in bytes.
+    pub got: usize,
+}
+
+#[cfg(feature = "storage")]
+impl std::fmt::Display for ValueTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "value of {} bytes exceeds the maximum of {} bytes",
+            self.got, self.max
+        )
+    }
+}
+
+#[cfg(feature = "storage")]
+impl std::error::Error for ValueTooLarge {}
+
+/// A user-supplied token identifying a commit, such as a block hash.
+///
+/// See [`Session::set_commit_token`].
+pub type CommitToken = [u8; 32];
+
+#[cfg(feature = "storage")]
 struct Shared {
     /// The current root of the trie.
     root: Node,
+    /// The time of the last [`Nomt::read_amplification`] report and the read totals back then.
+    read_report_base: (Duration, u64, u64),
+    /// The read totals as of the last commit, which have been added to the metrics.
+    read_metrics_base: (u64, u64),
+    /// The observation as of the last [`Nomt::tuning_advice`].
+    advice_base: advisor::Observation,
+    /// The root of the last coalesced commit, if it has not been synced, and thus anchored, yet.
+    unanchored_root: Option<Node>,
 }
 
 /// A witness that can be used to prove the correctness of state trie retrievals and updates.
 ///
-/// Expected to be serializable.
+/// See [`Witness::encode_versioned`] for its canonical encoding, with the `scale` feature.
+#[cfg_attr(
+    feature = "scale",
+    derive(parity_scale_codec::Encode, parity_scale_codec::Decode)
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Witness {
-    /// Various paths down the trie used as part of this witness.
+    /// Various paths down the trie used as part of this witness. Empty in
+    /// [`WitnessMode::Multiproof`].
     pub path_proofs: Vec<WitnessedPath>,
+    /// The paths down the trie merged into a multiproof, in [`WitnessMode::Multiproof`]. The paths
+    /// are in the same order as the path proofs would be, so the path indices of
+    /// [`WitnessedOperations`] index into [`MultiProof::paths`].
+    ///
+    /// [`MultiProof::paths`]: multi_proof::MultiProof::paths
+    pub multi_proof: Option<multi_proof::MultiProof>,
+    /// The pages of the trie accessed by the commit, with their contents prior to it, sorted by
+    /// page ID. Only recorded with [`SessionParams::witness_pages`], empty otherwise.
+    pub pages: Vec<WitnessedPage>,
+}
+
+/// A page of the trie accessed by a commit. See [`Witness::pages`].
+///
+/// This includes every page visited on the way to the terminal nodes of the keys, including
+/// those only read, and the pages holding the leaf children of terminal leaves, so that the
+/// page-level accesses of the commit can be reproduced.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WitnessedPage {
+    /// The ID of the page.
+    pub page_id: nomt_core::page_id::PageId,
+    /// The nodes of the page prior to the commit, in the order they are laid out in the page. A
+    /// page which did not exist consists of terminators.
+    pub nodes: Vec<Node>,
 }
 
 /// Operations provable by a corresponding witness.
 // TODO: the format of this structure depends heavily on how it'd be used with the path proofs.
+#[cfg_attr(
+    feature = "scale",
+    derive(parity_scale_codec::Encode, parity_scale_codec::Decode)
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WitnessedOperations {
     /// Read operations.
     pub reads: Vec<WitnessedRead>,
@@ -76,7 +392,125 @@ pub struct WitnessedOperations {
     pub writes: Vec<WitnessedWrite>,
 }
 
+impl WitnessedOperations {
+    /// Iterate over the reads and writes in the order the merkle update consumed them: by key,
+    /// with the read of a key preceding its write.
+    ///
+    /// Each operation carries its index in [`Self::reads`] or [`Self::writes`], along with the
+    /// index of the operation of the other kind on the same key, if any.
+    pub fn iter(&self) -> WitnessedOperationsIter<'_> {
+        WitnessedOperationsIter {
+            reads: &self.reads,
+            writes: &self.writes,
+            next_read: 0,
+            next_write: 0,
+        }
+    }
+}
+
+/// A read or write in [`WitnessedOperations`]. See [`WitnessedOperations::iter`].
+#[derive(Clone, Copy)]
+pub enum WitnessedOperation<'a> {
+    /// A read.
+    Read {
+        /// The index of the read in [`WitnessedOperations::reads`].
+        index: usize,
+        /// The index of the write of the same key in [`WitnessedOperations::writes`], which
+        /// follows the read.
+        write_index: Option<usize>,
+        /// The read.
+        read: &'a WitnessedRead,
+    },
+    /// A write.
+    Write {
+        /// The index of the write in [`WitnessedOperations::writes`].
+        index: usize,
+        /// The index of the read of the same key in [`WitnessedOperations::reads`], which
+        /// precedes the write.
+        read_index: Option<usize>,
+        /// The write.
+        write: &'a WitnessedWrite,
+    },
+}
+
+impl WitnessedOperation<'_> {
+    /// The key of the operation.
+    pub fn key(&self) -> &KeyPath {
+        match self {
+            WitnessedOperation::Read { read, .. } => &read.key,
+            WitnessedOperation::Write { write, .. } => &write.key,
+        }
+    }
+}
+
+/// An iterator over [`WitnessedOperations`] in the order of the merkle update. See
+/// [`WitnessedOperations::iter`].
+pub struct WitnessedOperationsIter<'a> {
+    reads: &'a [WitnessedRead],
+    writes: &'a [WitnessedWrite],
+    next_read: usize,
+    next_write: usize,
+}
+
+impl<'a> Iterator for WitnessedOperationsIter<'a> {
+    type Item = WitnessedOperation<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (reads, writes) = (self.reads, self.writes);
+        let read = reads.get(self.next_read);
+        let write = writes.get(self.next_write);
+        let read_first = match (read, write) {
+            (Some(read), Some(write)) => read.key <= write.key,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => return None,
+        };
+
+        if read_first {
+            // UNWRAP: checked above.
+            let read = read.unwrap();
+            let index = self.next_read;
+            self.next_read += 1;
+            let write_index = write
+                .filter(|write| write.key == read.key)
+                .map(|_| self.next_write);
+            Some(WitnessedOperation::Read {
+                index,
+                write_index,
+                read,
+            })
+        } else {
+            // UNWRAP: checked above.
+            let write = write.unwrap();
+            let index = self.next_write;
+            self.next_write += 1;
+            // The read of the same key, if any, was the last one returned.
+            let read_index = self
+                .next_read
+                .checked_sub(1)
+                .filter(|&read_index| reads[read_index].key == write.key);
+            Some(WitnessedOperation::Write {
+                index,
+                read_index,
+                write,
+            })
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.reads.len() - self.next_read + self.writes.len() - self.next_write;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for WitnessedOperationsIter<'_> {}
+
 /// A path observed in the witness.
+#[cfg_attr(
+    feature = "scale",
+    derive(parity_scale_codec::Encode, parity_scale_codec::Decode)
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WitnessedPath {
     /// Proof of a query path along the trie.
     pub inner: PathProof,
@@ -84,27 +518,64 @@ pub struct WitnessedPath {
     pub path: TriePosition,
 }
 
+impl Witness {
+    /// Verify the path proofs against the root prior to the commit and return the prior value
+    /// hashes of all keys read or written by the given operations. `None` means that the key had
+    /// no value. See [`proof::verify_prior_values`].
+    ///
+    /// This allows re-executing the operations from the witness alone. Witnesses in
+    /// [`WitnessMode::Multiproof`] are not supported and fail with
+    /// [`proof::PriorValuesError::MissingPath`].
+    pub fn prior_values<H: NodeHasher>(
+        &self,
+        operations: &WitnessedOperations,
+        prev_root: Node,
+    ) -> Result<std::collections::BTreeMap<KeyPath, Option<ValueHash>>, proof::PriorValuesError>
+    {
+        let paths = self
+            .path_proofs
+            .iter()
+            .map(|path| (path.path.path(), &path.inner));
+        let reads = operations
+            .reads
+            .iter()
+            .map(|read| (read.key, read.path_index));
+        let writes = operations
+            .writes
+            .iter()
+            .map(|write| (write.key, write.path_index));
+        proof::verify_prior_values::<H>(prev_root, paths, reads.chain(writes))
+    }
+}
+
 /// A witness of a read value.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WitnessedRead {
     /// The key of the read value.
     pub key: KeyPath,
-    /// The hash of the value witnessed. None means no value.
+    /// The hash of the value witnessed. None means no value, while an empty value is witnessed
+    /// by its hash.
     pub value: Option<ValueHash>,
     /// The index of the path in the corresponding witness.
     pub path_index: usize,
 }
 
 /// A witness of a write operation.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WitnessedWrite {
     /// The key of the written value.
     pub key: KeyPath,
-    /// The hash of the written value. `None` means "delete".
+    /// The hash of the written value. `None` means "delete", while writing an empty value is
+    /// witnessed by its hash.
     pub value: Option<ValueHash>,
     /// The index of the path in the corresponding witness.
     pub path_index: usize,
 }
 
 /// Whether a key was read, written, or both, along with old and new values.
+///
+/// `None` means no value: a read found none, a write deletes the key. `Some` of an empty value is
+/// an empty value, which is present and distinct from a deletion, see [`Value`].
 #[derive(Debug, Clone)]
 pub enum KeyReadWrite {
     /// The key was read. Contains the read value.
@@ -162,6 +633,7 @@ impl KeyReadWrite {
         }
     }
 
+    #[cfg(feature = "storage")]
     fn to_compact<T: HashAlgorithm>(&self) -> crate::merkle::KeyReadWrite {
         let hash = |v: &Value| T::hash_value(v);
         match self {
@@ -175,6 +647,7 @@ impl KeyReadWrite {
 }
 
 /// An instance of the Nearly-Optimal Merkle Trie Database.
+#[cfg(feature = "storage")]
 pub struct Nomt<T: HashAlgorithm> {
     merkle_update_pool: UpdatePool,
     /// The handle to the page cache.
@@ -185,11 +658,50 @@ pub struct Nomt<T: HashAlgorithm> {
     /// The number of active sessions. Expected to be either 0 or 1.
     session_cnt: Arc<AtomicUsize>,
     metrics: Metrics,
+    root_anchor: Option<anchor::AnchorWorker>,
+    audit_merkle_updates: bool,
+    thread_config: ThreadConfig,
+    max_value_size: usize,
+    default_values: Arc<default_values::DefaultValues>,
+    advisor_config: Mutex<advisor::Config>,
+    keyspaces: Mutex<keyspace::Registry>,
+    /// The options the database was opened with, to reopen it with on [`Nomt::refresh`] and to
+    /// open forks with.
+    options: Options,
     _marker: std::marker::PhantomData<T>,
+    /// Deletes the directory of a fork, see [`Nomt::fork`]. Declared last so that the directory
+    /// is deleted after everything else is dropped.
+    discard: Option<DiscardOnDrop>,
+}
+
+/// Deletes a directory when dropped.
+#[cfg(feature = "storage")]
+struct DiscardOnDrop(std::path::PathBuf);
+
+#[cfg(feature = "storage")]
+impl Drop for DiscardOnDrop {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
 }
 
+/// The new root, the witness and witnessed operations if requested, the estimated costs and the
+/// proofs of the session's proof keys, as returned by `Nomt::commit_inner`.
+#[cfg(feature = "storage")]
+type CommitInnerOutput = (
+    Node,
+    Option<Witness>,
+    Option<WitnessedOperations>,
+    CommitCosts,
+    Vec<PathProof>,
+);
+
+#[cfg(feature = "storage")]
 impl<T: HashAlgorithm> Nomt<T> {
     /// Open the database with the given options.
+    ///
+    /// The hash algorithm `T` is recorded when the database is created. Opening it with a
+    /// different one fails.
     pub fn open(mut o: Options) -> anyhow::Result<Self> {
         if o.commit_concurrency == 0 {
             anyhow::bail!("commit concurrency must be greater than zero".to_string());
@@ -199,25 +711,114 @@ impl<T: HashAlgorithm> Nomt<T> {
             o.commit_concurrency = MAX_COMMIT_CONCURRENCY;
         }
 
-        let metrics = Metrics::new(o.metrics);
-
-        let page_pool = PagePool::new();
-        let store = Store::open(&o, page_pool.clone())?;
+        let metrics = Metrics::new(o.metrics.then(|| {
+            o.metrics_label
+                .clone()
+                .unwrap_or_else(|| o.path.display().to_string())
+        }));
+        let fsync_skipping = o.durability == Durability::PowerLossProtected;
+        metrics.set(Metric::FsyncSkipping, fsync_skipping as u64);
+
+        let page_pool = o
+            .page_pool
+            .clone()
+            .unwrap_or_else(|| PagePool::with_regions(None, o.huge_pages, o.numa_node));
+        let options = o.clone();
+        let store = Store::open(
+            &o,
+            page_pool.clone(),
+            metrics.clone(),
+            hasher_fingerprint::<T>(),
+        )?;
         let root_page = store.load_page(ROOT_PAGE_ID)?;
         let page_cache = PageCache::new(root_page, &o, metrics.clone());
         let root = compute_root_node::<T>(&page_cache);
+        let keyspaces = keyspace::Registry::load(store.read_aux_prefix(keyspace::AUX_PREFIX))?;
+        // The pages read above belong to the last sync only if the writer hasn't synced since.
+        store.check_snapshot()?;
+        let (logical_reads, physical_page_reads) = store.read_totals();
+        let read_report_base = (
+            store.clock().monotonic(),
+            logical_reads,
+            physical_page_reads,
+        );
+        let advice_base = advisor::Observation::new(&metrics, &store.hash_table_stats());
+        let advisor_config = advisor::Config::new(&o);
         Ok(Self {
-            merkle_update_pool: UpdatePool::new(o.commit_concurrency, o.warm_up),
+            merkle_update_pool: UpdatePool::new(o.commit_concurrency, o.warm_up, &o.thread_config)?,
             page_cache,
             page_pool,
             store,
-            shared: Arc::new(Mutex::new(Shared { root })),
+            shared: Arc::new(Mutex::new(Shared {
+                root,
+                read_report_base,
+                read_metrics_base: (logical_reads, physical_page_reads),
+                advice_base,
+                unanchored_root: None,
+            })),
             session_cnt: Arc::new(AtomicUsize::new(0)),
             metrics,
+            root_anchor: o.root_anchor.map(anchor::AnchorWorker::new),
+            audit_merkle_updates: o.audit_merkle_updates,
+            thread_config: o.thread_config,
+            max_value_size: o.max_value_size,
+            default_values: Arc::new(o.default_values.clone()),
+            advisor_config: Mutex::new(advisor_config),
+            keyspaces: Mutex::new(keyspaces),
+            options,
             _marker: std::marker::PhantomData,
+            discard: None,
         })
     }
 
+    /// Pick up the syncs of the writer since a read-only database was opened or last refreshed.
+    ///
+    /// Re-reads the manifest and, if another sync has happened, reopens the database, dropping
+    /// all caches. Returns whether it did. Fails if the database isn't opened read-only, and
+    /// should be retried if the writer is in the middle of a sync. See [`Options::read_only`].
+    ///
+    /// This function assumes no sessions are active and panics otherwise.
+    pub fn refresh(&mut self) -> anyhow::Result<bool> {
+        assert_eq!(
+            self.session_cnt.load(std::sync::atomic::Ordering::Relaxed),
+            0,
+            "refresh cannot run while a session is active"
+        );
+        if !self.options.read_only {
+            anyhow::bail!("refresh: the database is not opened read-only");
+        }
+        if self.store.manifest_sync_seqn()? == self.store.sync_seqn() {
+            return Ok(false);
+        }
+        *self = Self::open(self.options.clone())?;
+        Ok(true)
+    }
+
+    /// Returns whether the database is opened read-only. See [`Options::read_only`].
+    pub fn is_read_only(&self) -> bool {
+        self.options.read_only
+    }
+
+    /// Fail if the database is opened read-only and the writer has synced since it was opened or
+    /// last refreshed, in which case the data read since may not belong to [`Nomt::root`].
+    ///
+    /// Reads which can fail, such as [`Session::read`], check this themselves. Call it after
+    /// reads which can't, such as iterating with [`Nomt::iter`], to know whether their results
+    /// are valid.
+    ///
+    /// This also fails once a sync has failed: the database must be reopened then, and every
+    /// commit and read fails until it is.
+    pub fn check_snapshot(&self) -> anyhow::Result<()> {
+        self.store.check_snapshot()
+    }
+
+    fn ensure_writable(&self) -> anyhow::Result<()> {
+        if self.options.read_only {
+            anyhow::bail!("the database is opened read-only");
+        }
+        Ok(())
+    }
+
     /// Returns a recent root of the trie.
     pub fn root(&self) -> Node {
         self.shared.lock().root.clone()
@@ -235,7 +836,8 @@ impl<T: HashAlgorithm> Nomt<T> {
     /// This is used for testing for now.
     #[doc(hidden)]
     pub fn read(&self, path: KeyPath) -> anyhow::Result<Option<Value>> {
-        self.store.load_value(path)
+        let value = self.store.load_value(path)?;
+        Ok(self.default_values.apply(&path, value))
     }
 
     /// Creates a new [`Session`] object, that serves a purpose of capturing the reads and writes
@@ -243,36 +845,121 @@ impl<T: HashAlgorithm> Nomt<T> {
     /// re-execute the same operations without having access to the full trie.
     ///
     /// Only a single session may be created at a time. Creating a new session without dropping or
-    /// committing an existing open session will lead to a panic.
+    /// committing an existing open session will lead to a panic. Sessions reading the last synced
+    /// state are exempt, see [`SessionParams::read_consistency`].
     pub fn begin_session(&self) -> Session {
-        self.begin_session_inner(/* allow_rollback */ true)
+        self.begin_session_with_params(SessionParams::default())
     }
 
-    fn begin_session_inner(&self, allow_rollback: bool) -> Session {
-        let prev = self
-            .session_cnt
-            .swap(1, std::sync::atomic::Ordering::Relaxed);
-        assert_eq!(prev, 0, "only one session could be active at a time");
+    /// Creates a new [`Session`] with the given parameters. See [`Nomt::begin_session`].
+    pub fn begin_session_with_params(&self, params: SessionParams) -> Session {
+        self.begin_session_inner(/* allow_rollback */ true, params)
+    }
+
+    fn begin_session_inner(&self, allow_rollback: bool, params: SessionParams) -> Session {
+        // Sessions reading the last synced state are never committed, so they need neither the
+        // session slot nor the warm-ups of the merkle trie.
+        let exclusive = params.read_consistency == ReadConsistency::Latest;
+        if exclusive {
+            let prev = self
+                .session_cnt
+                .swap(1, std::sync::atomic::Ordering::Relaxed);
+            assert_eq!(prev, 0, "only one session could be active at a time");
+            self.page_cache.begin_session();
+        }
         let store = self.store.clone();
-        let rollback_delta = if allow_rollback {
+        let rollback_delta = if allow_rollback && exclusive {
             self.store.rollback().map(|r| r.delta_builder())
         } else {
             None
         };
+        let mut merkle_updater = self.merkle_update_pool.begin(
+            self.page_cache.clone(),
+            self.page_pool.clone(),
+            self.store.clone(),
+            self.root(),
+            /* warm_up */ exclusive,
+            params.record_witness && params.witness_pages,
+        );
+        merkle_updater.set_witness_filter(params.witness_filter);
         Session {
             store,
-            merkle_updater: Some(self.merkle_update_pool.begin(
-                self.page_cache.clone(),
-                self.page_pool.clone(),
-                self.store.clone(),
-                self.root(),
-            )),
+            merkle_updater: Some(merkle_updater),
             session_cnt: self.session_cnt.clone(),
             metrics: self.metrics.clone(),
             rollback_delta,
+            commit_token: None,
+            block_number: None,
+            aux_writes: BTreeMap::new(),
+            replay: false,
+            record_witness: params.record_witness,
+            witness_mode: params.witness_mode,
+            deduplicated_value_fetches_base: self.store.deduplicated_value_fetches(),
+            bulk_writes: Vec::new(),
+            expiries: HashMap::new(),
+            sequential_readahead: params.sequential_readahead,
+            sequential_ranges: Vec::new(),
+            speculative_reads: Mutex::new(Vec::new()),
+            read_consistency: params.read_consistency,
+            time: params.time,
+            proof_keys: Vec::new(),
+            default_values: self.default_values.clone(),
         }
     }
 
+    /// Returns the token attached to the last commit, if any.
+    ///
+    /// The token is persisted atomically with the commit itself, so after a crash this tells
+    /// whether the commit carrying a given token made it to disk. Commits which carried no token,
+    /// including those performed by [`Nomt::rollback`], reset this to `None`. With
+    /// [`Options::commit_coalescing`], this reflects the last commit synced to disk.
+    pub fn last_commit_token(&self) -> Option<CommitToken> {
+        self.store.last_commit_token()
+    }
+
+    /// Returns the block number attached to the last commit, if any.
+    ///
+    /// Like [`Nomt::last_commit_token`], this is persisted atomically with the commit, reset by
+    /// commits which carried no block number and reflects the last commit synced to disk.
+    pub fn last_block_number(&self) -> Option<u64> {
+        self.store.last_block_number()
+    }
+
+    /// Returns the value stored under the given key in the auxiliary column. See
+    /// [`Session::write_aux`].
+    ///
+    /// This includes the writes of commits held back by [`Options::commit_coalescing`].
+    pub fn read_aux(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.store.read_aux(key)
+    }
+
+    /// Returns the sequence number of the sync which persisted the commit carrying the given block
+    /// number. See [`Session::set_block_number`].
+    ///
+    /// If several syncs carried the block number, e.g. because the block was committed again
+    /// after a [`Nomt::rollback`], the last one is returned. With [`Options::commit_coalescing`],
+    /// only the block number of the last commit of each sync is recorded. Returns `None` if no
+    /// sync carried the block number.
+    pub fn seqn_for_block(&self, block_number: u64) -> Option<u32> {
+        self.store.seqn_for_block(block_number)
+    }
+
+    /// Returns the maximum size of a value in bytes. See [`Options::max_value_size`].
+    pub fn max_value_size(&self) -> usize {
+        self.max_value_size
+    }
+
+    /// Returns the value read for the key while it is absent, if any. See
+    /// [`Options::default_value`].
+    pub fn default_value(&self, path: KeyPath) -> Option<&[u8]> {
+        self.default_values.get(&path).map(|value| &value[..])
+    }
+
+    /// Returns the sequence number of the last sync, which is 0 for an empty database.
+    pub fn sync_seqn(&self) -> u32 {
+        self.store.sync_seqn()
+    }
+
     /// Commit the transaction and returns the new root.
     ///
     /// The actuals are a list of key paths and the corresponding read/write operations. The list
@@ -283,7 +970,21 @@ impl<T: HashAlgorithm> Nomt<T> {
         actuals: Vec<(KeyPath, KeyReadWrite)>,
     ) -> anyhow::Result<Node> {
         match self.commit_inner(session, actuals, false)? {
-            (node, None, None) => Ok(node),
+            (node, None, None, _, _) => Ok(node),
+            // UNWRAP: witness specified to false
+            _ => unreachable!(),
+        }
+    }
+
+    /// Commit the transaction like [`Nomt::commit`], additionally returning an estimate of the
+    /// resources it consumed.
+    pub fn commit_with_costs(
+        &self,
+        session: Session,
+        actuals: Vec<(KeyPath, KeyReadWrite)>,
+    ) -> anyhow::Result<CommitOutput> {
+        match self.commit_inner(session, actuals, false)? {
+            (root, None, None, costs, _) => Ok(CommitOutput { root, costs }),
             // UNWRAP: witness specified to false
             _ => unreachable!(),
         }
@@ -293,50 +994,204 @@ impl<T: HashAlgorithm> Nomt<T> {
     ///
     /// The actuals are a list of key paths and the corresponding read/write operations. The list
     /// must be sorted by the key paths in ascending order. The key paths must be unique.
+    ///
+    /// Fails without committing if the session was created with
+    /// [`SessionParams::record_witness`] disabled.
     pub fn commit_and_prove(
         &self,
         session: Session,
         actuals: Vec<(KeyPath, KeyReadWrite)>,
     ) -> anyhow::Result<(Node, Witness, WitnessedOperations)> {
+        if !session.record_witness {
+            anyhow::bail!("session does not record a witness");
+        }
+        let witness_mode = session.witness_mode;
         match self.commit_inner(session, actuals, true)? {
-            (node, Some(witness), Some(witnessed_ops)) => Ok((node, witness, witnessed_ops)),
+            (node, Some(mut witness), Some(witnessed_ops), _, _) => {
+                if witness_mode == WitnessMode::Multiproof {
+                    // The path proofs are sorted by their paths, as the multiproof requires.
+                    let path_proofs = mem::take(&mut witness.path_proofs);
+                    witness.multi_proof = Some(multi_proof::MultiProof::from_path_proofs(
+                        path_proofs.into_iter().map(|path| path.inner).collect(),
+                    ));
+                }
+                Ok((node, witness, witnessed_ops))
+            }
             // UNWRAP: witness specified to true
             _ => unreachable!(),
         }
     }
 
+    /// Check whether committing the given actuals would produce `expected_root`, without
+    /// persisting anything.
+    ///
+    /// The trie update is performed against a private page cache, which is discarded afterwards.
+    /// It starts out with copies of the pages changed by commits held back by
+    /// [`Options::commit_coalescing`], which are neither flushed nor otherwise affected. Neither
+    /// the on-disk state nor the shared page cache are modified, but all other pages needed for
+    /// the update have to be loaded from disk, so this is generally slower than a warmed-up
+    /// [`Nomt::commit`].
+    ///
+    /// The actuals are a list of key paths and the corresponding read/write operations. The list
+    /// must be sorted by the key paths in ascending order. The key paths must be unique.
+    ///
+    /// This function assumes no sessions are active and panics otherwise.
+    pub fn validate_commit(
+        &self,
+        actuals: Vec<(KeyPath, KeyReadWrite)>,
+        expected_root: Node,
+    ) -> anyhow::Result<bool> {
+        let _slot = SessionSlot::take(&self.session_cnt, "validate_commit");
+        self.validate_commit_inner(actuals, expected_root)
+    }
+
+    fn validate_commit_inner(
+        &self,
+        actuals: Vec<(KeyPath, KeyReadWrite)>,
+        expected_root: Node,
+    ) -> anyhow::Result<bool> {
+        check_actuals_sorted(&actuals);
+
+        let page_cache = self
+            .page_cache
+            .detached_copy(&self.page_pool, self.page_cache.shard_count());
+
+        let mut compact_actuals = Vec::with_capacity(actuals.len());
+        for (path, read_write) in &actuals {
+            compact_actuals.push((*path, read_write.to_compact::<T>()));
+        }
+
+        let merkle_update = self
+            .merkle_update_pool
+            .begin(
+                page_cache,
+                self.page_pool.clone(),
+                self.store.clone(),
+                self.root(),
+                /* warm_up */ false,
+                /* witness_pages */ false,
+            )
+            .update_and_prove::<T>(compact_actuals, /* witness */ false)
+            .join()?;
+
+        Ok(merkle_update.root == expected_root)
+    }
+
+    // Run the merkle update against a private page cache with a different number of workers than
+    // the live one. Used to audit merkle updates for nondeterminism. Held back commits are flushed
+    // first, as the private page cache loads the pages from disk.
+    fn audit_merkle_update(
+        &self,
+        compact_actuals: Vec<(KeyPath, merkle::KeyReadWrite)>,
+    ) -> anyhow::Result<(PageCache, merkle::Output)> {
+        self.flush()?;
+        let shard_count = if self.page_cache.shard_count() == 1 {
+            4
+        } else {
+            1
+        };
+        let root_page = self.store.load_page(ROOT_PAGE_ID)?;
+        let page_cache = PageCache::new_detached(root_page, shard_count);
+        let output = UpdatePool::new(shard_count, false, &self.thread_config)?
+            .begin(
+                page_cache.clone(),
+                self.page_pool.clone(),
+                self.store.clone(),
+                self.root(),
+                /* warm_up */ false,
+                /* witness_pages */ false,
+            )
+            .update_and_prove::<T>(compact_actuals, /* witness */ false)
+            .join()?;
+        Ok((page_cache, output))
+    }
+
     // Effectively commit the transaction.
     // If 'witness' is set to true, it collects the witness and
     // returns `(Node, Some(Witness), Some(WitnessedOperations))`
     // Otherwise, it solely returns the new root node, returning
-    // `(Node, None, None)`
+    // `(Node, None, None)`. The estimated costs are returned in either case.
     fn commit_inner(
         &self,
         mut session: Session,
-        actuals: Vec<(KeyPath, KeyReadWrite)>,
+        mut actuals: Vec<(KeyPath, KeyReadWrite)>,
         witness: bool,
-    ) -> anyhow::Result<(Node, Option<Witness>, Option<WitnessedOperations>)> {
-        if cfg!(debug_assertions) {
-            // Check that the actuals are sorted by key path.
-            for i in 1..actuals.len() {
-                assert!(
-                    actuals[i].0 > actuals[i - 1].0,
-                    "actuals are not sorted at index {}",
-                    i
-                );
-            }
+    ) -> anyhow::Result<CommitInnerOutput> {
+        self.ensure_writable()?;
+        // The rollback delta is written before the store is reached.
+        self.store.check_poisoned()?;
+        if session.read_consistency == ReadConsistency::LastSynced {
+            anyhow::bail!("sessions reading the last synced state can't be committed");
+        }
+        check_actuals_sorted(&actuals);
+        if !session.replay
+            && session
+                .aux_writes
+                .keys()
+                .any(|key| key.starts_with(store::RESERVED_AUX_PREFIX))
+        {
+            anyhow::bail!("auxiliary keys starting with \"\\0nomt:\" are reserved");
         }
+        let mut bulk_writes = take_bulk_writes(&mut session, &actuals)?;
+        self.default_values
+            .normalize(&mut actuals, &mut bulk_writes);
+        // A replayed segment already holds the deletions of the values which had expired by the
+        // original commit, and its auxiliary writes hold the records of the expiries.
+        let expiries = if session.replay {
+            Vec::new()
+        } else {
+            take_expiries(&mut session, &mut actuals, &mut bulk_writes, &self.store)?
+        };
+        check_value_sizes(&actuals, &bulk_writes, self.max_value_size)?;
+        self.store
+            .record_logical_reads((actuals.len() + bulk_writes.len()) as u64);
+        let mut costs = CommitCosts::default();
         if let Some(delta_builder) = session.rollback_delta.take() {
             // UNWRAP: if rollback_delta is `Some``, then rollback must be also `Some`.
             let rollback = self.store.rollback().unwrap();
-            rollback.commit(self.store.clone(), &actuals, delta_builder)?;
+            if bulk_writes.is_empty() {
+                rollback.commit(self.store.clone(), &actuals, delta_builder, self.root())?;
+            } else {
+                // The written values only serve to store the priors compactly and are not needed.
+                let mut rollback_actuals = actuals.clone();
+                rollback_actuals.extend(
+                    bulk_writes
+                        .iter()
+                        .map(|(path, _)| (*path, KeyReadWrite::Write(None))),
+                );
+                rollback.commit(
+                    self.store.clone(),
+                    &rollback_actuals,
+                    delta_builder,
+                    self.root(),
+                )?;
+            }
         }
 
-        let mut compact_actuals = Vec::with_capacity(actuals.len());
-        for (path, read_write) in &actuals {
+        let mut compact_actuals = Vec::with_capacity(actuals.len() + bulk_writes.len());
+        // Sessions which don't record a witness skip the merkle paths of keys which are only read.
+        let visit_reads = witness || session.record_witness;
+        for (path, read_write) in actuals
+            .iter()
+            .filter(|(_, rw)| visit_reads || rw.is_write())
+        {
             compact_actuals.push((path.clone(), read_write.to_compact::<T>()));
         }
+        for (path, value) in &bulk_writes {
+            let value_hash = value.as_ref().map(|value| value.hash::<T>());
+            compact_actuals.push((*path, crate::merkle::KeyReadWrite::Write(value_hash)));
+        }
+        if !bulk_writes.is_empty() {
+            compact_actuals.sort_by_key(|(path, _)| *path);
+        }
+
+        let audit = if self.audit_merkle_updates {
+            Some(self.audit_merkle_update(compact_actuals.clone())?)
+        } else {
+            None
+        };
 
+        let merkle_timer = self.metrics.record(Metric::MerkleUpdateTime);
         // UNWRAP: merkle_updater always `Some` during lifecycle.
         let merkle_update_handle = session
             .merkle_updater
@@ -345,79 +1200,909 @@ impl<T: HashAlgorithm> Nomt<T> {
             .update_and_prove::<T>(compact_actuals, witness);
 
         let mut tx = self.store.new_value_tx();
+        for (key, value) in mem::take(&mut session.aux_writes) {
+            tx.write_aux(key, value);
+        }
+        for (path, expiry) in expiries {
+            tx.set_expiry(path, expiry);
+        }
+        let keyspace_records = self.keyspaces.lock().unrecorded();
+        let new_keyspaces = keyspace_records.len();
+        for (key, value) in keyspace_records {
+            tx.write_aux(key, Some(value));
+        }
         for (path, read_write) in actuals {
             if let KeyReadWrite::Write(value) | KeyReadWrite::ReadThenWrite(_, value) = read_write {
-                tx.write_value::<T>(path, value);
+                costs.writes += 1;
+                if let Some(ref value) = value {
+                    costs.value_bytes += value.len() as u64;
+                    costs.hashes += 1;
+                }
+                tx.write_value::<T>(path, value.map(ValueHandle::from));
+            } else {
+                costs.reads += 1;
             }
         }
+        for (path, value) in bulk_writes {
+            costs.writes += 1;
+            if let Some(ref value) = value {
+                costs.value_bytes += value.len() as u64;
+                costs.hashes += 1;
+            }
+            tx.write_value::<T>(path, value);
+        }
+
+        let merkle_update = merkle_update_handle.join()?;
+        drop(merkle_timer);
+        if let Some((audit_page_cache, audit_update)) = audit {
+            check_merkle_audit(
+                (&self.page_cache, &merkle_update),
+                (&audit_page_cache, &audit_update),
+            );
+        }
 
-        let merkle_update = merkle_update_handle.join();
+        for (page_id, page_diff) in merkle_update.page_diffs.iter() {
+            costs.pages_touched += 1;
+            costs.hashes += page_diff.count() as u64;
+            match (page_diff.cleared(), self.page_cache.is_stored(page_id)) {
+                (true, true) => costs.deleted_pages += 1,
+                (false, false) => costs.new_pages += 1,
+                _ => {}
+            }
+        }
 
         let new_root = merkle_update.root;
+        // The page cache holds the updated trie until the commit below hands it to the store.
+        let proofs = merkle::prove_keys(
+            new_root,
+            self.page_cache.clone(),
+            &self.store,
+            &session.proof_keys,
+        )?;
         self.shared.lock().root = new_root;
-        self.store
-            .commit(tx, self.page_cache.clone(), merkle_update.page_diffs)?;
+        let synced = self.store.commit(
+            tx,
+            self.page_cache.clone(),
+            merkle_update.page_diffs,
+            session.commit_token,
+            session.block_number,
+        )?;
+        self.keyspaces.lock().mark_recorded(new_keyspaces);
+        self.anchor_root(Some(new_root), synced);
+
+        let (logical_reads, physical_page_reads) = self.store.read_totals();
+        let (base_logical_reads, base_physical_page_reads) = mem::replace(
+            &mut self.shared.lock().read_metrics_base,
+            (logical_reads, physical_page_reads),
+        );
+        self.metrics
+            .count_n(Metric::LogicalReads, logical_reads - base_logical_reads);
+        self.metrics.count_n(
+            Metric::PhysicalPageReads,
+            physical_page_reads - base_physical_page_reads,
+        );
+        if self.metrics.is_active() {
+            let hash_table_stats = self.store.hash_table_stats();
+            self.metrics
+                .set(Metric::HashTablePageLoads, hash_table_stats.page_loads);
+            self.metrics.set(
+                Metric::HashTableProbedBuckets,
+                hash_table_stats.probed_buckets,
+            );
+            self.metrics.set(
+                Metric::PagePoolReservedBytes,
+                self.page_pool.reserved_bytes() as u64,
+            );
+            self.metrics.set(
+                Metric::PagePoolFreePages,
+                self.page_pool.free_pages() as u64,
+            );
+            self.metrics
+                .set(Metric::PagePoolAllocations, self.page_pool.allocations());
+        }
 
         Ok((
             new_root,
             merkle_update.witness,
             merkle_update.witnessed_operations,
+            costs,
+            proofs,
         ))
     }
 
-    /// Perform a rollback of the last `n` commits.
+    /// Sync the commits held back by [`Options::commit_coalescing`] to disk.
     ///
-    /// This function assumes no sessions are active and panics otherwise.
-    pub fn rollback(&self, n: usize) -> anyhow::Result<()> {
-        if n == 0 {
-            return Ok(());
-        }
-        let Some(rollback) = self.store.rollback() else {
-            anyhow::bail!("rollback: not enabled");
-        };
-        let Some(traceback) = rollback.truncate(n)? else {
-            anyhow::bail!("rollback: not enough logged for rolling back");
-        };
-
-        // Begin a new session. We do not allow rollback for this operation because that would
-        // interfere with the rollback log: if another rollback were to be issued, it must rollback
-        // the changes in the rollback log and not the changes performed by the current rollback.
-        let sess = self.begin_session_inner(/* allow_rollback */ false);
+    /// This is a no-op if there are none. Dropping the database flushes as well, but can only
+    /// report a failure to [`Options::on_background_error`], so callers which need to handle it
+    /// should flush before dropping.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        let synced = self.store.flush()?;
+        self.anchor_root(None, synced);
+        Ok(())
+    }
 
-        // Convert the traceback into a series of write commands.
-        let mut actuals = Vec::new();
-        for (key, value) in traceback {
-            sess.warm_up(key);
-            let value = KeyReadWrite::Write(value);
-            actuals.push((key, value));
+    /// Dispatch the root persisted by a sync to the root anchor, if any. The roots of earlier
+    /// commits coalesced into the same sync are never persisted, so they are not anchored.
+    fn anchor_root(&self, new_root: Option<Node>, synced: bool) {
+        let Some(ref root_anchor) = self.root_anchor else {
+            return;
+        };
+        let mut shared = self.shared.lock();
+        if new_root.is_some() {
+            shared.unanchored_root = new_root;
         }
+        if synced {
+            if let Some(root) = shared.unanchored_root.take() {
+                root_anchor.dispatch(self.store.sync_seqn(), root);
+            }
+        }
+    }
 
-        self.commit_inner(sess, actuals, /* witness */ false)?;
-
+    /// Evict all cached pages of the merkle trie and leaves of the b-tree, returning their memory to
+    /// the page pool, e.g. to measure cold-start behavior or to recover memory.
+    ///
+    /// Commits held back by [`Options::commit_coalescing`] are flushed first. If `release_memory`
+    /// is set, the memory of the free pages of the pool is also returned to the OS. The root page
+    /// of the trie is kept.
+    ///
+    /// This function assumes no sessions are active and panics otherwise.
+    pub fn drop_caches(&self, release_memory: bool) -> anyhow::Result<()> {
+        assert_eq!(
+            self.session_cnt.load(std::sync::atomic::Ordering::Relaxed),
+            0,
+            "drop_caches cannot run while a session is active"
+        );
+        self.flush()?;
+        self.store.drop_caches(&self.page_cache, release_memory);
         Ok(())
     }
 
-    /// Return Nomt's metrics.
-    /// To collect them, they need to be activated at [`Nomt`] creation
-    pub fn metrics(&self) -> Metrics {
-        self.metrics.clone()
+    /// Create a copy of the database in a new directory at the given path, e.g. to branch off
+    /// the state for a test network or a simulation.
+    ///
+    /// Commits held back by [`Options::commit_coalescing`] are flushed first. On file systems
+    /// supporting reflinks, such as btrfs and XFS, the copy shares the data of the files with the
+    /// original until either of them is modified, so it is cheap regardless of the size of the
+    /// database. Elsewhere, the files are copied in full. The copy is a database of its own which
+    /// can be opened with [`Nomt::open`]. Files outside of the database directory, such as
+    /// [`Options::wal_mirror`], are not copied.
+    ///
+    /// This function assumes no sessions are active and panics otherwise. Fails if the path
+    /// already exists.
+    pub fn fork_to(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        assert_eq!(
+            self.session_cnt.load(std::sync::atomic::Ordering::Relaxed),
+            0,
+            "fork_to cannot run while a session is active"
+        );
+        self.flush()?;
+        self.store.fork_to(path.as_ref())
     }
-}
 
-/// A session presents a way of interaction with the trie.
-///
-/// During a session the application is assumed to perform a zero or more reads and writes. When
-/// the session is finished, the application can [commit][`Nomt::commit_and_prove`] the changes
-/// and create a [`Witness`] that can be used to prove the correctness of replaying the same
-/// operations.
-pub struct Session {
-    store: Store,
+    /// Fork the database into a new directory at the given path and open the fork, e.g. to apply
+    /// speculative transactions to the current state and throw them away afterwards.
+    ///
+    /// The fork is created as by [`Nomt::fork_to`], so on file systems supporting reflinks it
+    /// shares all pages of the hash-table and the b-tree it doesn't modify with this database. It
+    /// is opened with the options of this database shaping its contents and performance, including
+    /// [`Options::encryption_key`]. Those connecting it to the outside are not carried over: the
+    /// fork doesn't write to [`Options::wal_mirror`], [`Options::wal_sink`]s or
+    /// [`Options::wal_archive`], doesn't report roots to [`Options::root_anchor`] or errors to
+    /// [`Options::on_background_error`], has no [`Options::fault_injector`] and is labelled by
+    /// its path in metrics, so that nothing done to the fork reaches this database or anything
+    /// following it. A fork of an in-memory database is stored at the path.
+    ///
+    /// The directory of the fork is deleted when the fork is dropped, discarding everything
+    /// committed to it. Use [`Nomt::fork_to`] for a copy which is kept.
+    ///
+    /// This function assumes no sessions are active and panics otherwise. Fails if the path
+    /// already exists or the database is opened read-only.
+    pub fn fork(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if self.options.read_only {
+            anyhow::bail!("fork: the database is opened read-only");
+        }
+        self.fork_to(path)?;
+        // From here on, the directory is ours to delete.
+        let discard = DiscardOnDrop(path.to_path_buf());
+
+        let mut fork = Self::open(self.options.for_fork(path))?;
+        fork.discard = Some(discard);
+        Ok(fork)
+    }
+
+    /// Export a consistent copy of the database to a new directory at the given path, while the
+    /// database continues serving sessions and commits, e.g. for online backups.
+    ///
+    /// The copy reflects the last sync when the export starts; commits held back by
+    /// [`Options::commit_coalescing`] are not included. Syncs are blocked only briefly at the
+    /// start. Afterwards, pages about to be overwritten by syncs are copied first, which slows
+    /// syncs down until the export finishes. The rollback log and files outside of the database
+    /// directory, such as [`Options::wal_mirror`], are not copied. The copy can be opened with
+    /// [`Nomt::open`].
+    ///
+    /// Fails if the path already exists.
+    pub fn snapshot_to(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        self.store.snapshot_to(path.as_ref())
+    }
+
+    /// Restore a database as of the sync with the given sequence number, from a copy made with
+    /// [`Nomt::snapshot_to`] or [`Nomt::fork_to`] and the [`WalSegment`]s archived since with
+    /// [`Options::wal_archive`], e.g. to recover from a bad commit or a lost device.
+    ///
+    /// The base snapshot is copied to the path of the options. The segments must be given in
+    /// order of their sequence numbers, as returned by [`WalSegment::read_dir`]. Those up to the
+    /// last sync of the snapshot are skipped, and the following ones are replayed up to
+    /// `until_seqn`, each as a commit synced on its own. The restored database therefore has the
+    /// same sync sequence numbers, roots, commit tokens, block numbers and auxiliary records as
+    /// the original had. It is then opened with the given options.
+    ///
+    /// Values written with [`Session::write_with_ttl`] are deleted by the replayed commits just as
+    /// by the original ones.
+    ///
+    /// Fails if the path already exists, if the snapshot is past `until_seqn` or if a segment up
+    /// to it is missing, in which case the restored directory is removed.
+    pub fn restore(
+        o: Options,
+        base_snapshot: impl AsRef<std::path::Path>,
+        segments: impl IntoIterator<Item = WalSegment>,
+        until_seqn: u32,
+    ) -> anyhow::Result<Self> {
+        if o.read_only {
+            anyhow::bail!("restore: the database must be opened for writing");
+        }
+        if o.path.exists() {
+            anyhow::bail!("restore: {} already exists", o.path.display());
+        }
+        store::copy_database(base_snapshot.as_ref(), &o.path)?;
+        let res =
+            Self::replay_segments(&o, segments, until_seqn).and_then(|()| Self::open(o.clone()));
+        if res.is_err() {
+            let _ = std::fs::remove_dir_all(&o.path);
+        }
+        res
+    }
+
+    /// Replay the given segments on the database at the path of the options, up to `until_seqn`.
+    ///
+    /// The database is opened anew afterwards, which loads the expiries and keyspaces recorded by
+    /// the replayed auxiliary writes.
+    fn replay_segments(
+        o: &Options,
+        segments: impl IntoIterator<Item = WalSegment>,
+        until_seqn: u32,
+    ) -> anyhow::Result<()> {
+        let mut o = o.clone();
+        o.wal_archive = None;
+        o.root_anchor = None;
+        let nomt = Self::open(o)?;
+        let mut seqn = nomt.sync_seqn();
+        if seqn > until_seqn {
+            anyhow::bail!("restore: the base snapshot is at sync {seqn}, past {until_seqn}");
+        }
+        for segment in segments {
+            if seqn == until_seqn {
+                break;
+            }
+            if segment.sync_seqn() <= seqn {
+                continue;
+            }
+            if segment.sync_seqn() != seqn + 1 {
+                anyhow::bail!("restore: the segment of sync {} is missing", seqn + 1);
+            }
+            let mut session = nomt.begin_session();
+            session.replay = true;
+            session.commit_token = segment.commit_token();
+            session.block_number = segment.block_number();
+            session.aux_writes = segment.aux().iter().cloned().collect();
+            let actuals = segment
+                .values()
+                .iter()
+                .map(|(key, value)| (*key, KeyReadWrite::Write(value.clone())))
+                .collect();
+            nomt.commit(session, actuals)?;
+            // Sync each segment on its own, also when commits are coalesced.
+            nomt.flush()?;
+            seqn += 1;
+        }
+        if seqn != until_seqn {
+            anyhow::bail!("restore: the segments end at sync {seqn}, before {until_seqn}");
+        }
+        Ok(())
+    }
+
+    /// Iterate over all keys and their values in trie order, which is the lexicographic order of
+    /// the key paths. This order is guaranteed to remain stable across versions.
+    ///
+    /// See [`KeyValueIter`] for how commits made while iterating are reflected.
+    pub fn iter(&self) -> KeyValueIter {
+        KeyValueIter::new(self.store.clone(), [0; 32], [0xff; 32], Vec::new(), None)
+    }
+
+    /// Returns the keyspace with the given name, creating it if it doesn't exist yet.
+    ///
+    /// Keyspaces are disjoint parts of the trie, each with its own root, which share the files of
+    /// the database. A single commit updates any number of keyspaces atomically, see
+    /// [`KeyspaceHandle::session`]. A new keyspace is assigned the next free id, which is recorded
+    /// with the next commit. At most [`MAX_KEYSPACES`] keyspaces can be created. Fails for unknown
+    /// names if the database is opened read-only.
+    pub fn keyspace(&self, name: &str) -> anyhow::Result<KeyspaceHandle> {
+        self.keyspaces
+            .lock()
+            .get_or_assign(name, !self.options.read_only)
+    }
+
+    /// Returns the root of the given keyspace, i.e. the root of the subtree under its prefix, see
+    /// [`SubtreeArchive::root`]. The root of an empty keyspace is the terminator.
+    ///
+    /// Commits held back by [`Options::commit_coalescing`] are flushed first.
+    pub fn keyspace_root(&self, keyspace: KeyspaceHandle) -> anyhow::Result<Node> {
+        self.flush()?;
+        keyspace::subtree_root(self, &keyspace.prefix())
+    }
+
+    /// Compute the root resulting from updates fed in sorted batches, sealing subtrees as they
+    /// can no longer change, so that their roots are known before all updates are. See
+    /// [`IncrementalRoot`].
+    ///
+    /// The key space is split into `2^seal_bits` subtrees by key prefix, `seal_bits` being at most
+    /// [`MAX_SEAL_BITS`]. The updates apply to the last commit, which must remain the last commit
+    /// while feeding them. They are not committed: this only computes the root a commit of them
+    /// would have. Commits held back by [`Options::commit_coalescing`] are flushed first.
+    pub fn incremental_root(&self, seal_bits: u32) -> anyhow::Result<IncrementalRoot<'_, T>> {
+        if seal_bits > MAX_SEAL_BITS {
+            anyhow::bail!("incremental root: at most {MAX_SEAL_BITS} seal bits");
+        }
+        self.flush()?;
+        Ok(IncrementalRoot::new(self, seal_bits))
+    }
+
+    /// Export the keys and values under the given prefix, along with the root of the subtree they
+    /// form, for transfer into another database with [`Nomt::import_subtree`].
+    ///
+    /// The archive reflects the last commit. The prefix is given in bits and may be at most 256
+    /// bits long.
+    pub fn export_subtree(&self, prefix: &BitSlice<u8, Msb0>) -> SubtreeArchive {
+        let (start, end) = subtree::prefix_range(prefix);
+        let entries = self.store.load_value_range(start, end, usize::MAX);
+        SubtreeArchive::new::<T>(prefix.to_bitvec(), entries)
+    }
+
+    /// Replace the keys and values under the prefix of the archive with those of the archive and
+    /// return the new root. Keys outside of the prefix are left untouched.
+    ///
+    /// The archive is verified first, see [`SubtreeArchive::verify`]. Once imported, the subtree
+    /// under the prefix has the root of the archive.
+    ///
+    /// This function assumes no sessions are active and panics otherwise.
+    pub fn import_subtree(&self, archive: SubtreeArchive) -> anyhow::Result<Node> {
+        archive.verify::<T>()?;
+        let (start, end) = subtree::prefix_range(archive.prefix());
+
+        let session = self.begin_session();
+        let mut actuals = self
+            .store
+            .load_value_range(start, end, usize::MAX)
+            .into_iter()
+            .map(|(key, _)| (key, KeyReadWrite::Write(None)))
+            .collect::<BTreeMap<_, _>>();
+        for (key, value) in archive.entries() {
+            actuals.insert(*key, KeyReadWrite::Write(Some(value.clone())));
+        }
+        for key in actuals.keys() {
+            session.warm_up(*key);
+        }
+
+        self.commit(session, actuals.into_iter().collect())
+    }
+
+    /// Export the chunks with the given indices when the key space is split into `2^chunk_bits`
+    /// chunks by key prefix, for bootstrapping another database with a [`ChunkImporter`]. Every
+    /// chunk holds the keys and values under its prefix along with a proof against the root.
+    ///
+    /// Commits held back by [`Options::commit_coalescing`] are flushed first. The chunks reflect the
+    /// last commit. `chunk_bits` may be at most 32.
+    pub fn export_chunks(
+        &self,
+        chunk_bits: u32,
+        range: std::ops::Range<u64>,
+    ) -> anyhow::Result<Vec<StateChunk>> {
+        if chunk_bits > 32 || range.end > 1 << chunk_bits {
+            anyhow::bail!("state sync: chunk index out of range");
+        }
+        self.flush()?;
+        range
+            .map(|index| {
+                state_sync::export_chunk(self, state_sync::chunk_prefix(chunk_bits, index))
+            })
+            .collect()
+    }
+
+    /// Perform a rollback of the last `n` commits.
+    ///
+    /// This function assumes no sessions are active and panics otherwise.
+    pub fn rollback(&self, n: usize) -> anyhow::Result<()> {
+        self.rollback_with_token(n, None)
+    }
+
+    /// Perform a rollback of the last `n` commits like [`Nomt::rollback`], attaching the given
+    /// token to the commit undoing them. See [`Session::set_commit_token`].
+    pub(crate) fn rollback_with_token(
+        &self,
+        n: usize,
+        token: Option<CommitToken>,
+    ) -> anyhow::Result<()> {
+        if n == 0 {
+            return Ok(());
+        }
+        self.ensure_writable()?;
+        let Some(rollback) = self.store.rollback() else {
+            anyhow::bail!("rollback: not enabled");
+        };
+        let Some(traceback) = rollback.truncate(self.store.clone(), n)? else {
+            anyhow::bail!("rollback: not enough logged for rolling back");
+        };
+
+        // Begin a new session. We do not allow rollback for this operation because that would
+        // interfere with the rollback log: if another rollback were to be issued, it must rollback
+        // the changes in the rollback log and not the changes performed by the current rollback.
+        let mut params = SessionParams::default();
+        params.record_witness(false);
+        let mut sess = self.begin_session_inner(/* allow_rollback */ false, params);
+        sess.commit_token = token;
+
+        // Convert the traceback into a series of write commands.
+        let mut actuals = Vec::new();
+        for (key, value) in traceback {
+            sess.warm_up(key);
+            let value = KeyReadWrite::Write(value);
+            actuals.push((key, value));
+        }
+
+        self.commit_inner(sess, actuals, /* witness */ false)?;
+
+        Ok(())
+    }
+
+    /// Returns the roots which [`Nomt::rollback_to_root`] can return to, from the most recent to
+    /// the oldest one. The `n`th root is the root prior to the `n`th most recent commit, which
+    /// [`Nomt::rollback`] with `n` returns to.
+    ///
+    /// Only commits whose rollback deltas recorded their prior root are covered, which excludes
+    /// commits made by versions not recording it and everything before them. Empty if rollback is
+    /// not enabled.
+    pub fn retained_roots(&self) -> Vec<Node> {
+        self.store.rollback().map_or(Vec::new(), |rollback| {
+            rollback
+                .prior_roots()
+                .into_iter()
+                .map_while(|root| root)
+                .collect()
+        })
+    }
+
+    /// Roll back as many commits as needed to return to the given root, which must be the current
+    /// root or one of the [`Nomt::retained_roots`]. If a root occurs more than once, the most
+    /// recent occurrence is returned to.
+    ///
+    /// All commits are rolled back at once, like [`Nomt::rollback`]: the reverse deltas are applied
+    /// in a single commit, which truncates the rollback log in the same sync. A crash leaves the
+    /// database either before or after the whole rollback.
+    ///
+    /// This function assumes no sessions are active and panics otherwise.
+    pub fn rollback_to_root(&self, root: Node) -> anyhow::Result<()> {
+        if root == self.root() {
+            return Ok(());
+        }
+        if self.store.rollback().is_none() {
+            anyhow::bail!("rollback: not enabled");
+        }
+        let Some(n) = self
+            .retained_roots()
+            .iter()
+            .position(|prior| *prior == root)
+        else {
+            anyhow::bail!("rollback: root not retained");
+        };
+        self.rollback(n + 1)
+    }
+
+    /// Discard the rollback deltas of all but the last `keep` commits and reclaim their disk space.
+    /// Afterwards, at most `keep` commits can be rolled back with [`Nomt::rollback`].
+    ///
+    /// Commits held back by [`Options::commit_coalescing`] are flushed first. To bound the rollback
+    /// log automatically at every sync instead, see [`Options::max_rollback_log_len`].
+    ///
+    /// This function assumes no sessions are active and panics otherwise. Fails if rollback is
+    /// not enabled.
+    pub fn prune_to(&self, keep: usize) -> anyhow::Result<()> {
+        assert_eq!(
+            self.session_cnt.load(std::sync::atomic::Ordering::Relaxed),
+            0,
+            "prune_to cannot run while a session is active"
+        );
+        self.ensure_writable()?;
+        self.store.prune_rollback(keep)
+    }
+
+    /// Reclaim the disk space of values overwritten or deleted since they were stored in the
+    /// value logs, see [`Options::value_log_threshold`].
+    ///
+    /// Every log file in which at most `max_live_ratio` of the bytes belong to live values is
+    /// collected: the live values are rewritten by the next commit, and the file is deleted when
+    /// the commit after it is synced. Files still being appended to are closed for writing and
+    /// considered as well. Returns the number of bytes which will be reclaimed.
+    ///
+    /// This scans all log files, reading every entry's header and the leaves referring to it.
+    pub fn collect_value_log_garbage(&self, max_live_ratio: f64) -> anyhow::Result<u64> {
+        self.ensure_writable()?;
+        self.store.collect_value_log_garbage(max_live_ratio)
+    }
+
+    /// Iterate over the leaf pages of the b-tree storing the values, in key order, along with their
+    /// physical page numbers and fill levels.
+    ///
+    /// This is intended for maintenance tooling deciding which regions of the store to compact.
+    /// The iterator reflects the state as of its creation, excluding commits which have not been
+    /// synced yet, and reads uncached leaves from disk. Commits block when syncing until the
+    /// iterator is dropped.
+    pub fn btree_leaves(&self) -> LeafIter {
+        self.store.btree_leaves()
+    }
+
+    /// Returns the read amplification since the previous call, or since the database was opened.
+    ///
+    /// Calling this periodically yields the read amplification per interval. The totals since the
+    /// database was opened are part of the [`Nomt::metrics`].
+    pub fn read_amplification(&self) -> ReadAmplification {
+        let (logical_reads, physical_page_reads) = self.store.read_totals();
+        let now = self.store.clock().monotonic();
+        let (since, base_logical_reads, base_physical_page_reads) = mem::replace(
+            &mut self.shared.lock().read_report_base,
+            (now, logical_reads, physical_page_reads),
+        );
+        ReadAmplification {
+            logical_reads: logical_reads - base_logical_reads,
+            physical_page_reads: physical_page_reads - base_physical_page_reads,
+            interval: now - since,
+        }
+    }
+
+    /// Returns recommendations for tuning the database to its workload, based on its behavior
+    /// since the previous call, or since the database was opened.
+    ///
+    /// The recommendations take cache hit rates, the probe lengths of the hash-table and the
+    /// times of the phases of commits into account. Except for the hash-table, these are only
+    /// known with [`Options::metrics`] enabled. Calling this periodically, e.g. every few minutes,
+    /// judges each interval on its own. Nothing is recommended for intervals with too little
+    /// activity to judge.
+    ///
+    /// Recommendations for which [`Recommendation::is_live`] holds can be applied with
+    /// [`Nomt::apply_tuning`], the others by reopening the database with adjusted options.
+    pub fn tuning_advice(&self) -> Vec<Recommendation> {
+        let hash_table = self.store.hash_table_stats();
+        let now = advisor::Observation::new(&self.metrics, &hash_table);
+        let base = mem::replace(&mut self.shared.lock().advice_base, now.clone());
+        advisor::advise(&self.advisor_config.lock(), &base, &now, &hash_table)
+    }
+
+    /// Apply a recommendation of [`Nomt::tuning_advice`] to the open database.
+    ///
+    /// Fails if the recommendation can't be applied live, see [`Recommendation::is_live`], or if
+    /// applying it fails.
+    pub fn apply_tuning(&self, recommendation: &Recommendation) -> anyhow::Result<()> {
+        match recommendation {
+            Recommendation::GrowHashTable { buckets, .. } => self.resize_hash_table(*buckets),
+            _ => anyhow::bail!("applying requires reopening the database: {recommendation}"),
+        }
+    }
+
+    /// Change the settings of the open database which are set in the given configuration. See
+    /// [`RuntimeConfig`].
+    ///
+    /// Changes to the I/O settings apply to requests taken by the I/O workers from then on,
+    /// including those of a sync running in the background.
+    ///
+    /// This function assumes no sessions are active and panics otherwise. Fails if the commit
+    /// concurrency is zero.
+    pub fn reconfigure(&self, config: RuntimeConfig) -> anyhow::Result<()> {
+        let _slot = SessionSlot::take(&self.session_cnt, "reconfigure");
+        self.reconfigure_inner(config)
+    }
+
+    fn reconfigure_inner(&self, config: RuntimeConfig) -> anyhow::Result<()> {
+        if let Some(commit_concurrency) = config.commit_concurrency {
+            if commit_concurrency == 0 {
+                anyhow::bail!("commit concurrency must be greater than zero");
+            }
+            // The page cache is sharded by the concurrency set at opening, which bounds the
+            // number of workers able to update the trie in parallel.
+            let commit_concurrency = commit_concurrency.min(self.page_cache.shard_count());
+            self.merkle_update_pool
+                .resize(commit_concurrency, &self.thread_config)?;
+            self.advisor_config
+                .lock()
+                .set_commit_concurrency(commit_concurrency);
+        }
+
+        let tuning = self.store.io_pool().tuning();
+        if config.io_submit_batch.is_some() || config.io_max_in_flight.is_some() {
+            let mut limits = tuning.limits();
+            limits.submit_batch = config.io_submit_batch.unwrap_or(limits.submit_batch);
+            limits.max_in_flight = config.io_max_in_flight.unwrap_or(limits.max_in_flight);
+            tuning.set_limits(limits);
+        }
+        if let Some(prioritize_reads) = config.io_prioritize_reads {
+            tuning.set_prioritize_reads(prioritize_reads);
+        }
+        if let Some(durability) = config.durability {
+            tuning.set_durability(durability);
+            let fsync_skipping = durability == Durability::PowerLossProtected;
+            self.metrics
+                .set(Metric::FsyncSkipping, fsync_skipping as u64);
+        }
+        Ok(())
+    }
+
+    /// Returns statistics about the hash-table storing the pages of the merkle trie.
+    pub fn hash_table_stats(&self) -> HashTableStats {
+        self.store.hash_table_stats()
+    }
+
+    /// Returns statistics about the cache of the pages of the merkle trie, including the memory
+    /// it holds. See [`Options::page_cache_size_bytes`].
+    pub fn page_cache_stats(&self) -> PageCacheStats {
+        self.page_cache.stats()
+    }
+
+    /// Grow the hash-table storing the pages of the merkle trie to the given number of buckets,
+    /// while the database stays available.
+    ///
+    /// A hash-table file of the new size is created next to the existing one. From then on, pages
+    /// are moved into it incrementally: pages written by commits are moved along with the write,
+    /// and up to [`Options::hashtable_resize_budget`] further pages are moved on every sync. Once
+    /// all pages have been moved, the next sync replaces the old hash-table with the new one.
+    /// Progress can be followed through [`HashTableStats::pages_to_migrate`]. A resize survives
+    /// crashes and restarts.
+    ///
+    /// Creating the file blocks syncs. While resizing, page loads which miss the new hash-table
+    /// probe the old one as well, compaction is paused and [`Nomt::snapshot_to`] fails.
+    ///
+    /// Fails if the hash-table already has at least as many buckets or is already being resized.
+    pub fn resize_hash_table(&self, buckets: u32) -> anyhow::Result<()> {
+        self.ensure_writable()?;
+        self.store.resize_hash_table(buckets)
+    }
+
+    /// Give back disk space which is no longer needed, e.g. after pruning a large part of the
+    /// state.
+    ///
+    /// The hash-table is resized to be at most half full, if that makes it smaller, see
+    /// [`Nomt::resize_hash_table`]. The free pages at the end of the files of the b-tree storing
+    /// the values are dropped and the files truncated. Free pages elsewhere are kept for reuse.
+    ///
+    /// This performs syncs carrying no commit until all pages have been moved to the smaller
+    /// hash-table, each moving up to [`Options::hashtable_resize_budget`] pages, and blocks
+    /// commits meanwhile. Commits held back by [`Options::commit_coalescing`] are flushed first.
+    /// A resize started before is carried to completion as well.
+    ///
+    /// This function assumes no sessions are active and panics otherwise. Fails while a
+    /// [`Nomt::snapshot_to`] is in progress.
+    pub fn shrink(&self) -> anyhow::Result<()> {
+        assert_eq!(
+            self.session_cnt.load(std::sync::atomic::Ordering::Relaxed),
+            0,
+            "shrink cannot run while a session is active"
+        );
+        self.ensure_writable()?;
+        self.flush()?;
+        self.store.shrink(&self.page_cache)
+    }
+
+    /// Report how much of the space of the b-tree storing the values is in use, see
+    /// [`CompactionStats`].
+    ///
+    /// This reads every leaf of the b-tree which is not cached, blocking the current thread, and
+    /// holds back syncs meanwhile. It is meant for occasional monitoring, e.g. to decide when to
+    /// call [`Nomt::compact`].
+    pub fn compaction_stats(&self) -> CompactionStats {
+        self.store.compaction_stats()
+    }
+
+    /// Rewrite the b-tree storing the values to give back the space left by deleted values, e.g.
+    /// during a scheduled maintenance window.
+    ///
+    /// Deletions not written to the leaves yet are written out. Then leaves and large values
+    /// stored at the end of the leaf file are moved into free pages before them, and the free
+    /// pages at the end of the files of the b-tree are dropped and the files truncated, until no
+    /// more pages can be moved. Values stored in the value log are not moved, see
+    /// [`Nomt::collect_value_log_garbage`]. The contents of the database and its root are left
+    /// unchanged.
+    ///
+    /// This performs syncs carrying no commit, each reading all leaves, and blocks commits
+    /// meanwhile. Commits held back by [`Options::commit_coalescing`] are flushed first.
+    ///
+    /// This function assumes no sessions are active and panics otherwise. Fails while a
+    /// [`Nomt::snapshot_to`] is in progress.
+    pub fn compact(&self) -> anyhow::Result<()> {
+        assert_eq!(
+            self.session_cnt.load(std::sync::atomic::Ordering::Relaxed),
+            0,
+            "compact cannot run while a session is active"
+        );
+        self.ensure_writable()?;
+        self.flush()?;
+        self.store.compact(&self.page_cache)
+    }
+
+    /// Return Nomt's metrics.
+    /// To collect them, they need to be activated at [`Nomt`] creation
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+}
+
+#[cfg(feature = "storage")]
+impl<T: HashAlgorithm> Drop for Nomt<T> {
+    fn drop(&mut self) {
+        // Don't lose commits which are still held back for coalescing, unless they are discarded
+        // along with the fork anyway.
+        if self.discard.is_none() {
+            if let Err(e) = self.flush() {
+                self.store
+                    .io_pool()
+                    .background_errors()
+                    .report(BackgroundErrorSource::FlushOnDrop, e);
+            }
+        }
+    }
+}
+
+/// The outcome of [`Session::read_cached`].
+#[cfg(feature = "storage")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheResult {
+    /// The value was resolved from memory. `None` means no value is stored under the key.
+    Hit(Option<Value>),
+    /// Resolving the value requires I/O.
+    Miss,
+}
+
+/// A read made with [`Session::read_speculative`], which can be checked for invalidation with
+/// [`Session::validate_tickets`].
+#[cfg(feature = "storage")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReadTicket {
+    index: usize,
+    key: KeyPath,
+}
+
+#[cfg(feature = "storage")]
+impl ReadTicket {
+    /// The key which was read.
+    pub fn key(&self) -> KeyPath {
+        self.key
+    }
+}
+
+/// A session presents a way of interaction with the trie.
+///
+/// During a session the application is assumed to perform a zero or more reads and writes. When
+/// the session is finished, the application can [commit][`Nomt::commit_and_prove`] the changes
+/// and create a [`Witness`] that can be used to prove the correctness of replaying the same
+/// operations.
+#[cfg(feature = "storage")]
+pub struct Session {
+    store: Store,
     merkle_updater: Option<Updater>, // always `Some` during lifecycle.
     session_cnt: Arc<AtomicUsize>,
     metrics: Metrics,
     rollback_delta: Option<rollback::ReverseDeltaBuilder>,
+    commit_token: Option<CommitToken>,
+    block_number: Option<u64>,
+    aux_writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    /// Whether the session replays a [`WalSegment`], whose auxiliary writes may include reserved
+    /// keys and whose values include the deletions of expired values.
+    replay: bool,
+    record_witness: bool,
+    witness_mode: WitnessMode,
+    deduplicated_value_fetches_base: u64,
+    bulk_writes: Vec<(KeyPath, Option<ValueHandle>)>,
+    /// The expiries of the writes made with [`Session::write_with_ttl`], in milliseconds since the
+    /// unix epoch. Cleared by later writes to the same key.
+    expiries: HashMap<KeyPath, u64>,
+    sequential_readahead: usize,
+    /// The inclusive key ranges declared with [`Session::hint_sequential`].
+    sequential_ranges: Vec<(KeyPath, KeyPath)>,
+    /// The keys read with [`Session::read_speculative`], along with the number of writes made
+    /// with [`Session::write_all`] at the time. Indexed by [`ReadTicket`].
+    speculative_reads: Mutex<Vec<(KeyPath, usize)>>,
+    read_consistency: ReadConsistency,
+    /// See [`SessionParams::time`].
+    time: Option<u64>,
+    /// The keys to prove against the new root, see [`Session::finish_with_proofs`].
+    proof_keys: Vec<KeyPath>,
+    /// See [`Options::default_value`].
+    default_values: Arc<default_values::DefaultValues>,
+}
+
+/// The read amplification over an interval. See [`Nomt::read_amplification`].
+#[cfg(feature = "storage")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadAmplification {
+    /// The number of keys read or committed.
+    pub logical_reads: u64,
+    /// The number of pages read from disk, from both the hash-table and the b-tree.
+    pub physical_page_reads: u64,
+    /// The length of the interval.
+    pub interval: Duration,
+}
+
+#[cfg(feature = "storage")]
+impl ReadAmplification {
+    /// The number of pages read from disk per key, or `None` if no keys were read.
+    pub fn factor(&self) -> Option<f64> {
+        (self.logical_reads > 0)
+            .then(|| self.physical_page_reads as f64 / self.logical_reads as f64)
+    }
+}
+
+/// The result of [`Nomt::commit_with_costs`].
+#[cfg(feature = "storage")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitOutput {
+    /// The new root.
+    pub root: Node,
+    /// An estimate of the resources consumed by the commit.
+    pub costs: CommitCosts,
+}
+
+/// An estimate of the I/O and CPU consumed by a commit, for calibrating the costs charged for
+/// storage operations. See [`Nomt::commit_with_costs`].
+#[cfg(feature = "storage")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitCosts {
+    /// The number of keys written, including deletions.
+    pub writes: u64,
+    /// The number of keys which were only read.
+    pub reads: u64,
+    /// The total size of the values written, in bytes.
+    pub value_bytes: u64,
+    /// The number of merkle pages changed, including new and deleted pages. Every changed page is
+    /// written to the hash-table in full.
+    pub pages_touched: u64,
+    /// The number of changed merkle pages which were not stored yet and occupy a new bucket.
+    pub new_pages: u64,
+    /// The number of changed merkle pages which were deleted, leaving a tombstone behind.
+    pub deleted_pages: u64,
+    /// The number of hash invocations: one per value written and one per changed trie node.
+    pub hashes: u64,
 }
 
+#[cfg(feature = "storage")]
+impl CommitCosts {
+    /// The average number of merkle pages changed per key written, or `None` if nothing was
+    /// written.
+    pub fn pages_per_write(&self) -> Option<f64> {
+        (self.writes > 0).then(|| self.pages_touched as f64 / self.writes as f64)
+    }
+
+    /// The average number of hash invocations per key written, or `None` if nothing was written.
+    pub fn hashes_per_write(&self) -> Option<f64> {
+        (self.writes > 0).then(|| self.hashes as f64 / self.writes as f64)
+    }
+}
+
+/// Statistics about a [`Session`]. See [`Session::stats`].
+#[cfg(feature = "storage")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionStats {
+    /// The number of b-tree page fetches which attached to a fetch of the same page already in
+    /// progress instead of issuing a duplicate one, e.g. a read overtaking a warm-up.
+    pub deduplicated_value_fetches: u64,
+}
+
+#[cfg(feature = "storage")]
 impl Session {
     /// Signal to the backend to warm up the merkle paths and b-tree pages for a key, so they are
     /// ready by the time you commit the session.
@@ -430,17 +2115,479 @@ impl Session {
     /// The purpose of warming up is to move I/O out of the critical path of committing a
     /// session to maximize throughput.
     /// There is no correctness issue with doing too many warm-ups, but there is a cost for I/O.
+    ///
+    /// The b-tree page holding the key's value is fetched in the background. A [`Session::read`]
+    /// of the key issued while that fetch is still in progress waits for it rather than fetching
+    /// the page again. See [`Session::stats`].
     pub fn warm_up(&self, path: KeyPath) {
         // UNWRAP: merkle_updater always `Some` during lifecycle.
         self.merkle_updater.as_ref().unwrap().warm_up(path);
+        self.store.prefetch_value(path);
+    }
+
+    /// Warm up all of the given keys, as with [`Session::warm_up`], without blocking.
+    ///
+    /// This is meant for callers which know the keys to be accessed in advance, e.g. block
+    /// builders knowing the transactions of a block. It should be called right after beginning the
+    /// session, before execution starts. The merkle paths of all keys are loaded by the warm-up
+    /// worker, which keeps as many page reads in flight as the I/O pool admits, and the b-tree
+    /// leaves holding their values are read at once, each leaf only once.
+    pub fn warm_up_all(&self, keys: impl IntoIterator<Item = KeyPath>) {
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        // UNWRAP: merkle_updater always `Some` during lifecycle.
+        let merkle_updater = self.merkle_updater.as_ref().unwrap();
+        for key in &keys {
+            merkle_updater.warm_up(*key);
+        }
+        self.store.prefetch_values(keys);
+    }
+
+    /// Warm up as many of the given keys as possible before the deadline, prioritized by the
+    /// weight given with each key.
+    ///
+    /// The merkle paths of the keys are loaded in order of descending weight, keys of equal weight
+    /// in the given order, until all of them are loaded or the deadline passes. This blocks the
+    /// current thread until then. Keys whose merkle path was loaded are then warmed up as with
+    /// [`Session::warm_up`], which fetches their values in the background. No I/O is issued for
+    /// the remaining keys.
+    ///
+    /// Returns a bit-vector with one bit per key, set if the key was warmed up. Fails only if I/O
+    /// fails.
+    pub fn warm_up_batch(
+        &self,
+        keys: &[(KeyPath, u64)],
+        deadline: Instant,
+    ) -> anyhow::Result<BitVec> {
+        self.check_latest()?;
+        // UNWRAP: merkle_updater always `Some` during lifecycle.
+        let warmed = self
+            .merkle_updater
+            .as_ref()
+            .unwrap()
+            .warm_up_batch(keys, deadline)?;
+        for index in warmed.iter_ones() {
+            self.warm_up(keys[index].0);
+        }
+        Ok(warmed)
+    }
+
+    /// Returns statistics about the session so far.
+    pub fn stats(&self) -> SessionStats {
+        SessionStats {
+            deduplicated_value_fetches: self.store.deduplicated_value_fetches()
+                - self.deduplicated_value_fetches_base,
+        }
     }
 
     /// Synchronously read the value stored under the given key.
     ///
-    /// Returns `None` if the value is not stored under the given key. Fails only if I/O fails.
+    /// Returns `None` if the value is not stored under the given key, or if it was written with
+    /// [`Session::write_with_ttl`] and has expired by the time of the session, see
+    /// [`SessionParams::time`], unless the key has a default value, see
+    /// [`Options::default_value`]. Fails only if I/O fails.
     pub fn read(&self, path: KeyPath) -> anyhow::Result<Option<Value>> {
         let _maybe_guard = self.metrics.record(Metric::ValueFetchTime);
-        self.store.load_value(path)
+        if self.is_expired(path) {
+            return Ok(self.default_values.get(&path).cloned());
+        }
+        let value =
+            self.store
+                .load_value_with(path, self.admission(path), self.read_consistency)?;
+        Ok(self.default_values.apply(&path, value))
+    }
+
+    /// Read the value stored under the given key like [`Session::read`], returning a reader of it
+    /// instead of the value itself.
+    ///
+    /// Large values stored in overflow pages or the value log are read from disk as the reader is
+    /// consumed, so that they are never held in memory in their entirety. See [`ValueReader`],
+    /// which must not be held across commits.
+    pub fn read_streaming(&self, path: KeyPath) -> anyhow::Result<Option<ValueReader>> {
+        let _maybe_guard = self.metrics.record(Metric::ValueFetchTime);
+        let reader = match self.is_expired(path) {
+            true => None,
+            false => self.store.load_value_streaming(path, self.read_consistency),
+        };
+        self.store.check_snapshot()?;
+        let reader = match self.store.is_read_only() {
+            true => reader.map(|reader| reader.checked(self.store.clone())),
+            false => reader,
+        };
+        Ok(reader.or_else(|| {
+            let default = self.default_values.get(&path)?;
+            Some(ValueReader::from_vec(default.clone()))
+        }))
+    }
+
+    /// Read the value stored under the given key like [`Session::read`], recording the steps taken:
+    /// the caches searched, the b-tree leaf and overflow pages read, and the pages of the merkle
+    /// path of the key visited, along with the bytes read from disk and the time taken by each.
+    ///
+    /// This is meant for diagnosing slow reads of specific keys and is slower than a regular read.
+    /// Pages of the merkle path read from disk are not placed in the page cache, and a b-tree leaf
+    /// read from disk is placed in the leaf cache so that it is evicted first.
+    pub fn explain_read(&self, path: KeyPath) -> anyhow::Result<ReadExplanation> {
+        let mut steps = explain::StepRecorder::new();
+        let expired = self.is_expired(path);
+        steps.record(ReadStepKind::Expiry { expired }, 0);
+        let value = match expired {
+            true => None,
+            false => self
+                .store
+                .explain_value(path, self.read_consistency, &mut steps),
+        };
+        // UNWRAP: merkle_updater always `Some` during lifecycle.
+        self.merkle_updater
+            .as_ref()
+            .unwrap()
+            .explain_path(path, &mut steps)?;
+        self.store.check_snapshot()?;
+        Ok(ReadExplanation {
+            value: self.default_values.apply(&path, value),
+            steps: steps.finish(),
+        })
+    }
+
+    /// Read the value stored under the given key without blocking the thread.
+    ///
+    /// This behaves like [`Session::read`], but the b-tree pages holding the value are read
+    /// through the I/O workers, which wake the task once they are available. The future does not
+    /// borrow the session and works with any executor. Values large enough to be stored in the
+    /// value log, see [`Options::value_log_threshold`], are read from it blocking.
+    ///
+    /// The time spent is not recorded in the value fetch time metric.
+    pub fn read_async(
+        &self,
+        path: KeyPath,
+    ) -> impl std::future::Future<Output = anyhow::Result<Option<Value>>> + Send + 'static {
+        let lookup = (!self.is_expired(path)).then(|| {
+            self.store
+                .load_value_async(path, self.admission(path), self.read_consistency)
+        });
+        let default_values = self.default_values.clone();
+        let store = self.store.clone();
+        async move {
+            let value = match lookup {
+                Some(lookup) => lookup.await?,
+                None => None,
+            };
+            store.check_snapshot()?;
+            Ok(default_values.apply(&path, value))
+        }
+    }
+
+    /// Fail if the session reads the last synced state, which the merkle trie doesn't reflect.
+    fn check_latest(&self) -> anyhow::Result<()> {
+        if self.read_consistency == ReadConsistency::LastSynced {
+            anyhow::bail!("the merkle trie reflects the latest state, not the last synced one");
+        }
+        Ok(())
+    }
+
+    /// How to admit the leaf holding the key to the cache, see [`Session::hint_sequential`].
+    fn admission(&self, path: KeyPath) -> beatree::Admission {
+        let scanned = self
+            .sequential_ranges
+            .iter()
+            .any(|(start, end)| (start..=end).contains(&&path));
+        if scanned {
+            beatree::Admission::Cold
+        } else {
+            beatree::Admission::Hot
+        }
+    }
+
+    /// Whether the value of the given key has expired by the time of the session, see
+    /// [`SessionParams::time`].
+    fn is_expired(&self, path: KeyPath) -> bool {
+        self.time
+            .is_some_and(|now| self.store.is_expired(path, now))
+    }
+
+    /// Read the value of the given key as seen by this session, returning a ticket for checking
+    /// later whether the read is still valid.
+    ///
+    /// Unlike [`Session::read`], this reflects the writes already made within this session with
+    /// [`Session::write_all`]: the last of them to the key wins, otherwise the value reflects the
+    /// last commit. This suits optimistic execution of transactions on top of the session, where a
+    /// transaction's reads are validated with [`Session::validate_tickets`] before its writes are
+    /// applied.
+    ///
+    /// Fails only if I/O fails.
+    pub fn read_speculative(&self, path: KeyPath) -> anyhow::Result<(Option<Value>, ReadTicket)> {
+        let value = match self.bulk_writes.iter().rev().find(|(key, _)| *key == path) {
+            Some((_, value)) => value.as_ref().map(|value| value.to_vec()),
+            None => self.read(path)?,
+        };
+        let value = self.default_values.apply(&path, value);
+        let mut speculative_reads = self.speculative_reads.lock();
+        let ticket = ReadTicket {
+            index: speculative_reads.len(),
+            key: path,
+        };
+        speculative_reads.push((path, self.bulk_writes.len()));
+        Ok((value, ticket))
+    }
+
+    /// Returns the tickets of the reads made with [`Session::read_speculative`] which have been
+    /// invalidated, in the order of the reads.
+    ///
+    /// A read is invalidated by any write to its key made with [`Session::write_all`] after the
+    /// read, even if it writes the value which was read.
+    pub fn validate_tickets(&self) -> Vec<ReadTicket> {
+        let mut last_writes = std::collections::HashMap::new();
+        for (index, (key, _)) in self.bulk_writes.iter().enumerate() {
+            last_writes.insert(*key, index);
+        }
+        self.speculative_reads
+            .lock()
+            .iter()
+            .enumerate()
+            .filter(|(_, (key, writes))| last_writes.get(key).is_some_and(|last| last >= writes))
+            .map(|(index, (key, _))| ReadTicket { index, key: *key })
+            .collect()
+    }
+
+    /// Iterate over the keys in the inclusive range `start..=end` and their values, in trie order.
+    ///
+    /// Like [`Session::read`], this reflects the last commit, except for the writes already made
+    /// within this session with [`Session::write_all`], which take precedence. The actuals passed
+    /// to [`Nomt::commit`] are not known to the session and thus not reflected. See
+    /// [`KeyValueIter`] for how commits made while iterating are reflected. Regardless of
+    /// [`SessionParams::read_consistency`], commits which have not been synced yet are reflected.
+    pub fn iter_range(&self, start: KeyPath, end: KeyPath) -> KeyValueIter {
+        let mut overlay = self
+            .bulk_writes
+            .iter()
+            .filter(|(path, _)| (start..=end).contains(path))
+            .map(|(path, value)| (*path, value.as_ref().map(|value| value.to_vec())))
+            .collect::<Vec<_>>();
+        // The sort is stable, so the last write to a key comes last among its duplicates.
+        overlay.sort_by_key(|(path, _)| *path);
+        overlay.reverse();
+        overlay.dedup_by_key(|(path, _)| *path);
+        overlay.reverse();
+        KeyValueIter::new(self.store.clone(), start, end, overlay, self.time)
+    }
+
+    /// Iterate over the keys starting with the given prefix and their values, in trie order. See
+    /// [`Session::iter_range`].
+    ///
+    /// The prefix is given in bits and may be at most 256 bits long.
+    pub fn iter_prefix(&self, prefix: &BitSlice<u8, Msb0>) -> KeyValueIter {
+        let (start, end) = subtree::prefix_range(prefix);
+        self.iter_range(start, end)
+    }
+
+    /// Declare that the keys starting with the given prefix are about to be read in key order,
+    /// e.g. by a scan.
+    ///
+    /// This reads ahead the b-tree leaves holding the values of these keys in the background, up
+    /// to the limit set with [`SessionParams::sequential_readahead`]. For the rest of the session,
+    /// leaves read for these keys are placed in the cache so that they are evicted first, which
+    /// keeps a large scan from displacing the working set of point reads.
+    ///
+    /// The prefix is given in bits and may be at most 256 bits long.
+    pub fn hint_sequential(&mut self, prefix: &BitSlice<u8, Msb0>) {
+        let (start, end) = subtree::prefix_range(prefix);
+        self.store
+            .prefetch_value_range(start, end, self.sequential_readahead);
+        self.sequential_ranges.push((start, end));
+    }
+
+    /// Declare that the given keys are about to be read in no particular order.
+    ///
+    /// This fetches the b-tree leaves holding their values in the background and places them in
+    /// the cache as recently used, even if the keys fall under a prefix given to
+    /// [`Session::hint_sequential`]. Unlike [`Session::warm_up`], the merkle paths of the keys are
+    /// not loaded, so this is cheaper for keys which are only read and not proven.
+    pub fn hint_random(&mut self, keys: &[KeyPath]) {
+        for key in keys {
+            self.store.prefetch_value(*key);
+        }
+    }
+
+    /// Read the value stored under the given key, but only if this can be done without any I/O.
+    ///
+    /// This never blocks on the disk, which makes it suitable for execution engines with strict
+    /// time budgets: on [`CacheResult::Miss`] the caller may schedule the work for later, for
+    /// example after warming up the key, instead of stalling.
+    pub fn read_cached(&self, path: KeyPath) -> CacheResult {
+        if self.is_expired(path) {
+            return CacheResult::Hit(self.default_values.get(&path).cloned());
+        }
+        match self.store.load_value_cached(path, self.read_consistency) {
+            Some(value) => CacheResult::Hit(self.default_values.apply(&path, value)),
+            None => CacheResult::Miss,
+        }
+    }
+
+    /// Check whether values are stored under the given keys.
+    ///
+    /// This consults only the merkle trie and never loads values, which makes it considerably
+    /// cheaper than [`Session::read`] when only existence matters. Lookups for all keys are issued
+    /// concurrently.
+    ///
+    /// Like [`Session::read`], this reflects the state as of the last commit: writes which are
+    /// going to be made within this session are not taken into account.
+    ///
+    /// Returns a bit-vector with one bit per key, set if the key has a value. Fails only if I/O
+    /// fails.
+    pub fn contains_batch(&self, keys: &[KeyPath]) -> anyhow::Result<BitVec> {
+        self.check_latest()?;
+        // UNWRAP: merkle_updater always `Some` during lifecycle.
+        let contained = self.merkle_updater.as_ref().unwrap().contains_batch(keys)?;
+        self.store.check_snapshot()?;
+        Ok(contained)
+    }
+
+    /// Prove the root of the subtree holding all keys which start with the given prefix, e.g.
+    /// the storage of a contract, against the root this session began with.
+    ///
+    /// A single proof stands in for proofs of every key under the prefix: the verifier checks it
+    /// with [`subtree_proof::SubtreeProof::verify`] and learns the root of the subtree, which is
+    /// the [`nomt_core::trie::TERMINATOR`] if no key starts with the prefix and the leaf of the
+    /// key if only one does.
+    ///
+    /// Like [`Session::read`], this reflects the state as of the last commit. Fails if the prefix
+    /// is longer than a key path or if I/O fails.
+    pub fn prove_subtree(
+        &self,
+        prefix: &BitSlice<u8, Msb0>,
+    ) -> anyhow::Result<subtree_proof::SubtreeProof> {
+        self.check_latest()?;
+        // UNWRAP: merkle_updater always `Some` during lifecycle.
+        let proof = self
+            .merkle_updater
+            .as_ref()
+            .unwrap()
+            .prove_subtree(prefix)?;
+        self.store.check_snapshot()?;
+        Ok(proof)
+    }
+
+    /// Attach a token to the commit of this session, such as the hash of the block being
+    /// committed.
+    ///
+    /// The token is persisted in the manifest atomically with the commit and can be queried with
+    /// [`Nomt::last_commit_token`], which allows crash-recovery logic to reliably tell whether the
+    /// commit has been completed.
+    pub fn set_commit_token(&mut self, token: CommitToken) {
+        self.commit_token = Some(token);
+    }
+
+    /// Attach a block number, or any other height such as a timestamp, to the commit of
+    /// this session.
+    ///
+    /// The block number is persisted in the manifest atomically with the commit, along with an
+    /// index mapping it to the sequence number of the sync. It can be queried with
+    /// [`Nomt::last_block_number`] and [`Nomt::seqn_for_block`].
+    pub fn set_block_number(&mut self, block_number: u64) {
+        self.block_number = Some(block_number);
+    }
+
+    /// Commit the session like [`Nomt::commit`] and create proofs of the given keys against the new
+    /// root. Returns the new root along with the proofs, in the same order as the keys.
+    ///
+    /// The proofs are created from the updated trie as part of the commit, which saves beginning
+    /// another session to prove the post-state, e.g. to light clients. A proof shows the value
+    /// hash of its key or the absence of the key, see [`proof::PathProof::verify`]. The merkle
+    /// paths of the keys are warmed up along with those of the actuals.
+    pub fn finish_with_proofs<T: HashAlgorithm>(
+        mut self,
+        nomt: &Nomt<T>,
+        actuals: Vec<(KeyPath, KeyReadWrite)>,
+        keys: Vec<KeyPath>,
+    ) -> anyhow::Result<(Node, Vec<PathProof>)> {
+        // UNWRAP: merkle_updater always `Some` during lifecycle.
+        let merkle_updater = self.merkle_updater.as_ref().unwrap();
+        for key in &keys {
+            merkle_updater.warm_up(*key);
+        }
+        self.proof_keys = keys;
+        match nomt.commit_inner(self, actuals, false)? {
+            (root, None, None, _, proofs) => Ok((root, proofs)),
+            // UNWRAP: witness specified to false
+            _ => unreachable!(),
+        }
+    }
+
+    /// Write a value to the auxiliary column, or delete it if `None`, as part of the commit of
+    /// this session.
+    ///
+    /// The auxiliary column is a small key-value store for node-local records, such as block
+    /// metadata or indexes, which are not part of the merkle trie. Its writes are persisted
+    /// atomically with the commit and can be read with [`Nomt::read_aux`]. They are not undone by
+    /// [`Nomt::rollback`]. The whole column is kept in memory, so it is not meant for large
+    /// amounts of data. If a key is written multiple times, the last write wins. Keys starting with
+    /// `"\0nomt:"` are reserved for records kept by the database itself, such as the expiries of
+    /// [`Session::write_with_ttl`].
+    pub fn write_aux(&mut self, key: impl Into<Vec<u8>>, value: Option<Vec<u8>>) {
+        self.aux_writes.insert(key.into(), value);
+    }
+
+    /// Write the given values, or delete them if `None`, as part of the commit of this session.
+    /// Empty values are written, not deleted, see [`Value`].
+    ///
+    /// The writes are applied in addition to the actuals given to [`Nomt::commit`] and its
+    /// variants. The keys written here must not appear in the actuals, otherwise the commit fails.
+    /// If a key is written multiple times, the last write wins. Keys are warmed up as with
+    /// [`Session::warm_up`].
+    ///
+    /// The given buffers are shared with the commit rather than copied up front: the values are
+    /// hashed from them, and they are kept alive until the commit has been written back.
+    pub fn write_all(&mut self, writes: impl IntoIterator<Item = (KeyPath, Option<ValueHandle>)>) {
+        for (path, value) in writes {
+            self.warm_up(path);
+            self.preserve_prior_value(path);
+            if !self.expiries.is_empty() {
+                self.expiries.remove(&path);
+            }
+            self.bulk_writes.push((path, value));
+        }
+    }
+
+    /// Write the given value as part of the commit of this session, to expire once the given
+    /// time-to-live has elapsed since the time of the session, see [`SessionParams::time`].
+    ///
+    /// This behaves like [`Session::write_all`] otherwise. Once expired, the value is treated as
+    /// absent by the reads and iteration of sessions whose time is past the expiry, and the commit
+    /// of such a session deletes it from the trie. Until then, it remains part of the root, proofs
+    /// and exports. Expiry thus only depends on the times given to the sessions, not on when they
+    /// are committed.
+    ///
+    /// Any later write to the key clears the expiry, unless it is made with this function too.
+    /// Expiries are not undone by [`Nomt::rollback`], but values restored by it never expire.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the session has no time.
+    pub fn write_with_ttl(&mut self, path: KeyPath, value: ValueHandle, ttl: Duration) {
+        let Some(now) = self.time else {
+            panic!("write_with_ttl requires the time of the session, see SessionParams::time");
+        };
+        self.write_all([(path, Some(value))]);
+        self.expiries
+            .insert(path, now.saturating_add(ttl.as_millis() as u64));
+    }
+
+    /// Write the value read from the given reader, until its end, as part of the commit of this
+    /// session. This behaves like [`Session::write_all`] otherwise.
+    ///
+    /// The value is buffered in pages of the page pool instead of a contiguous allocation, and is
+    /// hashed and written to disk from those pages, so that writing multi-megabyte values doesn't
+    /// require a contiguous buffer of their size. The value is still subject to
+    /// [`Options::max_value_size`], which is checked by the commit.
+    ///
+    /// Fails if reading fails, in which case nothing is written.
+    pub fn write_streaming(
+        &mut self,
+        path: KeyPath,
+        reader: impl std::io::Read,
+    ) -> anyhow::Result<()> {
+        let value = streaming::StreamedValue::read_from(self.store.page_pool().clone(), reader)?;
+        self.write_all([(path, Some(ValueHandle::streamed(value)))]);
+        Ok(())
     }
 
     /// Signals that the given key is going to be written to. Relevant only if rollback is enabled.
@@ -470,8 +2617,12 @@ impl Session {
     }
 }
 
+#[cfg(feature = "storage")]
 impl Drop for Session {
     fn drop(&mut self) {
+        if self.read_consistency == ReadConsistency::LastSynced {
+            return;
+        }
         let prev = self
             .session_cnt
             .swap(0, std::sync::atomic::Ordering::Relaxed);
@@ -479,10 +2630,45 @@ impl Drop for Session {
     }
 }
 
+/// Holds the session slot for an operation which must not run alongside a session. The slot is
+/// freed when this is dropped, also when the operation panics.
+#[cfg(feature = "storage")]
+struct SessionSlot<'a>(&'a AtomicUsize);
+
+#[cfg(feature = "storage")]
+impl<'a> SessionSlot<'a> {
+    /// Take the slot. Panics if a session is active.
+    fn take(session_cnt: &'a AtomicUsize, operation: &str) -> Self {
+        let prev = session_cnt.swap(1, std::sync::atomic::Ordering::Relaxed);
+        assert_eq!(prev, 0, "{operation} cannot run while a session is active");
+        SessionSlot(session_cnt)
+    }
+}
+
+#[cfg(feature = "storage")]
+impl Drop for SessionSlot<'_> {
+    fn drop(&mut self) {
+        self.0.store(0, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
 /// A hasher for arbitrary-length values.
 pub trait ValueHasher {
     /// Hash an arbitrary-length value.
     fn hash_value(value: &[u8]) -> [u8; 32];
+
+    /// Hash an arbitrary-length value given as consecutive chunks. This must be equal to the hash
+    /// of their concatenation, see [`ValueHasher::hash_value`].
+    ///
+    /// The default implementation concatenates the chunks. Hash functions which can be fed
+    /// incrementally should override it, so that values written with
+    /// [`Session::write_streaming`] are never gathered into a contiguous buffer.
+    fn hash_value_chunks<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> [u8; 32]
+    where
+        Self: Sized,
+    {
+        Self::hash_value(&chunks.into_iter().flatten().copied().collect::<Vec<_>>())
+    }
 }
 
 /// A hash algorithm that uses Blake3 for both nodes and values.
@@ -498,6 +2684,43 @@ impl ValueHasher for Blake3Hasher {
     fn hash_value(data: &[u8]) -> [u8; 32] {
         blake3::hash(data).into()
     }
+
+    fn hash_value_chunks<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        hasher.finalize().into()
+    }
+}
+
+/// A hash algorithm that uses Keccak-256 for both nodes and values, as the EVM does.
+#[cfg(feature = "keccak")]
+pub struct Keccak256Hasher;
+
+#[cfg(feature = "keccak")]
+impl NodeHasher for Keccak256Hasher {
+    fn hash_node(data: &NodePreimage) -> [u8; 32] {
+        use sha3::Digest as _;
+        sha3::Keccak256::digest(data).into()
+    }
+}
+
+#[cfg(feature = "keccak")]
+impl ValueHasher for Keccak256Hasher {
+    fn hash_value(data: &[u8]) -> [u8; 32] {
+        use sha3::Digest as _;
+        sha3::Keccak256::digest(data).into()
+    }
+
+    fn hash_value_chunks<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> [u8; 32] {
+        use sha3::Digest as _;
+        let mut hasher = sha3::Keccak256::new();
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        hasher.finalize().into()
+    }
 }
 
 /// A marker trait for hash functions usable with NOMT. The type must support both hashing nodes as
@@ -506,6 +2729,190 @@ pub trait HashAlgorithm: ValueHasher + NodeHasher {}
 
 impl<T: ValueHasher + NodeHasher> HashAlgorithm for T {}
 
+/// Identifies the hash algorithm by hashing a fixed node whose preimage embeds the hash of a fixed
+/// value. Recorded in the manifest so that a database is not reopened with another algorithm.
+#[cfg(feature = "storage")]
+fn hasher_fingerprint<T: HashAlgorithm>() -> [u8; 32] {
+    let mut preimage = [0; 64];
+    preimage[..32].copy_from_slice(&T::hash_value(b"nomt hasher fingerprint"));
+    T::hash_node(&preimage)
+}
+
+#[cfg(feature = "storage")]
+fn check_actuals_sorted(actuals: &[(KeyPath, KeyReadWrite)]) {
+    if cfg!(debug_assertions) {
+        // Check that the actuals are sorted by key path.
+        for i in 1..actuals.len() {
+            assert!(
+                actuals[i].0 > actuals[i - 1].0,
+                "actuals are not sorted at index {}",
+                i
+            );
+        }
+    }
+}
+
+/// Take the writes made with [`Session::write_all`], sorted by key path with only the last write
+/// to each key retained. Fails if any of them is also part of the actuals.
+#[cfg(feature = "storage")]
+fn take_bulk_writes(
+    session: &mut Session,
+    actuals: &[(KeyPath, KeyReadWrite)],
+) -> anyhow::Result<Vec<(KeyPath, Option<ValueHandle>)>> {
+    let mut bulk_writes = mem::take(&mut session.bulk_writes);
+    // The sort is stable, so the last write to a key comes last among its duplicates.
+    bulk_writes.sort_by_key(|(path, _)| *path);
+    bulk_writes.reverse();
+    bulk_writes.dedup_by_key(|(path, _)| *path);
+    bulk_writes.reverse();
+
+    for (path, _) in &bulk_writes {
+        if actuals.binary_search_by_key(path, |(p, _)| *p).is_ok() {
+            anyhow::bail!("key path written with write_all is also part of the actuals");
+        }
+    }
+    Ok(bulk_writes)
+}
+
+/// Take the expiries set with [`Session::write_with_ttl`], along with the changes to the expiries
+/// of the other written keys, which lose them.
+///
+/// The keys whose values have expired by the time of the session and which are not written by
+/// this commit are deleted: the reads among the actuals are turned into deletions, with the stored
+/// value as the one read, and the other keys are added to the bulk writes.
+#[cfg(feature = "storage")]
+fn take_expiries(
+    session: &mut Session,
+    actuals: &mut [(KeyPath, KeyReadWrite)],
+    bulk_writes: &mut Vec<(KeyPath, Option<ValueHandle>)>,
+    store: &Store,
+) -> anyhow::Result<Vec<(KeyPath, Option<u64>)>> {
+    let mut session_expiries = mem::take(&mut session.expiries);
+    let written = actuals
+        .iter()
+        .filter(|(_, read_write)| read_write.is_write())
+        .map(|(path, _)| path)
+        .chain(bulk_writes.iter().map(|(path, _)| path));
+    let mut expiries = Vec::new();
+    for path in written {
+        let expiry = session_expiries.remove(path);
+        if expiry.is_some() || store.expiry(*path).is_some() {
+            expiries.push((*path, expiry));
+        }
+    }
+
+    let Some(now) = session.time else {
+        return Ok(expiries);
+    };
+    let num_bulk_writes = bulk_writes.len();
+    for path in store.expired_keys(now) {
+        let in_bulk_writes = bulk_writes[..num_bulk_writes]
+            .binary_search_by_key(&path, |(p, _)| *p)
+            .is_ok();
+        if in_bulk_writes {
+            continue;
+        }
+        match actuals.binary_search_by_key(&path, |(p, _)| *p) {
+            Ok(i) => {
+                let (_, read_write) = &mut actuals[i];
+                if read_write.is_write() {
+                    continue;
+                }
+                *read_write = KeyReadWrite::ReadThenWrite(store.load_value(path)?, None);
+            }
+            Err(_) => bulk_writes.push((path, None)),
+        }
+        expiries.push((path, None));
+    }
+    if bulk_writes.len() != num_bulk_writes {
+        bulk_writes.sort_by_key(|(path, _)| *path);
+    }
+    Ok(expiries)
+}
+
+/// Fails with [`ValueTooLarge`] if any written value exceeds the maximum size.
+#[cfg(feature = "storage")]
+fn check_value_sizes(
+    actuals: &[(KeyPath, KeyReadWrite)],
+    bulk_writes: &[(KeyPath, Option<ValueHandle>)],
+    max: usize,
+) -> anyhow::Result<()> {
+    let written = actuals
+        .iter()
+        .filter_map(|(_, read_write)| match read_write {
+            KeyReadWrite::Read(_) => None,
+            KeyReadWrite::Write(value) | KeyReadWrite::ReadThenWrite(_, value) => {
+                value.as_ref().map(Vec::len)
+            }
+        })
+        .chain(
+            bulk_writes
+                .iter()
+                .filter_map(|(_, value)| value.as_ref().map(ValueHandle::len)),
+        );
+    for len in written {
+        if len > max {
+            return Err(ValueTooLarge { max, got: len }.into());
+        }
+    }
+    Ok(())
+}
+
+/// Panics if two merkle updates of the same actuals, each given with the page cache it was
+/// performed against, differ in the resulting root, in the nodes they change or in the contents of
+/// the changed nodes.
+///
+/// Nodes an update leaves untouched are not compared: pages created by an update are not zeroed,
+/// so the nodes below their leaves and terminators hold whatever the page pool handed out.
+#[cfg(feature = "storage")]
+fn check_merkle_audit(
+    (live_cache, live): (&PageCache, &merkle::Output),
+    (audit_cache, audit): (&PageCache, &merkle::Output),
+) {
+    assert_eq!(
+        live.root, audit.root,
+        "merkle update audit: root differs across worker counts"
+    );
+
+    let live_nodes = changed_nodes(live_cache, live);
+    let audit_nodes = changed_nodes(audit_cache, audit);
+    for key in live_nodes.keys().chain(audit_nodes.keys()) {
+        assert_eq!(
+            live_nodes.get(key),
+            audit_nodes.get(key),
+            "merkle update audit: page {:?} differs at node {} across worker counts",
+            key.0,
+            key.1
+        );
+    }
+}
+
+/// The nodes changed by a merkle update, keyed by page and node index, as found in the page cache
+/// the update was performed against. All nodes of a cleared page are terminators.
+#[cfg(feature = "storage")]
+fn changed_nodes(
+    page_cache: &PageCache,
+    output: &merkle::Output,
+) -> std::collections::HashMap<(PageId, usize), Node> {
+    let read_pass = page_cache.new_read_pass();
+    let mut nodes = std::collections::HashMap::new();
+    for (page_id, page_diff) in output.page_diffs.iter() {
+        let page = page_cache.get(page_id.clone());
+        for i in 0..page_cache::NODES_PER_PAGE {
+            let node = if page_diff.cleared() {
+                TERMINATOR
+            } else if page_diff.changed(i) {
+                page.as_ref().map_or(TERMINATOR, |p| p.node(&read_pass, i))
+            } else {
+                continue;
+            };
+            nodes.insert((page_id.clone(), i), node);
+        }
+    }
+    nodes
+}
+
+#[cfg(feature = "storage")]
 fn compute_root_node<H: NodeHasher>(page_cache: &PageCache) -> Node {
     let Some(root_page) = page_cache.get(ROOT_PAGE_ID) else {
         return TERMINATOR;
@@ -534,7 +2941,7 @@ fn compute_root_node<H: NodeHasher>(page_cache: &PageCache) -> Node {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "storage"))]
 mod tests {
 
     #[test]
@@ -3,25 +3,31 @@
 //! This splits the work of warming-up and performing the trie update across worker threads.
 
 use anyhow::Context;
+use bitvec::prelude::*;
 use crossbeam::channel::{self, Receiver, Sender};
 use parking_lot::Mutex;
 
 use nomt_core::{
+    page::DEPTH,
     page_id::PageId,
+    proof::{PathProof, PathProofTerminal},
+    subtree_proof::{SubtreeProof, SubtreeProofTerminal},
     trie::{self, KeyPath, Node, NodeHasher, ValueHash},
     trie_pos::TriePosition,
 };
-use seek::Seek;
+use seek::{Completion, Seek, Seeker};
 
-use std::{collections::HashMap, sync::Arc};
+use std::{cmp::Reverse, collections::HashMap, sync::Arc, time::Instant};
 
 use crate::{
-    io::PagePool,
-    page_cache::{PageCache, ShardIndex},
+    explain::{ReadStepKind, StepRecorder},
+    io::{FatPage, PagePool, PAGE_SIZE},
+    page_cache::{Page, PageCache, ShardIndex, NODES_PER_PAGE},
     page_diff::PageDiff,
-    rw_pass_cell::WritePassEnvelope,
+    rw_pass_cell::{ReadPass, RegionContains, WritePassEnvelope},
     store::Store,
-    Witness, WitnessedOperations, WitnessedPath, WitnessedRead, WitnessedWrite,
+    threads, ThreadConfig, Witness, WitnessedOperations, WitnessedPage, WitnessedPath,
+    WitnessedRead, WitnessedWrite,
 };
 use threadpool::ThreadPool;
 
@@ -32,6 +38,19 @@ mod worker;
 /// Page diffs produced by update workers.
 pub struct PageDiffs(Vec<Vec<(PageId, PageDiff)>>);
 
+impl PageDiffs {
+    /// Iterate over all changed pages and their diffs.
+    pub fn iter(&self) -> impl Iterator<Item = &(PageId, PageDiff)> {
+        self.0.iter().flatten()
+    }
+}
+
+impl FromIterator<(PageId, PageDiff)> for PageDiffs {
+    fn from_iter<I: IntoIterator<Item = (PageId, PageDiff)>>(iter: I) -> Self {
+        PageDiffs(vec![iter.into_iter().collect()])
+    }
+}
+
 impl IntoIterator for PageDiffs {
     type Item = (PageId, PageDiff);
     type IntoIter = std::iter::Flatten<<Vec<Vec<Self::Item>> as IntoIterator>::IntoIter>;
@@ -77,6 +96,39 @@ impl KeyReadWrite {
     }
 }
 
+/// The pages of the trie accessed by a commit, along with their contents when first accessed.
+///
+/// Pages are accessed by the seekers before they are modified by the page walkers, so the recorded
+/// contents are those prior to the commit. Shared by the warm-up and update workers of a session.
+#[derive(Clone, Default)]
+pub struct PageAccesses(Arc<Mutex<HashMap<PageId, Vec<Node>>>>);
+
+impl PageAccesses {
+    /// Note an access to the page, recording its contents unless it was accessed before.
+    fn record(
+        &self,
+        read_pass: &ReadPass<impl RegionContains<ShardIndex>>,
+        page_id: &PageId,
+        page: &Page,
+    ) {
+        self.0.lock().entry(page_id.clone()).or_insert_with(|| {
+            (0..NODES_PER_PAGE)
+                .map(|index| page.node(read_pass, index))
+                .collect()
+        });
+    }
+
+    /// Take all pages accessed so far, sorted by page ID.
+    fn take(&self) -> Vec<WitnessedPage> {
+        let mut pages = std::mem::take(&mut *self.0.lock())
+            .into_iter()
+            .map(|(page_id, nodes)| WitnessedPage { page_id, nodes })
+            .collect::<Vec<_>>();
+        pages.sort_unstable_by(|a, b| a.page_id.cmp(&b.page_id));
+        pages
+    }
+}
+
 /// The update worker pool.
 pub struct UpdatePool {
     worker_tp: ThreadPool,
@@ -84,19 +136,35 @@ pub struct UpdatePool {
 }
 
 impl UpdatePool {
-    /// Create a new `UpdatePool`.
+    /// Create a new `UpdatePool`, applying the merkle settings of the thread config to its
+    /// workers.
     ///
     /// # Panics
     ///
     /// Panics if `num_workers` is zero.
-    pub fn new(num_workers: usize, do_warm_up: bool) -> Self {
-        UpdatePool {
-            worker_tp: threadpool::Builder::new()
-                .num_threads(num_workers)
-                .thread_name("nomt-commit".to_string())
-                .build(),
+    pub fn new(
+        num_workers: usize,
+        do_warm_up: bool,
+        threads: &ThreadConfig,
+    ) -> anyhow::Result<Self> {
+        Ok(UpdatePool {
+            worker_tp: threads::pool(
+                threads::thread_name(threads, "nomt-commit"),
+                num_workers,
+                &threads.merkle,
+            )?,
             do_warm_up,
-        }
+        })
+    }
+
+    /// Change the number of workers. No update may be in progress.
+    pub fn resize(&self, num_workers: usize, threads: &ThreadConfig) -> anyhow::Result<()> {
+        threads::resize_pool(
+            &self.worker_tp,
+            &threads::thread_name(threads, "nomt-commit"),
+            num_workers,
+            &threads.merkle,
+        )
     }
 
     /// Create a `Updater` that uses the underlying pool.
@@ -106,20 +174,28 @@ impl UpdatePool {
     /// The Updater expects to have exclusive access to the page cache, so if there
     /// are outstanding read passes, write passes, or threads waiting on write passes,
     /// deadlocks are practically guaranteed at some point during the lifecycle of the Updater.
+    ///
+    /// Keys are warmed up in the background only if `warm_up` is set and the pool was created with
+    /// warm-ups enabled. The pages accessed by the warm-ups and the update are recorded in the
+    /// witness if `witness_pages` is set.
     pub fn begin(
         &self,
         page_cache: PageCache,
         page_pool: PagePool,
         store: Store,
         root: Node,
+        warm_up: bool,
+        witness_pages: bool,
     ) -> Updater {
+        let page_accesses = witness_pages.then(PageAccesses::default);
         let params = worker::WarmUpParams {
             page_cache: page_cache.clone(),
             store: store.clone(),
             root,
+            page_accesses: page_accesses.clone(),
         };
 
-        let warm_up = if self.do_warm_up {
+        let warm_up = if self.do_warm_up && warm_up {
             Some(spawn_warm_up(&self.worker_tp, params))
         } else {
             None
@@ -132,10 +208,15 @@ impl UpdatePool {
             root,
             store,
             page_pool,
+            witness_filter: None,
+            page_accesses,
         }
     }
 }
 
+/// A predicate selecting the keys whose operations are witnessed.
+pub type WitnessFilter = Arc<dyn Fn(&KeyPath) -> bool + Send + Sync>;
+
 /// Parallel commit handler.
 ///
 /// The expected usage is to call `warm_up` repeatedly and conclude with `commit`.
@@ -146,9 +227,16 @@ pub struct Updater {
     root: Node,
     store: Store,
     page_pool: PagePool,
+    witness_filter: Option<WitnessFilter>,
+    page_accesses: Option<PageAccesses>,
 }
 
 impl Updater {
+    /// Only witness operations on keys matching the given predicate.
+    pub fn set_witness_filter(&mut self, witness_filter: Option<WitnessFilter>) {
+        self.witness_filter = witness_filter;
+    }
+
     /// Warm up the given key-path by pre-fetching the relevant pages.
     pub fn warm_up(&self, key_path: KeyPath) {
         if let Some(ref warm_up) = self.warm_up {
@@ -156,6 +244,216 @@ impl Updater {
         }
     }
 
+    /// Check whether each of the given keys has a value in the trie, without loading any values.
+    ///
+    /// Existence is determined by seeking the terminal node of each key and comparing its key-path
+    /// to the key. Page fetches for all keys are multiplexed over the I/O pool.
+    ///
+    /// The returned bit-vector has one bit per key, in the same order as `keys`.
+    pub fn contains_batch(&self, keys: &[KeyPath]) -> anyhow::Result<BitVec> {
+        let read_pass = self.page_cache.new_read_pass();
+        let mut seeker = Seeker::new(
+            self.root,
+            self.page_cache.clone(),
+            self.store.page_loader(),
+            /* record_siblings */ false,
+        );
+
+        for key in keys {
+            seeker.push(*key);
+        }
+
+        // Seeks are completed in the order they were pushed.
+        let mut contained = BitVec::repeat(false, keys.len());
+        let mut completed = 0;
+        while completed < keys.len() {
+            if let Some(Completion::Seek(result)) = seeker.take_completion() {
+                let found = result
+                    .terminal
+                    .is_some_and(|leaf| leaf.key_path == keys[completed]);
+                contained.set(completed, found);
+                completed += 1;
+                continue;
+            }
+
+            seeker.submit_all(&read_pass)?;
+            if seeker.has_live_requests() {
+                seeker.recv_page(&read_pass)?;
+            }
+        }
+
+        Ok(contained)
+    }
+
+    /// Create a proof of the root of the subtree under the given prefix. See [`SubtreeProof`].
+    pub fn prove_subtree(&self, prefix: &BitSlice<u8, Msb0>) -> anyhow::Result<SubtreeProof> {
+        anyhow::ensure!(prefix.len() <= 256, "prefix longer than a key path");
+        let depth = prefix.len();
+        if depth == 0 {
+            return Ok(SubtreeProof {
+                terminal: SubtreeProofTerminal::Node(self.root),
+                siblings: Vec::new(),
+            });
+        }
+
+        // Seek the path of the prefix, and the path through the sibling of the node at the
+        // position of the prefix. If the first path goes past the prefix, the node is internal and
+        // the second path goes down to its depth at least, recording the node as a sibling.
+        let mut key = KeyPath::default();
+        key.view_bits_mut::<Msb0>()[..depth].copy_from_bitslice(prefix);
+        let mut sibling_key = key;
+        sibling_key
+            .view_bits_mut::<Msb0>()
+            .set(depth - 1, !prefix[depth - 1]);
+        let mut proofs = prove_keys(
+            self.root,
+            self.page_cache.clone(),
+            &self.store,
+            &[key, sibling_key],
+        )?;
+        // UNWRAP: one proof is returned per key.
+        let sibling_proof = proofs.pop().unwrap();
+        let mut proof = proofs.pop().unwrap();
+
+        if proof.siblings.len() <= depth {
+            let terminal = match proof.terminal {
+                PathProofTerminal::Leaf(leaf) => SubtreeProofTerminal::Leaf(leaf),
+                PathProofTerminal::Terminator(_) => SubtreeProofTerminal::Terminator,
+            };
+            return Ok(SubtreeProof {
+                terminal,
+                siblings: proof.siblings,
+            });
+        }
+
+        let node = *sibling_proof
+            .siblings
+            .get(depth - 1)
+            .context("path through the sibling of an internal node ends above it")?;
+        proof.siblings.truncate(depth);
+        Ok(SubtreeProof {
+            terminal: SubtreeProofTerminal::Node(node),
+            siblings: proof.siblings,
+        })
+    }
+
+    /// Load the merkle paths of as many of the given keys as possible before the deadline, in
+    /// order of descending weight. Keys of equal weight are loaded in the given order.
+    ///
+    /// The pages are placed in the page cache. The returned bit-vector has one bit per key, in the
+    /// same order as `keys`, set if the merkle path of the key was loaded in full.
+    pub fn warm_up_batch(
+        &self,
+        keys: &[(KeyPath, u64)],
+        deadline: Instant,
+    ) -> anyhow::Result<BitVec> {
+        let read_pass = self.page_cache.new_read_pass();
+        let mut seeker = Seeker::new(
+            self.root,
+            self.page_cache.clone(),
+            self.store.page_loader(),
+            /* record_siblings */ false,
+        );
+
+        // The sort is stable, so that ties keep their order.
+        let mut order = (0..keys.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| Reverse(keys[i].1));
+        for &i in &order {
+            seeker.push(keys[i].0);
+        }
+
+        // Seeks are completed in the order they were pushed.
+        let mut warmed = BitVec::repeat(false, keys.len());
+        let mut completed = 0;
+        while completed < keys.len() {
+            if let Some(Completion::Seek(_)) = seeker.take_completion() {
+                warmed.set(order[completed], true);
+                completed += 1;
+                continue;
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+
+            seeker.submit_all(&read_pass)?;
+            if seeker.has_live_requests() && !seeker.recv_page_before(&read_pass, deadline)? {
+                break;
+            }
+        }
+
+        Ok(warmed)
+    }
+
+    /// Walk the merkle path of the given key down to its terminal node, recording a step for
+    /// every page visited.
+    ///
+    /// Pages missing from the page cache are read from the store without being added to the cache.
+    /// The number of buckets probed is derived from the hash-table statistics, so it includes the
+    /// probes of page loads running concurrently.
+    pub fn explain_path(&self, key_path: KeyPath, steps: &mut StepRecorder) -> anyhow::Result<()> {
+        enum Source {
+            Cached(Page),
+            Loaded(Option<FatPage>),
+        }
+
+        let read_pass = self.page_cache.new_read_pass();
+        let mut node = self.root;
+        let mut position = TriePosition::new();
+        let mut page: Option<(PageId, Source)> = None;
+        for bit in key_path.view_bits::<Msb0>().iter().by_vals() {
+            if !trie::is_internal(&node) {
+                break;
+            }
+            position.down(bit);
+            // UNWRAP: below the root, every position lies in a page.
+            let page_id = position.page_id().unwrap();
+            if page.as_ref().is_none_or(|(id, _)| *id != page_id) {
+                let depth = (position.depth() as usize - 1) / DEPTH;
+                let source = match self.page_cache.get(page_id.clone()) {
+                    Some(cached) => {
+                        steps.record(
+                            ReadStepKind::TriePage {
+                                depth,
+                                cached: true,
+                                probed_buckets: 0,
+                            },
+                            0,
+                        );
+                        Source::Cached(cached)
+                    }
+                    None => {
+                        let probed_before = self.store.hash_table_stats().probed_buckets;
+                        let loaded = self.store.load_page(page_id.clone())?;
+                        let probed_buckets =
+                            self.store.hash_table_stats().probed_buckets - probed_before;
+                        steps.record(
+                            ReadStepKind::TriePage {
+                                depth,
+                                cached: false,
+                                probed_buckets,
+                            },
+                            probed_buckets * PAGE_SIZE as u64,
+                        );
+                        Source::Loaded(loaded.map(|(page, _)| page))
+                    }
+                };
+                page = Some((page_id, source));
+            }
+
+            // UNWRAP: the page was just set.
+            node = match &page.as_ref().unwrap().1 {
+                Source::Cached(cached) => cached.node(&read_pass, position.node_index()),
+                Source::Loaded(Some(loaded)) => {
+                    let mut node = Node::default();
+                    node.copy_from_slice(&loaded[position.node_index() * 32..][..32]);
+                    node
+                }
+                Source::Loaded(None) => trie::TERMINATOR,
+            };
+        }
+        Ok(())
+    }
+
     /// Update the trie with the given key-value read/write operations.
     /// Key-paths should be in sorted order
     /// and should appear at most once within the vector. Witness specifies whether or not
@@ -170,6 +468,8 @@ impl Updater {
         }
         let shared = Arc::new(UpdateShared {
             witness,
+            witness_filter: self.witness_filter.clone(),
+            page_accesses: self.page_accesses.clone().filter(|_| witness),
             read_write,
             root_page_pending: Mutex::new(Vec::with_capacity(64)),
         });
@@ -217,6 +517,51 @@ impl Updater {
     }
 }
 
+/// Create proofs of the given keys against the given root, in the same order as the keys.
+///
+/// The keys are sought in the page cache, loading missing pages from the store, so the proofs
+/// reflect the trie as updated in the page cache, even before the update is synced.
+pub fn prove_keys(
+    root: Node,
+    page_cache: PageCache,
+    store: &Store,
+    keys: &[KeyPath],
+) -> anyhow::Result<Vec<PathProof>> {
+    let read_pass = page_cache.new_read_pass();
+    let mut seeker = Seeker::new(
+        root,
+        page_cache,
+        store.page_loader(),
+        /* record_siblings */ true,
+    );
+
+    for key in keys {
+        seeker.push(*key);
+    }
+
+    // Seeks are completed in the order they were pushed.
+    let mut proofs = Vec::with_capacity(keys.len());
+    while proofs.len() < keys.len() {
+        if let Some(Completion::Seek(result)) = seeker.take_completion() {
+            proofs.push(PathProof {
+                siblings: result.siblings,
+                terminal: match result.terminal {
+                    Some(leaf_data) => PathProofTerminal::Leaf(leaf_data),
+                    None => PathProofTerminal::Terminator(result.position),
+                },
+            });
+            continue;
+        }
+
+        seeker.submit_all(&read_pass)?;
+        if seeker.has_live_requests() {
+            seeker.recv_page(&read_pass)?;
+        }
+    }
+
+    Ok(proofs)
+}
+
 /// A handle for waiting on the results of a commit operation.
 pub struct UpdateHandle {
     shared: Arc<UpdateShared>,
@@ -226,11 +571,15 @@ pub struct UpdateHandle {
 
 impl UpdateHandle {
     /// Wait on the results of the commit operation.
-    pub fn join(self) -> Output {
+    ///
+    /// Fails if a worker failed, e.g. to read a page, once all workers are done.
+    pub fn join(self) -> anyhow::Result<Output> {
         let mut new_root = None;
 
         let mut maybe_witness = self.shared.witness.then_some(Witness {
             path_proofs: Vec::new(),
+            multi_proof: None,
+            pages: Vec::new(),
         });
 
         let mut maybe_witnessed_ops = self.shared.witness.then_some(WitnessedOperations {
@@ -240,15 +589,20 @@ impl UpdateHandle {
 
         let mut page_diffs = Vec::new();
 
-        let mut path_proof_offset = 0;
         let mut witnessed_start = 0;
 
         let mut received_outputs = 0;
+        let mut error = None;
         for output in self.worker_rx.into_iter() {
-            // TODO: handle error better.
-            let output = output.unwrap();
-
             received_outputs += 1;
+            let output = match output {
+                Ok(output) => output,
+                Err(e) => {
+                    error.get_or_insert(e);
+                    continue;
+                }
+            };
+
             if let Some(root) = output.root {
                 assert!(new_root.is_none());
                 new_root = Some(root);
@@ -266,14 +620,25 @@ impl UpdateHandle {
                 let witness = maybe_witness.as_mut().unwrap();
                 let witnessed_ops = maybe_witnessed_ops.as_mut().unwrap();
 
-                let path_proof_count = witnessed_paths.len();
                 witness.path_proofs.reserve(witnessed_paths.len());
-                for (path_index, (path, leaf_data, batch_size)) in
-                    witnessed_paths.into_iter().enumerate()
-                {
-                    witness.path_proofs.push(path);
+                for (path, leaf_data, batch_size) in witnessed_paths {
                     let witnessed_end = witnessed_start + batch_size;
-                    for (k, v) in &self.shared.read_write[witnessed_start..witnessed_end] {
+                    let ops = &self.shared.read_write[witnessed_start..witnessed_end];
+                    witnessed_start = witnessed_end;
+
+                    let witnessed = |k: &KeyPath| {
+                        self.shared
+                            .witness_filter
+                            .as_ref()
+                            .is_none_or(|filter| filter(k))
+                    };
+                    // Paths which only serve filtered out keys are left out altogether.
+                    if !ops.iter().any(|(k, _)| witnessed(k)) {
+                        continue;
+                    }
+                    let path_index = witness.path_proofs.len();
+                    witness.path_proofs.push(path);
+                    for (k, v) in ops.iter().filter(|(k, _)| witnessed(k)) {
                         if v.is_read() {
                             let value_hash = leaf_data.as_ref().and_then(|leaf_data| {
                                 if &leaf_data.key_path == k {
@@ -286,34 +651,40 @@ impl UpdateHandle {
                             witnessed_ops.reads.push(WitnessedRead {
                                 key: *k,
                                 value: value_hash,
-                                path_index: path_index + path_proof_offset,
+                                path_index,
                             });
                         }
                         if let Some(written) = v.written_value() {
                             witnessed_ops.writes.push(WitnessedWrite {
                                 key: *k,
                                 value: written,
-                                path_index: path_index + path_proof_offset,
+                                path_index,
                             });
                         }
                     }
-                    witnessed_start = witnessed_end;
                 }
-
-                path_proof_offset += path_proof_count;
             }
         }
 
         // TODO: handle error when a worker dies unexpectedly.
         assert_eq!(self.num_workers, received_outputs);
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        if let (Some(witness), Some(page_accesses)) =
+            (maybe_witness.as_mut(), self.shared.page_accesses.as_ref())
+        {
+            witness.pages = page_accesses.take();
+        }
 
         // UNWRAP: one thread always produces the root.
-        Output {
+        Ok(Output {
             root: new_root.unwrap(),
             page_diffs: PageDiffs(page_diffs),
             witness: maybe_witness,
             witnessed_operations: maybe_witnessed_ops,
-        }
+        })
     }
 }
 
@@ -369,6 +740,10 @@ struct UpdateShared {
     // nodes needing to be written to pages above a shard.
     root_page_pending: Mutex<Vec<(TriePosition, RootPagePending)>>,
     witness: bool,
+    /// Operations on keys not matching the predicate are left out of the witness.
+    witness_filter: Option<WitnessFilter>,
+    /// Records the pages accessed by the update, if they are witnessed.
+    page_accesses: Option<PageAccesses>,
 }
 
 impl UpdateShared {
@@ -51,6 +51,7 @@ use nomt_core::{
     update::WriteNode,
 };
 
+use super::PageAccesses;
 use crate::{
     io::PagePool,
     page_cache::{Page, PageCache, PageCacheShard, ShardIndex},
@@ -130,6 +131,9 @@ pub struct PageWalker<H> {
     sibling_stack: Vec<(Node, usize)>,
     prev_node: Option<Node>, // the node at `self.position` which was replaced in a previous call
 
+    // records the pages read outside of the seeks, if any.
+    page_accesses: Option<PageAccesses>,
+
     _marker: std::marker::PhantomData<H>,
 }
 
@@ -157,10 +161,17 @@ impl<H: NodeHasher> PageWalker<H> {
             stack: Vec::new(),
             sibling_stack: Vec::new(),
             prev_node: None,
+            page_accesses: None,
             _marker: std::marker::PhantomData,
         }
     }
 
+    /// Record the pages read by the walker which were not necessarily accessed by a seek, i.e.
+    /// those holding the leaf children of sibling leaves.
+    pub fn record_page_accesses(&mut self, page_accesses: Option<PageAccesses>) {
+        self.page_accesses = page_accesses;
+    }
+
     /// Advance to a given trie position and replace the terminal node there with a trie
     /// based on the provided key-value pairs.
     ///
@@ -375,6 +386,8 @@ impl<H: NodeHasher> PageWalker<H> {
         if self.position.depth_in_page() == 1 {
             // UNWRAP: we never move up beyond the root / parent page.
             let stack_item = self.stack.pop().unwrap();
+            // The page is written out along with its diff, so it must stay cached until then.
+            stack_item.page.mark_dirty();
             self.diffs.push((stack_item.page_id, stack_item.diff));
         }
         self.position.up(1);
@@ -646,13 +659,16 @@ impl<H: NodeHasher> PageWalker<H> {
             .map(|stack_item| (&stack_item.page_id, &stack_item.page))
             .or(self.parent_page.as_ref().map(|(ref id, ref p)| (id, p)));
 
-        let (page, _, children) = crate::page_cache::locate_leaf_data::<NeedsPage>(
+        let (page, page_id, children) = crate::page_cache::locate_leaf_data::<NeedsPage>(
             &self.position,
             cur_page,
             |page_id| {
                 get_page(&self.page_source, page_id.clone()).ok_or_else(move || NeedsPage(page_id))
             },
         )?;
+        if let Some(ref page_accesses) = self.page_accesses {
+            page_accesses.record(read_pass, &page_id, &page);
+        }
 
         Ok(trie::LeafData {
             key_path: page.node(&read_pass, children.left()),
@@ -746,6 +762,7 @@ impl<H: NodeHasher> PageWalker<H> {
         self.position = position;
         let Some(page_id) = new_page_id else {
             for stack_item in self.stack.drain(..) {
+                stack_item.page.mark_dirty();
                 self.diffs.push((stack_item.page_id, stack_item.diff));
             }
             return;
@@ -1,5 +1,6 @@
 //! Multiplexer for page requests.
 
+use super::PageAccesses;
 use crate::{
     io::page_pool::FatPage,
     page_cache::{Page, PageCache, ShardIndex},
@@ -14,12 +15,16 @@ use nomt_core::{
     trie_pos::{ChildNodeIndices, TriePosition},
 };
 
-use std::collections::{
-    hash_map::{Entry, HashMap},
-    VecDeque,
+use std::{
+    collections::{
+        hash_map::{Entry, HashMap},
+        VecDeque,
+    },
+    time::Instant,
 };
 
 use bitvec::prelude::*;
+use crossbeam::channel::Select;
 use slab::Slab;
 
 const MAX_INFLIGHT: usize = 1024;
@@ -185,6 +190,7 @@ pub struct Seeker {
     idle_page_loads: VecDeque<usize>,
     record_siblings: bool,
     single_page_request: Option<SinglePageRequestState>,
+    page_accesses: Option<PageAccesses>,
 }
 
 impl Seeker {
@@ -207,9 +213,15 @@ impl Seeker {
             idle_page_loads: VecDeque::new(),
             record_siblings,
             single_page_request: None,
+            page_accesses: None,
         }
     }
 
+    /// Record every page the seeker accesses, along with its contents at the time.
+    pub fn record_page_accesses(&mut self, page_accesses: Option<PageAccesses>) {
+        self.page_accesses = page_accesses;
+    }
+
     pub fn is_empty(&self) -> bool {
         self.requests.is_empty() && self.single_page_request.is_none()
     }
@@ -288,6 +300,23 @@ impl Seeker {
         Ok(())
     }
 
+    /// Block on processing the next I/O, but not past the deadline.
+    ///
+    /// Returns `false` if the deadline passed before the next I/O completed.
+    pub fn recv_page_before(
+        &mut self,
+        read_pass: &ReadPass<ShardIndex>,
+        deadline: Instant,
+    ) -> anyhow::Result<bool> {
+        let mut select = Select::new();
+        select.recv(self.page_loader.io_handle().receiver());
+        if select.ready_deadline(deadline).is_err() {
+            return Ok(false);
+        }
+        self.try_recv_page(read_pass)?;
+        Ok(true)
+    }
+
     /// Push a request for key path.
     pub fn push(&mut self, key: KeyPath) {
         let request_index = self.processed + self.requests.len();
@@ -398,6 +427,9 @@ impl Seeker {
             let page_id: PageId = request.next_page_id();
 
             if let Some(page) = self.page_cache.get(page_id.clone()) {
+                if let Some(ref page_accesses) = self.page_accesses {
+                    page_accesses.record(read_pass, &page_id, &page);
+                }
                 request.continue_seek(read_pass, page_id, &page, self.record_siblings);
                 continue;
             }
@@ -452,6 +484,9 @@ impl Seeker {
         let page = self
             .page_cache
             .insert(page_load.page_id().clone(), page_data);
+        if let Some(ref page_accesses) = self.page_accesses {
+            page_accesses.record(read_pass, page_load.page_id(), &page);
+        }
 
         for waiting_request in self
             .page_loads
@@ -26,7 +26,8 @@ use std::{
 use super::{
     page_walker::{NeedsPage, Output, PageSource, PageWalker},
     seek::{Completion, Seek, Seeker},
-    KeyReadWrite, RootPagePending, UpdateCommand, UpdateShared, WarmUpCommand, WorkerOutput,
+    KeyReadWrite, PageAccesses, RootPagePending, UpdateCommand, UpdateShared, WarmUpCommand,
+    WorkerOutput,
 };
 
 use crate::{
@@ -52,6 +53,7 @@ pub(super) struct WarmUpParams {
     pub page_cache: PageCache,
     pub store: Store,
     pub root: Node,
+    pub page_accesses: Option<PageAccesses>,
 }
 
 pub(super) fn run_warm_up(
@@ -63,7 +65,8 @@ pub(super) fn run_warm_up(
     let read_pass = params.page_cache.new_read_pass();
     let page_loader = params.store.page_loader();
     let page_io_receiver = page_loader.io_handle().receiver().clone();
-    let seeker = Seeker::new(params.root, params.page_cache.clone(), page_loader, true);
+    let mut seeker = Seeker::new(params.root, params.page_cache.clone(), page_loader, true);
+    seeker.record_page_accesses(params.page_accesses);
 
     let result = warm_up_phase(read_pass, page_io_receiver, seeker, warmup_rx, finish_rx);
 
@@ -86,12 +89,13 @@ pub(super) fn run_update<H: NodeHasher>(params: UpdateParams) -> anyhow::Result<
         ..
     } = params;
 
-    let seeker = Seeker::new(
+    let mut seeker = Seeker::new(
         root,
         page_cache.clone(),
         store.page_loader(),
         command.shared.witness,
     );
+    seeker.record_page_accesses(command.shared.page_accesses.clone());
 
     update::<H>(root, page_cache, page_pool, seeker, command, warm_ups)
 }
@@ -202,6 +206,7 @@ fn update<H: NodeHasher>(
         page_pool.clone(),
         None,
     );
+    root_page_updater.record_page_accesses(shared.page_accesses.clone());
 
     for (trie_pos, pending_op) in pending_ops {
         match pending_op {
@@ -322,16 +327,15 @@ impl<H: NodeHasher> RangeUpdater<H> {
             ShardIndex::Shard(i) => PageSource::PageCacheShard(page_cache.get_shard(*i)),
         };
 
+        let mut page_walker =
+            PageWalker::<H>::new(root, page_source, page_pool.clone(), Some(ROOT_PAGE_ID));
+        page_walker.record_page_accesses(shared.page_accesses.clone());
+
         RangeUpdater {
             shared,
             write_pass,
             region,
-            page_walker: PageWalker::<H>::new(
-                root,
-                page_source,
-                page_pool.clone(),
-                Some(ROOT_PAGE_ID),
-            ),
+            page_walker,
             range_start,
             range_end,
             saved_advance: None,
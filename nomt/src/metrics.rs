@@ -1,14 +1,78 @@
-use std::sync::{
-    atomic::{AtomicU64, Ordering},
-    Arc,
+use std::{
+    fmt::Write as _,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
 };
 
+/// The active metrics of all open databases, so that processes running several databases can
+/// report all of them. See [`registered_metrics`].
+static REGISTRY: Mutex<Vec<Weak<ActiveMetrics>>> = Mutex::new(Vec::new());
+
 /// Metrics collector, if active, it provides Counters and Timers
 #[derive(Clone)]
 pub struct Metrics {
     metrics: Option<Arc<ActiveMetrics>>,
 }
 
+/// The values of the metrics of a database at some point in time. See [`Metrics::snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// The label of the database. See [`crate::Options::metrics_label`].
+    pub label: String,
+    /// The number of page requests.
+    pub page_requests: u64,
+    /// The number of page requests which missed the page cache.
+    pub page_cache_misses: u64,
+    /// The number of keys read or committed.
+    pub logical_reads: u64,
+    /// The number of pages read from disk, from both the hash-table and the b-tree.
+    pub physical_page_reads: u64,
+    /// The mean page fetch time in nanoseconds, if any page was fetched.
+    pub page_fetch_mean_ns: Option<u64>,
+    /// The mean value fetch time during reads in nanoseconds, if any value was fetched.
+    pub value_fetch_mean_ns: Option<u64>,
+    /// The number of hash-table pages loaded from disk, as of the last commit.
+    pub hash_table_page_loads: u64,
+    /// The number of hash-table buckets probed by page loads, as of the last commit.
+    pub hash_table_probed_buckets: u64,
+    /// The number of bytes the page pool reserved from the OS, as of the last commit.
+    pub page_pool_reserved_bytes: u64,
+    /// The number of free pages of the page pool, including those in thread-local freelists, as
+    /// of the last commit.
+    pub page_pool_free_pages: u64,
+    /// The number of pages allocated from the page pool, as of the last commit.
+    pub page_pool_allocations: u64,
+    /// The number of b-tree nodes split off, leaves and branches alike.
+    pub btree_splits: u64,
+    /// The number of b-tree nodes merged into their right sibling, leaves and branches alike.
+    pub btree_merges: u64,
+    /// The mean time of the merkle update of a commit in nanoseconds, if any.
+    pub merkle_update_mean_ns: Option<u64>,
+    /// The mean time of a sync, writing one or more commits to disk, in nanoseconds, if any.
+    pub sync_mean_ns: Option<u64>,
+    /// The mean time a sync waits for the WAL to be written and synced in nanoseconds, if any.
+    pub wal_sync_mean_ns: Option<u64>,
+    /// Whether syncs skip the `fsync` of all files but the manifest. See
+    /// [`crate::Durability::PowerLossProtected`].
+    pub fsync_skipping: bool,
+}
+
+/// Returns the metrics of all open databases with metrics collection enabled, in the order the
+/// databases were opened.
+pub fn registered_metrics() -> Vec<Metrics> {
+    // UNWRAP: the lock is never held across code which may panic.
+    let registry = REGISTRY.lock().unwrap();
+    registry
+        .iter()
+        .filter_map(Weak::upgrade)
+        .map(|metrics| Metrics {
+            metrics: Some(metrics),
+        })
+        .collect()
+}
+
 /// Metrics that can be collected during execution
 #[derive(PartialEq, Eq, Hash)]
 pub enum Metric {
@@ -20,44 +84,336 @@ pub enum Metric {
     PageFetchTime,
     /// Timer used to record average value fetch time during reads
     ValueFetchTime,
+    /// Counter of keys read or committed
+    LogicalReads,
+    /// Counter of pages read from disk, from both the hash-table and the b-tree
+    PhysicalPageReads,
+    /// Sampled total of hash-table pages loaded from disk
+    HashTablePageLoads,
+    /// Sampled total of hash-table buckets probed by page loads
+    HashTableProbedBuckets,
+    /// Sampled number of bytes reserved by the page pool
+    PagePoolReservedBytes,
+    /// Sampled number of free pages of the page pool, including thread-local freelists
+    PagePoolFreePages,
+    /// Sampled total of pages allocated from the page pool
+    PagePoolAllocations,
+    /// Counter of b-tree nodes split off
+    BtreeSplits,
+    /// Counter of b-tree nodes merged into their right sibling
+    BtreeMerges,
+    /// 1 if syncs skip the fsync of all files but the manifest, 0 otherwise
+    FsyncSkipping,
+    /// Timer used to record the merkle update of each commit
+    MerkleUpdateTime,
+    /// Timer used to record each sync of commits to disk
+    SyncTime,
+    /// Timer used to record how long each sync waits for the WAL writeout
+    WalSyncTime,
 }
 
 struct ActiveMetrics {
+    label: String,
     page_requests: AtomicU64,
     page_cache_misses: AtomicU64,
+    logical_reads: AtomicU64,
+    physical_page_reads: AtomicU64,
+    hash_table_page_loads: AtomicU64,
+    hash_table_probed_buckets: AtomicU64,
+    page_pool_reserved_bytes: AtomicU64,
+    page_pool_free_pages: AtomicU64,
+    page_pool_allocations: AtomicU64,
+    btree_splits: AtomicU64,
+    btree_merges: AtomicU64,
+    fsync_skipping: AtomicU64,
     page_fetch_time: Timer,
     value_fetch_time: Timer,
+    merkle_update_time: Timer,
+    sync_time: Timer,
+    wal_sync_time: Timer,
+}
+
+impl ActiveMetrics {
+    fn timer(&self, metric: Metric) -> &Timer {
+        match metric {
+            Metric::PageFetchTime => &self.page_fetch_time,
+            Metric::ValueFetchTime => &self.value_fetch_time,
+            Metric::MerkleUpdateTime => &self.merkle_update_time,
+            Metric::SyncTime => &self.sync_time,
+            Metric::WalSyncTime => &self.wal_sync_time,
+            _ => panic!("Specified metric is not a Timer"),
+        }
+    }
+
+    /// The counters and sampled values, along with their Prometheus names, types and help texts.
+    fn values(&self) -> [(&'static str, &'static str, &'static str, &AtomicU64); 12] {
+        [
+            (
+                "nomt_page_requests_total",
+                "counter",
+                "Page requests.",
+                &self.page_requests,
+            ),
+            (
+                "nomt_page_cache_misses_total",
+                "counter",
+                "Page requests which missed the page cache.",
+                &self.page_cache_misses,
+            ),
+            (
+                "nomt_logical_reads_total",
+                "counter",
+                "Keys read or committed.",
+                &self.logical_reads,
+            ),
+            (
+                "nomt_physical_page_reads_total",
+                "counter",
+                "Pages read from disk.",
+                &self.physical_page_reads,
+            ),
+            (
+                "nomt_hash_table_page_loads_total",
+                "counter",
+                "Hash-table pages loaded from disk.",
+                &self.hash_table_page_loads,
+            ),
+            (
+                "nomt_hash_table_probed_buckets_total",
+                "counter",
+                "Hash-table buckets probed by page loads.",
+                &self.hash_table_probed_buckets,
+            ),
+            (
+                "nomt_page_pool_reserved_bytes",
+                "gauge",
+                "Bytes reserved from the OS by the page pool.",
+                &self.page_pool_reserved_bytes,
+            ),
+            (
+                "nomt_page_pool_free_pages",
+                "gauge",
+                "Free pages of the page pool.",
+                &self.page_pool_free_pages,
+            ),
+            (
+                "nomt_page_pool_allocations_total",
+                "counter",
+                "Pages allocated from the page pool.",
+                &self.page_pool_allocations,
+            ),
+            (
+                "nomt_btree_splits_total",
+                "counter",
+                "B-tree nodes split off.",
+                &self.btree_splits,
+            ),
+            (
+                "nomt_btree_merges_total",
+                "counter",
+                "B-tree nodes merged into their right sibling.",
+                &self.btree_merges,
+            ),
+            (
+                "nomt_fsync_skipping",
+                "gauge",
+                "1 if syncs skip the fsync of all files but the manifest.",
+                &self.fsync_skipping,
+            ),
+        ]
+    }
+
+    /// The timers, along with their Prometheus names and help texts.
+    fn timers(&self) -> [(&'static str, &'static str, &Timer); 5] {
+        [
+            (
+                "nomt_page_fetch_seconds",
+                "Time spent fetching pages.",
+                &self.page_fetch_time,
+            ),
+            (
+                "nomt_value_fetch_seconds",
+                "Time spent fetching values during reads.",
+                &self.value_fetch_time,
+            ),
+            (
+                "nomt_merkle_update_seconds",
+                "Time spent updating the merkle trie on commit.",
+                &self.merkle_update_time,
+            ),
+            (
+                "nomt_sync_seconds",
+                "Time spent syncing commits to disk.",
+                &self.sync_time,
+            ),
+            (
+                "nomt_wal_sync_seconds",
+                "Time syncs spent waiting for the WAL writeout.",
+                &self.wal_sync_time,
+            ),
+        ]
+    }
+}
+
+/// Encode the metrics of the given databases in the Prometheus text exposition format, e.g.
+/// `encode_prometheus(&registered_metrics())`.
+///
+/// Every sample carries the label of its database as the `db` label. Timers are exported as
+/// summaries without quantiles. Inactive metrics are skipped.
+pub fn encode_prometheus(metrics: &[Metrics]) -> String {
+    let active = metrics
+        .iter()
+        .filter_map(|metrics| metrics.metrics.as_deref())
+        .collect::<Vec<_>>();
+    let mut out = String::new();
+    let Some(first) = active.first() else {
+        return out;
+    };
+
+    // UNWRAP: writing to a string never fails.
+    for (i, (name, kind, help, _)) in first.values().into_iter().enumerate() {
+        writeln!(out, "# HELP {name} {help}").unwrap();
+        writeln!(out, "# TYPE {name} {kind}").unwrap();
+        for metrics in &active {
+            let value = metrics.values()[i].3.load(Ordering::Relaxed);
+            let db = escape_label(&metrics.label);
+            writeln!(out, "{name}{{db=\"{db}\"}} {value}").unwrap();
+        }
+    }
+    for (i, (name, help, _)) in first.timers().into_iter().enumerate() {
+        writeln!(out, "# HELP {name} {help}").unwrap();
+        writeln!(out, "# TYPE {name} summary").unwrap();
+        for metrics in &active {
+            let (count, sum_ns) = metrics.timers()[i].2.totals();
+            let db = escape_label(&metrics.label);
+            let sum = sum_ns as f64 / 1e9;
+            writeln!(out, "{name}_sum{{db=\"{db}\"}} {sum}").unwrap();
+            writeln!(out, "{name}_count{{db=\"{db}\"}} {count}").unwrap();
+        }
+    }
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
 }
 
 impl Metrics {
-    /// Returns the Metrics object, active or not based on the specified input
-    pub fn new(active: bool) -> Self {
+    /// Returns the Metrics object, active and registered under the given label if one is given
+    ///
+    /// Without the `metrics` feature, the object is never active.
+    pub fn new(label: Option<String>) -> Self {
+        let Some(label) = label.filter(|_| cfg!(feature = "metrics")) else {
+            return Self { metrics: None };
+        };
+        let metrics = Arc::new(ActiveMetrics {
+            label,
+            page_requests: AtomicU64::new(0),
+            page_cache_misses: AtomicU64::new(0),
+            logical_reads: AtomicU64::new(0),
+            physical_page_reads: AtomicU64::new(0),
+            hash_table_page_loads: AtomicU64::new(0),
+            hash_table_probed_buckets: AtomicU64::new(0),
+            page_pool_reserved_bytes: AtomicU64::new(0),
+            page_pool_free_pages: AtomicU64::new(0),
+            page_pool_allocations: AtomicU64::new(0),
+            btree_splits: AtomicU64::new(0),
+            btree_merges: AtomicU64::new(0),
+            fsync_skipping: AtomicU64::new(0),
+            page_fetch_time: Timer::new(),
+            value_fetch_time: Timer::new(),
+            merkle_update_time: Timer::new(),
+            sync_time: Timer::new(),
+            wal_sync_time: Timer::new(),
+        });
+        // UNWRAP: the lock is never held across code which may panic.
+        let mut registry = REGISTRY.lock().unwrap();
+        registry.retain(|metrics| metrics.strong_count() > 0);
+        registry.push(Arc::downgrade(&metrics));
         Self {
-            metrics: if active {
-                Some(Arc::new(ActiveMetrics {
-                    page_requests: AtomicU64::new(0),
-                    page_cache_misses: AtomicU64::new(0),
-                    page_fetch_time: Timer::new(),
-                    value_fetch_time: Timer::new(),
-                }))
-            } else {
-                None
-            },
+            metrics: Some(metrics),
         }
     }
 
+    /// Returns whether metrics collection is active
+    pub fn is_active(&self) -> bool {
+        self.metrics.is_some()
+    }
+
+    /// Returns the label of the database, if metrics collection is active
+    pub fn label(&self) -> Option<&str> {
+        self.metrics.as_ref().map(|metrics| &metrics.label[..])
+    }
+
+    /// Returns the current values of the metrics, if metrics collection is active
+    pub fn snapshot(&self) -> Option<MetricsSnapshot> {
+        self.metrics.as_ref().map(|metrics| MetricsSnapshot {
+            label: metrics.label.clone(),
+            page_requests: metrics.page_requests.load(Ordering::Relaxed),
+            page_cache_misses: metrics.page_cache_misses.load(Ordering::Relaxed),
+            logical_reads: metrics.logical_reads.load(Ordering::Relaxed),
+            physical_page_reads: metrics.physical_page_reads.load(Ordering::Relaxed),
+            page_fetch_mean_ns: metrics.page_fetch_time.mean(),
+            value_fetch_mean_ns: metrics.value_fetch_time.mean(),
+            hash_table_page_loads: metrics.hash_table_page_loads.load(Ordering::Relaxed),
+            hash_table_probed_buckets: metrics.hash_table_probed_buckets.load(Ordering::Relaxed),
+            page_pool_reserved_bytes: metrics.page_pool_reserved_bytes.load(Ordering::Relaxed),
+            page_pool_free_pages: metrics.page_pool_free_pages.load(Ordering::Relaxed),
+            page_pool_allocations: metrics.page_pool_allocations.load(Ordering::Relaxed),
+            btree_splits: metrics.btree_splits.load(Ordering::Relaxed),
+            btree_merges: metrics.btree_merges.load(Ordering::Relaxed),
+            merkle_update_mean_ns: metrics.merkle_update_time.mean(),
+            sync_mean_ns: metrics.sync_time.mean(),
+            wal_sync_mean_ns: metrics.wal_sync_time.mean(),
+            fsync_skipping: metrics.fsync_skipping.load(Ordering::Relaxed) != 0,
+        })
+    }
+
     /// Increase the Counter specified by the input
     ///
     /// panics if the specified [`Metric`] is not a Counter
     pub fn count(&self, metric: Metric) {
+        self.count_n(metric, 1)
+    }
+
+    /// Increase the Counter specified by the input by `n`
+    ///
+    /// panics if the specified [`Metric`] is not a Counter
+    pub fn count_n(&self, metric: Metric, n: u64) {
         if let Some(ref metrics) = self.metrics {
             let counter = match metric {
                 Metric::PageRequests => &metrics.page_requests,
                 Metric::PageCacheMisses => &metrics.page_cache_misses,
+                Metric::LogicalReads => &metrics.logical_reads,
+                Metric::PhysicalPageReads => &metrics.physical_page_reads,
+                Metric::BtreeSplits => &metrics.btree_splits,
+                Metric::BtreeMerges => &metrics.btree_merges,
                 _ => panic!("Specified metric is not a Counter"),
             };
 
-            counter.fetch_add(1, Ordering::Relaxed);
+            counter.fetch_add(n, Ordering::Relaxed);
+        }
+    }
+
+    /// Set the sampled value specified by the input
+    ///
+    /// panics if the specified [`Metric`] is not a sampled value
+    pub fn set(&self, metric: Metric, value: u64) {
+        if let Some(ref metrics) = self.metrics {
+            let sample = match metric {
+                Metric::HashTablePageLoads => &metrics.hash_table_page_loads,
+                Metric::HashTableProbedBuckets => &metrics.hash_table_probed_buckets,
+                Metric::PagePoolReservedBytes => &metrics.page_pool_reserved_bytes,
+                Metric::PagePoolFreePages => &metrics.page_pool_free_pages,
+                Metric::PagePoolAllocations => &metrics.page_pool_allocations,
+                Metric::FsyncSkipping => &metrics.fsync_skipping,
+                _ => panic!("Specified metric is not a sampled value"),
+            };
+
+            sample.store(value, Ordering::Relaxed);
         }
     }
 
@@ -65,21 +421,29 @@ impl Metrics {
     ///
     /// panics if the specified [`Metric`] is not a Timer
     pub fn record<'a>(&'a self, metric: Metric) -> Option<impl Drop + 'a> {
-        self.metrics.as_ref().and_then(|metrics| {
-            let timer = match metric {
-                Metric::PageFetchTime => &metrics.page_fetch_time,
-                Metric::ValueFetchTime => &metrics.value_fetch_time,
-                _ => panic!("Specified metric is not a Timer"),
-            };
+        self.metrics
+            .as_ref()
+            .map(|metrics| metrics.timer(metric).record())
+    }
 
-            Some(timer.record())
-        })
+    /// Returns the number of records and the sum of the recorded times in nanoseconds of the
+    /// Timer specified by the input, or zeros if metrics collection is not active
+    ///
+    /// panics if the specified [`Metric`] is not a Timer
+    pub(crate) fn timer_totals(&self, metric: Metric) -> (u64, u64) {
+        self.metrics
+            .as_ref()
+            .map_or((0, 0), |metrics| metrics.timer(metric).totals())
     }
 
     /// Print collected metrics to stdout
     pub fn print(&self) {
         if let Some(ref metrics) = self.metrics {
-            println!("metrics");
+            println!("metrics ({})", metrics.label);
+
+            if metrics.fsync_skipping.load(Ordering::Relaxed) != 0 {
+                println!("  WARNING: fsync skipped, durability relies on power-loss protection");
+            }
 
             let tot_page_requests = metrics.page_requests.load(Ordering::Relaxed);
             println!("  page requests         {}", tot_page_requests);
@@ -95,6 +459,16 @@ impl Metrics {
                 );
             }
 
+            let logical_reads = metrics.logical_reads.load(Ordering::Relaxed);
+            if logical_reads != 0 {
+                let physical_page_reads = metrics.physical_page_reads.load(Ordering::Relaxed);
+                let amplification = physical_page_reads as f64 / logical_reads as f64;
+                println!(
+                    "  read amplification    {:.2} - {} pages read for {} keys",
+                    amplification, physical_page_reads, logical_reads
+                );
+            }
+
             if let Some(mean) = metrics.page_fetch_time.mean() {
                 println!("  page fetch mean       {}", pretty_display_ns(mean));
             }
@@ -102,6 +476,35 @@ impl Metrics {
             if let Some(mean) = metrics.value_fetch_time.mean() {
                 println!("  value fetch mean      {}", pretty_display_ns(mean));
             }
+
+            let page_loads = metrics.hash_table_page_loads.load(Ordering::Relaxed);
+            if page_loads != 0 {
+                let probed_buckets = metrics.hash_table_probed_buckets.load(Ordering::Relaxed);
+                println!(
+                    "  hash-table probes     {:.2} - {} buckets for {} page loads",
+                    probed_buckets as f64 / page_loads as f64,
+                    probed_buckets,
+                    page_loads
+                );
+            }
+
+            let splits = metrics.btree_splits.load(Ordering::Relaxed);
+            let merges = metrics.btree_merges.load(Ordering::Relaxed);
+            if splits != 0 || merges != 0 {
+                println!("  b-tree nodes          {splits} split off, {merges} merged");
+            }
+
+            if let Some(mean) = metrics.merkle_update_time.mean() {
+                println!("  merkle update mean    {}", pretty_display_ns(mean));
+            }
+
+            if let Some(mean) = metrics.sync_time.mean() {
+                println!("  sync mean             {}", pretty_display_ns(mean));
+            }
+
+            if let Some(mean) = metrics.wal_sync_time.mean() {
+                println!("  wal sync mean         {}", pretty_display_ns(mean));
+            }
         } else {
             println!("Metrics collection was not activated")
         }
@@ -137,11 +540,18 @@ impl Timer {
     }
 
     fn mean(&self) -> Option<u64> {
-        let n = self.number_of_records.load(Ordering::Relaxed);
-        let sum = self.sum.load(Ordering::Relaxed);
+        let (n, sum) = self.totals();
         sum.checked_div(n)
     }
 
+    /// The number of records and the sum of the recorded times in nanoseconds.
+    fn totals(&self) -> (u64, u64) {
+        (
+            self.number_of_records.load(Ordering::Relaxed),
+            self.sum.load(Ordering::Relaxed),
+        )
+    }
+
     fn record<'a>(&'a self) -> impl Drop + 'a {
         struct TimerGuard<'a> {
             start: std::time::Instant,
@@ -1,17 +1,38 @@
-use std::path::PathBuf;
+use std::{ops::ControlFlow, path::PathBuf, sync::Arc, time::Duration};
+
+use bitvec::prelude::*;
+
+use crate::{
+    bitbox::{HashTableCreationCallback, WalReplayCallback},
+    io::PagePool,
+    merkle::WitnessFilter,
+    wal_archive::{self, WalArchiveCallback},
+    BackgroundError, Clock, FaultInjector, HashTableCreationProgress, KeyPath, RootAnchor,
+    WalReplayProgress, WalSegment, WalSink,
+};
 
 /// Options when opening a [`crate::Nomt`] instance.
+#[derive(Clone)]
 pub struct Options {
     /// The path to the directory where the trie is stored.
     pub(crate) path: PathBuf,
     /// The number of commit workers. Values over 64 will be rounded down to 64.
     pub(crate) commit_concurrency: usize,
-    /// The number of io_uring instances, or I/O threads on non-Linux platforms.
+    /// The number of io_uring instances, or I/O threads of the posix backend.
     pub(crate) io_workers: usize,
     /// Enable or disable metrics collection.
     pub(crate) metrics: bool,
+    pub(crate) metrics_label: Option<String>,
     pub(crate) bitbox_num_pages: u32,
+    /// The memory budget of the cache of merkle trie pages.
+    pub(crate) page_cache_size_bytes: usize,
+    /// The policy choosing the pages evicted from the cache of merkle trie pages.
+    pub(crate) page_cache_eviction: PageCacheEviction,
     pub(crate) bitbox_seed: [u8; 16],
+    /// Whether to redistribute the hash-table of an existing database according to `bitbox_seed`.
+    pub(crate) reseed_hashtable: bool,
+    /// Whether to open an existing database without write access, next to a writer.
+    pub(crate) read_only: bool,
     pub(crate) panic_on_sync: bool,
     pub(crate) rollback: bool,
     /// The maximum number of commits that can be rolled back.
@@ -21,6 +42,68 @@ pub struct Options {
     pub(crate) rollback_tp_size: usize,
     /// Whether to preallocate the hashtable file.
     pub(crate) preallocate_ht: bool,
+    /// Informed of the progress of creating the hashtable file.
+    pub(crate) hash_table_creation_progress: Option<HashTableCreationCallback>,
+    /// Whether to read back hashtable pages after writing them.
+    pub(crate) verify_ht_writes: bool,
+    /// The key the pages of the hash-table, WAL and b-tree files are encrypted with.
+    pub(crate) encryption_key: Option<[u8; 32]>,
+    /// The backend performing page I/O.
+    pub(crate) io_backend: IoBackend,
+    /// How the io_uring instances submit and complete I/O.
+    pub(crate) io_uring_mode: IoUringMode,
+    /// The number of requests an I/O worker pushes to its submission queue before submitting.
+    pub(crate) io_submit_batch: usize,
+    /// The maximum number of requests in flight per I/O worker.
+    pub(crate) io_max_in_flight: usize,
+    /// Whether I/O workers take queued reads before writes.
+    pub(crate) io_prioritize_reads: bool,
+    /// Names, CPU affinity and priorities of internal threads.
+    pub(crate) thread_config: ThreadConfig,
+    /// The path of a second copy of the WAL file.
+    pub(crate) wal_mirror: Option<PathBuf>,
+    /// Sinks the WAL is streamed to in addition to the local WAL file.
+    pub(crate) wal_sinks: Vec<Arc<dyn WalSink>>,
+    /// The number of WAL sinks which must acknowledge a WAL writeout. `None` means all of them.
+    pub(crate) wal_sink_quorum: Option<usize>,
+    /// Informed of the progress of the WAL replay on open.
+    pub(crate) wal_replay_progress: Option<WalReplayCallback>,
+    /// Informed of every new root after it has been committed.
+    pub(crate) root_anchor: Option<Arc<dyn RootAnchor>>,
+    /// Receives the changes of every sync before it is made durable.
+    pub(crate) wal_archive: Option<WalArchiveCallback>,
+    /// Whether to double-check every merkle update with a different number of workers.
+    pub(crate) audit_merkle_updates: bool,
+    /// The maximum number of hash-table pages examined for compaction on each commit.
+    pub(crate) hashtable_compaction_budget: usize,
+    /// The maximum number of hash-table pages moved to a grown hash-table on each commit.
+    pub(crate) hashtable_resize_budget: usize,
+    /// The maximum number of commits and the maximum delay coalesced into a single sync.
+    pub(crate) commit_coalescing: Option<(usize, Duration)>,
+    /// The maximum size of a value in bytes.
+    pub(crate) max_value_size: usize,
+    /// The values read for absent keys, by key-path prefix.
+    pub(crate) default_values: crate::default_values::DefaultValues,
+    /// How values are compressed when written to the b-tree.
+    pub(crate) value_compression: ValueCompression,
+    pub(crate) value_log_threshold: Option<usize>,
+    /// The pool pages are allocated from. `None` means a pool of the instance's own.
+    pub(crate) page_pool: Option<PagePool>,
+    /// How the memory of the pool of the instance's own is backed.
+    pub(crate) huge_pages: HugePages,
+    /// The NUMA node the memory of the pool of the instance's own is bound to.
+    pub(crate) numa_node: Option<u32>,
+    /// Consulted before I/O and able to fail it.
+    pub(crate) fault_injector: Option<Arc<dyn FaultInjector>>,
+    /// Receives the errors of internal threads.
+    pub(crate) on_background_error: Option<crate::background_error::BackgroundErrorCallback>,
+    /// How the files written by a sync are made durable.
+    pub(crate) durability: Durability,
+    /// The source of time of time-based behavior.
+    pub(crate) clock: Arc<dyn Clock>,
+    /// Simulates a power failure at a sync point.
+    #[cfg(feature = "crash-simulation")]
+    pub(crate) crash_simulator: Option<Arc<crate::io::crash::CrashSimulator>>,
 }
 
 impl Options {
@@ -35,14 +118,151 @@ impl Options {
             commit_concurrency: 1,
             io_workers: 3,
             metrics: false,
+            metrics_label: None,
             bitbox_num_pages: 64_000,
+            page_cache_size_bytes: crate::page_cache::DEFAULT_CACHE_SIZE_BYTES,
+            page_cache_eviction: PageCacheEviction::Clock,
             bitbox_seed,
+            reseed_hashtable: false,
+            read_only: false,
             panic_on_sync: false,
             rollback: false,
             max_rollback_log_len: 100,
             warm_up: false,
             rollback_tp_size: 4,
             preallocate_ht: true,
+            hash_table_creation_progress: None,
+            verify_ht_writes: false,
+            encryption_key: None,
+            io_backend: IoBackend::default(),
+            io_uring_mode: IoUringMode::IoPoll,
+            io_submit_batch: 128,
+            io_max_in_flight: 128,
+            io_prioritize_reads: false,
+            thread_config: ThreadConfig::default(),
+            wal_mirror: None,
+            wal_sinks: Vec::new(),
+            wal_sink_quorum: None,
+            wal_replay_progress: None,
+            root_anchor: None,
+            wal_archive: None,
+            audit_merkle_updates: false,
+            hashtable_compaction_budget: 0,
+            hashtable_resize_budget: 1024,
+            commit_coalescing: None,
+            max_value_size: u32::MAX as usize,
+            default_values: Default::default(),
+            value_compression: ValueCompression::None,
+            value_log_threshold: None,
+            page_pool: None,
+            huge_pages: HugePages::Off,
+            numa_node: None,
+            fault_injector: None,
+            on_background_error: None,
+            durability: Durability::Fsync,
+            clock: Arc::new(crate::SystemClock::new()),
+            #[cfg(feature = "crash-simulation")]
+            crash_simulator: None,
+        }
+    }
+
+    /// The options a fork at the given path is opened with, see [`crate::Nomt::fork`].
+    ///
+    /// Only the options shaping the contents of the database and how it performs are kept. Those
+    /// connecting the database to the outside, such as WAL sinks, root anchors, callbacks and
+    /// fault injectors, are reset to their defaults, so that nothing done to the fork reaches
+    /// anything following the original. Every field is listed, so that a new option has to be
+    /// placed on one side or the other.
+    pub(crate) fn for_fork(&self, path: &std::path::Path) -> Self {
+        let Options {
+            path: _,
+            commit_concurrency,
+            io_workers,
+            metrics,
+            metrics_label: _,
+            bitbox_num_pages,
+            page_cache_size_bytes,
+            page_cache_eviction,
+            bitbox_seed,
+            reseed_hashtable: _,
+            read_only: _,
+            panic_on_sync,
+            rollback,
+            max_rollback_log_len,
+            warm_up,
+            rollback_tp_size,
+            preallocate_ht,
+            hash_table_creation_progress: _,
+            verify_ht_writes,
+            encryption_key,
+            io_backend,
+            io_uring_mode,
+            io_submit_batch,
+            io_max_in_flight,
+            io_prioritize_reads,
+            thread_config,
+            wal_mirror: _,
+            wal_sinks: _,
+            wal_sink_quorum: _,
+            wal_replay_progress: _,
+            root_anchor: _,
+            wal_archive: _,
+            audit_merkle_updates,
+            hashtable_compaction_budget,
+            hashtable_resize_budget,
+            commit_coalescing,
+            max_value_size,
+            default_values,
+            value_compression,
+            value_log_threshold,
+            page_pool,
+            huge_pages,
+            numa_node,
+            fault_injector: _,
+            on_background_error: _,
+            durability,
+            clock,
+            #[cfg(feature = "crash-simulation")]
+                crash_simulator: _,
+        } = self;
+
+        Options {
+            path: path.to_path_buf(),
+            commit_concurrency: *commit_concurrency,
+            io_workers: *io_workers,
+            metrics: *metrics,
+            bitbox_num_pages: *bitbox_num_pages,
+            page_cache_size_bytes: *page_cache_size_bytes,
+            page_cache_eviction: *page_cache_eviction,
+            bitbox_seed: *bitbox_seed,
+            panic_on_sync: *panic_on_sync,
+            rollback: *rollback,
+            max_rollback_log_len: *max_rollback_log_len,
+            warm_up: *warm_up,
+            rollback_tp_size: *rollback_tp_size,
+            preallocate_ht: *preallocate_ht,
+            verify_ht_writes: *verify_ht_writes,
+            encryption_key: *encryption_key,
+            io_backend: *io_backend,
+            io_uring_mode: *io_uring_mode,
+            io_submit_batch: *io_submit_batch,
+            io_max_in_flight: *io_max_in_flight,
+            io_prioritize_reads: *io_prioritize_reads,
+            thread_config: thread_config.clone(),
+            audit_merkle_updates: *audit_merkle_updates,
+            hashtable_compaction_budget: *hashtable_compaction_budget,
+            hashtable_resize_budget: *hashtable_resize_budget,
+            commit_coalescing: *commit_coalescing,
+            max_value_size: *max_value_size,
+            default_values: default_values.clone(),
+            value_compression: *value_compression,
+            value_log_threshold: *value_log_threshold,
+            page_pool: page_pool.clone(),
+            huge_pages: *huge_pages,
+            numa_node: *numa_node,
+            durability: *durability,
+            clock: clock.clone(),
+            ..Options::new()
         }
     }
 
@@ -53,21 +273,32 @@ impl Options {
 
     /// Set the maximum number of concurrent commit workers.
     ///
-    /// Values over 64 will be rounded down to 64.
+    /// Values over 64 will be rounded down to 64. This also bounds the values later set with
+    /// [`RuntimeConfig::commit_concurrency`].
     ///
     /// May not be zero.
     pub fn commit_concurrency(&mut self, commit_concurrency: usize) {
         self.commit_concurrency = commit_concurrency;
     }
 
-    /// Set metrics collection on or off.
+    /// Set metrics collection on or off. Metrics are only collected with the `metrics` feature,
+    /// which is enabled by default; without it, this has no effect.
     ///
     /// Default: off.
     pub fn metrics(&mut self, metrics: bool) {
         self.metrics = metrics;
     }
 
-    /// Set the number of io_uring instances.
+    /// Set the label identifying the database in its metrics, e.g. the name of the chain it
+    /// stores, to tell apart several databases open in the same process. See
+    /// [`crate::registered_metrics`].
+    ///
+    /// Default: the path of the database.
+    pub fn metrics_label(&mut self, label: impl Into<String>) {
+        self.metrics_label = Some(label.into());
+    }
+
+    /// Set the number of io_uring instances, or of I/O threads with [`IoBackend::Posix`].
     ///
     /// Must be more than 0
     pub fn io_workers(&mut self, io_workers: usize) {
@@ -75,18 +306,156 @@ impl Options {
         self.io_workers = io_workers;
     }
 
+    /// Set the backend performing page I/O. See [`IoBackend`].
+    ///
+    /// Opening fails if the backend is not available on this platform.
+    ///
+    /// Default: [`IoBackend::IoUring`] on Linux, [`IoBackend::Posix`] elsewhere.
+    pub fn io_backend(&mut self, io_backend: IoBackend) {
+        self.io_backend = io_backend;
+    }
+
+    /// Set how the io_uring instances submit and complete I/O. See [`IoUringMode`].
+    ///
+    /// Only relevant on Linux. Polling requires direct I/O, so databases on tmpfs, or on file
+    /// systems whose direct I/O alignment exceeds 4 KiB, use [`IoUringMode::Interrupt`] whatever
+    /// the mode set here.
+    ///
+    /// Default: [`IoUringMode::IoPoll`], i.e. interrupts on tmpfs and polling elsewhere.
+    pub fn io_uring_mode(&mut self, io_uring_mode: IoUringMode) {
+        self.io_uring_mode = io_uring_mode;
+    }
+
+    /// Set the number of requests, e.g. page writes, each I/O worker pushes to its submission
+    /// queue before handing them to the kernel.
+    ///
+    /// Smaller batches get the device busy sooner, larger ones cost fewer system calls. Values
+    /// above [`Options::io_max_in_flight`] behave like it.
+    ///
+    /// Only relevant on Linux. Must be more than 0.
+    ///
+    /// Default: 128.
+    pub fn io_submit_batch(&mut self, io_submit_batch: usize) {
+        assert!(io_submit_batch > 0);
+        self.io_submit_batch = io_submit_batch;
+    }
+
+    /// Set the maximum number of requests each I/O worker keeps in flight.
+    ///
+    /// Devices with high latency but deep queues, like cloud block storage, tend to benefit from
+    /// higher values than local NVMe drives.
+    ///
+    /// The io_uring instances are sized for this value, so it also bounds the values later set
+    /// with [`RuntimeConfig::io_max_in_flight`].
+    ///
+    /// Only relevant on Linux. Must be more than 0 and at most 32768.
+    ///
+    /// Default: 128.
+    pub fn io_max_in_flight(&mut self, io_max_in_flight: usize) {
+        assert!(io_max_in_flight > 0 && io_max_in_flight <= 32768);
+        self.io_max_in_flight = io_max_in_flight;
+    }
+
+    /// Set whether I/O workers take queued reads before queued writes.
+    ///
+    /// Reads are mostly made by sessions waiting on them, while writes are mostly the writeback of
+    /// a sync running in the background. Prioritizing reads keeps the latency of sessions low
+    /// while a sync is writing many pages, at the cost of a longer sync. Otherwise, reads and
+    /// writes are taken in turns.
+    ///
+    /// Default: `false`.
+    pub fn io_prioritize_reads(&mut self, io_prioritize_reads: bool) {
+        self.io_prioritize_reads = io_prioritize_reads;
+    }
+
+    /// Set the names, CPU affinity and priorities of internal threads. See [`ThreadConfig`].
+    ///
+    /// Opening the database fails if the settings cannot be applied, e.g. due to missing
+    /// privileges.
+    ///
+    /// Default: unnamed, unpinned threads with default priority.
+    pub fn thread_config(&mut self, thread_config: ThreadConfig) {
+        self.thread_config = thread_config;
+    }
+
+    /// Set the memory budget of the cache of the pages of the merkle trie, in bytes.
+    ///
+    /// Every page takes 4 KiB. Pages are evicted as the cache outgrows the budget, both while
+    /// pages are loaded and after every sync, except for pinned pages: those used by the active
+    /// session or by a commit in progress, and those changed by commits not yet synced. The cache
+    /// exceeds the budget while the pinned pages don't fit, e.g. during a session touching more
+    /// pages than the budget. See [`crate::Nomt::page_cache_stats`].
+    ///
+    /// The budget is split evenly among [`Options::commit_concurrency`] shards of the cache.
+    ///
+    /// Default: 256 MiB.
+    pub fn page_cache_size_bytes(&mut self, page_cache_size_bytes: usize) {
+        self.page_cache_size_bytes = page_cache_size_bytes;
+    }
+
+    /// Set the policy choosing the pages evicted from the cache of the pages of the merkle trie.
+    /// See [`PageCacheEviction`].
+    ///
+    /// Default: [`PageCacheEviction::Clock`].
+    pub fn page_cache_eviction(&mut self, page_cache_eviction: PageCacheEviction) {
+        self.page_cache_eviction = page_cache_eviction;
+    }
+
     /// Set the number of hashtable buckets to use when creating the database.
+    ///
+    /// The hash-table of an existing database can be grown with [`crate::Nomt::resize_hash_table`].
     pub fn hashtable_buckets(&mut self, hashtable_buckets: u32) {
         self.bitbox_num_pages = hashtable_buckets;
     }
 
     /// Set the seed for the hash function used by the bitbox store.
     ///
+    /// The seed determines which buckets of the hash-table the pages are placed in. It is chosen
+    /// when creating the database and persisted in its manifest, so that the placement differs
+    /// between deployments and cannot be targeted by adversarially chosen keys. Existing databases
+    /// keep their persisted seed, unless [`Self::reseed_hashtable`] is set.
+    ///
     /// Useful for reproducibility.
+    ///
+    /// Default: random.
     pub fn bitbox_seed(&mut self, bitbox_seed: [u8; 16]) {
         self.bitbox_seed = bitbox_seed;
     }
 
+    /// Set to `true` to redistribute the hash-table of an existing database according to the seed
+    /// set with [`Self::bitbox_seed`], if it differs from the persisted one.
+    ///
+    /// This migrates databases which were created with a fixed or otherwise known seed to a fresh
+    /// random one. The pages are rewritten into a copy of the hash-table while opening the
+    /// database, which takes time and disk space proportional to the size of the hash-table. If
+    /// interrupted by a crash, the reseed is completed by the next open.
+    ///
+    /// Default: `false`.
+    pub fn reseed_hashtable(&mut self, reseed_hashtable: bool) {
+        self.reseed_hashtable = reseed_hashtable;
+    }
+
+    /// Set to `true` to open an existing database without write access, e.g. to serve queries
+    /// from replica processes on the same machine as the process writing to it.
+    ///
+    /// The files are opened read-only and neither the directory lock nor the WAL are taken, so a
+    /// single writer and any number of read-only instances may have the database open at the same
+    /// time. Commits, rollbacks and any other writes fail. Options concerning writes, such as
+    /// [`Self::rollback`] or [`Self::wal_mirror`], are ignored.
+    ///
+    /// A read-only instance serves the state of the last sync before it was opened. Call
+    /// [`crate::Nomt::refresh`] periodically to pick up the syncs of the writer since. The writer
+    /// overwrites the pages of the state it no longer needs, so once it has synced, reads from
+    /// disk fail until the instance is refreshed: every read which can fail checks the manifest
+    /// after reading, at the cost of a read of the manifest. Iterators can't fail, so check with
+    /// [`crate::Nomt::check_snapshot`] after iterating. Opening fails, and should be retried,
+    /// while the writer is in the middle of a sync.
+    ///
+    /// Default: `false`.
+    pub fn read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
     /// Set to `true` to panic on sync after writing the WAL file and updating the manifest, but
     /// before the data has been written to the HT file.
     ///
@@ -102,6 +471,9 @@ impl Options {
 
     /// Set the maximum number of commits that can be rolled back.
     ///
+    /// At every sync, the rollback deltas of older commits are discarded and their disk space is
+    /// reclaimed. To prune the rollback log on demand, see [`crate::Nomt::prune_to`].
+    ///
     /// Only relevant if rollback is enabled.
     ///
     /// Default: 100.
@@ -128,13 +500,767 @@ impl Options {
     /// Sets whether to preallocate the hashtable file.
     ///
     /// Many filesystems don't handle sparse files well. If the `preallocate_ht` option is set to
-    /// `true`, NOMT will try to make sure that the file is fully allocated.
+    /// `true`, NOMT will try to make sure that the file is fully allocated. On Linux, this uses
+    /// `fallocate`, zeroing the range where supported, and falls back to writing zeros elsewhere.
+    /// Writing zeros takes a while for large tables, see [`Options::hash_table_creation_progress`].
     ///
     /// If set to `false` this won't allocate the disk space for the hashtable file upfront, but can
-    /// lead to fragmentation later.
+    /// lead to fragmentation later: the file is sparse and its blocks are allocated in the random
+    /// order in which buckets are first written. Files on tmpfs are never preallocated.
     ///
     /// Default: `true`.
     pub fn preallocate_ht(&mut self, preallocate_ht: bool) {
         self.preallocate_ht = preallocate_ht;
     }
+
+    /// Set a callback which is informed of the progress of creating the hashtable file when a new
+    /// database is created.
+    ///
+    /// The callback is called before the file is allocated and then after every GiB allocated,
+    /// with the number of bytes allocated so far. It's called from the thread opening the
+    /// database.
+    ///
+    /// Default: none.
+    pub fn hash_table_creation_progress(
+        &mut self,
+        callback: impl Fn(HashTableCreationProgress) + Send + Sync + 'static,
+    ) {
+        self.hash_table_creation_progress = Some(Arc::new(callback));
+    }
+
+    /// Sets whether to verify hashtable pages after writing them.
+    ///
+    /// If set to `true`, every page written to the hashtable file is checksummed before the write
+    /// and read back afterwards. A mismatch, e.g. due to silent corruption by the storage device,
+    /// fails the commit before the WAL is truncated, so the pages are restored from the WAL the
+    /// next time the database is opened. This roughly doubles the I/O of hashtable writeouts.
+    ///
+    /// The pages are only read back from the device with direct I/O, which bypasses the page cache
+    /// of the OS. Otherwise, they would be read back from the page cache, so this is a no-op
+    /// without direct I/O: on tmpfs, on file systems which don't support direct I/O of 4 KiB pages
+    /// and on platforms other than Linux.
+    ///
+    /// Default: `false`.
+    pub fn verify_ht_writes(&mut self, verify_ht_writes: bool) {
+        self.verify_ht_writes = verify_ht_writes;
+    }
+
+    /// Encrypt the pages of the hash-table, WAL and b-tree files at rest with the given key,
+    /// using AES-256 in XTS mode. Pages are encrypted and decrypted by the I/O layer, keep their
+    /// size and layout, and are only ever held decrypted in memory.
+    ///
+    /// XTS protects the confidentiality of the pages, not their integrity: modified pages are not
+    /// detected as such. The manifest, which holds no keys or values, is not encrypted. Neither are
+    /// the WAL blobs handed to WAL sinks, nor the block index, which only maps syncs to block
+    /// numbers. The auxiliary column is encrypted too, with every batch padded to whole pages.
+    ///
+    /// Encryption can't be combined with [`Options::rollback`], [`Options::value_log_threshold`]
+    /// or [`Options::wal_archive`], whose logs would store values unencrypted:
+    /// [`crate::Nomt::open`] fails if any of them is enabled.
+    ///
+    /// The key can only be set when the database is created. Opening an encrypted database fails
+    /// without the key it was created with, and opening a database which is not encrypted fails
+    /// with a key.
+    ///
+    /// Default: none.
+    #[cfg(feature = "encryption")]
+    pub fn encryption_key(&mut self, key: [u8; 32]) {
+        self.encryption_key = Some(key);
+    }
+
+    /// Set the path of a file which mirrors the WAL file, ideally on a different device.
+    ///
+    /// Every commit writes the WAL to both files and is acknowledged only once both have durably
+    /// stored it. When recovering from an unclean shutdown, the copy with the longer valid prefix
+    /// is replayed, so the database survives corruption of either copy. The file is created if it
+    /// doesn't exist.
+    ///
+    /// Default: none.
+    pub fn wal_mirror(&mut self, path: impl Into<PathBuf>) {
+        self.wal_mirror = Some(path.into());
+    }
+
+    /// Add a sink to which the WAL is streamed on every commit, in addition to the local WAL file.
+    ///
+    /// A commit is acknowledged only once the local WAL file is durable and a quorum of sinks has
+    /// acknowledged the WAL. See [`Options::wal_sink_quorum`]. Failing to reach the quorum fails
+    /// the commit with an error, after which every commit and read fails until the database is
+    /// reopened to recover its last synced state.
+    ///
+    /// The WAL only holds the hash-table pages of the merkle trie, not the values, which are
+    /// written to the b-tree files directly. Sinks therefore give no remote durability of the
+    /// state: the database can't be rebuilt from what they store.
+    pub fn wal_sink(&mut self, sink: Arc<dyn WalSink>) {
+        self.wal_sinks.push(sink);
+    }
+
+    /// Set the number of WAL sinks which must acknowledge the WAL before a commit is acknowledged.
+    ///
+    /// Must not exceed the number of sinks added with [`Options::wal_sink`].
+    ///
+    /// Default: all sinks.
+    pub fn wal_sink_quorum(&mut self, quorum: usize) {
+        self.wal_sink_quorum = Some(quorum);
+    }
+
+    /// Set a callback which is informed of the progress of replaying the WAL while opening the
+    /// database, after an unclean shutdown.
+    ///
+    /// The callback is called before the replay starts and then periodically. Returning
+    /// [`ControlFlow::Break`] cancels the replay, in which case [`crate::Nomt::open`] fails with
+    /// [`crate::WalReplayCancelled`]. The progress made so far is kept: the next open resumes the
+    /// replay where it stopped.
+    ///
+    /// Default: none.
+    pub fn wal_replay_progress(
+        &mut self,
+        callback: impl Fn(WalReplayProgress) -> ControlFlow<()> + Send + Sync + 'static,
+    ) {
+        self.wal_replay_progress = Some(Arc::new(callback));
+    }
+
+    /// Set a hook which is informed of every root persisted by a sync, in the background. Only the
+    /// last of several coalesced commits is anchored. With the `root-anchor-log` feature, `RootAnchorLog` is a built-in implementation.
+    ///
+    /// Default: none.
+    pub fn root_anchor(&mut self, root_anchor: Arc<dyn RootAnchor>) {
+        self.root_anchor = Some(root_anchor);
+    }
+
+    /// Set a callback which receives the [`WalSegment`] of every sync, holding its value changes
+    /// and auxiliary writes, e.g. to ship them to a backup location for point-in-time recovery
+    /// with [`crate::Nomt::restore`].
+    ///
+    /// The callback is called on the committing thread once the WAL of the sync is durable and
+    /// before the sync is made durable, so every durable sync has been archived. An error fails
+    /// the sync like an I/O error would, after which the database must be reopened. Expiries set
+    /// with [`crate::Session::write_with_ttl`] are archived as auxiliary writes. Replaces any
+    /// callback set with [`Options::wal_archive_dir`].
+    ///
+    /// The segments hold the values in plaintext, so the archive can't be combined with
+    /// [`Options::encryption_key`].
+    ///
+    /// Default: none.
+    pub fn wal_archive(
+        &mut self,
+        callback: impl Fn(&WalSegment) -> anyhow::Result<()> + Send + Sync + 'static,
+    ) {
+        self.wal_archive = Some(Arc::new(callback));
+    }
+
+    /// Archive the [`WalSegment`] of every sync as a file of the given directory, which is created
+    /// if it doesn't exist. The segments can be read back with [`WalSegment::read_dir`].
+    ///
+    /// This is a [`Options::wal_archive`] callback which writes and syncs each segment to a file
+    /// named after its sequence number. Replaces any callback set with [`Options::wal_archive`].
+    ///
+    /// Default: none.
+    pub fn wal_archive_dir(&mut self, path: impl Into<PathBuf>) {
+        self.wal_archive = Some(wal_archive::archive_to_dir(path.into()));
+    }
+
+    /// Set to `true` to audit every merkle update for nondeterminism.
+    ///
+    /// Each commit then additionally runs the merkle update with a different number of workers,
+    /// and therefore a different schedule, against a private page cache, and panics if the
+    /// resulting root or any of the updated pages differ. This is a debugging aid which makes
+    /// commits considerably slower.
+    ///
+    /// Default: `false`.
+    pub fn audit_merkle_updates(&mut self, audit_merkle_updates: bool) {
+        self.audit_merkle_updates = audit_merkle_updates;
+    }
+
+    /// Set the maximum number of hash-table pages examined for compaction on each commit.
+    ///
+    /// Deleted pages leave tombstones in the hash-table, which lengthen the probe sequences of page
+    /// lookups. Compaction continuously sweeps over the hash-table in the background of commits,
+    /// moving pages into tombstones earlier on their probe sequences and, once a sweep is complete,
+    /// turning tombstones which are no longer passed through by any probe sequence back into empty
+    /// buckets. Every examined page costs one read from the hash-table file. Changes are logged
+    /// through the WAL like any other. See [`crate::Nomt::hash_table_stats`].
+    ///
+    /// Zero disables compaction.
+    ///
+    /// Default: 0.
+    pub fn hashtable_compaction_budget(&mut self, hashtable_compaction_budget: usize) {
+        self.hashtable_compaction_budget = hashtable_compaction_budget;
+    }
+
+    /// Set the maximum number of hash-table pages moved to the grown hash-table on each commit
+    /// while the hash-table is being resized. See [`crate::Nomt::resize_hash_table`].
+    ///
+    /// Every moved page costs one read from the hash-table file. Pages written by commits are
+    /// moved along with the write regardless. Compaction is paused while resizing.
+    ///
+    /// Must be more than 0.
+    ///
+    /// Default: 1024.
+    pub fn hashtable_resize_budget(&mut self, hashtable_resize_budget: usize) {
+        assert!(hashtable_resize_budget > 0);
+        self.hashtable_resize_budget = hashtable_resize_budget;
+    }
+
+    /// Coalesce consecutive commits into a single sync to disk.
+    ///
+    /// Every commit still computes and returns its own root, and its changes are visible to reads
+    /// right away, but the commit is only made durable once `max_commits` commits have accumulated
+    /// or `max_delay` has elapsed since the first of them. The delay is checked on commit. Use
+    /// [`crate::Nomt::flush`] to sync outstanding commits earlier, and before dropping the
+    /// database to learn whether they were synced: dropping syncs them as well, but can only
+    /// report a failure to [`Options::on_background_error`]. After a crash, the database reflects
+    /// the last sync, which may lose a group of commits at once. See
+    /// [`crate::Nomt::last_commit_token`] for telling which commits survived.
+    ///
+    /// Default: disabled, every commit is synced on its own.
+    pub fn commit_coalescing(&mut self, max_commits: usize, max_delay: Duration) {
+        self.commit_coalescing = Some((max_commits, max_delay));
+    }
+
+    /// Set the maximum size of a value in bytes.
+    ///
+    /// Commits writing a larger value fail with [`crate::ValueTooLarge`] before anything is
+    /// written. The limit can be queried with [`crate::Nomt::max_value_size`]. Must be more than 0
+    /// and at most `u32::MAX`.
+    ///
+    /// Default: `u32::MAX`.
+    pub fn max_value_size(&mut self, max_value_size: usize) {
+        assert!(max_value_size > 0 && max_value_size <= u32::MAX as usize);
+        self.max_value_size = max_value_size;
+    }
+
+    /// Read absent keys starting with the given prefix as having the given value, e.g. a zero
+    /// balance for accounts.
+    ///
+    /// Reads of such keys return the default value where they would otherwise return `None`.
+    /// Writing the default value deletes the key instead, and reads of the default value recorded
+    /// in the actuals are taken as reads of an absent key, so that a key holding its default value
+    /// is never stored. A proof of absence of a key thus also proves that it holds its default
+    /// value, see [`crate::proof::VerifiedPathProof::confirm_value_or_default`]. Iterating
+    /// over ranges of keys does not yield keys holding their default value.
+    ///
+    /// A key under several configured prefixes takes the default value of the longest one.
+    /// Configuring a prefix again replaces its default value. All parties verifying proofs must
+    /// agree on the default values, which are not persisted.
+    ///
+    /// Default: none.
+    pub fn default_value(&mut self, prefix: &BitSlice<u8, Msb0>, value: Vec<u8>) {
+        self.default_values.insert(prefix, value);
+    }
+
+    /// Set how values are compressed when written to the b-tree, both in leaves and in overflow
+    /// pages. A value is only stored compressed if that makes it smaller.
+    ///
+    /// Whether a value is compressed is recorded along with it, so this can be changed freely
+    /// between openings of a database: values are read back regardless of how they were stored.
+    ///
+    /// Default: `ValueCompression::None`.
+    pub fn value_compression(&mut self, value_compression: ValueCompression) {
+        self.value_compression = value_compression;
+    }
+
+    /// Store values of at least `threshold` bytes in append-only value logs next to the b-tree
+    /// instead of in chains of overflow pages, so that updating them only rewrites a small reference
+    /// in a leaf. Values small enough to fit in a leaf are never affected.
+    ///
+    /// Values are separated into logs by size. Overwritten and deleted values remain in the logs
+    /// until collected with [`crate::Nomt::collect_value_log_garbage`].
+    ///
+    /// Values already in the logs remain readable if this is disabled, and are moved back to
+    /// overflow pages by garbage collection.
+    ///
+    /// Default: disabled.
+    pub fn value_log_threshold(&mut self, threshold: usize) {
+        self.value_log_threshold = Some(threshold);
+    }
+
+    /// Allocate pages from the given pool, which may be shared with other instances. Instances
+    /// sharing a pool reuse each other's free pages, so their memory usage is bounded by the pool
+    /// as a whole. See [`PagePool::with_limit`].
+    ///
+    /// Default: a pool of the instance's own.
+    pub fn page_pool(&mut self, page_pool: PagePool) {
+        self.page_pool = Some(page_pool);
+    }
+
+    /// Set whether the memory of the page pool is backed by huge pages, which reduces TLB misses
+    /// when accessing cached pages. See [`HugePages`].
+    ///
+    /// This only applies to the pool of the instance's own. A pool set with
+    /// [`Options::page_pool`] is configured through [`PagePool::with_regions`].
+    ///
+    /// Default: [`HugePages::Off`].
+    pub fn huge_pages(&mut self, huge_pages: HugePages) {
+        self.huge_pages = huge_pages;
+    }
+
+    /// Bind the memory of the page pool to the given NUMA node. Only supported on Linux, and
+    /// ignored if the node doesn't exist.
+    ///
+    /// This only applies to the pool of the instance's own. A pool set with
+    /// [`Options::page_pool`] is configured through [`PagePool::with_regions`].
+    ///
+    /// Default: none, memory is allocated according to the policy of the process.
+    pub fn numa_node(&mut self, numa_node: u32) {
+        self.numa_node = Some(numa_node);
+    }
+
+    /// Set a hook which is consulted before page I/O, WAL writes and fsyncs and may fail them, for
+    /// testing how an application copes with I/O errors. See [`crate::FaultInjector`].
+    ///
+    /// Default: none.
+    pub fn fault_injector(&mut self, fault_injector: Arc<dyn FaultInjector>) {
+        self.fault_injector = Some(fault_injector);
+    }
+
+    /// Set a callback receiving the errors which occur on internal threads, such as the I/O
+    /// workers, WAL sinks and the compaction of the hash-table. See [`crate::BackgroundError`].
+    /// It also receives a warning if a hash-table file is left sparse despite preallocation.
+    ///
+    /// These errors don't fail any call, so this is the only way to learn about them, e.g. to halt
+    /// the node, degrade service or alert an operator. The callback is invoked on the thread the
+    /// error occurred on and must not block for long, nor call into the database.
+    ///
+    /// Default: none, the errors are discarded.
+    pub fn on_background_error(
+        &mut self,
+        callback: impl Fn(BackgroundError) + Send + Sync + 'static,
+    ) {
+        self.on_background_error = Some(Arc::new(callback));
+    }
+
+    /// Set how the files written by a sync are made durable. See [`Durability`].
+    ///
+    /// [`Durability::PowerLossProtected`] is only safe on storage which guarantees that completed
+    /// writes survive a power loss. The mode in effect is reported by the metrics.
+    ///
+    /// Default: [`Durability::Fsync`].
+    pub fn durability(&mut self, durability: Durability) {
+        self.durability = durability;
+    }
+
+    /// Set the clock consulted by time-based behavior: the delay of
+    /// [`Options::commit_coalescing`] and the intervals of [`crate::Nomt::read_amplification`].
+    /// Tests and simulations may pass a [`crate::ManualClock`] to advance time virtually instead
+    /// of sleeping.
+    ///
+    /// Default: [`crate::SystemClock`].
+    pub fn clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Simulate a power failure at a sync point, for testing crash consistency. See
+    /// [`crate::CrashSimulator`].
+    ///
+    /// The simulator is also installed as the fault injector, replacing any set before.
+    ///
+    /// Default: none.
+    #[cfg(feature = "crash-simulation")]
+    pub fn crash_simulator(&mut self, crash_simulator: Arc<crate::io::crash::CrashSimulator>) {
+        self.fault_injector = Some(crash_simulator.clone());
+        self.crash_simulator = Some(crash_simulator);
+    }
+}
+
+/// How values are compressed when written to the b-tree. See [`Options::value_compression`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueCompression {
+    /// Values are stored as they are.
+    None,
+    /// Values are compressed with zstd at the given compression level.
+    Zstd {
+        /// The zstd compression level, from 1 to 22. Higher levels compress better but slower.
+        level: i32,
+    },
+}
+
+/// The policy choosing the pages evicted from the page cache. See [`Options::page_cache_eviction`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PageCacheEviction {
+    /// Evict pages not accessed since the previous eviction pass passed over them, following the
+    /// CLOCK algorithm. This approximates LRU at the cost of a flag per page.
+    #[default]
+    Clock,
+    /// Evict the least recently used pages.
+    Lru,
+    /// Evict the least recently used of the pages accessed only once since they were loaded, and
+    /// only then the least recently used of the others, like the 2Q algorithm. A one-off pass over
+    /// many pages, e.g. proving a batch of keys far from the usual ones, doesn't push out the pages
+    /// in regular use, as it does with LRU.
+    TwoQueue,
+}
+
+/// How the files written by a sync are made durable. See [`Options::durability`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Durability {
+    /// Every file written by a sync is synced with `fsync`.
+    Fsync,
+    /// Only the manifest is synced with `fsync`, once per sync. The hash-table, the WAL and the
+    /// b-tree files are only written back with `sync_file_range` on Linux, or `fdatasync`
+    /// elsewhere, which skips flushing the device cache and committing the file system journal.
+    ///
+    /// The `fsync` of the manifest acts as the write barrier of a sync: it commits the journal,
+    /// including the size and allocation changes of the files written back before it, and flushes
+    /// the device cache once. This relies on the journal being committed in order, as is the case
+    /// for ext4 and XFS.
+    ///
+    /// **Warning**: data which was written back but not flushed from the device cache is lost on
+    /// power loss, unless the device guarantees otherwise. Only use this on battery-backed or
+    /// enterprise storage with power-loss protection. Otherwise, a power loss can corrupt the
+    /// database beyond recovery.
+    PowerLossProtected,
+}
+
+/// How the memory of the page pool is backed. See [`Options::huge_pages`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HugePages {
+    /// Regular pages, or transparent huge pages if the system enables them for all memory.
+    #[default]
+    Off,
+    /// Transparent huge pages, requested with `madvise(MADV_HUGEPAGE)`. The kernel backs memory
+    /// with huge pages when it can, which requires the `madvise` or `always` mode in
+    /// `/sys/kernel/mm/transparent_hugepage/enabled`.
+    Transparent,
+    /// 2 MiB huge pages reserved by the administrator, mapped with `MAP_HUGETLB`. Falls back to
+    /// [`HugePages::Transparent`] once the reserved huge pages run out.
+    Explicit,
+}
+
+/// The backend performing page I/O. See [`Options::io_backend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoBackend {
+    /// Each I/O worker drives an io_uring instance. Only available on Linux.
+    IoUring,
+    /// Each I/O worker is a thread issuing blocking `pread` and `pwrite` calls, one page at a
+    /// time. Available on all Unix platforms.
+    ///
+    /// Page cache bypass is unaffected: files are still opened with `O_DIRECT` on Linux, where
+    /// supported, and `F_NOCACHE` is set on macOS.
+    Posix,
+}
+
+impl Default for IoBackend {
+    fn default() -> Self {
+        if cfg!(target_os = "linux") {
+            IoBackend::IoUring
+        } else {
+            IoBackend::Posix
+        }
+    }
+}
+
+/// How the io_uring instances submit and complete I/O. See [`Options::io_uring_mode`].
+///
+/// Polling for completions requires files to be opened with `O_DIRECT`, which is not done on
+/// tmpfs or on file systems whose direct I/O alignment exceeds 4 KiB. There, completions are always
+/// delivered by interrupts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoUringMode {
+    /// Submit with system calls and receive completions through interrupts.
+    Interrupt,
+    /// Submit with system calls and busy-poll the device for completions (`IORING_SETUP_IOPOLL`).
+    IoPoll,
+    /// Like [`IoUringMode::IoPoll`], but completion work is only run when the I/O worker enters
+    /// the kernel, rather than interrupting it (`IORING_SETUP_COOP_TASKRUN`). Requires Linux 5.19.
+    Cooperative,
+    /// Like [`IoUringMode::IoPoll`], but a kernel thread polls the submission queue
+    /// (`IORING_SETUP_SQPOLL`), so that submitting I/O requires no system calls.
+    ///
+    /// A single kernel thread is shared by all I/O workers. It busy-polls, consuming a full CPU
+    /// core, and is accounted to the kernel rather than to this process. To limit that, it goes to
+    /// sleep after `idle_ms` milliseconds without submissions and it is pinned to `cpu`, if set.
+    /// Older kernels require elevated privileges for this mode.
+    SqPoll {
+        /// The number of milliseconds without submissions after which the polling thread sleeps.
+        idle_ms: u32,
+        /// The CPU the polling thread is pinned to.
+        cpu: Option<u32>,
+    },
+}
+
+/// Configuration of the internal threads of a database. See [`Options::thread_config`].
+///
+/// Threads are grouped as follows:
+///   - I/O: the I/O workers, see [`Options::io_workers`].
+///   - Commit: the threads writing out commits to the hash-table and the b-tree.
+///   - Merkle: the workers updating the trie, see [`Options::commit_concurrency`].
+///
+/// Other threads, such as those used for fsyncs, rollback and root anchoring, are not affected.
+#[derive(Debug, Clone, Default)]
+pub struct ThreadConfig {
+    pub(crate) name_prefix: Option<String>,
+    pub(crate) io: ThreadSettings,
+    pub(crate) commit: ThreadSettings,
+    pub(crate) merkle: ThreadSettings,
+}
+
+impl ThreadConfig {
+    /// Set a prefix for the names of all threads in the groups above, to tell apart the threads of
+    /// several databases. Note that Linux truncates thread names to 15 bytes.
+    ///
+    /// Default: none.
+    pub fn name_prefix(&mut self, name_prefix: impl Into<String>) {
+        self.name_prefix = Some(name_prefix.into());
+    }
+
+    /// Set the settings of the I/O workers.
+    pub fn io(&mut self, settings: ThreadSettings) {
+        self.io = settings;
+    }
+
+    /// Set the settings of the threads writing out commits.
+    pub fn commit(&mut self, settings: ThreadSettings) {
+        self.commit = settings;
+    }
+
+    /// Set the settings of the merkle update workers.
+    pub fn merkle(&mut self, settings: ThreadSettings) {
+        self.merkle = settings;
+    }
+}
+
+/// CPU affinity and priority of a group of threads. See [`ThreadConfig`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThreadSettings {
+    pub(crate) cpus: Vec<usize>,
+    pub(crate) priority: Option<ThreadPriority>,
+}
+
+impl ThreadSettings {
+    /// Pin the threads to the given CPUs. Each thread may run on any of them.
+    ///
+    /// Only supported on Linux.
+    ///
+    /// Default: all CPUs.
+    pub fn cpus(&mut self, cpus: impl IntoIterator<Item = usize>) {
+        self.cpus = cpus.into_iter().collect();
+    }
+
+    /// Set the scheduling priority of the threads.
+    ///
+    /// Default: inherited from the thread opening the database.
+    pub fn priority(&mut self, priority: ThreadPriority) {
+        self.priority = Some(priority);
+    }
+}
+
+/// The scheduling priority of a thread. See [`ThreadSettings::priority`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadPriority {
+    /// Run with the normal scheduling policy and the given nice value, from -20 (highest) to 19
+    /// (lowest). Lowering the nice value usually requires elevated privileges.
+    ///
+    /// Only supported on Linux.
+    Nice(i32),
+    /// Run with the `SCHED_FIFO` real-time scheduling policy and the given priority, from 1
+    /// (lowest) to 99 (highest). Usually requires elevated privileges.
+    ///
+    /// Real-time threads preempt all others, so this is only advisable for threads pinned to
+    /// dedicated CPUs.
+    RealTime(i32),
+}
+
+/// The form of the witness produced by [`crate::Nomt::commit_and_prove`]. See
+/// [`SessionParams::witness_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WitnessMode {
+    /// One path proof for every path down the trie, in [`crate::Witness::path_proofs`].
+    Paths,
+    /// The path proofs merged into a single multiproof, in [`crate::Witness::multi_proof`].
+    ///
+    /// Siblings shared by several paths, or which can be computed from the other paths, are left
+    /// out, which shrinks the witness considerably when many of the proven keys share prefixes.
+    /// The multiproof can be checked against the root with
+    /// [`nomt_core::multi_proof_verification::verify`].
+    Multiproof,
+}
+
+/// The state served by the reads of a [`crate::Session`]. See
+/// [`SessionParams::read_consistency`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadConsistency {
+    /// Reads see every commit made so far, including those held back by
+    /// [`Options::commit_coalescing`] and those being synced to disk.
+    #[default]
+    Latest,
+    /// Reads see the state as of the last sync which has fully completed, never that of a sync in
+    /// progress. This lags behind [`ReadConsistency::Latest`] by at most the commits held back by
+    /// [`Options::commit_coalescing`] and those of the sync in progress, if any.
+    ///
+    /// Sessions reading the last synced state can't be committed. Unlike other sessions, any
+    /// number of them may be active at a time, alongside a session which is going to be committed.
+    LastSynced,
+}
+
+/// Parameters of a [`crate::Session`].
+pub struct SessionParams {
+    pub(crate) record_witness: bool,
+    pub(crate) witness_mode: WitnessMode,
+    pub(crate) witness_pages: bool,
+    pub(crate) sequential_readahead: usize,
+    pub(crate) witness_filter: Option<WitnessFilter>,
+    pub(crate) read_consistency: ReadConsistency,
+    pub(crate) time: Option<u64>,
+}
+
+impl Default for SessionParams {
+    fn default() -> Self {
+        Self {
+            record_witness: true,
+            witness_mode: WitnessMode::Paths,
+            witness_pages: false,
+            sequential_readahead: 256,
+            witness_filter: None,
+            read_consistency: ReadConsistency::Latest,
+            time: None,
+        }
+    }
+}
+
+impl SessionParams {
+    /// Set whether the session may be committed with a witness.
+    ///
+    /// Disabling this is useful for high-frequency commits which are never proven, such as local
+    /// indexing data. Sessions which don't record a witness can only be committed with
+    /// [`crate::Nomt::commit`], which skips the merkle paths of keys which are only read, so they
+    /// need not warm up such keys. Nor do they record [`SessionParams::witness_pages`].
+    ///
+    /// Default: `true`.
+    pub fn record_witness(&mut self, record_witness: bool) {
+        self.record_witness = record_witness;
+    }
+
+    /// Set the form of the witness produced when committing with
+    /// [`crate::Nomt::commit_and_prove`].
+    ///
+    /// Default: [`WitnessMode::Paths`].
+    pub fn witness_mode(&mut self, witness_mode: WitnessMode) {
+        self.witness_mode = witness_mode;
+    }
+
+    /// Set whether the witness records the pages of the trie accessed by the commit, along with
+    /// their contents prior to it, in [`crate::Witness::pages`].
+    ///
+    /// This captures the page-level accesses of the commit, including those made for reads which
+    /// end at terminator nodes, so that they can be reproduced from the witness, e.g. by a
+    /// challenger in a fraud-proof system. The pages accessed while warming up keys are included,
+    /// even if the keys are not part of the commit in the end. Each page adds about 4 KiB to the
+    /// witness. The witness filter does not apply to pages.
+    ///
+    /// Default: `false`.
+    pub fn witness_pages(&mut self, witness_pages: bool) {
+        self.witness_pages = witness_pages;
+    }
+
+    /// Set the maximum number of b-tree leaves read ahead for every
+    /// [`crate::Session::hint_sequential`] call. Every leaf is a 4 KiB page.
+    ///
+    /// Default: `256`.
+    pub fn sequential_readahead(&mut self, leaves: usize) {
+        self.sequential_readahead = leaves;
+    }
+
+    /// Only witness reads and writes of keys matching the given predicate, e.g. keys under certain
+    /// prefixes, when committing with [`crate::Nomt::commit_and_prove`].
+    ///
+    /// Path proofs which only serve other keys are left out of the witness, shrinking it. This
+    /// suits designs where only part of the state is proven and the rest is trusted. Note that
+    /// the new root still reflects all writes, so it can't be recomputed from a filtered witness.
+    ///
+    /// Default: all keys are witnessed.
+    pub fn witness_filter(&mut self, filter: impl Fn(&KeyPath) -> bool + Send + Sync + 'static) {
+        self.witness_filter = Some(Arc::new(filter));
+    }
+
+    /// Set the state served by [`crate::Session::read`] and its variants.
+    ///
+    /// With [`ReadConsistency::LastSynced`], reads never observe commits which may still be lost
+    /// to a crash, and the session does not block the creation of other sessions. Such a session
+    /// can't be committed, nor can it check existence in the merkle trie, which reflects the
+    /// latest state.
+    ///
+    /// Default: [`ReadConsistency::Latest`].
+    pub fn read_consistency(&mut self, read_consistency: ReadConsistency) {
+        self.read_consistency = read_consistency;
+    }
+
+    /// Set the time of the session in milliseconds since the unix epoch, e.g. the timestamp of
+    /// the block it applies.
+    ///
+    /// Values written with [`crate::Session::write_with_ttl`] expire relative to this time: the
+    /// session reads the values which have expired by then as absent, and its commit deletes them
+    /// from the trie. The time is chosen by the caller rather than read from a clock, so that
+    /// nodes applying the same sessions reach the same roots. Without a time, no values expire
+    /// within the session.
+    ///
+    /// Default: none.
+    pub fn time(&mut self, unix_millis: u64) {
+        self.time = Some(unix_millis);
+    }
+}
+
+/// Settings of an open database which can be changed with [`crate::Nomt::reconfigure`], e.g. to
+/// switch between catching up with a chain, favoring throughput, and following it live, favoring
+/// latency.
+///
+/// Only the settings which are set are changed, the others keep their current values.
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeConfig {
+    pub(crate) commit_concurrency: Option<usize>,
+    pub(crate) io_submit_batch: Option<usize>,
+    pub(crate) io_max_in_flight: Option<usize>,
+    pub(crate) io_prioritize_reads: Option<bool>,
+    pub(crate) durability: Option<Durability>,
+}
+
+impl RuntimeConfig {
+    /// Create a configuration changing nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of threads updating the merkle trie. See [`Options::commit_concurrency`].
+    ///
+    /// The trie is partitioned among the workers when the database is opened, so values above the
+    /// one it was opened with behave like it. The b-tree is synced with the concurrency the
+    /// database was opened with.
+    ///
+    /// May not be zero.
+    pub fn commit_concurrency(&mut self, commit_concurrency: usize) {
+        self.commit_concurrency = Some(commit_concurrency);
+    }
+
+    /// Set the number of requests each I/O worker pushes to its submission queue before handing
+    /// them to the kernel. See [`Options::io_submit_batch`].
+    ///
+    /// Must be more than 0.
+    pub fn io_submit_batch(&mut self, io_submit_batch: usize) {
+        assert!(io_submit_batch > 0);
+        self.io_submit_batch = Some(io_submit_batch);
+    }
+
+    /// Set the maximum number of requests each I/O worker keeps in flight, i.e. the depth of its
+    /// queue. See [`Options::io_max_in_flight`].
+    ///
+    /// Values above the one the database was opened with behave like it.
+    ///
+    /// Must be more than 0.
+    pub fn io_max_in_flight(&mut self, io_max_in_flight: usize) {
+        assert!(io_max_in_flight > 0);
+        self.io_max_in_flight = Some(io_max_in_flight);
+    }
+
+    /// Set whether I/O workers take queued reads before queued writes. See
+    /// [`Options::io_prioritize_reads`].
+    pub fn io_prioritize_reads(&mut self, io_prioritize_reads: bool) {
+        self.io_prioritize_reads = Some(io_prioritize_reads);
+    }
+
+    /// Set how the files written by a sync are made durable. See [`Options::durability`].
+    ///
+    /// A sync running meanwhile may make some of its files durable with either mode, so it is only
+    /// as durable as the weaker of the two.
+    pub fn durability(&mut self, durability: Durability) {
+        self.durability = Some(durability);
+    }
 }
@@ -1,35 +1,44 @@
 use crate::{
     bitbox::BucketIndex,
-    io::{page_pool::FatPage, PagePool},
+    io::{page_pool::FatPage, PagePool, PAGE_SIZE},
     metrics::{Metric, Metrics},
     page_diff::PageDiff,
     page_region::PageRegion,
     rw_pass_cell::{ReadPass, Region, RegionContains, RwPassCell, RwPassDomain, WritePass},
     store::MerkleTransaction,
-    Options,
+    Options, PageCacheEviction,
 };
+use dashmap::DashMap;
 use fxhash::FxBuildHasher;
-use lru::LruCache;
 use nomt_core::{
     page::DEPTH,
     page_id::{ChildPageIndex, PageId, NUM_CHILDREN, ROOT_PAGE_ID},
     trie::{LeafData, Node},
     trie_pos::{ChildNodeIndices, TriePosition},
 };
-use parking_lot::{Mutex, RwLock};
-use std::{borrow::Cow, fmt, num::NonZeroUsize, sync::Arc};
+use parking_lot::RwLock;
+use std::{
+    borrow::Cow,
+    fmt,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 // Total number of nodes stored in one Page. It depends on the `DEPTH`
 // of the rootless sub-binary tree stored in a page following this formula:
 // (2^(DEPTH + 1)) - 2
 pub const NODES_PER_PAGE: usize = (1 << DEPTH + 1) - 2;
 
-// Every 256 pages is 1MB.
-const CACHE_PAGE_LIMIT: usize = 256 * 256;
-const PAGE_LIMIT_PER_ROOT_CHILD: usize = CACHE_PAGE_LIMIT / 64;
+/// The default memory budget of the cache: 256 MiB, i.e. 65536 pages.
+pub const DEFAULT_CACHE_SIZE_BYTES: usize = 256 * 256 * PAGE_SIZE;
 
 struct PageData {
     data: RwPassCell<Option<FatPage>, ShardIndex>,
+    // whether the page was changed and not written out yet. See `PageCache::prepare_transaction`.
+    dirty: AtomicBool,
 }
 
 impl PageData {
@@ -37,6 +46,7 @@ impl PageData {
     fn pristine_with_data(domain: &RwPassDomain, shard_index: ShardIndex, data: FatPage) -> Self {
         Self {
             data: domain.protect_with_id(Some(data), shard_index),
+            dirty: AtomicBool::new(false),
         }
     }
 
@@ -44,6 +54,7 @@ impl PageData {
     fn pristine_empty(domain: &RwPassDomain, shard_index: ShardIndex) -> Self {
         Self {
             data: domain.protect_with_id(None, shard_index),
+            dirty: AtomicBool::new(false),
         }
     }
 
@@ -69,6 +80,7 @@ impl PageData {
         node: Node,
     ) {
         assert!(index < NODES_PER_PAGE, "index out of bounds");
+        self.dirty.store(true, Ordering::Relaxed);
         let mut data = self.data.write(write_pass);
         let data = data.get_or_insert_with(|| page_pool.alloc_fat_page());
         let start = index * 32;
@@ -85,6 +97,7 @@ impl PageData {
     ) {
         let left_index = children.left();
         assert!(left_index < NODES_PER_PAGE - 1, "index out of bounds");
+        self.dirty.store(true, Ordering::Relaxed);
         let mut data = self.data.write(write_pass);
         let data = data.get_or_insert_with(|| page_pool.alloc_fat_page());
         let start = left_index * 32;
@@ -101,6 +114,7 @@ impl PageData {
     ) {
         let left_index = children.left();
         assert!(left_index < NODES_PER_PAGE - 1, "index out of bounds");
+        self.dirty.store(true, Ordering::Relaxed);
 
         let mut data = self.data.write(write_pass);
         let data = data.get_or_insert_with(|| page_pool.alloc_fat_page());
@@ -164,6 +178,12 @@ impl Page {
     ) {
         self.inner.clear_leaf_data(page_pool, write_pass, children)
     }
+
+    /// Keep the page cached until it is written out, even if it wasn't changed. Pages changed by
+    /// the methods above are kept anyway.
+    pub fn mark_dirty(&self) {
+        self.inner.dirty.store(true, Ordering::Relaxed);
+    }
 }
 
 impl fmt::Debug for Page {
@@ -215,45 +235,231 @@ struct CacheEntry {
     page_data: Arc<PageData>,
     // the bucket index where this page is stored. `None` if it's a fresh page.
     bucket_index: Option<BucketIndex>,
+    // whether the page was accessed since the last eviction pass passed over it. Used by CLOCK.
+    referenced: AtomicBool,
+    // the number of pages inserted into the shard as of the last access. Used by LRU and 2Q.
+    last_access: AtomicU64,
+    // whether the page was accessed again after being inserted. Used by 2Q.
+    protected: AtomicBool,
+    // the session epoch of the last access. See `PageCache::begin_session`.
+    epoch: AtomicU64,
 }
 
 impl CacheEntry {
+    fn page(&self) -> Page {
+        Page {
+            inner: self.page_data.clone(),
+        }
+    }
+
+    fn touch(&self, eviction: PageCacheEviction, now: u64, epoch: u64) {
+        if self.epoch.load(Ordering::Relaxed) != epoch {
+            self.epoch.store(epoch, Ordering::Relaxed);
+        }
+        match eviction {
+            PageCacheEviction::Clock => self.referenced.store(true, Ordering::Relaxed),
+            PageCacheEviction::Lru => self.last_access.store(now, Ordering::Relaxed),
+            PageCacheEviction::TwoQueue => {
+                self.last_access.store(now, Ordering::Relaxed);
+                if !self.protected.load(Ordering::Relaxed) {
+                    self.protected.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// The order in which LRU and 2Q evict pages, lowest first.
+    fn rank(&self, eviction: PageCacheEviction) -> (bool, u64) {
+        let protected =
+            eviction == PageCacheEviction::TwoQueue && self.protected.load(Ordering::Relaxed);
+        (protected, self.last_access.load(Ordering::Relaxed))
+    }
+
+    /// Whether evicting the page is safe and frees its memory, i.e. the page is not pinned.
+    ///
+    /// Pages referenced outside of the cache, e.g. by a merkle update in progress, would be loaded
+    /// anew if evicted and the two copies would diverge. Pages accessed by the current session
+    /// are expected to be found in the cache by its merkle update. Dirty pages are yet to be
+    /// written out.
+    fn evictable(&self, epoch: u64) -> bool {
+        self.epoch.load(Ordering::Relaxed) != epoch
+            && Arc::strong_count(&self.page_data) == 1
+            && !self.page_data.dirty.load(Ordering::Relaxed)
+    }
+
+    /// Copy the entry into another cache, keeping its bucket index and whether it is dirty.
+    fn copy(
+        &self,
+        domain: &RwPassDomain,
+        shard_index: ShardIndex,
+        page_pool: &PagePool,
+        read_pass: &ReadPass<ShardIndex>,
+    ) -> Self {
+        let data = self.page_data.data.read(read_pass).as_ref().map(|data| {
+            let mut copy = page_pool.alloc_fat_page();
+            copy.copy_from_slice(data);
+            copy
+        });
+        let page_data = PageData {
+            data: domain.protect_with_id(data, shard_index),
+            dirty: AtomicBool::new(self.page_data.dirty.load(Ordering::Relaxed)),
+        };
+        CacheEntry {
+            page_data: Arc::new(page_data),
+            bucket_index: self.bucket_index,
+            referenced: AtomicBool::new(true),
+            last_access: AtomicU64::new(0),
+            protected: AtomicBool::new(false),
+            epoch: AtomicU64::new(0),
+        }
+    }
+
     fn init(
         domain: &RwPassDomain,
         shard_index: ShardIndex,
         maybe_page: Option<(FatPage, BucketIndex)>,
+        now: u64,
+        epoch: u64,
     ) -> Self {
-        match maybe_page {
-            Some((data, bucket_index)) => CacheEntry {
-                page_data: Arc::new(PageData::pristine_with_data(domain, shard_index, data)),
-                bucket_index: Some(bucket_index),
-            },
-            None => CacheEntry {
-                page_data: Arc::new(PageData::pristine_empty(domain, shard_index)),
-                bucket_index: None,
-            },
+        let (page_data, bucket_index) = match maybe_page {
+            Some((data, bucket_index)) => (
+                PageData::pristine_with_data(domain, shard_index, data),
+                Some(bucket_index),
+            ),
+            None => (PageData::pristine_empty(domain, shard_index), None),
+        };
+        CacheEntry {
+            page_data: Arc::new(page_data),
+            bucket_index,
+            referenced: AtomicBool::new(true),
+            last_access: AtomicU64::new(now),
+            protected: AtomicBool::new(false),
+            epoch: AtomicU64::new(epoch),
         }
     }
 }
 
 // Each shard has its own domain and handles a sub-tree of the page tree, defined by a
 // continuous set of children of the root page.
+//
+// Lookups only take a shared lock on a part of the map, so that the many threads reading pages
+// during block execution don't contend with each other. For the same reason, there is no LRU
+// order to maintain on every access: CLOCK only needs a flag per page, while LRU and 2Q stamp
+// pages with the number of inserts so far and sort the pages only when evicting.
 struct CacheShard {
     region: PageRegion,
-    locked: Mutex<CacheShardLocked>,
+    cached: DashMap<PageId, CacheEntry, FxBuildHasher>,
     page_limit: NonZeroUsize,
+    eviction: PageCacheEviction,
+    // the number of pages inserted so far, the clock of LRU and 2Q.
+    inserts: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
 }
 
-struct CacheShardLocked {
-    cached: LruCache<PageId, CacheEntry, FxBuildHasher>,
-}
+impl CacheShard {
+    fn get(&self, page_id: &PageId, epoch: u64) -> Option<Page> {
+        match self.cached.get(page_id) {
+            Some(entry) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                entry.touch(self.eviction, self.inserts.load(Ordering::Relaxed), epoch);
+                Some(entry.page())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn insert(
+        &self,
+        domain: &RwPassDomain,
+        shard_index: usize,
+        page_id: PageId,
+        page: Option<(FatPage, BucketIndex)>,
+        epoch: u64,
+    ) -> Page {
+        let now = self.inserts.fetch_add(1, Ordering::Relaxed);
+        let page = self
+            .cached
+            .entry(page_id)
+            .or_insert_with(|| {
+                CacheEntry::init(domain, ShardIndex::Shard(shard_index), page, now, epoch)
+            })
+            .page();
+
+        // Pages are evicted in batches, so that the cost of picking them is spread over many
+        // inserts. The budget holds between syncs too, e.g. for many sessions only reading.
+        let limit = self.page_limit.get();
+        if self.cached.len() > limit + limit / 16 {
+            self.evict(epoch);
+        }
+        page
+    }
+
+    fn evict(&self, epoch: u64) {
+        let limit = self.page_limit.get();
+        match self.eviction {
+            PageCacheEviction::Clock => self.evict_clock(limit, epoch),
+            PageCacheEviction::Lru | PageCacheEviction::TwoQueue => {
+                self.evict_lowest_ranked(limit, epoch)
+            }
+        }
+    }
 
-impl CacheShardLocked {
-    fn evict(&mut self, limit: NonZeroUsize) {
-        while self.cached.len() > limit.get() {
-            let _ = self.cached.pop_lru();
+    fn evict_clock(&self, limit: usize, epoch: u64) {
+        // Every pass evicts pages not accessed since the previous pass and clears the flags of the
+        // others, until enough pages are evicted. Two passes are enough, unless too many pages
+        // can't be evicted, in which case the shard stays over its limit.
+        for _ in 0..2 {
+            let excess = self.cached.len().saturating_sub(limit);
+            if excess == 0 {
+                break;
+            }
+            let mut evicted = 0;
+            self.cached.retain(|_, entry| {
+                if evicted < excess
+                    && entry.evictable(epoch)
+                    && !entry.referenced.swap(false, Ordering::Relaxed)
+                {
+                    evicted += 1;
+                    false
+                } else {
+                    true
+                }
+            });
+            self.evictions.fetch_add(evicted as u64, Ordering::Relaxed);
         }
     }
+
+    fn evict_lowest_ranked(&self, limit: usize, epoch: u64) {
+        let excess = self.cached.len().saturating_sub(limit);
+        if excess == 0 {
+            return;
+        }
+        let mut candidates = self
+            .cached
+            .iter()
+            .filter(|entry| entry.evictable(epoch))
+            .map(|entry| (entry.rank(self.eviction), entry.key().clone()))
+            .collect::<Vec<_>>();
+        let n = excess.min(candidates.len());
+        if n < candidates.len() {
+            candidates.select_nth_unstable_by_key(n, |(rank, _)| *rank);
+        }
+
+        let mut evicted = 0;
+        for (rank, page_id) in &candidates[..n] {
+            // The page may have been accessed or pinned since it was ranked.
+            let removed = self.cached.remove_if(page_id, |_, entry| {
+                entry.evictable(epoch) && entry.rank(self.eviction) == *rank
+            });
+            evicted += removed.is_some() as u64;
+        }
+        self.evictions.fetch_add(evicted, Ordering::Relaxed);
+    }
 }
 
 struct Shared {
@@ -261,6 +467,14 @@ struct Shared {
     root_page: RwLock<CacheEntry>,
     page_rw_pass_domain: RwPassDomain,
     metrics: Metrics,
+    // the epoch of the current session. See `PageCache::begin_session`.
+    epoch: AtomicU64,
+}
+
+impl Shared {
+    fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Relaxed)
+    }
 }
 
 fn shard_regions(num_shards: usize) -> Vec<(PageRegion, usize)> {
@@ -304,17 +518,26 @@ fn shard_index_for(num_shards: usize, first_ancestor: usize) -> usize {
     }
 }
 
-fn make_shards(num_shards: usize) -> Vec<CacheShard> {
+fn make_shards(
+    num_shards: usize,
+    size_bytes: usize,
+    eviction: PageCacheEviction,
+) -> Vec<CacheShard> {
     assert!(num_shards > 0);
+    // The budget is split by the number of children of the root page each shard covers.
+    let page_limit = size_bytes / PAGE_SIZE;
     shard_regions(num_shards)
         .into_iter()
         .map(|(region, count)| CacheShard {
             region,
-            locked: Mutex::new(CacheShardLocked {
-                cached: LruCache::unbounded_with_hasher(FxBuildHasher::default()),
-            }),
-            // UNWRAP: both factors are non-zero
-            page_limit: NonZeroUsize::new(PAGE_LIMIT_PER_ROOT_CHILD * count).unwrap(),
+            cached: DashMap::with_hasher(FxBuildHasher::default()),
+            page_limit: NonZeroUsize::new(page_limit * count / NUM_CHILDREN)
+                .unwrap_or(NonZeroUsize::MIN),
+            eviction,
+            inserts: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
         })
         .collect()
 }
@@ -346,14 +569,88 @@ impl PageCache {
         let domain = RwPassDomain::new();
         Self {
             shared: Arc::new(Shared {
-                shards: make_shards(o.commit_concurrency),
-                root_page: RwLock::new(CacheEntry::init(&domain, ShardIndex::Root, root_page_data)),
+                shards: make_shards(
+                    o.commit_concurrency,
+                    o.page_cache_size_bytes,
+                    o.page_cache_eviction,
+                ),
+                root_page: RwLock::new(CacheEntry::init(
+                    &domain,
+                    ShardIndex::Root,
+                    root_page_data,
+                    0,
+                    0,
+                )),
                 page_rw_pass_domain: domain,
-                metrics: metrics.into().unwrap_or(Metrics::new(false)),
+                metrics: metrics.into().unwrap_or(Metrics::new(None)),
+                epoch: AtomicU64::new(0),
             }),
         }
     }
 
+    /// Create a new `PageCache` with the given number of shards, not tied to any [`Options`].
+    /// Metrics are not collected for the new cache.
+    pub fn new_detached(
+        root_page_data: Option<(FatPage, BucketIndex)>,
+        shard_count: usize,
+    ) -> Self {
+        let domain = RwPassDomain::new();
+        Self {
+            shared: Arc::new(Shared {
+                shards: make_shards(
+                    shard_count,
+                    DEFAULT_CACHE_SIZE_BYTES,
+                    PageCacheEviction::default(),
+                ),
+                root_page: RwLock::new(CacheEntry::init(
+                    &domain,
+                    ShardIndex::Root,
+                    root_page_data,
+                    0,
+                    0,
+                )),
+                page_rw_pass_domain: domain,
+                metrics: Metrics::new(None),
+                epoch: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Create a new `PageCache` like [`PageCache::new_detached`], holding copies of the root page
+    /// and of the dirty pages of this one, i.e. those not written out yet.
+    ///
+    /// The copied pages stay dirty, so they are never evicted from the new cache.
+    pub fn detached_copy(&self, page_pool: &PagePool, shard_count: usize) -> Self {
+        let copy = Self::new_detached(None, shard_count);
+        let domain = &copy.shared.page_rw_pass_domain;
+        let read_pass = self.new_read_pass();
+
+        *copy.shared.root_page.write() =
+            self.shared
+                .root_page
+                .read()
+                .copy(domain, ShardIndex::Root, page_pool, &read_pass);
+        for shard in &self.shared.shards {
+            for entry in shard.cached.iter() {
+                if !entry.page_data.dirty.load(Ordering::Relaxed) {
+                    continue;
+                }
+                // UNWRAP: all pages which are not the root page are in a shard.
+                let shard_index = copy.shard_index_for(entry.key()).unwrap();
+                let entry_copy = entry.copy(
+                    domain,
+                    ShardIndex::Shard(shard_index),
+                    page_pool,
+                    &read_pass,
+                );
+                copy.shard(shard_index)
+                    .cached
+                    .insert(entry.key().clone(), entry_copy);
+            }
+        }
+        copy
+    }
+
     fn shard_index_for(&self, page_id: &PageId) -> Option<usize> {
         if page_id == &ROOT_PAGE_ID {
             None
@@ -380,16 +677,11 @@ impl PageCache {
             Some(i) => i,
         };
 
-        let mut shard = self.shard(shard_index).locked.lock();
-        match shard.cached.get(&page_id) {
-            Some(page) => Some(Page {
-                inner: page.page_data.clone(),
-            }),
-            None => {
-                self.shared.metrics.count(Metric::PageCacheMisses);
-                None
-            }
+        let page = self.shard(shard_index).get(&page_id, self.shared.epoch());
+        if page.is_none() {
+            self.shared.metrics.count(Metric::PageCacheMisses);
         }
+        page
     }
 
     /// Insert a page into the cache by its data. If `Some`, provide the bucket index where the
@@ -406,14 +698,8 @@ impl PageCache {
             Some(i) => i,
         };
 
-        let mut shard = self.shard(shard_index).locked.lock();
-        let cache_entry = shard.cached.get_or_insert(page_id, || {
-            CacheEntry::init(domain, ShardIndex::Shard(shard_index), page)
-        });
-
-        Page {
-            inner: cache_entry.page_data.clone(),
-        }
+        self.shard(shard_index)
+            .insert(domain, shard_index, page_id, page, self.shared.epoch())
     }
 
     /// Acquire a read pass for all pages in the cache.
@@ -457,6 +743,9 @@ impl PageCache {
 
     /// Prepares a transaction of altered pages, according to the provided page diffs.
     /// This takes a read pass.
+    ///
+    /// Only the part of a shard holding the page being written back is locked at a time, so
+    /// concurrent reads of other pages are rarely blocked by the writeback.
     pub fn prepare_transaction(
         &self,
         page_diffs: impl IntoIterator<Item = (PageId, PageDiff)>,
@@ -484,15 +773,6 @@ impl PageCache {
             }
         };
 
-        // helper for exploiting locality effects in the diffs to avoid searching through
-        // shards constantly.
-        let mut shard_guards = self
-            .shared
-            .shards
-            .iter()
-            .map(|s| s.locked.lock())
-            .collect::<Vec<_>>();
-
         for (page_id, page_diff) in page_diffs {
             if page_id == ROOT_PAGE_ID {
                 let mut root_page = self.shared.root_page.write();
@@ -505,30 +785,96 @@ impl PageCache {
 
             // UNWRAP: all pages which are not the root page are in a shard.
             let shard_index = self.shard_index_for(&page_id).unwrap();
-
-            if let Some(ref mut entry) = shard_guards[shard_index].cached.peek_mut(&page_id) {
-                let page_data = entry.page_data.data.read(&read_pass);
-                let bucket = &mut entry.bucket_index;
-                apply_page(page_id, bucket, page_data.as_ref(), page_diff);
-            } else {
+            let Some(mut entry) = self.shard(shard_index).cached.get_mut(&page_id) else {
                 panic!("dirty page {:?} is missing", page_id);
+            };
+            let entry = &mut *entry;
+            let page_data = entry.page_data.data.read(&read_pass);
+            apply_page(
+                page_id,
+                &mut entry.bucket_index,
+                page_data.as_ref(),
+                page_diff,
+            );
+            entry.page_data.dirty.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether the given page is cached and already stored in the hash-table, as opposed to having
+    /// been created since the last commit.
+    pub fn is_stored(&self, page_id: &PageId) -> bool {
+        match self.shard_index_for(page_id) {
+            None => self.shared.root_page.read().bucket_index.is_some(),
+            Some(shard_index) => self
+                .shard(shard_index)
+                .cached
+                .get(page_id)
+                .is_some_and(|entry| entry.bucket_index.is_some()),
+        }
+    }
+
+    /// Update the bucket index of a page which has been moved to another bucket, if it is cached.
+    pub fn relocate(&self, page_id: PageId, bucket_index: BucketIndex) {
+        match self.shard_index_for(&page_id) {
+            None => self.shared.root_page.write().bucket_index = Some(bucket_index),
+            Some(shard_index) => {
+                if let Some(mut entry) = self.shard(shard_index).cached.get_mut(&page_id) {
+                    entry.bucket_index = Some(bucket_index);
+                }
             }
         }
     }
 
-    /// Evict stale pages for the cache. This should only be used after all dirty pages have been
-    /// prepared for writeout with `prepare_transaction`.
+    /// Start a new session: the pages accessed from now on are pinned until the next session
+    /// starts, while those accessed by earlier sessions may be evicted.
+    pub fn begin_session(&self) {
+        self.shared.epoch.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Evict pages until every shard is within its budget, if possible. Pinned pages and pages
+    /// not yet prepared for writeout with `prepare_transaction` are kept.
     pub fn evict(&self) {
-        let shard_guards = self
-            .shared
-            .shards
-            .iter()
-            .map(|s| s.locked.lock())
-            .collect::<Vec<_>>();
+        // Parts of the shards are locked one at a time, so that readers are blocked only on the
+        // part which is currently being evicted.
+        let epoch = self.shared.epoch();
+        for shard in &self.shared.shards {
+            shard.evict(epoch);
+        }
+    }
+
+    /// Evict all pages except the root page. This should only be used when no pages are dirty,
+    /// i.e. when no session is active and all commits have been synced.
+    pub fn clear(&self) {
+        for shard in &self.shared.shards {
+            shard.cached.clear();
+        }
+    }
 
-        for (shard, mut guard) in self.shared.shards.iter().zip(shard_guards) {
-            guard.evict(shard.page_limit);
+    /// Gather the statistics of the cache. This walks all cached pages.
+    pub fn stats(&self) -> PageCacheStats {
+        let mut stats = PageCacheStats {
+            capacity_bytes: 0,
+            cached_bytes: 0,
+            pinned_bytes: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        };
+        let epoch = self.shared.epoch();
+        for shard in &self.shared.shards {
+            stats.capacity_bytes += shard.page_limit.get() * PAGE_SIZE;
+            stats.cached_bytes += shard.cached.len() * PAGE_SIZE;
+            stats.pinned_bytes += shard
+                .cached
+                .iter()
+                .filter(|entry| !entry.evictable(epoch))
+                .count()
+                * PAGE_SIZE;
+            stats.hits += shard.hits.load(Ordering::Relaxed);
+            stats.misses += shard.misses.load(Ordering::Relaxed);
+            stats.evictions += shard.evictions.load(Ordering::Relaxed);
         }
+        stats
     }
 
     fn shard(&self, index: usize) -> &CacheShard {
@@ -536,6 +882,40 @@ impl PageCache {
     }
 }
 
+/// Statistics of the cache of the pages of the merkle trie. See [`crate::Nomt::page_cache_stats`].
+///
+/// Every page counts as 4 KiB. The root page, which is always cached, is left out. The counters are
+/// cumulative since the database was opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageCacheStats {
+    /// The budget of the cache, set with [`crate::Options::page_cache_size_bytes`].
+    pub capacity_bytes: usize,
+    /// The memory held by the cached pages.
+    pub cached_bytes: usize,
+    /// The memory held by cached pages which can't be evicted: pages in use by a session or a
+    /// commit, and pages changed by a commit which are yet to be written out. These count towards
+    /// the budget, which is exceeded if they don't fit.
+    pub pinned_bytes: usize,
+    /// The number of page requests served from the cache.
+    pub hits: u64,
+    /// The number of page requests which missed the cache.
+    pub misses: u64,
+    /// The number of pages evicted.
+    pub evictions: u64,
+}
+
+impl PageCacheStats {
+    /// The share of page requests served from the cache, or `None` if no pages were requested.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let requests = self.hits + self.misses;
+        if requests == 0 {
+            None
+        } else {
+            Some(self.hits as f64 / requests as f64)
+        }
+    }
+}
+
 /// A shard of the page cache. This should only be used for pages which fall within
 /// that shard, with the exception of the root page, which is accessible via this shard.
 pub struct PageCacheShard {
@@ -558,16 +938,11 @@ impl PageCacheShard {
             .region
             .contains_exclusive(&page_id));
 
-        let mut shard = self.shared.shards[self.shard_index].locked.lock();
-        match shard.cached.get(&page_id) {
-            Some(page) => Some(Page {
-                inner: page.page_data.clone(),
-            }),
-            None => {
-                self.shared.metrics.count(Metric::PageCacheMisses);
-                None
-            }
+        let page = self.shared.shards[self.shard_index].get(&page_id, self.shared.epoch());
+        if page.is_none() {
+            self.shared.metrics.count(Metric::PageCacheMisses);
         }
+        page
     }
 
     /// Insert a page into the cache by its data. If `Some`, provide the bucket index where the
@@ -585,14 +960,13 @@ impl PageCacheShard {
             .region
             .contains_exclusive(&page_id));
 
-        let mut shard = self.shared.shards[self.shard_index].locked.lock();
-        let cache_entry = shard.cached.get_or_insert(page_id, || {
-            CacheEntry::init(domain, ShardIndex::Shard(self.shard_index), page)
-        });
-
-        Page {
-            inner: cache_entry.page_data.clone(),
-        }
+        self.shared.shards[self.shard_index].insert(
+            domain,
+            self.shard_index,
+            page_id,
+            page,
+            self.shared.epoch(),
+        )
     }
 }
 
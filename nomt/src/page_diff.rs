@@ -31,6 +31,16 @@ impl PageDiff {
         Some(diff)
     }
 
+    /// Create a page diff marking every node of the page as changed, e.g. for a page written to a
+    /// bucket which held a different page before.
+    pub fn full() -> Self {
+        let mut diff = PageDiff::default();
+        for slot_index in 0..NODES_PER_PAGE {
+            diff.set_changed(slot_index);
+        }
+        diff
+    }
+
     /// Note that some 32-byte slot in the page data has changed.
     ///
     /// The acceptable range is 0..NODES_PER_PAGE. Erases the clear bit.
@@ -61,6 +71,24 @@ impl PageDiff {
         self.changed_nodes[1] & CLEAR_BIT == CLEAR_BIT
     }
 
+    /// Fold a diff of a later change to the same page into this one, so that the result describes
+    /// both changes.
+    ///
+    /// A page which was cleared and then filled again is marked as changed entirely.
+    pub fn merge(&mut self, later: &PageDiff) {
+        if later.cleared() {
+            *self = later.clone();
+        } else if self.cleared() {
+            *self = PageDiff::default();
+            for slot_index in 0..NODES_PER_PAGE {
+                self.set_changed(slot_index);
+            }
+        } else {
+            self.changed_nodes[0] |= later.changed_nodes[0];
+            self.changed_nodes[1] |= later.changed_nodes[1];
+        }
+    }
+
     /// Given the page data, collect the nodes that have changed according to this diff.
     /// Panics if this is a cleared page-diff.
     pub fn pack_changed_nodes<'a, 'b: 'a>(
@@ -158,4 +186,23 @@ mod tests {
 
         assert_eq!(iterated_set_bits, set_bits);
     }
+
+    #[test]
+    fn merge() {
+        let mut diff = PageDiff::default();
+        diff.set_changed(1);
+        let mut later = PageDiff::default();
+        later.set_changed(100);
+        diff.merge(&later);
+        assert_eq!(diff.iter_ones().collect::<Vec<_>>(), vec![1, 100]);
+
+        let mut cleared = PageDiff::default();
+        cleared.set_cleared();
+        diff.merge(&cleared);
+        assert!(diff.cleared());
+
+        diff.merge(&later);
+        assert!(!diff.cleared());
+        assert_eq!(diff.count(), NODES_PER_PAGE);
+    }
 }
@@ -1,15 +1,121 @@
-use nomt_core::trie::KeyPath;
+use nomt_core::trie::{KeyPath, Node};
 use std::{
     collections::HashMap,
     io::{Cursor, Read as _},
 };
 
+/// The first four bytes of a delta encoded in the compact format. In the legacy format, these
+/// bytes hold the number of keys to erase, which can never reach this value.
+const COMPACT_MAGIC: [u8; 4] = [0xff; 4];
+
+/// Set in the flags of a compact delta if its body is compressed with zstd.
+const FLAG_COMPRESSED: u8 = 1;
+
+/// Set in the flags of a compact delta if the root prior to the commit follows the flags.
+const FLAG_PRIOR_ROOT: u8 = 2;
+
+/// The zstd compression level of delta bodies.
+const COMPRESSION_LEVEL: i32 = 3;
+
 /// A delta that should be applied to reverse a commit.
 #[derive(Debug)]
 pub struct Delta {
     /// This map contains the prior value for each key that was written by the commit this delta
-    /// reverses. `None` indicates that the key did not exist before the commit.
-    pub(crate) priors: HashMap<KeyPath, Option<Vec<u8>>>,
+    /// reverses.
+    pub(crate) priors: HashMap<KeyPath, Prior>,
+    /// The root of the trie prior to the commit this delta reverses. `None` for deltas written
+    /// before roots were recorded.
+    pub(crate) prior_root: Option<Node>,
+}
+
+/// The value of a key prior to a commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Prior {
+    /// The key did not exist before the commit.
+    Erase,
+    /// The key had the given value before the commit.
+    Value(Vec<u8>),
+    /// The key had a value before the commit, which is stored as a patch against the value written
+    /// by the commit.
+    Patch(Patch),
+}
+
+impl Prior {
+    /// Create the prior of a key, encoded as a patch against the value written by the commit if
+    /// that is smaller.
+    pub fn new(prior: Option<Vec<u8>>, new: Option<&[u8]>) -> Self {
+        match (prior, new) {
+            (None, _) => Prior::Erase,
+            (Some(prior), Some(new)) => {
+                let patch = Patch::diff(&prior, new);
+                if patch.encoded_len() < 4 + prior.len() {
+                    Prior::Patch(patch)
+                } else {
+                    Prior::Value(prior)
+                }
+            }
+            (Some(prior), None) => Prior::Value(prior),
+        }
+    }
+
+    /// Recover the prior value, given the value written by the commit.
+    pub fn resolve(self, new: Option<&[u8]>) -> anyhow::Result<Option<Vec<u8>>> {
+        match self {
+            Prior::Erase => Ok(None),
+            Prior::Value(value) => Ok(Some(value)),
+            Prior::Patch(patch) => match new {
+                Some(new) => patch.apply(new).map(Some),
+                None => anyhow::bail!("patched prior value of a deleted key"),
+            },
+        }
+    }
+
+    /// Whether resolving the prior requires the value written by the commit.
+    pub fn is_patch(&self) -> bool {
+        matches!(self, Prior::Patch(_))
+    }
+}
+
+/// A value expressed as a change of a base value: the prior value is the base value with all but
+/// the first `prefix` and the last `suffix` bytes replaced by `middle`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    prefix: u32,
+    suffix: u32,
+    middle: Vec<u8>,
+}
+
+impl Patch {
+    fn diff(value: &[u8], base: &[u8]) -> Self {
+        let prefix = value.iter().zip(base).take_while(|(a, b)| a == b).count();
+        let suffix = value[prefix..]
+            .iter()
+            .rev()
+            .zip(base[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        Patch {
+            prefix: prefix as u32,
+            suffix: suffix as u32,
+            middle: value[prefix..value.len() - suffix].to_vec(),
+        }
+    }
+
+    fn apply(&self, base: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let (prefix, suffix) = (self.prefix as usize, self.suffix as usize);
+        if prefix + suffix > base.len() {
+            anyhow::bail!("patch does not match the value written by the commit");
+        }
+        let mut value = Vec::with_capacity(prefix + self.middle.len() + suffix);
+        value.extend_from_slice(&base[..prefix]);
+        value.extend_from_slice(&self.middle);
+        value.extend_from_slice(&base[base.len() - suffix..]);
+        Ok(value)
+    }
+
+    fn encoded_len(&self) -> usize {
+        12 + self.middle.len()
+    }
 }
 
 impl Delta {
@@ -17,76 +123,186 @@ impl Delta {
     fn empty() -> Self {
         Self {
             priors: HashMap::new(),
+            prior_root: None,
         }
     }
 
     /// Encode the delta into a buffer.
-    ///
-    /// Returns the number of bytes written.
     pub fn encode(&self) -> Vec<u8> {
         // The serialization format has the following layout.
         //
-        // The keys are split into two groups and written as separate arrays. Those groups are:
+        // The header consists of `COMPACT_MAGIC`, a flags byte, the prior root if any and, if the
+        // body is compressed, the length of the uncompressed body encoded as a u32.
         //
-        // 1. erase: The keys that did not exist before the commit.
-        // 2. reinstateThe keys that had prior values.
+        // The body starts with a table of the distinct prior values stored in full, so that
+        // identical values within the delta are stored once. Values are not shared with other
+        // deltas, so that every delta can be discarded on its own. The table is written as the
+        // number of values followed by the values, each prefixed with its length.
         //
-        // The keys that did not exist are written first. The keys that had prior values are
-        // written second.
+        // Then, the number of keys is written, followed by every key with a tag byte and the data
+        // of its prior:
         //
-        // For each kind of key, we first write out the length of the array encoded as a u32.
-        // This is followed by the keys themselves, written contiguously in little-endian order.
+        // 0. erase: nothing.
+        // 1. reinstate: the index of the value in the table.
+        // 2. patch: the prefix and suffix lengths, followed by the length-prefixed middle.
         //
-        // The keys are written as 32-byte big-endian values.
-
-        // Sort the keys into two groups.
-        let mut to_erase = Vec::with_capacity(self.priors.len());
-        let mut to_reinstate = Vec::with_capacity(self.priors.len());
-        for (key, value) in self.priors.iter() {
-            match value {
-                None => to_erase.push(key),
-                Some(value) => to_reinstate.push((key, value)),
+        // All integers are encoded as little-endian u32s. The keys are written as 32-byte
+        // big-endian values.
+        let mut table: Vec<&[u8]> = Vec::new();
+        let mut table_index: HashMap<&[u8], u32> = HashMap::new();
+        let mut entries = Vec::with_capacity(self.priors.len() * 37);
+        for (key, prior) in self.priors.iter() {
+            entries.extend_from_slice(&key[..]);
+            match prior {
+                Prior::Erase => entries.push(0),
+                Prior::Value(value) => {
+                    let index = *table_index.entry(&value[..]).or_insert_with(|| {
+                        table.push(&value[..]);
+                        table.len() as u32 - 1
+                    });
+                    entries.push(1);
+                    entries.extend_from_slice(&index.to_le_bytes());
+                }
+                Prior::Patch(patch) => {
+                    entries.push(2);
+                    entries.extend_from_slice(&patch.prefix.to_le_bytes());
+                    entries.extend_from_slice(&patch.suffix.to_le_bytes());
+                    entries.extend_from_slice(&(patch.middle.len() as u32).to_le_bytes());
+                    entries.extend_from_slice(&patch.middle);
+                }
             }
         }
 
-        let to_erase_len = to_erase.len() as u32;
-        let mut buf = Vec::with_capacity(4 + 32 * to_erase.len());
-        buf.extend_from_slice(&to_erase_len.to_le_bytes());
-        for key in to_erase {
-            buf.extend_from_slice(&key[..]);
+        let mut body = Vec::with_capacity(8 + entries.len());
+        body.extend_from_slice(&(table.len() as u32).to_le_bytes());
+        for value in table {
+            body.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            body.extend_from_slice(value);
         }
+        body.extend_from_slice(&(self.priors.len() as u32).to_le_bytes());
+        body.extend_from_slice(&entries);
 
-        let to_reinstate_len = to_reinstate.len() as u32;
-        buf.extend_from_slice(&to_reinstate_len.to_le_bytes());
-        for (key, value) in to_reinstate {
-            buf.extend_from_slice(&key[..]);
-            let value_len = value.len() as u32;
-            buf.extend_from_slice(&value_len.to_le_bytes());
-            buf.extend_from_slice(value);
+        let mut buf = COMPACT_MAGIC.to_vec();
+        let root_flag = if self.prior_root.is_some() {
+            FLAG_PRIOR_ROOT
+        } else {
+            0
+        };
+        let prior_root = self.prior_root.as_ref().map_or(&[][..], |root| &root[..]);
+        match zstd::bulk::compress(&body, COMPRESSION_LEVEL) {
+            Ok(compressed) if compressed.len() + 4 < body.len() => {
+                buf.push(FLAG_COMPRESSED | root_flag);
+                buf.extend_from_slice(prior_root);
+                buf.extend_from_slice(&(body.len() as u32).to_le_bytes());
+                buf.extend_from_slice(&compressed);
+            }
+            _ => {
+                buf.push(root_flag);
+                buf.extend_from_slice(prior_root);
+                buf.extend_from_slice(&body);
+            }
         }
-
         buf
     }
 
     /// Decodes the delta from a buffer.
+    ///
+    /// Deltas written in the legacy format, which stores every prior value in full, are accepted
+    /// as well.
     pub fn decode(reader: &mut Cursor<impl AsRef<[u8]>>) -> anyhow::Result<Self> {
-        let mut priors = HashMap::new();
-
-        // Read the number of keys to erase.
         let mut buf = [0; 4];
         reader.read_exact(&mut buf)?;
-        let to_erase_len = u32::from_le_bytes(buf);
+        if buf != COMPACT_MAGIC {
+            return Self::decode_legacy(u32::from_le_bytes(buf), reader);
+        }
+
+        let mut flags = [0; 1];
+        reader.read_exact(&mut flags)?;
+        let prior_root = if flags[0] & FLAG_PRIOR_ROOT != 0 {
+            let mut root = [0; 32];
+            reader.read_exact(&mut root)?;
+            Some(root)
+        } else {
+            None
+        };
+        let mut body = Vec::new();
+        if flags[0] & FLAG_COMPRESSED != 0 {
+            reader.read_exact(&mut buf)?;
+            let body_len = u32::from_le_bytes(buf) as usize;
+            let mut compressed = Vec::new();
+            reader.read_to_end(&mut compressed)?;
+            body = zstd::bulk::decompress(&compressed, body_len)?;
+        } else {
+            reader.read_to_end(&mut body)?;
+        }
+        let mut reader = Cursor::new(body);
+
+        // Read the table of values.
+        reader.read_exact(&mut buf)?;
+        let table_len = u32::from_le_bytes(buf);
+        let mut table = Vec::new();
+        for _ in 0..table_len {
+            table.push(read_bytes(&mut reader)?);
+        }
+
+        // Read the keys along with their priors.
+        reader.read_exact(&mut buf)?;
+        let priors_len = u32::from_le_bytes(buf);
+        let mut priors = HashMap::new();
+        for _ in 0..priors_len {
+            let mut key_path = [0; 32];
+            reader.read_exact(&mut key_path)?;
+            let mut tag = [0; 1];
+            reader.read_exact(&mut tag)?;
+            let prior = match tag[0] {
+                0 => Prior::Erase,
+                1 => {
+                    reader.read_exact(&mut buf)?;
+                    match table.get(u32::from_le_bytes(buf) as usize) {
+                        Some(value) => Prior::Value(value.clone()),
+                        None => anyhow::bail!("value index out of bounds: {:?}", key_path),
+                    }
+                }
+                2 => {
+                    reader.read_exact(&mut buf)?;
+                    let prefix = u32::from_le_bytes(buf);
+                    reader.read_exact(&mut buf)?;
+                    let suffix = u32::from_le_bytes(buf);
+                    let middle = read_bytes(&mut reader)?;
+                    Prior::Patch(Patch {
+                        prefix,
+                        suffix,
+                        middle,
+                    })
+                }
+                tag => anyhow::bail!("unknown prior tag {}: {:?}", tag, key_path),
+            };
+            let preempted = priors.insert(key_path, prior).is_some();
+            if preempted {
+                anyhow::bail!("duplicate key path: {:?}", key_path);
+            }
+        }
+        Ok(Delta { priors, prior_root })
+    }
+
+    fn decode_legacy(
+        to_erase_len: u32,
+        reader: &mut Cursor<impl AsRef<[u8]>>,
+    ) -> anyhow::Result<Self> {
+        let mut priors = HashMap::new();
+
         // Read the keys to erase.
         for _ in 0..to_erase_len {
             let mut key_path = [0; 32];
             reader.read_exact(&mut key_path)?;
-            let preemted = priors.insert(key_path, None).is_some();
+            let preemted = priors.insert(key_path, Prior::Erase).is_some();
             if preemted {
                 anyhow::bail!("duplicate key path (erase): {:?}", key_path);
             }
         }
 
         // Read the number of keys to reinstate.
+        let mut buf = [0; 4];
         reader.read_exact(&mut buf)?;
         let to_reinsate_len = u32::from_le_bytes(buf);
         // Read the keys to reinstate along with their values.
@@ -95,20 +311,27 @@ impl Delta {
             let mut key_path = [0; 32];
             reader.read_exact(&mut key_path)?;
             // Read the value.
-            let mut value = Vec::new();
-            reader.read_exact(&mut buf)?;
-            let value_len = u32::from_le_bytes(buf);
-            value.resize(value_len as usize, 0);
-            reader.read_exact(&mut value)?;
-            let preempted = priors.insert(key_path, Some(value)).is_some();
+            let value = read_bytes(reader)?;
+            let preempted = priors.insert(key_path, Prior::Value(value)).is_some();
             if preempted {
                 anyhow::bail!("duplicate key path (reinstate): {:?}", key_path);
             }
         }
-        Ok(Delta { priors })
+        Ok(Delta {
+            priors,
+            prior_root: None,
+        })
     }
 }
 
+fn read_bytes(reader: &mut Cursor<impl AsRef<[u8]>>) -> anyhow::Result<Vec<u8>> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    let mut value = vec![0; u32::from_le_bytes(buf) as usize];
+    reader.read_exact(&mut value)?;
+    Ok(value)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -116,14 +339,33 @@ mod tests {
     #[test]
     fn delta_roundtrip() {
         let mut delta = Delta::empty();
-        delta.priors.insert([1; 32], Some(b"value1".to_vec()));
-        delta.priors.insert([2; 32], None);
-        delta.priors.insert([3; 32], Some(b"value3".to_vec()));
+        delta
+            .priors
+            .insert([1; 32], Prior::Value(b"value1".to_vec()));
+        delta.priors.insert([2; 32], Prior::Erase);
+        delta
+            .priors
+            .insert([3; 32], Prior::Value(b"value3".to_vec()));
+        delta.priors.insert(
+            [4; 32],
+            Prior::new(Some(vec![5; 1000]), Some(&[6; 1000][..])),
+        );
 
         let mut buf = delta.encode();
         let mut cursor = Cursor::new(&mut buf);
         let delta2 = Delta::decode(&mut cursor).unwrap();
         assert_eq!(delta.priors, delta2.priors);
+        assert_eq!(delta2.prior_root, None);
+
+        // The prior root is kept alongside compressed and uncompressed bodies alike.
+        delta.prior_root = Some([9; 32]);
+        let delta3 = Delta::decode(&mut Cursor::new(delta.encode())).unwrap();
+        assert_eq!(delta.priors, delta3.priors);
+        assert_eq!(delta3.prior_root, Some([9; 32]));
+        let mut small = Delta::empty();
+        small.prior_root = Some([9; 32]);
+        let small2 = Delta::decode(&mut Cursor::new(small.encode())).unwrap();
+        assert_eq!(small2.prior_root, Some([9; 32]));
     }
 
     #[test]
@@ -134,4 +376,54 @@ mod tests {
         let delta2 = Delta::decode(&mut cursor).unwrap();
         assert_eq!(delta.priors, delta2.priors);
     }
+
+    #[test]
+    fn delta_decode_legacy() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&1u32.to_le_bytes());
+        buf.extend_from_slice(&[2; 32]);
+        buf.extend_from_slice(&1u32.to_le_bytes());
+        buf.extend_from_slice(&[1; 32]);
+        buf.extend_from_slice(&6u32.to_le_bytes());
+        buf.extend_from_slice(b"value1");
+
+        let delta = Delta::decode(&mut Cursor::new(buf)).unwrap();
+        assert_eq!(delta.priors.len(), 2);
+        assert_eq!(delta.priors[&[2; 32]], Prior::Erase);
+        assert_eq!(delta.priors[&[1; 32]], Prior::Value(b"value1".to_vec()));
+    }
+
+    #[test]
+    fn delta_compacts_similar_and_identical_values() {
+        let mut delta = Delta::empty();
+        for i in 0..100u8 {
+            let mut prior = vec![0; 1000];
+            let mut new = prior.clone();
+            new[500] = i;
+            prior[500] = i + 1;
+            delta
+                .priors
+                .insert([i; 32], Prior::new(Some(prior), Some(&new)));
+            delta
+                .priors
+                .insert([i + 100; 32], Prior::Value(vec![7; 1000]));
+        }
+        assert!(delta.priors[&[0; 32]].is_patch());
+        assert!(delta.encode().len() < 10_000);
+    }
+
+    #[test]
+    fn patch_roundtrip() {
+        let cases: &[(&[u8], &[u8])] = &[
+            (b"abcdef", b"abXYef"),
+            (b"abc", b"abcabc"),
+            (b"abcabc", b"abc"),
+            (b"", b"abc"),
+            (b"abc", b""),
+            (b"aaaa", b"aaaa"),
+        ];
+        for (value, base) in cases {
+            assert_eq!(Patch::diff(value, base).apply(base).unwrap(), *value);
+        }
+    }
 }
@@ -1,7 +1,11 @@
 //! This module implements the rollback log.
 //!
 //! The rollback log maintains a list of reverse deltas. A reverse delta contains the prior value
-//! for every key that was modified or deleted.
+//! for every key that was modified or deleted. Prior values which are similar to the values written
+//! by the commit are stored as patches against them, which are resolved against the current state
+//! of the store when the log is truncated. Identical prior values are stored once per delta, and
+//! delta bodies are compressed with zstd. Deltas never refer to each other, so that the oldest ones
+//! can be discarded and the newest ones truncated without rewriting the others.
 //!
 //! The deltas are stored in an in-memory ring buffer. When the buffer size reaches the limit, the
 //! oldest deltas are discarded to make space for new ones.
@@ -17,11 +21,11 @@ use std::{
 };
 
 use dashmap::DashMap;
-use nomt_core::trie::KeyPath;
+use nomt_core::trie::{KeyPath, Node};
 use parking_lot::{Condvar, Mutex};
 use threadpool::ThreadPool;
 
-use self::delta::Delta;
+use self::delta::{Delta, Prior};
 use crate::{
     seglog::{self, RecordId, SegmentedLog},
     KeyReadWrite,
@@ -36,8 +40,8 @@ const MAX_SEGMENT_SIZE: u64 = 64 * 1024 * 1024; // 64 MiB
 struct InMemory {
     /// The log of deltas that we have accumulated so far.
     ///
-    /// The items are pushed onto the back and popped from the front. When the log reaches
-    /// [`Shared::max_rollback_log_len`], the oldest delta is discarded.
+    /// The items are pushed onto the back and popped from the front. When the log exceeds
+    /// [`Shared::max_rollback_log_len`] at a sync, the oldest deltas are discarded.
     ///
     /// The deltas are stored in-memory even after they are dumped on disk. Upon restart, the deltas
     /// are re-read from disk and stored here.
@@ -74,6 +78,24 @@ impl InMemory {
         self.log.pop_back()
     }
 
+    /// Discard the oldest deltas until at most `keep` are left. Returns the new start of the live
+    /// range of the log, which is nil if no deltas are left, or `None` if nothing was discarded.
+    fn discard_oldest(&mut self, keep: usize) -> Option<RecordId> {
+        let mut new_start_live = None;
+        while self.log.len() > keep {
+            // UNWRAP: the log is not empty.
+            let (record_id, _) = self.log.pop_front().unwrap();
+            new_start_live = Some(record_id.next());
+        }
+        new_start_live.map(|start| {
+            if self.log.is_empty() {
+                RecordId::nil()
+            } else {
+                start
+            }
+        })
+    }
+
     // Returns the total number of deltas, including the staged one.
     fn total_len(&self) -> usize {
         self.log.len()
@@ -132,14 +154,15 @@ impl Rollback {
     /// Saves the delta into the log.
     ///
     /// This function accepts the final list of operations that should be performed sorted by the
-    /// key paths in ascending order.
+    /// key paths in ascending order, along with the root of the trie prior to them.
     pub fn commit(
         &self,
         store: impl LoadValue,
         actuals: &[(KeyPath, KeyReadWrite)],
         delta: ReverseDeltaBuilder,
+        prior_root: Node,
     ) -> anyhow::Result<()> {
-        let delta = delta.finalize(store, actuals);
+        let delta = delta.finalize(store, actuals, prior_root);
         let delta_bytes = delta.encode();
 
         let mut in_memory = self.shared.in_memory.lock();
@@ -150,14 +173,29 @@ impl Rollback {
         Ok(())
     }
 
+    /// Returns the roots prior to the commits reversed by the deltas, from the most recent delta
+    /// to the oldest one. The `n`th root is the one reached by truncating `n` deltas. `None` for
+    /// deltas which did not record their root.
+    pub fn prior_roots(&self) -> Vec<Option<Node>> {
+        let in_memory = self.shared.in_memory.lock();
+        in_memory
+            .log
+            .iter()
+            .rev()
+            .map(|(_, delta)| delta.prior_root)
+            .collect()
+    }
+
     /// Truncates the rollback log by removing the last `n` deltas.
     ///
     /// This function returns the keys and values that we should apply to the database to restore
-    /// the state as it was before the last `n` deltas were applied.
+    /// the state as it was before the last `n` deltas were applied. `store` must reflect the state
+    /// after the last delta, as it is used to resolve prior values stored as patches.
     ///
-    /// This function is destructive and consumes the rollback log.
+    /// This function is destructive and consumes the rollback log, unless it fails.
     pub fn truncate(
         &self,
+        store: impl LoadValue,
         mut n: usize,
     ) -> anyhow::Result<Option<BTreeMap<KeyPath, Option<Vec<u8>>>>> {
         assert!(n > 0);
@@ -166,19 +204,30 @@ impl Rollback {
             return Ok(None);
         }
 
-        let mut traceback = BTreeMap::new();
+        // Go through the deltas from the most recent one and add their original values to the
+        // traceback, potentially overwriting some of the values that were added in previous
+        // iterations. Before that, the traceback holds the value each key had after the commit
+        // reversed by the delta, unless the key was not written by any of the later commits.
+        let mut traceback: BTreeMap<KeyPath, Option<Vec<u8>>> = BTreeMap::new();
+        for (_, delta) in in_memory.log.iter().rev().take(n) {
+            for (key, prior) in &delta.priors {
+                let new = if !prior.is_patch() {
+                    None
+                } else if let Some(value) = traceback.get(key) {
+                    value.clone()
+                } else {
+                    store.load_value(*key)?
+                };
+                traceback.insert(*key, prior.clone().resolve(new.as_deref())?);
+            }
+        }
+
         let mut earliest_record_id = None;
         while n > 0 {
-            // Pop the most recent delta from the log and add its original values to the traceback,
-            // potentially overwriting some of the values that were added in previous iterations.
-            //
             // UNWRAP: we checked above that `n` is greater or equal to the total number of deltas
             //         and `n` is strictly decreasing.
-            let (record_id, delta) = in_memory.pop_back().unwrap();
+            let (record_id, _) = in_memory.pop_back().unwrap();
             earliest_record_id = Some(record_id);
-            for (key, value) in delta.priors {
-                traceback.insert(key, value);
-            }
             n -= 1;
         }
         // UNWRAP: we checked above that `n` is greater than 0 and that means that there is at
@@ -201,6 +250,35 @@ impl Rollback {
         Ok(Some(traceback))
     }
 
+    /// Discard all but the `keep` most recent deltas and delete them from disk.
+    ///
+    /// `record_live_range` must durably record the new live range `(start_live, end_live)` of the
+    /// log in the manifest. It is called before anything is deleted, so that a crash cannot leave
+    /// the manifest referencing deleted deltas. Must not run concurrently with a sync.
+    pub fn prune(
+        &self,
+        keep: usize,
+        record_live_range: impl FnOnce(u64, u64) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let mut in_memory = self.shared.in_memory.lock();
+        assert!(in_memory.pending_truncate.is_none());
+        if in_memory.total_len() <= keep {
+            return Ok(());
+        }
+
+        if keep == 0 {
+            record_live_range(0, 0)?;
+        } else {
+            let (_, end_live) = self.shared.seglog.lock().live_range();
+            let (last_discarded, _) = &in_memory.log[in_memory.total_len() - keep - 1];
+            record_live_range(last_discarded.next().0, end_live.0)?;
+        }
+
+        // UNWRAP: the log is longer than `keep`, so the oldest delta is discarded.
+        let new_start_live = in_memory.discard_oldest(keep).unwrap();
+        self.writeout_end(Some(new_start_live.0), None)
+    }
+
     /// Returns a controller for the sync process.
     pub fn sync(&self) -> SyncController {
         SyncController::new(self.clone())
@@ -223,18 +301,19 @@ impl Rollback {
             };
         }
 
-        let prune_to_new_start_live = if in_memory.total_len() > self.shared.max_rollback_log_len {
-            Some(in_memory.pop_back().unwrap().0.next().0)
-        } else {
-            None
-        };
+        let prune_to_new_start_live = in_memory.discard_oldest(self.shared.max_rollback_log_len);
 
-        let (rollback_start_live, rollback_end_live) = seglog.live_range();
+        // The manifest must not reference the deltas pruned after it is written.
+        let (rollback_start_live, rollback_end_live) = match prune_to_new_start_live {
+            Some(new_start_live) if new_start_live.is_nil() => (RecordId::nil(), RecordId::nil()),
+            Some(new_start_live) => (new_start_live, seglog.live_range().1),
+            None => seglog.live_range(),
+        };
 
         WriteoutData {
             rollback_start_live: rollback_start_live.0,
             rollback_end_live: rollback_end_live.0,
-            prune_to_new_start_live,
+            prune_to_new_start_live: prune_to_new_start_live.map(|id| id.0),
             prune_to_new_end_live: None,
         }
     }
@@ -389,7 +468,12 @@ impl ReverseDeltaBuilder {
     /// Finalize the delta.
     ///
     /// This function is expected to be called before the store is modified.
-    fn finalize(self, store: impl LoadValue, actuals: &[(KeyPath, KeyReadWrite)]) -> Delta {
+    fn finalize(
+        self,
+        store: impl LoadValue,
+        actuals: &[(KeyPath, KeyReadWrite)],
+        prior_root: Node,
+    ) -> Delta {
         // Wait for all tentative writes issued so far to complete.
         //
         // NB: This doesn't take into account other users of `tp`. If there are any, we will be
@@ -433,8 +517,21 @@ impl ReverseDeltaBuilder {
         // values to be preserved.
         self.tp.join();
 
+        let final_priors = Arc::into_inner(final_priors).unwrap();
+        let priors = actuals
+            .iter()
+            .filter_map(|(path, read_write)| {
+                let (path, prior) = final_priors.remove(path)?;
+                let new = match read_write {
+                    KeyReadWrite::Write(new) | KeyReadWrite::ReadThenWrite(_, new) => new,
+                    KeyReadWrite::Read(_) => &None,
+                };
+                Some((path, Prior::new(prior, new.as_deref())))
+            })
+            .collect();
         Delta {
-            priors: Arc::into_inner(final_priors).unwrap().into_iter().collect(),
+            priors,
+            prior_root: Some(prior_root),
         }
     }
 }
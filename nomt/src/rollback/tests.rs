@@ -2,6 +2,7 @@ use std::{collections::BTreeSet, fs::OpenOptions, sync::Arc};
 
 use super::{BTreeMap, KeyPath, KeyReadWrite, LoadValue, Rollback};
 use hex_literal::hex;
+use nomt_core::trie::TERMINATOR;
 
 const MAX_ROLLBACK_LOG_LEN: u32 = 100;
 const ROLLBACK_TP_SIZE: usize = 4;
@@ -106,11 +107,12 @@ fn truncate_works() {
                 ),
             ],
             builder,
+            TERMINATOR,
         )
         .unwrap();
 
     // We want to see the old values for all the keys that have been changed during the commit.
-    let traceback = rollback.truncate(1).unwrap().unwrap();
+    let traceback = rollback.truncate(store, 1).unwrap().unwrap();
     assert_eq!(traceback.len(), 2);
     assert_eq!(
         traceback
@@ -182,11 +184,12 @@ fn without_tentative_preserve_prior() {
                 ),
             ],
             builder,
+            TERMINATOR,
         )
         .unwrap();
 
     // We want to see the old values for all the keys that have been changed during the commit.
-    let traceback = rollback.truncate(1).unwrap().unwrap();
+    let traceback = rollback.truncate(store, 1).unwrap().unwrap();
     assert_eq!(traceback.len(), 2);
     assert_eq!(
         traceback
@@ -238,7 +241,7 @@ fn delta_builder_doesnt_load_read_then_write_priors() {
     let builder = rollback.delta_builder();
     rollback
         .commit(
-            store,
+            store.clone(),
             &[(
                 key_1,
                 KeyReadWrite::ReadThenWrite(
@@ -247,15 +250,79 @@ fn delta_builder_doesnt_load_read_then_write_priors() {
                 ),
             )],
             builder,
+            TERMINATOR,
         )
         // This will panic if the delta builder attempts to load from store the prior value for
         // key_1.
         .unwrap();
 
     // We expect that the traceback will contain the specified prior value for key_1.
-    let traceback = rollback.truncate(1).unwrap().unwrap();
+    let traceback = rollback.truncate(store, 1).unwrap().unwrap();
     assert_eq!(
         traceback.get(&key_1).unwrap(),
         &Some(b"prior_value".to_vec())
     );
 }
+
+#[test]
+fn truncate_resolves_patches() {
+    // Priors similar to the written values are stored as patches. Resolving them requires the
+    // state after the last delta and, for keys written multiple times, the later deltas.
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_dir_path = temp_dir.path().join("db");
+    std::fs::create_dir_all(&db_dir_path).unwrap();
+    let db_dir_fd = OpenOptions::new()
+        .read(true)
+        .open(db_dir_path.clone())
+        .unwrap();
+
+    let key_1 = [1; 32];
+    let key_2 = [2; 32];
+    let value = |tag: u8| {
+        let mut value = vec![0; 1000];
+        value[500] = tag;
+        value
+    };
+
+    let rollback = Rollback::read(
+        MAX_ROLLBACK_LOG_LEN,
+        ROLLBACK_TP_SIZE,
+        db_dir_path,
+        Arc::new(db_dir_fd),
+        0,
+        0,
+    )
+    .unwrap();
+
+    let mut store = MockStore::new();
+    store.insert(key_1, Some(value(1)));
+    store.insert(key_2, Some(value(1)));
+    rollback
+        .commit(
+            store.clone(),
+            &[
+                (key_1, KeyReadWrite::Write(Some(value(2)))),
+                (key_2, KeyReadWrite::Write(Some(value(2)))),
+            ],
+            rollback.delta_builder(),
+            TERMINATOR,
+        )
+        .unwrap();
+
+    store.insert(key_1, Some(value(2)));
+    store.insert(key_2, Some(value(2)));
+    rollback
+        .commit(
+            store.clone(),
+            &[(key_1, KeyReadWrite::Write(Some(value(3))))],
+            rollback.delta_builder(),
+            TERMINATOR,
+        )
+        .unwrap();
+
+    store.insert(key_1, Some(value(3)));
+    let traceback = rollback.truncate(store, 2).unwrap().unwrap();
+    assert_eq!(traceback.len(), 2);
+    assert_eq!(traceback[&key_1], Some(value(1)));
+    assert_eq!(traceback[&key_2], Some(value(1)));
+}
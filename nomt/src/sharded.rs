@@ -0,0 +1,290 @@
+//! Splitting the key space across multiple NOMT instances.
+//!
+//! Each shard is a fully independent [`Nomt`] instance, potentially stored on a different disk,
+//! which holds all keys starting with a particular prefix. The roots of the shards are combined
+//! into a single root by hashing them pairwise as internal nodes. This is not the root a single
+//! instance holding all keys would have, see [`ShardedNomt::root`].
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use nomt_core::trie::{InternalData, NodeHasherExt, TERMINATOR};
+
+use crate::{
+    CommitToken, HashAlgorithm, KeyPath, KeyReadWrite, Node, Nomt, Options, Session, Value,
+};
+
+/// A set of [`Nomt`] instances, each responsible for a disjoint part of the key space.
+///
+/// Keys are assigned to shards by their leading bits, so the number of shards must be a power of
+/// two no greater than 256.
+///
+/// Commits are coordinated through commit tokens: every shard is committed with the same token,
+/// derived from a sequence number maintained by the sharded database. A commit only starts if all
+/// shards carry the token of the last commit. The shards are committed independently and
+/// compensated on failure, not through a two-phase commit: if a commit fails on some of the
+/// shards, the shards it reached are rolled back to that token, while a shard whose sync failed
+/// refuses every commit until the sharded database is reopened, see [`Nomt::check_snapshot`]. On
+/// open, the shards ahead of the others, which were reached by a commit interrupted by a crash,
+/// are rolled back likewise. Rolling back requires [`Options::rollback`] on every shard; without
+/// it, the shards stay inconsistent and committing and opening fail.
+pub struct ShardedNomt<T: HashAlgorithm> {
+    shards: Vec<Nomt<T>>,
+    shard_bits: u32,
+    commit_seqn: AtomicU64,
+}
+
+impl<T: HashAlgorithm> ShardedNomt<T> {
+    /// Open the sharded database, with one set of options per shard.
+    ///
+    /// The order of the options is significant: the shard at index `i` holds the keys whose
+    /// leading bits equal `i`.
+    pub fn open(options: Vec<Options>) -> anyhow::Result<Self> {
+        let n = options.len();
+        if !n.is_power_of_two() || n > 256 {
+            anyhow::bail!("number of shards must be a power of two between 1 and 256, got {n}");
+        }
+
+        let shards = options
+            .into_iter()
+            .map(Nomt::open)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let nomt = Self {
+            shards,
+            shard_bits: n.trailing_zeros(),
+            commit_seqn: AtomicU64::new(0),
+        };
+        let token = nomt.recover()?;
+        nomt.commit_seqn
+            .store(token.map_or(0, seqn_from_token), Ordering::Relaxed);
+        Ok(nomt)
+    }
+
+    /// Returns the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the roots of the individual shards.
+    pub fn shard_roots(&self) -> Vec<Node> {
+        self.shards.iter().map(|shard| shard.root()).collect()
+    }
+
+    /// Returns the combined root of all shards.
+    ///
+    /// The shard roots are hashed pairwise, bottom-up, as if they were the nodes at the depth of
+    /// the shard prefix. This is not the root a single instance holding all keys would have: the
+    /// root of a shard holding several keys also covers the shard prefix, which all its keys
+    /// share, and the leaf of a shard holding a single key isn't moved up past empty neighbouring
+    /// shards.
+    pub fn root(&self) -> Node {
+        combine_roots::<T>(self.shard_roots())
+    }
+
+    /// Creates a new [`ShardedSession`]. See [`Nomt::begin_session`].
+    pub fn begin_session(&self) -> ShardedSession {
+        ShardedSession {
+            sessions: self
+                .shards
+                .iter()
+                .map(|shard| shard.begin_session())
+                .collect(),
+            shard_bits: self.shard_bits,
+        }
+    }
+
+    /// Commit the session on all shards and return the new combined root.
+    ///
+    /// The actuals must be sorted by the key paths in ascending order and the key paths must be
+    /// unique. Shards are committed concurrently.
+    ///
+    /// Fails if the shards are inconsistent, as a previous commit failed to be rolled back, or if
+    /// a sync of a shard failed since the sharded database was opened. If committing fails on
+    /// some of the shards, the shards it reached are rolled back before returning the error. The commit sequence number advances either way, so the token of a
+    /// failed commit is never reused.
+    pub fn commit(
+        &self,
+        session: ShardedSession,
+        actuals: Vec<(KeyPath, KeyReadWrite)>,
+    ) -> anyhow::Result<Node>
+    where
+        T: Sync,
+    {
+        let mut shard_actuals = (0..self.shards.len())
+            .map(|_| Vec::new())
+            .collect::<Vec<_>>();
+        for (key_path, read_write) in actuals {
+            shard_actuals[shard_index(self.shard_bits, &key_path)].push((key_path, read_write));
+        }
+
+        // Prepare: a commit on top of shards which don't agree on the last commit could never be
+        // rolled back to a consistent state, and a shard whose sync failed is ahead of its files
+        // until it is reopened.
+        for shard in &self.shards {
+            shard.check_snapshot()?;
+        }
+        let last = self.shards[0].last_commit_token();
+        if self
+            .shards
+            .iter()
+            .any(|shard| shard.last_commit_token() != last)
+        {
+            anyhow::bail!("shards are inconsistent: the last commit did not reach all shards");
+        }
+
+        let seqn = self.commit_seqn.fetch_add(1, Ordering::Relaxed) + 1;
+        let token = token_from_seqn(seqn);
+
+        let results = std::thread::scope(|scope| {
+            let handles = self
+                .shards
+                .iter()
+                .zip(session.sessions)
+                .zip(shard_actuals)
+                .map(|((shard, mut session), actuals)| {
+                    session.set_commit_token(token);
+                    scope.spawn(move || shard.commit(session, actuals))
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                // UNWRAP: propagate panics of the shard commits.
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        if let Some(err) = results.into_iter().find_map(Result::err) {
+            return Err(match self.recover() {
+                Ok(_) => err,
+                Err(recovery_err) => err.context(recovery_err),
+            });
+        }
+        Ok(self.root())
+    }
+
+    /// Roll the shards reached by a commit which didn't reach all of them back to the last commit
+    /// which did, returning its token.
+    ///
+    /// Commits don't start while the shards are inconsistent, so the shards are at most one
+    /// commit apart and the last commit which reached all shards is the one with the lowest token.
+    fn recover(&self) -> anyhow::Result<Option<CommitToken>> {
+        let tokens = self
+            .shards
+            .iter()
+            .map(|shard| shard.last_commit_token())
+            .collect::<Vec<_>>();
+        // UNWRAP: there is at least one shard.
+        let common = *tokens
+            .iter()
+            .min_by_key(|token| token.map(seqn_from_token))
+            .unwrap();
+        for (shard, token) in self.shards.iter().zip(tokens) {
+            if token != common {
+                shard.rollback_with_token(1, common).map_err(|e| {
+                    anyhow::anyhow!(
+                        "shards are inconsistent: rolling back a commit which did not reach all \
+                         shards failed: {e}"
+                    )
+                })?;
+            }
+        }
+        Ok(common)
+    }
+}
+
+/// A session spanning all shards of a [`ShardedNomt`].
+pub struct ShardedSession {
+    sessions: Vec<Session>,
+    shard_bits: u32,
+}
+
+impl ShardedSession {
+    /// See [`Session::warm_up`].
+    pub fn warm_up(&self, path: KeyPath) {
+        self.sessions[shard_index(self.shard_bits, &path)].warm_up(path);
+    }
+
+    /// See [`Session::read`].
+    pub fn read(&self, path: KeyPath) -> anyhow::Result<Option<Value>> {
+        self.sessions[shard_index(self.shard_bits, &path)].read(path)
+    }
+}
+
+fn shard_index(shard_bits: u32, key_path: &KeyPath) -> usize {
+    if shard_bits == 0 {
+        0
+    } else {
+        (key_path[0] >> (8 - shard_bits)) as usize
+    }
+}
+
+fn token_from_seqn(seqn: u64) -> CommitToken {
+    let mut token = [0; 32];
+    token[..8].copy_from_slice(&seqn.to_le_bytes());
+    token
+}
+
+fn seqn_from_token(token: CommitToken) -> u64 {
+    // UNWRAP: slice is exactly 8 bytes.
+    u64::from_le_bytes(token[..8].try_into().unwrap())
+}
+
+/// Combine the shard roots into a single root by hashing them pairwise, bottom-up. A pair of
+/// empty subtrees yields an empty subtree.
+fn combine_roots<H: HashAlgorithm>(mut roots: Vec<Node>) -> Node {
+    while roots.len() > 1 {
+        roots = roots
+            .chunks(2)
+            .map(|pair| {
+                if pair[0] == TERMINATOR && pair[1] == TERMINATOR {
+                    TERMINATOR
+                } else {
+                    H::hash_internal(&InternalData {
+                        left: pair[0],
+                        right: pair[1],
+                    })
+                }
+            })
+            .collect();
+    }
+    roots[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{combine_roots, shard_index};
+    use crate::Blake3Hasher;
+    use nomt_core::trie::{InternalData, NodeHasherExt, TERMINATOR};
+
+    #[test]
+    fn shard_index_uses_leading_bits() {
+        let mut key = [0; 32];
+        key[0] = 0b1011_0000;
+        assert_eq!(shard_index(0, &key), 0);
+        assert_eq!(shard_index(1, &key), 1);
+        assert_eq!(shard_index(2, &key), 2);
+        assert_eq!(shard_index(4, &key), 11);
+        assert_eq!(shard_index(8, &key), 176);
+    }
+
+    #[test]
+    fn combine_roots_pairwise() {
+        let a = [1; 32];
+        let b = [2; 32];
+        assert_eq!(combine_roots::<Blake3Hasher>(vec![a]), a);
+        assert_eq!(
+            combine_roots::<Blake3Hasher>(vec![TERMINATOR; 4]),
+            TERMINATOR
+        );
+
+        let left = Blake3Hasher::hash_internal(&InternalData { left: a, right: b });
+        assert_eq!(
+            combine_roots::<Blake3Hasher>(vec![a, b, TERMINATOR, TERMINATOR]),
+            Blake3Hasher::hash_internal(&InternalData {
+                left,
+                right: TERMINATOR
+            })
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Proofs that one root is the result of applying a changeset to another.
+//!
+//! A [`StateDeltaProof`] carries the path proofs of all keys written by a commit, checked against
+//! the root prior to the commit, along with the written value hashes. Replaying the writes on top
+//! of the path proofs yields the root after the commit, so a light client or a bridge which trusts
+//! the prior root can follow the transition without trusting the node which produced it.
+
+use nomt_core::{
+    proof::{verify_update, PathUpdate, VerifiedPathProof},
+    trie::{KeyPath, Node, NodeHasher, ValueHash},
+};
+
+use crate::{
+    witness_chunks::{decode_path, encode_path, Reader},
+    Witness, WitnessedOperations, WitnessedPath,
+};
+
+/// A proof that `new_root` results from applying `writes` to the trie with root `prev_root`.
+/// See [`Witness::into_delta_proof`].
+pub struct StateDeltaProof {
+    /// The root prior to the changeset.
+    pub prev_root: Node,
+    /// The root after the changeset.
+    pub new_root: Node,
+    /// The path proofs of all written keys against `prev_root`, in ascending order.
+    pub paths: Vec<WitnessedPath>,
+    /// The written keys in ascending order, along with the hashes of their new values. `None`
+    /// means the key was deleted.
+    pub writes: Vec<(KeyPath, Option<ValueHash>)>,
+}
+
+/// Errors in decoding or verifying a [`StateDeltaProof`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateDeltaError {
+    /// The encoding of the proof is malformed.
+    Malformed,
+    /// A path proof does not verify against the prior root.
+    InvalidPath {
+        /// The index of the path proof within the proof.
+        path_index: usize,
+    },
+    /// A write is not covered by any of the path proofs.
+    UnprovenWrite,
+    /// The writes can't be applied to the path proofs, e.g. because they are out of order or a
+    /// path proof has no writes.
+    InvalidUpdate,
+    /// Applying the writes yields a root other than the new root.
+    RootMismatch,
+}
+
+impl Witness {
+    /// Turn the witness of a commit into a proof of the state transition it performed.
+    ///
+    /// `prev_root` is the root prior to the commit which produced the witness and the operations,
+    /// and `new_root` the root it returned. Path proofs which only serve reads are dropped.
+    pub fn into_delta_proof(
+        self,
+        ops: &WitnessedOperations,
+        prev_root: Node,
+        new_root: Node,
+    ) -> StateDeltaProof {
+        let mut written = vec![false; self.path_proofs.len()];
+        for write in &ops.writes {
+            written[write.path_index] = true;
+        }
+        let paths = self
+            .path_proofs
+            .into_iter()
+            .zip(written)
+            .filter_map(|(path, written)| written.then_some(path))
+            .collect();
+        let writes = ops
+            .writes
+            .iter()
+            .map(|write| (write.key, write.value))
+            .collect();
+        StateDeltaProof {
+            prev_root,
+            new_root,
+            paths,
+            writes,
+        }
+    }
+}
+
+impl StateDeltaProof {
+    /// Check that applying the writes to the trie with the prior root yields the new root.
+    pub fn verify<H: NodeHasher>(&self) -> Result<(), StateDeltaError> {
+        if self.paths.is_empty() {
+            // Nothing was written, so the root must be unchanged.
+            return match (self.writes.is_empty(), self.prev_root == self.new_root) {
+                (false, _) => Err(StateDeltaError::UnprovenWrite),
+                (true, false) => Err(StateDeltaError::RootMismatch),
+                (true, true) => Ok(()),
+            };
+        }
+
+        let mut writes = self.writes.iter().peekable();
+        let mut updates = Vec::with_capacity(self.paths.len());
+        for (path_index, path) in self.paths.iter().enumerate() {
+            let verified = path
+                .inner
+                .verify::<H>(path.path.path(), self.prev_root)
+                .map_err(|_| StateDeltaError::InvalidPath { path_index })?;
+            let mut ops = Vec::new();
+            while let Some((key, value)) = writes.next_if(|(key, _)| key_in_path(key, &verified)) {
+                ops.push((*key, *value));
+            }
+            updates.push(PathUpdate {
+                inner: verified,
+                ops,
+            });
+        }
+        if writes.next().is_some() {
+            return Err(StateDeltaError::UnprovenWrite);
+        }
+
+        let root = verify_update::<H>(self.prev_root, &updates)
+            .map_err(|_| StateDeltaError::InvalidUpdate)?;
+        if root != self.new_root {
+            return Err(StateDeltaError::RootMismatch);
+        }
+        Ok(())
+    }
+
+    /// Encode the proof.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&self.prev_root);
+        buf.extend_from_slice(&self.new_root);
+        buf.extend_from_slice(&(self.paths.len() as u32).to_le_bytes());
+        for path in &self.paths {
+            buf.extend_from_slice(&encode_path(path));
+        }
+        buf.extend_from_slice(&(self.writes.len() as u32).to_le_bytes());
+        for (key, value) in &self.writes {
+            buf.extend_from_slice(key);
+            match value {
+                None => buf.push(0),
+                Some(value_hash) => {
+                    buf.push(1);
+                    buf.extend_from_slice(value_hash);
+                }
+            }
+        }
+        buf
+    }
+
+    /// Decode a proof encoded with [`StateDeltaProof::encode`].
+    ///
+    /// This only checks the encoding. Use [`StateDeltaProof::verify`] to check the contents.
+    pub fn decode(buf: &[u8]) -> Result<Self, StateDeltaError> {
+        Self::decode_inner(&mut Reader(buf)).map_err(|_| StateDeltaError::Malformed)
+    }
+
+    fn decode_inner(reader: &mut Reader) -> Result<Self, crate::WitnessChunkError> {
+        let prev_root = reader.array()?;
+        let new_root = reader.array()?;
+        let num_paths = u32::from_le_bytes(reader.array()?);
+        let paths = (0..num_paths)
+            .map(|_| decode_path(reader))
+            .collect::<Result<Vec<_>, _>>()?;
+        let num_writes = u32::from_le_bytes(reader.array()?);
+        let mut writes = Vec::new();
+        for _ in 0..num_writes {
+            let key = reader.array()?;
+            let value = match reader.array::<1>()?[0] {
+                0 => None,
+                1 => Some(reader.array()?),
+                _ => return Err(crate::WitnessChunkError::Malformed),
+            };
+            writes.push((key, value));
+        }
+        if !reader.0.is_empty() {
+            return Err(crate::WitnessChunkError::Malformed);
+        }
+        Ok(StateDeltaProof {
+            prev_root,
+            new_root,
+            paths,
+            writes,
+        })
+    }
+}
+
+// Whether the key path begins with the path of the proof, compared bit by bit, most significant
+// first.
+fn key_in_path(key: &KeyPath, verified: &VerifiedPathProof) -> bool {
+    verified
+        .path()
+        .iter()
+        .enumerate()
+        .all(|(i, bit)| *bit == ((key[i / 8] >> (7 - i % 8)) & 1 == 1))
+}
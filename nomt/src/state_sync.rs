@@ -0,0 +1,403 @@
+//! Bootstrapping a database from the state of another one, chunk by chunk.
+//!
+//! The key space is split into chunks by key prefix. [`crate::Nomt::export_chunks`] captures the
+//! keys and values under each prefix in a [`StateChunk`], along with a proof of the subtree they
+//! form against the root of the trie. A [`ChunkImporter`] verifies every chunk against a trusted
+//! root before writing it to a fresh database, so chunks may be fetched from untrusted peers and
+//! in any order.
+//!
+//! The proof consists of the siblings of the nodes along the prefix, from the root down. If the
+//! trie ends above the prefix, i.e. in a leaf or a terminator, the proof ends there, and the leaf
+//! determines the contents of the chunk.
+
+use std::collections::{BTreeMap, HashMap};
+
+use bitvec::prelude::*;
+use nomt_core::{
+    page::DEPTH,
+    page_id::{PageId, ROOT_PAGE_ID},
+    proof::hash_path,
+    trie::{self, KeyPath, LeafData, Node, NodeHasherExt, TERMINATOR},
+    trie_pos::TriePosition,
+};
+
+use crate::{
+    io::FatPage,
+    subtree::{prefix_range, subtree_root},
+    HashAlgorithm, KeyReadWrite, Nomt, Value,
+};
+
+/// The keys and values under a key prefix, along with a proof of them against the root of the
+/// trie. See [`crate::Nomt::export_chunks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateChunk {
+    prefix: BitVec<u8, Msb0>,
+    root: Node,
+    entries: Vec<(KeyPath, Value)>,
+    /// The siblings of the nodes along the prefix, starting with the sibling at depth 1.
+    siblings: Vec<Node>,
+    /// The leaf the trie ends in, if it ends in a leaf above the prefix.
+    terminal: Option<LeafData>,
+}
+
+impl StateChunk {
+    /// The prefix shared by all keys of the chunk, in bits.
+    pub fn prefix(&self) -> &BitSlice<u8, Msb0> {
+        &self.prefix
+    }
+
+    /// The root of the trie the chunk is proven against.
+    ///
+    /// The root is part of the chunk and only checked for consistency with the entries. Compare it
+    /// against a trusted root before using a chunk from an untrusted source.
+    pub fn root(&self) -> Node {
+        self.root
+    }
+
+    /// The keys and values under the prefix, sorted by key.
+    pub fn entries(&self) -> &[(KeyPath, Value)] {
+        &self.entries
+    }
+
+    /// Check that the entries are sorted, lie under the prefix and are exactly the entries under
+    /// the prefix in the trie with the chunk's root.
+    pub fn verify<T: HashAlgorithm>(&self) -> anyhow::Result<()> {
+        if self.prefix.len() > 256 || self.siblings.len() > self.prefix.len() {
+            anyhow::bail!("malformed state chunk: proof longer than the prefix");
+        }
+        if self.entries.windows(2).any(|w| w[0].0 >= w[1].0) {
+            anyhow::bail!("malformed state chunk: entries not sorted");
+        }
+        if self
+            .entries
+            .iter()
+            .any(|(key, _)| !key.view_bits::<Msb0>().starts_with(&self.prefix))
+        {
+            anyhow::bail!("malformed state chunk: key outside of the prefix");
+        }
+
+        let depth = self.siblings.len();
+        let node = if depth == self.prefix.len() {
+            if self.terminal.is_some() {
+                anyhow::bail!("malformed state chunk: terminal below the proof");
+            }
+            subtree_root::<T>(depth, &self.entries)
+        } else {
+            // The trie ends above the prefix, so the chunk holds the terminal leaf if it lies
+            // under the prefix and nothing otherwise.
+            let expected = match &self.terminal {
+                Some(leaf) if leaf.key_path.view_bits::<Msb0>().starts_with(&self.prefix) => {
+                    Some(leaf)
+                }
+                _ => None,
+            };
+            let matches = match (expected, &self.entries[..]) {
+                (None, []) => true,
+                (Some(leaf), [(key, value)]) => {
+                    *key == leaf.key_path && T::hash_value(value) == leaf.value_hash
+                }
+                _ => false,
+            };
+            if !matches {
+                anyhow::bail!("state chunk entries do not match the terminal of its proof");
+            }
+            match &self.terminal {
+                Some(leaf) => T::hash_leaf(leaf),
+                None => TERMINATOR,
+            }
+        };
+
+        let siblings = self.siblings.iter().rev().copied();
+        if hash_path::<T>(node, &self.prefix[..depth], siblings) != self.root {
+            anyhow::bail!("state chunk does not match its root");
+        }
+        Ok(())
+    }
+
+    /// Decode a chunk. See [`StateChunk::encode`].
+    ///
+    /// This only checks the encoding. Use [`StateChunk::verify`] to check the contents.
+    pub fn decode(mut buf: &[u8]) -> anyhow::Result<Self> {
+        let prefix_len = read_u32(&mut buf)? as usize;
+        let prefix_bytes = read_slice(&mut buf, prefix_len.div_ceil(8))?;
+        let mut prefix = BitVec::<u8, Msb0>::from_slice(prefix_bytes);
+        prefix.truncate(prefix_len);
+        let root = read_array(&mut buf)?;
+
+        let sibling_count = read_u32(&mut buf)?;
+        let mut siblings = Vec::new();
+        for _ in 0..sibling_count {
+            siblings.push(read_array(&mut buf)?);
+        }
+        let terminal = match read_slice(&mut buf, 1)?[0] {
+            0 => None,
+            1 => Some(LeafData {
+                key_path: read_array(&mut buf)?,
+                value_hash: read_array(&mut buf)?,
+            }),
+            _ => anyhow::bail!("malformed state chunk: invalid terminal"),
+        };
+
+        let count = read_u32(&mut buf)?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let key = read_array(&mut buf)?;
+            let value_len = read_u32(&mut buf)? as usize;
+            let value = read_slice(&mut buf, value_len)?.to_vec();
+            entries.push((key, value));
+        }
+        if !buf.is_empty() {
+            anyhow::bail!("malformed state chunk: trailing bytes");
+        }
+        Ok(StateChunk {
+            prefix,
+            root,
+            entries,
+            siblings,
+            terminal,
+        })
+    }
+
+    /// Encode the chunk for transfer.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&(self.prefix.len() as u32).to_le_bytes());
+        buf.extend_from_slice(self.prefix.as_raw_slice());
+        buf.extend_from_slice(&self.root);
+        buf.extend_from_slice(&(self.siblings.len() as u32).to_le_bytes());
+        for sibling in &self.siblings {
+            buf.extend_from_slice(sibling);
+        }
+        match &self.terminal {
+            None => buf.push(0),
+            Some(leaf) => {
+                buf.push(1);
+                buf.extend_from_slice(&leaf.key_path);
+                buf.extend_from_slice(&leaf.value_hash);
+            }
+        }
+        buf.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for (key, value) in &self.entries {
+            buf.extend_from_slice(key);
+            buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            buf.extend_from_slice(value);
+        }
+        buf
+    }
+}
+
+/// Export the chunk under the given prefix from the synced state of the database.
+pub(crate) fn export_chunk<T: HashAlgorithm>(
+    nomt: &Nomt<T>,
+    prefix: BitVec<u8, Msb0>,
+) -> anyhow::Result<StateChunk> {
+    let mut reader = NodeReader::new(nomt);
+    let root = nomt.root();
+    let mut node = root;
+    let mut position = TriePosition::new();
+    let mut siblings = Vec::new();
+    for bit in prefix.iter().by_vals() {
+        if !trie::is_internal(&node) {
+            break;
+        }
+        position.down(!bit);
+        siblings.push(reader.node(&position)?);
+        position.sibling();
+        node = reader.node(&position)?;
+    }
+
+    let terminal = match siblings.len() < prefix.len() && trie::is_leaf(&node) {
+        true => Some(reader.leaf_data(&position)?),
+        false => None,
+    };
+    let (start, end) = prefix_range(&prefix);
+    let entries = nomt.store.load_value_range(start, end, usize::MAX);
+    Ok(StateChunk {
+        prefix,
+        root,
+        entries,
+        siblings,
+        terminal,
+    })
+}
+
+/// The prefix of the chunk with the given index when the key space is split into
+/// `2^chunk_bits` chunks.
+pub(crate) fn chunk_prefix(chunk_bits: u32, index: u64) -> BitVec<u8, Msb0> {
+    (0..chunk_bits)
+        .rev()
+        .map(|bit| index >> bit & 1 == 1)
+        .collect()
+}
+
+/// Reads nodes of the synced trie, caching the pages read.
+pub(crate) struct NodeReader<'a, T: HashAlgorithm> {
+    nomt: &'a Nomt<T>,
+    pages: HashMap<PageId, Option<FatPage>>,
+}
+
+impl<'a, T: HashAlgorithm> NodeReader<'a, T> {
+    pub(crate) fn new(nomt: &'a Nomt<T>) -> Self {
+        NodeReader {
+            nomt,
+            pages: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn node(&mut self, position: &TriePosition) -> anyhow::Result<Node> {
+        match position.page_id() {
+            None => Ok(self.nomt.root()),
+            Some(page_id) => self.slot(page_id, position.node_index()),
+        }
+    }
+
+    // The leaf data of a leaf node is stored in its two child slots.
+    pub(crate) fn leaf_data(&mut self, position: &TriePosition) -> anyhow::Result<LeafData> {
+        let (page_id, index) = match position.page_id() {
+            None => (ROOT_PAGE_ID, 0),
+            Some(page_id) if position.depth_in_page() == DEPTH => {
+                let child_page_id = page_id
+                    .child_page_id(position.child_page_index())
+                    .map_err(|_| anyhow::anyhow!("leaf at maximum depth"))?;
+                (child_page_id, 0)
+            }
+            Some(page_id) => (page_id, position.child_node_indices().left()),
+        };
+        Ok(LeafData {
+            key_path: self.slot(page_id.clone(), index)?,
+            value_hash: self.slot(page_id, index + 1)?,
+        })
+    }
+
+    fn slot(&mut self, page_id: PageId, index: usize) -> anyhow::Result<[u8; 32]> {
+        let page = match self.pages.get(&page_id) {
+            Some(page) => page,
+            None => {
+                let page = self.nomt.store.load_page(page_id.clone())?;
+                self.pages
+                    .entry(page_id)
+                    .or_insert(page.map(|(page, _)| page))
+            }
+        };
+        let mut slot = [0; 32];
+        if let Some(page) = page {
+            slot.copy_from_slice(&page[index * 32..][..32]);
+        }
+        Ok(slot)
+    }
+}
+
+/// Rebuilds the state of a trie with a trusted root in a fresh database from [`StateChunk`]s.
+///
+/// Every chunk is verified against the root before its entries are committed. Chunks may be
+/// imported in any order and may differ in the length of their prefixes, but must not overlap.
+/// Once the chunks cover the whole key space, the database has the trusted root.
+pub struct ChunkImporter<'a, T: HashAlgorithm> {
+    nomt: &'a Nomt<T>,
+    root: Node,
+    /// The key ranges of the imported chunks, by their first key.
+    imported: BTreeMap<KeyPath, KeyPath>,
+}
+
+impl<'a, T: HashAlgorithm> ChunkImporter<'a, T> {
+    /// Create an importer rebuilding the trie with the given root in the given database, which must
+    /// be empty.
+    pub fn new(nomt: &'a Nomt<T>, root: Node) -> anyhow::Result<Self> {
+        if !nomt.is_empty() {
+            anyhow::bail!("state sync: database not empty");
+        }
+        Ok(ChunkImporter {
+            nomt,
+            root,
+            imported: BTreeMap::new(),
+        })
+    }
+
+    /// Verify the chunk against the trusted root and commit its entries.
+    ///
+    /// Fails if the chunk does not match the root or overlaps a chunk imported before.
+    ///
+    /// This function assumes no sessions are active and panics otherwise.
+    pub fn import(&mut self, chunk: StateChunk) -> anyhow::Result<()> {
+        if chunk.root() != self.root {
+            anyhow::bail!("state sync: chunk proven against a different root");
+        }
+        chunk.verify::<T>()?;
+
+        let (start, end) = prefix_range(chunk.prefix());
+        let before = self.imported.range(..=end).next_back();
+        if before.is_some_and(|(_, before_end)| *before_end >= start) {
+            anyhow::bail!("state sync: chunk overlaps an imported chunk");
+        }
+
+        if !chunk.entries.is_empty() {
+            let session = self.nomt.begin_session();
+            let actuals = chunk
+                .entries
+                .into_iter()
+                .map(|(key, value)| {
+                    session.warm_up(key);
+                    (key, KeyReadWrite::Write(Some(value)))
+                })
+                .collect::<Vec<_>>();
+            self.nomt.commit(session, actuals)?;
+        }
+        self.imported.insert(start, end);
+        Ok(())
+    }
+
+    /// Whether the imported chunks cover the whole key space.
+    pub fn is_complete(&self) -> bool {
+        let mut next = Some(KeyPath::default());
+        for (start, end) in &self.imported {
+            if next != Some(*start) {
+                return false;
+            }
+            next = successor(*end);
+        }
+        next.is_none()
+    }
+
+    /// Finish the import, checking that the whole key space has been imported. Returns the root of
+    /// the database, which is the trusted root.
+    pub fn finish(self) -> anyhow::Result<Node> {
+        if !self.is_complete() {
+            anyhow::bail!("state sync: chunks missing");
+        }
+        let root = self.nomt.root();
+        if root != self.root {
+            anyhow::bail!("state sync: root mismatch after import");
+        }
+        Ok(root)
+    }
+}
+
+/// The key path following the given one, if any.
+fn successor(mut key: KeyPath) -> Option<KeyPath> {
+    for byte in key.iter_mut().rev() {
+        let (next, overflow) = byte.overflowing_add(1);
+        *byte = next;
+        if !overflow {
+            return Some(key);
+        }
+    }
+    None
+}
+
+fn read_u32(buf: &mut &[u8]) -> anyhow::Result<u32> {
+    Ok(u32::from_le_bytes(read_array(buf)?))
+}
+
+fn read_array<const N: usize>(buf: &mut &[u8]) -> anyhow::Result<[u8; N]> {
+    // UNWRAP: the slice is `N` bytes long.
+    Ok(read_slice(buf, N)?.try_into().unwrap())
+}
+
+fn read_slice<'a>(buf: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+    if buf.len() < len {
+        anyhow::bail!("malformed state chunk: unexpected end");
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
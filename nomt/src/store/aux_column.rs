@@ -0,0 +1,386 @@
+//! The auxiliary column: a small key-value store for node-local records which are not part of the
+//! merkle trie, such as block metadata or indexes.
+//!
+//! The column is kept in memory and persisted in the `aux` file, a log of batches. Every sync
+//! carrying auxiliary writes appends a batch tagged with its sync sequence number, and syncs it,
+//! before the manifest is written. On open, batches of syncs which never reached the manifest, as
+//! well as a batch torn by a crash, are discarded. Once the log has grown to several times the size
+//! of the live records, it is replaced by a single batch holding them.
+//!
+//! With [`crate::Options::encryption_key`], the body of every batch is padded to whole pages, which
+//! are encrypted like those of the other files, with their offset in the log as the tweak. Batches
+//! written without encryption, before it was supported for the column, are still read.
+
+use crate::io::{PageCipher, PAGE_SIZE};
+use anyhow::Result;
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{Read as _, Seek as _, SeekFrom, Write as _},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Writes to the auxiliary column, with `None` meaning deletion.
+pub type AuxWrites = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// The size of the header of a batch: the length of the body and its checksum.
+const HEADER_SIZE: usize = 4 + 32;
+
+/// Set in the length of a batch whose body is encrypted.
+const ENCRYPTED: u32 = 1 << 31;
+
+/// The log is compacted once it is this many times larger than the live records.
+const COMPACTION_FACTOR: u64 = 4;
+
+/// The log is not compacted below this size.
+const MIN_COMPACTION_SIZE: u64 = 64 * 1024;
+
+pub struct AuxColumn {
+    path: PathBuf,
+    fd: File,
+    /// The length of the log.
+    len: u64,
+    records: BTreeMap<Vec<u8>, Vec<u8>>,
+    cipher: Option<Arc<PageCipher>>,
+}
+
+impl AuxColumn {
+    /// Open the column in the given database directory, creating it if it doesn't exist.
+    ///
+    /// `sync_seqn` is the sequence number of the last sync according to the manifest. Batches are
+    /// encrypted with the given cipher, if any.
+    pub fn open(db_dir: &Path, sync_seqn: u32, cipher: Option<Arc<PageCipher>>) -> Result<Self> {
+        Self::load(db_dir, sync_seqn, cipher, false)
+    }
+
+    /// Open the existing column in the given database directory without write access, leaving
+    /// the batches of syncs which never reached the manifest in place.
+    pub fn open_read_only(
+        db_dir: &Path,
+        sync_seqn: u32,
+        cipher: Option<Arc<PageCipher>>,
+    ) -> Result<Self> {
+        Self::load(db_dir, sync_seqn, cipher, true)
+    }
+
+    fn load(
+        db_dir: &Path,
+        sync_seqn: u32,
+        cipher: Option<Arc<PageCipher>>,
+        read_only: bool,
+    ) -> Result<Self> {
+        let path = db_dir.join("aux");
+        let mut fd = OpenOptions::new()
+            .read(true)
+            .write(!read_only)
+            .create(!read_only)
+            .truncate(false)
+            .open(&path)?;
+        let mut buf = Vec::new();
+        fd.read_to_end(&mut buf)?;
+
+        let mut records = BTreeMap::new();
+        let mut len = 0;
+        while let Some((batch_seqn, writes, batch_len)) =
+            decode_batch(&buf[len..], len as u64, cipher.as_deref())
+        {
+            if batch_seqn > sync_seqn {
+                break;
+            }
+            apply(&mut records, writes);
+            len += batch_len;
+        }
+        if len != buf.len() && !read_only {
+            fd.set_len(len as u64)?;
+            fd.sync_data()?;
+        }
+        fd.seek(SeekFrom::Start(len as u64))?;
+
+        Ok(AuxColumn {
+            path,
+            fd,
+            len: len as u64,
+            records,
+            cipher,
+        })
+    }
+
+    /// Returns the value stored under the given key.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.records.get(key).map(|value| &value[..])
+    }
+
+    /// Iterate over the records whose keys start with the given prefix, in key order.
+    pub fn prefix<'a>(&'a self, prefix: &'a [u8]) -> impl Iterator<Item = (&'a [u8], &'a [u8])> {
+        self.records
+            .range(prefix.to_vec()..)
+            .take_while(move |(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (&key[..], &value[..]))
+    }
+
+    /// Durably append the writes of the sync with the given sequence number and apply them.
+    pub fn append(&mut self, sync_seqn: u32, writes: AuxWrites) -> Result<()> {
+        if writes.is_empty() {
+            return Ok(());
+        }
+        let batch = encode_batch(
+            sync_seqn,
+            writes.iter().map(|(key, value)| (key, value.as_ref())),
+            self.len,
+            self.cipher.as_deref(),
+        );
+        self.fd.write_all(&batch)?;
+        self.fd.sync_data()?;
+        self.len += batch.len() as u64;
+        apply(&mut self.records, writes);
+        Ok(())
+    }
+
+    /// Replace the log by a single batch of the live records, if it has grown large enough.
+    ///
+    /// Must only be called after the manifest of the last sync, with the given sequence number,
+    /// has been written, so that the batch does not include writes which may still be discarded.
+    pub fn maybe_compact(&mut self, sync_seqn: u32) -> Result<()> {
+        let live = self
+            .records
+            .iter()
+            .map(|(key, value)| (key.len() + value.len() + 9) as u64)
+            .sum::<u64>();
+        if self.len < MIN_COMPACTION_SIZE || self.len < live * COMPACTION_FACTOR {
+            return Ok(());
+        }
+
+        let batch = encode_batch(
+            sync_seqn,
+            self.records.iter().map(|(key, value)| (key, Some(value))),
+            0,
+            self.cipher.as_deref(),
+        );
+        let tmp_path = self.path.with_extension("tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(&batch)?;
+        tmp.sync_all()?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        if let Some(dir) = self.path.parent() {
+            File::open(dir)?.sync_all()?;
+        }
+        self.fd = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        self.fd.seek(SeekFrom::End(0))?;
+        self.len = batch.len() as u64;
+        Ok(())
+    }
+}
+
+fn apply(records: &mut BTreeMap<Vec<u8>, Vec<u8>>, writes: AuxWrites) {
+    for (key, value) in writes {
+        match value {
+            Some(value) => records.insert(key, value),
+            None => records.remove(&key),
+        };
+    }
+}
+
+/// Encode a batch to be written at the given offset of the log, encrypting its body with the
+/// cipher, if any.
+fn encode_batch<'a, V: AsRef<[u8]> + 'a>(
+    sync_seqn: u32,
+    writes: impl Iterator<Item = (&'a Vec<u8>, Option<V>)>,
+    offset: u64,
+    cipher: Option<&PageCipher>,
+) -> Vec<u8> {
+    let mut body = sync_seqn.to_le_bytes().to_vec();
+    for (key, value) in writes {
+        body.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        body.extend_from_slice(key);
+        match value {
+            None => body.push(0),
+            Some(value) => {
+                let value = value.as_ref();
+                body.push(1);
+                body.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                body.extend_from_slice(value);
+            }
+        }
+    }
+
+    let mut len = body.len() as u32;
+    if let Some(cipher) = cipher {
+        // The length of the plain body precedes it, so that the padding can be told apart.
+        let plain = body;
+        body = Vec::with_capacity(plain.len().next_multiple_of(PAGE_SIZE));
+        body.extend_from_slice(&(plain.len() as u32).to_le_bytes());
+        body.extend_from_slice(&plain);
+        body.resize(body.len().next_multiple_of(PAGE_SIZE), 0);
+        let body_offset = offset + HEADER_SIZE as u64;
+        for (i, page) in body.chunks_exact_mut(PAGE_SIZE).enumerate() {
+            cipher.encrypt(body_offset + (i * PAGE_SIZE) as u64, page);
+        }
+        len = body.len() as u32 | ENCRYPTED;
+    }
+
+    let mut batch = Vec::with_capacity(HEADER_SIZE + body.len());
+    batch.extend_from_slice(&len.to_le_bytes());
+    batch.extend_from_slice(blake3::hash(&body).as_bytes());
+    batch.extend_from_slice(&body);
+    batch
+}
+
+/// Decode the batch at the start of the buffer, read from the given offset of the log, returning
+/// its sync sequence number, its writes and its length. Returns `None` if the batch is incomplete
+/// or corrupt, or encrypted while no cipher is given.
+fn decode_batch(
+    buf: &[u8],
+    offset: u64,
+    cipher: Option<&PageCipher>,
+) -> Option<(u32, AuxWrites, usize)> {
+    let (header, rest) = buf.split_first_chunk::<HEADER_SIZE>()?;
+    // UNWRAP: the slice is 4 bytes long.
+    let len = u32::from_le_bytes(header[..4].try_into().unwrap());
+    let body_len = (len & !ENCRYPTED) as usize;
+    let body = rest.get(..body_len)?;
+    if blake3::hash(body).as_bytes() != &header[4..] {
+        return None;
+    }
+
+    let plain;
+    let body = if len & ENCRYPTED != 0 {
+        let mut decrypted = body.to_vec();
+        let body_offset = offset + HEADER_SIZE as u64;
+        for (i, page) in decrypted.chunks_exact_mut(PAGE_SIZE).enumerate() {
+            cipher?.decrypt(body_offset + (i * PAGE_SIZE) as u64, page);
+        }
+        plain = decrypted;
+        let (plain_len, rest) = plain.split_first_chunk::<4>()?;
+        rest.get(..u32::from_le_bytes(*plain_len) as usize)?
+    } else {
+        body
+    };
+
+    let (sync_seqn, mut body) = body.split_first_chunk::<4>()?;
+    let mut writes = AuxWrites::new();
+    while !body.is_empty() {
+        let key = read_slice(&mut body)?.to_vec();
+        let (tag, rest) = body.split_first()?;
+        body = rest;
+        let value = match tag {
+            0 => None,
+            _ => Some(read_slice(&mut body)?.to_vec()),
+        };
+        writes.insert(key, value);
+    }
+    Some((
+        u32::from_le_bytes(*sync_seqn),
+        writes,
+        HEADER_SIZE + body_len,
+    ))
+}
+
+/// Read a slice prefixed by its length.
+fn read_slice<'a>(buf: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let (len, rest) = buf.split_first_chunk::<4>()?;
+    let len = u32::from_le_bytes(*len) as usize;
+    let slice = rest.get(..len)?;
+    *buf = &rest[len..];
+    Some(slice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuxColumn, AuxWrites};
+
+    fn writes(entries: &[(&[u8], Option<&[u8]>)]) -> AuxWrites {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_vec(), value.map(|value| value.to_vec())))
+            .collect()
+    }
+
+    #[test]
+    fn discards_unsynced_and_torn_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut aux = AuxColumn::open(dir.path(), 0, None).unwrap();
+        aux.append(1, writes(&[(b"a", Some(b"1")), (b"b", Some(b"2"))]))
+            .unwrap();
+        aux.append(2, writes(&[(b"a", None)])).unwrap();
+        aux.append(3, writes(&[(b"b", Some(b"3"))])).unwrap();
+        drop(aux);
+
+        // The manifest only made it to sync 2.
+        let aux = AuxColumn::open(dir.path(), 2, None).unwrap();
+        assert_eq!(aux.get(b"a"), None);
+        assert_eq!(aux.get(b"b"), Some(&b"2"[..]));
+        drop(aux);
+
+        // A torn batch is discarded.
+        let path = dir.path().join("aux");
+        let len = std::fs::metadata(&path).unwrap().len();
+        let mut aux = AuxColumn::open(dir.path(), 2, None).unwrap();
+        aux.append(3, writes(&[(b"c", Some(b"4"))])).unwrap();
+        drop(aux);
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len + 10).unwrap();
+        let aux = AuxColumn::open(dir.path(), 3, None).unwrap();
+        assert_eq!(aux.get(b"c"), None);
+        assert_eq!(aux.get(b"b"), Some(&b"2"[..]));
+    }
+
+    #[test]
+    fn compaction_keeps_live_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut aux = AuxColumn::open(dir.path(), 0, None).unwrap();
+        let value = vec![7; 1024];
+        for seqn in 1..=100 {
+            aux.append(seqn, writes(&[(b"key", Some(&value))])).unwrap();
+        }
+        aux.append(101, writes(&[(b"other", Some(b"x"))])).unwrap();
+        aux.maybe_compact(101).unwrap();
+        assert!(std::fs::metadata(dir.path().join("aux")).unwrap().len() < 2048);
+
+        aux.append(102, writes(&[(b"other", None)])).unwrap();
+        drop(aux);
+        let aux = AuxColumn::open(dir.path(), 102, None).unwrap();
+        assert_eq!(aux.get(b"key"), Some(&value[..]));
+        assert_eq!(aux.get(b"other"), None);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_batches_follow_plain_ones() {
+        use crate::io::PageCipher;
+        use std::sync::Arc;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("aux");
+        let mut aux = AuxColumn::open(dir.path(), 0, None).unwrap();
+        aux.append(1, writes(&[(b"plain", Some(b"1"))])).unwrap();
+        drop(aux);
+
+        let cipher = Some(Arc::new(PageCipher::new(&[5; 32])));
+        let mut aux = AuxColumn::open(dir.path(), 1, cipher.clone()).unwrap();
+        aux.append(2, writes(&[(b"secret key", Some(b"secret value"))]))
+            .unwrap();
+        aux.append(3, writes(&[(b"plain", None)])).unwrap();
+        drop(aux);
+        let contents = std::fs::read(&path).unwrap();
+        let contains = |needle: &[u8]| contents.windows(needle.len()).any(|w| w == needle);
+        assert!(contains(b"plain"));
+        assert!(!contains(b"secret"));
+
+        let aux = AuxColumn::open(dir.path(), 3, cipher.clone()).unwrap();
+        assert_eq!(aux.get(b"secret key"), Some(&b"secret value"[..]));
+        assert_eq!(aux.get(b"plain"), None);
+        drop(aux);
+
+        // A torn encrypted batch is discarded.
+        let len = std::fs::metadata(&path).unwrap().len();
+        let mut aux = AuxColumn::open(dir.path(), 3, cipher.clone()).unwrap();
+        aux.append(4, writes(&[(b"torn", Some(b"x"))])).unwrap();
+        drop(aux);
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len + 100).unwrap();
+        let aux = AuxColumn::open(dir.path(), 4, cipher).unwrap();
+        assert_eq!(aux.get(b"torn"), None);
+        assert_eq!(aux.get(b"secret key"), Some(&b"secret value"[..]));
+    }
+}
@@ -0,0 +1,96 @@
+//! The index of block numbers supplied with commits.
+//!
+//! Every sync which carries a block number appends a record of its sync sequence number and the
+//! block number to the `blocks` file. Records are fixed-size, so a record torn by a crash is
+//! recognized by the file length and dropped on open. The block number of the last sync is also
+//! persisted in the manifest, which is written first, so a record lost to a crash is restored from
+//! there.
+
+use anyhow::Result;
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read as _, Seek as _, SeekFrom, Write as _},
+    path::Path,
+};
+
+const RECORD_SIZE: usize = 12;
+
+pub struct BlockIndex {
+    fd: File,
+    /// `(sync_seqn, block_number)` in the order of syncs.
+    records: Vec<(u32, u64)>,
+}
+
+impl BlockIndex {
+    /// Open the index in the given database directory, creating it if it doesn't exist.
+    ///
+    /// `last` is the sync sequence number and the block number of the last sync according to the
+    /// manifest.
+    pub fn open(db_dir: &Path, last: Option<(u32, u64)>) -> Result<Self> {
+        Self::load(db_dir, last, false)
+    }
+
+    /// Open the existing index in the given database directory without write access. A record
+    /// missing from the file is restored in memory only.
+    pub fn open_read_only(db_dir: &Path, last: Option<(u32, u64)>) -> Result<Self> {
+        Self::load(db_dir, last, true)
+    }
+
+    fn load(db_dir: &Path, last: Option<(u32, u64)>, read_only: bool) -> Result<Self> {
+        let mut fd = OpenOptions::new()
+            .read(true)
+            .write(!read_only)
+            .create(!read_only)
+            .truncate(false)
+            .open(db_dir.join("blocks"))?;
+        let mut buf = Vec::new();
+        fd.read_to_end(&mut buf)?;
+
+        let records = buf
+            .chunks_exact(RECORD_SIZE)
+            .map(|record| {
+                // UNWRAP: the slices have the right length.
+                let sync_seqn = u32::from_le_bytes(record[0..4].try_into().unwrap());
+                let block_number = u64::from_le_bytes(record[4..12].try_into().unwrap());
+                (sync_seqn, block_number)
+            })
+            .collect::<Vec<_>>();
+        if buf.len() % RECORD_SIZE != 0 && !read_only {
+            let len = (records.len() * RECORD_SIZE) as u64;
+            fd.set_len(len)?;
+            fd.seek(SeekFrom::Start(len))?;
+        }
+
+        let mut index = BlockIndex { fd, records };
+        if let Some((sync_seqn, block_number)) = last {
+            if index.records.last().map(|&(seqn, _)| seqn) != Some(sync_seqn) {
+                if read_only {
+                    index.records.push((sync_seqn, block_number));
+                } else {
+                    index.append(sync_seqn, block_number)?;
+                }
+            }
+        }
+        Ok(index)
+    }
+
+    /// Record that the sync with the given sequence number carried the given block number.
+    pub fn append(&mut self, sync_seqn: u32, block_number: u64) -> Result<()> {
+        let mut record = [0; RECORD_SIZE];
+        record[0..4].copy_from_slice(&sync_seqn.to_le_bytes());
+        record[4..12].copy_from_slice(&block_number.to_le_bytes());
+        self.fd.write_all(&record)?;
+        self.fd.sync_data()?;
+        self.records.push((sync_seqn, block_number));
+        Ok(())
+    }
+
+    /// Returns the sequence number of the last sync which carried the given block number.
+    pub fn seqn_for_block(&self, block_number: u64) -> Option<u32> {
+        self.records
+            .iter()
+            .rev()
+            .find(|&&(_, block)| block == block_number)
+            .map(|&(seqn, _)| seqn)
+    }
+}
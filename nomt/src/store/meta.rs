@@ -6,8 +6,10 @@ use std::os::unix::fs::FileExt as _;
 use crate::io::{self, PagePool};
 
 pub(crate) const MAGIC: [u8; 4] = *b"NOMT";
-pub(crate) const VERSION: u32 = 1;
-pub(crate) const META_SIZE: usize = 64;
+pub(crate) const VERSION: u32 = 7;
+/// The first version whose WAL starts with the sequence number of the sync which produced it.
+pub(crate) const WAL_HEADER_VERSION: u32 = 7;
+pub(crate) const META_SIZE: usize = 176;
 
 /// This data structure describes the state of the btree.
 #[derive(Clone, Debug)]
@@ -36,18 +38,44 @@ pub struct Meta {
     pub sync_seqn: u32,
     /// The number of pages in the bitbox store.
     pub bitbox_num_pages: u32,
+    /// The number of pages of the bitbox store being grown into, if it is being resized.
+    ///
+    /// Introduced in version 6. Always `None` for databases of earlier versions.
+    pub bitbox_resize_num_pages: Option<u32>,
     /// The random seed used for populating the hash-table in a unique way.
     pub bitbox_seed: [u8; 16],
     /// The first live record ID in the rollback seglog.
     pub rollback_start_live: u64,
     /// The last live record ID in the rollback seglog.
     pub rollback_end_live: u64,
+    /// The token supplied with the last commit, if any.
+    ///
+    /// Introduced in version 2. Always `None` for databases of earlier versions.
+    pub commit_token: Option<[u8; 32]>,
+    /// The block number supplied with the last commit, if any.
+    ///
+    /// Introduced in version 3. Always `None` for databases of earlier versions.
+    pub block_number: Option<u64>,
+    /// Identifies the key the data files are encrypted with, if they are. See
+    /// [`crate::io::PageCipher::key_check`].
+    ///
+    /// Introduced in version 4. Always `None` for databases of earlier versions.
+    pub encryption_key_check: Option<[u8; 32]>,
+    /// The fingerprint of the hash algorithm the trie was built with.
+    ///
+    /// Introduced in version 5. Always `None` for databases of earlier versions.
+    pub hasher_fingerprint: Option<[u8; 32]>,
 }
 
 impl Meta {
-    /// Returns a newly initialized [`Meta`] instance with the given bitbox seed and number of
-    /// pages.
-    pub fn create_new(bitbox_seed: [u8; 16], bitbox_num_pages: u32) -> Self {
+    /// Returns a newly initialized [`Meta`] instance with the given bitbox seed, number of pages,
+    /// hasher fingerprint and encryption key check.
+    pub fn create_new(
+        bitbox_seed: [u8; 16],
+        bitbox_num_pages: u32,
+        hasher_fingerprint: [u8; 32],
+        encryption_key_check: Option<[u8; 32]>,
+    ) -> Self {
         Self {
             magic: MAGIC,
             version: VERSION,
@@ -57,9 +85,14 @@ impl Meta {
             bbn_bump: 1,
             sync_seqn: 0,
             bitbox_num_pages,
+            bitbox_resize_num_pages: None,
             bitbox_seed,
             rollback_start_live: 0,
             rollback_end_live: 0,
+            commit_token: None,
+            block_number: None,
+            hasher_fingerprint: Some(hasher_fingerprint),
+            encryption_key_check,
         }
     }
 
@@ -76,6 +109,23 @@ impl Meta {
         buf[32..48].copy_from_slice(&self.bitbox_seed);
         buf[48..56].copy_from_slice(&self.rollback_start_live.to_le_bytes());
         buf[56..64].copy_from_slice(&self.rollback_end_live.to_le_bytes());
+        match self.commit_token {
+            Some(ref token) => {
+                buf[64..96].copy_from_slice(token);
+                buf[96] = 1;
+            }
+            None => {
+                buf[64..96].copy_from_slice(&[0; 32]);
+                buf[96] = 0;
+            }
+        }
+        buf[97..105].copy_from_slice(&self.block_number.unwrap_or(0).to_le_bytes());
+        buf[105] = self.block_number.is_some() as u8;
+        buf[106..138].copy_from_slice(&self.encryption_key_check.unwrap_or([0; 32]));
+        buf[138] = self.encryption_key_check.is_some() as u8;
+        buf[139..171].copy_from_slice(&self.hasher_fingerprint.unwrap_or([0; 32]));
+        buf[171] = self.hasher_fingerprint.is_some() as u8;
+        buf[172..176].copy_from_slice(&self.bitbox_resize_num_pages.unwrap_or(0).to_le_bytes());
     }
 
     pub fn decode(buf: &[u8]) -> Self {
@@ -91,6 +141,33 @@ impl Meta {
         let bitbox_seed = buf[32..48].try_into().unwrap();
         let rollback_start_live = u64::from_le_bytes(buf[48..56].try_into().unwrap());
         let rollback_end_live = u64::from_le_bytes(buf[56..64].try_into().unwrap());
+        // Version 1 did not encode anything past the rollback range.
+        let commit_token = if version >= 2 && buf[96] == 1 {
+            Some(buf[64..96].try_into().unwrap())
+        } else {
+            None
+        };
+        let block_number = if version >= 3 && buf[105] == 1 {
+            Some(u64::from_le_bytes(buf[97..105].try_into().unwrap()))
+        } else {
+            None
+        };
+        let encryption_key_check = if version >= 4 && buf[138] == 1 {
+            Some(buf[106..138].try_into().unwrap())
+        } else {
+            None
+        };
+        let hasher_fingerprint = if version >= 5 && buf[171] == 1 {
+            Some(buf[139..171].try_into().unwrap())
+        } else {
+            None
+        };
+        // Zero buckets means that no resize is in progress.
+        let bitbox_resize_num_pages = if version >= 6 {
+            Some(u32::from_le_bytes(buf[172..176].try_into().unwrap())).filter(|&n| n > 0)
+        } else {
+            None
+        };
         Self {
             magic,
             version,
@@ -100,9 +177,14 @@ impl Meta {
             bbn_bump,
             sync_seqn,
             bitbox_num_pages,
+            bitbox_resize_num_pages,
             bitbox_seed,
             rollback_start_live,
             rollback_end_live,
+            commit_token,
+            block_number,
+            encryption_key_check,
+            hasher_fingerprint,
         }
     }
 
@@ -138,7 +220,7 @@ impl Meta {
     }
 
     pub fn read(page_pool: &PagePool, fd: &File) -> Result<Self> {
-        let page = io::read_page(page_pool, fd, 0)?;
+        let page = io::read_page(page_pool, None, fd, 0)?;
         let meta = Meta::decode(&page[..META_SIZE]);
         Ok(meta)
     }
@@ -168,9 +250,26 @@ mod tests {
                 bbn_bump: u32::arbitrary(g),
                 sync_seqn: u32::arbitrary(g),
                 bitbox_num_pages: u32::arbitrary(g),
+                bitbox_resize_num_pages: Option::<u32>::arbitrary(g).filter(|&n| n > 0),
                 bitbox_seed: u128::arbitrary(g).to_le_bytes(),
                 rollback_start_live: u64::arbitrary(g),
                 rollback_end_live: u64::arbitrary(g),
+                commit_token: Option::<u128>::arbitrary(g).map(|x| {
+                    let mut token = [0; 32];
+                    token[..16].copy_from_slice(&x.to_le_bytes());
+                    token
+                }),
+                block_number: Option::<u64>::arbitrary(g),
+                encryption_key_check: Option::<u128>::arbitrary(g).map(|x| {
+                    let mut check = [0; 32];
+                    check[8..24].copy_from_slice(&x.to_le_bytes());
+                    check
+                }),
+                hasher_fingerprint: Option::<u128>::arbitrary(g).map(|x| {
+                    let mut fingerprint = [0; 32];
+                    fingerprint[16..].copy_from_slice(&x.to_le_bytes());
+                    fingerprint
+                }),
             }
         }
     }
@@ -191,7 +290,12 @@ mod tests {
             meta.bitbox_num_pages == decoded.bitbox_num_pages &&
             meta.bitbox_seed == decoded.bitbox_seed &&
             meta.rollback_start_live == decoded.rollback_start_live &&
-            meta.rollback_end_live == decoded.rollback_end_live
+            meta.rollback_end_live == decoded.rollback_end_live &&
+            (meta.version < 2 || meta.commit_token == decoded.commit_token) &&
+            (meta.version < 3 || meta.block_number == decoded.block_number) &&
+            (meta.version < 4 || meta.encryption_key_check == decoded.encryption_key_check) &&
+            (meta.version < 5 || meta.hasher_fingerprint == decoded.hasher_fingerprint) &&
+            (meta.version < 6 || meta.bitbox_resize_num_pages == decoded.bitbox_resize_num_pages)
         }
     }
 }
@@ -4,20 +4,30 @@
 //! b-tree key-value storage (beatree).
 
 use crate::{
+    background_error::BackgroundErrors,
     beatree, bitbox,
     io::{self, page_pool::FatPage, IoPool, PagePool},
     merkle,
+    metrics::Metrics,
     page_cache::PageCache,
     page_diff::PageDiff,
     rollback::Rollback,
-    ValueHasher,
+    Clock, IoUringMode, ReadConsistency, ValueHandle, ValueHasher,
 };
+use aux_column::{AuxColumn, AuxWrites};
+use block_index::BlockIndex;
 use meta::Meta;
 use nomt_core::{page_id::PageId, trie::KeyPath};
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use std::{
     fs::{File, OpenOptions},
-    sync::Arc,
+    os::fd::RawFd,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
 #[cfg(target_os = "linux")]
@@ -26,10 +36,20 @@ use std::os::unix::fs::OpenOptionsExt as _;
 pub use self::page_loader::{PageLoad, PageLoadCompletion, PageLoader};
 pub use bitbox::BucketIndex;
 
+/// The prefix of the keys of the auxiliary column reserved for records kept by the database
+/// itself. Keys written by the user must not start with it.
+pub const RESERVED_AUX_PREFIX: &[u8] = b"\0nomt:";
+
+mod aux_column;
+mod block_index;
 mod flock;
 mod meta;
 mod page_loader;
 mod sync;
+mod ttl;
+
+/// The minimum number of buckets [`Store::shrink`] leaves the hash-table with.
+const MIN_SHRUNK_BUCKETS: usize = 1024;
 
 /// This is a lightweight handle and can be cloned cheaply.
 #[derive(Clone)]
@@ -44,9 +64,27 @@ struct Shared {
     rollback: Option<Rollback>,
     page_pool: PagePool,
     io_pool: IoPool,
+    metrics: Metrics,
     meta_fd: File,
+    /// The names and the raw descriptors of the files written through the I/O pool.
+    data_fds: [(&'static str, RawFd); 3],
+    /// The number of keys read or committed. See [`Store::read_totals`].
+    logical_reads: AtomicU64,
+    /// The maximum number of commits and the maximum delay coalesced into a single sync.
+    commit_coalescing: Option<(usize, Duration)>,
+    /// `None` if the store is read-only.
     #[allow(unused)]
-    flock: flock::Flock,
+    flock: Option<flock::Flock>,
+    /// Whether the store is opened read-only, next to a writer in another process.
+    read_only: bool,
+    /// Whether a sync has failed. The in-memory state has moved past the files on disk then, so
+    /// every later commit and read fails until the store is reopened.
+    poisoned: AtomicBool,
+    /// The database directory.
+    path: PathBuf,
+    /// The expiries of the values written with a time-to-live, as of the last commit.
+    expiries: RwLock<ttl::Expiries>,
+    clock: Arc<dyn Clock>,
 
     // Retained for the lifetime of the store.
     _db_dir_fd: Arc<File>,
@@ -54,75 +92,184 @@ struct Shared {
 
 impl Store {
     /// Open the store with the provided `Options`.
-    pub fn open(o: &crate::Options, page_pool: PagePool) -> anyhow::Result<Self> {
+    ///
+    /// `hasher_fingerprint` identifies the hash algorithm of the trie. Opening fails if the
+    /// database was created with a different one.
+    pub fn open(
+        o: &crate::Options,
+        page_pool: PagePool,
+        metrics: Metrics,
+        hasher_fingerprint: [u8; 32],
+    ) -> anyhow::Result<Self> {
+        if o.encryption_key.is_some() && (o.rollback || o.value_log_threshold.is_some()) {
+            anyhow::bail!("encryption is not supported with rollback or the value log");
+        }
+        if o.encryption_key.is_some() && o.wal_archive.is_some() {
+            anyhow::bail!("encryption is not supported with the WAL archive");
+        }
+
         let db_dir_fd = if !o.path.exists() {
+            if o.read_only {
+                anyhow::bail!("the database doesn't exist and can't be created read-only");
+            }
             // NB: note TOCTOU here. Deemed acceptable for this case.
-            create(&page_pool, &o)?
+            create(&page_pool, o, hasher_fingerprint)?
         } else {
             let mut options = OpenOptions::new();
             options.read(true);
             options.open(&o.path)?
         };
         let db_dir_fd = Arc::new(db_dir_fd);
-        let flock = flock::Flock::lock(&o.path, ".lock")?;
+        // A read-only store doesn't take the lock, which is held by the writer.
+        let flock = if o.read_only {
+            None
+        } else {
+            Some(flock::Flock::lock(&o.path, ".lock")?)
+        };
 
         cfg_if::cfg_if! {
             if #[cfg(target_os = "linux")] {
+                // Pages are 4 KiB, so direct I/O requires the file system to accept that alignment.
+                // Devices with larger logical blocks, and tmpfs, fall back to buffered I/O.
                 let is_tmpfs = crate::sys::linux::tmpfs_check(&db_dir_fd);
-                let iopoll = !is_tmpfs;
+                let dio_alignment =
+                    crate::sys::linux::direct_io_alignment(&File::open(o.path.join("meta"))?);
+                let direct_io = !is_tmpfs
+                    && dio_alignment.is_none_or(|a| (io::PAGE_SIZE as u32).is_multiple_of(a));
+                let mode = if direct_io { o.io_uring_mode } else { IoUringMode::Interrupt };
             } else {
-                let iopoll = true;
+                let direct_io = false;
+                let mode = o.io_uring_mode;
             }
         }
 
-        let io_pool = io::start_io_pool(o.io_workers, iopoll, page_pool.clone());
+        #[cfg(feature = "encryption")]
+        let cipher = o
+            .encryption_key
+            .map(|key| Arc::new(io::PageCipher::new(&key)));
+        #[cfg(not(feature = "encryption"))]
+        let cipher = None;
+        let io_limits = io::IoLimits {
+            submit_batch: o.io_submit_batch,
+            max_in_flight: o.io_max_in_flight,
+        };
+        let io_pool = io::start_io_pool(
+            o.io_workers,
+            o.io_backend,
+            mode,
+            &o.thread_config,
+            page_pool.clone(),
+            io::IoPoolConfig {
+                tuning: io::IoTuning::new(io_limits, o.durability, o.io_prioritize_reads),
+                cipher,
+                fault_injector: o.fault_injector.clone(),
+                background_errors: BackgroundErrors::new(o.on_background_error.clone()),
+            },
+        )?;
+        #[cfg(feature = "crash-simulation")]
+        let io_pool = {
+            let mut io_pool = io_pool;
+            if let Some(ref crash_simulator) = o.crash_simulator {
+                io_pool.set_crash_simulator(crash_simulator.clone());
+            }
+            io_pool
+        };
 
         let meta_fd = {
             let mut options = OpenOptions::new();
-            options.read(true).write(true);
+            options.read(true).write(!o.read_only);
             #[cfg(target_os = "linux")]
-            if !is_tmpfs {
+            if direct_io {
                 options.custom_flags(libc::O_DIRECT);
             }
             options.open(&o.path.join("meta"))?
         };
 
+        let mut meta = meta::Meta::read(&page_pool, &meta_fd)?;
+        meta.validate()?;
+        match (meta.encryption_key_check, o.encryption_key) {
+            (None, None) => {}
+            (None, Some(_)) => anyhow::bail!("the database is not encrypted"),
+            (Some(_), None) => anyhow::bail!("the database is encrypted and requires a key"),
+            (Some(check), Some(key)) if check != io::PageCipher::key_check(&key) => {
+                anyhow::bail!("the database was encrypted with a different key")
+            }
+            (Some(_), Some(_)) => {}
+        }
+        // Databases of earlier versions adopt the hash algorithm they are opened with on the
+        // next sync.
+        if meta
+            .hasher_fingerprint
+            .is_some_and(|fingerprint| fingerprint != hasher_fingerprint)
+        {
+            anyhow::bail!("the database was created with a different hash algorithm");
+        }
+        if meta.bitbox_resize_num_pages.is_none() && !o.read_only {
+            // Rename or discard the file of a resize not recorded in the manifest.
+            bitbox::resize::settle(&o.path, meta.bitbox_num_pages)?;
+        }
+
         let ln_fd = {
             let mut options = OpenOptions::new();
-            options.read(true).write(true);
+            options.read(true).write(!o.read_only);
             #[cfg(target_os = "linux")]
-            if !is_tmpfs {
+            if direct_io {
                 options.custom_flags(libc::O_DIRECT);
             }
             Arc::new(options.open(&o.path.join("ln"))?)
         };
         let bbn_fd = {
             let mut options = OpenOptions::new();
-            options.read(true).write(true);
+            options.read(true).write(!o.read_only);
             #[cfg(target_os = "linux")]
-            if !is_tmpfs {
+            if direct_io {
                 options.custom_flags(libc::O_DIRECT);
             }
             Arc::new(options.open(&o.path.join("bbn"))?)
         };
         let ht_fd = {
             let mut options = OpenOptions::new();
-            options.read(true).write(true);
+            options.read(true).write(!o.read_only);
             #[cfg(target_os = "linux")]
-            if !is_tmpfs {
+            if direct_io {
                 options.custom_flags(libc::O_DIRECT);
             }
             options.open(&o.path.join("ht"))?
         };
+        let ht_resize_fd = meta
+            .bitbox_resize_num_pages
+            .map(|num_pages| {
+                let mut options = OpenOptions::new();
+                options.read(true).write(!o.read_only);
+                options
+                    .open(o.path.join(bitbox::resize::RESIZE_FILE))
+                    .map(|fd| (num_pages, fd))
+            })
+            .transpose()?;
         let wal_fd = {
             let options = &mut OpenOptions::new();
-            options.read(true).write(true);
+            options.read(true).write(!o.read_only);
             #[cfg(target_os = "linux")]
-            if !is_tmpfs {
+            if direct_io {
                 options.custom_flags(libc::O_DIRECT);
             }
             options.open(&o.path.join("wal"))?
         };
+        // The mirror may reside on a different file system, so it doesn't use direct I/O. It is
+        // synced on every writeout regardless.
+        let wal_mirror_fd = o
+            .wal_mirror
+            .as_ref()
+            .filter(|_| !o.read_only)
+            .map(|path| {
+                OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(path)
+            })
+            .transpose()?;
 
         #[cfg(target_os = "macos")]
         {
@@ -136,8 +283,43 @@ impl Store {
             }
         }
 
-        let meta = meta::Meta::read(&page_pool, &meta_fd)?;
-        meta.validate()?;
+        let data_fds = {
+            use std::os::fd::AsRawFd as _;
+            [
+                ("ht", ht_fd.as_raw_fd()),
+                ("ln", ln_fd.as_raw_fd()),
+                ("bbn", bbn_fd.as_raw_fd()),
+            ]
+        };
+        #[cfg(feature = "crash-simulation")]
+        if let Some(ref crash_simulator) = o.crash_simulator {
+            use crate::SyncedFile;
+            let synced_files = [
+                SyncedFile::HashTable,
+                SyncedFile::Leaves,
+                SyncedFile::Branches,
+            ];
+            for ((name, fd), synced_file) in data_fds.into_iter().zip(synced_files) {
+                crash_simulator.register(synced_file, fd, &o.path.join(name))?;
+            }
+        }
+
+        if o.read_only {
+            // The writer is in the middle of a sync or of a reseed, so the hash-table may not
+            // match the manifest yet. The WAL is written before the manifest and truncated once
+            // the hash-table has been written, so an empty WAL after reading the manifest means
+            // the hash-table matches it, unless another sync completes meanwhile. That is caught
+            // by checking the manifest once everything has been read, see `Store::check_snapshot`.
+            if wal_fd.metadata()?.len() > 0 || bitbox::reseed::in_progress(&o.path) {
+                anyhow::bail!("the database is being written to, retry opening it read-only");
+            }
+        } else if let Some(seed) = bitbox::reseed::pending(&o.path)? {
+            // Finish a reseed of the hash-table interrupted by a crash.
+            bitbox::reseed::apply(&o.path, &page_pool, &ht_fd)?;
+            meta.bitbox_seed = seed;
+            Meta::write(&page_pool, &meta_fd, &meta)?;
+            bitbox::reseed::finish(&o.path)?;
+        }
         let values = beatree::Tree::open(
             page_pool.clone(),
             &io_pool,
@@ -148,16 +330,52 @@ impl Store {
             bbn_fd,
             ln_fd,
             o.commit_concurrency,
+            o.value_compression,
+            beatree::ValueLog::open(o.path.join("vlog"), o.value_log_threshold)?,
+            &o.thread_config,
         )?;
-        let pages = bitbox::DB::open(
+        if o.wal_sink_quorum.is_some_and(|q| q > o.wal_sinks.len()) {
+            anyhow::bail!("WAL sink quorum exceeds the number of WAL sinks");
+        }
+        let mut pages = bitbox::DB::open(
             meta.bitbox_num_pages,
             meta.bitbox_seed,
             page_pool.clone(),
+            o.path.clone(),
             ht_fd,
+            ht_resize_fd,
             wal_fd,
+            wal_mirror_fd,
+            bitbox::WalSinks::new(
+                o.wal_sinks.clone(),
+                o.wal_sink_quorum.unwrap_or(o.wal_sinks.len()),
+                io_pool.background_errors().clone(),
+            ),
+            io_pool.clone(),
+            o.hashtable_compaction_budget,
+            o.hashtable_resize_budget,
+            &o.thread_config,
+            &bitbox::WalReplay {
+                sync_seqn: meta.sync_seqn,
+                wal_header: meta.version >= meta::WAL_HEADER_VERSION,
+                checkpoint_path: o.path.join("wal-replay"),
+                progress: o.wal_replay_progress.clone(),
+            },
         )?;
-        let rollback = o
-            .rollback
+        if meta.version < meta::WAL_HEADER_VERSION && !o.read_only {
+            // The WAL has been replayed and truncated. Upgrade the manifest before the next sync
+            // writes a WAL with a header, so that a crash before its manifest is written doesn't
+            // leave a WAL which would be read as one without.
+            meta.version = meta::VERSION;
+            Meta::write(&page_pool, &meta_fd, &meta)?;
+        }
+        if o.reseed_hashtable && o.bitbox_seed != meta.bitbox_seed && !o.read_only {
+            pages = pages.reseed(&o.path, o.bitbox_seed)?;
+            meta.bitbox_seed = o.bitbox_seed;
+            Meta::write(&page_pool, &meta_fd, &meta)?;
+            bitbox::reseed::finish(&o.path)?;
+        }
+        let rollback = (o.rollback && !o.read_only)
             .then(|| {
                 Rollback::read(
                     o.max_rollback_log_len,
@@ -169,12 +387,29 @@ impl Store {
                 )
             })
             .transpose()?;
+        let last_block = meta.block_number.map(|block| (meta.sync_seqn, block));
+        let (block_index, aux_column) = if o.read_only {
+            (
+                BlockIndex::open_read_only(&o.path, last_block)?,
+                AuxColumn::open_read_only(&o.path, meta.sync_seqn, io_pool.cipher().cloned())?,
+            )
+        } else {
+            (
+                BlockIndex::open(&o.path, last_block)?,
+                AuxColumn::open(&o.path, meta.sync_seqn, io_pool.cipher().cloned())?,
+            )
+        };
+        let expiries = ttl::Expiries::load(&aux_column)?;
         Ok(Self {
             sync: Arc::new(Mutex::new(sync::Sync::new(
-                meta.sync_seqn,
-                meta.bitbox_num_pages,
-                meta.bitbox_seed,
+                &meta,
+                hasher_fingerprint,
                 o.panic_on_sync,
+                // Without direct I/O, the pages would be read back from the page cache.
+                o.verify_ht_writes && direct_io,
+                block_index,
+                aux_column,
+                o.wal_archive.clone(),
             ))),
             shared: Arc::new(Shared {
                 rollback,
@@ -182,13 +417,27 @@ impl Store {
                 values,
                 pages,
                 io_pool,
+                metrics,
                 _db_dir_fd: db_dir_fd,
                 meta_fd,
+                data_fds,
                 flock,
+                read_only: o.read_only,
+                poisoned: AtomicBool::new(false),
+                path: o.path.clone(),
+                logical_reads: AtomicU64::new(0),
+                commit_coalescing: o.commit_coalescing,
+                expiries: RwLock::new(expiries),
+                clock: o.clock.clone(),
             }),
         })
     }
 
+    /// Iterate over the leaves of the value store. See [`beatree::LeafIter`].
+    pub fn btree_leaves(&self) -> beatree::LeafIter {
+        self.shared.values.read_transaction().leaves()
+    }
+
     /// Returns a handle to the rollback object. `None` if the rollback feature is not enabled.
     pub fn rollback(&self) -> Option<&Rollback> {
         self.shared.rollback.as_ref()
@@ -196,7 +445,271 @@ impl Store {
 
     /// Loads the flat value stored under the given key.
     pub fn load_value(&self, key: KeyPath) -> anyhow::Result<Option<Vec<u8>>> {
-        Ok(self.shared.values.lookup(key))
+        self.load_value_with(key, beatree::Admission::Hot, ReadConsistency::Latest)
+    }
+
+    /// Loads the flat value stored under the given key as of the given consistency, admitting the
+    /// b-tree leaf holding it to the leaf cache according to the given policy.
+    pub fn load_value_with(
+        &self,
+        key: KeyPath,
+        admission: beatree::Admission,
+        consistency: ReadConsistency,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        self.record_logical_reads(1);
+        let value = self.shared.values.lookup(key, admission, consistency);
+        self.check_snapshot()?;
+        Ok(value)
+    }
+
+    /// Loads the flat value stored under the given key like [`Self::load_value_with`], recording
+    /// the steps taken.
+    pub fn explain_value(
+        &self,
+        key: KeyPath,
+        consistency: ReadConsistency,
+        steps: &mut crate::explain::StepRecorder,
+    ) -> Option<Vec<u8>> {
+        self.record_logical_reads(1);
+        self.shared.values.explain_lookup(key, consistency, steps)
+    }
+
+    /// Loads the flat value stored under the given key like [`Self::load_value_with`], without
+    /// blocking the thread on I/O.
+    pub fn load_value_async(
+        &self,
+        key: KeyPath,
+        admission: beatree::Admission,
+        consistency: ReadConsistency,
+    ) -> beatree::LookupFuture {
+        self.record_logical_reads(1);
+        self.shared.values.lookup_async(key, admission, consistency)
+    }
+
+    /// Loads the flat value stored under the given key like [`Self::load_value_with`], returning a
+    /// reader of it. Overflow values are read from disk as the reader is consumed.
+    pub fn load_value_streaming(
+        &self,
+        key: KeyPath,
+        consistency: ReadConsistency,
+    ) -> Option<crate::ValueReader> {
+        self.record_logical_reads(1);
+        self.shared.values.lookup_streaming(key, consistency)
+    }
+
+    /// Loads the first `limit` flat values stored under keys in the inclusive range
+    /// `start..=end`, in key order.
+    pub fn load_value_range(
+        &self,
+        start: KeyPath,
+        end: KeyPath,
+        limit: usize,
+    ) -> Vec<(KeyPath, Vec<u8>)> {
+        let values = self.shared.values.range(start, end, limit);
+        self.record_logical_reads(values.len() as u64);
+        values
+    }
+
+    /// Record that the given number of keys have been accessed other than through
+    /// [`Self::load_value`], e.g. by having their merkle paths updated.
+    pub fn record_logical_reads(&self, n: u64) {
+        self.shared.logical_reads.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Returns the number of keys accessed and the number of pages physically read from the
+    /// hash-table and the b-tree since the store was opened.
+    pub fn read_totals(&self) -> (u64, u64) {
+        let logical_reads = self.shared.logical_reads.load(Ordering::Relaxed);
+        let physical_page_reads =
+            self.shared.pages.probed_buckets() + self.shared.values.page_reads();
+        (logical_reads, physical_page_reads)
+    }
+
+    /// Returns statistics about the hash-table storing the merkle trie pages.
+    pub fn hash_table_stats(&self) -> bitbox::HashTableStats {
+        self.shared.pages.stats()
+    }
+
+    /// Read every occupied bucket of the hash-table. See [`bitbox::DB::check_buckets`].
+    pub fn check_buckets(&self) -> anyhow::Result<Vec<bitbox::CheckedBucket>> {
+        // Holding the lock keeps syncs from running concurrently.
+        let _sync = self.sync.lock();
+        self.shared.pages.check_buckets()
+    }
+
+    /// Start growing the hash-table to the given number of buckets. See [`bitbox::resize`].
+    pub fn resize_hash_table(&self, num_pages: u32) -> anyhow::Result<()> {
+        // Holding the lock keeps syncs from running concurrently.
+        let _sync = self.sync.lock();
+        let buckets = self.shared.pages.num_pages().0;
+        if num_pages <= buckets {
+            anyhow::bail!("the hash-table can only grow, it has {buckets} buckets already");
+        }
+        self.shared.pages.begin_resize(num_pages)
+    }
+
+    /// Give back the disk space the store no longer needs. See [`crate::Nomt::shrink`].
+    ///
+    /// Commits held back by commit coalescing must have been flushed. `page_cache` is the page
+    /// cache of the merkle trie, which is written out by the syncs performed.
+    pub fn shrink(&self, page_cache: &PageCache) -> anyhow::Result<()> {
+        let mut sync = self.sync.lock();
+        assert!(sync.pending.is_none(), "shrink with commits held back");
+        if !self.shared.io_pool.cow_files().is_empty() {
+            // Truncated pages would not be copied to the snapshot.
+            anyhow::bail!("shrink: a snapshot is being taken");
+        }
+
+        // Leave the hash-table at most half full, so that probe sequences stay short.
+        let stats = self.shared.pages.stats();
+        let num_pages = (stats.occupied_buckets * 2).max(MIN_SHRUNK_BUCKETS);
+        if stats.resizing_to.is_none() && num_pages < stats.buckets {
+            self.shared.pages.begin_resize(num_pages as u32)?;
+        }
+        self.shared.values.request_trim();
+
+        // Syncs without changes move the pages to the smaller hash-table and trim the b-tree.
+        let mut pages_to_migrate = usize::MAX;
+        loop {
+            self.sync_maintenance(&mut sync, page_cache)?;
+            let stats = self.shared.pages.stats();
+            if stats.resizing_to.is_none() {
+                return Ok(());
+            }
+            if stats.pages_to_migrate >= pages_to_migrate {
+                anyhow::bail!("shrink: no pages could be moved to the smaller hash-table");
+            }
+            pages_to_migrate = stats.pages_to_migrate;
+        }
+    }
+
+    /// Statistics on the space used by the b-tree. See [`crate::Nomt::compaction_stats`].
+    pub fn compaction_stats(&self) -> beatree::CompactionStats {
+        self.shared.values.compaction_stats()
+    }
+
+    /// Rewrite the b-tree to give back the space left by deleted values. See
+    /// [`crate::Nomt::compact`].
+    ///
+    /// Commits held back by commit coalescing must have been flushed. `page_cache` is the page
+    /// cache of the merkle trie, which is written out by the syncs performed.
+    pub fn compact(&self, page_cache: &PageCache) -> anyhow::Result<()> {
+        let mut sync = self.sync.lock();
+        assert!(sync.pending.is_none(), "compact with commits held back");
+        if !self.shared.io_pool.cow_files().is_empty() {
+            // Truncated pages would not be copied to the snapshot.
+            anyhow::bail!("compact: a snapshot is being taken");
+        }
+
+        // Write out the deletions still staged. Each following sync without changes trims the
+        // files and orders the free-lists, so that the next relocation moves pages as far towards
+        // the start of the leaf store as possible. Every relocation frees the highest used page,
+        // unless rewriting leaves took more pages than planned.
+        self.sync_maintenance(&mut sync, page_cache)?;
+        let mut highest_moved = None;
+        loop {
+            self.shared.values.request_ordered_trim();
+            self.sync_maintenance(&mut sync, page_cache)?;
+            let Some(pn) = self.shared.values.stage_relocation() else {
+                return Ok(());
+            };
+            if highest_moved.is_some_and(|highest| pn >= highest) {
+                // Relocation doesn't make progress. Write out what is staged and stop there.
+                self.shared.values.request_trim();
+                return self.sync_maintenance(&mut sync, page_cache);
+            }
+            highest_moved = Some(pn);
+            self.sync_maintenance(&mut sync, page_cache)?;
+        }
+    }
+
+    /// Perform a sync carrying no commit, keeping the commit token and block number of the last
+    /// one.
+    fn sync_maintenance(
+        &self,
+        sync: &mut sync::Sync,
+        page_cache: &PageCache,
+    ) -> anyhow::Result<()> {
+        let commit_token = sync.commit_token;
+        let block_number = sync.block_number;
+        self.check_poisoned()?;
+        self.sync(
+            sync,
+            self.new_value_tx(),
+            Vec::new(),
+            page_cache.clone(),
+            std::iter::empty().collect(),
+            commit_token,
+            block_number,
+        )
+    }
+
+    /// Starts loading the b-tree leaf holding the value stored under the given key in the
+    /// background.
+    pub fn prefetch_value(&self, key: KeyPath) {
+        self.shared.values.prefetch(key)
+    }
+
+    /// Starts loading the b-tree leaves holding the values stored under the given keys in the
+    /// background, all at once. See [`beatree::Tree::prefetch_batch`].
+    pub fn prefetch_values(&self, keys: Vec<KeyPath>) {
+        self.shared.values.prefetch_batch(keys)
+    }
+
+    /// Starts loading up to `max_leaves` b-tree leaves holding the values stored under keys in the
+    /// inclusive range `start..=end` in the background. See [`beatree::Tree::prefetch_range`].
+    pub fn prefetch_value_range(&self, start: KeyPath, end: KeyPath, max_leaves: usize) {
+        self.shared.values.prefetch_range(start, end, max_leaves)
+    }
+
+    /// The number of value fetches which attached to a fetch already in progress.
+    pub fn deduplicated_value_fetches(&self) -> u64 {
+        self.shared.values.deduplicated_leaf_fetches()
+    }
+
+    /// Returns the expiry of the value stored under the given key, in milliseconds since the unix
+    /// epoch, if it was written with a time-to-live.
+    pub fn expiry(&self, key: KeyPath) -> Option<u64> {
+        self.shared.expiries.read().get(&key)
+    }
+
+    /// The clock the store was opened with. See [`crate::Options::clock`].
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.shared.clock
+    }
+
+    /// Whether the value stored under the given key has expired at the given time, in
+    /// milliseconds since the unix epoch.
+    pub fn is_expired(&self, key: KeyPath, now: u64) -> bool {
+        let expiries = self.shared.expiries.read();
+        !expiries.is_empty() && expiries.is_expired(&key, now)
+    }
+
+    /// Remove the entries whose values have expired at the given time from the given ones.
+    pub fn retain_unexpired<V>(&self, entries: &mut Vec<(KeyPath, V)>, now: u64) {
+        let expiries = self.shared.expiries.read();
+        if expiries.is_empty() {
+            return;
+        }
+        entries.retain(|(key, _)| !expiries.is_expired(key, now));
+    }
+
+    /// Returns the keys whose values have expired at the given time, in order of expiry.
+    pub fn expired_keys(&self, now: u64) -> Vec<KeyPath> {
+        self.shared.expiries.read().expired(now).collect()
+    }
+
+    /// Loads the flat value stored under the given key without performing any I/O.
+    ///
+    /// Returns `None` if the value cannot be determined without I/O.
+    pub fn load_value_cached(
+        &self,
+        key: KeyPath,
+        consistency: ReadConsistency,
+    ) -> Option<Option<Vec<u8>>> {
+        let value = self.shared.values.lookup_cached(key, consistency)?;
+        self.record_logical_reads(1);
+        Some(value)
     }
 
     /// Loads the given page, blocking the current thread.
@@ -222,6 +735,11 @@ impl Store {
         PageLoader { inner: page_loader }
     }
 
+    /// The page pool of the store.
+    pub fn page_pool(&self) -> &PagePool {
+        &self.shared.page_pool
+    }
+
     /// Access the underlying IoPool.
     pub fn io_pool(&self) -> &IoPool {
         &self.shared.io_pool
@@ -229,32 +747,329 @@ impl Store {
 
     /// Create a new raw value transaction to be applied against this database.
     pub fn new_value_tx(&self) -> ValueTransaction {
-        ValueTransaction { batch: Vec::new() }
+        ValueTransaction {
+            batch: Vec::new(),
+            aux: AuxWrites::new(),
+            expiries: Vec::new(),
+        }
+    }
+
+    /// Returns the sequence number of the last sync.
+    pub fn sync_seqn(&self) -> u32 {
+        self.sync.lock().sync_seqn
+    }
+
+    /// Read the sequence number of the last sync from the manifest on disk, which differs from
+    /// [`Self::sync_seqn`] if another process has synced since the store was opened.
+    pub fn manifest_sync_seqn(&self) -> anyhow::Result<u32> {
+        let meta = meta::Meta::read(&self.shared.page_pool, &self.shared.meta_fd)?;
+        Ok(meta.sync_seqn)
+    }
+
+    /// Fail if the store is read-only and its writer has synced since it was opened.
+    ///
+    /// The writer overwrites the hash-table pages in place once the manifest of a sync is written,
+    /// and reuses the b-tree pages of the state before it in the sync after. Pages read from disk
+    /// before this succeeds thus belong to the state the store was opened with. Also fails if the
+    /// store is poisoned, see [`Self::check_poisoned`].
+    pub fn check_snapshot(&self) -> anyhow::Result<()> {
+        self.check_poisoned()?;
+        if self.shared.read_only && self.manifest_sync_seqn()? != self.sync_seqn() {
+            anyhow::bail!(
+                "the writer has synced since the database was opened read-only, refresh it"
+            );
+        }
+        Ok(())
+    }
+
+    /// Fail if a sync has failed since the store was opened.
+    ///
+    /// A failed sync may leave the hash-table, the b-tree and the page cache ahead of the files on
+    /// disk, so the store refuses to commit or to read until it is reopened, which recovers the
+    /// state of the last successful sync.
+    pub fn check_poisoned(&self) -> anyhow::Result<()> {
+        if self.shared.poisoned.load(Ordering::Acquire) {
+            anyhow::bail!("a sync has failed, the database must be reopened");
+        }
+        Ok(())
+    }
+
+    /// Returns whether the store is opened read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.shared.read_only
+    }
+
+    /// Returns the token supplied with the last commit, if any.
+    pub fn last_commit_token(&self) -> Option<[u8; 32]> {
+        self.sync.lock().commit_token
+    }
+
+    /// Returns the block number supplied with the last commit, if any.
+    pub fn last_block_number(&self) -> Option<u64> {
+        self.sync.lock().block_number
+    }
+
+    /// Returns the sequence number of the last sync which carried the given block number.
+    pub fn seqn_for_block(&self, block_number: u64) -> Option<u32> {
+        self.sync.lock().block_index.seqn_for_block(block_number)
+    }
+
+    /// Returns the synced records of the auxiliary column whose keys start with the given prefix,
+    /// in key order.
+    pub fn read_aux_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.sync
+            .lock()
+            .aux_column
+            .prefix(prefix)
+            .map(|(key, value)| (key.to_vec(), value.to_vec()))
+            .collect()
+    }
+
+    /// Returns the value stored under the given key in the auxiliary column, including the writes
+    /// of commits held back by commit coalescing.
+    pub fn read_aux(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let sync = self.sync.lock();
+        if let Some(value) = sync
+            .pending
+            .as_ref()
+            .and_then(|pending| pending.aux.get(key))
+        {
+            return value.clone();
+        }
+        sync.aux_column.get(key).map(|value| value.to_vec())
     }
 
     /// Atomically apply the given transaction.
     ///
+    /// The commit token and the block number are persisted in the manifest along with the rest of
+    /// the transaction.
+    ///
     /// After this function returns, accessor methods such as [`Self::load_page`] will return the
     /// updated values.
+    ///
+    /// If commit coalescing is enabled, the transaction may be held back and synced along with the
+    /// following ones. Returns whether this commit, and any held back before it, has been synced.
     pub fn commit(
         &self,
         value_tx: ValueTransaction,
         page_cache: PageCache,
         page_diffs: merkle::PageDiffs,
+        commit_token: Option<[u8; 32]>,
+        block_number: Option<u64>,
+    ) -> anyhow::Result<bool> {
+        let mut sync = self.sync.lock();
+        self.check_poisoned()?;
+
+        if !value_tx.expiries.is_empty() {
+            let mut expiries = self.shared.expiries.write();
+            for (key, expiry) in &value_tx.expiries {
+                expiries.set(*key, *expiry);
+            }
+        }
+
+        let Some((max_commits, max_delay)) = self.shared.commit_coalescing else {
+            self.sync(
+                &mut sync,
+                value_tx,
+                Vec::new(),
+                page_cache,
+                page_diffs,
+                commit_token,
+                block_number,
+            )?;
+            return Ok(true);
+        };
+
+        let archive_values = sync.wal_archive.is_some();
+        let pending = sync
+            .pending
+            .get_or_insert_with(|| sync::Pending::new(page_cache, self.shared.clock.monotonic()));
+        if archive_values {
+            pending.values.extend(value_tx.batch.iter().cloned());
+        }
+        // Stage the values right away so that they can be read before the sync.
+        self.shared.values.stage(value_tx.batch);
+        pending.push(page_diffs, commit_token, block_number, value_tx.aux);
+        let delay = self.shared.clock.monotonic().saturating_sub(pending.since);
+        if pending.commits < max_commits && delay < max_delay {
+            return Ok(false);
+        }
+        self.sync_pending(&mut sync)
+    }
+
+    /// Copy the database to a new directory at the given path, sharing the data of the files
+    /// where the file system supports it.
+    ///
+    /// Commits held back by commit coalescing must have been flushed.
+    pub fn fork_to(&self, path: &Path) -> anyhow::Result<()> {
+        // Holding the lock keeps syncs from modifying the files while they are copied.
+        let sync = self.sync.lock();
+        assert!(sync.pending.is_none(), "fork with commits held back");
+        copy_database(&self.shared.path, path)
+    }
+
+    /// Copy the database as of the last sync to a new directory at the given path, while commits
+    /// continue.
+    ///
+    /// Syncs are blocked only while the small files are copied and the files of the value log,
+    /// which are never modified, are linked. Afterwards, pages overwritten by
+    /// syncs are copied before being overwritten, see [`io::cow`]. The rollback log is not copied.
+    pub fn snapshot_to(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::create_dir_all(path.parent().unwrap_or(Path::new("")))?;
+        std::fs::create_dir(path)?;
+        let mut files = Vec::new();
+        let res = self.snapshot_files(path, &mut files);
+        for file in &files {
+            self.shared.io_pool.cow_files().unregister(file);
+        }
+        res?;
+        for name in ["meta", "wal", "blocks", "aux"] {
+            File::open(path.join(name))?.sync_all()?;
+        }
+        File::open(path)?.sync_all()?;
+        Ok(())
+    }
+
+    /// Copy the files of the database to `path`, pushing the files registered for copy-on-write
+    /// to `files` so that the caller can unregister them even on failure.
+    fn snapshot_files(
+        &self,
+        path: &Path,
+        files: &mut Vec<Arc<io::cow::CowFile>>,
     ) -> anyhow::Result<()> {
+        {
+            // Holding the lock keeps syncs from modifying the files until they are registered.
+            let _sync = self.sync.lock();
+            if self.shared.pages.num_pages().1.is_some() {
+                anyhow::bail!("can't snapshot while the hash-table is being resized");
+            }
+            let mut meta = Meta::read(&self.shared.page_pool, &self.shared.meta_fd)?;
+            meta.rollback_start_live = 0;
+            meta.rollback_end_live = 0;
+            let meta_fd = File::create(path.join("meta"))?;
+            Meta::write(&self.shared.page_pool, &meta_fd, &meta)?;
+            for name in ["wal", "blocks", "aux"] {
+                std::fs::copy(self.shared.path.join(name), path.join(name))?;
+            }
+            self.shared.values.link_value_log(&path.join("vlog"))?;
+            for (name, fd) in self.shared.data_fds {
+                // The HT file is replaced when a resize of the hash-table completes.
+                let fd = if name == "ht" {
+                    self.shared.pages.ht_raw_fd()
+                } else {
+                    fd
+                };
+                let src = File::open(self.shared.path.join(name))?;
+                let dst = File::create(path.join(name))?;
+                files.push(self.shared.io_pool.cow_files().register(
+                    fd,
+                    src,
+                    dst,
+                    self.shared.page_pool.clone(),
+                )?);
+            }
+        }
+        for file in files.iter() {
+            file.copy_all()?;
+        }
+        Ok(())
+    }
+
+    /// Discard the rollback deltas of all but the last `keep` commits and delete them from disk.
+    ///
+    /// Commits held back by commit coalescing are synced first. No session must be active.
+    pub fn prune_rollback(&self, keep: usize) -> anyhow::Result<()> {
+        let Some(ref rollback) = self.shared.rollback else {
+            anyhow::bail!("rollback: not enabled");
+        };
+        let mut sync = self.sync.lock();
+        self.sync_pending(&mut sync)?;
+        rollback.prune(keep, |start_live, end_live| {
+            let mut meta = Meta::read(&self.shared.page_pool, &self.shared.meta_fd)?;
+            meta.rollback_start_live = start_live;
+            meta.rollback_end_live = end_live;
+            Meta::write(&self.shared.page_pool, &self.shared.meta_fd, &meta)
+        })
+    }
+
+    /// Collect the value log files in which at most `max_live_ratio` of the bytes are live. See
+    /// [`beatree::Tree::collect_value_log_garbage`].
+    pub fn collect_value_log_garbage(&self, max_live_ratio: f64) -> anyhow::Result<u64> {
+        // Holding the lock keeps syncs from running concurrently.
+        let _sync = self.sync.lock();
+        self.shared.values.collect_value_log_garbage(max_live_ratio)
+    }
+
+    /// Evict all pages but the root page from the page cache and all leaves from the leaf cache.
+    /// If `release_memory` is set, the memory of the free pages of the page pool is returned to
+    /// the OS.
+    ///
+    /// No session must be active and all commits must have been synced.
+    pub fn drop_caches(&self, page_cache: &PageCache, release_memory: bool) {
+        // Wait for any sync still running.
+        let _sync = self.sync.lock();
+        page_cache.clear();
+        self.shared.values.drop_caches();
+        if release_memory {
+            self.shared.page_pool.release_free_pages();
+        }
+    }
+
+    /// Sync the commits held back by commit coalescing, if any.
+    ///
+    /// Returns whether there were any.
+    pub fn flush(&self) -> anyhow::Result<bool> {
         let mut sync = self.sync.lock();
+        self.sync_pending(&mut sync)
+    }
+
+    fn sync_pending(&self, sync: &mut sync::Sync) -> anyhow::Result<bool> {
+        let Some(pending) = sync.pending.take() else {
+            return Ok(false);
+        };
+        self.check_poisoned()?;
+        let mut value_tx = self.new_value_tx();
+        value_tx.aux = pending.aux;
+        self.sync(
+            sync,
+            value_tx,
+            pending.values,
+            pending.page_cache,
+            pending.page_diffs.into_iter().collect(),
+            pending.commit_token,
+            pending.block_number,
+        )?;
+        Ok(true)
+    }
 
-        sync.sync(
+    /// Sync the given transaction, poisoning the store if the sync fails. See
+    /// [`sync::Sync::sync`] and [`Self::check_poisoned`].
+    fn sync(
+        &self,
+        sync: &mut sync::Sync,
+        value_tx: ValueTransaction,
+        staged_values: Vec<(KeyPath, beatree::ValueChange)>,
+        page_cache: PageCache,
+        page_diffs: merkle::PageDiffs,
+        commit_token: Option<[u8; 32]>,
+        block_number: Option<u64>,
+    ) -> anyhow::Result<()> {
+        let res = sync.sync(
             &self.shared,
             value_tx,
+            staged_values,
             self.shared.pages.clone(),
             self.shared.values.clone(),
             self.shared.rollback.clone(),
             page_cache,
             page_diffs,
-        )
-        .unwrap();
-        Ok(())
+            commit_token,
+            block_number,
+        );
+        if res.is_err() {
+            self.shared.poisoned.store(true, Ordering::Release);
+        }
+        res
     }
 }
 
@@ -262,14 +1077,31 @@ impl Store {
 /// with [`Store::commit`].
 pub struct ValueTransaction {
     batch: Vec<(KeyPath, beatree::ValueChange)>,
+    aux: AuxWrites,
+    expiries: Vec<(KeyPath, Option<u64>)>,
 }
 
 impl ValueTransaction {
     /// Write a value to flat storage.
-    pub fn write_value<T: ValueHasher>(&mut self, path: KeyPath, value: Option<Vec<u8>>) {
+    pub fn write_value<T: ValueHasher>(&mut self, path: KeyPath, value: Option<ValueHandle>) {
         self.batch
             .push((path, beatree::ValueChange::from_option::<T>(value)))
     }
+
+    /// Write a value to the auxiliary column, which is not part of the merkle trie.
+    pub fn write_aux(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) {
+        self.aux.insert(key, value);
+    }
+
+    /// Set the expiry of the value stored under the given key, in milliseconds since the unix
+    /// epoch, or clear it if `None`.
+    pub fn set_expiry(&mut self, key: KeyPath, expiry: Option<u64>) {
+        self.aux.insert(
+            ttl::aux_key(&key),
+            expiry.map(|expiry| expiry.to_le_bytes().to_vec()),
+        );
+        self.expiries.push((key, expiry));
+    }
 }
 
 /// An atomic transaction on merkle tree pages to be applied against the store
@@ -289,6 +1121,15 @@ impl MerkleTransaction {
         page: &FatPage,
         page_diff: PageDiff,
     ) -> BucketIndex {
+        let (bucket, page_diff) = match bucket {
+            Some(bucket) if self.bucket_allocator.is_retiring(bucket) => {
+                // The hash-table is being resized. Move the page to the new table along with the
+                // write. The new bucket may have held another page, so the whole page is logged.
+                self.delete_page(page_id.clone(), bucket);
+                (None, PageDiff::full())
+            }
+            bucket => (bucket, page_diff),
+        };
         let bucket_index =
             bucket.unwrap_or_else(|| self.bucket_allocator.allocate(page_id.clone()));
 
@@ -310,6 +1151,55 @@ impl MerkleTransaction {
     }
 }
 
+/// Copy the database directory `src` to a new directory at `dst`, sharing the data of the files
+/// where the file system supports it. The database must not be written to meanwhile.
+pub fn copy_database(src: &Path, dst: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dst.parent().unwrap_or(Path::new("")))?;
+    std::fs::create_dir(dst)?;
+    fork_dir(src, dst)?;
+    File::open(dst)?.sync_all()?;
+    Ok(())
+}
+
+/// Copy the files of the database directory `src` to the existing directory `dst`, recursively.
+fn fork_dir(src: &Path, dst: &Path) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == ".lock" {
+            continue;
+        }
+        let (src, dst) = (entry.path(), dst.join(name));
+        if entry.file_type()?.is_dir() {
+            std::fs::create_dir(&dst)?;
+            fork_dir(&src, &dst)?;
+        } else {
+            fork_file(&src, &dst)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy a file, sharing its data with the copy on file systems supporting reflinks.
+///
+/// All database files are modified in place, so hard links can't be used. Where reflinks are not
+/// supported, the file is copied with [`std::fs::copy`], which may still avoid copying the data,
+/// e.g. on NFS or on macOS.
+fn fork_file(src: &Path, dst: &Path) -> anyhow::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        let src_file = File::open(src)?;
+        let dst_file = File::create(dst)?;
+        if crate::sys::linux::reflink(&src_file, &dst_file).is_ok() {
+            dst_file.sync_all()?;
+            return Ok(());
+        }
+    }
+    std::fs::copy(src, dst)?;
+    File::open(dst)?.sync_all()?;
+    Ok(())
+}
+
 /// Creates and initializes a new empty database at the specified path.
 ///
 /// This function:
@@ -318,17 +1208,32 @@ impl MerkleTransaction {
 /// - Returns a file descriptor for the database directory
 ///
 /// The database directory must not exist when calling this function.
-fn create(page_pool: &PagePool, o: &crate::Options) -> anyhow::Result<File> {
+fn create(
+    page_pool: &PagePool,
+    o: &crate::Options,
+    hasher_fingerprint: [u8; 32],
+) -> anyhow::Result<File> {
     // Create the directory and its parent directories.
     std::fs::create_dir_all(&o.path)?;
     let db_dir_fd = std::fs::File::open(&o.path)?;
 
     let meta_fd = std::fs::File::create(o.path.join("meta"))?;
-    let meta = Meta::create_new(o.bitbox_seed, o.bitbox_num_pages);
+    let meta = Meta::create_new(
+        o.bitbox_seed,
+        o.bitbox_num_pages,
+        hasher_fingerprint,
+        o.encryption_key.as_ref().map(io::PageCipher::key_check),
+    );
     Meta::write(page_pool, &meta_fd, &meta)?;
     drop(meta_fd);
 
-    bitbox::create(o.path.clone(), o.bitbox_num_pages, o.preallocate_ht)?;
+    bitbox::create(
+        o.path.clone(),
+        o.bitbox_num_pages,
+        o.preallocate_ht,
+        o.hash_table_creation_progress.as_ref(),
+        &BackgroundErrors::new(o.on_background_error.clone()),
+    )?;
     beatree::create(&o.path)?;
 
     // As the last step, sync the directory. This makes sure that the directory is properly
@@ -1,41 +1,134 @@
 use super::{
+    aux_column::{AuxColumn, AuxWrites},
+    block_index::BlockIndex,
     meta::{self, Meta},
     MerkleTransaction, Shared, ValueTransaction,
 };
-use crate::{beatree, bitbox, merkle, page_cache::PageCache, rollback};
+use crate::{
+    beatree, bitbox, merkle,
+    metrics::Metric,
+    page_cache::PageCache,
+    page_diff::PageDiff,
+    rollback,
+    wal_archive::{WalArchiveCallback, WalSegment},
+    SyncedFile,
+};
+use nomt_core::{page_id::PageId, trie::KeyPath};
+use std::{collections::HashMap, time::Duration};
 
 pub struct Sync {
     pub(crate) sync_seqn: u32,
     pub(crate) bitbox_num_pages: u32,
     pub(crate) bitbox_seed: [u8; 16],
+    pub(crate) encryption_key_check: Option<[u8; 32]>,
+    pub(crate) hasher_fingerprint: [u8; 32],
     pub(crate) panic_on_sync: bool,
+    pub(crate) verify_ht_writes: bool,
+    pub(crate) commit_token: Option<[u8; 32]>,
+    pub(crate) block_number: Option<u64>,
+    pub(crate) block_index: BlockIndex,
+    pub(crate) aux_column: AuxColumn,
+    pub(crate) wal_archive: Option<WalArchiveCallback>,
+    /// Commits which have been coalesced but not synced yet.
+    pub(crate) pending: Option<Pending>,
+}
+
+/// The merkle changes of commits coalesced into the next sync. Their value changes are staged in
+/// the b-tree right away, and only kept here for the WAL archive.
+pub struct Pending {
+    pub(crate) commits: usize,
+    /// The monotonic time of the store's clock at the first commit.
+    pub(crate) since: Duration,
+    pub(crate) page_cache: PageCache,
+    pub(crate) page_diffs: HashMap<PageId, PageDiff>,
+    pub(crate) commit_token: Option<[u8; 32]>,
+    pub(crate) block_number: Option<u64>,
+    pub(crate) aux: AuxWrites,
+    /// The value changes of the commits, in order, if the WAL is archived.
+    pub(crate) values: Vec<(KeyPath, beatree::ValueChange)>,
+}
+
+impl Pending {
+    pub fn new(page_cache: PageCache, since: Duration) -> Self {
+        Self {
+            commits: 0,
+            since,
+            page_cache,
+            page_diffs: HashMap::new(),
+            commit_token: None,
+            block_number: None,
+            aux: AuxWrites::new(),
+            values: Vec::new(),
+        }
+    }
+
+    /// Add a commit, merging its page diffs and auxiliary writes with those of the earlier
+    /// commits.
+    pub fn push(
+        &mut self,
+        page_diffs: merkle::PageDiffs,
+        commit_token: Option<[u8; 32]>,
+        block_number: Option<u64>,
+        aux: AuxWrites,
+    ) {
+        self.commits += 1;
+        for (page_id, page_diff) in page_diffs {
+            match self.page_diffs.get_mut(&page_id) {
+                Some(diff) => diff.merge(&page_diff),
+                None => {
+                    self.page_diffs.insert(page_id, page_diff);
+                }
+            }
+        }
+        self.commit_token = commit_token;
+        self.block_number = block_number;
+        self.aux.extend(aux);
+    }
 }
 
 impl Sync {
     pub fn new(
-        sync_seqn: u32,
-        bitbox_num_pages: u32,
-        bitbox_seed: [u8; 16],
+        meta: &Meta,
+        hasher_fingerprint: [u8; 32],
         panic_on_sync: bool,
+        verify_ht_writes: bool,
+        block_index: BlockIndex,
+        aux_column: AuxColumn,
+        wal_archive: Option<WalArchiveCallback>,
     ) -> Self {
         Self {
-            sync_seqn,
-            bitbox_num_pages,
-            bitbox_seed,
+            sync_seqn: meta.sync_seqn,
+            bitbox_num_pages: meta.bitbox_num_pages,
+            bitbox_seed: meta.bitbox_seed,
+            encryption_key_check: meta.encryption_key_check,
+            hasher_fingerprint,
             panic_on_sync,
+            verify_ht_writes,
+            commit_token: meta.commit_token,
+            block_number: meta.block_number,
+            block_index,
+            aux_column,
+            wal_archive,
+            pending: None,
         }
     }
 
+    /// Sync the given transaction, along with the value changes staged in the b-tree beforehand,
+    /// which are only needed for the WAL archive.
     pub fn sync(
         &mut self,
         shared: &Shared,
         value_tx: ValueTransaction,
+        staged_values: Vec<(KeyPath, beatree::ValueChange)>,
         bitbox: bitbox::DB,
         beatree: beatree::Tree,
         rollback: Option<rollback::Rollback>,
         page_cache: PageCache,
         page_diffs: merkle::PageDiffs,
+        commit_token: Option<[u8; 32]>,
+        block_number: Option<u64>,
     ) -> anyhow::Result<()> {
+        let _sync_timer = shared.metrics.record(Metric::SyncTime);
         self.sync_seqn += 1;
         let sync_seqn = self.sync_seqn;
 
@@ -48,15 +141,40 @@ impl Sync {
             bucket_allocator: bitbox.bucket_allocator(),
             new_pages: Vec::new(),
         };
-        bitbox_sync.begin_sync(page_cache, merkle_tx, page_diffs);
+        let wal_segment = self.wal_archive.is_some().then(|| {
+            WalSegment::new(
+                sync_seqn,
+                commit_token,
+                block_number,
+                staged_values.iter().chain(&value_tx.batch),
+                &value_tx.aux,
+            )
+        });
+        bitbox_sync.begin_sync(sync_seqn, page_cache, merkle_tx, page_diffs);
         beatree_sync.begin_sync(value_tx.batch);
         if let Some(ref mut rollback) = rollback_sync {
             rollback.begin_sync();
         }
 
-        // TODO: comprehensive error handling is coming later.
-        bitbox_sync.wait_pre_meta().unwrap();
-        let beatree_meta_wd = beatree_sync.wait_pre_meta().unwrap();
+        // Both writeouts are waited for before bailing out on either of their errors, so that
+        // neither is still in flight when the sync returns.
+        let wal_timer = shared.metrics.record(Metric::WalSyncTime);
+        let wal_result = bitbox_sync.wait_pre_meta();
+        drop(wal_timer);
+        let beatree_meta_wd = beatree_sync.wait_pre_meta();
+        wal_result?;
+        let beatree_meta_wd = beatree_meta_wd?;
+        shared
+            .metrics
+            .count_n(Metric::BtreeSplits, beatree_meta_wd.splits as u64);
+        shared
+            .metrics
+            .count_n(Metric::BtreeMerges, beatree_meta_wd.merges as u64);
+        let (bitbox_num_pages, bitbox_resize_num_pages) = bitbox_sync.num_pages();
+        self.bitbox_num_pages = bitbox_num_pages;
+        if let (Some(wal_archive), Some(wal_segment)) = (&self.wal_archive, &wal_segment) {
+            wal_archive(wal_segment)?;
+        }
         let (rollback_start_live, rollback_end_live) = match rollback_sync {
             Some(ref rollback) => rollback.wait_pre_meta(),
             None => (0, 0),
@@ -71,11 +189,23 @@ impl Sync {
             bbn_bump: beatree_meta_wd.bbn_bump,
             sync_seqn,
             bitbox_num_pages: self.bitbox_num_pages,
+            bitbox_resize_num_pages,
             bitbox_seed: self.bitbox_seed,
             rollback_start_live,
             rollback_end_live,
+            commit_token,
+            block_number,
+            encryption_key_check: self.encryption_key_check,
+            hasher_fingerprint: Some(self.hasher_fingerprint),
         };
+        self.aux_column.append(sync_seqn, value_tx.aux)?;
+
+        if let Some(fault_injector) = shared.io_pool.fault_injector() {
+            fault_injector.fsync(SyncedFile::Meta)?;
+        }
         Meta::write(&shared.io_pool.page_pool(), &shared.meta_fd, &new_meta)?;
+        self.commit_token = commit_token;
+        self.block_number = block_number;
 
         if self.panic_on_sync {
             panic!("panic_on_sync is true");
@@ -85,7 +215,7 @@ impl Sync {
             rollback.post_meta();
         }
 
-        bitbox_sync.post_meta(shared.io_pool.make_handle())?;
+        bitbox_sync.post_meta(shared.io_pool.make_handle(), self.verify_ht_writes)?;
         beatree_sync.post_meta();
 
         if let Some(ref rollback) = rollback_sync {
@@ -93,6 +223,11 @@ impl Sync {
             rollback.wait_post_meta().unwrap();
         }
 
+        if let Some(block_number) = block_number {
+            self.block_index.append(sync_seqn, block_number)?;
+        }
+        self.aux_column.maybe_compact(sync_seqn)?;
+
         Ok(())
     }
 }
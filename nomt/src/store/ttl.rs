@@ -0,0 +1,82 @@
+//! Expiry of values written with a time-to-live.
+//!
+//! The expiry of a key is the time, in milliseconds since the unix epoch, from which on sessions
+//! treat its value as absent, see [`crate::SessionParams::time`]. Expiries are persisted as records of the auxiliary column under
+//! [`AUX_PREFIX`] followed by the key, so that they are written atomically with the values they
+//! belong to, and are indexed in memory by key and by time.
+//!
+//! Expired values remain in the b-tree and the trie until the commit of a session whose time is
+//! past their expiry, which deletes them.
+
+use std::collections::{BTreeSet, HashMap};
+
+use nomt_core::trie::KeyPath;
+
+use super::aux_column::AuxColumn;
+
+/// The prefix of the auxiliary records holding expiries, within [`super::RESERVED_AUX_PREFIX`].
+pub const AUX_PREFIX: &[u8] = b"\0nomt:ttl:";
+
+/// The key of the auxiliary record holding the expiry of the given key.
+pub fn aux_key(key: &KeyPath) -> Vec<u8> {
+    let mut aux_key = AUX_PREFIX.to_vec();
+    aux_key.extend_from_slice(key);
+    aux_key
+}
+
+/// The expiries of all keys which have one.
+#[derive(Default)]
+pub struct Expiries {
+    by_key: HashMap<KeyPath, u64>,
+    by_time: BTreeSet<(u64, KeyPath)>,
+}
+
+impl Expiries {
+    /// Load the expiries recorded in the auxiliary column.
+    pub fn load(aux_column: &AuxColumn) -> anyhow::Result<Self> {
+        let mut expiries = Expiries::default();
+        for (aux_key, value) in aux_column.prefix(AUX_PREFIX) {
+            let (Ok(key), Ok(expiry)) = (
+                KeyPath::try_from(&aux_key[AUX_PREFIX.len()..]),
+                <[u8; 8]>::try_from(value),
+            ) else {
+                anyhow::bail!("malformed expiry record in the auxiliary column");
+            };
+            expiries.set(key, Some(u64::from_le_bytes(expiry)));
+        }
+        Ok(expiries)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_key.is_empty()
+    }
+
+    /// Returns the expiry of the given key, if it has one.
+    pub fn get(&self, key: &KeyPath) -> Option<u64> {
+        self.by_key.get(key).copied()
+    }
+
+    /// Whether the value of the given key has expired at the given time.
+    pub fn is_expired(&self, key: &KeyPath, now: u64) -> bool {
+        self.get(key).is_some_and(|expiry| expiry <= now)
+    }
+
+    /// Returns the keys which have expired at the given time, in order of expiry.
+    pub fn expired(&self, now: u64) -> impl Iterator<Item = KeyPath> + '_ {
+        self.by_time
+            .iter()
+            .take_while(move |(expiry, _)| *expiry <= now)
+            .map(|(_, key)| *key)
+    }
+
+    /// Set or clear the expiry of the given key.
+    pub fn set(&mut self, key: KeyPath, expiry: Option<u64>) {
+        if let Some(prev) = self.by_key.remove(&key) {
+            self.by_time.remove(&(prev, key));
+        }
+        if let Some(expiry) = expiry {
+            self.by_key.insert(key, expiry);
+            self.by_time.insert((expiry, key));
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Streaming reads and writes of large values. See [`crate::Session::read_streaming`] and
+//! [`crate::Session::write_streaming`].
+//!
+//! A value written from a reader is buffered in pages of the page pool rather than in a single
+//! contiguous allocation. It is hashed page by page and written to overflow pages or the value log
+//! straight from those pages. Only reading the value back as a byte slice, e.g. with
+//! [`crate::Session::read_speculative`] before it has been synced, gathers it into a contiguous
+//! buffer.
+
+use std::{
+    io::{self, Read},
+    sync::OnceLock,
+};
+
+use crate::{
+    beatree::ReadTransaction,
+    io::{PagePool, PooledBuf, PAGE_SIZE},
+    store::Store,
+    ValueHandle,
+};
+
+/// A value read from a reader, held in pool pages.
+pub(crate) struct StreamedValue {
+    buf: PooledBuf,
+    /// The value gathered into a contiguous buffer, once needed.
+    contiguous: OnceLock<Vec<u8>>,
+}
+
+impl StreamedValue {
+    /// Read the value from the reader until its end.
+    pub fn read_from(page_pool: PagePool, mut reader: impl Read) -> io::Result<Self> {
+        let mut buf = PooledBuf::new(page_pool);
+        let mut chunk = vec![0; PAGE_SIZE];
+        loop {
+            match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(StreamedValue {
+            buf,
+            contiguous: OnceLock::new(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// The consecutive chunks of the value, one per page.
+    pub fn chunks(&self) -> impl Iterator<Item = &[u8]> {
+        let len = self.buf.len();
+        self.buf
+            .pages()
+            .enumerate()
+            .map(move |(i, page)| &page[..(len - i * PAGE_SIZE).min(PAGE_SIZE)])
+    }
+
+    /// The value as a contiguous byte slice, gathered on first use.
+    pub fn contiguous(&self) -> &[u8] {
+        self.contiguous.get_or_init(|| self.buf.to_vec())
+    }
+
+    /// Copy the bytes starting at `pos` into `out`, returning the number of bytes copied.
+    pub fn read_at(&self, pos: usize, out: &mut [u8]) -> usize {
+        if pos >= self.buf.len() {
+            return 0;
+        }
+        let page = self.buf.page(pos / PAGE_SIZE);
+        let start = pos % PAGE_SIZE;
+        let end = (self.buf.len() - (pos - start)).min(PAGE_SIZE);
+        let n = out.len().min(end - start);
+        out[..n].copy_from_slice(&page[start..start + n]);
+        n
+    }
+}
+
+/// A reader of a single value. See [`crate::Session::read_streaming`].
+///
+/// Values stored in overflow pages or the value log are read from disk as the reader is consumed,
+/// a page at a time, and compressed values are decompressed on the fly. Reading blocks the current
+/// thread on I/O.
+///
+/// A reader of a value stored on disk keeps the pages of the value from being reused by blocking
+/// syncs from starting until it is dropped, so it must not be held across commits.
+pub struct ValueReader {
+    inner: Box<dyn Read + Send>,
+    _read_tx: Option<ReadTransaction>,
+}
+
+impl ValueReader {
+    /// A reader of a value read from disk, which must stay valid while the read transaction is
+    /// alive.
+    pub(crate) fn new(inner: Box<dyn Read + Send>, read_tx: Option<ReadTransaction>) -> Self {
+        ValueReader {
+            inner,
+            _read_tx: read_tx,
+        }
+    }
+
+    /// A reader of a value already in memory.
+    pub(crate) fn from_handle(value: ValueHandle) -> Self {
+        Self::new(Box::new(HandleReader { value, pos: 0 }), None)
+    }
+
+    /// A reader of a value already in memory.
+    pub(crate) fn from_vec(value: Vec<u8>) -> Self {
+        Self::new(Box::new(io::Cursor::new(value)), None)
+    }
+
+    /// Fail reads once the writer of a read-only store has synced, see
+    /// [`Store::check_snapshot`].
+    pub(crate) fn checked(self, store: Store) -> Self {
+        ValueReader {
+            inner: Box::new(CheckedReader {
+                inner: self.inner,
+                store,
+            }),
+            _read_tx: self._read_tx,
+        }
+    }
+}
+
+impl Read for ValueReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+struct CheckedReader {
+    inner: Box<dyn Read + Send>,
+    store: Store,
+}
+
+impl Read for CheckedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.store.check_snapshot().map_err(io::Error::other)?;
+        Ok(n)
+    }
+}
+
+struct HandleReader {
+    value: ValueHandle,
+    pos: usize,
+}
+
+impl Read for HandleReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.value.read_at(self.pos, buf);
+        self.pos += n;
+        Ok(n)
+    }
+}
@@ -0,0 +1,188 @@
+//! Transferring the state under a key prefix between databases.
+//!
+//! All keys sharing a prefix form a subtree of the trie, e.g. the storage of a single contract.
+//! [`crate::Nomt::export_subtree`] captures the keys and values of such a subtree in a
+//! [`SubtreeArchive`], along with the root of the subtree, and [`crate::Nomt::import_subtree`]
+//! replaces the subtree of another database with them.
+
+use bitvec::prelude::*;
+use nomt_core::{
+    trie::{KeyPath, Node},
+    update::build_trie,
+};
+
+use crate::{HashAlgorithm, Value};
+
+/// The keys and values under a key prefix, along with the root of the subtree they form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtreeArchive {
+    prefix: BitVec<u8, Msb0>,
+    root: Node,
+    entries: Vec<(KeyPath, Value)>,
+}
+
+impl SubtreeArchive {
+    /// Create an archive of the given entries, which must be sorted by key and lie under the
+    /// prefix.
+    pub(crate) fn new<T: HashAlgorithm>(
+        prefix: BitVec<u8, Msb0>,
+        entries: Vec<(KeyPath, Value)>,
+    ) -> Self {
+        let root = subtree_root::<T>(prefix.len(), &entries);
+        SubtreeArchive {
+            prefix,
+            root,
+            entries,
+        }
+    }
+
+    /// The prefix shared by all keys of the subtree, in bits.
+    pub fn prefix(&self) -> &BitSlice<u8, Msb0> {
+        &self.prefix
+    }
+
+    /// The root of the subtree, i.e. the node which resides at the position of the prefix within
+    /// a trie holding exactly these keys under the prefix.
+    ///
+    /// The root is part of the archive and only checked for consistency with the entries. Compare
+    /// it against a trusted root before importing an archive from an untrusted source.
+    pub fn root(&self) -> Node {
+        self.root
+    }
+
+    /// The keys and values of the subtree, sorted by key.
+    pub fn entries(&self) -> &[(KeyPath, Value)] {
+        &self.entries
+    }
+
+    /// Check that the entries are sorted, lie under the prefix and form a subtree with the
+    /// archive's root.
+    pub fn verify<T: HashAlgorithm>(&self) -> anyhow::Result<()> {
+        if self.prefix.len() > 256 {
+            anyhow::bail!("malformed subtree archive: prefix longer than 256 bits");
+        }
+        if self.entries.windows(2).any(|w| w[0].0 >= w[1].0) {
+            anyhow::bail!("malformed subtree archive: entries not sorted");
+        }
+        if self
+            .entries
+            .iter()
+            .any(|(key, _)| !key.view_bits::<Msb0>().starts_with(&self.prefix))
+        {
+            anyhow::bail!("malformed subtree archive: key outside of the prefix");
+        }
+        if subtree_root::<T>(self.prefix.len(), &self.entries) != self.root {
+            anyhow::bail!("subtree archive does not match its root");
+        }
+        Ok(())
+    }
+
+    /// Decode an archive. See [`SubtreeArchive::encode`].
+    ///
+    /// This only checks the encoding. Use [`SubtreeArchive::verify`] to check the contents.
+    pub fn decode(mut buf: &[u8]) -> anyhow::Result<Self> {
+        let prefix_len = read_u32(&mut buf)? as usize;
+        let prefix_bytes = read_slice(&mut buf, prefix_len.div_ceil(8))?;
+        let mut prefix = BitVec::<u8, Msb0>::from_slice(prefix_bytes);
+        prefix.truncate(prefix_len);
+        // UNWRAP: the slice is 32 bytes long.
+        let root = read_slice(&mut buf, 32)?.try_into().unwrap();
+
+        let count = read_u32(&mut buf)?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            // UNWRAP: the slice is 32 bytes long.
+            let key = read_slice(&mut buf, 32)?.try_into().unwrap();
+            let value_len = read_u32(&mut buf)? as usize;
+            let value = read_slice(&mut buf, value_len)?.to_vec();
+            entries.push((key, value));
+        }
+        if !buf.is_empty() {
+            anyhow::bail!("malformed subtree archive: trailing bytes");
+        }
+        Ok(SubtreeArchive {
+            prefix,
+            root,
+            entries,
+        })
+    }
+
+    /// Encode the archive for transfer.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&(self.prefix.len() as u32).to_le_bytes());
+        buf.extend_from_slice(self.prefix.as_raw_slice());
+        buf.extend_from_slice(&self.root);
+        buf.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for (key, value) in &self.entries {
+            buf.extend_from_slice(key);
+            buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            buf.extend_from_slice(value);
+        }
+        buf
+    }
+}
+
+/// Returns the first and the last key path under the given prefix.
+pub(crate) fn prefix_range(prefix: &BitSlice<u8, Msb0>) -> (KeyPath, KeyPath) {
+    assert!(prefix.len() <= 256, "prefix longer than 256 bits");
+    let mut start = KeyPath::default();
+    let mut end = [0xff; 32];
+    start.view_bits_mut::<Msb0>()[..prefix.len()].copy_from_bitslice(prefix);
+    end.view_bits_mut::<Msb0>()[..prefix.len()].copy_from_bitslice(prefix);
+    (start, end)
+}
+
+pub(crate) fn subtree_root<T: HashAlgorithm>(
+    prefix_len: usize,
+    entries: &[(KeyPath, Value)],
+) -> Node {
+    let leaves = entries
+        .iter()
+        .map(|(key, value)| (*key, T::hash_value(value)));
+    build_trie::<T>(prefix_len, leaves, |_| {})
+}
+
+fn read_u32(buf: &mut &[u8]) -> anyhow::Result<u32> {
+    let Some((bytes, rest)) = buf.split_first_chunk::<4>() else {
+        anyhow::bail!("malformed subtree archive: unexpected end");
+    };
+    *buf = rest;
+    Ok(u32::from_le_bytes(*bytes))
+}
+
+fn read_slice<'a>(buf: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+    if buf.len() < len {
+        anyhow::bail!("malformed subtree archive: unexpected end");
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::SubtreeArchive;
+    use crate::Blake3Hasher;
+
+    #[test]
+    fn archive_roundtrip() {
+        let prefix = bits![u8, Msb0; 1, 0, 1].to_bitvec();
+        let entries = vec![([0xa0; 32], vec![1, 2, 3]), ([0xb0; 32], vec![])];
+        let archive = SubtreeArchive::new::<Blake3Hasher>(prefix, entries);
+        archive.verify::<Blake3Hasher>().unwrap();
+
+        let decoded = SubtreeArchive::decode(&archive.encode()).unwrap();
+        assert_eq!(decoded, archive);
+
+        let mut tampered = archive.clone();
+        tampered.entries[1].1.push(0);
+        assert!(tampered.verify::<Blake3Hasher>().is_err());
+
+        let mut outside = archive;
+        outside.entries[1].0 = [0xc0; 32];
+        assert!(outside.verify::<Blake3Hasher>().is_err());
+    }
+}
@@ -18,20 +18,160 @@ pub fn tmpfs_check(file: &File) -> bool {
     }
 }
 
-/// fallocate changes the size of the file to the given length if it's less than the current size.
-/// If the file is larger than the given length, the file is not truncated.
+/// Returns the alignment of file offsets, lengths and memory buffers required for direct I/O on
+/// the file, as reported by `statx` since Linux 6.1. `Some(0)` means the file doesn't support
+/// direct I/O. `None` if the alignment is not reported.
+pub fn direct_io_alignment(file: &File) -> Option<u32> {
+    #[cfg(target_env = "gnu")]
+    unsafe {
+        // SAFETY: unsafe because ffi call. This should be IO-safe because the file is passed
+        //         by reference. This should be memory-safe because the `statx` struct is zeroed
+        //         and the path is a valid, empty C string.
+        let mut stx: libc::statx = std::mem::zeroed();
+        cvt_r(|| {
+            libc::statx(
+                file.as_raw_fd(),
+                c"".as_ptr(),
+                libc::AT_EMPTY_PATH,
+                libc::STATX_DIOALIGN,
+                &mut stx,
+            )
+        })
+        .ok()?;
+        if stx.stx_mask & libc::STATX_DIOALIGN == 0 {
+            return None;
+        }
+        if stx.stx_dio_offset_align == 0 {
+            return Some(0);
+        }
+        Some(stx.stx_dio_offset_align.max(stx.stx_dio_mem_align))
+    }
+    #[cfg(not(target_env = "gnu"))]
+    {
+        let _ = file;
+        None
+    }
+}
+
+/// Zeroes the given range of the file and allocates its blocks, extending the file if the range
+/// ends beyond it. The file is not truncated.
 ///
 /// Doesn't work on tmpfs.
-pub fn falloc_zero_file(file: &File, len: u64) -> std::io::Result<()> {
+pub fn falloc_zero_range(file: &File, offset: u64, len: u64) -> std::io::Result<()> {
+    fallocate(file, libc::FALLOC_FL_ZERO_RANGE, offset, len)
+}
+
+/// Allocates the blocks of the given range of the file, extending the file if the range ends
+/// beyond it. Ranges which were not written before read as zeros. The file is not truncated.
+///
+/// Supported by more file systems than [`falloc_zero_range`].
+pub fn falloc_range(file: &File, offset: u64, len: u64) -> std::io::Result<()> {
+    fallocate(file, 0, offset, len)
+}
+
+fn fallocate(file: &File, mode: libc::c_int, offset: u64, len: u64) -> std::io::Result<()> {
     cvt_r(|| unsafe {
         // SAFETY: unsafe because ffi call. This should be IO-safe because the file is passed
         //         by reference.
-        libc::fallocate(
-            file.as_raw_fd(),
-            libc::FALLOC_FL_ZERO_RANGE,
-            0 as _,
-            len as _,
+        libc::fallocate(file.as_raw_fd(), mode, offset as _, len as _)
+    })
+    .map(drop)
+}
+
+/// Writes back the dirty pages of the file and waits for the writeback to complete. Unlike
+/// `fsync`, this neither flushes the device cache nor syncs the metadata of the file.
+pub fn write_back(file: &File) -> std::io::Result<()> {
+    let flags = libc::SYNC_FILE_RANGE_WAIT_BEFORE
+        | libc::SYNC_FILE_RANGE_WRITE
+        | libc::SYNC_FILE_RANGE_WAIT_AFTER;
+    // SAFETY: unsafe because ffi call. This should be IO-safe because the file is passed by
+    //         reference.
+    cvt_r(|| unsafe { libc::sync_file_range(file.as_raw_fd(), 0, 0, flags) }).map(drop)
+}
+
+/// Makes `dst` share the data of `src` without copying it, on file systems supporting reflinks,
+/// e.g. btrfs and XFS. Both files are modified independently afterwards.
+pub fn reflink(src: &File, dst: &File) -> std::io::Result<()> {
+    // SAFETY: unsafe because ffi call. This should be IO-safe because the files are passed by
+    //         reference.
+    cvt_r(|| unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) }).map(drop)
+}
+
+/// Restricts the calling thread to run on the given CPUs.
+pub fn set_thread_affinity(cpus: &[usize]) -> std::io::Result<()> {
+    unsafe {
+        // SAFETY: unsafe because ffi call. This should be memory-safe because the `cpu_set_t` is
+        //         zeroed, indices are checked against its capacity, and it is passed with its size.
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        for &cpu in cpus {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+            }
+            libc::CPU_SET(cpu, &mut set);
+        }
+        cvt_r(|| libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)).map(drop)
+    }
+}
+
+/// Sets the nice value of the calling thread.
+pub fn set_thread_nice(nice: i32) -> std::io::Result<()> {
+    // SAFETY: unsafe because ffi call. Both calls only take integer arguments.
+    cvt_r(|| unsafe {
+        let tid = libc::gettid();
+        libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice)
+    })
+    .map(drop)
+}
+
+/// Maps `len` bytes of anonymous memory backed by 2 MiB huge pages, which must have been reserved
+/// by the administrator, e.g. through `/proc/sys/vm/nr_hugepages`. Fails if not enough are free.
+pub fn map_huge_pages(len: usize) -> std::io::Result<*mut u8> {
+    // SAFETY: unsafe because ffi call. This creates a new mapping and doesn't touch existing memory.
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_HUGETLB | libc::MAP_HUGE_2MB,
+            -1,
+            0,
         )
+    };
+    if ptr == libc::MAP_FAILED {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(ptr as *mut u8)
+}
+
+/// Asks for the given mapping to be backed by transparent huge pages.
+pub fn advise_huge_pages(ptr: *mut u8, len: usize) -> std::io::Result<()> {
+    // SAFETY: unsafe because ffi call. The advice doesn't change the contents of the memory.
+    cvt_r(|| unsafe { libc::madvise(ptr as *mut libc::c_void, len, libc::MADV_HUGEPAGE) }).map(drop)
+}
+
+/// Binds the given mapping to a NUMA node, so that its pages are allocated from the memory of
+/// that node once touched.
+pub fn bind_to_numa_node(ptr: *mut u8, len: usize, node: u32) -> std::io::Result<()> {
+    const MPOL_BIND: libc::c_long = 2;
+    let node = node as usize;
+    let mut nodemask = vec![0 as libc::c_ulong; node / libc::c_ulong::BITS as usize + 1];
+    nodemask[node / libc::c_ulong::BITS as usize] |= 1 << (node % libc::c_ulong::BITS as usize);
+    // The kernel reads one bit less than `maxnode`.
+    let maxnode = nodemask.len() * libc::c_ulong::BITS as usize + 1;
+    // SAFETY: unsafe because ffi call. This should be memory-safe because the node mask is
+    //         passed along with its size in bits, and the policy doesn't change the contents of
+    //         the memory.
+    cvt_r(|| unsafe {
+        libc::syscall(
+            libc::SYS_mbind,
+            ptr,
+            len,
+            MPOL_BIND,
+            nodemask.as_ptr(),
+            maxnode,
+            0,
+        ) as i32
     })
     .map(drop)
 }
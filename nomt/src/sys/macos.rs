@@ -1 +1,27 @@
 //! macOS-specific code.
+
+/// Restricts the calling thread to run on the given CPUs. Not supported on macOS.
+pub fn set_thread_affinity(_cpus: &[usize]) -> std::io::Result<()> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+}
+
+/// Sets the nice value of the calling thread. Not supported on macOS, where the nice value
+/// applies to the whole process.
+pub fn set_thread_nice(_nice: i32) -> std::io::Result<()> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+}
+
+/// Maps memory backed by huge pages. Not supported on macOS.
+pub fn map_huge_pages(_len: usize) -> std::io::Result<*mut u8> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+}
+
+/// Asks for a mapping to be backed by transparent huge pages. Not supported on macOS.
+pub fn advise_huge_pages(_ptr: *mut u8, _len: usize) -> std::io::Result<()> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+}
+
+/// Binds a mapping to a NUMA node. Not supported on macOS.
+pub fn bind_to_numa_node(_ptr: *mut u8, _len: usize, _node: u32) -> std::io::Result<()> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+}
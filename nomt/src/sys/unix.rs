@@ -10,6 +10,21 @@ pub fn unlock(file: &File) -> std::io::Result<()> {
     unsafe { cvt_r(|| libc::flock(file.as_raw_fd(), libc::LOCK_UN)).map(drop) }
 }
 
+/// Switches the calling thread to the `SCHED_FIFO` policy with the given priority.
+pub fn set_thread_realtime(priority: i32) -> std::io::Result<()> {
+    // SAFETY: unsafe because ffi call. The parameters are passed by reference and outlive the call.
+    let res = unsafe {
+        let mut param: libc::sched_param = std::mem::zeroed();
+        param.sched_priority = priority;
+        libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param)
+    };
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::from_raw_os_error(res))
+    }
+}
+
 pub(super) fn cvt_r<F>(mut f: F) -> std::io::Result<i32>
 where
     F: FnMut() -> i32,
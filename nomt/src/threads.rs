@@ -0,0 +1,126 @@
+//! Spawning of internal threads according to the [`ThreadConfig`].
+
+use std::sync::{Arc, Barrier};
+
+use anyhow::Context as _;
+use threadpool::ThreadPool;
+
+use crate::options::{ThreadConfig, ThreadPriority, ThreadSettings};
+
+/// The name of a thread, prefixed with the configured name prefix, if any.
+pub fn thread_name(config: &ThreadConfig, name: &str) -> String {
+    match config.name_prefix {
+        Some(ref prefix) => format!("{prefix}-{name}"),
+        None => name.to_string(),
+    }
+}
+
+/// Spawn a thread with the given name and settings, running `f`.
+///
+/// Fails if the settings cannot be applied, in which case `f` is not run.
+pub fn spawn(
+    name: String,
+    settings: &ThreadSettings,
+    f: impl FnOnce() + Send + 'static,
+) -> anyhow::Result<()> {
+    let (result_tx, result_rx) = crossbeam_channel::bounded(1);
+    let settings = settings.clone();
+    let _ = std::thread::Builder::new()
+        .name(name.clone())
+        .spawn(move || {
+            let result = apply(&settings);
+            let ok = result.is_ok();
+            let _ = result_tx.send(result);
+            if ok {
+                f();
+            }
+        })
+        .with_context(|| format!("failed to spawn thread {name}"))?;
+    result_rx
+        .recv()
+        .context("thread exited before applying its settings")?
+        .with_context(|| format!("failed to apply settings to thread {name}"))
+}
+
+/// Create a thread pool with the given name and number of threads, and apply the settings to all
+/// of its threads.
+///
+/// Note that the settings are not applied to threads respawned after a job panicked.
+pub fn pool(
+    name: String,
+    num_threads: usize,
+    settings: &ThreadSettings,
+) -> anyhow::Result<ThreadPool> {
+    let tp = threadpool::Builder::new()
+        .num_threads(num_threads)
+        .thread_name(name.clone())
+        .build();
+    apply_to_pool(&tp, &name, settings)?;
+    Ok(tp)
+}
+
+/// Change the number of threads of a pool, applying the settings to the threads spawned. The pool
+/// must be idle.
+///
+/// Threads in excess exit once they take another job.
+pub fn resize_pool(
+    tp: &ThreadPool,
+    name: &str,
+    num_threads: usize,
+    settings: &ThreadSettings,
+) -> anyhow::Result<()> {
+    let grow = num_threads > tp.max_count();
+    // Clones share the threads of the pool.
+    tp.clone().set_num_threads(num_threads);
+    if grow {
+        // The settings are re-applied to the existing threads as well, which is harmless.
+        apply_to_pool(tp, name, settings)?;
+    }
+    Ok(())
+}
+
+/// Apply the settings to all threads of the pool, waiting for them to become idle.
+fn apply_to_pool(tp: &ThreadPool, name: &str, settings: &ThreadSettings) -> anyhow::Result<()> {
+    if *settings == ThreadSettings::default() {
+        return Ok(());
+    }
+
+    // Every job waits until all of them are running, so each runs on a different thread.
+    let num_threads = tp.max_count();
+    let barrier = Arc::new(Barrier::new(num_threads));
+    let (result_tx, result_rx) = crossbeam_channel::unbounded();
+    for _ in 0..num_threads {
+        let barrier = barrier.clone();
+        let settings = settings.clone();
+        let result_tx = result_tx.clone();
+        tp.execute(move || {
+            let _ = result_tx.send(apply(&settings));
+            barrier.wait();
+        });
+    }
+    drop(result_tx);
+    for result in result_rx {
+        result.with_context(|| format!("failed to apply settings to thread pool {name}"))?;
+    }
+    Ok(())
+}
+
+/// Apply the settings to the calling thread.
+fn apply(settings: &ThreadSettings) -> std::io::Result<()> {
+    if !settings.cpus.is_empty() {
+        platform::set_thread_affinity(&settings.cpus)?;
+    }
+    match settings.priority {
+        None => Ok(()),
+        Some(ThreadPriority::Nice(nice)) => platform::set_thread_nice(nice),
+        Some(ThreadPriority::RealTime(priority)) => crate::sys::unix::set_thread_realtime(priority),
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use crate::sys::linux as platform;
+    } else {
+        use crate::sys::macos as platform;
+    }
+}
@@ -0,0 +1,277 @@
+//! Archiving the changes of every sync, for point-in-time recovery.
+//!
+//! The WAL only covers the hash-table and is recycled by every sync, so it can't be used to bring
+//! an older copy of the database forward. Instead, every sync hands a [`WalSegment`] holding its
+//! value changes and auxiliary writes to the callback set with [`crate::Options::wal_archive`],
+//! before the sync is made durable. [`crate::Nomt::restore`] replays archived segments on top of
+//! a snapshot, up to a chosen sync sequence number.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Write as _,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use nomt_core::trie::KeyPath;
+
+use crate::{beatree::ValueChange, CommitToken, Value};
+
+/// A callback receiving the [`WalSegment`] of every sync.
+pub type WalArchiveCallback = Arc<dyn Fn(&WalSegment) -> anyhow::Result<()> + Send + Sync>;
+
+const SEGMENT_EXTENSION: &str = "seg";
+
+/// The changes made by a single sync, as archived with [`crate::Options::wal_archive`].
+///
+/// A sync carries one commit, or several when commits are coalesced, in which case the segment
+/// holds the last change to every key. Syncs carrying no commit, such as those of
+/// [`crate::Nomt::compact`], have empty segments. The sequence numbers of the segments of a
+/// database are therefore contiguous.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalSegment {
+    sync_seqn: u32,
+    commit_token: Option<CommitToken>,
+    block_number: Option<u64>,
+    values: Vec<(KeyPath, Option<Value>)>,
+    aux: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl WalSegment {
+    /// Create the segment of the given value changes, applied in order, and auxiliary writes.
+    pub(crate) fn new<'a>(
+        sync_seqn: u32,
+        commit_token: Option<CommitToken>,
+        block_number: Option<u64>,
+        values: impl IntoIterator<Item = &'a (KeyPath, ValueChange)>,
+        aux: &BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    ) -> Self {
+        let values = values
+            .into_iter()
+            .map(|(key, change)| (*key, change.as_option().map(|value| value.to_vec())))
+            .collect::<BTreeMap<_, _>>();
+        WalSegment {
+            sync_seqn,
+            commit_token,
+            block_number,
+            values: values.into_iter().collect(),
+            aux: aux
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        }
+    }
+
+    /// The sequence number of the sync, see [`crate::Nomt::sync_seqn`].
+    pub fn sync_seqn(&self) -> u32 {
+        self.sync_seqn
+    }
+
+    /// The commit token recorded by the sync, see [`crate::Nomt::last_commit_token`].
+    pub fn commit_token(&self) -> Option<CommitToken> {
+        self.commit_token
+    }
+
+    /// The block number recorded by the sync, see [`crate::Nomt::last_block_number`].
+    pub fn block_number(&self) -> Option<u64> {
+        self.block_number
+    }
+
+    /// The values written by the sync, or `None` for deletions, sorted by key.
+    pub fn values(&self) -> &[(KeyPath, Option<Value>)] {
+        &self.values
+    }
+
+    /// The writes to the auxiliary column made by the sync, or `None` for deletions, sorted by
+    /// key.
+    pub fn aux(&self) -> &[(Vec<u8>, Option<Vec<u8>>)] {
+        &self.aux
+    }
+
+    /// Decode a segment. See [`WalSegment::encode`].
+    pub fn decode(mut buf: &[u8]) -> anyhow::Result<Self> {
+        let sync_seqn = read_u32(&mut buf)?;
+        let commit_token = match read_flag(&mut buf)? {
+            // UNWRAP: the slice is 32 bytes long.
+            true => Some(read_slice(&mut buf, 32)?.try_into().unwrap()),
+            false => None,
+        };
+        let block_number = match read_flag(&mut buf)? {
+            // UNWRAP: the slice is 8 bytes long.
+            true => Some(u64::from_le_bytes(
+                read_slice(&mut buf, 8)?.try_into().unwrap(),
+            )),
+            false => None,
+        };
+
+        let count = read_u32(&mut buf)?;
+        let mut values = Vec::new();
+        for _ in 0..count {
+            // UNWRAP: the slice is 32 bytes long.
+            let key = read_slice(&mut buf, 32)?.try_into().unwrap();
+            values.push((key, read_value(&mut buf)?));
+        }
+        let count = read_u32(&mut buf)?;
+        let mut aux = Vec::new();
+        for _ in 0..count {
+            let key_len = read_u32(&mut buf)? as usize;
+            let key = read_slice(&mut buf, key_len)?.to_vec();
+            aux.push((key, read_value(&mut buf)?));
+        }
+        if !buf.is_empty() {
+            anyhow::bail!("malformed WAL segment: trailing bytes");
+        }
+        let sorted_values = values.windows(2).all(|w| w[0].0 < w[1].0);
+        if !sorted_values || aux.windows(2).any(|w| w[0].0 >= w[1].0) {
+            anyhow::bail!("malformed WAL segment: keys not sorted");
+        }
+        Ok(WalSegment {
+            sync_seqn,
+            commit_token,
+            block_number,
+            values,
+            aux,
+        })
+    }
+
+    /// Encode the segment for storage.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&self.sync_seqn.to_le_bytes());
+        buf.push(self.commit_token.is_some() as u8);
+        if let Some(ref commit_token) = self.commit_token {
+            buf.extend_from_slice(commit_token);
+        }
+        buf.push(self.block_number.is_some() as u8);
+        if let Some(block_number) = self.block_number {
+            buf.extend_from_slice(&block_number.to_le_bytes());
+        }
+        buf.extend_from_slice(&(self.values.len() as u32).to_le_bytes());
+        for (key, value) in &self.values {
+            buf.extend_from_slice(key);
+            write_value(&mut buf, value.as_deref());
+        }
+        buf.extend_from_slice(&(self.aux.len() as u32).to_le_bytes());
+        for (key, value) in &self.aux {
+            buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            buf.extend_from_slice(key);
+            write_value(&mut buf, value.as_deref());
+        }
+        buf
+    }
+
+    /// Read the segments stored in the given directory by [`crate::Options::wal_archive_dir`],
+    /// in order of their sequence numbers.
+    pub fn read_dir(dir: impl AsRef<Path>) -> anyhow::Result<Vec<WalSegment>> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == SEGMENT_EXTENSION) {
+                paths.push(path);
+            }
+        }
+        // The file names are zero-padded sequence numbers.
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|path| WalSegment::decode(&std::fs::read(path)?))
+            .collect()
+    }
+}
+
+/// A callback storing every segment in a file of the given directory, which is created if it
+/// doesn't exist.
+pub(crate) fn archive_to_dir(dir: PathBuf) -> WalArchiveCallback {
+    Arc::new(move |segment: &WalSegment| {
+        std::fs::create_dir_all(&dir)?;
+        let name = format!("{:010}.{SEGMENT_EXTENSION}", segment.sync_seqn);
+        let tmp_path = dir.join(format!("{name}.tmp"));
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&segment.encode())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, dir.join(name))?;
+        File::open(&dir)?.sync_all()?;
+        Ok(())
+    })
+}
+
+fn write_value(buf: &mut Vec<u8>, value: Option<&[u8]>) {
+    buf.push(value.is_some() as u8);
+    if let Some(value) = value {
+        buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        buf.extend_from_slice(value);
+    }
+}
+
+fn read_value(buf: &mut &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+    if !read_flag(buf)? {
+        return Ok(None);
+    }
+    let len = read_u32(buf)? as usize;
+    Ok(Some(read_slice(buf, len)?.to_vec()))
+}
+
+fn read_flag(buf: &mut &[u8]) -> anyhow::Result<bool> {
+    match read_slice(buf, 1)?[0] {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => anyhow::bail!("malformed WAL segment: bad flag"),
+    }
+}
+
+fn read_u32(buf: &mut &[u8]) -> anyhow::Result<u32> {
+    let Some((bytes, rest)) = buf.split_first_chunk::<4>() else {
+        anyhow::bail!("malformed WAL segment: unexpected end");
+    };
+    *buf = rest;
+    Ok(u32::from_le_bytes(*bytes))
+}
+
+fn read_slice<'a>(buf: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+    if buf.len() < len {
+        anyhow::bail!("malformed WAL segment: unexpected end");
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::WalSegment;
+    use crate::{beatree::ValueChange, ValueHandle};
+
+    #[test]
+    fn segment_roundtrip() {
+        let changes = vec![
+            (
+                [2; 32],
+                ValueChange::Insert(ValueHandle::new(vec![1, 2, 3])),
+            ),
+            ([1; 32], ValueChange::Delete),
+            ([2; 32], ValueChange::Insert(ValueHandle::new(vec![]))),
+        ];
+        let mut aux = BTreeMap::new();
+        aux.insert(b"a".to_vec(), Some(vec![7; 5]));
+        aux.insert(b"b".to_vec(), None);
+        let segment = WalSegment::new(7, Some([3; 32]), Some(42), &changes, &aux);
+        // The last change to a key wins.
+        assert_eq!(
+            segment.values(),
+            &[([1; 32], None), ([2; 32], Some(vec![]))][..]
+        );
+
+        let encoded = segment.encode();
+        assert_eq!(WalSegment::decode(&encoded).unwrap(), segment);
+        assert!(WalSegment::decode(&encoded[..encoded.len() - 1]).is_err());
+        let mut trailing = encoded;
+        trailing.push(0);
+        assert!(WalSegment::decode(&trailing).is_err());
+
+        let empty = WalSegment::new(8, None, None, std::iter::empty(), &BTreeMap::new());
+        assert_eq!(WalSegment::decode(&empty.encode()).unwrap(), empty);
+    }
+}
@@ -0,0 +1,302 @@
+//! Splitting a [`Witness`] into size-limited, independently verifiable chunks.
+//!
+//! This is intended for posting witnesses to data-availability layers which cap the size of a
+//! single blob. Every chunk carries a whole number of path proofs along with the root they prove
+//! against, so each chunk can be checked on its own. Additionally, every chunk carries a
+//! commitment to the whole set of chunks, which is checked when reassembling the witness.
+
+use nomt_core::{
+    proof::{PathProof, PathProofTerminal, VerifiedPathProof},
+    trie::{KeyPath, LeafData, Node, NodeHasher},
+    trie_pos::TriePosition,
+};
+
+use crate::{Witness, WitnessedPath};
+
+/// The size of an encoded chunk without its payload: root, index, count, commitment and payload
+/// length.
+const HEADER_SIZE: usize = 32 + 4 + 4 + 32 + 4;
+
+/// A part of a [`Witness`]. See [`Witness::into_chunks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WitnessChunk {
+    /// The root all path proofs in the chunk prove against.
+    pub root: Node,
+    /// The index of this chunk among all chunks of the witness.
+    pub index: u32,
+    /// The total number of chunks of the witness.
+    pub count: u32,
+    /// A commitment to the root and the payloads of all chunks of the witness.
+    pub commitment: [u8; 32],
+    /// The encoded path proofs.
+    payload: Vec<u8>,
+}
+
+/// Errors in creating, decoding or verifying [`WitnessChunk`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WitnessChunkError {
+    /// A single path proof does not fit into a chunk of the requested size.
+    PathTooLarge {
+        /// The index of the path proof within the witness.
+        path_index: usize,
+    },
+    /// The encoding of a chunk is malformed.
+    Malformed,
+    /// A path proof does not verify against the root of its chunk.
+    InvalidPath {
+        /// The index of the path proof within the chunk.
+        path_index: usize,
+    },
+    /// Chunks are missing, duplicated or belong to different witnesses.
+    Inconsistent,
+    /// The chunks don't match their commitment.
+    CommitmentMismatch,
+}
+
+impl Witness {
+    /// Split the witness into chunks of at most `max_bytes` bytes each, when encoded with
+    /// [`WitnessChunk::encode`]. `root` is the root the path proofs prove against, i.e. the root
+    /// prior to the commit which produced the witness.
+    ///
+    /// Path proofs are never split across chunks. Fails if a single path proof does not fit. Only
+    /// the path proofs are carried by the chunks, not the [`Witness::pages`].
+    pub fn into_chunks(
+        self,
+        root: Node,
+        max_bytes: usize,
+    ) -> Result<Vec<WitnessChunk>, WitnessChunkError> {
+        let max_payload = max_bytes.saturating_sub(HEADER_SIZE);
+        let mut payloads: Vec<Vec<u8>> = Vec::new();
+        let mut current = Vec::new();
+        for (path_index, path) in self.path_proofs.iter().enumerate() {
+            let encoded = encode_path(path);
+            if encoded.len() > max_payload {
+                return Err(WitnessChunkError::PathTooLarge { path_index });
+            }
+            if current.len() + encoded.len() > max_payload {
+                payloads.push(std::mem::take(&mut current));
+            }
+            current.extend_from_slice(&encoded);
+        }
+        if !current.is_empty() || payloads.is_empty() {
+            payloads.push(current);
+        }
+
+        let count = payloads.len() as u32;
+        let commitment = commitment(root, &payloads);
+        Ok(payloads
+            .into_iter()
+            .enumerate()
+            .map(|(index, payload)| WitnessChunk {
+                root,
+                index: index as u32,
+                count,
+                commitment,
+                payload,
+            })
+            .collect())
+    }
+}
+
+impl WitnessChunk {
+    /// Encode the chunk.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        buf.extend_from_slice(&self.root);
+        buf.extend_from_slice(&self.index.to_le_bytes());
+        buf.extend_from_slice(&self.count.to_le_bytes());
+        buf.extend_from_slice(&self.commitment);
+        buf.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&self.payload);
+        buf
+    }
+
+    /// Decode a chunk encoded with [`WitnessChunk::encode`].
+    pub fn decode(buf: &[u8]) -> Result<Self, WitnessChunkError> {
+        let mut reader = Reader(buf);
+        let root = reader.array()?;
+        let index = u32::from_le_bytes(reader.array()?);
+        let count = u32::from_le_bytes(reader.array()?);
+        let commitment = reader.array()?;
+        let payload_len = u32::from_le_bytes(reader.array()?) as usize;
+        let payload = reader.bytes(payload_len)?.to_vec();
+        if !reader.0.is_empty() || index >= count {
+            return Err(WitnessChunkError::Malformed);
+        }
+        Ok(WitnessChunk {
+            root,
+            index,
+            count,
+            commitment,
+            payload,
+        })
+    }
+
+    /// Decode the path proofs in this chunk.
+    pub fn paths(&self) -> Result<Vec<WitnessedPath>, WitnessChunkError> {
+        let mut reader = Reader(&self.payload);
+        let mut paths = Vec::new();
+        while !reader.0.is_empty() {
+            paths.push(decode_path(&mut reader)?);
+        }
+        Ok(paths)
+    }
+
+    /// Decode the path proofs in this chunk and verify them against its root.
+    ///
+    /// This does not check the commitment, which requires all chunks. See
+    /// [`WitnessChunk::reassemble`].
+    pub fn verify<H: NodeHasher>(
+        &self,
+    ) -> Result<Vec<(WitnessedPath, VerifiedPathProof)>, WitnessChunkError> {
+        self.paths()?
+            .into_iter()
+            .enumerate()
+            .map(|(path_index, path)| {
+                let verified = path
+                    .inner
+                    .verify::<H>(path.path.path(), self.root)
+                    .map_err(|_| WitnessChunkError::InvalidPath { path_index })?;
+                Ok((path, verified))
+            })
+            .collect()
+    }
+
+    /// Verify all chunks of a witness, in any order, and reassemble the witness.
+    ///
+    /// Fails if any path proof doesn't verify, chunks are missing, or the chunks don't match their
+    /// commitment.
+    pub fn reassemble<H: NodeHasher>(
+        mut chunks: Vec<WitnessChunk>,
+    ) -> Result<Witness, WitnessChunkError> {
+        chunks.sort_by_key(|chunk| chunk.index);
+        let Some(first) = chunks.first() else {
+            return Err(WitnessChunkError::Inconsistent);
+        };
+        let (root, count, expected_commitment) = (first.root, first.count, first.commitment);
+        let consistent = chunks.len() == count as usize
+            && chunks.iter().enumerate().all(|(i, chunk)| {
+                chunk.index == i as u32
+                    && chunk.count == count
+                    && chunk.root == root
+                    && chunk.commitment == expected_commitment
+            });
+        if !consistent {
+            return Err(WitnessChunkError::Inconsistent);
+        }
+
+        let payloads = chunks
+            .iter()
+            .map(|chunk| chunk.payload.clone())
+            .collect::<Vec<_>>();
+        if commitment(root, &payloads) != expected_commitment {
+            return Err(WitnessChunkError::CommitmentMismatch);
+        }
+
+        let mut path_proofs = Vec::new();
+        for chunk in &chunks {
+            path_proofs.extend(chunk.verify::<H>()?.into_iter().map(|(path, _)| path));
+        }
+        Ok(Witness {
+            path_proofs,
+            multi_proof: None,
+            pages: Vec::new(),
+        })
+    }
+}
+
+fn commitment(root: Node, payloads: &[Vec<u8>]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&root);
+    hasher.update(&(payloads.len() as u32).to_le_bytes());
+    for payload in payloads {
+        hasher.update(blake3::hash(payload).as_bytes());
+    }
+    hasher.finalize().into()
+}
+
+// Encoding of a path proof:
+//   - the query path: depth (u16) followed by the path, padded to 32 bytes.
+//   - the terminal: 0 followed by the key path and value hash of a leaf, or 1 followed by the
+//     position of a terminator, encoded like the query path.
+//   - the siblings: their number (u16) followed by the siblings.
+pub(crate) fn encode_path(path: &WitnessedPath) -> Vec<u8> {
+    let mut buf = Vec::new();
+    encode_position(&path.path, &mut buf);
+    match path.inner.terminal {
+        PathProofTerminal::Leaf(ref leaf) => {
+            buf.push(0);
+            buf.extend_from_slice(&leaf.key_path);
+            buf.extend_from_slice(&leaf.value_hash);
+        }
+        PathProofTerminal::Terminator(ref position) => {
+            buf.push(1);
+            encode_position(position, &mut buf);
+        }
+    }
+    buf.extend_from_slice(&(path.inner.siblings.len() as u16).to_le_bytes());
+    for sibling in &path.inner.siblings {
+        buf.extend_from_slice(sibling);
+    }
+    buf
+}
+
+pub(crate) fn decode_path(reader: &mut Reader) -> Result<WitnessedPath, WitnessChunkError> {
+    let path = decode_position(reader)?;
+    let terminal = match reader.array::<1>()?[0] {
+        0 => PathProofTerminal::Leaf(LeafData {
+            key_path: reader.array()?,
+            value_hash: reader.array()?,
+        }),
+        1 => PathProofTerminal::Terminator(decode_position(reader)?),
+        _ => return Err(WitnessChunkError::Malformed),
+    };
+    let num_siblings = u16::from_le_bytes(reader.array()?) as usize;
+    let siblings = (0..num_siblings)
+        .map(|_| reader.array())
+        .collect::<Result<Vec<Node>, _>>()?;
+    Ok(WitnessedPath {
+        inner: PathProof { terminal, siblings },
+        path,
+    })
+}
+
+fn encode_position(position: &TriePosition, buf: &mut Vec<u8>) {
+    let bits = position.path();
+    let mut path = KeyPath::default();
+    for i in 0..bits.len() {
+        if bits[i] {
+            path[i / 8] |= 0x80 >> (i % 8);
+        }
+    }
+    buf.extend_from_slice(&(bits.len() as u16).to_le_bytes());
+    buf.extend_from_slice(&path);
+}
+
+pub(crate) fn decode_position(reader: &mut Reader) -> Result<TriePosition, WitnessChunkError> {
+    let depth = u16::from_le_bytes(reader.array()?);
+    let path = reader.array()?;
+    match depth {
+        0 => Ok(TriePosition::new()),
+        1..=256 => Ok(TriePosition::from_path_and_depth(path, depth)),
+        _ => Err(WitnessChunkError::Malformed),
+    }
+}
+
+pub(crate) struct Reader<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], WitnessChunkError> {
+        if self.0.len() < len {
+            return Err(WitnessChunkError::Malformed);
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], WitnessChunkError> {
+        // UNWRAP: `bytes` returns exactly `N` bytes.
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+}
@@ -0,0 +1,127 @@
+//! Canonical, versioned encodings of [`Witness`]es and [`WitnessedOperations`].
+//!
+//! Unlike [`Witness::encode`], which only carries the path proofs, these encodings cover every
+//! part of a witness and are meant to be agreed upon byte for byte by provers and on-chain
+//! verifiers. See [`nomt_core::codec`] for the encoding rules and the limits enforced on decoding.
+//!
+//! With the `serde` feature, witnesses also implement `Serialize` and `Deserialize`.
+
+use nomt_core::codec::{decode_versioned, encode_versioned, DecodeError, DecodeLimits};
+
+use crate::{Witness, WitnessedOperations};
+
+impl Witness {
+    /// Encode the witness in its canonical encoding, prefixed with
+    /// [`FORMAT_VERSION`](nomt_core::codec::FORMAT_VERSION).
+    pub fn encode_versioned(&self) -> Vec<u8> {
+        encode_versioned(self)
+    }
+
+    /// Decode a witness encoded with [`Witness::encode_versioned`], rejecting encodings larger
+    /// than the limits and any encoding which is not canonical.
+    pub fn decode_versioned(bytes: &[u8], limits: &DecodeLimits) -> Result<Self, DecodeError> {
+        decode_versioned(bytes, limits)
+    }
+}
+
+impl WitnessedOperations {
+    /// Encode the operations in their canonical encoding, prefixed with
+    /// [`FORMAT_VERSION`](nomt_core::codec::FORMAT_VERSION).
+    pub fn encode_versioned(&self) -> Vec<u8> {
+        encode_versioned(self)
+    }
+
+    /// Decode operations encoded with [`WitnessedOperations::encode_versioned`], rejecting
+    /// encodings larger than the limits and any encoding which is not canonical.
+    pub fn decode_versioned(bytes: &[u8], limits: &DecodeLimits) -> Result<Self, DecodeError> {
+        decode_versioned(bytes, limits)
+    }
+}
+
+mod scale {
+    use nomt_core::{
+        page::NODES_PER_PAGE,
+        page_id::PageId,
+        trie::{KeyPath, Node, ValueHash},
+    };
+    use parity_scale_codec::{Decode, Encode, Error, Input, Output};
+
+    use crate::{WitnessedPage, WitnessedRead, WitnessedWrite};
+
+    // Path indices are encoded as `u32`s, so that the encoding doesn't depend on the platform.
+
+    fn decode_path_index<I: Input>(input: &mut I) -> Result<usize, Error> {
+        u32::decode(input).map(|index| index as usize)
+    }
+
+    impl Encode for WitnessedRead {
+        fn size_hint(&self) -> usize {
+            32 + self.value.size_hint() + 4
+        }
+
+        fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+            self.key.encode_to(dest);
+            self.value.encode_to(dest);
+            (self.path_index as u32).encode_to(dest);
+        }
+    }
+
+    impl Decode for WitnessedRead {
+        fn decode<I: Input>(input: &mut I) -> Result<Self, Error> {
+            Ok(WitnessedRead {
+                key: KeyPath::decode(input)?,
+                value: Option::<ValueHash>::decode(input)?,
+                path_index: decode_path_index(input)?,
+            })
+        }
+    }
+
+    impl Encode for WitnessedWrite {
+        fn size_hint(&self) -> usize {
+            32 + self.value.size_hint() + 4
+        }
+
+        fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+            self.key.encode_to(dest);
+            self.value.encode_to(dest);
+            (self.path_index as u32).encode_to(dest);
+        }
+    }
+
+    impl Decode for WitnessedWrite {
+        fn decode<I: Input>(input: &mut I) -> Result<Self, Error> {
+            Ok(WitnessedWrite {
+                key: KeyPath::decode(input)?,
+                value: Option::<ValueHash>::decode(input)?,
+                path_index: decode_path_index(input)?,
+            })
+        }
+    }
+
+    // The nodes of a page are encoded without a length, since there are always `NODES_PER_PAGE`
+    // of them.
+
+    impl Encode for WitnessedPage {
+        fn size_hint(&self) -> usize {
+            self.page_id.size_hint() + NODES_PER_PAGE * 32
+        }
+
+        fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+            assert_eq!(self.nodes.len(), NODES_PER_PAGE);
+            self.page_id.encode_to(dest);
+            for node in &self.nodes {
+                node.encode_to(dest);
+            }
+        }
+    }
+
+    impl Decode for WitnessedPage {
+        fn decode<I: Input>(input: &mut I) -> Result<Self, Error> {
+            let page_id = <PageId as Decode>::decode(input)?;
+            let nodes = (0..NODES_PER_PAGE)
+                .map(|_| Node::decode(input))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(WitnessedPage { page_id, nodes })
+        }
+    }
+}
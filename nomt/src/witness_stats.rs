@@ -0,0 +1,124 @@
+//! Statistics about the shape of a [`Witness`].
+//!
+//! These help to understand which access patterns make witnesses large or expensive to verify:
+//! deep paths, paths spread over many pages of the trie, and siblings shared by several paths,
+//! which are repeated in [`WitnessMode::Paths`] but carried once in [`WitnessMode::Multiproof`].
+//!
+//! [`WitnessMode::Paths`]: crate::WitnessMode::Paths
+//! [`WitnessMode::Multiproof`]: crate::WitnessMode::Multiproof
+
+use std::collections::HashSet;
+
+use nomt_core::{page::DEPTH, proof::PathProofTerminal, trie::KeyPath};
+
+use crate::Witness;
+
+/// Statistics about a [`Witness`]. See [`Witness::stats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WitnessStats {
+    /// The number of paths proven by the witness.
+    pub paths: usize,
+    /// The number of sibling nodes carried by the witness at each depth. `nodes_by_depth[i]` is
+    /// the number of siblings at depth `i + 1`, i.e. the children of the root are at index 0.
+    pub nodes_by_depth: Vec<usize>,
+    /// The number of distinct pages of the trie the proven paths traverse.
+    pub unique_pages: usize,
+    /// The number of siblings carried more than once because several paths share them. Always
+    /// zero for multiproofs, which carry shared siblings once.
+    pub duplicate_siblings: usize,
+    /// An estimate of the cost of verifying the witness, as the number of hash invocations: one
+    /// per leaf node proven plus one per internal node recomputed on the way to the root.
+    pub verifier_hashes: usize,
+}
+
+impl WitnessStats {
+    /// The total number of sibling nodes carried by the witness.
+    pub fn nodes(&self) -> usize {
+        self.nodes_by_depth.iter().sum()
+    }
+}
+
+impl Witness {
+    /// Gather statistics about the shape of this witness.
+    pub fn stats(&self) -> WitnessStats {
+        let paths = match &self.multi_proof {
+            Some(multi_proof) => multi_proof
+                .paths
+                .iter()
+                .map(|path| (&path.terminal, path.depth))
+                .collect::<Vec<_>>(),
+            None => self
+                .path_proofs
+                .iter()
+                .map(|path| (&path.inner.terminal, path.inner.siblings.len()))
+                .collect::<Vec<_>>(),
+        };
+
+        let mut stats = WitnessStats {
+            paths: paths.len(),
+            ..WitnessStats::default()
+        };
+
+        // Nodes are identified by their depth along with the path leading to them.
+        let mut internal = HashSet::new();
+        let mut terminals = HashSet::new();
+        let mut siblings = HashSet::new();
+        let mut pages = HashSet::new();
+        let mut carried = 0;
+        for &(terminal, depth) in &paths {
+            for d in 0..depth {
+                internal.insert((d, prefix(terminal, d, None)));
+                siblings.insert((d + 1, prefix(terminal, d + 1, Some(d))));
+                if d % DEPTH == 0 {
+                    pages.insert((d, prefix(terminal, d, None)));
+                }
+            }
+            terminals.insert((depth, prefix(terminal, depth, None)));
+            carried += depth;
+            if let PathProofTerminal::Leaf(_) = terminal {
+                stats.verifier_hashes += 1;
+            }
+        }
+        stats.unique_pages = pages.len();
+
+        if self.multi_proof.is_some() {
+            // Siblings lying on another path are computed by the verifier rather than carried.
+            for (depth, sibling) in &siblings {
+                if !internal.contains(&(*depth, *sibling))
+                    && !terminals.contains(&(*depth, *sibling))
+                {
+                    count_sibling(&mut stats.nodes_by_depth, *depth);
+                }
+            }
+            stats.verifier_hashes += internal.len();
+        } else {
+            for &(_, depth) in &paths {
+                for d in 1..=depth {
+                    count_sibling(&mut stats.nodes_by_depth, d);
+                }
+            }
+            stats.duplicate_siblings = carried - siblings.len();
+            stats.verifier_hashes += carried;
+        }
+        stats
+    }
+}
+
+fn count_sibling(nodes_by_depth: &mut Vec<usize>, depth: usize) {
+    if nodes_by_depth.len() < depth {
+        nodes_by_depth.resize(depth, 0);
+    }
+    nodes_by_depth[depth - 1] += 1;
+}
+
+/// The first `len` bits of the path to the terminal, with the bit at `flip` inverted if given.
+fn prefix(terminal: &PathProofTerminal, len: usize, flip: Option<usize>) -> KeyPath {
+    let key_path = terminal.path();
+    let mut prefix = KeyPath::default();
+    for i in 0..len {
+        if key_path[i] != (flip == Some(i)) {
+            prefix[i / 8] |= 0x80 >> (i % 8);
+        }
+    }
+    prefix
+}
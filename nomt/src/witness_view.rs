@@ -0,0 +1,240 @@
+//! Zero-copy access to serialized [`Witness`]es.
+//!
+//! Witnesses of large commits, e.g. archived witnesses of whole blocks, can be larger than the
+//! memory of the machine verifying them. [`WitnessView`] reads the path proofs directly from the
+//! serialized witness, decoding and verifying each path proof only when it is visited, so that a
+//! witness can be verified from a memory-mapped file (see [`MappedWitness`]) with the kernel
+//! paging it in and out as needed.
+
+use nomt_core::{
+    proof::{hash_path, PathProof, PathProofTerminal},
+    trie::{LeafData, Node, NodeHasher},
+    trie_pos::TriePosition,
+};
+
+use crate::{
+    witness_chunks::{decode_position, encode_path, Reader},
+    Witness, WitnessChunkError, WitnessedPath,
+};
+
+impl Witness {
+    /// Serialize the witness for access with [`WitnessView`]: the number of path proofs (8 bytes,
+    /// little-endian) followed by the path proofs, encoded as in [`WitnessChunk`]s.
+    ///
+    /// [`WitnessChunk`]: crate::WitnessChunk
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = (self.path_proofs.len() as u64).to_le_bytes().to_vec();
+        for path in &self.path_proofs {
+            buf.extend_from_slice(&encode_path(path));
+        }
+        buf
+    }
+}
+
+/// A serialized witness, see [`Witness::encode`], accessed in place.
+///
+/// Only the header is checked on creation. The path proofs are decoded when visited, and
+/// malformed ones are reported then.
+#[derive(Clone, Copy)]
+pub struct WitnessView<'a> {
+    count: u64,
+    paths: &'a [u8],
+}
+
+impl<'a> WitnessView<'a> {
+    /// Create a view of the serialized witness.
+    pub fn new(buf: &'a [u8]) -> Result<Self, WitnessChunkError> {
+        let mut reader = Reader(buf);
+        let count = u64::from_le_bytes(reader.array()?);
+        Ok(WitnessView {
+            count,
+            paths: reader.0,
+        })
+    }
+
+    /// The number of path proofs in the witness.
+    pub fn len(&self) -> u64 {
+        self.count
+    }
+
+    /// Whether the witness holds no path proofs.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Iterate over the path proofs, in order. Stops after the first malformed path proof.
+    pub fn paths(&self) -> PathViews<'a> {
+        PathViews {
+            reader: Reader(self.paths),
+            remaining: self.count,
+        }
+    }
+
+    /// Verify all path proofs against the root, one at a time.
+    ///
+    /// The `path_index` of [`WitnessChunkError::InvalidPath`] is the index within the whole
+    /// witness. Fails with [`WitnessChunkError::Malformed`] if the witness is malformed, including
+    /// if there are bytes past the last path proof.
+    pub fn verify<H: NodeHasher>(&self, root: Node) -> Result<(), WitnessChunkError> {
+        let mut paths = self.paths();
+        for path_index in 0..self.count {
+            // UNWRAP: yields exactly `count` items, unless an error ended the iteration early.
+            let path = paths.next().unwrap()?;
+            if !path.verify::<H>(root) {
+                return Err(WitnessChunkError::InvalidPath {
+                    path_index: path_index as usize,
+                });
+            }
+        }
+        if !paths.reader.0.is_empty() {
+            return Err(WitnessChunkError::Malformed);
+        }
+        Ok(())
+    }
+}
+
+/// An iterator over the path proofs of a [`WitnessView`].
+pub struct PathViews<'a> {
+    reader: Reader<'a>,
+    remaining: u64,
+}
+
+impl<'a> Iterator for PathViews<'a> {
+    type Item = Result<PathView<'a>, WitnessChunkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let path = decode_path_view(&mut self.reader);
+        self.remaining = match path {
+            Ok(_) => self.remaining - 1,
+            Err(_) => 0,
+        };
+        Some(path)
+    }
+}
+
+/// A path proof within a serialized witness. The siblings are read in place.
+pub struct PathView<'a> {
+    /// The query path.
+    pub path: TriePosition,
+    /// The terminal node of the path.
+    pub terminal: PathProofTerminal,
+    siblings: &'a [u8],
+}
+
+impl<'a> PathView<'a> {
+    /// The siblings along the path, from the root downwards.
+    pub fn siblings(&self) -> impl DoubleEndedIterator<Item = Node> + ExactSizeIterator + 'a {
+        // UNWRAP: the chunks are 32 bytes long.
+        self.siblings
+            .chunks_exact(32)
+            .map(|sibling| sibling.try_into().unwrap())
+    }
+
+    /// Verify the path proof against the root, without copying the siblings.
+    pub fn verify<H: NodeHasher>(&self, root: Node) -> bool {
+        let key_path = self.path.path();
+        let num_siblings = self.siblings().len();
+        if num_siblings > key_path.len() {
+            return false;
+        }
+        let node = self.terminal.node::<H>();
+        hash_path::<H>(node, &key_path[..num_siblings], self.siblings().rev()) == root
+    }
+
+    /// Copy the path proof out of the serialized witness.
+    pub fn to_witnessed_path(&self) -> WitnessedPath {
+        WitnessedPath {
+            inner: PathProof {
+                terminal: self.terminal.clone(),
+                siblings: self.siblings().collect(),
+            },
+            path: self.path.clone(),
+        }
+    }
+}
+
+fn decode_path_view<'a>(reader: &mut Reader<'a>) -> Result<PathView<'a>, WitnessChunkError> {
+    let path = decode_position(reader)?;
+    let terminal = match reader.array::<1>()?[0] {
+        0 => PathProofTerminal::Leaf(LeafData {
+            key_path: reader.array()?,
+            value_hash: reader.array()?,
+        }),
+        1 => PathProofTerminal::Terminator(decode_position(reader)?),
+        _ => return Err(WitnessChunkError::Malformed),
+    };
+    let num_siblings = u16::from_le_bytes(reader.array()?) as usize;
+    let siblings = reader.bytes(num_siblings * 32)?;
+    Ok(PathView {
+        path,
+        terminal,
+        siblings,
+    })
+}
+
+/// A serialized witness file mapped into memory read-only.
+#[cfg(feature = "storage")]
+pub struct MappedWitness {
+    ptr: *mut u8,
+    len: usize,
+}
+
+#[cfg(feature = "storage")]
+impl MappedWitness {
+    /// Map the witness file at the given path. The file must not be modified while mapped.
+    pub fn open(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        use std::os::fd::AsRawFd as _;
+
+        let file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            anyhow::bail!("witness file is empty");
+        }
+        let ptr = unsafe {
+            let addr = libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            );
+            if addr == libc::MAP_FAILED {
+                anyhow::bail!("mmap failed: {:?}", std::io::Error::last_os_error());
+            }
+            // Path proofs are usually visited in order. Not fatal if unsupported.
+            let _ = libc::madvise(addr, len, libc::MADV_SEQUENTIAL);
+            addr as *mut u8
+        };
+        Ok(MappedWitness { ptr, len })
+    }
+
+    /// The mapped bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: the mapping is valid for `len` bytes until dropped.
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    /// A view of the mapped witness.
+    pub fn view(&self) -> Result<WitnessView<'_>, WitnessChunkError> {
+        WitnessView::new(self.as_bytes())
+    }
+}
+
+// SAFETY: the mapping is read-only.
+#[cfg(feature = "storage")]
+unsafe impl Send for MappedWitness {}
+#[cfg(feature = "storage")]
+unsafe impl Sync for MappedWitness {}
+
+#[cfg(feature = "storage")]
+impl Drop for MappedWitness {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = libc::munmap(self.ptr as *mut _, self.len);
+        }
+    }
+}
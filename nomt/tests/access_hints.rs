@@ -0,0 +1,82 @@
+mod common;
+
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use bitvec::prelude::*;
+use common::{account_path, test_dir, Test};
+use nomt::{CacheResult, KeyPath, KeyReadWrite, Session};
+
+fn populate(path: &Path) -> Vec<KeyPath> {
+    let t = Test::open(path);
+    let nomt = t.nomt();
+    let mut actuals = (0..5000)
+        .map(|id| {
+            (
+                account_path(id),
+                KeyReadWrite::Write(Some(vec![id as u8; 32])),
+            )
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(path, _)| *path);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals.clone()).unwrap();
+    actuals.into_iter().map(|(path, _)| path).collect()
+}
+
+fn wait_until_cached(session: &Session, keys: &[KeyPath]) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while keys
+        .iter()
+        .any(|key| session.read_cached(*key) == CacheResult::Miss)
+    {
+        assert!(Instant::now() < deadline, "keys were not read ahead");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn hint_sequential_reads_ahead() {
+    let dir = test_dir("hint_sequential");
+    let path = dir.path().join("db");
+    let keys = populate(&path);
+    let t = Test::open(path);
+    let nomt = t.nomt();
+
+    let prefix = bits![u8, Msb0; 1, 0];
+    let (scanned, other): (Vec<KeyPath>, Vec<KeyPath>) = keys
+        .into_iter()
+        .partition(|key| key.view_bits::<Msb0>().starts_with(prefix));
+    let mut session = nomt.begin_session();
+    assert!(scanned
+        .iter()
+        .all(|key| session.read_cached(*key) == CacheResult::Miss));
+
+    session.hint_sequential(prefix);
+    wait_until_cached(&session, &scanned);
+    for key in &scanned {
+        assert!(session.read(*key).unwrap().is_some());
+    }
+
+    // Keys far from the prefix have not been read ahead.
+    assert!(other
+        .iter()
+        .filter(|key| key[0] < 0x40)
+        .all(|key| session.read_cached(*key) == CacheResult::Miss));
+}
+
+#[test]
+fn hint_random_fetches_keys() {
+    let dir = test_dir("hint_random");
+    let path = dir.path().join("db");
+    let keys = populate(&path);
+    let t = Test::open(path);
+    let nomt = t.nomt();
+
+    let hinted = keys.iter().step_by(100).copied().collect::<Vec<_>>();
+    let mut session = nomt.begin_session();
+    session.hint_random(&hinted);
+    wait_until_cached(&session, &hinted);
+}
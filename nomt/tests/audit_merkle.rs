@@ -0,0 +1,47 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::KeyReadWrite;
+
+fn audited_commits(name: &str, commit_concurrency: usize, coalescing: bool) {
+    let dir = test_dir(name);
+    let t = Test::open_with(dir.path().join("db"), move |o| {
+        o.commit_concurrency(commit_concurrency);
+        o.audit_merkle_updates(true);
+        if coalescing {
+            o.commit_coalescing(10, std::time::Duration::from_secs(3600));
+        }
+    });
+    let nomt = t.nomt();
+
+    for round in 0..4u64 {
+        let mut actuals = (0..1000)
+            .map(|id| {
+                let value = if (id + round) % 3 == 0 {
+                    None
+                } else {
+                    Some(vec![round as u8; 8])
+                };
+                (account_path(id), KeyReadWrite::Write(value))
+            })
+            .collect::<Vec<_>>();
+        actuals.sort_by_key(|(k, _)| *k);
+        let session = nomt.begin_session();
+        nomt.commit(session, actuals).unwrap();
+    }
+}
+
+#[test]
+fn audit_single_worker() {
+    audited_commits("audit_merkle_1", 1, false);
+}
+
+#[test]
+fn audit_multiple_workers() {
+    audited_commits("audit_merkle_3", 3, false);
+}
+
+#[test]
+fn audit_held_back_commits() {
+    audited_commits("audit_merkle_coalescing", 2, true);
+}
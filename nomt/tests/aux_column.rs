@@ -0,0 +1,64 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{KeyReadWrite, Nomt};
+use std::time::Duration;
+
+fn commit(nomt: &Nomt<nomt::Blake3Hasher>, id: u64, aux: &[(&str, Option<&str>)]) {
+    let mut session = nomt.begin_session();
+    for (key, value) in aux {
+        session.write_aux(*key, value.map(|value| value.as_bytes().to_vec()));
+    }
+    let actuals = vec![(account_path(id), KeyReadWrite::Write(Some(vec![1; 8])))];
+    nomt.commit(session, actuals).unwrap();
+}
+
+#[test]
+fn aux_writes_persist_with_commits() {
+    let dir = test_dir("aux_column");
+    let path = dir.path().join("db");
+    let t = Test::open(&path);
+    let nomt = t.nomt();
+    commit(
+        nomt,
+        0,
+        &[("head", Some("block-1")), ("checkpoint", Some("7"))],
+    );
+    commit(nomt, 1, &[("head", Some("block-2"))]);
+    commit(nomt, 2, &[("checkpoint", None)]);
+    assert_eq!(nomt.read_aux(b"head"), Some(b"block-2".to_vec()));
+    assert_eq!(nomt.read_aux(b"checkpoint"), None);
+    let root = nomt.root();
+
+    let t = t.reopen();
+    let nomt = t.nomt();
+    assert_eq!(nomt.read_aux(b"head"), Some(b"block-2".to_vec()));
+    assert_eq!(nomt.read_aux(b"checkpoint"), None);
+
+    // The auxiliary column is not part of the merkle trie.
+    let other = Test::open(dir.path().join("other"));
+    for id in 0..3 {
+        commit(other.nomt(), id, &[]);
+    }
+    assert_eq!(other.nomt().root(), root);
+}
+
+#[test]
+fn aux_writes_are_coalesced() {
+    let dir = test_dir("aux_column_coalescing");
+    let path = dir.path().join("db");
+    let t = Test::open_with(&path, |o| {
+        o.commit_coalescing(10, Duration::from_secs(3600))
+    });
+    let nomt = t.nomt();
+    commit(nomt, 0, &[("a", Some("1")), ("b", Some("2"))]);
+    commit(nomt, 1, &[("a", None)]);
+    assert_eq!(nomt.read_aux(b"a"), None);
+    assert_eq!(nomt.read_aux(b"b"), Some(b"2".to_vec()));
+    nomt.flush().unwrap();
+
+    let t = t.reopen();
+    let nomt = t.nomt();
+    assert_eq!(nomt.read_aux(b"a"), None);
+    assert_eq!(nomt.read_aux(b"b"), Some(b"2".to_vec()));
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::{test_dir, Test};
+use nomt::{BackgroundErrorSource, KeyReadWrite, Nomt, Options, WalSink};
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    time::Duration,
+};
+
+struct Sink {
+    fail: bool,
+}
+
+impl WalSink for Sink {
+    fn write(&self, _sync_seqn: u32, _wal_blob: &[u8]) -> anyhow::Result<()> {
+        if self.fail {
+            anyhow::bail!("sink is offline");
+        }
+        Ok(())
+    }
+}
+
+// Sinks which fail unless marked as ok, reporting their errors to `errors`.
+fn sinks(sinks: &'static [bool], errors: mpsc::Sender<String>) -> impl Fn(&mut Options) {
+    move |o| {
+        for &ok in sinks {
+            o.wal_sink(Arc::new(Sink { fail: !ok }));
+        }
+        o.wal_sink_quorum(1);
+        let errors = Mutex::new(errors.clone());
+        o.on_background_error(move |e| {
+            let BackgroundErrorSource::WalSink { index, sync_seqn } = e.source else {
+                return;
+            };
+            let _ = errors
+                .lock()
+                .unwrap()
+                .send(format!("{index} {sync_seqn} {:#}", e.error));
+        });
+    }
+}
+
+fn commit(nomt: &Nomt<nomt::Blake3Hasher>, i: u8) -> anyhow::Result<()> {
+    let session = nomt.begin_session();
+    nomt.commit(session, vec![([i; 32], KeyReadWrite::Write(Some(vec![i])))])?;
+    Ok(())
+}
+
+#[test]
+fn failed_wal_sink_is_reported() {
+    let dir = test_dir("background_error_sink");
+    let (tx, errors) = mpsc::channel();
+    let t = Test::open_with(dir.path().join("db"), sinks(&[true, false], tx));
+    let nomt = t.nomt();
+
+    // The quorum is reached without the failing sink, so the commits succeed.
+    commit(nomt, 1).unwrap();
+    commit(nomt, 2).unwrap();
+
+    // The failing sink may still be writing in the background when a commit returns.
+    let timeout = Duration::from_secs(10);
+    let mut reported = vec![
+        errors.recv_timeout(timeout).unwrap(),
+        errors.recv_timeout(timeout).unwrap(),
+    ];
+    reported.sort();
+    assert_eq!(reported, ["1 1 sink is offline", "1 2 sink is offline"]);
+}
+
+#[test]
+fn failed_quorum_is_reported_and_fails_commit() {
+    let dir = test_dir("background_error_quorum");
+    let (tx, errors) = mpsc::channel();
+    let t = Test::open_with(dir.path().join("db"), sinks(&[false], tx));
+    let nomt = t.nomt();
+
+    assert!(commit(nomt, 1).is_err());
+    let error = errors.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(error, "0 1 sink is offline");
+}
+
+#[test]
+fn healthy_database_reports_nothing() {
+    let dir = test_dir("background_error_healthy");
+    let (tx, errors) = mpsc::channel();
+    let t = Test::open_with(dir.path().join("db"), sinks(&[true], tx));
+
+    commit(t.nomt(), 1).unwrap();
+    drop(t);
+    assert!(errors.try_recv().is_err());
+}
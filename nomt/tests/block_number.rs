@@ -0,0 +1,79 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{KeyReadWrite, Nomt};
+
+fn commit(nomt: &Nomt<nomt::Blake3Hasher>, id: u64, block_number: Option<u64>) {
+    let mut session = nomt.begin_session();
+    if let Some(block_number) = block_number {
+        session.set_block_number(block_number);
+    }
+    let actuals = vec![(account_path(id), KeyReadWrite::Write(Some(vec![1; 8])))];
+    nomt.commit(session, actuals).unwrap();
+}
+
+#[test]
+fn block_numbers_map_to_syncs() {
+    let dir = test_dir("block_number");
+    let path = dir.path().join("db");
+    {
+        let t = Test::open(&path);
+        let nomt = t.nomt();
+        assert_eq!(nomt.last_block_number(), None);
+        commit(nomt, 0, Some(100));
+        commit(nomt, 1, Some(101));
+        commit(nomt, 2, None);
+        commit(nomt, 3, Some(102));
+        assert_eq!(nomt.last_block_number(), Some(102));
+        assert_eq!(nomt.sync_seqn(), 4);
+    }
+
+    let t = Test::open(&path);
+    let nomt = t.nomt();
+    assert_eq!(nomt.last_block_number(), Some(102));
+    assert_eq!(nomt.seqn_for_block(100), Some(1));
+    assert_eq!(nomt.seqn_for_block(101), Some(2));
+    assert_eq!(nomt.seqn_for_block(102), Some(4));
+    assert_eq!(nomt.seqn_for_block(103), None);
+
+    // A commit without a block number clears the last one, but not the index.
+    commit(nomt, 4, None);
+    assert_eq!(nomt.last_block_number(), None);
+    assert_eq!(nomt.seqn_for_block(102), Some(4));
+
+    // The last record wins if a block number is committed again.
+    commit(nomt, 5, Some(101));
+    assert_eq!(nomt.seqn_for_block(101), Some(6));
+}
+
+#[test]
+fn torn_index_record_is_restored_from_manifest() {
+    let dir = test_dir("block_number_torn");
+    let path = dir.path().join("db");
+    {
+        let t = Test::open(&path);
+        let nomt = t.nomt();
+        commit(nomt, 0, Some(7));
+        commit(nomt, 1, Some(8));
+    }
+
+    // Simulate a crash in the middle of writing the last record.
+    let blocks = std::fs::OpenOptions::new()
+        .write(true)
+        .open(path.join("blocks"))
+        .unwrap();
+    let len = blocks.metadata().unwrap().len();
+    blocks.set_len(len - 5).unwrap();
+    drop(blocks);
+
+    let t = Test::open(&path);
+    let nomt = t.nomt();
+    assert_eq!(nomt.seqn_for_block(7), Some(1));
+    assert_eq!(nomt.seqn_for_block(8), Some(2));
+    commit(nomt, 2, Some(9));
+
+    let t = t.reopen();
+    let nomt = t.nomt();
+    assert_eq!(nomt.seqn_for_block(8), Some(2));
+    assert_eq!(nomt.seqn_for_block(9), Some(3));
+}
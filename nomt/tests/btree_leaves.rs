@@ -0,0 +1,31 @@
+mod common;
+
+use common::Test;
+use nomt::LeafInfo;
+
+#[test]
+fn btree_leaves_cover_all_values() {
+    let mut t = Test::new("btree_leaves");
+    assert_eq!(t.btree_leaves().len(), 0);
+
+    for id in 0..10_000 {
+        common::set_balance(&mut t, id, 1000);
+    }
+    t.commit();
+
+    let leaves = t.btree_leaves();
+    assert!(leaves.len() > 1);
+    assert_eq!(leaves.iter().map(|l| l.entries).sum::<usize>(), 10_000);
+    assert!(leaves.windows(2).all(|w| w[0].separator < w[1].separator));
+    assert!(leaves
+        .iter()
+        .all(|l| l.used_bytes <= LeafInfo::CAPACITY && l.fill_level() > 0.0));
+
+    // Further commits are not blocked once the iterator is gone.
+    common::set_balance(&mut t, 10_000, 1000);
+    t.commit();
+    assert_eq!(
+        t.btree_leaves().iter().map(|l| l.entries).sum::<usize>(),
+        10_001
+    );
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyReadWrite, ManualClock, Nomt};
+
+fn commit(nomt: &Nomt<Blake3Hasher>, round: u8) {
+    let mut session = nomt.begin_session();
+    session.set_commit_token([round; 32]);
+    nomt.commit(
+        session,
+        vec![(account_path(0), KeyReadWrite::Write(Some(vec![round])))],
+    )
+    .unwrap();
+}
+
+#[test]
+fn coalescing_delay_follows_the_clock() {
+    let clock = Arc::new(ManualClock::new(0));
+    let dir = test_dir("clock_coalescing");
+    let t = Test::open_with(dir.path().join("db"), {
+        let clock = clock.clone();
+        move |o| {
+            o.commit_coalescing(100, Duration::from_secs(60));
+            o.clock(clock.clone());
+        }
+    });
+    let nomt = t.nomt();
+    commit(nomt, 1);
+    clock.advance(Duration::from_secs(59));
+    commit(nomt, 2);
+    assert_eq!(nomt.last_commit_token(), None);
+
+    // The delay has passed by the third commit, which syncs all of them.
+    clock.advance(Duration::from_secs(1));
+    commit(nomt, 3);
+    assert_eq!(nomt.last_commit_token(), Some([3; 32]));
+
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(nomt.read_amplification().interval, Duration::from_secs(65));
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::{account_path, test_dir, Test};
+use nomt::{BackgroundErrorSource, FaultInjector, KeyReadWrite, Node, Nomt, Options};
+
+fn commit(nomt: &Nomt<nomt::Blake3Hasher>, round: u64) -> Node {
+    let mut actuals = (0..100)
+        .map(|id| {
+            let value = if id % 10 == round % 10 {
+                None
+            } else {
+                Some(vec![round as u8; 16])
+            };
+            (account_path(id), KeyReadWrite::Write(value))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(path, _)| *path);
+    let mut session = nomt.begin_session();
+    session.set_commit_token([round as u8; 32]);
+    nomt.commit(session, actuals).unwrap()
+}
+
+#[test]
+fn coalesced_commits_match_individual_commits() {
+    let dir = test_dir("commit_coalescing");
+    let path = dir.path().join("coalesced");
+    let plain = Test::open(dir.path().join("plain"));
+    let t = Test::open_with(&path, |o| o.commit_coalescing(4, Duration::from_secs(3600)));
+    let coalesced = t.nomt();
+
+    for round in 1..=6 {
+        assert_eq!(commit(coalesced, round), commit(plain.nomt(), round));
+
+        // Reads observe the commit right away, whether it has been synced or not.
+        assert_eq!(coalesced.read(account_path(round)).unwrap(), None);
+        assert_eq!(
+            coalesced.read(account_path(round + 1)).unwrap(),
+            Some(vec![round as u8; 16])
+        );
+
+        // Only every fourth commit is synced.
+        let synced = if round < 4 { None } else { Some([4; 32]) };
+        assert_eq!(coalesced.last_commit_token(), synced);
+    }
+
+    coalesced.flush().unwrap();
+    assert_eq!(coalesced.last_commit_token(), Some([6; 32]));
+    let root = coalesced.root();
+
+    let t = t.reopen();
+    let reopened = t.nomt();
+    assert_eq!(reopened.root(), root);
+    assert_eq!(reopened.read(account_path(7)).unwrap(), Some(vec![6; 16]));
+    assert_eq!(reopened.read(account_path(6)).unwrap(), None);
+}
+
+#[test]
+fn drop_syncs_coalesced_commits() {
+    let dir = test_dir("commit_coalescing_drop");
+    let path = dir.path().join("db");
+    let t = Test::open_with(&path, |o| {
+        o.commit_coalescing(100, Duration::from_secs(3600))
+    });
+    let nomt = t.nomt();
+    commit(nomt, 1);
+    commit(nomt, 2);
+    assert_eq!(nomt.last_commit_token(), None);
+    let root = nomt.root();
+
+    let t = t.reopen();
+    let reopened = t.nomt();
+    assert_eq!(reopened.root(), root);
+    assert_eq!(reopened.last_commit_token(), Some([2; 32]));
+    assert_eq!(reopened.read(account_path(3)).unwrap(), Some(vec![2; 16]));
+}
+
+struct FailingWal;
+
+impl FaultInjector for FailingWal {
+    fn wal_write(&self, _sync_seqn: u32, _wal_blob: &[u8]) -> std::io::Result<()> {
+        Err(std::io::Error::other("injected"))
+    }
+}
+
+#[test]
+fn failed_flush_on_drop_is_reported() {
+    let dir = test_dir("commit_coalescing_drop_error");
+    let path = dir.path().join("db");
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let failing_wal = |coalescing: bool| {
+        let errors = errors.clone();
+        move |o: &mut Options| {
+            if coalescing {
+                o.commit_coalescing(100, Duration::from_secs(3600));
+            }
+            o.fault_injector(Arc::new(FailingWal));
+            let errors = errors.clone();
+            o.on_background_error(move |e| errors.lock().unwrap().push(e.source));
+        }
+    };
+    let t = Test::open_with(&path, failing_wal(true));
+    commit(t.nomt(), 1);
+    drop(t);
+    assert_eq!(
+        *errors.lock().unwrap(),
+        vec![BackgroundErrorSource::FlushOnDrop]
+    );
+
+    // Nothing is reported if there are no commits to sync.
+    errors.lock().unwrap().clear();
+    let t = Test::open_with(&path, failing_wal(false));
+    assert_eq!(t.nomt().last_commit_token(), None);
+    drop(t);
+    assert!(errors.lock().unwrap().is_empty());
+}
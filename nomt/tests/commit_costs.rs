@@ -0,0 +1,51 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::KeyReadWrite;
+
+fn actuals(ids: std::ops::Range<u64>, value: Option<Vec<u8>>) -> Vec<([u8; 32], KeyReadWrite)> {
+    let mut actuals = ids
+        .map(|id| (account_path(id), KeyReadWrite::Write(value.clone())))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    actuals
+}
+
+#[test]
+fn costs_account_for_writes_and_pages() {
+    let dir = test_dir("commit_costs");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+
+    let session = nomt.begin_session();
+    let output = nomt
+        .commit_with_costs(session, actuals(0..1000, Some(vec![1; 10])))
+        .unwrap();
+    assert_eq!(output.root, nomt.root());
+    let costs = output.costs;
+    assert_eq!(costs.writes, 1000);
+    assert_eq!(costs.reads, 0);
+    assert_eq!(costs.value_bytes, 10_000);
+    assert!(costs.new_pages > 0);
+    assert!(costs.pages_touched >= costs.new_pages);
+    assert_eq!(costs.deleted_pages, 0);
+    assert!(costs.hashes > costs.writes);
+    assert!(costs.pages_per_write().unwrap() > 0.0);
+
+    let session = nomt.begin_session();
+    let costs = nomt
+        .commit_with_costs(session, actuals(0..1000, None))
+        .unwrap()
+        .costs;
+    assert_eq!(costs.writes, 1000);
+    assert_eq!(costs.value_bytes, 0);
+    assert!(costs.deleted_pages > 0);
+    assert_eq!(costs.new_pages, 0);
+
+    let session = nomt.begin_session();
+    let read = vec![(account_path(0), KeyReadWrite::Read(None))];
+    let costs = nomt.commit_with_costs(session, read).unwrap().costs;
+    assert_eq!(costs.reads, 1);
+    assert_eq!(costs.pages_touched, 0);
+    assert_eq!(costs.pages_per_write(), None);
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use common::Test;
+
+#[test]
+fn commit_token_persists_across_reopen() {
+    {
+        let mut t = Test::new("commit_token");
+        assert_eq!(t.last_commit_token(), None);
+
+        common::set_balance(&mut t, 0, 1000);
+        t.set_commit_token([1; 32]);
+        t.commit();
+        assert_eq!(t.last_commit_token(), Some([1; 32]));
+
+        common::set_balance(&mut t, 1, 1000);
+        t.set_commit_token([2; 32]);
+        t.commit();
+        assert_eq!(t.last_commit_token(), Some([2; 32]));
+    }
+
+    let mut t = Test::new_with_params("commit_token", 1, 64_000, false, false);
+    assert_eq!(t.last_commit_token(), Some([2; 32]));
+
+    // A commit without a token clears it.
+    common::set_balance(&mut t, 2, 1000);
+    t.commit();
+    assert_eq!(t.last_commit_token(), None);
+}
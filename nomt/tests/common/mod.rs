@@ -1,4 +1,7 @@
-use nomt::{KeyPath, KeyReadWrite, Node, Nomt, Options, Session, Witness, WitnessedOperations};
+use nomt::{
+    CacheResult, CommitToken, KeyPath, KeyReadWrite, LeafInfo, Node, Nomt, Options, Session,
+    SessionStats, Witness, WitnessedOperations,
+};
 use std::{
     collections::{hash_map::Entry, HashMap},
     mem,
@@ -36,17 +39,36 @@ fn opts(path: PathBuf) -> Options {
     opts
 }
 
+/// A fresh directory for the databases of the test with the given name, removed along with them
+/// when dropped.
+#[allow(dead_code)]
+pub fn test_dir(name: &str) -> tempfile::TempDir {
+    std::fs::create_dir_all("test").unwrap();
+    tempfile::Builder::new()
+        .prefix(&format!("{name}-"))
+        .tempdir_in("test")
+        .unwrap()
+}
+
+/// A database opened by a test, along with the reads and writes of its next commit.
+///
+/// The session of the commit is only begun by the first access, so that the database can be used
+/// directly through [`Test::nomt`] as well.
 pub struct Test {
+    path: PathBuf,
+    configure: Box<dyn Fn(&mut Options) + Send>,
     nomt: Nomt<nomt::Blake3Hasher>,
     session: Option<Session>,
     access: HashMap<KeyPath, KeyReadWrite>,
 }
 
 impl Test {
+    #[allow(unused)]
     pub fn new(name: impl AsRef<Path>) -> Self {
         Self::new_with_params(name, 1, 64_000, false, true)
     }
 
+    #[allow(unused)]
     pub fn new_with_params(
         name: impl AsRef<Path>,
         commit_concurrency: usize,
@@ -62,18 +84,98 @@ impl Test {
         if cleanup_dir {
             let _ = std::fs::remove_dir_all(&path);
         }
-        let mut o = opts(path);
-        o.panic_on_sync(panic_on_sync);
-        o.bitbox_seed([0; 16]);
-        o.hashtable_buckets(hashtable_buckets);
-        o.commit_concurrency(commit_concurrency);
-        let nomt = Nomt::open(o).unwrap();
-        let session = nomt.begin_session();
-        Self {
-            nomt,
-            session: Some(session),
+        Self::open_with(path, move |o| {
+            o.panic_on_sync(panic_on_sync);
+            o.bitbox_seed([0; 16]);
+            o.hashtable_buckets(hashtable_buckets);
+            o.preallocate_ht(true);
+            o.commit_concurrency(commit_concurrency);
+        })
+    }
+
+    /// Open the database at the given path with the default options of the tests: a small
+    /// hash-table which is not preallocated.
+    #[allow(unused)]
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        Self::open_with(path, |_| {})
+    }
+
+    /// Open the database at the given path with the default options, adjusted by `configure`.
+    /// They are adjusted the same way whenever the database is reopened.
+    #[allow(unused)]
+    pub fn open_with(
+        path: impl Into<PathBuf>,
+        configure: impl Fn(&mut Options) + Send + 'static,
+    ) -> Self {
+        Self::try_open_with(path, configure).unwrap()
+    }
+
+    /// Like [`Test::open_with`], for tests of failures to open.
+    #[allow(unused)]
+    pub fn try_open_with(
+        path: impl Into<PathBuf>,
+        configure: impl Fn(&mut Options) + Send + 'static,
+    ) -> anyhow::Result<Self> {
+        let path = path.into();
+        let mut o = opts(path.clone());
+        o.hashtable_buckets(10_000);
+        o.preallocate_ht(false);
+        configure(&mut o);
+        Ok(Self {
+            path,
+            configure: Box::new(configure),
+            nomt: Nomt::open(o)?,
+            session: None,
             access: HashMap::default(),
-        }
+        })
+    }
+
+    /// Close the database and open it again with the same options. Uncommitted accesses are
+    /// discarded.
+    #[allow(unused)]
+    pub fn reopen(self) -> Self {
+        let Self {
+            path,
+            configure,
+            nomt,
+            session,
+            ..
+        } = self;
+        drop(session);
+        drop(nomt);
+        Self::open_with(path, configure)
+    }
+
+    /// Close the database and open it again, with the default options adjusted by `configure`
+    /// from now on.
+    #[allow(unused)]
+    pub fn reopen_with(self, configure: impl Fn(&mut Options) + Send + 'static) -> Self {
+        let path = self.path.clone();
+        drop(self);
+        Self::open_with(path, configure)
+    }
+
+    /// The path of the database.
+    #[allow(unused)]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The database, to be used directly while no reads or writes are pending.
+    #[allow(unused)]
+    pub fn nomt(&self) -> &Nomt<nomt::Blake3Hasher> {
+        &self.nomt
+    }
+
+    /// Like [`Test::nomt`], for the methods which need exclusive access.
+    #[allow(unused)]
+    pub fn nomt_mut(&mut self) -> &mut Nomt<nomt::Blake3Hasher> {
+        &mut self.nomt
+    }
+
+    fn session(&mut self) -> &mut Session {
+        self.session
+            .get_or_insert_with(|| self.nomt.begin_session())
     }
 
     pub fn write_id(&mut self, id: u64, value: Option<Vec<u8>>) {
@@ -89,7 +191,7 @@ impl Test {
                 v.insert(KeyReadWrite::Write(value));
             }
         }
-        self.session.as_mut().unwrap().warm_up(key);
+        self.session().warm_up(key);
     }
 
     pub fn read_id(&mut self, id: u64) -> Option<Vec<u8>> {
@@ -100,7 +202,9 @@ impl Test {
         match self.access.entry(key) {
             Entry::Occupied(o) => o.get().last_value().map(|v| v.to_vec()),
             Entry::Vacant(v) => {
-                let session = self.session.as_mut().unwrap();
+                let session = self
+                    .session
+                    .get_or_insert_with(|| self.nomt.begin_session());
                 let value = session.read(key).unwrap();
                 session.warm_up(key);
                 v.insert(KeyReadWrite::Read(value.clone()));
@@ -109,13 +213,62 @@ impl Test {
         }
     }
 
+    #[allow(unused)]
+    pub fn warm_up_id(&mut self, id: u64) {
+        self.session().warm_up(account_path(id));
+    }
+
+    #[allow(unused)]
+    pub fn session_stats(&mut self) -> SessionStats {
+        self.session().stats()
+    }
+
+    #[allow(unused)]
+    pub fn read_cached_id(&mut self, id: u64) -> CacheResult {
+        self.session().read_cached(account_path(id))
+    }
+
+    #[allow(unused)]
+    pub fn btree_leaves(&self) -> Vec<LeafInfo> {
+        self.nomt.btree_leaves().collect()
+    }
+
+    #[allow(unused)]
+    pub fn contains_batch(&mut self, keys: &[KeyPath]) -> Vec<bool> {
+        self.session()
+            .contains_batch(keys)
+            .unwrap()
+            .iter()
+            .by_vals()
+            .collect()
+    }
+
+    #[allow(unused)]
+    pub fn set_commit_token(&mut self, token: CommitToken) {
+        self.session().set_commit_token(token);
+    }
+
+    #[allow(unused)]
+    pub fn last_commit_token(&self) -> Option<CommitToken> {
+        self.nomt.last_commit_token()
+    }
+
+    #[allow(unused)]
+    pub fn drop_caches(&mut self, release_memory: bool) {
+        // the session must be dropped first.
+        self.session = None;
+        self.nomt.drop_caches(release_memory).unwrap();
+    }
+
+    #[allow(unused)]
     pub fn commit(&mut self) -> (Node, Witness, WitnessedOperations) {
-        let session = mem::take(&mut self.session).unwrap();
+        let session = self
+            .session
+            .take()
+            .unwrap_or_else(|| self.nomt.begin_session());
         let mut actual_access: Vec<_> = mem::take(&mut self.access).into_iter().collect();
         actual_access.sort_by_key(|(k, _)| *k);
-        let x = self.nomt.commit_and_prove(session, actual_access).unwrap();
-        self.session = Some(self.nomt.begin_session());
-        x
+        self.nomt.commit_and_prove(session, actual_access).unwrap()
     }
 }
 
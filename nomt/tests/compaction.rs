@@ -0,0 +1,91 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyReadWrite, Nomt};
+
+fn value(id: u64) -> Vec<u8> {
+    // The first accounts store values too large for a leaf.
+    let len = if id < 40 { 10_000 } else { 100 };
+    vec![id as u8; len]
+}
+
+fn write(nomt: &Nomt<Blake3Hasher>, ids: impl Iterator<Item = u64>, delete: bool) {
+    let mut actuals = ids
+        .map(|id| {
+            let value = (!delete).then(|| value(id));
+            (account_path(id), KeyReadWrite::Write(value))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+}
+
+#[test]
+fn compact_after_deleting_most_keys() {
+    let dir = test_dir("compaction");
+    let path = dir.path().join("db");
+    let t = Test::open_with(&path, |o| o.commit_concurrency(2));
+    let nomt = t.nomt();
+    write(nomt, 0..8000, false);
+    let stats = nomt.compaction_stats();
+    assert_eq!(stats.live_entries, 8000);
+    assert_eq!(stats.dead_entries, 0);
+    assert!(stats.overflow_pages >= 40 * 2, "{stats:?}");
+    assert!(stats.overflow_waste() > 0.0 && stats.overflow_waste() < 1.0);
+
+    // Keep one key out of eight, spread over the whole key space.
+    write(nomt, (0..8000).filter(|id| id % 8 != 0), true);
+    let root = nomt.root();
+    let before = nomt.compaction_stats();
+    assert_eq!(before.live_entries, 1000);
+    assert!(before.ln_free_pages > 0, "{before:?}");
+    assert!(before.leaf_pages < stats.leaf_pages, "{before:?}");
+
+    nomt.compact().unwrap();
+    let after = nomt.compaction_stats();
+    assert_eq!(after.live_entries, 1000);
+    assert_eq!(after.leaf_pages, before.leaf_pages);
+    assert!(after.ln_pages < before.ln_pages, "{before:?} {after:?}");
+    assert!(after.ln_fragmentation() < before.ln_fragmentation());
+    assert_eq!(nomt.root(), root);
+    for id in [0, 8, 32, 800, 7992] {
+        assert_eq!(nomt.read(account_path(id)).unwrap(), Some(value(id)));
+    }
+    assert_eq!(nomt.read(account_path(1)).unwrap(), None);
+
+    // The database keeps working after compaction, also when reopened.
+    write(nomt, 8000..9000, false);
+    let root = nomt.root();
+    let t = t.reopen_with(|_| {});
+    let nomt = t.nomt();
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.read(account_path(16)).unwrap(), Some(value(16)));
+    assert_eq!(nomt.read(account_path(8500)).unwrap(), Some(value(8500)));
+    assert_eq!(nomt.compaction_stats().live_entries, 2000);
+}
+
+#[test]
+fn compaction_stats_count_deletions_held_back() {
+    let dir = test_dir("compaction_held_back");
+    let t = Test::open_with(dir.path().join("db"), |o| {
+        o.commit_concurrency(2);
+        o.commit_coalescing(4, Duration::from_secs(3600));
+    });
+    let nomt = t.nomt();
+    write(nomt, 0..1000, false);
+    nomt.flush().unwrap();
+    write(nomt, 0..100, true);
+
+    // The deletions are committed but not synced yet.
+    let stats = nomt.compaction_stats();
+    assert_eq!(stats.dead_entries, 100);
+    assert_eq!(stats.live_entries, 1000);
+
+    nomt.compact().unwrap();
+    let stats = nomt.compaction_stats();
+    assert_eq!(stats.dead_entries, 0);
+    assert_eq!(stats.live_entries, 900);
+}
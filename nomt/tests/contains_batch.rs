@@ -0,0 +1,32 @@
+mod common;
+
+use common::{account_path, Test};
+
+#[test]
+fn contains_batch_reflects_committed_state() {
+    let mut t = Test::new("contains_batch");
+
+    for id in 0..1000 {
+        common::set_balance(&mut t, id, 1000);
+    }
+    t.commit();
+
+    // Keys present and absent, interleaved and unsorted.
+    let ids = [5, 2000, 999, 1000, 0, 123456, 512];
+    let keys = ids.iter().map(|id| account_path(*id)).collect::<Vec<_>>();
+    assert_eq!(
+        t.contains_batch(&keys),
+        vec![true, false, true, false, true, false, true],
+    );
+
+    for id in 0..500 {
+        common::kill(&mut t, id);
+    }
+    t.commit();
+
+    assert_eq!(
+        t.contains_batch(&keys),
+        vec![false, false, true, false, false, false, true],
+    );
+    assert!(t.contains_batch(&[]).is_empty());
+}
@@ -0,0 +1,169 @@
+#![cfg(feature = "crash-simulation")]
+
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{CrashPoint, CrashSimulator, KeyPath, KeyReadWrite, Node, Nomt, Options, SyncedFile};
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+const BATCHES: u64 = 4;
+
+type Model = BTreeMap<KeyPath, Vec<u8>>;
+
+fn crash_simulator(crash_simulator: Option<Arc<CrashSimulator>>) -> impl Fn(&mut Options) {
+    move |o| {
+        o.bitbox_seed([0; 16]);
+        o.hashtable_buckets(16_000);
+        o.audit_merkle_updates(true);
+        if let Some(ref crash_simulator) = crash_simulator {
+            o.crash_simulator(crash_simulator.clone());
+        }
+    }
+}
+
+fn batch(round: u64) -> Vec<(KeyPath, KeyReadWrite)> {
+    let mut actuals = (0..1000)
+        .map(|id| {
+            let value = if (id + round).is_multiple_of(3) {
+                None
+            } else {
+                Some(vec![round as u8; 8])
+            };
+            (account_path(id), KeyReadWrite::Write(value))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    actuals
+}
+
+fn apply(model: &mut Model, round: u64) {
+    for (key, access) in batch(round) {
+        match access {
+            KeyReadWrite::Write(Some(value)) => model.insert(key, value),
+            _ => model.remove(&key),
+        };
+    }
+}
+
+/// The state after each number of committed batches.
+struct Reference {
+    roots: Vec<Node>,
+    models: Vec<Model>,
+    occupied_buckets: Vec<usize>,
+}
+
+fn reference(path: &Path) -> Reference {
+    // Tests run in parallel, so each builds a reference of its own.
+    let t = Test::open_with(path, crash_simulator(None));
+    let nomt = t.nomt();
+    let mut reference = Reference {
+        roots: vec![nomt.root()],
+        models: vec![Model::new()],
+        occupied_buckets: vec![nomt.hash_table_stats().occupied_buckets],
+    };
+    for round in 0..BATCHES {
+        let session = nomt.begin_session();
+        nomt.commit(session, batch(round)).unwrap();
+        let mut model = reference.models.last().unwrap().clone();
+        apply(&mut model, round);
+        reference.roots.push(nomt.root());
+        reference.models.push(model);
+        let occupied_buckets = nomt.hash_table_stats().occupied_buckets;
+        reference.occupied_buckets.push(occupied_buckets);
+    }
+    reference
+}
+
+/// Commit batches until the simulator crashes and return the number of the crashed batch.
+fn commit_until_crash(nomt: &Nomt<nomt::Blake3Hasher>, crash_simulator: &CrashSimulator) -> u64 {
+    for round in 0..BATCHES {
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let session = nomt.begin_session();
+            nomt.commit(session, batch(round))
+        }));
+        if crash_simulator.has_crashed() {
+            assert!(!matches!(r, Ok(Ok(_))));
+            return round;
+        }
+        r.unwrap().unwrap();
+    }
+    panic!("the crash point was not reached");
+}
+
+fn crash_and_recover(dir: &Path, reference: &Reference, file: SyncedFile, nth: usize, seed: u64) {
+    let path = dir.join(format!("{nth}_{seed}"));
+    let simulator = CrashSimulator::new(CrashPoint { file, nth }, seed);
+
+    let t = Test::open_with(&path, crash_simulator(Some(simulator.clone())));
+    let crashed_round = commit_until_crash(t.nomt(), &simulator);
+    assert_eq!(crashed_round, nth as u64 - 1);
+    drop(t);
+    simulator.power_loss().unwrap();
+
+    // The hash-table is written out after the manifest, so the crashed batch is committed then.
+    let committed = match file {
+        SyncedFile::HashTable => nth,
+        _ => nth - 1,
+    };
+    let context = format!("crash at {file:?} sync {nth}, seed {seed}");
+
+    let t = Test::open_with(&path, crash_simulator(None));
+    let nomt = t.nomt();
+    assert_eq!(nomt.root(), reference.roots[committed], "{context}");
+    let values = nomt.iter().collect::<Model>();
+    assert_eq!(values, reference.models[committed], "{context}");
+    assert_eq!(
+        nomt.hash_table_stats().occupied_buckets,
+        reference.occupied_buckets[committed],
+        "{context}"
+    );
+
+    // The recovered database must keep committing like one which never crashed.
+    let session = nomt.begin_session();
+    nomt.commit(session, batch(committed as u64)).unwrap();
+    assert_eq!(nomt.root(), reference.roots[committed + 1], "{context}");
+
+    let t = t.reopen();
+    let nomt = t.nomt();
+    assert_eq!(nomt.root(), reference.roots[committed + 1], "{context}");
+    assert_eq!(
+        nomt.iter().collect::<Model>(),
+        reference.models[committed + 1],
+        "{context}"
+    );
+}
+
+fn crash_at(file: SyncedFile) {
+    let dir = test_dir(&format!("crash_consistency_{file:?}"));
+    let reference = reference(&dir.path().join("reference"));
+    for nth in 1..BATCHES as usize {
+        for seed in 0..2 {
+            crash_and_recover(dir.path(), &reference, file, nth, seed);
+        }
+    }
+}
+
+#[test]
+fn crash_at_wal_sync() {
+    crash_at(SyncedFile::Wal);
+}
+
+#[test]
+fn crash_at_leaves_sync() {
+    crash_at(SyncedFile::Leaves);
+}
+
+#[test]
+fn crash_at_branches_sync() {
+    crash_at(SyncedFile::Branches);
+}
+
+#[test]
+fn crash_at_meta_sync() {
+    crash_at(SyncedFile::Meta);
+}
+
+#[test]
+fn crash_at_hash_table_sync() {
+    crash_at(SyncedFile::HashTable);
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use bitvec::prelude::*;
+use common::{test_dir, Test};
+use nomt::{
+    debug::{dump_subtree, DumpFormat},
+    KeyReadWrite, Nomt,
+};
+
+fn key(first_byte: u8) -> [u8; 32] {
+    let mut key = [0; 32];
+    key[0] = first_byte;
+    key
+}
+
+fn populate(nomt: &Nomt<nomt::Blake3Hasher>) {
+    // Keys 00.., 01.. and 10.. in binary.
+    let actuals = [0b0000_0000, 0b0100_0000, 0b1000_0000]
+        .into_iter()
+        .enumerate()
+        .map(|(i, b)| (key(b), KeyReadWrite::Write(Some(vec![0; i + 1]))))
+        .collect();
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+}
+
+#[test]
+fn dump_text() {
+    let dir = test_dir("debug_dump_text");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    populate(nomt);
+    let dump = dump_subtree(nomt, BitSlice::empty(), 8, DumpFormat::Text).unwrap();
+    let lines = dump.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 5, "{dump}");
+    assert!(lines[0].starts_with("[] internal "));
+    assert!(lines[1].starts_with("  [0] internal "));
+    assert!(lines[2].starts_with("    [00] leaf "));
+    assert!(lines[2].ends_with("key=00000000 value=1 bytes"));
+    assert!(lines[3].ends_with("key=40000000 value=2 bytes"));
+    assert!(lines[4].starts_with("  [1] leaf "));
+    assert!(lines[4].ends_with("value=3 bytes"));
+
+    let dump = dump_subtree(nomt, bits![u8, Msb0; 0], 0, DumpFormat::Text).unwrap();
+    assert_eq!(dump.lines().count(), 2, "{dump}");
+    assert!(dump.starts_with("[0] internal "));
+    assert!(dump.ends_with("  ...\n"));
+}
+
+#[test]
+fn dump_dot() {
+    let dir = test_dir("debug_dump_dot");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    populate(nomt);
+    let dump = dump_subtree(nomt, BitSlice::empty(), 8, DumpFormat::Dot).unwrap();
+    assert!(dump.starts_with("digraph subtree {"));
+    assert!(dump.contains("  n -> n0 [label=\"0\"];"));
+    assert!(dump.contains("  n0 -> n01 [label=\"1\"];"));
+    assert!(dump.contains("  n01 [label=\"[01] leaf "));
+    assert!(dump.ends_with("}\n"));
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use bitvec::prelude::*;
+use common::{test_dir, Test};
+use nomt::{
+    Blake3Hasher, CacheResult, KeyPath, KeyReadWrite, LeafData, Options, ValueHandle, ValueHasher,
+};
+use nomt_core::trie::TERMINATOR;
+
+const ZERO: [u8; 8] = [0; 8];
+
+fn default_values(o: &mut Options) {
+    o.default_value(bits![u8, Msb0; 1, 1], ZERO.to_vec());
+    o.default_value(bits![u8, Msb0; 1, 1, 1, 1], vec![1]);
+}
+
+/// A key under the prefix `11`, but not `1111`.
+fn account(id: u8) -> KeyPath {
+    let mut key = [id; 32];
+    key[0] = 0b1100_0000;
+    key
+}
+
+#[test]
+fn absent_keys_read_as_default() {
+    let dir = test_dir("default_values_read");
+    let t = Test::open_with(dir.path().join("db"), default_values);
+    let nomt = t.nomt();
+    let other = [0; 32];
+    let mut nested = [0; 32];
+    nested[0] = 0b1111_0000;
+    assert_eq!(nomt.default_value(account(1)), Some(&ZERO[..]));
+    assert_eq!(nomt.default_value(nested), Some(&[1][..]));
+    assert_eq!(nomt.default_value(other), None);
+
+    let mut session = nomt.begin_session();
+    session.write_all([(account(1), Some(ValueHandle::from(vec![5; 8])))]);
+    nomt.commit(session, Vec::new()).unwrap();
+
+    let session = nomt.begin_session();
+    assert_eq!(session.read(account(1)).unwrap(), Some(vec![5; 8]));
+    assert_eq!(session.read(account(2)).unwrap(), Some(ZERO.to_vec()));
+    assert_eq!(session.read(nested).unwrap(), Some(vec![1]));
+    assert_eq!(session.read(other).unwrap(), None);
+    assert_eq!(
+        session.read_cached(account(2)),
+        CacheResult::Hit(Some(ZERO.to_vec()))
+    );
+    assert_eq!(
+        session.explain_read(account(2)).unwrap().value,
+        Some(ZERO.to_vec())
+    );
+}
+
+#[test]
+fn writing_the_default_deletes_the_key() {
+    let dir = test_dir("default_values_write");
+    let t = Test::open_with(dir.path().join("db"), default_values);
+    let nomt = t.nomt();
+    let mut session = nomt.begin_session();
+    session.write_all([(account(1), Some(ValueHandle::from(vec![5; 8])))]);
+    nomt.commit(session, Vec::new()).unwrap();
+    assert_ne!(nomt.root(), TERMINATOR);
+
+    // Reading and writing the default value are taken as reading and deleting an absent key.
+    let mut actuals = vec![
+        (
+            account(1),
+            KeyReadWrite::ReadThenWrite(Some(vec![5; 8]), Some(ZERO.to_vec())),
+        ),
+        (account(2), KeyReadWrite::Write(Some(ZERO.to_vec()))),
+    ];
+    actuals.sort_by_key(|(key, _)| *key);
+    let keys = vec![account(1), account(2)];
+    let session = nomt.begin_session();
+    let (root, proofs) = session
+        .finish_with_proofs(nomt, actuals, keys.clone())
+        .unwrap();
+    assert_eq!(root, TERMINATOR);
+
+    // Proofs of absence prove the default value.
+    for (key, proof) in keys.iter().zip(&proofs) {
+        let verified = proof
+            .verify::<Blake3Hasher>(key.view_bits::<Msb0>(), root)
+            .unwrap();
+        let default = LeafData {
+            key_path: *key,
+            value_hash: Blake3Hasher::hash_value(&ZERO),
+        };
+        let other = LeafData {
+            key_path: *key,
+            value_hash: Blake3Hasher::hash_value(&[5; 8]),
+        };
+        let default_hash = Blake3Hasher::hash_value(&ZERO);
+        assert!(verified
+            .confirm_value_or_default(&default, default_hash)
+            .unwrap());
+        assert!(!verified
+            .confirm_value_or_default(&other, default_hash)
+            .unwrap());
+    }
+}
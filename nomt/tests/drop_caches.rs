@@ -0,0 +1,34 @@
+mod common;
+
+use common::Test;
+use nomt::CacheResult;
+
+#[test]
+fn drop_caches_leaves_a_cold_database() {
+    let mut t = Test::new("drop_caches");
+    for id in 0..1000 {
+        common::set_balance(&mut t, id, 1000);
+    }
+    let (root, _, _) = t.commit();
+    let balance = Some(1000u64.to_le_bytes().to_vec());
+    assert_eq!(t.read_cached_id(0), CacheResult::Hit(balance.clone()));
+
+    t.drop_caches(true);
+    assert_eq!(t.read_cached_id(0), CacheResult::Miss);
+    for id in 0..1000 {
+        assert_eq!(common::read_balance(&mut t, id), Some(1000));
+    }
+    assert_eq!(t.read_cached_id(0), CacheResult::Hit(balance));
+
+    // The database remains usable: commits load the evicted trie pages again.
+    t.drop_caches(false);
+    for id in 0..1000 {
+        common::set_balance(&mut t, id, 2000);
+    }
+    let (new_root, _, _) = t.commit();
+    assert_ne!(new_root, root);
+    for id in 0..1000 {
+        common::set_balance(&mut t, id, 1000);
+    }
+    assert_eq!(t.commit().0, root);
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{Durability, KeyReadWrite};
+
+#[test]
+fn power_loss_protected_durability() {
+    let dir = test_dir("durability");
+    let path = dir.path().join("db");
+    let t = Test::open_with(&path, |o| {
+        o.durability(Durability::PowerLossProtected);
+        o.metrics(true);
+    });
+    let nomt = t.nomt();
+    #[cfg(feature = "metrics")]
+    assert!(nomt.metrics().snapshot().unwrap().fsync_skipping);
+    for i in 0..10 {
+        let session = nomt.begin_session();
+        let actuals = vec![(account_path(i), KeyReadWrite::Write(Some(vec![i as u8; 8])))];
+        nomt.commit(session, actuals).unwrap();
+    }
+    let root = nomt.root();
+
+    let t = t.reopen();
+    let nomt = t.nomt();
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.read(account_path(3)).unwrap(), Some(vec![3; 8]));
+}
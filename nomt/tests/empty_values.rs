@@ -0,0 +1,104 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyReadWrite, Nomt, SessionParams, ValueCompression, ValueHasher};
+use std::time::Duration;
+
+fn write(nomt: &Nomt<Blake3Hasher>, id: u64, value: Option<Vec<u8>>) {
+    let session = nomt.begin_session();
+    nomt.commit(
+        session,
+        vec![(account_path(id), KeyReadWrite::Write(value))],
+    )
+    .unwrap();
+}
+
+fn read(nomt: &Nomt<Blake3Hasher>, id: u64) -> Option<Vec<u8>> {
+    nomt.begin_session().read(account_path(id)).unwrap()
+}
+
+#[test]
+fn empty_value_is_present() {
+    let dir = test_dir("empty_values_present");
+    let t = Test::open_with(dir.path().join("db"), |o| o.bitbox_seed([0; 16]));
+    let nomt = t.nomt();
+    write(nomt, 1, Some(Vec::new()));
+    write(nomt, 2, None);
+
+    assert_eq!(read(nomt, 1), Some(Vec::new()));
+    assert_eq!(read(nomt, 2), None);
+    assert!(!nomt.is_empty());
+    let session = nomt.begin_session();
+    assert_eq!(
+        session.iter_range([0; 32], [255; 32]).collect::<Vec<_>>(),
+        vec![(account_path(1), Vec::new())]
+    );
+    drop(session);
+
+    // Deleting the empty value empties the trie again.
+    write(nomt, 1, None);
+    assert_eq!(read(nomt, 1), None);
+    assert!(nomt.is_empty());
+}
+
+#[test]
+fn empty_value_is_witnessed_by_its_hash() {
+    let dir = test_dir("empty_values_witness");
+    let t = Test::open_with(dir.path().join("db"), |o| o.bitbox_seed([0; 16]));
+    let nomt = t.nomt();
+    write(nomt, 1, Some(Vec::new()));
+    let prev_root = nomt.root();
+
+    let mut params = SessionParams::default();
+    params.record_witness(true);
+    let session = nomt.begin_session_with_params(params);
+    let mut actuals = vec![
+        (account_path(1), KeyReadWrite::Read(Some(Vec::new()))),
+        (account_path(2), KeyReadWrite::Read(None)),
+    ];
+    actuals.sort_by_key(|(key, _)| *key);
+    let (_, witness, witnessed) = nomt.commit_and_prove(session, actuals).unwrap();
+
+    let values = witness
+        .prior_values::<Blake3Hasher>(&witnessed, prev_root)
+        .unwrap();
+    assert_eq!(
+        values[&account_path(1)],
+        Some(Blake3Hasher::hash_value(&[]))
+    );
+    assert_eq!(values[&account_path(2)], None);
+}
+
+#[test]
+fn rollback_restores_empty_value() {
+    let dir = test_dir("empty_values_rollback");
+    let t = Test::open_with(dir.path().join("db"), |o| {
+        o.bitbox_seed([0; 16]);
+        o.rollback(true);
+    });
+    let nomt = t.nomt();
+    write(nomt, 1, Some(Vec::new()));
+
+    write(nomt, 1, Some(vec![1]));
+    nomt.rollback(1).unwrap();
+    assert_eq!(read(nomt, 1), Some(Vec::new()));
+
+    write(nomt, 1, None);
+    nomt.rollback(1).unwrap();
+    assert_eq!(read(nomt, 1), Some(Vec::new()));
+}
+
+#[test]
+fn empty_value_survives_compression_and_coalescing() {
+    let dir = test_dir("empty_values_coalescing");
+    let t = Test::open_with(dir.path().join("db"), |o| {
+        o.bitbox_seed([0; 16]);
+        o.value_compression(ValueCompression::Zstd { level: 3 });
+        o.commit_coalescing(10, Duration::from_secs(60));
+    });
+    let nomt = t.nomt();
+    write(nomt, 1, Some(Vec::new()));
+    assert_eq!(read(nomt, 1), Some(Vec::new()));
+    nomt.flush().unwrap();
+    assert_eq!(read(nomt, 1), Some(Vec::new()));
+}
@@ -0,0 +1,155 @@
+#![cfg(feature = "encryption")]
+
+mod common;
+
+use common::{test_dir, Test};
+
+use nomt::{KeyReadWrite, Node, Nomt, Options};
+
+const KEY: [u8; 32] = [7; 32];
+
+/// A value which is easy to find in the files, large enough to be stored in overflow pages.
+fn value(i: u8) -> Vec<u8> {
+    let mut value = b"plaintext value ".repeat(1000);
+    value.push(i);
+    value
+}
+
+fn key(key: Option<[u8; 32]>, panic_on_sync: bool) -> impl Fn(&mut Options) {
+    move |o| {
+        o.hashtable_buckets(10_000);
+        o.panic_on_sync(panic_on_sync);
+        if let Some(key) = key {
+            o.encryption_key(key);
+        }
+    }
+}
+
+fn commit(nomt: &Nomt<nomt::Blake3Hasher>) -> Node {
+    let session = nomt.begin_session();
+    let actuals = (0..100u8)
+        .map(|i| ([i; 32], KeyReadWrite::Write(Some(value(i)))))
+        .collect();
+    nomt.commit(session, actuals).unwrap()
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+#[test]
+fn encrypted_files_roundtrip() {
+    let dir = test_dir("encryption_roundtrip");
+    let path = dir.path().join("db");
+
+    let t = Test::open_with(&path, key(Some(KEY), false));
+    let root = commit(t.nomt());
+    drop(t);
+
+    for file in ["ht", "ln", "bbn"] {
+        let data = std::fs::read(path.join(file)).unwrap();
+        assert!(
+            !contains(&data, b"plaintext value"),
+            "{file} is not encrypted"
+        );
+    }
+
+    let t = Test::open_with(&path, key(Some(KEY), false));
+    let nomt = t.nomt();
+    assert_eq!(nomt.root(), root);
+    for i in 0..100u8 {
+        assert_eq!(nomt.read([i; 32]).unwrap(), Some(value(i)));
+    }
+    let session = nomt.begin_session();
+    let actuals = vec![([0; 32], KeyReadWrite::Write(None))];
+    nomt.commit(session, actuals).unwrap();
+    assert_eq!(nomt.read([0; 32]).unwrap(), None);
+}
+
+#[test]
+fn encrypted_wal_is_replayed() {
+    let dir = test_dir("encryption_wal");
+    let path = dir.path().join("db");
+
+    let plain = dir.path().join("plain");
+    let expected_root = commit(Test::open_with(&plain, key(None, false)).nomt());
+
+    // Crash after the WAL has been written, before the hash-table has.
+    let t = Test::open_with(&path, key(Some(KEY), true));
+    let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| commit(t.nomt())));
+    assert!(r.is_err());
+    drop(t);
+    assert!(std::fs::metadata(path.join("wal")).unwrap().len() > 0);
+
+    let t = Test::open_with(&path, key(Some(KEY), false));
+    let nomt = t.nomt();
+    assert_eq!(nomt.root(), expected_root);
+    for i in 0..100u8 {
+        assert_eq!(nomt.read([i; 32]).unwrap(), Some(value(i)));
+    }
+}
+
+#[test]
+fn opening_requires_the_right_key() {
+    let dir = test_dir("encryption_key");
+    let path = dir.path().join("db");
+
+    drop(Test::open_with(&path, key(Some(KEY), false)));
+    assert!(Test::try_open_with(&path, key(None, false)).is_err());
+    assert!(Test::try_open_with(&path, key(Some([8; 32]), false)).is_err());
+    drop(Test::open_with(&path, key(Some(KEY), false)));
+
+    let plain = dir.path().join("plain");
+    drop(Test::open_with(&plain, key(None, false)));
+    assert!(Test::try_open_with(&plain, key(Some(KEY), false)).is_err());
+}
+
+#[test]
+fn rollback_is_rejected() {
+    let dir = test_dir("encryption_rollback");
+    let t = Test::try_open_with(dir.path().join("db"), |o| {
+        o.encryption_key(KEY);
+        o.rollback(true);
+    });
+    assert!(t.is_err());
+}
+
+#[test]
+fn value_log_is_rejected() {
+    let dir = test_dir("encryption_value_log");
+    let t = Test::try_open_with(dir.path().join("db"), |o| {
+        o.encryption_key(KEY);
+        o.value_log_threshold(1 << 20);
+    });
+    assert!(t.is_err());
+}
+
+#[test]
+fn wal_archive_is_rejected() {
+    let dir = test_dir("encryption_wal_archive");
+    let archive = dir.path().join("archive");
+    let t = Test::try_open_with(dir.path().join("db"), move |o| {
+        o.encryption_key(KEY);
+        o.wal_archive_dir(archive.clone());
+    });
+    assert!(t.is_err());
+}
+
+#[test]
+fn aux_column_is_encrypted() {
+    let dir = test_dir("encryption_aux");
+    let path = dir.path().join("db");
+
+    let t = Test::open_with(&path, key(Some(KEY), false));
+    let nomt = t.nomt();
+    let mut session = nomt.begin_session();
+    session.write_aux(b"plaintext key".to_vec(), Some(value(1)));
+    nomt.commit(session, Vec::new()).unwrap();
+    drop(t);
+
+    let data = std::fs::read(path.join("aux")).unwrap();
+    assert!(!contains(&data, b"plaintext"));
+
+    let t = Test::open_with(&path, key(Some(KEY), false));
+    assert_eq!(t.nomt().read_aux(b"plaintext key"), Some(value(1)));
+}
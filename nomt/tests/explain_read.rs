@@ -0,0 +1,74 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, Nomt, ReadStepKind, ValueHandle};
+
+fn populate(nomt: &Nomt<Blake3Hasher>) {
+    let mut session = nomt.begin_session();
+    session.write_all((0..1000).map(|id| (account_path(id), Some(ValueHandle::from(vec![1; 32])))));
+    session.write_all([(account_path(1000), Some(ValueHandle::from(vec![2; 20_000])))]);
+    nomt.commit(session, Vec::new()).unwrap();
+}
+
+#[test]
+fn explains_cold_and_warm_reads() {
+    let dir = test_dir("explain_read_cold");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    populate(nomt);
+    nomt.drop_caches(false).unwrap();
+
+    let session = nomt.begin_session();
+    let cold = session.explain_read(account_path(7)).unwrap();
+    assert_eq!(cold.value, Some(vec![1; 32]));
+    assert_eq!(cold.steps[0].kind, ReadStepKind::Expiry { expired: false });
+    assert_eq!(cold.steps[1].kind, ReadStepKind::Staging { hit: false });
+    assert!(matches!(
+        cold.steps[2].kind,
+        ReadStepKind::BranchIndex { leaf: Some(_) }
+    ));
+    assert!(matches!(
+        cold.steps[3].kind,
+        ReadStepKind::Leaf { cached: false, .. }
+    ));
+    assert!(cold.steps[3].bytes_read > 0);
+    let trie_pages = cold
+        .steps
+        .iter()
+        .filter(|step| matches!(step.kind, ReadStepKind::TriePage { .. }))
+        .count();
+    assert!(trie_pages >= 2);
+    assert!(cold.bytes_read() > 0);
+    assert!(!cold.to_string().is_empty());
+
+    // The leaf is cached by the first explanation.
+    let warm = session.explain_read(account_path(7)).unwrap();
+    assert!(matches!(
+        warm.steps[3].kind,
+        ReadStepKind::Leaf { cached: true, .. }
+    ));
+    assert_eq!(warm.steps[3].bytes_read, 0);
+}
+
+#[test]
+fn explains_large_and_absent_values() {
+    let dir = test_dir("explain_read_large");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    populate(nomt);
+
+    let session = nomt.begin_session();
+    let large = session.explain_read(account_path(1000)).unwrap();
+    assert_eq!(large.value, Some(vec![2; 20_000]));
+    assert!(large
+        .steps
+        .iter()
+        .any(|step| matches!(step.kind, ReadStepKind::Overflow { pages } if pages > 1)));
+
+    let absent = session.explain_read(account_path(5000)).unwrap();
+    assert_eq!(absent.value, None);
+    assert!(!absent
+        .steps
+        .iter()
+        .any(|step| matches!(step.kind, ReadStepKind::Overflow { .. })));
+}
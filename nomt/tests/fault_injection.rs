@@ -0,0 +1,235 @@
+mod common;
+
+use common::{test_dir, Test};
+use nomt::{
+    FaultInjector, IoBackend, KeyPath, KeyReadWrite, Node, Nomt, Options, PageIo, SyncedFile,
+};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+#[derive(Default)]
+struct RecordingInjector {
+    page_writes: Mutex<usize>,
+    wal_writes: Mutex<Vec<u32>>,
+    fsyncs: Mutex<Vec<SyncedFile>>,
+}
+
+impl FaultInjector for RecordingInjector {
+    fn page_io(&self, io: PageIo) -> std::io::Result<()> {
+        if let PageIo::Write(_) = io {
+            *self.page_writes.lock().unwrap() += 1;
+        }
+        Ok(())
+    }
+
+    fn wal_write(&self, sync_seqn: u32, _wal_blob: &[u8]) -> std::io::Result<()> {
+        self.wal_writes.lock().unwrap().push(sync_seqn);
+        Ok(())
+    }
+
+    fn fsync(&self, file: SyncedFile) -> std::io::Result<()> {
+        self.fsyncs.lock().unwrap().push(file);
+        Ok(())
+    }
+}
+
+#[test]
+fn fault_injector_observes_commit_io() {
+    let dir = test_dir("fault_injection");
+    let injector = Arc::new(RecordingInjector::default());
+    let t = Test::open_with(dir.path().join("db"), {
+        let injector = injector.clone();
+        move |o| o.fault_injector(injector.clone())
+    });
+    let nomt = t.nomt();
+
+    for i in 0..2u8 {
+        let session = nomt.begin_session();
+        nomt.commit(session, vec![([i; 32], KeyReadWrite::Write(Some(vec![i])))])
+            .unwrap();
+    }
+
+    assert_eq!(*injector.wal_writes.lock().unwrap(), vec![1, 2]);
+    assert!(*injector.page_writes.lock().unwrap() > 0);
+    let fsyncs = injector.fsyncs.lock().unwrap();
+    for file in [
+        SyncedFile::Meta,
+        SyncedFile::HashTable,
+        SyncedFile::Wal,
+        SyncedFile::Leaves,
+        SyncedFile::Branches,
+    ] {
+        assert_eq!(fsyncs.iter().filter(|f| **f == file).count(), 2, "{file:?}");
+    }
+    assert!(!fsyncs.contains(&SyncedFile::WalMirror));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FaultPoint {
+    PageRead,
+    PageWrite,
+    WalWrite,
+    Fsync(SyncedFile),
+}
+
+/// Fails every operation at the fault point once armed.
+struct FailingInjector {
+    point: FaultPoint,
+    armed: AtomicBool,
+}
+
+impl FailingInjector {
+    fn fail(&self, point: FaultPoint) -> std::io::Result<()> {
+        if point == self.point && self.armed.load(Ordering::Relaxed) {
+            return Err(std::io::Error::other(format!("injected fault: {point:?}")));
+        }
+        Ok(())
+    }
+}
+
+impl FaultInjector for FailingInjector {
+    fn page_io(&self, io: PageIo) -> std::io::Result<()> {
+        match io {
+            PageIo::Read(_) => self.fail(FaultPoint::PageRead),
+            PageIo::Write(_) => self.fail(FaultPoint::PageWrite),
+        }
+    }
+
+    fn wal_write(&self, _sync_seqn: u32, _wal_blob: &[u8]) -> std::io::Result<()> {
+        self.fail(FaultPoint::WalWrite)
+    }
+
+    fn fsync(&self, file: SyncedFile) -> std::io::Result<()> {
+        self.fail(FaultPoint::Fsync(file))
+    }
+}
+
+fn injector(path: &Path, injector: Option<Arc<FailingInjector>>) -> impl Fn(&mut Options) {
+    let wal_mirror = path.join("wal-mirror");
+    move |o| {
+        o.hashtable_buckets(10_000);
+        // The faults are injected regardless of the backend, and the I/O workers of io_uring keep
+        // polling after the database is closed, which slows down reopening it for every fault point.
+        o.io_backend(IoBackend::Posix);
+        o.wal_mirror(wal_mirror.clone());
+        if let Some(ref injector) = injector {
+            o.fault_injector(injector.clone());
+        }
+    }
+}
+
+fn key(i: u8, byte: u8) -> KeyPath {
+    let mut key = [0; 32];
+    key[0] = i;
+    key[1] = byte;
+    key
+}
+
+fn commit(nomt: &Nomt<nomt::Blake3Hasher>, byte: u8) -> anyhow::Result<Node> {
+    let actuals = (0..=255u8)
+        .map(|i| (key(i, byte), KeyReadWrite::Write(Some(vec![byte; 64]))))
+        .collect::<Vec<_>>();
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals)
+}
+
+/// Fail a commit at the fault point and check that the database recovers to either the state
+/// before or after the commit on reopening.
+fn fail_commit_and_recover(name: &str, point: FaultPoint) {
+    let dir = test_dir(&format!("fault_injection_{name}"));
+    let path = dir.path().join("db");
+
+    let failing = Arc::new(FailingInjector {
+        point,
+        armed: AtomicBool::new(false),
+    });
+    let t = Test::open_with(&path, injector(&path, Some(failing.clone())));
+    let nomt = t.nomt();
+    let prev_root = commit(nomt, 1).unwrap();
+
+    // The caches are dropped, so that the pages of the commit are read from disk.
+    nomt.drop_caches(true).unwrap();
+    failing.armed.store(true, Ordering::Relaxed);
+    assert!(commit(nomt, 2).is_err(), "{point:?}");
+
+    let t = t.reopen_with(injector(&path, None));
+    let nomt = t.nomt();
+    let recovered = nomt.read(key(0, 2)).unwrap();
+    if nomt.root() == prev_root {
+        assert_eq!(recovered, None, "{point:?}");
+    } else {
+        assert_eq!(recovered, Some(vec![2; 64]), "{point:?}");
+    }
+    assert_eq!(nomt.read(key(0, 1)).unwrap(), Some(vec![1; 64]));
+    commit(nomt, 3).unwrap();
+}
+
+#[test]
+fn failed_page_read_fails_commit() {
+    fail_commit_and_recover("page_read", FaultPoint::PageRead);
+}
+
+#[test]
+fn failed_page_write_fails_commit() {
+    fail_commit_and_recover("page_write", FaultPoint::PageWrite);
+}
+
+#[test]
+fn failed_wal_write_fails_commit() {
+    fail_commit_and_recover("wal_write", FaultPoint::WalWrite);
+}
+
+#[test]
+fn failed_fsync_fails_commit() {
+    for file in [
+        SyncedFile::Meta,
+        SyncedFile::HashTable,
+        SyncedFile::Wal,
+        SyncedFile::WalMirror,
+        SyncedFile::Leaves,
+        SyncedFile::Branches,
+    ] {
+        fail_commit_and_recover(&format!("fsync_{file:?}"), FaultPoint::Fsync(file));
+    }
+}
+
+#[test]
+fn failed_sync_poisons_database() {
+    for point in [
+        FaultPoint::WalWrite,
+        FaultPoint::Fsync(SyncedFile::Meta),
+        FaultPoint::Fsync(SyncedFile::HashTable),
+    ] {
+        let dir = test_dir(&format!("fault_injection_poison_{point:?}"));
+        let path = dir.path().join("db");
+
+        let failing = Arc::new(FailingInjector {
+            point,
+            armed: AtomicBool::new(false),
+        });
+        let t = Test::open_with(&path, injector(&path, Some(failing.clone())));
+        let nomt = t.nomt();
+        commit(nomt, 1).unwrap();
+        failing.armed.store(true, Ordering::Relaxed);
+        assert!(commit(nomt, 2).is_err(), "{point:?}");
+
+        // Once the fault is gone, the database still refuses to commit and to read, instead of
+        // building on the state of the failed sync.
+        failing.armed.store(false, Ordering::Relaxed);
+        assert!(commit(nomt, 3).is_err(), "{point:?}");
+        assert!(nomt.read(key(0, 1)).is_err(), "{point:?}");
+        assert!(nomt.check_snapshot().is_err(), "{point:?}");
+
+        let t = t.reopen_with(injector(&path, None));
+        let nomt = t.nomt();
+        assert_eq!(nomt.read(key(0, 1)).unwrap(), Some(vec![1; 64]));
+        assert_eq!(nomt.read(key(0, 3)).unwrap(), None);
+        commit(nomt, 3).unwrap();
+        assert_eq!(nomt.read(key(0, 3)).unwrap(), Some(vec![3; 64]));
+    }
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{KeyReadWrite, Nomt};
+
+fn commit(nomt: &Nomt<nomt::Blake3Hasher>, ids: std::ops::Range<u64>, value: u8) {
+    let mut actuals = ids
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![value; 8]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+}
+
+#[test]
+fn fork_branches_state() {
+    let dir = test_dir("fork");
+    let path = dir.path().join("source");
+    let fork_path = dir.path().join("fork");
+
+    let t = Test::open(&path);
+    let nomt = t.nomt();
+    commit(nomt, 0..1000, 1);
+    nomt.fork_to(&fork_path).unwrap();
+    assert!(nomt.fork_to(&fork_path).is_err());
+
+    // The original and the fork evolve independently.
+    commit(nomt, 0..10, 2);
+    let fork = Test::open(&fork_path);
+    assert_ne!(fork.nomt().root(), nomt.root());
+    assert_eq!(fork.nomt().read(account_path(0)).unwrap(), Some(vec![1; 8]));
+    commit(fork.nomt(), 1000..1010, 3);
+    assert_eq!(nomt.read(account_path(1000)).unwrap(), None);
+
+    // The fork reproduces the root of the original given the same commits.
+    commit(fork.nomt(), 0..10, 2);
+    drop(fork);
+    let fork = Test::open(&fork_path);
+    commit(nomt, 1000..1010, 3);
+    assert_eq!(fork.nomt().root(), nomt.root());
+}
+
+#[test]
+fn speculative_fork_is_discarded() {
+    let dir = test_dir("fork_speculative");
+    let path = dir.path().join("source");
+    let fork_path = dir.path().join("fork");
+
+    let t = Test::open(&path);
+    let nomt = t.nomt();
+    commit(nomt, 0..1000, 1);
+    let root = nomt.root();
+
+    let fork = nomt.fork(&fork_path).unwrap();
+    assert_eq!(fork.root(), root);
+    assert!(nomt.fork(&fork_path).is_err());
+    for block in 0..10 {
+        commit(&fork, block * 100..block * 100 + 200, block as u8 + 2);
+    }
+    assert_ne!(fork.root(), root);
+    assert_eq!(fork.read(account_path(0)).unwrap(), Some(vec![2; 8]));
+
+    // Nothing committed to the fork reaches the original.
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.read(account_path(0)).unwrap(), Some(vec![1; 8]));
+    drop(fork);
+    assert!(!fork_path.exists());
+
+    // The original keeps working and can be forked again.
+    commit(nomt, 0..10, 2);
+    let root = nomt.root();
+    let fork = nomt.fork(&fork_path).unwrap();
+    assert_eq!(fork.root(), root);
+    drop(fork);
+    let t = t.reopen();
+    assert_eq!(t.nomt().root(), root);
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn speculative_fork_keeps_encryption_key() {
+    let dir = test_dir("fork_encrypted");
+    let path = dir.path().join("source");
+    let fork_path = dir.path().join("fork");
+
+    let t = Test::open_with(&path, |o| o.encryption_key([7; 32]));
+    let nomt = t.nomt();
+    commit(nomt, 0..1000, 1);
+    let fork = nomt.fork(&fork_path).unwrap();
+    assert_eq!(fork.read(account_path(0)).unwrap(), Some(vec![1; 8]));
+    commit(&fork, 0..10, 2);
+    assert_eq!(fork.read(account_path(0)).unwrap(), Some(vec![2; 8]));
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{test_dir, Test};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use nomt::HashTableCreationProgress;
+
+fn create(path: &Path, preallocate: bool) -> Vec<HashTableCreationProgress> {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let t = Test::open_with(path, {
+        let reports = reports.clone();
+        move |o| {
+            let reports = reports.clone();
+            o.hashtable_buckets(100_000);
+            o.preallocate_ht(preallocate);
+            o.hash_table_creation_progress(move |progress| reports.lock().unwrap().push(progress));
+        }
+    });
+
+    // Reopening an existing database doesn't create the file again.
+    drop(t.reopen_with({
+        let reports = reports.clone();
+        move |o| {
+            let reports = reports.clone();
+            o.hash_table_creation_progress(move |progress| reports.lock().unwrap().push(progress))
+        }
+    }));
+
+    let reports = reports.lock().unwrap().clone();
+    reports
+}
+
+fn check_reports(path: &Path, reports: &[HashTableCreationProgress]) {
+    let total = std::fs::metadata(path.join("ht")).unwrap().len();
+    assert_eq!(reports.first().unwrap().allocated, 0);
+    assert_eq!(reports.last().unwrap().allocated, total);
+    assert!(reports.iter().all(|progress| progress.total == total));
+    assert!(reports
+        .windows(2)
+        .all(|pair| pair[0].allocated < pair[1].allocated));
+}
+
+#[test]
+fn preallocation_reports_progress() {
+    let dir = test_dir("hash_table_creation_preallocate");
+    let path = dir.path().join("db");
+    let reports = create(&path, true);
+    check_reports(&path, &reports);
+}
+
+#[test]
+fn sparse_creation_reports_progress() {
+    let dir = test_dir("hash_table_creation_sparse");
+    let path = dir.path().join("db");
+    let reports = create(&path, false);
+    check_reports(&path, &reports);
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::{account_path, test_dir};
+use nomt::{KeyReadWrite, NodeHasher, NodePreimage, Nomt, Options, ValueHasher};
+
+/// Blake3 in keyed mode: a hash algorithm distinct from `Blake3Hasher`.
+struct KeyedBlake3Hasher;
+
+impl NodeHasher for KeyedBlake3Hasher {
+    fn hash_node(data: &NodePreimage) -> [u8; 32] {
+        blake3::keyed_hash(&[7; 32], data).into()
+    }
+}
+
+impl ValueHasher for KeyedBlake3Hasher {
+    fn hash_value(data: &[u8]) -> [u8; 32] {
+        blake3::keyed_hash(&[7; 32], data).into()
+    }
+}
+
+fn options(path: &std::path::Path) -> Options {
+    let mut o = Options::new();
+    o.path(path);
+    o.hashtable_buckets(10_000);
+    o.preallocate_ht(false);
+    o
+}
+
+#[test]
+fn reopening_with_another_hasher_fails() {
+    let dir = test_dir("hasher_mismatch");
+    let path = dir.path().join("db");
+    let root = {
+        let nomt = Nomt::<nomt::Blake3Hasher>::open(options(&path)).unwrap();
+        let session = nomt.begin_session();
+        let actuals = vec![(account_path(0), KeyReadWrite::Write(Some(vec![1; 8])))];
+        nomt.commit(session, actuals).unwrap();
+        nomt.root()
+    };
+
+    let err = Nomt::<KeyedBlake3Hasher>::open(options(&path))
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string().contains("different hash algorithm"),
+        "{err}"
+    );
+
+    let nomt = Nomt::<nomt::Blake3Hasher>::open(options(&path)).unwrap();
+    assert_eq!(nomt.root(), root);
+}
+
+#[cfg(feature = "keccak")]
+#[test]
+fn keccak_hasher_matches_evm() {
+    use nomt::Keccak256Hasher;
+    assert_eq!(
+        Keccak256Hasher::hash_value(&[]),
+        [
+            0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7,
+            0x03, 0xc0, 0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04,
+            0x5d, 0x85, 0xa4, 0x70,
+        ]
+    );
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyReadWrite, Node, Nomt, Options};
+
+fn compaction_budget(compaction_budget: usize, panic_on_sync: bool) -> impl Fn(&mut Options) {
+    move |o| {
+        o.bitbox_seed([0; 16]);
+        o.hashtable_buckets(20_000);
+        o.hashtable_compaction_budget(compaction_budget);
+        o.panic_on_sync(panic_on_sync);
+    }
+}
+
+fn write(nomt: &Nomt<Blake3Hasher>, ids: impl Iterator<Item = u64>, value: Option<u64>) -> Node {
+    let mut actuals = ids
+        .map(|id| {
+            let value = value.map(|v| (v + id).to_le_bytes().to_vec());
+            (account_path(id), KeyReadWrite::Write(value))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+    nomt.root()
+}
+
+// Populate the database, then delete most of it to leave many tombstones behind.
+fn populate(nomt: &Nomt<Blake3Hasher>) {
+    write(nomt, 0..3000, Some(0));
+    write(nomt, 0..2700, None);
+}
+
+#[test]
+fn compaction_preserves_pages() {
+    let dir = test_dir("hashtable_compaction");
+    let path = dir.path().join("compacted");
+    let reference = Test::open_with(dir.path().join("reference"), compaction_budget(0, false));
+    let reference = reference.nomt();
+    let t = Test::open_with(&path, compaction_budget(1000, false));
+    let compacted = t.nomt();
+    populate(reference);
+    populate(compacted);
+    let tombstones = compacted.hash_table_stats().tombstones;
+    assert!(tombstones > 0);
+
+    for round in 0..40 {
+        let ids = (2700 + round * 7..2700 + round * 7 + 7).collect::<Vec<_>>();
+        assert_eq!(
+            write(reference, ids.iter().copied(), Some(round)),
+            write(compacted, ids.iter().copied(), Some(round)),
+        );
+    }
+
+    let stats = compacted.hash_table_stats();
+    assert!(stats.relocated_pages > 0);
+    assert!(stats.reclaimed_tombstones > 0);
+    assert!(stats.tombstones < reference.hash_table_stats().tombstones);
+    assert!(stats.average_probe_length().unwrap() >= 1.0);
+
+    let t = t.reopen();
+    let compacted = t.nomt();
+    assert_eq!(compacted.root(), reference.root());
+    assert_eq!(
+        write(reference, 2500..3000, Some(1000)),
+        write(compacted, 2500..3000, Some(1000)),
+    );
+}
+
+#[test]
+fn compaction_is_recovered_from_wal() {
+    let dir = test_dir("hashtable_compaction_wal");
+    let path = dir.path().join("compacted");
+    let reference = Test::open_with(dir.path().join("reference"), compaction_budget(0, false));
+    let reference = reference.nomt();
+    populate(reference);
+    write(reference, 2700..2701, Some(1));
+
+    let t = Test::open_with(&path, compaction_budget(0, false));
+    populate(t.nomt());
+
+    // Crash after writing the WAL of a commit which relocates pages.
+    let t = t.reopen_with(compaction_budget(20_000, true));
+    let compacted = t.nomt();
+    let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        write(compacted, 2700..2701, Some(1));
+    }));
+    assert!(r.is_err());
+    assert!(compacted.hash_table_stats().relocated_pages > 0);
+
+    let t = t.reopen_with(compaction_budget(0, false));
+    let compacted = t.nomt();
+    assert_eq!(compacted.root(), reference.root());
+    assert_eq!(
+        write(reference, 2500..3000, Some(1000)),
+        write(compacted, 2500..3000, Some(1000)),
+    );
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyReadWrite, Node, Nomt, Options};
+
+fn buckets(buckets: u32) -> impl Fn(&mut Options) {
+    move |o| {
+        o.bitbox_seed([0; 16]);
+        o.hashtable_buckets(buckets);
+        o.hashtable_resize_budget(64);
+    }
+}
+
+fn write(nomt: &Nomt<Blake3Hasher>, ids: impl Iterator<Item = u64>, value: Option<u64>) -> Node {
+    let mut actuals = ids
+        .map(|id| {
+            let value = value.map(|v| (v + id).to_le_bytes().to_vec());
+            (account_path(id), KeyReadWrite::Write(value))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+    nomt.root()
+}
+
+// Commit to both databases in small rounds until the resize completes, checking that they agree.
+fn write_until_resized(reference: &Nomt<Blake3Hasher>, resized: &Nomt<Blake3Hasher>) {
+    for round in 0..100 {
+        if resized.hash_table_stats().resizing_to.is_none() {
+            return;
+        }
+        let ids = (round * 7..round * 7 + 7).collect::<Vec<_>>();
+        assert_eq!(
+            write(reference, ids.iter().copied(), Some(round)),
+            write(resized, ids.iter().copied(), Some(round)),
+        );
+    }
+    panic!("resize did not complete");
+}
+
+#[test]
+fn resize_preserves_pages() {
+    let dir = test_dir("resize");
+    let path = dir.path().join("resized");
+    let reference = Test::open_with(dir.path().join("reference"), buckets(20_000));
+    let reference = reference.nomt();
+    let t = Test::open_with(&path, buckets(5_000));
+    let resized = t.nomt();
+    write(reference, 0..3000, Some(0));
+    write(resized, 0..3000, Some(0));
+    let occupied = resized.hash_table_stats().occupied_buckets;
+
+    resized.resize_hash_table(20_000).unwrap();
+    let stats = resized.hash_table_stats();
+    assert_eq!(stats.resizing_to, Some(20_000));
+    assert_eq!(stats.pages_to_migrate, occupied);
+    assert!(path.join("ht.resize").exists());
+
+    write_until_resized(reference, resized);
+    let stats = resized.hash_table_stats();
+    assert_eq!(stats.buckets, 20_000);
+    assert_eq!(stats.pages_to_migrate, 0);
+    assert!(stats.migrated_pages > 0);
+    assert!(!path.join("ht.resize").exists());
+
+    let t = t.reopen();
+    let resized = t.nomt();
+    assert_eq!(resized.hash_table_stats().buckets, 20_000);
+    assert_eq!(resized.root(), reference.root());
+    assert_eq!(
+        write(reference, 2500..3000, Some(1000)),
+        write(resized, 2500..3000, Some(1000)),
+    );
+}
+
+#[test]
+fn resize_continues_after_reopen() {
+    let dir = test_dir("resize_reopen");
+    let path = dir.path().join("resized");
+    let reference = Test::open_with(dir.path().join("reference"), buckets(20_000));
+    let reference = reference.nomt();
+    let t = Test::open_with(&path, buckets(5_000));
+    let resized = t.nomt();
+    write(reference, 0..3000, Some(0));
+    write(resized, 0..3000, Some(0));
+
+    resized.resize_hash_table(20_000).unwrap();
+    assert_eq!(
+        write(reference, 0..10, Some(1)),
+        write(resized, 0..10, Some(1)),
+    );
+    assert!(resized.hash_table_stats().migrated_pages > 0);
+
+    let t = t.reopen();
+    let resized = t.nomt();
+    let stats = resized.hash_table_stats();
+    assert_eq!(stats.buckets, 5_000);
+    assert_eq!(stats.resizing_to, Some(20_000));
+    assert_eq!(resized.root(), reference.root());
+
+    write_until_resized(reference, resized);
+    let t = t.reopen();
+    let resized = t.nomt();
+    assert_eq!(resized.hash_table_stats().buckets, 20_000);
+    assert_eq!(resized.root(), reference.root());
+}
+
+#[test]
+fn resize_started_without_commit_is_abandoned() {
+    let dir = test_dir("resize_abandoned");
+    let path = dir.path().join("db");
+    let t = Test::open_with(&path, buckets(5_000));
+    let nomt = t.nomt();
+    write(nomt, 0..100, Some(0));
+    let root = nomt.root();
+    nomt.resize_hash_table(20_000).unwrap();
+
+    let t = t.reopen();
+    let nomt = t.nomt();
+    let stats = nomt.hash_table_stats();
+    assert_eq!(stats.buckets, 5_000);
+    assert_eq!(stats.resizing_to, None);
+    assert_eq!(nomt.root(), root);
+    assert!(!path.join("ht.resize").exists());
+}
+
+#[test]
+fn resize_must_grow() {
+    let dir = test_dir("resize_must_grow");
+    let t = Test::open_with(dir.path().join("db"), buckets(5_000));
+    let nomt = t.nomt();
+    assert!(nomt.resize_hash_table(5_000).is_err());
+    nomt.resize_hash_table(10_000).unwrap();
+    assert!(nomt.resize_hash_table(20_000).is_err());
+}
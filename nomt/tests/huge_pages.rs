@@ -0,0 +1,51 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{HugePages, KeyReadWrite, Options, PagePool};
+
+fn commit_and_read(name: &str, o: impl Fn(&mut Options) + Send + 'static) {
+    let dir = test_dir(name);
+    let t = Test::open_with(dir.path().join("db"), o);
+    let nomt = t.nomt();
+
+    let mut actuals = (0..1000)
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![7; 32]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+    for id in 0..1000 {
+        assert_eq!(nomt.read(account_path(id)).unwrap(), Some(vec![7; 32]));
+    }
+}
+
+#[test]
+fn transparent_huge_pages() {
+    commit_and_read("huge_pages_transparent", |o| {
+        o.huge_pages(HugePages::Transparent)
+    });
+}
+
+#[test]
+fn explicit_huge_pages_fall_back() {
+    // Most test machines have no huge pages reserved, in which case regular pages are used.
+    commit_and_read("huge_pages_explicit", |o| o.huge_pages(HugePages::Explicit));
+}
+
+#[test]
+fn numa_node_binding() {
+    commit_and_read("huge_pages_numa", |o| {
+        o.huge_pages(HugePages::Transparent);
+        o.numa_node(0);
+    });
+}
+
+#[test]
+fn shared_pool_with_huge_pages() {
+    let page_pool = PagePool::with_regions(Some(512 * 1024 * 1024), HugePages::Explicit, Some(0));
+    commit_and_read("huge_pages_shared_pool", {
+        let page_pool = page_pool.clone();
+        move |o| o.page_pool(page_pool.clone())
+    });
+    assert!(page_pool.reserved_bytes() > 0);
+}
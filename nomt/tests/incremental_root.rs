@@ -0,0 +1,102 @@
+mod common;
+
+use bitvec::prelude::*;
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyPath, KeyReadWrite, Nomt, Value};
+
+fn commit(nomt: &Nomt<Blake3Hasher>, updates: &[(KeyPath, Option<Value>)]) {
+    let session = nomt.begin_session();
+    for (key, _) in updates {
+        session.warm_up(*key);
+    }
+    let actuals = updates
+        .iter()
+        .map(|(key, value)| (*key, KeyReadWrite::Write(value.clone())))
+        .collect();
+    nomt.commit(session, actuals).unwrap();
+}
+
+fn sorted(mut updates: Vec<(KeyPath, Option<Value>)>) -> Vec<(KeyPath, Option<Value>)> {
+    updates.sort_by_key(|(key, _)| *key);
+    updates
+}
+
+#[test]
+fn matches_commit() {
+    let dir = test_dir("incremental_root_matches_commit");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    commit(
+        nomt,
+        &sorted(
+            (0..500)
+                .map(|id| (account_path(id), Some(vec![1; 32])))
+                .collect(),
+        ),
+    );
+
+    // Overwrite, delete and insert keys.
+    let updates = sorted(
+        (0..100)
+            .map(|id| (account_path(id), Some(vec![2; 16])))
+            .chain((100..200).map(|id| (account_path(id), None)))
+            .chain((500..600).map(|id| (account_path(id), Some(vec![3; 8]))))
+            .chain([(account_path(1000), None)])
+            .collect(),
+    );
+
+    let mut incremental = nomt.incremental_root(4).unwrap();
+    let mut sealed = Vec::new();
+    for batch in updates.chunks(70) {
+        let newly_sealed = incremental.feed(batch).unwrap();
+        // The subtree of the last key of the batch stays open.
+        let last = batch.last().unwrap().0;
+        assert!(newly_sealed
+            .iter()
+            .all(|subtree| !last.view_bits::<Msb0>().starts_with(&subtree.prefix)));
+        sealed.extend(newly_sealed);
+    }
+    let (root, rest) = incremental.finish().unwrap();
+    sealed.extend(rest);
+    assert_eq!(sealed.len(), 16);
+
+    commit(nomt, &updates);
+    assert_eq!(root, nomt.root());
+    for subtree in sealed {
+        assert_eq!(subtree.root, nomt.export_subtree(&subtree.prefix).root());
+    }
+}
+
+#[test]
+fn sparse_trie_and_no_updates() {
+    let dir = test_dir("incremental_root_sparse");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    commit(nomt, &[(account_path(0), Some(vec![1; 4]))]);
+
+    // Unchanged subtrees keep their committed roots, including a leaf above the seal depth.
+    let (root, sealed) = nomt.incremental_root(8).unwrap().finish().unwrap();
+    assert_eq!(root, nomt.root());
+    assert_eq!(sealed.iter().filter(|s| s.root != [0; 32]).count(), 1);
+
+    // Deleting the only key empties the trie.
+    let mut incremental = nomt.incremental_root(0).unwrap();
+    incremental.feed(&[(account_path(0), None)]).unwrap();
+    let (root, _) = incremental.finish().unwrap();
+    assert_eq!(root, [0; 32]);
+}
+
+#[test]
+fn rejects_unsorted_keys() {
+    let dir = test_dir("incremental_root_unsorted");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    let mut incremental = nomt.incremental_root(2).unwrap();
+    let updates = sorted(vec![
+        (account_path(1), Some(vec![1])),
+        (account_path(2), Some(vec![2])),
+    ]);
+    incremental.feed(&updates[1..]).unwrap();
+    assert!(incremental.feed(&updates[..1]).is_err());
+    assert!(nomt.incremental_root(17).is_err());
+}
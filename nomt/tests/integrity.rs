@@ -0,0 +1,76 @@
+mod common;
+
+use std::{fs::OpenOptions, os::unix::fs::FileExt as _, path::Path};
+
+use common::{account_path, test_dir, Test};
+use nomt::{integrity, Blake3Hasher, ValueHandle};
+
+const BUCKETS: u32 = 10_000;
+
+fn populate(path: &Path) {
+    let t = Test::open_with(path, |o| {
+        o.commit_concurrency(1);
+        o.hashtable_buckets(BUCKETS);
+    });
+    let nomt = t.nomt();
+
+    let mut session = nomt.begin_session();
+    session.write_all((0..1000).map(|id| (account_path(id), Some(ValueHandle::from(vec![1; 32])))));
+    session.write_all([(account_path(1000), Some(ValueHandle::from(vec![2; 20_000])))]);
+    nomt.commit(session, Vec::new()).unwrap();
+
+    // Deletions clear pages, which must not be left behind.
+    let mut session = nomt.begin_session();
+    session.write_all((0..900).map(|id| (account_path(id), None)));
+    nomt.commit(session, Vec::new()).unwrap();
+}
+
+#[test]
+fn intact_database_passes() {
+    let dir = test_dir("integrity_intact");
+    let path = dir.path().join("db");
+    populate(&path);
+    let report = integrity::check::<Blake3Hasher>(&path).unwrap();
+    assert!(report.is_ok(), "{report}");
+    assert_eq!(report.leaves, 101);
+    assert_eq!(report.values, 101);
+    assert_eq!(report.pages, report.buckets);
+    assert!(report.internal_nodes > 0);
+}
+
+#[test]
+fn corrupted_pages_are_reported() {
+    let dir = test_dir("integrity_corrupted");
+    let path = dir.path().join("db");
+    populate(&path);
+
+    // Flip a bit of the first node of every occupied bucket, past the meta-map pages.
+    let page_size = 4096u64;
+    let meta_pages = (BUCKETS as u64).div_ceil(page_size);
+    let ht = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path.join("ht"))
+        .unwrap();
+    let mut page = vec![0; page_size as usize];
+    let mut corrupted = 0;
+    for bucket in 0..BUCKETS as u64 {
+        let offset = (meta_pages + bucket) * page_size;
+        ht.read_exact_at(&mut page, offset).unwrap();
+        if page.iter().all(|b| *b == 0) {
+            continue;
+        }
+        ht.write_all_at(&[page[1] ^ 1], offset + 1).unwrap();
+        corrupted += 1;
+    }
+    assert!(corrupted > 0);
+    drop(ht);
+
+    let report = integrity::check::<Blake3Hasher>(&path).unwrap();
+    assert!(!report.is_ok());
+    assert!(report.issues.iter().any(|issue| matches!(
+        issue,
+        integrity::Issue::InternalHashMismatch { .. } | integrity::Issue::LeafHashMismatch { .. }
+    )));
+    assert!(!report.to_string().is_empty());
+}
@@ -0,0 +1,35 @@
+mod common;
+
+use common::{test_dir, Test};
+use nomt::KeyReadWrite;
+
+#[test]
+fn commits_with_custom_io_limits() {
+    // Tiny batches and few requests in flight: the workers have to wait for completions often.
+    let dir = test_dir("io_limits");
+    let path = dir.path().join("db");
+    let t = Test::open_with(&path, |o| {
+        o.io_workers(2);
+        o.io_submit_batch(1);
+        o.io_max_in_flight(3);
+    });
+    let nomt = t.nomt();
+    for i in 0..10u8 {
+        let session = nomt.begin_session();
+        let actuals = (0..200u8)
+            .map(|j| ([j; 32], KeyReadWrite::Write(Some(vec![i, j]))))
+            .collect();
+        nomt.commit(session, actuals).unwrap();
+    }
+    let root = nomt.root();
+
+    let t = t.reopen_with(|o| {
+        o.io_workers(2);
+        o.io_submit_batch(512);
+        o.io_max_in_flight(1024);
+    });
+    let nomt = t.nomt();
+    assert_eq!(nomt.root(), root);
+    let session = nomt.begin_session();
+    assert_eq!(session.read([7; 32]).unwrap(), Some(vec![9, 7]));
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use common::{test_dir, Test};
+use nomt::{IoUringMode, KeyReadWrite};
+use std::path::Path;
+
+#[test]
+fn commits_with_interrupt_driven_io() {
+    let dir = test_dir("io_uring_mode");
+    let path = dir.path().join("db");
+    let t = Test::open_with(&path, |o| {
+        o.io_workers(2);
+        o.io_uring_mode(IoUringMode::Interrupt);
+    });
+    let nomt = t.nomt();
+    for i in 0..10u8 {
+        let session = nomt.begin_session();
+        let actuals = (0..100u8)
+            .map(|j| ([j; 32], KeyReadWrite::Write(Some(vec![i, j]))))
+            .collect();
+        nomt.commit(session, actuals).unwrap();
+    }
+    let root = nomt.root();
+
+    let t = t.reopen_with(|o| {
+        o.io_workers(2);
+        o.io_uring_mode(IoUringMode::IoPoll);
+    });
+    let nomt = t.nomt();
+    assert_eq!(nomt.root(), root);
+    let session = nomt.begin_session();
+    assert_eq!(session.read([7; 32]).unwrap(), Some(vec![9, 7]));
+}
+
+// Polling needs direct I/O, which tmpfs doesn't support, so the default mode falls back to
+// interrupts there.
+#[cfg(target_os = "linux")]
+#[test]
+fn default_mode_falls_back_on_tmpfs() {
+    let shm = Path::new("/dev/shm");
+    if !shm.is_dir() {
+        return;
+    }
+    let dir = tempfile::tempdir_in(shm).unwrap();
+    let t = Test::open_with(dir.path().join("db"), |o| o.io_workers(2));
+    let nomt = t.nomt();
+    let session = nomt.begin_session();
+    nomt.commit(session, vec![([1; 32], KeyReadWrite::Write(Some(vec![1])))])
+        .unwrap();
+    let root = nomt.root();
+
+    let t = t.reopen();
+    let nomt = t.nomt();
+    assert_eq!(nomt.root(), root);
+    let session = nomt.begin_session();
+    assert_eq!(session.read([1; 32]).unwrap(), Some(vec![1]));
+}
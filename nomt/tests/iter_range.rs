@@ -0,0 +1,71 @@
+mod common;
+
+use bitvec::prelude::*;
+use common::{account_path, test_dir, Test};
+use nomt::{KeyPath, KeyReadWrite, ValueHandle};
+use std::collections::BTreeMap;
+
+#[test]
+fn iter_range_reflects_session_writes() {
+    let dir = test_dir("iter_range");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    let mut model = BTreeMap::new();
+
+    let mut actuals = (0..3000u64)
+        .map(|id| {
+            let value = id.to_le_bytes().to_vec();
+            model.insert(account_path(id), value.clone());
+            (account_path(id), KeyReadWrite::Write(Some(value)))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+
+    // Overwrite, delete and insert keys, writing some of them twice.
+    let mut session = nomt.begin_session();
+    let mut writes = Vec::new();
+    for id in (0..3000).step_by(7) {
+        writes.push((account_path(id), None));
+    }
+    for id in (0..3000).step_by(11) {
+        writes.push((account_path(id), Some(vec![1; 4])));
+    }
+    for id in 3000..3500 {
+        writes.push((account_path(id), Some(vec![2; 4])));
+    }
+    for id in (3000..3500).step_by(5) {
+        writes.push((account_path(id), None));
+    }
+    for (key, value) in &writes {
+        match value {
+            Some(value) => model.insert(*key, value.clone()),
+            None => model.remove(key),
+        };
+    }
+    session.write_all(
+        writes
+            .into_iter()
+            .map(|(key, value)| (key, value.map(ValueHandle::new))),
+    );
+
+    let start: KeyPath = [0x40; 32];
+    let end: KeyPath = [0xc0; 32];
+    let expected = model
+        .range(start..=end)
+        .map(|(key, value)| (*key, value.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(session.iter_range(start, end).collect::<Vec<_>>(), expected);
+
+    let prefix = bits![u8, Msb0; 1, 0, 1];
+    let expected = model
+        .iter()
+        .filter(|(key, _)| key.view_bits::<Msb0>().starts_with(prefix))
+        .map(|(key, value)| (*key, value.clone()))
+        .collect::<Vec<_>>();
+    assert!(!expected.is_empty());
+    assert_eq!(session.iter_prefix(prefix).collect::<Vec<_>>(), expected);
+
+    assert_eq!(session.iter_range(end, start).next(), None);
+}
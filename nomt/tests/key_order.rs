@@ -0,0 +1,97 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use bitvec::prelude::*;
+use common::{account_path, test_dir, Test};
+use nomt::{KeyPath, KeyReadWrite, Nomt};
+
+fn commit(nomt: &Nomt<nomt::Blake3Hasher>, writes: &BTreeMap<KeyPath, Option<Vec<u8>>>) {
+    let actuals = writes
+        .iter()
+        .map(|(key, value)| (*key, KeyReadWrite::Write(value.clone())))
+        .collect();
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+}
+
+#[test]
+fn iteration_follows_trie_order() {
+    let dir = test_dir("key_order");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    let mut model = BTreeMap::new();
+
+    // Enough keys to span several batches, committed in rounds, with deletions in between, so that
+    // some keys are only in the b-tree and others still staged.
+    for round in 0..4u64 {
+        let mut writes = BTreeMap::new();
+        for id in round * 1000..(round + 1) * 1000 + 500 {
+            writes.insert(account_path(id), Some(id.to_le_bytes().to_vec()));
+        }
+        for id in (0..round * 1000).step_by(7) {
+            writes.insert(account_path(id), None);
+        }
+        commit(nomt, &writes);
+        for (key, value) in writes {
+            match value {
+                Some(value) => model.insert(key, value),
+                None => model.remove(&key),
+            };
+        }
+
+        let entries = nomt.iter().collect::<Vec<_>>();
+        assert_eq!(entries, model.clone().into_iter().collect::<Vec<_>>());
+    }
+
+    // Trie order is the bitwise order of the key paths, most significant bit first.
+    let keys = nomt.iter().map(|(key, _)| key).collect::<Vec<_>>();
+    assert!(keys
+        .windows(2)
+        .all(|w| w[0].view_bits::<Msb0>() < w[1].view_bits::<Msb0>()));
+
+    // The leaves of the trie, taken in iteration order, form the trie itself.
+    assert_eq!(nomt.export_subtree(BitSlice::empty()).root(), nomt.root());
+}
+
+#[test]
+fn iteration_order_is_stable() {
+    // A fixed vector: changing the order of these keys is a breaking change.
+    let keys = [
+        [0xff; 32],
+        [0x80; 32],
+        [0x00; 32],
+        [0x7f; 32],
+        [0x01; 32],
+        {
+            let mut key = [0x00; 32];
+            key[31] = 0x01;
+            key
+        },
+        {
+            let mut key = [0x80; 32];
+            key[1] = 0x00;
+            key
+        },
+    ];
+    let dir = test_dir("key_order_stable");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    commit(
+        nomt,
+        &keys.iter().map(|key| (*key, Some(vec![1]))).collect(),
+    );
+
+    let expected = [
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        "0000000000000000000000000000000000000000000000000000000000000001",
+        "0101010101010101010101010101010101010101010101010101010101010101",
+        "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+        "8000808080808080808080808080808080808080808080808080808080808080",
+        "8080808080808080808080808080808080808080808080808080808080808080",
+        "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+    ]
+    .map(|hex| hex::decode(hex).unwrap());
+    let iterated = nomt.iter().map(|(key, _)| key.to_vec()).collect::<Vec<_>>();
+    assert_eq!(iterated, expected.to_vec());
+}
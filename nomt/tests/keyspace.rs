@@ -0,0 +1,138 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyReadWrite, KeyspaceHandle, Node, ValueHandle, ValueHasher};
+use nomt_core::trie::TERMINATOR;
+
+fn expected_root(
+    keyspace: KeyspaceHandle,
+    ids: impl IntoIterator<Item = u64>,
+    value: &[u8],
+) -> Node {
+    let mut leaves = ids
+        .into_iter()
+        .map(|id| {
+            (
+                keyspace.key_path(account_path(id)),
+                Blake3Hasher::hash_value(value),
+            )
+        })
+        .collect::<Vec<_>>();
+    leaves.sort_by_key(|(key, _)| *key);
+    nomt_core::update::build_trie::<Blake3Hasher>(8, leaves, |_| {})
+}
+
+#[test]
+fn keyspaces_are_independent_and_committed_together() {
+    let dir = test_dir("keyspace_independent");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    let state = nomt.keyspace("state").unwrap();
+    let receipts = nomt.keyspace("receipts").unwrap();
+    assert_ne!(state.id(), receipts.id());
+    assert_eq!(nomt.keyspace("state").unwrap(), state);
+
+    let sync_seqn = nomt.sync_seqn();
+    let mut session = nomt.begin_session();
+    state
+        .session(&mut session)
+        .write_all((0..100).map(|id| (account_path(id), Some(ValueHandle::from(vec![1; 8])))));
+    receipts
+        .session(&mut session)
+        .write_all((0..10).map(|id| (account_path(id), Some(ValueHandle::from(vec![2; 8])))));
+    nomt.commit(session, Vec::new()).unwrap();
+    assert_eq!(nomt.sync_seqn(), sync_seqn + 1);
+
+    let mut session = nomt.begin_session();
+    assert_eq!(
+        state.session(&mut session).read(account_path(5)).unwrap(),
+        Some(vec![1; 8])
+    );
+    assert_eq!(
+        receipts
+            .session(&mut session)
+            .read(account_path(5))
+            .unwrap(),
+        Some(vec![2; 8])
+    );
+    assert_eq!(receipts.session(&mut session).iter().count(), 10);
+    drop(session);
+
+    let state_root = nomt.keyspace_root(state).unwrap();
+    assert_eq!(state_root, expected_root(state, 0..100, &[1; 8]));
+    assert_eq!(
+        nomt.keyspace_root(receipts).unwrap(),
+        expected_root(receipts, 0..10, &[2; 8])
+    );
+    assert_eq!(
+        nomt.export_subtree(&receipts.prefix()).root(),
+        nomt.keyspace_root(receipts).unwrap()
+    );
+
+    // Changing one keyspace leaves the root of the other untouched.
+    let session = nomt.begin_session();
+    nomt.commit(
+        session,
+        vec![(
+            receipts.key_path(account_path(100)),
+            KeyReadWrite::Write(Some(vec![2; 8])),
+        )],
+    )
+    .unwrap();
+    assert_eq!(nomt.keyspace_root(state).unwrap(), state_root);
+    assert_eq!(
+        nomt.keyspace_root(receipts).unwrap(),
+        expected_root(receipts, (0..10).chain([100]), &[2; 8])
+    );
+}
+
+#[test]
+fn small_keyspace_roots() {
+    let dir = test_dir("keyspace_small");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    let a = nomt.keyspace("a").unwrap();
+    let b = nomt.keyspace("b").unwrap();
+    assert_eq!(nomt.keyspace_root(a).unwrap(), TERMINATOR);
+
+    // A single key ends the trie in a leaf above the prefixes of both keyspaces.
+    let mut session = nomt.begin_session();
+    a.session(&mut session)
+        .write_all([(account_path(1), Some(ValueHandle::from(vec![1])))]);
+    nomt.commit(session, Vec::new()).unwrap();
+    assert_eq!(nomt.keyspace_root(a).unwrap(), nomt.root());
+    assert_eq!(nomt.keyspace_root(b).unwrap(), TERMINATOR);
+}
+
+#[test]
+fn keyspace_ids_survive_reopen() {
+    let dir = test_dir("keyspace_reopen");
+    let path = dir.path().join("db");
+    let t = Test::open(&path);
+    let nomt = t.nomt();
+    nomt.keyspace("first").unwrap();
+    let second = nomt.keyspace("second").unwrap();
+    let mut session = nomt.begin_session();
+    second
+        .session(&mut session)
+        .write_all([(account_path(1), Some(ValueHandle::from(vec![1])))]);
+    nomt.commit(session, Vec::new()).unwrap();
+    let root = nomt.keyspace_root(second).unwrap();
+
+    let t = t.reopen();
+    let nomt = t.nomt();
+    let third = nomt.keyspace("third").unwrap();
+    assert_eq!(nomt.keyspace("second").unwrap(), second);
+    assert_ne!(third, second);
+    assert_eq!(nomt.keyspace_root(second).unwrap(), root);
+}
+
+#[test]
+fn keyspace_records_are_reserved() {
+    let dir = test_dir("keyspace_reserved_aux");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    let mut session = nomt.begin_session();
+    session.write_aux(b"\0nomt:keyspace:x".to_vec(), Some(vec![1]));
+    assert!(nomt.commit(session, Vec::new()).is_err());
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use common::{test_dir, Test};
+use nomt::{LargeKeyBucket, LargeKeys, Nomt};
+
+fn commit(nomt: &Nomt<nomt::Blake3Hasher>, keys: &LargeKeys, writes: &[(&[u8], Option<&[u8]>)]) {
+    let session = nomt.begin_session();
+    let mut batch = keys.batch();
+    for (key, value) in writes {
+        batch.write(key, value.map(|v| v.to_vec())).unwrap();
+    }
+    let actuals = batch.into_actuals(&session).unwrap();
+    nomt.commit(session, actuals).unwrap();
+}
+
+#[test]
+fn large_keys_roundtrip() {
+    let dir = test_dir("large_keys_roundtrip");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    let keys = LargeKeys::new(1024);
+    let long_key = vec![0xab; 1024];
+
+    commit(
+        nomt,
+        &keys,
+        &[(b"short", Some(b"1")), (&long_key, Some(b"2"))],
+    );
+
+    let session = nomt.begin_session();
+    assert_eq!(keys.read(&session, b"short").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(keys.read(&session, &long_key).unwrap(), Some(b"2".to_vec()));
+    assert_eq!(keys.read(&session, b"missing").unwrap(), None);
+    assert!(keys.read(&session, &[0; 1025]).is_err());
+    drop(session);
+
+    commit(nomt, &keys, &[(b"short", None), (&long_key, None)]);
+    assert!(nomt.is_empty());
+}
+
+#[test]
+fn large_keys_collisions() {
+    let dir = test_dir("large_keys_collisions");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    // Every key collides.
+    let keys = LargeKeys::with_key_path(64, |_| [7; 32]);
+
+    commit(
+        nomt,
+        &keys,
+        &[(b"a", Some(b"1")), (b"b", Some(b"2")), (b"c", Some(b"3"))],
+    );
+    commit(nomt, &keys, &[(b"b", None), (b"c", Some(b"4"))]);
+
+    let session = nomt.begin_session();
+    assert_eq!(keys.read(&session, b"a").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(keys.read(&session, b"b").unwrap(), None);
+    assert_eq!(keys.read(&session, b"c").unwrap(), Some(b"4".to_vec()));
+
+    let bucket = LargeKeyBucket::decode(&session.read([7; 32]).unwrap().unwrap()).unwrap();
+    assert_eq!(
+        bucket.iter().collect::<Vec<_>>(),
+        vec![(&b"a"[..], &b"1"[..]), (&b"c"[..], &b"4"[..])]
+    );
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{KeyReadWrite, ValueHandle, ValueTooLarge};
+
+#[test]
+fn oversized_values_are_rejected() {
+    let dir = test_dir("max_value_size");
+    let t = Test::open_with(dir.path().join("db"), |o| o.max_value_size(1000));
+    let nomt = t.nomt();
+    assert_eq!(nomt.max_value_size(), 1000);
+
+    let session = nomt.begin_session();
+    let mut actuals = vec![
+        (account_path(0), KeyReadWrite::Write(Some(vec![1; 1000]))),
+        (
+            account_path(1),
+            KeyReadWrite::ReadThenWrite(None, Some(vec![1; 1001])),
+        ),
+    ];
+    actuals.sort_by_key(|(key, _)| *key);
+    let err = nomt.commit(session, actuals).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<ValueTooLarge>(),
+        Some(&ValueTooLarge {
+            max: 1000,
+            got: 1001
+        })
+    );
+    assert!(nomt.is_empty());
+
+    let mut session = nomt.begin_session();
+    session.write_all([(account_path(2), Some(ValueHandle::new(vec![2; 2000])))]);
+    let err = nomt.commit(session, Vec::new()).unwrap_err();
+    assert!(err.is::<ValueTooLarge>());
+
+    let session = nomt.begin_session();
+    let actuals = vec![(account_path(0), KeyReadWrite::Write(Some(vec![1; 1000])))];
+    nomt.commit(session, actuals).unwrap();
+    assert_eq!(nomt.read(account_path(0)).unwrap(), Some(vec![1; 1000]));
+}
@@ -0,0 +1,43 @@
+#![cfg(feature = "metrics")]
+
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{registered_metrics, KeyReadWrite};
+
+#[test]
+fn metrics_are_partitioned_by_instance() {
+    let dir = test_dir("metrics_label");
+    let chain_a = Test::open_with(dir.path().join("a"), |o| {
+        o.metrics(true);
+        o.metrics_label("chain-a");
+    });
+    let path_b = dir.path().join("b");
+    let label_b = path_b.to_str().unwrap();
+    let chain_b = Test::open_with(&path_b, |o| o.metrics(true));
+    assert_eq!(chain_a.nomt().metrics().label(), Some("chain-a"));
+    assert_eq!(chain_b.nomt().metrics().label(), Some(label_b));
+
+    let session = chain_a.nomt().begin_session();
+    let actuals = vec![(account_path(0), KeyReadWrite::Write(Some(vec![1])))];
+    chain_a.nomt().commit(session, actuals).unwrap();
+
+    let snapshots = registered_metrics()
+        .iter()
+        .map(|metrics| metrics.snapshot().unwrap())
+        .collect::<Vec<_>>();
+    let labels = snapshots
+        .iter()
+        .map(|snapshot| &snapshot.label[..])
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec!["chain-a", label_b]);
+    assert!(snapshots[0].logical_reads > 0);
+    assert_eq!(snapshots[1].logical_reads, 0);
+
+    drop(chain_a);
+    let labels = registered_metrics()
+        .iter()
+        .map(|metrics| metrics.label().unwrap().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec![label_b]);
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyReadWrite, Nomt, PageCacheEviction};
+
+fn commit_block(nomt: &Nomt<Blake3Hasher>, block: u64) {
+    // New accounts along with updates of accounts created by every earlier block.
+    let mut actuals = (block * 500..(block + 1) * 500)
+        .chain((0..block).map(|b| b * 500 + block))
+        .map(|id| {
+            let value = [id.to_le_bytes(), block.to_le_bytes()].concat();
+            (account_path(id), KeyReadWrite::Write(Some(value)))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+}
+
+#[test]
+fn small_cache_evicts_without_changing_roots() {
+    let dir = test_dir("page_cache");
+    let reference = Test::open_with(dir.path().join("reference"), |o| o.commit_concurrency(2));
+    let reference = reference.nomt();
+    for block in 0..20 {
+        commit_block(reference, block);
+    }
+
+    for (name, eviction) in [
+        ("clock", PageCacheEviction::Clock),
+        ("lru", PageCacheEviction::Lru),
+        ("2q", PageCacheEviction::TwoQueue),
+    ] {
+        let t = Test::open_with(dir.path().join(name), move |o| {
+            o.commit_concurrency(2);
+            o.page_cache_size_bytes(1024 * 1024);
+            o.page_cache_eviction(eviction);
+        });
+        let nomt = t.nomt();
+        for block in 0..20 {
+            commit_block(nomt, block);
+        }
+        assert_eq!(nomt.root(), reference.root(), "{name}");
+        for id in [0, 1, 499, 500, 9_999] {
+            assert_eq!(
+                nomt.read(account_path(id)).unwrap(),
+                reference.read(account_path(id)).unwrap(),
+                "{name}"
+            );
+        }
+
+        let stats = nomt.page_cache_stats();
+        assert_eq!(stats.capacity_bytes, 1024 * 1024, "{name}");
+        assert!(stats.evictions > 0, "{name}");
+        assert!(stats.hits > 0 && stats.misses > 0, "{name}");
+        assert!(stats.hit_rate().is_some(), "{name}");
+        // Only the pages of the last session may exceed the budget, besides some slack.
+        assert!(
+            stats.cached_bytes <= stats.capacity_bytes * 17 / 16 + stats.pinned_bytes,
+            "{name}: {stats:?}"
+        );
+    }
+
+    // The default budget holds all pages of this database.
+    assert_eq!(reference.page_cache_stats().evictions, 0);
+}
+
+#[test]
+fn pages_of_the_active_session_are_pinned() {
+    let dir = test_dir("page_cache_pinned");
+    let t = Test::open_with(dir.path().join("db"), |o| {
+        o.commit_concurrency(2);
+        o.page_cache_size_bytes(1024 * 1024);
+        o.page_cache_eviction(PageCacheEviction::Lru);
+    });
+    let nomt = t.nomt();
+    for block in 0..5 {
+        commit_block(nomt, block);
+    }
+
+    // A session warming up many keys keeps all the pages it loaded, even beyond the budget.
+    let session = nomt.begin_session();
+    for id in 0..2500 {
+        session.warm_up(account_path(id));
+    }
+    let mut actuals = (0..2500)
+        .map(|id| {
+            let value = nomt.read(account_path(id)).unwrap();
+            (account_path(id), KeyReadWrite::Read(value))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let root = nomt.root();
+    nomt.commit(session, actuals).unwrap();
+    assert_eq!(nomt.root(), root);
+    let stats = nomt.page_cache_stats();
+    assert!(stats.pinned_bytes > 0, "{stats:?}");
+
+    // Once another session begins, they can be evicted.
+    let session = nomt.begin_session();
+    drop(session);
+    commit_block(nomt, 5);
+    let stats = nomt.page_cache_stats();
+    assert!(
+        stats.cached_bytes <= stats.capacity_bytes * 17 / 16 + stats.pinned_bytes,
+        "{stats:?}"
+    );
+}
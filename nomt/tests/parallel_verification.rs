@@ -0,0 +1,100 @@
+mod common;
+
+use common::Test;
+use nomt::{proof, Blake3Hasher, Node, Witness, WitnessedOperations};
+
+fn path_updates(
+    witnessed: &WitnessedOperations,
+    verified: Vec<proof::VerifiedPathProof>,
+) -> Vec<proof::PathUpdate> {
+    let mut updates = Vec::new();
+    for (i, verified) in verified.into_iter().enumerate() {
+        let ops = witnessed
+            .writes
+            .iter()
+            .filter(|write| write.path_index == i)
+            .map(|write| (write.key, write.value))
+            .collect::<Vec<_>>();
+        if !ops.is_empty() {
+            updates.push(proof::PathUpdate {
+                inner: verified,
+                ops,
+            });
+        }
+    }
+    updates
+}
+
+fn commit_large_update(name: &str) -> (Node, Node, Witness, WitnessedOperations) {
+    let mut t = Test::new(name);
+    for id in 0..5000 {
+        common::set_balance(&mut t, id, 1000);
+    }
+    let (prev_root, _, _) = t.commit();
+
+    // Delete some keys, overwrite others and insert new ones.
+    for id in (0..5000).step_by(3) {
+        common::kill(&mut t, id);
+    }
+    for id in (1..5000).step_by(3) {
+        common::set_balance(&mut t, id, 2000);
+    }
+    for id in 5000..7000 {
+        common::set_balance(&mut t, id, 3000);
+    }
+    let (new_root, witness, witnessed) = t.commit();
+    (prev_root, new_root, witness, witnessed)
+}
+
+#[test]
+fn parallel_verification_matches_sequential() {
+    let (prev_root, new_root, witness, witnessed) = commit_large_update("parallel_verification");
+
+    let paths = witness
+        .path_proofs
+        .iter()
+        .map(|path| (path.path.path(), &path.inner))
+        .collect::<Vec<_>>();
+    let verified = proof::verify_parallel::<Blake3Hasher>(prev_root, &paths).unwrap();
+    assert_eq!(verified.len(), paths.len());
+
+    let updates = path_updates(&witnessed, verified);
+    assert_eq!(
+        proof::verify_update_parallel::<Blake3Hasher>(prev_root, &updates).unwrap(),
+        new_root,
+    );
+    assert_eq!(
+        proof::verify_update::<Blake3Hasher>(prev_root, &updates).unwrap(),
+        new_root,
+    );
+}
+
+#[test]
+fn parallel_verification_rejects_invalid_paths() {
+    let (prev_root, _, witness, _) = commit_large_update("parallel_verification_invalid");
+
+    let paths = witness
+        .path_proofs
+        .iter()
+        .map(|path| (path.path.path(), &path.inner))
+        .collect::<Vec<_>>();
+    assert!(matches!(
+        proof::verify_parallel::<Blake3Hasher>([1; 32], &paths),
+        Err(proof::InvalidPathProof {
+            error: proof::PathProofVerificationError::RootMismatch,
+            ..
+        })
+    ));
+
+    let mut tampered = witness.path_proofs[7].inner.clone();
+    tampered.siblings[0][1] ^= 1;
+    let mut paths = paths;
+    paths[7].1 = &tampered;
+    assert_eq!(
+        proof::verify_parallel::<Blake3Hasher>(prev_root, &paths).unwrap_err(),
+        proof::InvalidPathProof {
+            path_index: 7,
+            error: proof::PathProofVerificationError::RootMismatch,
+        }
+    );
+}
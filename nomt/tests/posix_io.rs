@@ -0,0 +1,26 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{IoBackend, KeyReadWrite};
+
+#[test]
+fn posix_backend_commits_and_reopens() {
+    let dir = test_dir("posix_io");
+    let path = dir.path().join("db");
+    let t = Test::open_with(&path, |o| {
+        o.io_backend(IoBackend::Posix);
+        o.io_workers(2);
+    });
+    let nomt = t.nomt();
+    for i in 0..100 {
+        let session = nomt.begin_session();
+        let actuals = vec![(account_path(i), KeyReadWrite::Write(Some(vec![i as u8; 8])))];
+        nomt.commit(session, actuals).unwrap();
+    }
+    let root = nomt.root();
+
+    let t = t.reopen();
+    let nomt = t.nomt();
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.read(account_path(42)).unwrap(), Some(vec![42; 8]));
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use bitvec::prelude::*;
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyReadWrite, LeafData, ValueHandle, ValueHasher};
+
+#[test]
+fn proofs_are_against_the_new_root() {
+    let dir = test_dir("post_state_proofs");
+    let t = Test::open_with(dir.path().join("db"), |o| o.commit_concurrency(2));
+    let nomt = t.nomt();
+    let mut session = nomt.begin_session();
+    session.write_all((0..1000).map(|id| (account_path(id), Some(ValueHandle::from(vec![1; 8])))));
+    nomt.commit(session, Vec::new()).unwrap();
+
+    // Overwrite some keys, delete others and prove them along with untouched and absent keys.
+    let mut actuals = vec![
+        (account_path(1), KeyReadWrite::Write(Some(vec![2; 8]))),
+        (account_path(2), KeyReadWrite::Write(None)),
+        (account_path(1000), KeyReadWrite::Write(Some(vec![3; 8]))),
+    ];
+    actuals.sort_by_key(|(key, _)| *key);
+    let keys = [1, 2, 3, 1000, 2000].map(account_path).to_vec();
+    let session = nomt.begin_session();
+    let (root, proofs) = session
+        .finish_with_proofs(nomt, actuals, keys.clone())
+        .unwrap();
+    assert_eq!(root, nomt.root());
+    assert_eq!(proofs.len(), keys.len());
+
+    let expected = [
+        Some(vec![2; 8]),
+        None,
+        Some(vec![1; 8]),
+        Some(vec![3; 8]),
+        None,
+    ];
+    for ((key, proof), value) in keys.iter().zip(&proofs).zip(expected) {
+        let verified = proof
+            .verify::<Blake3Hasher>(key.view_bits::<Msb0>(), root)
+            .unwrap();
+        match value {
+            Some(value) => assert!(verified
+                .confirm_value(&LeafData {
+                    key_path: *key,
+                    value_hash: Blake3Hasher::hash_value(&value),
+                })
+                .unwrap()),
+            None => assert!(verified.confirm_nonexistence(key).unwrap()),
+        }
+    }
+}
+
+#[test]
+fn proofs_of_empty_trie() {
+    let dir = test_dir("post_state_proofs_empty");
+    let t = Test::open_with(dir.path().join("db"), |o| o.commit_concurrency(2));
+    let nomt = t.nomt();
+    let session = nomt.begin_session();
+    let (root, proofs) = session
+        .finish_with_proofs(nomt, Vec::new(), vec![account_path(1)])
+        .unwrap();
+    let verified = proofs[0]
+        .verify::<Blake3Hasher>(account_path(1).view_bits::<Msb0>(), root)
+        .unwrap();
+    assert!(verified.confirm_nonexistence(&account_path(1)).unwrap());
+}
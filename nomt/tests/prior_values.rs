@@ -0,0 +1,38 @@
+mod common;
+
+use common::Test;
+use nomt::{proof::PriorValuesError, Blake3Hasher, ValueHasher};
+
+#[test]
+fn prior_values_from_witness() {
+    let mut t = Test::new("prior_values");
+    for id in 0..100 {
+        common::set_balance(&mut t, id, 1000);
+    }
+    let (prev_root, _, _) = t.commit();
+
+    for id in 0..50 {
+        t.read_id(id);
+    }
+    for id in 50..150 {
+        common::set_balance(&mut t, id, 2000);
+    }
+    let (_, witness, witnessed) = t.commit();
+
+    let values = witness
+        .prior_values::<Blake3Hasher>(&witnessed, prev_root)
+        .unwrap();
+    assert_eq!(values.len(), 150);
+    let balance = Blake3Hasher::hash_value(&1000u64.to_le_bytes());
+    for id in 0..100 {
+        assert_eq!(values[&common::account_path(id)], Some(balance));
+    }
+    for id in 100..150 {
+        assert_eq!(values[&common::account_path(id)], None);
+    }
+
+    assert!(matches!(
+        witness.prior_values::<Blake3Hasher>(&witnessed, [1; 32]),
+        Err(PriorValuesError::InvalidPath { .. })
+    ));
+}
@@ -0,0 +1,79 @@
+#![cfg(feature = "metrics")]
+
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{encode_prometheus, KeyReadWrite};
+
+#[test]
+fn metrics_are_encoded_for_prometheus() {
+    let dir = test_dir("prometheus");
+    let t = Test::open_with(dir.path().join("db"), |o| {
+        o.metrics(true);
+        o.metrics_label("chain \"a\"");
+    });
+    let nomt = t.nomt();
+
+    for i in 0..3 {
+        let session = nomt.begin_session();
+        let actuals = vec![(account_path(i), KeyReadWrite::Write(Some(vec![1])))];
+        nomt.commit(session, actuals).unwrap();
+    }
+
+    let snapshot = nomt.metrics().snapshot().unwrap();
+    assert!(snapshot.merkle_update_mean_ns.is_some());
+    assert!(snapshot.sync_mean_ns.is_some());
+    assert!(snapshot.wal_sync_mean_ns.is_some());
+    assert!(snapshot.page_pool_reserved_bytes > 0);
+
+    let text = encode_prometheus(&[nomt.metrics()]);
+    assert!(text.contains("# TYPE nomt_logical_reads_total counter\n"));
+    assert!(text.contains("# TYPE nomt_sync_seconds summary\n"));
+    assert!(text.contains("nomt_sync_seconds_count{db=\"chain \\\"a\\\"\"} 3\n"));
+    for line in text.lines().filter(|line| !line.starts_with('#')) {
+        let (_, value) = line.rsplit_once(' ').unwrap();
+        assert!(value.parse::<f64>().is_ok(), "{line}");
+    }
+}
+
+#[test]
+fn btree_and_page_pool_metrics_are_collected() {
+    let dir = test_dir("prometheus_btree");
+    let t = Test::open_with(dir.path().join("db"), |o| o.metrics(true));
+    let nomt = t.nomt();
+
+    // Enough values to fill many leaves, which are split off the single initial one.
+    let session = nomt.begin_session();
+    let mut actuals = (0..1000)
+        .map(|i| (account_path(i), KeyReadWrite::Write(Some(vec![1; 100]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(path, _)| *path);
+    nomt.commit(session, actuals).unwrap();
+
+    let snapshot = nomt.metrics().snapshot().unwrap();
+    assert!(snapshot.btree_splits > 0);
+    assert_eq!(snapshot.btree_merges, 0);
+    assert!(snapshot.page_pool_allocations > 0);
+    assert!(snapshot.page_pool_free_pages > 0);
+
+    // Deleting all but a few values leaves the leaves underfull, so they are merged.
+    let session = nomt.begin_session();
+    let mut actuals = (10..1000)
+        .map(|i| (account_path(i), KeyReadWrite::Write(None)))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(path, _)| *path);
+    nomt.commit(session, actuals).unwrap();
+    assert!(nomt.metrics().snapshot().unwrap().btree_merges > 0);
+
+    let text = encode_prometheus(&[nomt.metrics()]);
+    assert!(text.contains("# TYPE nomt_btree_splits_total counter\n"));
+    assert!(text.contains("# TYPE nomt_page_pool_allocations_total counter\n"));
+}
+
+#[test]
+fn inactive_metrics_are_not_encoded() {
+    let dir = test_dir("prometheus_inactive");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    assert_eq!(encode_prometheus(&[nomt.metrics()]), "");
+}
@@ -0,0 +1,35 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::KeyReadWrite;
+
+#[test]
+fn read_amplification_per_interval() {
+    let dir = test_dir("read_amplification");
+    let path = dir.path().join("db");
+    let t = Test::open_with(&path, |o| o.metrics(true));
+    let nomt = t.nomt();
+    let mut actuals = (0..1000)
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![1; 10]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+    assert_eq!(nomt.read_amplification().logical_reads, 1000);
+
+    // Reads against a cold cache must hit the disk.
+    let t = t.reopen();
+    let nomt = t.nomt();
+    let session = nomt.begin_session();
+    for id in 0..100 {
+        assert_eq!(session.read(account_path(id)).unwrap(), Some(vec![1; 10]));
+    }
+    let amplification = nomt.read_amplification();
+    assert_eq!(amplification.logical_reads, 100);
+    assert!(amplification.physical_page_reads > 0);
+    assert!(amplification.factor().unwrap() > 0.0);
+
+    let amplification = nomt.read_amplification();
+    assert_eq!(amplification.logical_reads, 0);
+    assert_eq!(amplification.factor(), None);
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::KeyReadWrite;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::Thread,
+    time::Duration,
+};
+
+/// Wakes the polling thread and records that its future may make progress.
+struct Flag {
+    woken: AtomicBool,
+    thread: Thread,
+}
+
+impl Wake for Flag {
+    fn wake(self: Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+        self.thread.unpark();
+    }
+}
+
+/// Poll all futures on the current thread until they are ready, returning their outputs and how
+/// many were pending when first polled.
+fn join_all<F: Future + Unpin>(mut futures: Vec<F>) -> (Vec<F::Output>, usize) {
+    let flags = futures
+        .iter()
+        .map(|_| {
+            Arc::new(Flag {
+                woken: AtomicBool::new(true),
+                thread: std::thread::current(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut outputs = futures.iter().map(|_| None).collect::<Vec<_>>();
+    let mut first_pending = 0;
+    let mut polled = vec![false; futures.len()];
+    while outputs.iter().any(Option::is_none) {
+        for (i, future) in futures.iter_mut().enumerate() {
+            if outputs[i].is_some() || !flags[i].woken.swap(false, Ordering::SeqCst) {
+                continue;
+            }
+            let waker = Waker::from(flags[i].clone());
+            match Pin::new(future).poll(&mut Context::from_waker(&waker)) {
+                Poll::Ready(output) => outputs[i] = Some(output),
+                Poll::Pending if !polled[i] => first_pending += 1,
+                Poll::Pending => {}
+            }
+            polled[i] = true;
+        }
+        std::thread::park_timeout(Duration::from_millis(100));
+    }
+    (
+        outputs.into_iter().map(Option::unwrap).collect(),
+        first_pending,
+    )
+}
+
+fn value(id: u64) -> Vec<u8> {
+    // every tenth value is stored in overflow pages.
+    let len = if id.is_multiple_of(10) { 20_000 } else { 32 };
+    id.to_le_bytes().repeat(len / 8)
+}
+
+#[test]
+fn concurrent_async_reads_complete_on_one_thread() {
+    let dir = test_dir("read_async");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+
+    let mut actuals = (0..2000)
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(value(id)))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+    nomt.drop_caches(false).unwrap();
+
+    let session = nomt.begin_session();
+    let futures = (0..2500)
+        .map(|id| Box::pin(session.read_async(account_path(id))))
+        .collect::<Vec<_>>();
+    let (values, first_pending) = join_all(futures);
+    assert!(first_pending > 0);
+    for (id, read) in values.into_iter().enumerate() {
+        let expected = (id < 2000).then(|| value(id as u64));
+        assert_eq!(read.unwrap(), expected);
+    }
+
+    // The leaves have been cached, so reads complete right away.
+    let futures = (0..2000)
+        .map(|id| Box::pin(session.read_async(account_path(id))))
+        .collect::<Vec<_>>();
+    let (values, first_pending) = join_all(futures);
+    assert_eq!(first_pending, 200);
+    assert_eq!(values[1].as_ref().unwrap(), &Some(value(1)));
+}
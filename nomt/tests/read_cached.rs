@@ -0,0 +1,24 @@
+mod common;
+
+use common::Test;
+use nomt::CacheResult;
+
+#[test]
+fn read_cached_never_performs_io() {
+    let mut t = Test::new("read_cached");
+
+    let large = vec![7; 4096 * 4];
+    common::set_balance(&mut t, 0, 1000);
+    t.write_id(1, Some(large.clone()));
+    t.commit();
+
+    assert_eq!(
+        t.read_cached_id(0),
+        CacheResult::Hit(Some(1000u64.to_le_bytes().to_vec()))
+    );
+    assert_eq!(t.read_cached_id(2), CacheResult::Hit(None));
+
+    // Overflow values always live on disk.
+    assert_eq!(t.read_cached_id(1), CacheResult::Miss);
+    assert_eq!(t.read_id(1), Some(large));
+}
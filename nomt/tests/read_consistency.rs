@@ -0,0 +1,99 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{KeyReadWrite, Nomt, ReadConsistency, SessionParams};
+use std::time::Duration;
+
+fn last_synced() -> SessionParams {
+    let mut params = SessionParams::default();
+    params.read_consistency(ReadConsistency::LastSynced);
+    params
+}
+
+fn write(nomt: &Nomt<nomt::Blake3Hasher>, writes: Vec<(u64, Option<Vec<u8>>)>) {
+    let session = nomt.begin_session();
+    let mut actuals: Vec<_> = writes
+        .into_iter()
+        .map(|(id, value)| (account_path(id), KeyReadWrite::Write(value)))
+        .collect();
+    actuals.sort_by_key(|(path, _)| *path);
+    nomt.commit(session, actuals).unwrap();
+}
+
+#[test]
+fn last_synced_reads_skip_unsynced_commits() {
+    let dir = test_dir("read_consistency_last_synced");
+    let t = Test::open_with(dir.path().join("db"), |o| {
+        o.commit_coalescing(10, Duration::from_secs(3600))
+    });
+    let nomt = t.nomt();
+    write(nomt, vec![(0, Some(vec![1; 8])), (1, Some(vec![1; 8]))]);
+    nomt.flush().unwrap();
+
+    // Held back by commit coalescing, so not synced yet.
+    write(
+        nomt,
+        vec![(0, Some(vec![2; 8])), (1, None), (2, Some(vec![2; 8]))],
+    );
+
+    let session = nomt.begin_session_with_params(last_synced());
+    assert_eq!(session.read(account_path(0)).unwrap(), Some(vec![1; 8]));
+    assert_eq!(session.read(account_path(1)).unwrap(), Some(vec![1; 8]));
+    assert_eq!(session.read(account_path(2)).unwrap(), None);
+    drop(session);
+
+    let session = nomt.begin_session();
+    assert_eq!(session.read(account_path(0)).unwrap(), Some(vec![2; 8]));
+    assert_eq!(session.read(account_path(1)).unwrap(), None);
+    assert_eq!(session.read(account_path(2)).unwrap(), Some(vec![2; 8]));
+    drop(session);
+
+    nomt.flush().unwrap();
+    let session = nomt.begin_session_with_params(last_synced());
+    assert_eq!(session.read(account_path(0)).unwrap(), Some(vec![2; 8]));
+    assert_eq!(session.read(account_path(1)).unwrap(), None);
+    assert_eq!(session.read(account_path(2)).unwrap(), Some(vec![2; 8]));
+}
+
+#[test]
+fn last_synced_sessions_are_concurrent() {
+    let dir = test_dir("read_consistency_concurrent");
+    let t = Test::open_with(dir.path().join("db"), |o| {
+        o.commit_coalescing(10, Duration::from_secs(3600))
+    });
+    let nomt = t.nomt();
+    write(nomt, vec![(0, Some(vec![1; 8]))]);
+    nomt.flush().unwrap();
+
+    let first = nomt.begin_session_with_params(last_synced());
+    let second = nomt.begin_session_with_params(last_synced());
+
+    // A session which is going to be committed may be active alongside them.
+    write(nomt, vec![(0, Some(vec![2; 8]))]);
+    assert_eq!(first.read(account_path(0)).unwrap(), Some(vec![1; 8]));
+    assert_eq!(second.read(account_path(0)).unwrap(), Some(vec![1; 8]));
+    drop(first);
+
+    write(nomt, vec![(0, Some(vec![3; 8]))]);
+    assert_eq!(second.read(account_path(0)).unwrap(), Some(vec![1; 8]));
+    assert_eq!(nomt.read(account_path(0)).unwrap(), Some(vec![3; 8]));
+}
+
+#[test]
+fn last_synced_sessions_cannot_be_committed() {
+    let dir = test_dir("read_consistency_commit");
+    let t = Test::open_with(dir.path().join("db"), |o| {
+        o.commit_coalescing(10, Duration::from_secs(3600))
+    });
+    let nomt = t.nomt();
+    let root = nomt.root();
+
+    let session = nomt.begin_session_with_params(last_synced());
+    assert!(session.contains_batch(&[account_path(0)]).is_err());
+    let actuals = vec![(account_path(0), KeyReadWrite::Write(Some(vec![1; 8])))];
+    assert!(nomt.commit(session, actuals).is_err());
+    assert_eq!(nomt.root(), root);
+
+    // The failed commit must not have taken up the session slot.
+    write(nomt, vec![(0, Some(vec![1; 8]))]);
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use std::io::Read;
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyReadWrite, Node, Nomt, Options};
+
+fn read_only(read_only: bool) -> impl Fn(&mut Options) {
+    move |o| {
+        o.bitbox_seed([0; 16]);
+        o.read_only(read_only);
+    }
+}
+
+fn write(nomt: &Nomt<Blake3Hasher>, ids: std::ops::Range<u64>, value: u8) -> Node {
+    let mut actuals = ids
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![value; 8]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(path, _)| *path);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap()
+}
+
+#[test]
+fn replica_follows_writer() {
+    let dir = test_dir("read_only_follows");
+    let path = dir.path().join("db");
+
+    let writer = Test::try_open_with(&path, read_only(false)).unwrap();
+    let root = write(writer.nomt(), 0..1000, 1);
+
+    // The replica doesn't contend for the directory lock held by the writer.
+    let mut replica = Test::try_open_with(&path, read_only(true)).unwrap();
+    assert!(replica.nomt().is_read_only());
+    assert_eq!(replica.nomt().root(), root);
+    assert_eq!(replica.nomt().sync_seqn(), writer.nomt().sync_seqn());
+    assert!(!replica.nomt_mut().refresh().unwrap());
+
+    let root = write(writer.nomt(), 500..1500, 2);
+    // The replica keeps the root as of its opening until refreshed, but reads fail as the writer
+    // may have overwritten the pages of that state.
+    assert_ne!(replica.nomt().root(), root);
+    assert!(replica.nomt().read(account_path(1200)).is_err());
+    assert!(replica.nomt().check_snapshot().is_err());
+    {
+        let session = replica.nomt().begin_session();
+        assert!(session.read(account_path(0)).is_err());
+        assert!(session.contains_batch(&[account_path(0)]).is_err());
+    }
+
+    assert!(replica.nomt_mut().refresh().unwrap());
+    assert_eq!(replica.nomt().root(), root);
+    assert_eq!(replica.nomt().sync_seqn(), writer.nomt().sync_seqn());
+    let session = replica.nomt().begin_session();
+    assert_eq!(session.read(account_path(0)).unwrap(), Some(vec![1; 8]));
+    assert_eq!(session.read(account_path(1200)).unwrap(), Some(vec![2; 8]));
+    replica.nomt().check_snapshot().unwrap();
+}
+
+#[test]
+fn replica_streaming_read_fails_after_writer_sync() {
+    let dir = test_dir("read_only_streaming");
+    let path = dir.path().join("db");
+
+    let writer = Test::try_open_with(&path, read_only(false)).unwrap();
+    let session = writer.nomt().begin_session();
+    let actuals = vec![(account_path(0), KeyReadWrite::Write(Some(vec![1; 100_000])))];
+    writer.nomt().commit(session, actuals).unwrap();
+
+    let replica = Test::try_open_with(&path, read_only(true)).unwrap();
+    let session = replica.nomt().begin_session();
+    let mut reader = session.read_streaming(account_path(0)).unwrap().unwrap();
+    let mut buf = vec![0; 4096];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, vec![1; 4096]);
+
+    write(writer.nomt(), 1..2, 2);
+    assert!(reader.read_to_end(&mut Vec::new()).is_err());
+}
+
+#[test]
+fn replica_refuses_writes() {
+    let dir = test_dir("read_only_refuses_writes");
+    let path = dir.path().join("db");
+
+    let writer = Test::try_open_with(&path, read_only(false)).unwrap();
+    let root = write(writer.nomt(), 0..100, 1);
+    drop(writer);
+
+    let replica = Test::try_open_with(&path, read_only(true)).unwrap();
+    let session = replica.nomt().begin_session();
+    let actuals = vec![(account_path(0), KeyReadWrite::Write(None))];
+    assert!(replica.nomt().commit(session, actuals).is_err());
+    assert!(replica.nomt().resize_hash_table(20_000).is_err());
+    assert!(replica.nomt().prune_to(0).is_err());
+    assert_eq!(replica.nomt().root(), root);
+    drop(replica);
+
+    // The writer can open the database while the replica is open.
+    let replica = Test::try_open_with(&path, read_only(true)).unwrap();
+    let writer = Test::try_open_with(&path, read_only(false)).unwrap();
+    assert_eq!(writer.nomt().root(), replica.nomt().root());
+}
+
+#[test]
+fn replica_requires_existing_database() {
+    let dir = test_dir("read_only_missing");
+    let path = dir.path().join("db");
+
+    assert!(Test::try_open_with(&path, read_only(true)).is_err());
+    assert!(!path.exists());
+
+    let mut writer = Test::try_open_with(&path, read_only(false)).unwrap();
+    assert!(writer.nomt_mut().refresh().is_err());
+}
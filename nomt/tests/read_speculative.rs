@@ -0,0 +1,41 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{KeyReadWrite, ValueHandle};
+
+#[test]
+fn speculative_reads_are_invalidated_by_later_writes() {
+    let dir = test_dir("read_speculative");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+
+    let mut actuals = (0..3)
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![1]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+
+    let mut session = nomt.begin_session();
+    let (value, stale) = session.read_speculative(account_path(0)).unwrap();
+    assert_eq!(value, Some(vec![1]));
+    session.read_speculative(account_path(1)).unwrap();
+    session.write_all([
+        (account_path(0), Some(ValueHandle::new(vec![2]))),
+        (account_path(2), None),
+    ]);
+
+    // Reads reflect the writes made so far and are only invalidated by later ones.
+    let (value, _) = session.read_speculative(account_path(0)).unwrap();
+    assert_eq!(value, Some(vec![2]));
+    let (value, deleted) = session.read_speculative(account_path(2)).unwrap();
+    assert_eq!(value, None);
+    assert_eq!(session.validate_tickets(), vec![stale]);
+
+    session.write_all([(account_path(2), Some(ValueHandle::new(vec![3])))]);
+    assert_eq!(session.validate_tickets(), vec![stale, deleted]);
+    assert_eq!(stale.key(), account_path(0));
+
+    nomt.commit(session, Vec::new()).unwrap();
+    assert_eq!(nomt.read(account_path(2)).unwrap(), Some(vec![3]));
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::{test_dir, Test};
+use nomt::{Blake3Hasher, Durability, KeyReadWrite, Nomt, Options, RuntimeConfig};
+
+fn commit_concurrency(commit_concurrency: usize) -> impl Fn(&mut Options) {
+    move |o| {
+        o.commit_concurrency(commit_concurrency);
+        o.io_workers(2);
+        o.io_max_in_flight(256);
+    }
+}
+
+fn commit_block(nomt: &Nomt<Blake3Hasher>, block: u8) {
+    let session = nomt.begin_session();
+    let actuals = (0..=255u8)
+        .map(|j| ([j; 32], KeyReadWrite::Write(Some(vec![block, j]))))
+        .collect();
+    nomt.commit(session, actuals).unwrap();
+}
+
+#[test]
+fn commits_across_reconfigurations() {
+    let dir = test_dir("reconfigure");
+    let t = Test::open_with(dir.path().join("db"), commit_concurrency(4));
+    let nomt = t.nomt();
+    let reference = Test::open_with(dir.path().join("reference"), commit_concurrency(1));
+    let reference = reference.nomt();
+
+    // Catching up: full concurrency and deep queues.
+    let mut catch_up = RuntimeConfig::new();
+    catch_up.commit_concurrency(4);
+    catch_up.io_submit_batch(256);
+    catch_up.io_max_in_flight(256);
+    catch_up.io_prioritize_reads(false);
+    catch_up.durability(Durability::PowerLossProtected);
+
+    // Following the chain: fewer workers, shallow queues and reads ahead of the writeback.
+    let mut live = RuntimeConfig::new();
+    live.commit_concurrency(1);
+    live.io_submit_batch(1);
+    live.io_max_in_flight(4);
+    live.io_prioritize_reads(true);
+    live.durability(Durability::Fsync);
+
+    for block in 0..12u8 {
+        let config = if block % 4 < 2 { &catch_up } else { &live };
+        nomt.reconfigure(config.clone()).unwrap();
+        commit_block(nomt, block);
+        commit_block(reference, block);
+        assert_eq!(nomt.root(), reference.root());
+    }
+
+    // Values beyond those the database was opened with are capped.
+    let mut beyond = RuntimeConfig::new();
+    beyond.commit_concurrency(64);
+    beyond.io_max_in_flight(100_000);
+    nomt.reconfigure(beyond).unwrap();
+    commit_block(nomt, 12);
+    commit_block(reference, 12);
+    assert_eq!(nomt.root(), reference.root());
+
+    let session = nomt.begin_session();
+    assert_eq!(session.read([7; 32]).unwrap(), Some(vec![12, 7]));
+}
+
+#[test]
+fn zero_commit_concurrency_is_rejected() {
+    let dir = test_dir("reconfigure_zero");
+    let t = Test::open_with(dir.path().join("db"), commit_concurrency(2));
+    let nomt = t.nomt();
+    let mut config = RuntimeConfig::new();
+    config.commit_concurrency(0);
+    assert!(nomt.reconfigure(config).is_err());
+
+    // Nothing changed, the database is still usable.
+    commit_block(nomt, 0);
+    nomt.reconfigure(RuntimeConfig::new()).unwrap();
+    commit_block(nomt, 1);
+}
+
+#[test]
+#[should_panic(expected = "reconfigure cannot run while a session is active")]
+fn reconfigure_during_session_panics() {
+    let dir = test_dir("reconfigure_session");
+    let t = Test::open_with(dir.path().join("db"), commit_concurrency(1));
+    let nomt = t.nomt();
+    let _session = nomt.begin_session();
+    let _ = nomt.reconfigure(RuntimeConfig::new());
+}
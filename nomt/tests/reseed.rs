@@ -0,0 +1,75 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{KeyReadWrite, Options};
+
+fn seed(seed: u8, reseed: bool) -> impl Fn(&mut Options) {
+    move |o| {
+        o.bitbox_seed([seed; 16]);
+        o.reseed_hashtable(reseed);
+        o.hashtable_buckets(10_000);
+    }
+}
+
+#[test]
+fn reseed_preserves_state() {
+    let dir = test_dir("reseed");
+    let path = dir.path().join("db");
+
+    let t = Test::open_with(&path, seed(1, false));
+    let nomt = t.nomt();
+    let mut actuals = (0..2000)
+        .map(|id| {
+            (
+                account_path(id),
+                KeyReadWrite::Write(Some(vec![id as u8; 8])),
+            )
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(path, _)| *path);
+    let session = nomt.begin_session();
+    let root = nomt.commit(session, actuals).unwrap();
+    let occupied_buckets = nomt.hash_table_stats().occupied_buckets;
+
+    // The seed of an existing database is kept unless reseeding is requested.
+    let t = t.reopen_with(seed(2, false));
+    assert_eq!(t.nomt().root(), root);
+
+    let t = t.reopen_with(seed(2, true));
+    let nomt = t.nomt();
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.hash_table_stats().occupied_buckets, occupied_buckets);
+    assert!(!path.join("ht.reseed").exists());
+
+    // The pages are found in their new buckets when updating the trie.
+    let session = nomt.begin_session();
+    let actuals = vec![(account_path(0), KeyReadWrite::Write(None))];
+    let new_root = nomt.commit(session, actuals).unwrap();
+    assert_ne!(new_root, root);
+
+    // The new seed has been persisted.
+    let t = t.reopen_with(seed(3, false));
+    let nomt = t.nomt();
+    assert_eq!(nomt.root(), new_root);
+    for id in 1..2000 {
+        assert_eq!(
+            nomt.read(account_path(id)).unwrap(),
+            Some(vec![id as u8; 8])
+        );
+    }
+
+    // The same update against a database which was never reseeded yields the same root.
+    let t = Test::open_with(dir.path().join("reference"), seed(2, false));
+    let other = t.nomt();
+    let mut actuals = (1..2000)
+        .map(|id| {
+            (
+                account_path(id),
+                KeyReadWrite::Write(Some(vec![id as u8; 8])),
+            )
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(path, _)| *path);
+    let session = other.begin_session();
+    assert_eq!(other.commit(session, actuals).unwrap(), new_root);
+}
@@ -460,3 +460,115 @@ fn test_rollback_read_then_write() {
     nomt.rollback(1).unwrap();
     assert_eq!(nomt.read(key).unwrap(), None);
 }
+
+/// Commit `n` sessions writing a distinct value to the same key, returning the roots before each
+/// of them.
+fn commit_versions(nomt: &Nomt<Blake3Hasher>, n: u8) -> Vec<[u8; 32]> {
+    let key = KeyPath::from([0xAA; 32]);
+    let mut roots = Vec::new();
+    for version in 0..n {
+        roots.push(nomt.root());
+        let session = nomt.begin_session();
+        nomt.commit(
+            session,
+            vec![(key, KeyReadWrite::Write(Some(vec![version])))],
+        )
+        .unwrap();
+    }
+    roots
+}
+
+#[test]
+fn test_rollback_prune_to() {
+    let nomt = setup_nomt(
+        "rollback_prune_to",
+        /* rollback_enabled */ true,
+        /* commit_concurrency */ 1,
+        /* should_clean_up */ true,
+    );
+    let roots = commit_versions(&nomt, 10);
+
+    nomt.prune_to(3).unwrap();
+    assert!(nomt.rollback(4).is_err());
+    drop(nomt);
+
+    // The pruned log is persisted.
+    let nomt = setup_nomt(
+        "rollback_prune_to",
+        /* rollback_enabled */ true,
+        /* commit_concurrency */ 1,
+        /* should_clean_up */ false,
+    );
+    assert!(nomt.rollback(4).is_err());
+    nomt.rollback(3).unwrap();
+    assert_eq!(nomt.root(), roots[7]);
+
+    // Pruning everything empties the log, which then grows again.
+    let roots = commit_versions(&nomt, 2);
+    nomt.prune_to(0).unwrap();
+    assert!(nomt.rollback(1).is_err());
+    let roots_after = commit_versions(&nomt, 2);
+    nomt.rollback(2).unwrap();
+    assert_eq!(nomt.root(), roots_after[0]);
+    assert_ne!(nomt.root(), roots[0]);
+}
+
+#[test]
+fn test_rollback_max_log_len() {
+    let open = || {
+        let mut o = Options::new();
+        o.path("test/rollback_max_log_len");
+        o.bitbox_seed([0; 16]);
+        o.rollback(true);
+        o.max_rollback_log_len(3);
+        Nomt::<Blake3Hasher>::open(o).unwrap()
+    };
+    let _ = std::fs::remove_dir_all("test/rollback_max_log_len");
+    let nomt = open();
+    let roots = commit_versions(&nomt, 6);
+    drop(nomt);
+
+    let nomt = open();
+    assert!(nomt.rollback(4).is_err());
+    nomt.rollback(3).unwrap();
+    assert_eq!(nomt.root(), roots[3]);
+}
+
+#[test]
+fn test_rollback_to_root() {
+    let nomt = setup_nomt(
+        "rollback_to_root",
+        /* rollback_enabled */ true,
+        /* commit_concurrency */ 1,
+        /* should_clean_up */ true,
+    );
+    let roots = commit_versions(&nomt, 6);
+    let mut retained = roots.clone();
+    retained.reverse();
+    assert_eq!(nomt.retained_roots(), retained);
+
+    // Unknown roots are rejected without touching the database.
+    let root = nomt.root();
+    assert!(nomt.rollback_to_root([0x11; 32]).is_err());
+    assert_eq!(nomt.root(), root);
+    nomt.rollback_to_root(root).unwrap();
+    assert_eq!(nomt.root(), root);
+
+    nomt.rollback_to_root(roots[2]).unwrap();
+    assert_eq!(nomt.root(), roots[2]);
+    assert_eq!(nomt.read([0xAA; 32]).unwrap(), Some(vec![1]));
+    assert_eq!(nomt.retained_roots(), &retained[4..]);
+    drop(nomt);
+
+    // The roots are recorded in the persisted log.
+    let nomt = setup_nomt(
+        "rollback_to_root",
+        /* rollback_enabled */ true,
+        /* commit_concurrency */ 1,
+        /* should_clean_up */ false,
+    );
+    assert_eq!(nomt.root(), roots[2]);
+    nomt.rollback_to_root(roots[0]).unwrap();
+    assert_eq!(nomt.root(), roots[0]);
+    assert!(nomt.retained_roots().is_empty());
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "root-anchor-log")]
+
+mod common;
+
+use common::{test_dir, Test};
+use nomt::{KeyReadWrite, RootAnchorLog};
+use std::{sync::Arc, time::Duration};
+
+#[test]
+fn roots_are_anchored_after_commit() {
+    let dir = test_dir("root_anchor");
+    let path = dir.path().join("db");
+    let log_path = dir.path().join("root_anchor.log");
+    let key = [42; 32];
+
+    let log = Arc::new(RootAnchorLog::open(&log_path, key).unwrap());
+    let verifying_key = log.verifying_key();
+    let t = Test::open_with(path, move |o| o.root_anchor(log.clone()));
+    let nomt = t.nomt();
+
+    let mut roots = Vec::new();
+    for i in 0..3u8 {
+        let session = nomt.begin_session();
+        let root = nomt
+            .commit(session, vec![([i; 32], KeyReadWrite::Write(Some(vec![i])))])
+            .unwrap();
+        roots.push(root);
+    }
+    drop(t);
+
+    assert_eq!(
+        RootAnchorLog::verify(&log_path, verifying_key).unwrap(),
+        vec![(1, roots[0]), (2, roots[1]), (3, roots[2])],
+    );
+}
+
+#[test]
+fn only_the_last_coalesced_root_is_anchored() {
+    let dir = test_dir("root_anchor_coalesced");
+    let path = dir.path().join("db");
+    let log_path = dir.path().join("root_anchor.log");
+    let key = [42; 32];
+
+    let log = Arc::new(RootAnchorLog::open(&log_path, key).unwrap());
+    let verifying_key = log.verifying_key();
+    let t = Test::open_with(path, move |o| {
+        o.root_anchor(log.clone());
+        o.commit_coalescing(2, Duration::from_secs(3600));
+    });
+    let nomt = t.nomt();
+
+    let mut roots = Vec::new();
+    for i in 0..4u8 {
+        let session = nomt.begin_session();
+        let root = nomt
+            .commit(session, vec![([i; 32], KeyReadWrite::Write(Some(vec![i])))])
+            .unwrap();
+        roots.push(root);
+    }
+    drop(t);
+
+    assert_eq!(
+        RootAnchorLog::verify(&log_path, verifying_key).unwrap(),
+        vec![(1, roots[1]), (2, roots[3])],
+    );
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use common::{test_dir, Test};
+use nomt::{KeyReadWrite, SessionParams};
+
+#[test]
+fn witnessless_session_cannot_be_proven() {
+    let dir = test_dir("session_params");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+
+    let write = |i: u8| vec![([i; 32], KeyReadWrite::Write(Some(vec![i])))];
+
+    let mut params = SessionParams::default();
+    params.record_witness(false);
+    let session = nomt.begin_session_with_params(params);
+    assert!(nomt.commit_and_prove(session, write(1)).is_err());
+    assert_eq!(nomt.read([1; 32]).unwrap(), None);
+
+    let mut params = SessionParams::default();
+    params.record_witness(false);
+    let session = nomt.begin_session_with_params(params);
+    let root = nomt.commit(session, write(1)).unwrap();
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.read([1; 32]).unwrap(), Some(vec![1]));
+
+    let session = nomt.begin_session();
+    nomt.commit_and_prove(session, write(2)).unwrap();
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use common::{account_path, test_dir};
+use nomt::{FaultInjector, KeyReadWrite, Node, Nomt, Options, ShardedNomt};
+
+fn open(dir: &Path, shards: usize) -> ShardedNomt<nomt::Blake3Hasher> {
+    let options = (0..shards).map(|i| shard_options(dir, i, false)).collect();
+    ShardedNomt::open(options).unwrap()
+}
+
+fn shard_options(dir: &Path, shard: usize, rollback: bool) -> Options {
+    let mut o = Options::new();
+    o.path(dir.join(format!("shard{shard}")));
+    o.hashtable_buckets(10_000);
+    o.preallocate_ht(false);
+    o.rollback(rollback);
+    o
+}
+
+fn commit(
+    nomt: &ShardedNomt<nomt::Blake3Hasher>,
+    ids: std::ops::Range<u64>,
+) -> anyhow::Result<Node> {
+    let mut actuals = ids
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![1; 8]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals)
+}
+
+/// Fails the WAL writes once armed.
+#[derive(Default)]
+struct FailingWal(AtomicBool);
+
+impl FaultInjector for FailingWal {
+    fn wal_write(&self, _sync_seqn: u32, _wal_blob: &[u8]) -> std::io::Result<()> {
+        if self.0.load(Ordering::Relaxed) {
+            return Err(std::io::Error::other("injected WAL write failure"));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn sharded_commit_and_read() {
+    let dir = test_dir("sharded");
+    let nomt = open(dir.path(), 4);
+    assert_eq!(nomt.root(), [0; 32]);
+
+    let mut actuals = (0..100)
+        .map(|id| {
+            (
+                account_path(id),
+                KeyReadWrite::Write(Some(id.to_le_bytes().to_vec())),
+            )
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+
+    let session = nomt.begin_session();
+    let root = nomt.commit(session, actuals).unwrap();
+    assert_eq!(root, nomt.root());
+
+    // Keys are uniformly distributed, so every shard received some.
+    assert!(nomt.shard_roots().iter().all(|root| *root != [0; 32]));
+
+    let session = nomt.begin_session();
+    for id in 0..100 {
+        assert_eq!(
+            session.read(account_path(id)).unwrap(),
+            Some(id.to_le_bytes().to_vec())
+        );
+    }
+    assert_eq!(session.read(account_path(100)).unwrap(), None);
+}
+
+#[test]
+fn shard_count_must_be_power_of_two() {
+    let options = (0..3).map(|_| Options::new()).collect();
+    assert!(ShardedNomt::<nomt::Blake3Hasher>::open(options).is_err());
+}
+
+#[test]
+fn failed_commit_rolls_back_reached_shards() {
+    let dir = test_dir("sharded_failed_commit");
+    let injector = Arc::new(FailingWal::default());
+    let open_sharded = |injector: Option<Arc<FailingWal>>| {
+        let options = (0..2)
+            .map(|i| {
+                let mut o = shard_options(dir.path(), i, true);
+                if let (1, Some(injector)) = (i, &injector) {
+                    o.fault_injector(injector.clone());
+                }
+                o
+            })
+            .collect();
+        ShardedNomt::<nomt::Blake3Hasher>::open(options).unwrap()
+    };
+
+    let nomt = open_sharded(Some(injector.clone()));
+    let root = commit(&nomt, 0..100).unwrap();
+    let shard_roots = nomt.shard_roots();
+
+    // The commit reaches the first shard only and is rolled back there.
+    injector.0.store(true, Ordering::Relaxed);
+    assert!(commit(&nomt, 100..200).is_err());
+    assert_eq!(nomt.shard_roots()[0], shard_roots[0]);
+
+    // The failed shard is poisoned, so no commit starts until the database is reopened, not even
+    // once the fault is gone.
+    injector.0.store(false, Ordering::Relaxed);
+    assert!(commit(&nomt, 100..200).is_err());
+    assert_eq!(nomt.shard_roots()[0], shard_roots[0]);
+    drop(nomt);
+
+    let nomt = open_sharded(None);
+    assert_eq!(nomt.root(), root);
+    let session = nomt.begin_session();
+    assert_eq!(session.read(account_path(150)).unwrap(), None);
+    drop(session);
+    commit(&nomt, 100..200).unwrap();
+}
+
+#[test]
+fn open_rolls_back_interrupted_commit() {
+    let dir = test_dir("sharded_interrupted_commit");
+    let open_sharded = |rollback: bool| {
+        let options = (0..2)
+            .map(|i| shard_options(dir.path(), i, rollback))
+            .collect();
+        ShardedNomt::<nomt::Blake3Hasher>::open(options)
+    };
+
+    let nomt = open_sharded(true).unwrap();
+    let root = commit(&nomt, 0..100).unwrap();
+    drop(nomt);
+
+    // A commit which reached the first shard only before a crash.
+    let shard = Nomt::<nomt::Blake3Hasher>::open(shard_options(dir.path(), 0, true)).unwrap();
+    let mut session = shard.begin_session();
+    session.set_commit_token([0xff; 32]);
+    shard
+        .commit(session, vec![([0; 32], KeyReadWrite::Write(Some(vec![2])))])
+        .unwrap();
+    drop(shard);
+
+    // Without rollback, the shards can't be brought back in line.
+    assert!(open_sharded(false).is_err());
+
+    let nomt = open_sharded(true).unwrap();
+    assert_eq!(nomt.root(), root);
+    commit(&nomt, 100..200).unwrap();
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{KeyReadWrite, PagePool};
+
+#[test]
+fn stores_share_one_page_pool() {
+    let page_pool = PagePool::with_limit(512 * 1024 * 1024);
+    let dir = test_dir("shared_page_pool");
+    let stores = (0..2)
+        .map(|i| {
+            let page_pool = page_pool.clone();
+            let path = dir.path().join(format!("db{i}"));
+            std::thread::spawn(move || {
+                let t = Test::open_with(path, move |o| o.page_pool(page_pool.clone()));
+                let nomt = t.nomt();
+                let mut actuals = (0..1000)
+                    .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![i; 32]))))
+                    .collect::<Vec<_>>();
+                actuals.sort_by_key(|(key, _)| *key);
+                let session = nomt.begin_session();
+                nomt.commit(session, actuals).unwrap();
+                t
+            })
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect::<Vec<_>>();
+
+    for (i, t) in stores.iter().enumerate() {
+        assert_eq!(
+            t.nomt().read(account_path(7)).unwrap(),
+            Some(vec![i as u8; 32])
+        );
+    }
+    assert!(page_pool.reserved_bytes() > 0);
+    assert!(page_pool.reserved_bytes() <= 512 * 1024 * 1024);
+}
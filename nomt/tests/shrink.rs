@@ -0,0 +1,83 @@
+mod common;
+
+use std::path::Path;
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyReadWrite, Node, Nomt, Options};
+
+fn configure(o: &mut Options) {
+    o.bitbox_seed([0; 16]);
+    o.hashtable_buckets(20_000);
+    o.hashtable_resize_budget(64);
+}
+
+fn write(nomt: &Nomt<Blake3Hasher>, ids: std::ops::Range<u64>, value: Option<u8>) -> Node {
+    let mut actuals = ids
+        .map(|id| {
+            let value = value.map(|v| vec![v; 100]);
+            (account_path(id), KeyReadWrite::Write(value))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(path, _)| *path);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap()
+}
+
+fn file_len(path: &Path, name: &str) -> u64 {
+    std::fs::metadata(path.join(name)).unwrap().len()
+}
+
+#[test]
+fn shrink_after_pruning() {
+    let dir = test_dir("shrink");
+    let path = dir.path().join("db");
+
+    let t = Test::open_with(&path, configure);
+    let nomt = t.nomt();
+    write(nomt, 0..5000, Some(1));
+    let root = write(nomt, 100..5000, None);
+    let sync_seqn = nomt.sync_seqn();
+    let ht_len = file_len(&path, "ht");
+    let ln_len = file_len(&path, "ln");
+
+    nomt.shrink().unwrap();
+    let stats = nomt.hash_table_stats();
+    assert!(stats.buckets < 20_000);
+    assert_eq!(stats.resizing_to, None);
+    assert!(stats.occupied_buckets * 2 <= stats.buckets);
+    assert!(nomt.sync_seqn() > sync_seqn);
+    assert!(file_len(&path, "ht") < ht_len);
+    assert!(file_len(&path, "ln") < ln_len);
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.read(account_path(50)).unwrap(), Some(vec![1; 100]));
+
+    // The database keeps working after shrinking, also when reopened.
+    let root = write(nomt, 5000..6000, Some(2));
+    let t = t.reopen();
+    let nomt = t.nomt();
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.read(account_path(50)).unwrap(), Some(vec![1; 100]));
+    assert_eq!(nomt.read(account_path(5500)).unwrap(), Some(vec![2; 100]));
+    assert_eq!(nomt.read(account_path(500)).unwrap(), None);
+}
+
+#[test]
+fn shrink_keeps_commit_token() {
+    let dir = test_dir("shrink_commit_token");
+    let path = dir.path().join("db");
+
+    let t = Test::open_with(&path, configure);
+    let nomt = t.nomt();
+    let mut session = nomt.begin_session();
+    session.set_commit_token([7; 32]);
+    session.set_block_number(42);
+    nomt.commit(
+        session,
+        vec![(account_path(0), KeyReadWrite::Write(Some(vec![1])))],
+    )
+    .unwrap();
+
+    nomt.shrink().unwrap();
+    assert_eq!(nomt.last_commit_token(), Some([7; 32]));
+    assert_eq!(nomt.last_block_number(), Some(42));
+}
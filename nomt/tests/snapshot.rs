@@ -0,0 +1,65 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{KeyReadWrite, Nomt};
+use std::collections::HashMap;
+
+fn commit(nomt: &Nomt<nomt::Blake3Hasher>, ids: std::ops::Range<u64>, value: u32) {
+    let mut actuals = ids
+        .map(|id| {
+            let value = value.to_le_bytes().to_vec();
+            (account_path(id), KeyReadWrite::Write(Some(value)))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+}
+
+#[test]
+fn snapshot_while_committing() {
+    let dir = test_dir("snapshot");
+    let path = dir.path().join("source");
+    let snapshot_path = dir.path().join("snapshot");
+
+    let t = Test::open(&path);
+    let nomt = t.nomt();
+    commit(nomt, 0..5_000, 0);
+
+    // Record the root of every sync while snapshotting concurrently.
+    let mut roots = HashMap::new();
+    roots.insert(nomt.sync_seqn(), nomt.root());
+    std::thread::scope(|s| {
+        let committer = s.spawn(|| {
+            let mut roots = Vec::new();
+            for i in 1..10 {
+                commit(nomt, 0..5_000, i);
+                roots.push((nomt.sync_seqn(), nomt.root()));
+            }
+            roots
+        });
+        nomt.snapshot_to(&snapshot_path).unwrap();
+        roots.extend(committer.join().unwrap());
+    });
+    assert!(nomt.snapshot_to(&snapshot_path).is_err());
+
+    let snapshot = Test::open(&snapshot_path);
+    assert_eq!(
+        Some(&snapshot.nomt().root()),
+        roots.get(&snapshot.nomt().sync_seqn())
+    );
+    let value = snapshot.nomt().read(account_path(0)).unwrap().unwrap();
+    for id in (0..5_000).step_by(97) {
+        assert_eq!(
+            snapshot.nomt().read(account_path(id)).unwrap(),
+            Some(value.clone())
+        );
+    }
+
+    // The snapshot is a database of its own.
+    commit(snapshot.nomt(), 0..10, 100);
+    assert_eq!(
+        nomt.read(account_path(0)).unwrap(),
+        Some(9u32.to_le_bytes().to_vec())
+    );
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::Test;
+use nomt::{Blake3Hasher, StateDeltaError, StateDeltaProof};
+
+#[test]
+fn state_delta_proof() {
+    let mut t = Test::new("state_delta");
+    for id in 0..10 {
+        common::set_balance(&mut t, id, 1000);
+    }
+    let (prev_root, _, _) = t.commit();
+
+    for id in 0..10 {
+        t.read_id(id);
+    }
+    for id in 0..5 {
+        common::kill(&mut t, id);
+    }
+    for id in 10..15 {
+        common::set_balance(&mut t, id, 1000);
+    }
+    let (new_root, witness, witnessed) = t.commit();
+
+    let proof = witness.into_delta_proof(&witnessed, prev_root, new_root);
+    assert_eq!(proof.writes.len(), 10);
+    proof.verify::<Blake3Hasher>().unwrap();
+
+    let decoded = StateDeltaProof::decode(&proof.encode()).unwrap();
+    assert_eq!(decoded.encode(), proof.encode());
+    decoded.verify::<Blake3Hasher>().unwrap();
+
+    // A different changeset doesn't lead to the claimed root.
+    let mut tampered = StateDeltaProof::decode(&proof.encode()).unwrap();
+    tampered.writes[0].1 = Some([1; 32]);
+    assert_eq!(
+        tampered.verify::<Blake3Hasher>(),
+        Err(StateDeltaError::RootMismatch)
+    );
+
+    // Every write must be covered by a path proof.
+    let mut tampered = StateDeltaProof::decode(&proof.encode()).unwrap();
+    tampered.paths.pop();
+    assert_eq!(
+        tampered.verify::<Blake3Hasher>(),
+        Err(StateDeltaError::UnprovenWrite)
+    );
+
+    // The path proofs must match the prior root.
+    let mut tampered = StateDeltaProof::decode(&proof.encode()).unwrap();
+    tampered.prev_root = new_root;
+    assert_eq!(
+        tampered.verify::<Blake3Hasher>(),
+        Err(StateDeltaError::InvalidPath { path_index: 0 })
+    );
+}
+
+#[test]
+fn state_delta_proof_without_writes() {
+    let mut t = Test::new("state_delta_reads_only");
+    common::set_balance(&mut t, 0, 1000);
+    let (prev_root, _, _) = t.commit();
+
+    t.read_id(0);
+    let (new_root, witness, witnessed) = t.commit();
+    assert_eq!(prev_root, new_root);
+
+    let proof = witness.into_delta_proof(&witnessed, prev_root, new_root);
+    assert!(proof.paths.is_empty());
+    proof.verify::<Blake3Hasher>().unwrap();
+}
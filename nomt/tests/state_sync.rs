@@ -0,0 +1,88 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{ChunkImporter, KeyReadWrite, Nomt, StateChunk};
+
+fn populate(nomt: &Nomt<nomt::Blake3Hasher>, count: u64) {
+    let mut actuals = (0..count)
+        .map(|id| {
+            let value = id.to_le_bytes().repeat(1 + id as usize % 4);
+            (account_path(id), KeyReadWrite::Write(Some(value)))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+}
+
+/// Transfer all chunks, passing each through its encoding, in reverse order.
+fn sync(source: &Nomt<nomt::Blake3Hasher>, dest: &Nomt<nomt::Blake3Hasher>, chunk_bits: u32) {
+    let count = 1 << chunk_bits;
+    let mut chunks = source.export_chunks(chunk_bits, 0..count / 2).unwrap();
+    chunks.extend(source.export_chunks(chunk_bits, count / 2..count).unwrap());
+    assert_eq!(chunks.len() as u64, count);
+
+    let mut importer = ChunkImporter::new(dest, source.root()).unwrap();
+    for chunk in chunks.into_iter().rev() {
+        assert!(!importer.is_complete());
+        let chunk = StateChunk::decode(&chunk.encode()).unwrap();
+        importer.import(chunk).unwrap();
+    }
+    assert_eq!(importer.finish().unwrap(), source.root());
+}
+
+#[test]
+fn state_sync_rebuilds_trie() {
+    let dir = test_dir("state_sync");
+    let source = Test::open(dir.path().join("source"));
+    populate(source.nomt(), 2000);
+    let dest = Test::open(dir.path().join("dest"));
+    sync(source.nomt(), dest.nomt(), 4);
+    assert_eq!(dest.nomt().root(), source.nomt().root());
+    assert_eq!(
+        dest.nomt().read(account_path(1234)).unwrap(),
+        source.nomt().read(account_path(1234)).unwrap()
+    );
+}
+
+#[test]
+fn state_sync_sparse_trie() {
+    // With few keys, the trie ends above most prefixes.
+    let dir = test_dir("state_sync_sparse");
+    let source = Test::open(dir.path().join("source"));
+    populate(source.nomt(), 5);
+    let dest = Test::open(dir.path().join("dest"));
+    sync(source.nomt(), dest.nomt(), 6);
+    assert_eq!(dest.nomt().root(), source.nomt().root());
+}
+
+#[test]
+fn state_sync_rejects_bad_chunks() {
+    let dir = test_dir("state_sync_reject");
+    let source = Test::open(dir.path().join("source"));
+    populate(source.nomt(), 500);
+    let chunks = source.nomt().export_chunks(2, 0..4).unwrap();
+
+    let dest = Test::open(dir.path().join("dest"));
+    let mut importer = ChunkImporter::new(dest.nomt(), source.nomt().root()).unwrap();
+
+    // A chunk with an altered value no longer matches the root.
+    let mut encoded = chunks[1].encode();
+    *encoded.last_mut().unwrap() ^= 1;
+    let tampered = StateChunk::decode(&encoded).unwrap();
+    assert!(importer.import(tampered).is_err());
+
+    // A chunk of another trie is rejected.
+    let other = Test::open(dir.path().join("other"));
+    populate(other.nomt(), 400);
+    let foreign = other.nomt().export_chunks(2, 1..2).unwrap().remove(0);
+    assert!(foreign.verify::<nomt::Blake3Hasher>().is_ok());
+    assert!(importer.import(foreign).is_err());
+
+    // Chunks must not overlap.
+    importer.import(chunks[1].clone()).unwrap();
+    assert!(importer.import(chunks[1].clone()).is_err());
+
+    // The other chunks are still missing.
+    assert!(importer.finish().is_err());
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyPath, Nomt, ValueCompression, ValueHandle};
+use rand::{Rng, SeedableRng};
+use std::io::Read;
+
+fn blob(seed: u8, len: usize) -> Vec<u8> {
+    let mut rng = rand_pcg::Lcg64Xsh32::from_seed([seed; 16]);
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+fn read_streaming(nomt: &Nomt<Blake3Hasher>, key: KeyPath) -> Option<Vec<u8>> {
+    let session = nomt.begin_session();
+    let mut reader = session.read_streaming(key).unwrap()?;
+    // Read in small, odd-sized pieces to cross page boundaries.
+    let mut value = Vec::new();
+    let mut buf = [0; 1000];
+    loop {
+        let n = reader.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        value.extend_from_slice(&buf[..n]);
+    }
+    Some(value)
+}
+
+#[test]
+fn streamed_writes_match_buffered_writes() {
+    let values = [
+        (account_path(0), blob(1, 3 * 1024 * 1024 + 17)),
+        (account_path(1), blob(2, 20_000)),
+        (account_path(2), blob(3, 100)),
+        (account_path(3), Vec::new()),
+    ];
+
+    let dir = test_dir("streaming_values");
+    let streamed = Test::open(dir.path().join("streamed"));
+    let streamed = streamed.nomt();
+    let mut session = streamed.begin_session();
+    for (key, value) in &values {
+        session.write_streaming(*key, &value[..]).unwrap();
+    }
+    streamed.commit(session, Vec::new()).unwrap();
+
+    let buffered = Test::open(dir.path().join("buffered"));
+    let buffered = buffered.nomt();
+    let mut session = buffered.begin_session();
+    session.write_all(
+        values
+            .iter()
+            .map(|(key, value)| (*key, Some(ValueHandle::from(value.clone())))),
+    );
+    buffered.commit(session, Vec::new()).unwrap();
+
+    assert_eq!(streamed.root(), buffered.root());
+    for (key, value) in &values {
+        assert_eq!(streamed.read(*key).unwrap().as_ref(), Some(value));
+        assert_eq!(read_streaming(streamed, *key).as_ref(), Some(value));
+    }
+    assert_eq!(read_streaming(streamed, account_path(4)), None);
+}
+
+#[test]
+fn streamed_reads_of_every_storage() {
+    let compressible = (0..2_000_000u32)
+        .flat_map(|i| (i / 1000).to_le_bytes())
+        .collect::<Vec<_>>();
+    let values = [
+        (account_path(0), blob(4, 5_000_000)),
+        (account_path(1), compressible),
+        (account_path(2), blob(5, 10)),
+    ];
+
+    let dir = test_dir("streaming_values_storage");
+    for (name, compression, value_log) in [
+        ("overflow", ValueCompression::None, false),
+        ("compressed", ValueCompression::Zstd { level: 1 }, false),
+        ("log", ValueCompression::None, true),
+    ] {
+        let t = Test::open_with(dir.path().join(name), move |o| {
+            o.value_compression(compression);
+            if value_log {
+                o.value_log_threshold(1024 * 1024);
+            }
+        });
+        let nomt = t.nomt();
+        let mut session = nomt.begin_session();
+        session.write_all(
+            values
+                .iter()
+                .map(|(key, value)| (*key, Some(ValueHandle::from(value.clone())))),
+        );
+        nomt.commit(session, Vec::new()).unwrap();
+
+        for (key, value) in &values {
+            assert_eq!(read_streaming(nomt, *key).as_ref(), Some(value), "{name}");
+        }
+    }
+}
+
+#[test]
+fn failed_streamed_write_writes_nothing() {
+    struct Failing;
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("broken pipe"))
+        }
+    }
+
+    let dir = test_dir("streaming_values_failed");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    let mut session = nomt.begin_session();
+    assert!(session.write_streaming(account_path(0), Failing).is_err());
+    nomt.commit(session, Vec::new()).unwrap();
+    assert!(nomt.is_empty());
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use bitvec::prelude::*;
+use common::{account_path, test_dir, Test};
+use nomt::{KeyPath, KeyReadWrite, Nomt, SubtreeArchive};
+
+fn under_prefix(key: &KeyPath) -> bool {
+    key.view_bits::<Msb0>().starts_with(bits![u8, Msb0; 1, 0])
+}
+
+fn commit(nomt: &Nomt<nomt::Blake3Hasher>, writes: impl Iterator<Item = (KeyPath, Vec<u8>)>) {
+    let mut actuals = writes
+        .map(|(key, value)| (key, KeyReadWrite::Write(Some(value))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+}
+
+#[test]
+fn subtree_transfer() {
+    let dir = test_dir("subtree");
+    let source = Test::open(dir.path().join("source"));
+    let dest = Test::open(dir.path().join("dest"));
+
+    // The databases agree outside of the prefix, but not under it: the destination holds
+    // different values and keys which the source lacks.
+    commit(
+        source.nomt(),
+        (0..1000).map(|id| (account_path(id), vec![1; 8])),
+    );
+    commit(
+        dest.nomt(),
+        (0..1000)
+            .map(account_path)
+            .filter(|key| !under_prefix(key))
+            .map(|key| (key, vec![1; 8]))
+            .chain(
+                (500..2000)
+                    .map(account_path)
+                    .filter(under_prefix)
+                    .map(|key| (key, vec![2; 8])),
+            ),
+    );
+    assert_ne!(source.nomt().root(), dest.nomt().root());
+
+    let archive = source.nomt().export_subtree(bits![u8, Msb0; 1, 0]);
+    assert!(!archive.entries().is_empty());
+    assert!(archive.entries().iter().all(|(key, _)| under_prefix(key)));
+    let archive = SubtreeArchive::decode(&archive.encode()).unwrap();
+
+    let root = dest.nomt().import_subtree(archive.clone()).unwrap();
+    assert_eq!(root, source.nomt().root());
+    assert_eq!(dest.nomt().export_subtree(archive.prefix()), archive);
+    for id in 1000..2000 {
+        assert_eq!(dest.nomt().read(account_path(id)).unwrap(), None);
+    }
+}
+
+#[test]
+fn import_rejects_tampered_archive() {
+    let dir = test_dir("subtree_tampered");
+    let source = Test::open(dir.path().join("source"));
+    let dest = Test::open(dir.path().join("dest"));
+    commit(
+        source.nomt(),
+        (0..100).map(|id| (account_path(id), vec![1; 8])),
+    );
+
+    let archive = source.nomt().export_subtree(bits![u8, Msb0; 1, 0]);
+    let mut encoded = archive.encode();
+    // Flip a bit of the last value.
+    *encoded.last_mut().unwrap() ^= 1;
+    let tampered = SubtreeArchive::decode(&encoded).unwrap();
+
+    assert!(dest.nomt().import_subtree(tampered).is_err());
+    assert!(dest.nomt().is_empty());
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use bitvec::prelude::*;
+use common::{account_path, test_dir, Test};
+use nomt::{subtree_proof::SubtreeProofError, Blake3Hasher, KeyReadWrite};
+use nomt_core::trie::TERMINATOR;
+
+#[test]
+fn subtree_roots_match_exported_subtrees() {
+    let dir = test_dir("subtree_proof");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    let mut actuals = (0..1000)
+        .map(|id| {
+            (
+                account_path(id),
+                KeyReadWrite::Write(Some(vec![id as u8; 8])),
+            )
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+    let root = nomt.root();
+
+    let key = account_path(5);
+    let key_bits = key.view_bits::<Msb0>();
+    let mut empty_prefix = key_bits[..40].to_bitvec();
+    let last = !empty_prefix[39];
+    empty_prefix.set(39, last);
+    let prefixes = [
+        // The whole trie.
+        BitVec::<u8, Msb0>::new(),
+        // Many keys.
+        bitvec![u8, Msb0; 1, 0],
+        // A single key, whose leaf sits above the end of the prefix.
+        key_bits[..40].to_bitvec(),
+        key_bits.to_bitvec(),
+        // No key.
+        empty_prefix,
+    ];
+
+    let session = nomt.begin_session();
+    for prefix in &prefixes {
+        let proof = session.prove_subtree(prefix).unwrap();
+        let verified = proof.verify::<Blake3Hasher>(prefix, root).unwrap();
+        let archive = nomt.export_subtree(prefix);
+        assert_eq!(verified.root, archive.root(), "{prefix}");
+        assert_eq!(
+            verified.leaf.is_some(),
+            archive.entries().len() == 1,
+            "{prefix}"
+        );
+
+        // The proof is bound to the prefix and the root.
+        assert_eq!(
+            proof.verify::<Blake3Hasher>(prefix, [0xff; 32]).err(),
+            Some(SubtreeProofError::RootMismatch)
+        );
+    }
+
+    assert_eq!(
+        session
+            .prove_subtree(&prefixes[4])
+            .unwrap()
+            .verify::<Blake3Hasher>(&prefixes[4], root)
+            .unwrap()
+            .root,
+        TERMINATOR
+    );
+    let verified = session
+        .prove_subtree(&prefixes[2])
+        .unwrap()
+        .verify::<Blake3Hasher>(&prefixes[2], root)
+        .unwrap();
+    assert_eq!(verified.leaf.unwrap().key_path, key);
+    assert!(session.prove_subtree(&bitvec![u8, Msb0; 0; 257]).is_err());
+}
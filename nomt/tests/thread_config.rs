@@ -0,0 +1,60 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use common::{test_dir, Test};
+use nomt::{KeyReadWrite, Options, ThreadConfig, ThreadPriority, ThreadSettings};
+
+fn thread_config(thread_config: ThreadConfig) -> impl Fn(&mut Options) {
+    move |o| {
+        o.commit_concurrency(2);
+        o.thread_config(thread_config.clone());
+    }
+}
+
+fn thread_names() -> Vec<String> {
+    std::fs::read_dir("/proc/self/task")
+        .unwrap()
+        .filter_map(|task| std::fs::read_to_string(task.ok()?.path().join("comm")).ok())
+        .map(|comm| comm.trim_end().to_string())
+        .collect()
+}
+
+#[test]
+fn configured_threads_commit() {
+    let mut settings = ThreadSettings::default();
+    settings.cpus([0]);
+    settings.priority(ThreadPriority::Nice(5));
+    let mut config = ThreadConfig::default();
+    config.name_prefix("tcfg");
+    config.io(settings.clone());
+    config.commit(settings.clone());
+    config.merkle(settings);
+
+    let dir = test_dir("thread_config");
+    let t = Test::open_with(dir.path().join("db"), self::thread_config(config));
+    let nomt = t.nomt();
+    let names = thread_names();
+    for name in ["tcfg-bitbox-syn", "tcfg-beatree-sy", "tcfg-nomt-commi"] {
+        assert!(names.iter().any(|n| n == name), "{name} not in {names:?}");
+    }
+
+    let session = nomt.begin_session();
+    let actuals = (0..100u8)
+        .map(|j| ([j; 32], KeyReadWrite::Write(Some(vec![j]))))
+        .collect();
+    nomt.commit(session, actuals).unwrap();
+    let session = nomt.begin_session();
+    assert_eq!(session.read([7; 32]).unwrap(), Some(vec![7]));
+}
+
+#[test]
+fn invalid_thread_settings_fail_open() {
+    let mut settings = ThreadSettings::default();
+    settings.cpus([1 << 20]);
+    let mut config = ThreadConfig::default();
+    config.merkle(settings);
+
+    let dir = test_dir("thread_config_invalid");
+    assert!(Test::try_open_with(dir.path().join("db"), self::thread_config(config)).is_err());
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{
+    proof, Blake3Hasher, KeyReadWrite, LeafData, Nomt, Session, SessionParams, ValueHandle,
+};
+use std::time::Duration;
+
+const TTL: Duration = Duration::from_millis(200);
+const START: u64 = 1_000_000;
+const EXPIRED: u64 = START + TTL.as_millis() as u64;
+
+fn value(id: u8) -> ValueHandle {
+    ValueHandle::from(vec![id; 8])
+}
+
+fn session_at(nomt: &Nomt<Blake3Hasher>, time: u64) -> Session {
+    let mut params = SessionParams::default();
+    params.time(time);
+    nomt.begin_session_with_params(params)
+}
+
+#[test]
+fn expired_values_are_absent_and_deleted_by_next_commit() {
+    let dir = test_dir("ttl_expiry");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    let mut session = session_at(nomt, START);
+    session.write_all([(account_path(1), Some(value(1)))]);
+    session.write_with_ttl(account_path(2), value(2), TTL);
+    nomt.commit(session, Vec::new()).unwrap();
+
+    let session = session_at(nomt, EXPIRED - 1);
+    assert_eq!(session.read(account_path(2)).unwrap(), Some(vec![2; 8]));
+    drop(session);
+
+    let session = session_at(nomt, EXPIRED);
+    assert_eq!(session.read(account_path(1)).unwrap(), Some(vec![1; 8]));
+    assert_eq!(session.read(account_path(2)).unwrap(), None);
+    assert_eq!(session.iter_range([0; 32], [255; 32]).count(), 1);
+    nomt.commit(session, Vec::new()).unwrap();
+
+    // The trie no longer holds the expired value.
+    let expected = Test::open(dir.path().join("expected"));
+    let mut session = expected.nomt().begin_session();
+    session.write_all([(account_path(1), Some(value(1)))]);
+    expected.nomt().commit(session, Vec::new()).unwrap();
+    assert_eq!(nomt.root(), expected.nomt().root());
+}
+
+#[test]
+fn values_do_not_expire_without_session_time() {
+    let dir = test_dir("ttl_no_time");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    let mut session = session_at(nomt, START);
+    session.write_with_ttl(account_path(1), value(1), TTL);
+    nomt.commit(session, Vec::new()).unwrap();
+    let root = nomt.root();
+
+    let session = nomt.begin_session();
+    assert_eq!(session.read(account_path(1)).unwrap(), Some(vec![1; 8]));
+    nomt.commit(session, Vec::new()).unwrap();
+    assert_eq!(nomt.root(), root);
+    assert_eq!(nomt.read(account_path(1)).unwrap(), Some(vec![1; 8]));
+}
+
+#[test]
+fn expired_reads_are_deleted_and_witnessed() {
+    let dir = test_dir("ttl_expired_read");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    let mut session = session_at(nomt, START);
+    session.write_all([(account_path(1), Some(value(1)))]);
+    session.write_with_ttl(account_path(2), value(2), TTL);
+    nomt.commit(session, Vec::new()).unwrap();
+    let prev_root = nomt.root();
+
+    let session = session_at(nomt, EXPIRED);
+    assert_eq!(session.read(account_path(2)).unwrap(), None);
+    let (new_root, witness, witnessed) = nomt
+        .commit_and_prove(session, vec![(account_path(2), KeyReadWrite::Read(None))])
+        .unwrap();
+    assert_eq!(nomt.read(account_path(2)).unwrap(), None);
+
+    // The witness proves the stored value and its deletion.
+    assert_eq!(witnessed.reads.len(), 1);
+    assert_eq!(witnessed.writes.len(), 1);
+    assert_eq!(witnessed.writes[0].value, None);
+    let path = &witness.path_proofs[0];
+    let verified = path
+        .inner
+        .verify::<Blake3Hasher>(path.path.path(), prev_root)
+        .unwrap();
+    let read = &witnessed.reads[0];
+    let leaf = LeafData {
+        key_path: read.key,
+        value_hash: read.value.unwrap(),
+    };
+    assert!(verified.confirm_value(&leaf).unwrap());
+    let updates = [proof::PathUpdate {
+        inner: verified,
+        ops: vec![(account_path(2), None)],
+    }];
+    assert_eq!(
+        proof::verify_update::<Blake3Hasher>(prev_root, &updates).unwrap(),
+        new_root,
+    );
+}
+
+#[test]
+fn later_write_clears_expiry() {
+    let dir = test_dir("ttl_cleared");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    let mut session = session_at(nomt, START);
+    session.write_with_ttl(account_path(1), value(1), TTL);
+    session.write_with_ttl(account_path(2), value(2), TTL);
+    nomt.commit(session, Vec::new()).unwrap();
+
+    let session = session_at(nomt, START);
+    nomt.commit(
+        session,
+        vec![(account_path(1), KeyReadWrite::Write(Some(vec![3; 8])))],
+    )
+    .unwrap();
+
+    let session = session_at(nomt, EXPIRED);
+    assert_eq!(session.read(account_path(1)).unwrap(), Some(vec![3; 8]));
+    assert_eq!(session.read(account_path(2)).unwrap(), None);
+}
+
+#[test]
+fn expiries_survive_reopen() {
+    let dir = test_dir("ttl_reopen");
+    let path = dir.path().join("db");
+    let t = Test::open(&path);
+    let nomt = t.nomt();
+    let mut session = session_at(nomt, START);
+    session.write_with_ttl(account_path(1), value(1), TTL);
+    nomt.commit(session, Vec::new()).unwrap();
+
+    let t = t.reopen();
+    let nomt = t.nomt();
+    let session = session_at(nomt, EXPIRED);
+    assert_eq!(session.read(account_path(1)).unwrap(), None);
+    nomt.commit(session, Vec::new()).unwrap();
+    assert!(nomt.is_empty());
+}
+
+#[test]
+fn reserved_aux_keys_are_rejected() {
+    let dir = test_dir("ttl_reserved_aux");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    let mut session = nomt.begin_session();
+    session.write_aux(b"\0nomt:ttl:key".to_vec(), Some(vec![1]));
+    assert!(nomt.commit(session, Vec::new()).is_err());
+}
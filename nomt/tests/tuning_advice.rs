@@ -0,0 +1,79 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyReadWrite, Nomt, Options, Recommendation};
+
+const BUCKETS: u32 = 2000;
+
+fn configure(o: &mut Options) {
+    o.bitbox_seed([0; 16]);
+    o.hashtable_buckets(BUCKETS);
+    o.metrics(true);
+}
+
+fn write(nomt: &Nomt<Blake3Hasher>, ids: std::ops::Range<u64>) {
+    let mut actuals = ids
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![1; 8]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(path, _)| *path);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+}
+
+#[test]
+fn quiet_database_gets_no_advice() {
+    let dir = test_dir("tuning_advice_quiet");
+    let t = Test::open_with(dir.path().join("db"), configure);
+    let nomt = t.nomt();
+    write(nomt, 0..10);
+    assert_eq!(nomt.tuning_advice(), Vec::new());
+}
+
+#[test]
+fn full_hash_table_is_grown_live() {
+    let dir = test_dir("tuning_advice_grow");
+    let t = Test::open_with(dir.path().join("db"), configure);
+    let nomt = t.nomt();
+    let mut next = 0;
+    loop {
+        write(nomt, next..next + 50);
+        next += 50;
+        let stats = nomt.hash_table_stats();
+        if stats.occupied_buckets * 10 > stats.buckets * 8 {
+            break;
+        }
+    }
+
+    let advice = nomt.tuning_advice();
+    let grow = advice
+        .iter()
+        .find(|r| matches!(r, Recommendation::GrowHashTable { .. }))
+        .unwrap();
+    let Recommendation::GrowHashTable { buckets, occupancy } = *grow else {
+        unreachable!()
+    };
+    assert_eq!(buckets, BUCKETS * 2);
+    assert!(occupancy > 0.8);
+    assert!(grow.is_live());
+
+    nomt.apply_tuning(grow).unwrap();
+    assert_eq!(
+        nomt.hash_table_stats().resizing_to,
+        Some(BUCKETS as usize * 2)
+    );
+    // While resizing, growing is not recommended again.
+    assert!(!nomt
+        .tuning_advice()
+        .iter()
+        .any(|r| matches!(r, Recommendation::GrowHashTable { .. })));
+}
+
+#[test]
+fn advice_requiring_reopen_is_not_applied() {
+    let dir = test_dir("tuning_advice_reopen");
+    let t = Test::open_with(dir.path().join("db"), configure);
+    let nomt = t.nomt();
+    let warm_up = Recommendation::WarmUp { miss_rate: 0.5 };
+    assert!(!warm_up.is_live());
+    assert!(nomt.apply_tuning(&warm_up).is_err());
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::KeyReadWrite;
+
+fn actuals(ids: std::ops::Range<u64>, value: u8) -> Vec<([u8; 32], KeyReadWrite)> {
+    let mut actuals = ids
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![value; 8]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(k, _)| *k);
+    actuals
+}
+
+#[test]
+fn validate_commit_does_not_persist() {
+    let dir = test_dir("validate_commit");
+    let t = Test::open_with(dir.path().join("db"), |o| o.commit_concurrency(1));
+    let nomt = t.nomt();
+
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals(0..100, 1)).unwrap();
+    let prev_root = nomt.root();
+
+    assert!(!nomt
+        .validate_commit(actuals(50..150, 2), prev_root)
+        .unwrap());
+    assert_eq!(nomt.root(), prev_root);
+    assert_eq!(nomt.read(account_path(50)).unwrap(), Some(vec![1; 8]));
+    assert_eq!(nomt.read(account_path(120)).unwrap(), None);
+
+    // Compute the expected root by actually committing on a second database.
+    let reference = Test::open_with(dir.path().join("reference"), |o| o.commit_concurrency(1));
+    let session = reference.nomt().begin_session();
+    reference
+        .nomt()
+        .commit(session, actuals(0..100, 1))
+        .unwrap();
+    let session = reference.nomt().begin_session();
+    let expected_root = reference
+        .nomt()
+        .commit(session, actuals(50..150, 2))
+        .unwrap();
+
+    assert!(nomt
+        .validate_commit(actuals(50..150, 2), expected_root)
+        .unwrap());
+    assert_eq!(nomt.root(), prev_root);
+
+    let session = nomt.begin_session();
+    assert_eq!(
+        nomt.commit(session, actuals(50..150, 2)).unwrap(),
+        expected_root
+    );
+}
+
+#[test]
+fn validate_commit_sees_held_back_commits() {
+    let dir = test_dir("validate_commit_coalescing");
+    let t = Test::open_with(dir.path().join("db"), |o| {
+        o.commit_concurrency(1);
+        o.commit_coalescing(10, std::time::Duration::from_secs(3600));
+    });
+    let nomt = t.nomt();
+
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals(0..100, 1)).unwrap();
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals(100..200, 1)).unwrap();
+    let prev_root = nomt.root();
+    let sync_seqn = nomt.sync_seqn();
+
+    // Updating nothing has to leave the root of both held back commits.
+    assert!(nomt.validate_commit(Vec::new(), prev_root).unwrap());
+    assert!(nomt
+        .validate_commit(actuals(150..250, 1), prev_root)
+        .is_ok_and(|valid| !valid));
+    // The held back commits are not flushed.
+    assert_eq!(nomt.sync_seqn(), sync_seqn);
+
+    // The session slot is free again.
+    let session = nomt.begin_session();
+    let root = nomt.commit(session, actuals(150..250, 1)).unwrap();
+    assert_ne!(root, prev_root);
+}
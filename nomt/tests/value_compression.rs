@@ -0,0 +1,85 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{KeyReadWrite, Nomt, ValueCompression};
+use rand::{Rng, SeedableRng};
+
+fn values() -> Vec<(u64, Vec<u8>)> {
+    let mut rng = rand_pcg::Lcg64Xsh32::from_seed([7; 16]);
+    let mut values = Vec::new();
+    // small, compressible values.
+    for id in 0..200 {
+        let json = format!(r#"{{"balance":{id},"memo":"{}"}}"#, "a".repeat(200));
+        values.push((id, json.into_bytes()));
+    }
+    // large values which compress small enough to fit in a leaf.
+    for id in 200u64..210 {
+        values.push((id, id.to_le_bytes().repeat(2500)));
+    }
+    // large values which are not compressible.
+    for id in 210..215 {
+        values.push((id, (0..10_000).map(|_| rng.gen()).collect()));
+    }
+    // large values which remain in overflow pages when compressed.
+    for id in 215..220 {
+        values.push((id, (0..20_000).map(|_| rng.gen_range(0..16)).collect()));
+    }
+    values
+}
+
+fn write(nomt: &Nomt<nomt::Blake3Hasher>, values: &[(u64, Vec<u8>)], delete: bool) {
+    let mut actuals = values
+        .iter()
+        .map(|(id, value)| {
+            let value = (!delete).then(|| value.clone());
+            (account_path(*id), KeyReadWrite::Write(value))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+}
+
+fn check(nomt: &Nomt<nomt::Blake3Hasher>, values: &[(u64, Vec<u8>)]) {
+    for (id, value) in values {
+        assert_eq!(nomt.read(account_path(*id)).unwrap().as_ref(), Some(value));
+    }
+}
+
+fn used_bytes(nomt: &Nomt<nomt::Blake3Hasher>) -> usize {
+    nomt.btree_leaves().map(|leaf| leaf.used_bytes).sum()
+}
+
+#[test]
+fn compressed_and_uncompressed_values_coexist() {
+    let values = values();
+
+    let dir = test_dir("value_compression");
+    let path = dir.path().join("db");
+    let t = Test::open_with(&path, |o| o.value_compression(ValueCompression::None));
+    let nomt = t.nomt();
+    write(nomt, &values, false);
+    check(nomt, &values);
+    let uncompressed_bytes = used_bytes(nomt);
+
+    // Values written without compression are read back, and rewritten compressed.
+    let t = t.reopen_with(|o| o.value_compression(ValueCompression::Zstd { level: 3 }));
+    let nomt = t.nomt();
+    check(nomt, &values);
+    write(nomt, &values, false);
+    check(nomt, &values);
+    assert!(used_bytes(nomt) < uncompressed_bytes / 2);
+    let iterated = nomt.iter().map(|(_, value)| value).collect::<Vec<_>>();
+    assert_eq!(iterated.len(), values.len());
+    assert!(values.iter().all(|(_, value)| iterated.contains(value)));
+
+    // Compressed values are read back with compression disabled, and deleted.
+    let t = t.reopen_with(|o| o.value_compression(ValueCompression::None));
+    let nomt = t.nomt();
+    check(nomt, &values);
+    write(nomt, &values[150..], true);
+    check(nomt, &values[..150]);
+    for (id, _) in &values[150..] {
+        assert_eq!(nomt.read(account_path(*id)).unwrap(), None);
+    }
+}
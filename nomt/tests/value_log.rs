@@ -0,0 +1,113 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{KeyReadWrite, Nomt, Options};
+use rand::{Rng, SeedableRng};
+use std::path::Path;
+
+fn value_log(o: &mut Options) {
+    o.value_log_threshold(16 * 1024);
+}
+
+fn values(seed: u8, ids: std::ops::Range<u64>) -> Vec<(u64, Vec<u8>)> {
+    let mut rng = rand_pcg::Lcg64Xsh32::from_seed([seed; 16]);
+    ids.map(|id| {
+        // values from below the threshold up to the last size tier.
+        let len = [10_000, 20_000, 100_000, 300_000, 1_200_000][id as usize % 5];
+        (id, (0..len).map(|_| rng.gen()).collect())
+    })
+    .collect()
+}
+
+fn write(nomt: &Nomt<nomt::Blake3Hasher>, values: &[(u64, Vec<u8>)]) {
+    let mut actuals = values
+        .iter()
+        .map(|(id, value)| (account_path(*id), KeyReadWrite::Write(Some(value.clone()))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+}
+
+fn check(nomt: &Nomt<nomt::Blake3Hasher>, values: &[(u64, Vec<u8>)]) {
+    for (id, value) in values {
+        assert_eq!(nomt.read(account_path(*id)).unwrap().as_ref(), Some(value));
+    }
+}
+
+fn log_files(path: impl AsRef<Path>) -> usize {
+    match std::fs::read_dir(path.as_ref().join("vlog")) {
+        Ok(dir) => dir.count(),
+        Err(_) => 0,
+    }
+}
+
+#[test]
+fn large_values_are_stored_in_size_tiered_logs() {
+    let mut values = values(1, 0..20);
+    let dir = test_dir("value_log");
+    let path = dir.path().join("db");
+    let t = Test::open_with(&path, value_log);
+    let nomt = t.nomt();
+    write(nomt, &values);
+    check(nomt, &values);
+    // one file per size tier, the smallest values are stored in overflow pages.
+    assert_eq!(log_files(&path), 4);
+
+    // Files are not appended to after reopening.
+    let t = t.reopen();
+    let nomt = t.nomt();
+    check(nomt, &values);
+    let overwritten = self::values(2, 0..15);
+    write(nomt, &overwritten);
+    values[..15].clone_from_slice(&overwritten);
+    check(nomt, &values);
+    assert_eq!(log_files(&path), 8);
+    let iterated = nomt.iter().map(|(_, value)| value).collect::<Vec<_>>();
+    assert_eq!(iterated.len(), values.len());
+    assert!(values.iter().all(|(_, value)| iterated.contains(value)));
+
+    // Only the files written first hold enough garbage. Their live values are rewritten by the
+    // next commit and the files deleted with the one after it.
+    let reclaimed = nomt.collect_value_log_garbage(0.5).unwrap();
+    assert!(reclaimed > 3 * 1_200_000);
+    check(nomt, &values);
+    write(nomt, &values[19..]);
+    check(nomt, &values);
+    write(nomt, &values[19..]);
+    check(nomt, &values);
+    assert_eq!(log_files(&path), 8);
+
+    // With the value log disabled, values are read back and moved to overflow pages.
+    let t = t.reopen_with(|_| {});
+    let nomt = t.nomt();
+    check(nomt, &values);
+    assert!(nomt.collect_value_log_garbage(1.0).unwrap() > 0);
+    write(nomt, &values[..1]);
+    write(nomt, &values[..1]);
+    assert_eq!(log_files(&path), 0);
+    check(nomt, &values);
+}
+
+#[test]
+fn snapshot_links_value_log() {
+    let dir = test_dir("value_log_snapshot");
+    let path = dir.path().join("db");
+    let copy = dir.path().join("copy");
+    let t = Test::open_with(&path, value_log);
+    let nomt = t.nomt();
+    let values = values(3, 0..10);
+    write(nomt, &values);
+    nomt.snapshot_to(&copy).unwrap();
+    assert_eq!(log_files(&copy), 4);
+
+    // The snapshot is unaffected by the garbage collection of the original.
+    write(nomt, &self::values(4, 0..10));
+    nomt.collect_value_log_garbage(1.0).unwrap();
+    write(nomt, &values[..1]);
+    write(nomt, &values[..1]);
+    drop(t);
+
+    let copy = Test::open_with(&copy, value_log);
+    check(copy.nomt(), &values);
+}
@@ -0,0 +1,26 @@
+mod common;
+
+use common::{test_dir, Test};
+use nomt::KeyReadWrite;
+
+#[test]
+fn commits_with_verified_ht_writes() {
+    let dir = test_dir("verify_ht_writes");
+    let path = dir.path().join("db");
+    let t = Test::open_with(&path, |o| o.verify_ht_writes(true));
+    let nomt = t.nomt();
+    for i in 0..10u8 {
+        let session = nomt.begin_session();
+        let actuals = (0..100u8)
+            .map(|j| ([j; 32], KeyReadWrite::Write(Some(vec![i, j]))))
+            .collect();
+        nomt.commit(session, actuals).unwrap();
+    }
+    let root = nomt.root();
+
+    let t = t.reopen();
+    let nomt = t.nomt();
+    assert_eq!(nomt.root(), root);
+    let session = nomt.begin_session();
+    assert_eq!(session.read([7; 32]).unwrap(), Some(vec![9, 7]));
+}
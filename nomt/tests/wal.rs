@@ -35,3 +35,50 @@ fn wal_recovery_test() {
     assert_eq!(common::read_balance(&mut t, 1), Some(2000));
     assert_eq!(common::read_balance(&mut t, 2), Some(3000));
 }
+
+#[test]
+fn legacy_wal_recovery_test() {
+    let mut t = Test::new_with_params(
+        "wal_legacy",
+        /* commit_concurrency */ 1,
+        /* hashtable_buckets */ 1000000,
+        /* panic_on_sync */ true,
+        /* clean */ true,
+    );
+    common::set_balance(&mut t, 0, 1000);
+    common::set_balance(&mut t, 1, 2000);
+    let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        t.commit();
+    }));
+    assert!(r.is_err());
+    drop(t);
+
+    // Make the database look like one left by a build predating the WAL header: manifest version
+    // 6 and a WAL starting with its first entry.
+    let path = std::path::Path::new("test/wal_legacy");
+    let mut meta = std::fs::read(path.join("meta")).unwrap();
+    meta[4..8].copy_from_slice(&6u32.to_le_bytes());
+    std::fs::write(path.join("meta"), &meta).unwrap();
+    let mut wal = std::fs::read(path.join("wal")).unwrap();
+    assert!(!wal.is_empty());
+    wal.drain(..4);
+    wal.extend_from_slice(&[0; 4]);
+    std::fs::write(path.join("wal"), &wal).unwrap();
+
+    let mut t = Test::new_with_params(
+        "wal_legacy",
+        /* commit_concurrency */ 1,
+        /* hashtable_buckets */ 1000000,
+        /* panic_on_sync */ false,
+        /* clean */ false,
+    );
+    assert_eq!(common::read_balance(&mut t, 0), Some(1000));
+    assert_eq!(common::read_balance(&mut t, 1), Some(2000));
+    // The trie pages are only recovered from the WAL.
+    let mut reference = Test::new("wal_legacy_reference");
+    common::set_balance(&mut reference, 0, 1000);
+    common::set_balance(&mut reference, 1, 2000);
+    assert_eq!(t.commit().0, reference.commit().0);
+    let meta = std::fs::read(path.join("meta")).unwrap();
+    assert_eq!(u32::from_le_bytes(meta[4..8].try_into().unwrap()), 7);
+}
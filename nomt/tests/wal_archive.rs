@@ -0,0 +1,221 @@
+mod common;
+
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyReadWrite, Nomt, Options, SessionParams, ValueHandle, WalSegment};
+
+/// Archive the WAL to the `archive` directory next to the database, and adjust the options
+/// further with `configure`.
+fn archive(
+    dir: &Path,
+    configure: impl Fn(&mut Options) + Send + 'static,
+) -> impl Fn(&mut Options) + Send + 'static {
+    let archive_dir = dir.join("archive");
+    move |o| {
+        o.wal_archive_dir(archive_dir.clone());
+        configure(o);
+    }
+}
+
+fn restore(
+    dir: &Path,
+    name: &str,
+    base: &str,
+    segments: Vec<WalSegment>,
+    until_seqn: u32,
+) -> anyhow::Result<Nomt<Blake3Hasher>> {
+    let path = dir.join(name);
+    let _ = std::fs::remove_dir_all(&path);
+    let mut o = Options::new();
+    o.path(path);
+    Nomt::restore(o, dir.join(base), segments, until_seqn)
+}
+
+fn commit(nomt: &Nomt<Blake3Hasher>, ids: std::ops::Range<u64>, value: u8, token: u8) {
+    let mut actuals = ids
+        .map(|id| {
+            // Delete every third key written before.
+            let value = (value == 0 || id % 3 != 0).then(|| vec![value; 100]);
+            (account_path(id), KeyReadWrite::Write(value))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let mut session = nomt.begin_session();
+    session.set_commit_token([token; 32]);
+    session.write_aux(vec![token], Some(vec![value]));
+    nomt.commit(session, actuals).unwrap();
+}
+
+#[test]
+fn restore_to_any_sync() {
+    let dir = test_dir("wal_archive");
+    let t = Test::open_with(dir.path().join("db"), archive(dir.path(), |_| {}));
+    let nomt = t.nomt();
+    commit(nomt, 0..1000, 0, 1);
+    nomt.snapshot_to(dir.path().join("base")).unwrap();
+    let mut roots = vec![nomt.root()];
+    for i in 1..4 {
+        commit(nomt, 500..1500, i, i + 1);
+        roots.push(nomt.root());
+    }
+    assert_eq!(nomt.sync_seqn(), 4);
+
+    let segments = WalSegment::read_dir(dir.path().join("archive")).unwrap();
+    let seqns = segments.iter().map(|s| s.sync_seqn()).collect::<Vec<_>>();
+    assert_eq!(seqns, vec![1, 2, 3, 4]);
+    assert_eq!(segments[1].commit_token(), Some([2; 32]));
+    assert_eq!(segments[1].values().len(), 1000);
+    assert_eq!(segments[1].aux(), &[(vec![2], Some(vec![1]))][..]);
+
+    for until_seqn in 1..=4 {
+        let restored =
+            restore(dir.path(), "restored", "base", segments.clone(), until_seqn).unwrap();
+        let index = until_seqn as usize - 1;
+        assert_eq!(restored.sync_seqn(), until_seqn);
+        assert_eq!(restored.root(), roots[index]);
+        assert_eq!(restored.last_commit_token(), Some([until_seqn as u8; 32]));
+        assert_eq!(
+            restored.read_aux(&[until_seqn as u8]),
+            Some(vec![index as u8])
+        );
+        assert_eq!(
+            restored.read(account_path(1201)).unwrap(),
+            (index > 0).then(|| vec![index as u8; 100])
+        );
+    }
+
+    // The restored database keeps working.
+    let restored = restore(dir.path(), "restored", "base", segments.clone(), 2).unwrap();
+    commit(&restored, 500..1500, 2, 3);
+    assert_eq!(restored.root(), roots[2]);
+    drop(restored);
+
+    // A missing segment fails the restore and leaves nothing behind.
+    let mut missing = segments.clone();
+    missing.remove(2);
+    assert!(restore(dir.path(), "missing", "base", missing, 4).is_err());
+    assert!(!dir.path().join("missing").exists());
+    assert!(restore(dir.path(), "missing", "base", segments.clone(), 5).is_err());
+
+    // A snapshot past the chosen sync can't be brought back to it.
+    nomt.snapshot_to(dir.path().join("later_base")).unwrap();
+    assert!(restore(dir.path(), "missing", "later_base", segments, 2).is_err());
+}
+
+#[test]
+fn restore_coalesced_commits() {
+    let dir = test_dir("wal_archive_coalesced");
+    let t = Test::open_with(
+        dir.path().join("db"),
+        archive(dir.path(), |o| {
+            o.commit_coalescing(2, Duration::from_secs(3600))
+        }),
+    );
+    let nomt = t.nomt();
+    nomt.snapshot_to(dir.path().join("base")).unwrap();
+    for i in 0..4 {
+        commit(nomt, 0..1000, i, i + 1);
+    }
+    assert_eq!(nomt.sync_seqn(), 2);
+    let root = nomt.root();
+
+    // Each segment holds the last change to every key of the commits synced together.
+    let segments = WalSegment::read_dir(dir.path().join("archive")).unwrap();
+    assert_eq!(segments.len(), 2);
+    assert_eq!(segments[1].values().len(), 1000);
+    assert_eq!(segments[1].commit_token(), Some([4; 32]));
+
+    let restored = restore(dir.path(), "restored", "base", segments, 2).unwrap();
+    assert_eq!(restored.root(), root);
+    assert_eq!(restored.read(account_path(3)).unwrap(), None);
+    assert_eq!(restored.read(account_path(4)).unwrap(), Some(vec![3; 100]));
+}
+
+#[test]
+fn restore_expired_values() {
+    let dir = test_dir("wal_archive_ttl");
+    let t = Test::open_with(dir.path().join("db"), archive(dir.path(), |_| {}));
+    let nomt = t.nomt();
+    nomt.snapshot_to(dir.path().join("base")).unwrap();
+    let session_at = |time| {
+        let mut params = SessionParams::default();
+        params.time(time);
+        nomt.begin_session_with_params(params)
+    };
+    let mut session = session_at(1_000_000);
+    session.write_with_ttl(
+        account_path(1),
+        ValueHandle::from(vec![1]),
+        Duration::from_secs(10),
+    );
+    nomt.commit(session, Vec::new()).unwrap();
+    let mut roots = vec![nomt.root()];
+    commit(nomt, 2..3, 2, 2);
+    roots.push(nomt.root());
+
+    // The value expires and is deleted by the third commit.
+    let session = session_at(1_010_000);
+    nomt.commit(session, Vec::new()).unwrap();
+    roots.push(nomt.root());
+
+    // The replayed commits delete the value just as the original ones did.
+    let segments = WalSegment::read_dir(dir.path().join("archive")).unwrap();
+    for until_seqn in 1..=3 {
+        let restored =
+            restore(dir.path(), "restored", "base", segments.clone(), until_seqn).unwrap();
+        assert_eq!(restored.root(), roots[until_seqn as usize - 1]);
+        let value = (until_seqn < 3).then(|| vec![1]);
+        assert_eq!(restored.read(account_path(1)).unwrap(), value);
+    }
+}
+
+#[test]
+fn failed_archive_fails_commit() {
+    let dir = test_dir("wal_archive_failure");
+    let failing = Arc::new(AtomicBool::new(false));
+    let t = Test::open_with(
+        dir.path().join("db"),
+        archive(dir.path(), {
+            let failing = failing.clone();
+            move |o| {
+                let failing = failing.clone();
+                o.wal_archive(move |_| match failing.load(Ordering::Relaxed) {
+                    true => anyhow::bail!("archive unavailable"),
+                    false => Ok(()),
+                });
+            }
+        }),
+    );
+    let nomt = t.nomt();
+    let mut session = nomt.begin_session();
+    session.set_commit_token([1; 32]);
+    nomt.commit(
+        session,
+        vec![(account_path(0), KeyReadWrite::Write(Some(vec![1])))],
+    )
+    .unwrap();
+
+    failing.store(true, Ordering::Relaxed);
+    let session = nomt.begin_session();
+    let actuals = vec![(account_path(0), KeyReadWrite::Write(Some(vec![2])))];
+    assert!(nomt.commit(session, actuals).is_err());
+
+    // The database must be reopened after the failed sync, even though the archive works again.
+    failing.store(false, Ordering::Relaxed);
+    let session = nomt.begin_session();
+    let actuals = vec![(account_path(0), KeyReadWrite::Write(Some(vec![3])))];
+    assert!(nomt.commit(session, actuals).is_err());
+
+    let t = t.reopen_with(archive(dir.path(), |_| {}));
+    let nomt = t.nomt();
+    assert_eq!(nomt.last_commit_token(), Some([1; 32]));
+    assert_eq!(nomt.read(account_path(0)).unwrap(), Some(vec![1]));
+}
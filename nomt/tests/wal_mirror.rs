@@ -0,0 +1,79 @@
+mod common;
+
+use common::{test_dir, Test};
+use std::{
+    fs::OpenOptions,
+    os::unix::fs::FileExt as _,
+    path::{Path, PathBuf},
+};
+
+use nomt::{KeyReadWrite, Options};
+
+fn mirrored(path: &Path, panic_on_sync: bool) -> impl Fn(&mut Options) + Send + 'static {
+    let mirror = path.with_extension("wal_mirror");
+    move |o| {
+        o.wal_mirror(mirror.clone());
+        o.panic_on_sync(panic_on_sync);
+    }
+}
+
+/// Commit some values, crashing after the manifest has been updated but before the hash-table
+/// has been written out. Returns the path of the WAL file and of its mirror.
+fn crash_after_wal_writeout(path: &Path) -> (PathBuf, PathBuf) {
+    let t = Test::open_with(path, mirrored(path, true));
+    let nomt = t.nomt();
+    let session = nomt.begin_session();
+    let actuals = (0..100u8)
+        .map(|i| ([i; 32], KeyReadWrite::Write(Some(vec![i; 4]))))
+        .collect();
+    let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _ = nomt.commit(session, actuals);
+    }));
+    assert!(r.is_err());
+    drop(t);
+
+    let wal = path.join("wal");
+    let mirror = path.with_extension("wal_mirror");
+    let wal_len = std::fs::metadata(&wal).unwrap().len();
+    assert!(wal_len > 0);
+    assert_eq!(
+        std::fs::read(&wal).unwrap(),
+        std::fs::read(&mirror).unwrap()
+    );
+    (wal, mirror)
+}
+
+fn assert_recovered(path: &Path, wal: &Path, mirror: &Path) {
+    let t = Test::open_with(path, mirrored(path, false));
+    let nomt = t.nomt();
+    for i in 0..100u8 {
+        assert_eq!(nomt.read([i; 32]).unwrap(), Some(vec![i; 4]));
+    }
+    assert_eq!(std::fs::metadata(wal).unwrap().len(), 0);
+    assert_eq!(std::fs::metadata(mirror).unwrap().len(), 0);
+}
+
+#[test]
+fn recovers_from_mirror_when_wal_corrupted() {
+    let dir = test_dir("wal_mirror_corrupted");
+    let path = dir.path().join("db");
+    let (wal, mirror) = crash_after_wal_writeout(&path);
+
+    // Garble the start of the WAL file.
+    let file = OpenOptions::new().write(true).open(&wal).unwrap();
+    file.write_all_at(&[0xff; 64], 0).unwrap();
+    file.sync_all().unwrap();
+
+    assert_recovered(&path, &wal, &mirror);
+}
+
+#[test]
+fn recovers_from_wal_when_mirror_lost() {
+    let dir = test_dir("wal_mirror_lost");
+    let path = dir.path().join("db");
+    let (wal, mirror) = crash_after_wal_writeout(&path);
+
+    std::fs::File::create(&mirror).unwrap();
+
+    assert_recovered(&path, &wal, &mirror);
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::{
+    ops::ControlFlow,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use common::{account_path, test_dir};
+use nomt::{KeyReadWrite, Nomt, Options, WalReplayCancelled, WalReplayProgress};
+
+fn options(path: &Path, panic_on_sync: bool) -> Options {
+    let mut o = Options::new();
+    o.path(path);
+    o.panic_on_sync(panic_on_sync);
+    o.bitbox_seed([0; 16]);
+    o.hashtable_buckets(100_000);
+    o.preallocate_ht(false);
+    o
+}
+
+fn record_progress(
+    o: &mut Options,
+    cancel: impl Fn(WalReplayProgress) -> bool + Send + Sync + 'static,
+) -> Arc<Mutex<Vec<WalReplayProgress>>> {
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let reports_clone = reports.clone();
+    o.wal_replay_progress(move |progress| {
+        reports_clone.lock().unwrap().push(progress);
+        if cancel(progress) {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    reports
+}
+
+#[test]
+fn wal_replay_reports_progress_and_resumes() {
+    let dir = test_dir("wal_replay");
+    let path = dir.path().join("db");
+
+    // Leave a WAL behind by crashing during the sync.
+    let nomt = Nomt::<nomt::Blake3Hasher>::open(options(&path, true)).unwrap();
+    let mut actuals = (0..10_000)
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![1; 8]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(path, _)| *path);
+    let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let session = nomt.begin_session();
+        nomt.commit(session, actuals).unwrap();
+    }));
+    assert!(r.is_err());
+    drop(nomt);
+
+    // Cancel the replay after the first batch of records.
+    let mut o = options(&path, false);
+    let reports = record_progress(&mut o, |progress| progress.replayed > 0);
+    let err = Nomt::<nomt::Blake3Hasher>::open(o).err().unwrap();
+    assert!(err.downcast_ref::<WalReplayCancelled>().is_some());
+    let cancelled = reports.lock().unwrap().clone();
+    assert_eq!(cancelled.len(), 2);
+    assert_eq!(cancelled[0].replayed, 0);
+    let total = cancelled[0].total;
+    assert!(total > 0);
+    let cancelled_at = cancelled[1].replayed;
+
+    // The next replay resumes where the previous one stopped and completes.
+    let mut o = options(&path, false);
+    let reports = record_progress(&mut o, |_| false);
+    let nomt = Nomt::<nomt::Blake3Hasher>::open(o).unwrap();
+    let resumed = reports.lock().unwrap().clone();
+    assert_eq!(resumed.first().unwrap().replayed, cancelled_at);
+    assert_eq!(
+        *resumed.last().unwrap(),
+        WalReplayProgress {
+            replayed: total,
+            total
+        }
+    );
+    assert!(resumed.windows(2).all(|w| w[0].replayed < w[1].replayed));
+    for id in 0..10_000 {
+        assert_eq!(nomt.read(account_path(id)).unwrap(), Some(vec![1; 8]));
+    }
+    drop(nomt);
+
+    // Nothing is left to replay.
+    let mut o = options(&path, false);
+    let reports = record_progress(&mut o, |_| false);
+    let _nomt = Nomt::<nomt::Blake3Hasher>::open(o).unwrap();
+    assert!(reports.lock().unwrap().is_empty());
+}
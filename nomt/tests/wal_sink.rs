@@ -0,0 +1,54 @@
+mod common;
+
+use common::{test_dir, Test};
+use nomt::{KeyReadWrite, WalSink};
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct RecordingSink {
+    writes: Mutex<Vec<(u32, usize)>>,
+}
+
+impl WalSink for RecordingSink {
+    fn write(&self, sync_seqn: u32, wal_blob: &[u8]) -> anyhow::Result<()> {
+        self.writes
+            .lock()
+            .unwrap()
+            .push((sync_seqn, wal_blob.len()));
+        Ok(())
+    }
+}
+
+#[test]
+fn wal_is_streamed_to_sinks() {
+    let dir = test_dir("wal_sink");
+    let sink = Arc::new(RecordingSink::default());
+    let t = Test::open_with(dir.path().join("db"), {
+        let sink = sink.clone();
+        move |o| o.wal_sink(sink.clone())
+    });
+    let nomt = t.nomt();
+
+    for i in 0..3u8 {
+        let session = nomt.begin_session();
+        nomt.commit(session, vec![([i; 32], KeyReadWrite::Write(Some(vec![i])))])
+            .unwrap();
+    }
+
+    let writes = sink.writes.lock().unwrap();
+    assert_eq!(
+        writes.iter().map(|(seqn, _)| *seqn).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+    assert!(writes.iter().all(|(_, len)| *len > 0));
+}
+
+#[test]
+fn wal_sink_quorum_exceeding_sinks_is_rejected() {
+    let dir = test_dir("wal_sink_quorum");
+    let t = Test::try_open_with(dir.path().join("db"), |o| {
+        o.wal_sink(Arc::new(RecordingSink::default()));
+        o.wal_sink_quorum(2);
+    });
+    assert!(t.is_err());
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{account_path, test_dir, Test};
+use nomt::{CacheResult, KeyPath, KeyReadWrite};
+
+fn actuals(ids: std::ops::Range<u64>, value: u8) -> Vec<(KeyPath, KeyReadWrite)> {
+    let mut actuals = ids
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![value; 32]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(path, _)| *path);
+    actuals
+}
+
+#[test]
+fn warms_up_keys_in_the_background() {
+    let dir = test_dir("warm_up_all");
+    let path = dir.path().join("db");
+    let t = Test::open(&path);
+    let nomt = t.nomt();
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals(0..5000, 1)).unwrap();
+
+    let t = t.reopen();
+    let nomt = t.nomt();
+    let keys = (0..1000).map(account_path).collect::<Vec<_>>();
+    let session = nomt.begin_session();
+    assert!(keys
+        .iter()
+        .all(|key| session.read_cached(*key) == CacheResult::Miss));
+
+    // Keys not in the database are skipped.
+    session.warm_up_all(keys.iter().copied().chain([account_path(9999)]));
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while keys
+        .iter()
+        .any(|key| session.read_cached(*key) == CacheResult::Miss)
+    {
+        assert!(Instant::now() < deadline, "keys were not warmed up");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(
+        session.read_cached(keys[0]),
+        CacheResult::Hit(Some(vec![1; 32]))
+    );
+
+    // The warmed-up keys can be updated as usual.
+    nomt.commit(session, actuals(0..1000, 2)).unwrap();
+    let session = nomt.begin_session();
+    assert_eq!(session.read(keys[0]).unwrap(), Some(vec![2; 32]));
+    assert_eq!(session.read(account_path(4999)).unwrap(), Some(vec![1; 32]));
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::KeyReadWrite;
+use std::time::{Duration, Instant};
+
+#[test]
+fn warm_up_batch_before_deadline() {
+    let dir = test_dir("warm_up_batch");
+    let path = dir.path().join("db");
+    let t = Test::open_with(&path, |o| o.warm_up(true));
+    {
+        let nomt = t.nomt();
+        let session = nomt.begin_session();
+        let mut actuals = (0..1000)
+            .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![1; 8]))))
+            .collect::<Vec<_>>();
+        actuals.sort_by_key(|(path, _)| *path);
+        nomt.commit(session, actuals).unwrap();
+    }
+
+    let t = t.reopen();
+    let nomt = t.nomt();
+    let session = nomt.begin_session();
+    let keys = (0..100)
+        .map(|id| (account_path(id), id % 7))
+        .collect::<Vec<_>>();
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let warmed = session.warm_up_batch(&keys, deadline).unwrap();
+    assert_eq!(warmed.len(), keys.len());
+    assert!(warmed.all());
+    assert!(session.warm_up_batch(&[], deadline).unwrap().is_empty());
+
+    let mut actuals = keys
+        .iter()
+        .map(|(path, _)| (*path, KeyReadWrite::Write(Some(vec![2; 8]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(path, _)| *path);
+    nomt.commit(session, actuals).unwrap();
+    assert_eq!(nomt.read(account_path(3)).unwrap(), Some(vec![2; 8]));
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use common::Test;
+
+#[test]
+fn reads_overlapping_warm_ups() {
+    let mut t = Test::new("warm_up_read");
+    for id in 0..5000 {
+        common::set_balance(&mut t, id, id + 1);
+    }
+    t.commit();
+    drop(t);
+
+    // Reopen with a cold leaf cache, then race reads against the warm-ups of the same keys.
+    let mut t = Test::new_with_params("warm_up_read", 1, 64_000, false, false);
+    assert_eq!(t.session_stats().deduplicated_value_fetches, 0);
+    for id in 0..5000 {
+        t.warm_up_id(id);
+    }
+    for id in 0..5000 {
+        assert_eq!(common::read_balance(&mut t, id), Some(id + 1));
+    }
+    assert_eq!(common::read_balance(&mut t, 5000), None);
+}
+
+#[test]
+fn sequential_reads_are_not_deduplicated() {
+    let mut t = Test::new("warm_up_read_sequential");
+    for id in 0..5000 {
+        common::set_balance(&mut t, id, id + 1);
+    }
+    t.commit();
+    drop(t);
+
+    // Without warm-ups, no fetch is ever in progress when a read starts, whether the leaf is
+    // cached already or not. How many reads overtake a warm-up depends on timing, so the exact
+    // count of deduplicated fetches is covered by the unit tests of the leaf cache.
+    let mut t = Test::new_with_params("warm_up_read_sequential", 1, 64_000, false, false);
+    for id in 0..5000 {
+        assert_eq!(common::read_balance(&mut t, id), Some(id + 1));
+    }
+    assert_eq!(t.session_stats().deduplicated_value_fetches, 0);
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::Test;
+use nomt::{Blake3Hasher, Node, Witness, WitnessChunk, WitnessChunkError};
+
+fn witness() -> (Node, Witness) {
+    let mut t = Test::new("witness_chunks");
+    for id in 0..100 {
+        common::set_balance(&mut t, id, 1000);
+    }
+    let (prev_root, _, _) = t.commit();
+
+    for id in 0..50 {
+        t.read_id(id);
+    }
+    for id in 50..100 {
+        common::set_balance(&mut t, id, 2000);
+    }
+    let (_, witness, _) = t.commit();
+    (prev_root, witness)
+}
+
+#[test]
+fn chunks_verify_and_reassemble() {
+    let (root, witness) = witness();
+    let num_paths = witness.path_proofs.len();
+    let chunks = witness.into_chunks(root, 2048).unwrap();
+    assert!(chunks.len() > 1);
+
+    let mut decoded = Vec::new();
+    let mut verified_paths = 0;
+    for chunk in chunks.iter().rev() {
+        let encoded = chunk.encode();
+        assert!(encoded.len() <= 2048);
+        let chunk = WitnessChunk::decode(&encoded).unwrap();
+        assert_eq!(chunk.root, root);
+        verified_paths += chunk.verify::<Blake3Hasher>().unwrap().len();
+        decoded.push(chunk);
+    }
+    assert_eq!(verified_paths, num_paths);
+
+    let reassembled = WitnessChunk::reassemble::<Blake3Hasher>(decoded).unwrap();
+    assert_eq!(reassembled.path_proofs.len(), num_paths);
+    for path in &reassembled.path_proofs {
+        path.inner
+            .verify::<Blake3Hasher>(path.path.path(), root)
+            .unwrap();
+    }
+}
+
+#[test]
+fn incomplete_or_tampered_chunks_are_rejected() {
+    let (root, witness) = witness();
+    let mut chunks = witness.into_chunks(root, 2048).unwrap();
+
+    let mut missing = chunks.clone();
+    missing.pop();
+    assert_eq!(
+        WitnessChunk::reassemble::<Blake3Hasher>(missing).err(),
+        Some(WitnessChunkError::Inconsistent),
+    );
+
+    // Replace a sibling of the first path proof.
+    let mut encoded = chunks[0].encode();
+    let len = encoded.len();
+    encoded[len - 1] ^= 1;
+    chunks[0] = WitnessChunk::decode(&encoded).unwrap();
+    assert!(chunks[0].verify::<Blake3Hasher>().is_err());
+    assert_eq!(
+        WitnessChunk::reassemble::<Blake3Hasher>(chunks).err(),
+        Some(WitnessChunkError::CommitmentMismatch),
+    );
+}
+
+#[test]
+fn oversized_paths_are_rejected() {
+    let (root, witness) = witness();
+    assert_eq!(
+        witness.into_chunks(root, 128).unwrap_err(),
+        WitnessChunkError::PathTooLarge { path_index: 0 },
+    );
+}
@@ -0,0 +1,104 @@
+#![cfg(feature = "scale")]
+
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{
+    codec::{DecodeError, DecodeLimits, FORMAT_VERSION},
+    Blake3Hasher, KeyReadWrite, Node, SessionParams, Witness, WitnessMode, WitnessedOperations,
+};
+
+fn prove(name: &str, witness_mode: WitnessMode) -> (Node, Witness, WitnessedOperations) {
+    let dir = test_dir(name);
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+
+    let mut actuals = (0..1000)
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![1; 8]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+    let prev_root = nomt.root();
+
+    let mut params = SessionParams::default();
+    params.witness_mode(witness_mode);
+    params.witness_pages(true);
+    let session = nomt.begin_session_with_params(params);
+    let mut actuals = [1, 5, 1000, 1001]
+        .into_iter()
+        .map(|id| {
+            let key = account_path(id);
+            let read = session.read(key).unwrap();
+            (key, KeyReadWrite::ReadThenWrite(read, Some(vec![2; 8])))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let (_, witness, witnessed) = nomt.commit_and_prove(session, actuals).unwrap();
+    (prev_root, witness, witnessed)
+}
+
+#[test]
+fn witness_round_trips_byte_exactly() {
+    for (name, witness_mode) in [
+        ("witness_codec_paths", WitnessMode::Paths),
+        ("witness_codec_multiproof", WitnessMode::Multiproof),
+    ] {
+        let (prev_root, witness, witnessed) = prove(name, witness_mode);
+        let limits = DecodeLimits::default();
+
+        let bytes = witness.encode_versioned();
+        assert_eq!(bytes[0], FORMAT_VERSION);
+        let decoded = Witness::decode_versioned(&bytes, &limits).unwrap();
+        assert_eq!(decoded.encode_versioned(), bytes, "{name}");
+        assert_eq!(decoded.pages.len(), witness.pages.len(), "{name}");
+
+        let operations = witnessed.encode_versioned();
+        let decoded_operations =
+            WitnessedOperations::decode_versioned(&operations, &limits).unwrap();
+        assert_eq!(decoded_operations.encode_versioned(), operations, "{name}");
+
+        if witness_mode == WitnessMode::Paths {
+            assert_eq!(
+                decoded
+                    .prior_values::<Blake3Hasher>(&decoded_operations, prev_root)
+                    .unwrap(),
+                witness
+                    .prior_values::<Blake3Hasher>(&witnessed, prev_root)
+                    .unwrap(),
+            );
+        }
+    }
+}
+
+#[test]
+fn malformed_witnesses_are_rejected() {
+    let (_, witness, _) = prove("witness_codec_malformed", WitnessMode::Paths);
+    let bytes = witness.encode_versioned();
+
+    let limits = DecodeLimits {
+        max_bytes: bytes.len() - 1,
+    };
+    assert_eq!(
+        Witness::decode_versioned(&bytes, &limits).err(),
+        Some(DecodeError::TooLarge)
+    );
+
+    let limits = DecodeLimits::default();
+    let mut future = bytes.clone();
+    future[0] = FORMAT_VERSION + 1;
+    assert_eq!(
+        Witness::decode_versioned(&future, &limits).err(),
+        Some(DecodeError::UnsupportedVersion(FORMAT_VERSION + 1))
+    );
+    assert_eq!(
+        Witness::decode_versioned(&bytes[..bytes.len() - 1], &limits).err(),
+        Some(DecodeError::Malformed)
+    );
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert_eq!(
+        Witness::decode_versioned(&trailing, &limits).err(),
+        Some(DecodeError::Malformed)
+    );
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyPath, KeyReadWrite, LeafData, SessionParams};
+
+fn proven(key: &KeyPath) -> bool {
+    key[0] < 0x80
+}
+
+#[test]
+fn witness_only_covers_filtered_keys() {
+    let dir = test_dir("witness_filter");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+
+    let mut actuals = (0..100)
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![1; 8]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    let prev_root = nomt.commit(session, actuals).unwrap();
+
+    let mut params = SessionParams::default();
+    params.witness_filter(proven);
+    let session = nomt.begin_session_with_params(params);
+    let mut actuals = (0..200)
+        .map(|id| {
+            let key = account_path(id);
+            session.warm_up(key);
+            let read_write = match id % 3 {
+                0 => KeyReadWrite::Read(session.read(key).unwrap()),
+                1 => KeyReadWrite::Write(Some(vec![2; 8])),
+                _ => KeyReadWrite::Write(None),
+            };
+            (key, read_write)
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let expected_reads = actuals
+        .iter()
+        .filter(|(key, rw)| proven(key) && matches!(rw, KeyReadWrite::Read(_)))
+        .count();
+    let expected_writes = actuals
+        .iter()
+        .filter(|(key, rw)| proven(key) && !matches!(rw, KeyReadWrite::Read(_)))
+        .count();
+    let (_, witness, witnessed) = nomt.commit_and_prove(session, actuals).unwrap();
+
+    assert!(expected_reads > 0 && expected_writes > 0);
+    assert_eq!(witnessed.reads.len(), expected_reads);
+    assert_eq!(witnessed.writes.len(), expected_writes);
+    assert!(witnessed.reads.iter().all(|read| proven(&read.key)));
+    assert!(witnessed.writes.iter().all(|write| proven(&write.key)));
+
+    // Every path proof serves some witnessed operation, and the reads verify against the prior
+    // root.
+    let mut used = vec![false; witness.path_proofs.len()];
+    for read in &witnessed.reads {
+        used[read.path_index] = true;
+        let path = &witness.path_proofs[read.path_index];
+        let verified = path
+            .inner
+            .verify::<Blake3Hasher>(path.path.path(), prev_root)
+            .unwrap();
+        match read.value {
+            None => assert!(verified.confirm_nonexistence(&read.key).unwrap()),
+            Some(value_hash) => assert!(verified
+                .confirm_value(&LeafData {
+                    key_path: read.key,
+                    value_hash,
+                })
+                .unwrap()),
+        }
+    }
+    for write in &witnessed.writes {
+        used[write.path_index] = true;
+    }
+    assert!(used.into_iter().all(|used| used));
+}
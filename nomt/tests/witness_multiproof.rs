@@ -0,0 +1,77 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{
+    multi_proof_verification, Blake3Hasher, KeyReadWrite, LeafData, Node, SessionParams, Witness,
+    WitnessMode, WitnessedOperations,
+};
+
+/// Commit the same operations to a fresh database, proving them in the given mode. Returns the
+/// prior root along with the witness.
+fn prove(name: &str, witness_mode: WitnessMode) -> (Node, Witness, WitnessedOperations) {
+    let dir = test_dir(name);
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+
+    let mut actuals = (0..1000)
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![1; 8]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    let prev_root = nomt.commit(session, actuals).unwrap();
+
+    let mut params = SessionParams::default();
+    params.witness_mode(witness_mode);
+    let session = nomt.begin_session_with_params(params);
+    let mut actuals = (0..400)
+        .map(|id| {
+            let key = account_path(id * 3);
+            session.warm_up(key);
+            let read_write = match id % 2 {
+                0 => KeyReadWrite::Read(session.read(key).unwrap()),
+                _ => KeyReadWrite::Write(Some(vec![2; 8])),
+            };
+            (key, read_write)
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let (_, witness, witnessed) = nomt.commit_and_prove(session, actuals).unwrap();
+    (prev_root, witness, witnessed)
+}
+
+#[test]
+fn multiproof_verifies_and_dedups_siblings() {
+    let (_, paths, _) = prove("witness_multiproof_paths", WitnessMode::Paths);
+    assert!(paths.multi_proof.is_none());
+    let (prev_root, witness, witnessed) = prove("witness_multiproof", WitnessMode::Multiproof);
+    assert!(witness.path_proofs.is_empty());
+
+    // UNWRAP: requested above.
+    let multi_proof = witness.multi_proof.unwrap();
+    assert_eq!(multi_proof.paths.len(), paths.path_proofs.len());
+    let path_siblings = paths
+        .path_proofs
+        .iter()
+        .map(|path| path.inner.siblings.len())
+        .sum::<usize>();
+    assert!(multi_proof.siblings.len() < path_siblings / 2);
+
+    let verified =
+        multi_proof_verification::verify::<Blake3Hasher>(&multi_proof, prev_root).unwrap();
+    assert!(!witnessed.reads.is_empty());
+    for read in &witnessed.reads {
+        let confirmed = match read.value {
+            None => verified.confirm_nonexistence_with_index(&read.key, read.path_index),
+            Some(value_hash) => verified.confirm_value_with_index(
+                &LeafData {
+                    key_path: read.key,
+                    value_hash,
+                },
+                read.path_index,
+            ),
+        };
+        assert!(confirmed.unwrap());
+    }
+
+    assert!(multi_proof_verification::verify::<Blake3Hasher>(&multi_proof, [1; 32]).is_err());
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{Blake3Hasher, KeyReadWrite, Node, NodeHasher, Nomt, SessionParams, Witness};
+
+fn populate(nomt: &Nomt<Blake3Hasher>) {
+    let mut actuals = (0..1000)
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![1; 8]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+}
+
+fn prove(nomt: &Nomt<Blake3Hasher>, witness_pages: bool, ids: &[u64], write: bool) -> Witness {
+    let mut params = SessionParams::default();
+    params.witness_pages(witness_pages);
+    let session = nomt.begin_session_with_params(params);
+    let mut actuals = ids
+        .iter()
+        .map(|&id| {
+            let key = account_path(id);
+            session.warm_up(key);
+            let read_write = if write {
+                KeyReadWrite::ReadThenWrite(session.read(key).unwrap(), Some(vec![2; 8]))
+            } else {
+                KeyReadWrite::Read(session.read(key).unwrap())
+            };
+            (key, read_write)
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let (_, witness, _) = nomt.commit_and_prove(session, actuals).unwrap();
+    witness
+}
+
+fn hash_internal(left: Node, right: Node) -> Node {
+    let mut preimage = [0; 64];
+    preimage[..32].copy_from_slice(&left);
+    preimage[32..].copy_from_slice(&right);
+    let mut hash = Blake3Hasher::hash_node(&preimage);
+    hash[0] &= 0b01111111;
+    hash
+}
+
+#[test]
+fn pages_are_only_witnessed_on_request() {
+    let dir = test_dir("witness_pages_off");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    populate(nomt);
+    let witness = prove(nomt, false, &[1, 2, 3], true);
+    assert!(!witness.path_proofs.is_empty());
+    assert!(witness.pages.is_empty());
+}
+
+#[test]
+fn pages_hold_contents_prior_to_commit() {
+    let dir = test_dir("witness_pages_contents");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    populate(nomt);
+    let prev_root = nomt.root();
+    let witness = prove(nomt, true, &[1, 2, 3, 500, 999], true);
+    assert_ne!(nomt.root(), prev_root);
+
+    let pages = &witness.pages;
+    assert!(!pages.is_empty());
+    assert!(pages.windows(2).all(|w| w[0].page_id < w[1].page_id));
+    assert!(pages.iter().all(|page| page.nodes.len() == 126));
+    // The root page comes first and its top nodes hash to the root prior to the commit.
+    assert_eq!(
+        hash_internal(pages[0].nodes[0], pages[0].nodes[1]),
+        prev_root
+    );
+}
+
+#[test]
+fn reads_of_absent_keys_are_witnessed() {
+    let dir = test_dir("witness_pages_absent");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+    populate(nomt);
+    let witness = prove(nomt, true, &[5000, 5001, 5002], false);
+    assert!(!witness.pages.is_empty());
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{KeyReadWrite, SessionParams, Witness, WitnessMode};
+
+fn prove(name: &str, witness_mode: WitnessMode) -> Witness {
+    let dir = test_dir(name);
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+
+    let mut actuals = (0..1000)
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![1; 8]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+
+    let mut params = SessionParams::default();
+    params.witness_mode(witness_mode);
+    let session = nomt.begin_session_with_params(params);
+    // Reads of absent keys end in terminators.
+    let mut actuals = (0..300)
+        .map(|id| {
+            let key = account_path(id * 5);
+            session.warm_up(key);
+            (key, KeyReadWrite::Read(session.read(key).unwrap()))
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let (_, witness, _) = nomt.commit_and_prove(session, actuals).unwrap();
+    witness
+}
+
+#[test]
+fn stats_match_witness_contents() {
+    let paths = prove("witness_stats_paths", WitnessMode::Paths);
+    let stats = paths.stats();
+    assert_eq!(stats.paths, paths.path_proofs.len());
+    let siblings = paths
+        .path_proofs
+        .iter()
+        .map(|path| path.inner.siblings.len())
+        .sum::<usize>();
+    assert_eq!(stats.nodes(), siblings);
+    let max_depth = paths
+        .path_proofs
+        .iter()
+        .map(|path| path.inner.siblings.len())
+        .max()
+        .unwrap();
+    assert_eq!(stats.nodes_by_depth.len(), max_depth);
+    // Every path carries both children of the root.
+    assert_eq!(stats.nodes_by_depth[0], stats.paths);
+    // ... but only two of them are unique.
+    assert!(stats.duplicate_siblings >= stats.paths - 2);
+    assert!(stats.unique_pages > 1);
+
+    let multi = prove("witness_stats_multiproof", WitnessMode::Multiproof);
+    let multi_stats = multi.stats();
+    // UNWRAP: requested above.
+    let multi_proof = multi.multi_proof.as_ref().unwrap();
+    assert_eq!(multi_stats.paths, stats.paths);
+    assert_eq!(multi_stats.nodes(), multi_proof.siblings.len());
+    assert_eq!(multi_stats.duplicate_siblings, 0);
+    assert_eq!(multi_stats.unique_pages, stats.unique_pages);
+    assert!(multi_stats.verifier_hashes < stats.verifier_hashes);
+    assert!(multi_stats.nodes() <= stats.nodes() - stats.duplicate_siblings);
+}
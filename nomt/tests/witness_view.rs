@@ -0,0 +1,80 @@
+mod common;
+
+use common::Test;
+use nomt::{Blake3Hasher, MappedWitness, Node, Witness, WitnessChunkError, WitnessView};
+
+fn witness() -> (Node, Witness) {
+    let mut t = Test::new("witness_view");
+    for id in 0..100 {
+        common::set_balance(&mut t, id, 1000);
+    }
+    let (prev_root, _, _) = t.commit();
+
+    for id in 0..50 {
+        t.read_id(id);
+    }
+    for id in 50..100 {
+        common::set_balance(&mut t, id, 2000);
+    }
+    let (_, witness, _) = t.commit();
+    (prev_root, witness)
+}
+
+#[test]
+fn mapped_witness_verifies() {
+    let (root, witness) = witness();
+    let path = std::path::PathBuf::from("test/witness_view.bin");
+    std::fs::write(&path, witness.encode()).unwrap();
+
+    let mapped = MappedWitness::open(&path).unwrap();
+    let view = mapped.view().unwrap();
+    assert_eq!(view.len(), witness.path_proofs.len() as u64);
+    view.verify::<Blake3Hasher>(root).unwrap();
+    assert_eq!(
+        view.verify::<Blake3Hasher>([1; 32]),
+        Err(WitnessChunkError::InvalidPath { path_index: 0 })
+    );
+
+    for (path, expected) in view.paths().zip(&witness.path_proofs) {
+        let path = path.unwrap().to_witnessed_path();
+        assert_eq!(path.path.path(), expected.path.path());
+        assert_eq!(path.inner.siblings, expected.inner.siblings);
+    }
+}
+
+#[test]
+fn malformed_witness_is_rejected() {
+    let (root, witness) = witness();
+    let mut encoded = witness.encode();
+
+    // Tamper with the last sibling of the last path proof.
+    let len = encoded.len();
+    encoded[len - 1] ^= 1;
+    assert_eq!(
+        WitnessView::new(&encoded)
+            .unwrap()
+            .verify::<Blake3Hasher>(root),
+        Err(WitnessChunkError::InvalidPath {
+            path_index: witness.path_proofs.len() - 1
+        })
+    );
+
+    encoded.pop();
+    let view = WitnessView::new(&encoded).unwrap();
+    assert_eq!(
+        view.verify::<Blake3Hasher>(root),
+        Err(WitnessChunkError::Malformed)
+    );
+    assert!(view.paths().last().unwrap().is_err());
+
+    // Trailing bytes.
+    let mut encoded = witness.encode();
+    encoded.push(0);
+    assert_eq!(
+        WitnessView::new(&encoded)
+            .unwrap()
+            .verify::<Blake3Hasher>(root),
+        Err(WitnessChunkError::Malformed)
+    );
+    assert!(WitnessView::new(&[0; 4]).is_err());
+}
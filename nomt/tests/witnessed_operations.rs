@@ -0,0 +1,82 @@
+mod common;
+
+use common::{account_path, test_dir, Test};
+use nomt::{KeyReadWrite, WitnessedOperation};
+
+#[test]
+fn witnessed_operations_in_commit_order() {
+    let dir = test_dir("witnessed_operations");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+
+    let mut actuals = (0..100)
+        .map(|id| (account_path(id), KeyReadWrite::Write(Some(vec![1; 8]))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let session = nomt.begin_session();
+    nomt.commit(session, actuals).unwrap();
+
+    let session = nomt.begin_session();
+    let mut actuals = (0..150)
+        .map(|id| {
+            let key = account_path(id);
+            let read_write = match id % 3 {
+                0 => KeyReadWrite::Read(session.read(key).unwrap()),
+                1 => KeyReadWrite::Write(Some(vec![2; 8])),
+                _ => KeyReadWrite::ReadThenWrite(session.read(key).unwrap(), None),
+            };
+            (key, read_write)
+        })
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(key, _)| *key);
+    let (_, _, witnessed) = nomt.commit_and_prove(session, actuals.clone()).unwrap();
+
+    let ops = witnessed.iter().collect::<Vec<_>>();
+    assert_eq!(ops.len(), witnessed.reads.len() + witnessed.writes.len());
+    assert!(ops.windows(2).all(|w| w[0].key() <= w[1].key()));
+
+    // Every key of the commit appears in order, read before write.
+    let mut ops = ops.into_iter().peekable();
+    for (key, read_write) in &actuals {
+        let is_read = !matches!(read_write, KeyReadWrite::Write(_));
+        if is_read {
+            let Some(WitnessedOperation::Read {
+                index,
+                write_index,
+                read,
+            }) = ops.next()
+            else {
+                panic!("expected a read");
+            };
+            assert_eq!(&read.key, key);
+            assert!(std::ptr::eq(read, &witnessed.reads[index]));
+            match write_index {
+                Some(write_index) => {
+                    assert!(read_write.is_write());
+                    assert_eq!(&witnessed.writes[write_index].key, key);
+                }
+                None => assert!(!read_write.is_write()),
+            }
+        }
+        if read_write.is_write() {
+            let Some(WitnessedOperation::Write {
+                index,
+                read_index,
+                write,
+            }) = ops.next()
+            else {
+                panic!("expected a write");
+            };
+            assert_eq!(&write.key, key);
+            assert!(std::ptr::eq(write, &witnessed.writes[index]));
+            match read_index {
+                Some(read_index) => {
+                    assert!(is_read);
+                    assert_eq!(&witnessed.reads[read_index].key, key);
+                }
+                None => assert!(!is_read),
+            }
+        }
+    }
+    assert!(ops.next().is_none());
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{account_path, test_dir, Test};
+use nomt::{KeyReadWrite, ValueHandle};
+
+fn value(id: u64, len: usize) -> Vec<u8> {
+    vec![id as u8; len]
+}
+
+#[test]
+fn write_all_matches_actuals() {
+    let dir = test_dir("write_all");
+    let bulk = Test::open(dir.path().join("bulk"));
+    let plain = Test::open(dir.path().join("plain"));
+
+    // Small values as well as values requiring overflow pages.
+    let values = (0..50)
+        .map(|id| (account_path(id), value(id, 10 + id as usize * 200)))
+        .collect::<Vec<_>>();
+
+    let mut session = bulk.nomt().begin_session();
+    session.write_all(values.iter().map(|(path, value)| {
+        let buf: Arc<[u8]> = value.clone().into();
+        (*path, Some(ValueHandle::new(buf)))
+    }));
+    let bulk_root = bulk.nomt().commit(session, vec![]).unwrap();
+
+    let mut actuals = values
+        .iter()
+        .map(|(path, value)| (*path, KeyReadWrite::Write(Some(value.clone()))))
+        .collect::<Vec<_>>();
+    actuals.sort_by_key(|(path, _)| *path);
+    let session = plain.nomt().begin_session();
+    let plain_root = plain.nomt().commit(session, actuals).unwrap();
+
+    assert_eq!(bulk_root, plain_root);
+    for (path, value) in &values {
+        assert_eq!(bulk.nomt().read(*path).unwrap().as_ref(), Some(value));
+    }
+}
+
+#[test]
+fn write_all_last_write_wins() {
+    let dir = test_dir("write_all_last_write_wins");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+
+    let mut session = nomt.begin_session();
+    session.write_all([
+        (account_path(0), Some(value(1, 8).into())),
+        (account_path(1), Some(value(2, 8).into())),
+        (account_path(0), Some(value(3, 8).into())),
+        (account_path(1), None),
+    ]);
+    nomt.commit(session, vec![]).unwrap();
+
+    assert_eq!(nomt.read(account_path(0)).unwrap(), Some(value(3, 8)));
+    assert_eq!(nomt.read(account_path(1)).unwrap(), None);
+}
+
+#[test]
+fn write_all_conflicting_with_actuals() {
+    let dir = test_dir("write_all_conflicting_with_actuals");
+    let t = Test::open(dir.path().join("db"));
+    let nomt = t.nomt();
+
+    let mut session = nomt.begin_session();
+    session.write_all([(account_path(0), Some(value(1, 8).into()))]);
+    let actuals = vec![(account_path(0), KeyReadWrite::Read(None))];
+    assert!(nomt.commit(session, actuals).is_err());
+    assert!(nomt.is_empty());
+}